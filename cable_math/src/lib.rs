@@ -6,6 +6,7 @@ mod vec;
 mod mat;
 mod quat;
 mod traits;
+mod simd;
 
 #[cfg(feature = "serialize")]
 mod serialize;
@@ -74,7 +74,7 @@ impl<T: Number> Mat4<T> {
 
     /// Creates a new matrix with the given values. The values are specified
     /// row by row.
-    pub fn with_values(
+    pub const fn with_values(
         a11: T, a12: T, a13: T, a14: T,
         a21: T, a22: T, a23: T, a24: T,
         a31: T, a32: T, a33: T, a34: T,
@@ -88,6 +88,16 @@ impl<T: Number> Mat4<T> {
         }
     }
 
+    /// Transforms all the given points by this matrix, in place. The points are treated as having
+    /// a `w` component of one, so translations are applied. No perspective divide is done, so this
+    /// is intended for affine transforms such as model matrices.
+    ///
+    /// For `f32` matrices this uses SIMD instructions where available, and is considerably faster
+    /// than multiplying each point individually.
+    pub fn transform_points(&self, points: &mut [Vec3<T>]) {
+        T::mat4_transform_points(self, points);
+    }
+
     /// Creates a new matrix from a 4x4 array.
     pub fn from_col_nested(data: [[T; 4]; 4]) -> Mat4<T> {
         Mat4 {
@@ -326,7 +336,7 @@ impl<T: Number> Mat3<T> {
 
     /// Creates a new matrix with the given values. The values are specified
     /// row by row.
-    pub fn with_values(
+    pub const fn with_values(
         a11: T, a12: T, a13: T,
         a21: T, a22: T, a23: T,
         a31: T, a32: T, a33: T,
@@ -506,7 +516,7 @@ impl<T: Number> Mat2<T> {
 
     /// Creates a new matrix with the given values. The values are specified
     /// row by row.
-    pub fn with_values(
+    pub const fn with_values(
         a11: T, a12: T,
         a21: T, a22: T,
     ) -> Mat2<T> {
@@ -606,30 +616,51 @@ impl<T: Number> Mul for Mat4<T> {
     type Output = Self;
 
     fn mul(self, other: Self) -> Self {
-        let a = self;
-        let b = other;
+        T::mat4_mul(&self, &other)
+    }
+}
 
-        Mat4 {
-            a11: a.a11*b.a11 + a.a12*b.a21 + a.a13*b.a31 + a.a14*b.a41,
-            a12: a.a11*b.a12 + a.a12*b.a22 + a.a13*b.a32 + a.a14*b.a42,
-            a13: a.a11*b.a13 + a.a12*b.a23 + a.a13*b.a33 + a.a14*b.a43,
-            a14: a.a11*b.a14 + a.a12*b.a24 + a.a13*b.a34 + a.a14*b.a44,
-
-            a21: a.a21*b.a11 + a.a22*b.a21 + a.a23*b.a31 + a.a24*b.a41,
-            a22: a.a21*b.a12 + a.a22*b.a22 + a.a23*b.a32 + a.a24*b.a42,
-            a23: a.a21*b.a13 + a.a22*b.a23 + a.a23*b.a33 + a.a24*b.a43,
-            a24: a.a21*b.a14 + a.a22*b.a24 + a.a23*b.a34 + a.a24*b.a44,
-
-            a31: a.a31*b.a11 + a.a32*b.a21 + a.a33*b.a31 + a.a34*b.a41,
-            a32: a.a31*b.a12 + a.a32*b.a22 + a.a33*b.a32 + a.a34*b.a42,
-            a33: a.a31*b.a13 + a.a32*b.a23 + a.a33*b.a33 + a.a34*b.a43,
-            a34: a.a31*b.a14 + a.a32*b.a24 + a.a33*b.a34 + a.a34*b.a44,
-
-            a41: a.a41*b.a11 + a.a42*b.a21 + a.a43*b.a31 + a.a44*b.a41,
-            a42: a.a41*b.a12 + a.a42*b.a22 + a.a43*b.a32 + a.a44*b.a42,
-            a43: a.a41*b.a13 + a.a42*b.a23 + a.a43*b.a33 + a.a44*b.a43,
-            a44: a.a41*b.a14 + a.a42*b.a24 + a.a43*b.a34 + a.a44*b.a44,
-        }
+// These are used by `Number` for types which do not have a SIMD implementation
+pub(crate) fn mat4_mul_scalar<T: Number>(a: &Mat4<T>, b: &Mat4<T>) -> Mat4<T> {
+    Mat4 {
+        a11: a.a11*b.a11 + a.a12*b.a21 + a.a13*b.a31 + a.a14*b.a41,
+        a12: a.a11*b.a12 + a.a12*b.a22 + a.a13*b.a32 + a.a14*b.a42,
+        a13: a.a11*b.a13 + a.a12*b.a23 + a.a13*b.a33 + a.a14*b.a43,
+        a14: a.a11*b.a14 + a.a12*b.a24 + a.a13*b.a34 + a.a14*b.a44,
+
+        a21: a.a21*b.a11 + a.a22*b.a21 + a.a23*b.a31 + a.a24*b.a41,
+        a22: a.a21*b.a12 + a.a22*b.a22 + a.a23*b.a32 + a.a24*b.a42,
+        a23: a.a21*b.a13 + a.a22*b.a23 + a.a23*b.a33 + a.a24*b.a43,
+        a24: a.a21*b.a14 + a.a22*b.a24 + a.a23*b.a34 + a.a24*b.a44,
+
+        a31: a.a31*b.a11 + a.a32*b.a21 + a.a33*b.a31 + a.a34*b.a41,
+        a32: a.a31*b.a12 + a.a32*b.a22 + a.a33*b.a32 + a.a34*b.a42,
+        a33: a.a31*b.a13 + a.a32*b.a23 + a.a33*b.a33 + a.a34*b.a43,
+        a34: a.a31*b.a14 + a.a32*b.a24 + a.a33*b.a34 + a.a34*b.a44,
+
+        a41: a.a41*b.a11 + a.a42*b.a21 + a.a43*b.a31 + a.a44*b.a41,
+        a42: a.a41*b.a12 + a.a42*b.a22 + a.a43*b.a32 + a.a44*b.a42,
+        a43: a.a41*b.a13 + a.a42*b.a23 + a.a43*b.a33 + a.a44*b.a43,
+        a44: a.a41*b.a14 + a.a42*b.a24 + a.a43*b.a34 + a.a44*b.a44,
+    }
+}
+
+pub(crate) fn mat4_mul_vec4_scalar<T: Number>(m: &Mat4<T>, v: Vec4<T>) -> Vec4<T> {
+    Vec4 {
+        x: m.a11*v.x + m.a12*v.y + m.a13*v.z + m.a14*v.w,
+        y: m.a21*v.x + m.a22*v.y + m.a23*v.z + m.a24*v.w,
+        z: m.a31*v.x + m.a32*v.y + m.a33*v.z + m.a34*v.w,
+        w: m.a41*v.x + m.a42*v.y + m.a43*v.z + m.a44*v.w,
+    }
+}
+
+pub(crate) fn mat4_transform_points_scalar<T: Number>(m: &Mat4<T>, points: &mut [Vec3<T>]) {
+    for p in points.iter_mut() {
+        *p = Vec3 {
+            x: m.a11*p.x + m.a12*p.y + m.a13*p.z + m.a14,
+            y: m.a21*p.x + m.a22*p.y + m.a23*p.z + m.a24,
+            z: m.a31*p.x + m.a32*p.y + m.a33*p.z + m.a34,
+        };
     }
 }
 
@@ -746,12 +777,7 @@ impl<T: Number> MulAssign<T> for Mat2<T> {
 impl<T: Number> Mul<Vec4<T>> for Mat4<T> {
     type Output = Vec4<T>;
     fn mul(self, v: Vec4<T>) -> Vec4<T> {
-        Vec4 {
-            x: self.a11*v.x + self.a12*v.y + self.a13*v.z + self.a14*v.w,
-            y: self.a21*v.x + self.a22*v.y + self.a23*v.z + self.a24*v.w,
-            z: self.a31*v.x + self.a32*v.y + self.a33*v.z + self.a34*v.w,
-            w: self.a41*v.x + self.a42*v.y + self.a43*v.z + self.a44*v.w,
-        }
+        T::mat4_mul_vec4(&self, v)
     }
 }
 
@@ -17,6 +17,14 @@ pub struct Quaternion<T> {
 
 impl<T: Copy> Copy for Quaternion<T> {}
 
+impl<T> Quaternion<T> {
+    /// Creates a new quaternion with the given components. Note that this does not normalize the
+    /// quaternion.
+    pub const fn new(x: T, y: T, z: T, w: T) -> Quaternion<T> {
+        Quaternion { x, y, z, w }
+    }
+}
+
 impl<T: Number + Float> Default for Quaternion<T> {
     fn default() -> Quaternion<T> {
         Quaternion::IDENTITY
@@ -48,6 +56,102 @@ impl<T: Number + Float> Quaternion<T> {
         }
     }
     
+    /// Same as [`rotation`], but with the arguments in the opposite order.
+    ///
+    /// [`rotation`]: #method.rotation
+    pub fn from_axis_angle(axis: Vec3<T>, angle: T) -> Quaternion<T> {
+        Quaternion::rotation(angle, axis)
+    }
+
+    /// Finds the axis and the angle, in radians, of the rotation represented by this quaternion.
+    /// This is the inverse of [`from_axis_angle`]. If this quaternion represents no rotation, the
+    /// axis is the x-axis.
+    ///
+    /// [`from_axis_angle`]: #method.from_axis_angle
+    pub fn to_axis_angle(self) -> (Vec3<T>, T) {
+        let q = self.normalize();
+        let w = clamp_unit(q.w);
+
+        let angle = (T::ONE + T::ONE) * w.acos();
+        let sin = (T::ONE - w*w).sqrt();
+
+        if sin > T::ZERO {
+            (Vec3::new(q.x / sin, q.y / sin, q.z / sin), angle)
+        } else {
+            (Vec3::X, T::ZERO)
+        }
+    }
+
+    /// Creates a quaternion which first rotates around the x-axis, then around the y-axis and
+    /// finally around the z-axis, by the components of `angles` in radians. All rotations are
+    /// around the fixed world axes.
+    pub fn from_euler(angles: Vec3<T>) -> Quaternion<T> {
+        Quaternion::rotation_z(angles.z) * Quaternion::rotation_y(angles.y) * Quaternion::rotation_x(angles.x)
+    }
+
+    /// Finds the angles which give this rotation when passed to [`from_euler`]. The y angle is in
+    /// the range `-π/2..π/2`, the other angles are in the range `-π..π`.
+    ///
+    /// [`from_euler`]: #method.from_euler
+    pub fn to_euler(self) -> Vec3<T> {
+        let one = T::ONE;
+        let two = one + one;
+        let Quaternion { x, y, z, w } = self.normalize();
+
+        // These are entries of the equivalent rotation matrix
+        let a11 = one - two*(y*y + z*z);
+        let a21 = two*(x*y + w*z);
+        let a31 = two*(x*z - w*y);
+        let a32 = two*(y*z + w*x);
+        let a33 = one - two*(x*x + y*y);
+
+        Vec3::new(
+            a32.atan2(a33),
+            clamp_unit(-a31).asin(),
+            a21.atan2(a11),
+        )
+    }
+
+    /// Creates a quaternion which rotates the negative z-axis onto `forward`, and the y-axis as
+    /// close to `up` as possible. This follows the OpenGL convention of cameras looking down the
+    /// negative z-axis. `forward` and `up` do not need to be normalized, but they may not be
+    /// parallel.
+    pub fn look_rotation(forward: Vec3<T>, up: Vec3<T>) -> Quaternion<T> {
+        let z = -forward.normalize();
+        let x = Vec3::cross(up, z).normalize();
+        let y = Vec3::cross(z, x);
+
+        Quaternion::from_basis(x, y, z)
+    }
+
+    // Converts the rotation matrix with the given columns to a quaternion. The columns should be
+    // orthonormal.
+    fn from_basis(x: Vec3<T>, y: Vec3<T>, z: Vec3<T>) -> Quaternion<T> {
+        let one = T::ONE;
+        let two = one + one;
+        let quarter = one / (two + two);
+
+        let (a11, a22, a33) = (x.x, y.y, z.z);
+        let trace = a11 + a22 + a33;
+
+        // Pick the largest component first, to avoid dividing by values close to zero
+        let q = if trace > T::ZERO {
+            let s = (trace + one).sqrt() * two;
+            Quaternion { w: quarter * s, x: (y.z - z.y) / s, y: (z.x - x.z) / s, z: (x.y - y.x) / s }
+        } else if a11 > a22 && a11 > a33 {
+            let s = (one + a11 - a22 - a33).sqrt() * two;
+            Quaternion { w: (y.z - z.y) / s, x: quarter * s, y: (y.x + x.y) / s, z: (z.x + x.z) / s }
+        } else if a22 > a33 {
+            let s = (one + a22 - a11 - a33).sqrt() * two;
+            Quaternion { w: (z.x - x.z) / s, x: (y.x + x.y) / s, y: quarter * s, z: (z.y + y.z) / s }
+        } else {
+            let s = (one + a33 - a11 - a22).sqrt() * two;
+            Quaternion { w: (x.y - y.x) / s, x: (z.x + x.z) / s, y: (z.y + y.z) / s, z: quarter * s }
+        };
+
+        q.normalize()
+    }
+
     /// Creates a quaternion representing a counterclockwise rotation of `angle` radians around the 
     /// x-axis.
     pub fn rotation_x(angle: T) -> Quaternion<T> {
@@ -128,6 +232,39 @@ impl<T: Number + Float> Quaternion<T> {
         }
     }
 
+    /// Interpolates between the two given quaternions along the shortest arc, at a constant
+    /// angular velocity. This is more expensive than [`nlerp`], but gives smoother motion for
+    /// quaternions which are far apart. `t` should be in the range `0..1`.
+    ///
+    /// slerp stands for spherical linear interpolation.
+    ///
+    /// [`nlerp`]: #method.nlerp
+    pub fn slerp(a: Quaternion<T>, b: Quaternion<T>, t: T) -> Quaternion<T> {
+        let mut b = b;
+        let mut cos = Quaternion::dot(a, b);
+
+        // `b` and `-b` represent the same rotation. Pick the one closest to `a`.
+        if cos < T::ZERO {
+            b = b * -T::ONE;
+            cos = -cos;
+        }
+
+        let angle = clamp_unit(cos).acos();
+        let sin = angle.sin();
+        if sin <= T::ZERO {
+            return a;
+        }
+
+        let wa = ((T::ONE - t)*angle).sin() / sin;
+        let wb = (t*angle).sin() / sin;
+        a*wa + b*wb
+    }
+
+    /// Rotates the given vector by this quaternion. This is the same as `self * vec`.
+    pub fn rotate_vec3(self, vec: Vec3<T>) -> Vec3<T> {
+        self * vec
+    }
+
     /// Returns a quaternion represention the oposite rotation. This inverts the x, y and z
     /// components of this quaternion.
     pub fn conjugate(self) -> Quaternion<T> {
@@ -140,6 +277,17 @@ impl<T: Number + Float> Quaternion<T> {
     }
 }
 
+// Limits the value to `-1..1`, to keep rounding errors from producing NaNs in `acos` and `asin`
+fn clamp_unit<T: Number + Float>(value: T) -> T {
+    if value > T::ONE {
+        T::ONE
+    } else if value < -T::ONE {
+        -T::ONE
+    } else {
+        value
+    }
+}
+
 // Quaternion vector multiplication
 impl<T: Number + Float> Mul<Vec3<T>> for Quaternion<T> {
     type Output = Vec3<T>; 
@@ -367,4 +515,45 @@ mod tests {
         let diff = Quaternion::angle_between(c, expected);
         assert!(diff < 0.001);
     }
+
+    #[test]
+    fn slerp() {
+        let a = Quaternion::IDENTITY;
+        let b = Quaternion::rotation(f32::consts::PI*0.9, Vec3::new(0.0, 1.0, 0.0));
+
+        let c = Quaternion::slerp(a, b, 0.25);
+        let expected = Quaternion::rotation(f32::consts::PI*0.225, Vec3::new(0.0, 1.0, 0.0));
+        assert!(Quaternion::dot(c, expected) > 0.9999);
+
+        assert_eq!(a, Quaternion::slerp(a, a, 0.5));
+    }
+
+    #[test]
+    fn axis_angle_and_euler() {
+        let axis = Vec3::new(1.0, 2.0, -2.0).normalize();
+        let (axis_b, angle) = Quaternion::from_axis_angle(axis, 1.2).to_axis_angle();
+        assert!((axis - axis_b).len() < 0.001);
+        assert!((angle - 1.2f32).abs() < 0.001);
+
+        let angles = Vec3::new(0.3, -0.7, 2.1);
+        let quat = Quaternion::from_euler(angles);
+        assert!((quat.to_euler() - angles).len() < 0.001);
+
+        let mat: Mat4<f32> = quat.into();
+        let v = Vec3::new(0.5, -1.0, 2.0);
+        let a = mat * Vec4::from3(v, 1.0);
+        let b = Mat4::rotation_z(angles.z) * Mat4::rotation_y(angles.y) * Mat4::rotation_x(angles.x) * Vec4::from3(v, 1.0);
+        assert!((a - b).len() < 0.001);
+    }
+
+    #[test]
+    fn look_rotation() {
+        let quat = Quaternion::look_rotation(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!((quat.rotate_vec3(Vec3::new(0.0, 0.0, -1.0)) - Vec3::new(1.0, 0.0, 0.0)).len() < 0.001);
+        assert!((quat.rotate_vec3(Vec3::new(0.0, 1.0, 0.0)) - Vec3::new(0.0, 1.0, 0.0)).len() < 0.001);
+
+        let forward = Vec3::new(-1.0, -2.0, 0.5);
+        let quat = Quaternion::look_rotation(forward, Vec3::new(0.0, 1.0, 0.0));
+        assert!((quat * Vec3::new(0.0, 0.0, -1.0) - forward.normalize()).len() < 0.001);
+    }
 }
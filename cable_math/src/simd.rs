@@ -0,0 +1,200 @@
+
+//! SIMD implementations of hot `Mat4<f32>` operations. These are hooked into the generic
+//! implementations through `Number`, so `f32` matrices use them automatically. Other
+//! architectures fall back to the scalar implementations in `mat.rs`.
+//!
+//! All functions rely on `Mat4` being stored in column major order, so each column can be loaded
+//! as a single 4-wide register.
+
+use vec::{Vec3, Vec4};
+use mat::Mat4;
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use std::arch::x86_64::*;
+
+    use vec::{Vec3, Vec4};
+    use mat::Mat4;
+
+    // SSE2 is part of the x86_64 baseline, so no runtime detection is needed
+
+    #[inline(always)]
+    unsafe fn columns(m: &Mat4<f32>) -> [__m128; 4] {
+        let p = m as *const Mat4<f32> as *const f32;
+        [_mm_loadu_ps(p), _mm_loadu_ps(p.add(4)), _mm_loadu_ps(p.add(8)), _mm_loadu_ps(p.add(12))]
+    }
+
+    #[inline(always)]
+    unsafe fn combine(c: &[__m128; 4], x: f32, y: f32, z: f32, w: f32) -> __m128 {
+        let a = _mm_add_ps(_mm_mul_ps(c[0], _mm_set1_ps(x)), _mm_mul_ps(c[1], _mm_set1_ps(y)));
+        let b = _mm_add_ps(_mm_mul_ps(c[2], _mm_set1_ps(z)), _mm_mul_ps(c[3], _mm_set1_ps(w)));
+        _mm_add_ps(a, b)
+    }
+
+    pub fn mat4_mul(a: &Mat4<f32>, b: &Mat4<f32>) -> Mat4<f32> {
+        let mut result = Mat4::ZERO;
+
+        unsafe {
+            let a = columns(a);
+            let b = b as *const Mat4<f32> as *const f32;
+            let out = &mut result as *mut Mat4<f32> as *mut f32;
+
+            // Each column of the result is a combination of the columns of `a`, weighted by the
+            // corresponding column of `b`.
+            for j in 0..4 {
+                let b = b.add(4*j);
+                let col = combine(&a, *b, *b.add(1), *b.add(2), *b.add(3));
+                _mm_storeu_ps(out.add(4*j), col);
+            }
+        }
+
+        result
+    }
+
+    pub fn mat4_mul_vec4(m: &Mat4<f32>, v: Vec4<f32>) -> Vec4<f32> {
+        let mut result = Vec4::ZERO;
+        unsafe {
+            let c = columns(m);
+            let out = &mut result as *mut Vec4<f32> as *mut f32;
+            _mm_storeu_ps(out, combine(&c, v.x, v.y, v.z, v.w));
+        }
+        result
+    }
+
+    pub fn mat4_transform_points(m: &Mat4<f32>, points: &mut [Vec3<f32>]) {
+        unsafe {
+            let c = columns(m);
+            let mut out = [0.0f32; 4];
+
+            for p in points.iter_mut() {
+                _mm_storeu_ps(out.as_mut_ptr(), combine(&c, p.x, p.y, p.z, 1.0));
+                *p = Vec3::new(out[0], out[1], out[2]);
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use std::arch::aarch64::*;
+
+    use vec::{Vec3, Vec4};
+    use mat::Mat4;
+
+    // NEON is part of the aarch64 baseline, so no runtime detection is needed
+
+    #[inline(always)]
+    unsafe fn columns(m: &Mat4<f32>) -> [float32x4_t; 4] {
+        let p = m as *const Mat4<f32> as *const f32;
+        [vld1q_f32(p), vld1q_f32(p.add(4)), vld1q_f32(p.add(8)), vld1q_f32(p.add(12))]
+    }
+
+    #[inline(always)]
+    unsafe fn combine(c: &[float32x4_t; 4], x: f32, y: f32, z: f32, w: f32) -> float32x4_t {
+        let a = vaddq_f32(vmulq_n_f32(c[0], x), vmulq_n_f32(c[1], y));
+        let b = vaddq_f32(vmulq_n_f32(c[2], z), vmulq_n_f32(c[3], w));
+        vaddq_f32(a, b)
+    }
+
+    pub fn mat4_mul(a: &Mat4<f32>, b: &Mat4<f32>) -> Mat4<f32> {
+        let mut result = Mat4::ZERO;
+
+        unsafe {
+            let a = columns(a);
+            let b = b as *const Mat4<f32> as *const f32;
+            let out = &mut result as *mut Mat4<f32> as *mut f32;
+
+            for j in 0..4 {
+                let b = b.add(4*j);
+                let col = combine(&a, *b, *b.add(1), *b.add(2), *b.add(3));
+                vst1q_f32(out.add(4*j), col);
+            }
+        }
+
+        result
+    }
+
+    pub fn mat4_mul_vec4(m: &Mat4<f32>, v: Vec4<f32>) -> Vec4<f32> {
+        let mut result = Vec4::ZERO;
+        unsafe {
+            let c = columns(m);
+            let out = &mut result as *mut Vec4<f32> as *mut f32;
+            vst1q_f32(out, combine(&c, v.x, v.y, v.z, v.w));
+        }
+        result
+    }
+
+    pub fn mat4_transform_points(m: &Mat4<f32>, points: &mut [Vec3<f32>]) {
+        unsafe {
+            let c = columns(m);
+            let mut out = [0.0f32; 4];
+
+            for p in points.iter_mut() {
+                vst1q_f32(out.as_mut_ptr(), combine(&c, p.x, p.y, p.z, 1.0));
+                *p = Vec3::new(out[0], out[1], out[2]);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod arch {
+    use vec::{Vec3, Vec4};
+    use mat::{self, Mat4};
+
+    pub fn mat4_mul(a: &Mat4<f32>, b: &Mat4<f32>) -> Mat4<f32> {
+        mat::mat4_mul_scalar(a, b)
+    }
+
+    pub fn mat4_mul_vec4(m: &Mat4<f32>, v: Vec4<f32>) -> Vec4<f32> {
+        mat::mat4_mul_vec4_scalar(m, v)
+    }
+
+    pub fn mat4_transform_points(m: &Mat4<f32>, points: &mut [Vec3<f32>]) {
+        mat::mat4_transform_points_scalar(m, points)
+    }
+}
+
+pub fn mat4_mul(a: &Mat4<f32>, b: &Mat4<f32>) -> Mat4<f32> {
+    arch::mat4_mul(a, b)
+}
+
+pub fn mat4_mul_vec4(m: &Mat4<f32>, v: Vec4<f32>) -> Vec4<f32> {
+    arch::mat4_mul_vec4(m, v)
+}
+
+pub fn mat4_transform_points(m: &Mat4<f32>, points: &mut [Vec3<f32>]) {
+    arch::mat4_transform_points(m, points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mat;
+
+    #[test]
+    fn matches_scalar() {
+        let a = Mat4::with_values(
+            1.0, 7.0, 4.0, 3.0,
+            5.0, 6.0, 7.0, 8.0,
+            9.0, 2.0, 3.0, 1.0,
+            6.0, 6.0, 2.0, 7.0,
+        );
+        let b = Mat4::with_values(
+            7.0, 8.0, 2.0, 9.0,
+            1.0, 3.0, 5.0, 2.0,
+            3.0, 6.0, 3.0, 7.0,
+            2.0, 7.0, 3.0, 8.0,
+        );
+        let v = Vec4::new(1.0, -2.0, 3.0, 0.5);
+
+        assert_eq!(mat::mat4_mul_scalar(&a, &b), mat4_mul(&a, &b));
+        assert_eq!(mat::mat4_mul_vec4_scalar(&a, v), mat4_mul_vec4(&a, v));
+
+        let mut points = [Vec3::new(1.0, 2.0, 3.0), Vec3::new(-4.0, 0.0, 0.25)];
+        let mut expected = points;
+        mat::mat4_transform_points_scalar(&a, &mut expected);
+        mat4_transform_points(&a, &mut points);
+        assert_eq!(expected, points);
+    }
+}
@@ -1,5 +1,6 @@
 
 use {Vec2, Vec3, Vec4, Mat2, Mat3, Mat4, Quaternion};
+use {mat, simd};
 
 use std::ops::{Add, Sub, Mul, Div};
 use std::ops::{AddAssign, SubAssign, MulAssign, DivAssign};
@@ -15,6 +16,24 @@ pub trait Number:
 {
     const ONE: Self;
     const ZERO: Self;
+
+    // Hooks which allow individual number types to provide faster implementations of some
+    // operations. `f32` uses these to provide SIMD versions.
+
+    #[doc(hidden)]
+    fn mat4_mul(a: &Mat4<Self>, b: &Mat4<Self>) -> Mat4<Self> {
+        mat::mat4_mul_scalar(a, b)
+    }
+
+    #[doc(hidden)]
+    fn mat4_mul_vec4(m: &Mat4<Self>, v: Vec4<Self>) -> Vec4<Self> {
+        mat::mat4_mul_vec4_scalar(m, v)
+    }
+
+    #[doc(hidden)]
+    fn mat4_transform_points(m: &Mat4<Self>, points: &mut [Vec3<Self>]) {
+        mat::mat4_transform_points_scalar(m, points)
+    }
 }
 
 /// Allows us to be generic over signed numeric types in vectors
@@ -46,9 +65,26 @@ impl_number!(u8,  1, 0);
 impl_number!(u16, 1, 0);
 impl_number!(u32, 1, 0);
 impl_number!(u64, 1, 0);
-impl_number!(f32, 1.0, 0.0);
 impl_number!(f64, 1.0, 0.0);
 
+// f32 provides SIMD implementations of the hooks
+impl Number for f32 {
+    const ONE: Self = 1.0;
+    const ZERO: Self = 0.0;
+
+    fn mat4_mul(a: &Mat4<f32>, b: &Mat4<f32>) -> Mat4<f32> {
+        simd::mat4_mul(a, b)
+    }
+
+    fn mat4_mul_vec4(m: &Mat4<f32>, v: Vec4<f32>) -> Vec4<f32> {
+        simd::mat4_mul_vec4(m, v)
+    }
+
+    fn mat4_transform_points(m: &Mat4<f32>, points: &mut [Vec3<f32>]) {
+        simd::mat4_transform_points(m, points)
+    }
+}
+
 impl Signed for i8 {}
 impl Signed for i16 {}
 impl Signed for i32 {}
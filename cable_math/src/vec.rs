@@ -38,15 +38,15 @@ impl<T: Copy> Copy for Vec4<T> {}
 // Constructors
 impl<T> Vec2<T> {
     /// Creates a new vector with the given components
-    pub fn new(x: T, y: T) -> Vec2<T> { Vec2 { x: x, y: y } }
+    pub const fn new(x: T, y: T) -> Vec2<T> { Vec2 { x: x, y: y } }
 }
 impl<T> Vec3<T> {
     /// Creates a new vector with the given components
-    pub fn new(x: T, y: T, z: T) -> Vec3<T> { Vec3 { x: x, y: y, z: z } }
+    pub const fn new(x: T, y: T, z: T) -> Vec3<T> { Vec3 { x: x, y: y, z: z } }
 }
 impl<T> Vec4<T> {
     /// Creates a new vector with the given components
-    pub fn new(x: T, y: T, z: T, w: T) -> Vec4<T> { Vec4 { x: x, y: y, z: z, w: w } }
+    pub const fn new(x: T, y: T, z: T, w: T) -> Vec4<T> { Vec4 { x: x, y: y, z: z, w: w } }
 }
 
 // General functions
@@ -585,27 +585,27 @@ impl<T: Float> Vec4<T> {
 // Swizzling
 impl<T: Number> Vec3<T> {
     /// Equal to `Vec3::new(vec.x, vec.y, z)`
-    pub fn from2(vec: Vec2<T>, z: T) -> Vec3<T> { Vec3 { x: vec.x, y: vec.y, z: z } }
+    pub const fn from2(vec: Vec2<T>, z: T) -> Vec3<T> { Vec3 { x: vec.x, y: vec.y, z: z } }
     /// Equal to `Vec2::new(vec.x, vec.y)`.
-    pub fn xy(self) -> Vec2<T> { Vec2 { x: self.x, y: self.y } }
+    pub const fn xy(self) -> Vec2<T> { Vec2 { x: self.x, y: self.y } }
     /// Equal to `Vec2::new(vec.x, vec.z)`.
-    pub fn xz(self) -> Vec2<T> { Vec2 { x: self.x, y: self.z } }
+    pub const fn xz(self) -> Vec2<T> { Vec2 { x: self.x, y: self.z } }
     /// Equal to `Vec2::new(vec.y, vec.z)`.
-    pub fn yz(self) -> Vec2<T> { Vec2 { x: self.y, y: self.z } }
+    pub const fn yz(self) -> Vec2<T> { Vec2 { x: self.y, y: self.z } }
 }
 impl<T: Number> Vec4<T> {
     /// Equal to `Vec4::new(vec.x, vec.y, vec.z, w)`
-    pub fn from3(vec: Vec3<T>, w: T) -> Vec4<T> { Vec4 { x: vec.x, y: vec.y, z: vec.z, w: w } }
+    pub const fn from3(vec: Vec3<T>, w: T) -> Vec4<T> { Vec4 { x: vec.x, y: vec.y, z: vec.z, w: w } }
     /// Equal to `Vec4::new(vec.x, vec.y, z, w)`
-    pub fn from2(vec: Vec2<T>, z: T, w: T) -> Vec4<T> { Vec4 { x: vec.x, y: vec.y, z: z, w: w } }
+    pub const fn from2(vec: Vec2<T>, z: T, w: T) -> Vec4<T> { Vec4 { x: vec.x, y: vec.y, z: z, w: w } }
     /// Equal to `Vec4::new(vec.x, vec.y, vec.z)`
-    pub fn xyz(self) -> Vec3<T> { Vec3 { x: self.x, y: self.y, z: self.z } }
+    pub const fn xyz(self) -> Vec3<T> { Vec3 { x: self.x, y: self.y, z: self.z } }
     /// Equal to `Vec2::new(vec.x, vec.y)`.
-    pub fn xy(self) -> Vec2<T> { Vec2 { x: self.x, y: self.y } }
+    pub const fn xy(self) -> Vec2<T> { Vec2 { x: self.x, y: self.y } }
     /// Equal to `Vec2::new(vec.x, vec.z)`.
-    pub fn xz(self) -> Vec2<T> { Vec2 { x: self.x, y: self.z } }
+    pub const fn xz(self) -> Vec2<T> { Vec2 { x: self.x, y: self.z } }
     /// Equal to `Vec2::new(vec.y, vec.z)`.
-    pub fn yz(self) -> Vec2<T> { Vec2 { x: self.y, y: self.z } }
+    pub const fn yz(self) -> Vec2<T> { Vec2 { x: self.y, y: self.z } }
 }
 
 // Addition, subtraction and scaling
@@ -817,6 +817,46 @@ impl<T: fmt::Display + Number> fmt::Display for Vec4<T> {
     }
 }
 
+// Componentwise comparisons, mostly useful for integer vectors
+macro_rules! impl_componentwise {
+    ($vec:ident, $($field:ident),+) => {
+        impl<T: Number> $vec<T> {
+            /// The componentwise minimum of the two vectors
+            pub fn min(a: $vec<T>, b: $vec<T>) -> $vec<T> {
+                $vec { $($field: if b.$field < a.$field { b.$field } else { a.$field }),+ }
+            }
+
+            /// The componentwise maximum of the two vectors
+            pub fn max(a: $vec<T>, b: $vec<T>) -> $vec<T> {
+                $vec { $($field: if b.$field > a.$field { b.$field } else { a.$field }),+ }
+            }
+
+            /// Limits each component of this vector to lie between the corresponding components of
+            /// `min` and `max`.
+            pub fn clamp(self, min: $vec<T>, max: $vec<T>) -> $vec<T> {
+                $vec::min($vec::max(self, min), max)
+            }
+
+            /// The componentwise absolute difference of the two vectors. Unlike `(a - b).abs()`
+            /// this does not underflow for unsigned components.
+            pub fn abs_diff(a: $vec<T>, b: $vec<T>) -> $vec<T> {
+                $vec { $($field: if a.$field > b.$field { a.$field - b.$field } else { b.$field - a.$field }),+ }
+            }
+
+            /// The manhattan (taxicab) distance between the two vectors, i.e. the sum of the
+            /// components of `abs_diff(a, b)`.
+            pub fn manhattan(a: $vec<T>, b: $vec<T>) -> T {
+                let diff = $vec::abs_diff(a, b);
+                T::ZERO $(+ diff.$field)+
+            }
+        }
+    };
+}
+
+impl_componentwise!(Vec2, x, y);
+impl_componentwise!(Vec3, x, y, z);
+impl_componentwise!(Vec4, x, y, z, w);
+
 // Fake casting stuff
 macro_rules! impl_cast {
     ($a:ty, $b:ty, $fn_name:ident) => {
@@ -878,6 +918,39 @@ impl_cast!(f64, u64, as_u64);
 impl_cast!(f64, f32, as_f32);
 impl_cast!(f32, f64, as_f64);
 
+impl_cast!(u8,   u32,  as_u32);
+impl_cast!(i8,   u32,  as_u32);
+impl_cast!(u16,  u32,  as_u32);
+impl_cast!(i16,  u32,  as_u32);
+impl_cast!(i32,  u32,  as_u32);
+impl_cast!(u64,  u32,  as_u32);
+impl_cast!(i64,  u32,  as_u32);
+impl_cast!(usize, u32,  as_u32);
+impl_cast!(isize, u32,  as_u32);
+
+impl_cast!(u8,   i32,  as_i32);
+impl_cast!(i8,   i32,  as_i32);
+impl_cast!(u16,  i32,  as_i32);
+impl_cast!(i16,  i32,  as_i32);
+impl_cast!(u32,  i32,  as_i32);
+impl_cast!(u64,  i32,  as_i32);
+impl_cast!(i64,  i32,  as_i32);
+impl_cast!(usize, i32,  as_i32);
+impl_cast!(isize, i32,  as_i32);
+
+impl_cast!(u8,   usize, as_usize);
+impl_cast!(i8,   usize, as_usize);
+impl_cast!(u16,  usize, as_usize);
+impl_cast!(i16,  usize, as_usize);
+impl_cast!(u32,  usize, as_usize);
+impl_cast!(i32,  usize, as_usize);
+impl_cast!(u64,  usize, as_usize);
+impl_cast!(i64,  usize, as_usize);
+impl_cast!(isize, usize, as_usize);
+
+impl_cast!(usize, f32, as_f32);
+impl_cast!(isize, f32, as_f32);
+
 // Tuple to vector conversions
 impl<T> From<(T, T)> for Vec2<T> {
     fn from((x, y): (T, T)) -> Vec2<T> {
@@ -993,5 +1066,21 @@ mod tests {
         a *= 2;
         assert_eq!(Vec4::new(6, 8, 2, 4), a);
     }
+
+    #[test]
+    fn integer_ops() {
+        const TILE: Vec2<u32> = Vec2::new(3, 7);
+
+        let a = Vec2::new(1u32, 9);
+        assert_eq!(Vec2::new(1, 7), Vec2::min(a, TILE));
+        assert_eq!(Vec2::new(3, 9), Vec2::max(a, TILE));
+        assert_eq!(Vec2::new(2, 8), Vec2::new(0, 12).clamp(Vec2::new(2, 2), Vec2::new(8, 8)));
+        assert_eq!(Vec2::new(2, 2), Vec2::abs_diff(a, TILE));
+        assert_eq!(4, Vec2::manhattan(a, TILE));
+        assert_eq!(6, Vec3::manhattan(Vec3::new(-1, 2, 0), Vec3::new(1, 0, 2)));
+
+        assert_eq!(Vec2::new(3usize, 7), TILE.as_usize());
+        assert_eq!(Vec2::new(-1i32, 4).as_u32(), Vec2::new(u32::max_value(), 4));
+    }
 }
 
@@ -1,6 +1,7 @@
 
 //! Provides #[derive(Vertex)], which is used to define custom types which can be stored in vertex
-//! buffers and accessed from shaders
+//! buffers and accessed from shaders, and #[derive(Uniforms)], which is used to set groups of
+//! shader uniforms from a struct
 
 // TODO (Morten, 09.12.17) Check for repr(C)!
 
@@ -62,6 +63,7 @@ fn impl_vertex(ident: Ident, variant_data: VariantData) -> quote::Tokens {
             let mut setup_attrib_pointers_impl = Vec::with_capacity(fields.len()); 
             let mut shader_input_impl = Vec::with_capacity(fields.len());
             let mut single_attrib_impl = Vec::with_capacity(fields.len());
+            let mut attrib_bindings_impl = Vec::with_capacity(fields.len());
 
             let mut next_location = 0;
             for field in fields.iter() {
@@ -116,6 +118,21 @@ fn impl_vertex(ident: Ident, variant_data: VariantData) -> quote::Tokens {
                     index += 1;
                 });
 
+                attrib_bindings_impl.push(quote! {
+                    result.push((stringify!(#ident), ::gondola::buffer::AttribBinding {
+                        index: #location,
+                        primitives: <#ty as ::gondola::buffer::VertexData>::primitives(),
+                        primitive_type: <<#ty as ::gondola::buffer::VertexData>::Primitive as ::gondola::buffer::GlPrimitive>::GL_ENUM,
+                        normalized: false,
+                        integer: <<#ty as ::gondola::buffer::VertexData>::Primitive as ::gondola::buffer::GlPrimitive>::IS_INTEGER,
+                        stride,
+                        offset,
+                        divisor: 0,
+                    }));
+
+                    offset += ::std::mem::size_of::<#ty>();
+                });
+
                 single_attrib_impl.push(quote! {
                     <#ty as ::gondola::buffer::VertexData>::set_as_vertex_attrib(&self.#ident, #location);
                 });
@@ -142,6 +159,17 @@ fn impl_vertex(ident: Ident, variant_data: VariantData) -> quote::Tokens {
                 result
             };
 
+            // Join all the attribute binding code
+            let field_count = fields.len();
+            let attrib_bindings_impl = quote! {
+                let stride = ::std::mem::size_of::<#ident>();
+                let mut offset = 0;
+
+                let mut result = Vec::with_capacity(#field_count);
+                #( #attrib_bindings_impl )*
+                result
+            };
+
             // Join all the single attribute setting code
             let single_attrib_impl = quote! {
                 #( #single_attrib_impl )*
@@ -207,6 +235,10 @@ fn impl_vertex(ident: Ident, variant_data: VariantData) -> quote::Tokens {
                     fn set_as_vertex_attrib(&self) {
                         #single_attrib_impl
                     }
+
+                    fn attrib_bindings() -> Vec<(&'static str, ::gondola::buffer::AttribBinding)> {
+                        #attrib_bindings_impl
+                    }
                 }
             }
         },
@@ -219,3 +251,91 @@ fn impl_vertex(ident: Ident, variant_data: VariantData) -> quote::Tokens {
     }
 }
 
+
+#[proc_macro_derive(Uniforms, attributes(uniform))]
+pub fn uniforms(input: TokenStream) -> TokenStream {
+    let s = input.to_string();
+    let ast = syn::parse_macro_input(&s).unwrap();
+
+    let ident = ast.ident;
+    let gen = match ast.body {
+        Body::Enum(..) => panic!("#[derive(Uniforms)] is only defined for structs, not enums"),
+        Body::Struct(variant_data) => impl_uniforms(ident, variant_data)
+    };
+
+    gen.parse().unwrap()
+}
+
+fn impl_uniforms(ident: Ident, variant_data: VariantData) -> quote::Tokens {
+    match variant_data {
+        VariantData::Struct(fields) => {
+            if fields.is_empty() {
+                panic!("Can't #[derive(Uniforms)] for a struct with no fields");
+            }
+
+            fn get_name(field: &Field) -> Option<String> {
+                for attribute in field.attrs.iter() {
+                    if attribute.name() == "uniform" {
+                        if let MetaItem::List(_, ref items) = attribute.value {
+                            for item in items.iter() {
+                                if let NestedMetaItem::MetaItem(MetaItem::NameValue(ref name, Lit::Str(ref v, _))) = *item {
+                                    if name == "name" {
+                                        return Some(v.clone());
+                                    }
+                                }
+                            }
+                        }
+
+                        panic!("Expected #[uniform(name = \"<name>\")]");
+                    }
+                }
+
+                return None;
+            }
+
+            let mut uniform_list_impl = Vec::with_capacity(fields.len());
+            let mut apply_impl = Vec::with_capacity(fields.len());
+
+            for field in fields.iter() {
+                let ty = field.ty.clone();
+                let ident = field.ident.clone();
+
+                let name = match get_name(field) {
+                    Some(name) => name,
+                    None => ident.as_ref().unwrap().to_string(),
+                };
+
+                uniform_list_impl.push(quote! {
+                    (#name, <#ty as ::gondola::shader::UniformValue>::KIND)
+                });
+
+                // Errors are reported by `validate_uniforms`, so we don't report them again here
+                apply_impl.push(quote! {
+                    shader.try_set_uniform::<#ty>(#name, &self.#ident);
+                });
+            }
+
+            // Join all the code into a single implementation
+            quote! {
+                impl ::gondola::shader::Uniforms for #ident {
+                    fn apply(&self, shader: &::gondola::shader::Shader) {
+                        // This is a static, so each struct gets a unique address which
+                        // `validate_uniforms` uses to only check it once per shader
+                        static UNIFORMS: &'static [(&'static str, ::gondola::shader::UniformKind)] = &[
+                            #( #uniform_list_impl ),*
+                        ];
+                        shader.validate_uniforms(UNIFORMS);
+
+                        #( #apply_impl )*
+                    }
+                }
+            }
+        },
+        VariantData::Tuple(..) => {
+            panic!("#[derive(Uniforms)] is not defined for tupple structs");
+        },
+        VariantData::Unit => {
+            panic!("#[derive(Uniforms)] is not defined for unit structs");
+        }
+    }
+}
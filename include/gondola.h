@@ -0,0 +1,164 @@
+/*
+ * C api for gondola, enabled by the `ffi` cargo feature. See `src/c_api.rs` for details.
+ *
+ * Windows, inputs and draw groups are opaque handles, created and destroyed through the functions
+ * below. All functions must be called from the thread which created the window, and expect valid
+ * handles. Strings are nul terminated utf8.
+ *
+ *     GondolaWindowDesc desc = gondola_window_desc_default();
+ *     desc.title = "My title";
+ *
+ *     GondolaWindow *window = gondola_window_create(&desc);
+ *     if (!window) {
+ *         printf("%s\n", gondola_last_error());
+ *         return 1;
+ *     }
+ *     GondolaInput *input = gondola_input_create();
+ *     GondolaDrawGroup *group = gondola_draw_group_create();
+ *     gondola_window_show(window);
+ *
+ *     while (!gondola_window_close_requested(window)) {
+ *         gondola_window_poll_events(window, input);
+ *
+ *         gondola_draw_group_reset(group);
+ *         gondola_draw_group_circle(group, gondola_input_mouse_pos(input), 10.0f, red);
+ *         gondola_draw_group_draw(group, screen_transform, gondola_window_size(window));
+ *
+ *         gondola_window_swap_buffers(window);
+ *     }
+ *
+ *     gondola_draw_group_destroy(group);
+ *     gondola_input_destroy(input);
+ *     gondola_window_destroy(window);
+ */
+
+#ifndef GONDOLA_H
+#define GONDOLA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct GondolaWindow GondolaWindow;
+typedef struct GondolaInput GondolaInput;
+typedef struct GondolaDrawGroup GondolaDrawGroup;
+
+typedef struct GondolaVec2 {
+    float x, y;
+} GondolaVec2;
+
+/* Components are between 0 and 1 */
+typedef struct GondolaColor {
+    float r, g, b, a;
+} GondolaColor;
+
+typedef struct GondolaWindowDesc {
+    const char *title;
+    GondolaVec2 size;
+    bool resizable;
+    bool srgb;
+    uint32_t samples;
+    bool decorations;
+} GondolaWindowDesc;
+
+/* Returned by `gondola_input_key` and `gondola_input_mouse_key` */
+enum {
+    GONDOLA_KEY_STATE_UP             = 0,
+    GONDOLA_KEY_STATE_PRESSED        = 1,
+    GONDOLA_KEY_STATE_PRESSED_REPEAT = 2,
+    GONDOLA_KEY_STATE_DOWN           = 3,
+    GONDOLA_KEY_STATE_RELEASED       = 4,
+};
+
+/* Keys, in the same order as `Key`. These refer to positions on a american keyboard layout. */
+enum {
+    GONDOLA_KEY_1, GONDOLA_KEY_2, GONDOLA_KEY_3, GONDOLA_KEY_4, GONDOLA_KEY_5,
+    GONDOLA_KEY_6, GONDOLA_KEY_7, GONDOLA_KEY_8, GONDOLA_KEY_9, GONDOLA_KEY_0,
+
+    GONDOLA_KEY_Q, GONDOLA_KEY_W, GONDOLA_KEY_E, GONDOLA_KEY_R, GONDOLA_KEY_T,
+    GONDOLA_KEY_Y, GONDOLA_KEY_U, GONDOLA_KEY_I, GONDOLA_KEY_O, GONDOLA_KEY_P,
+    GONDOLA_KEY_A, GONDOLA_KEY_S, GONDOLA_KEY_D, GONDOLA_KEY_F, GONDOLA_KEY_G,
+    GONDOLA_KEY_H, GONDOLA_KEY_J, GONDOLA_KEY_K, GONDOLA_KEY_L,
+    GONDOLA_KEY_Z, GONDOLA_KEY_X, GONDOLA_KEY_C, GONDOLA_KEY_V, GONDOLA_KEY_B,
+    GONDOLA_KEY_N, GONDOLA_KEY_M,
+
+    GONDOLA_KEY_SPACE,
+
+    GONDOLA_KEY_ESCAPE, GONDOLA_KEY_GRAVE, GONDOLA_KEY_TAB, GONDOLA_KEY_CAPS_LOCK,
+    GONDOLA_KEY_LSHIFT, GONDOLA_KEY_LCTRL, GONDOLA_KEY_LALT,
+    GONDOLA_KEY_RALT, GONDOLA_KEY_RMETA, GONDOLA_KEY_RCTRL, GONDOLA_KEY_RSHIFT,
+    GONDOLA_KEY_RETURN, GONDOLA_KEY_BACK,
+
+    GONDOLA_KEY_RIGHT, GONDOLA_KEY_LEFT, GONDOLA_KEY_DOWN, GONDOLA_KEY_UP,
+
+    GONDOLA_KEY_INSERT, GONDOLA_KEY_DELETE, GONDOLA_KEY_HOME, GONDOLA_KEY_END,
+    GONDOLA_KEY_PAGE_UP, GONDOLA_KEY_PAGE_DOWN,
+
+    GONDOLA_KEY_F1, GONDOLA_KEY_F2, GONDOLA_KEY_F3, GONDOLA_KEY_F4,
+    GONDOLA_KEY_F5, GONDOLA_KEY_F6, GONDOLA_KEY_F7, GONDOLA_KEY_F8,
+    GONDOLA_KEY_F9, GONDOLA_KEY_F10, GONDOLA_KEY_F11, GONDOLA_KEY_F12,
+};
+
+/* The last error on this thread. Valid until the next function which can fail is called. */
+const char *gondola_last_error(void);
+
+/* Windows */
+GondolaWindowDesc gondola_window_desc_default(void);
+/* Also creates a OpenGL context and makes it current. Returns NULL on failure. */
+GondolaWindow *gondola_window_create(const GondolaWindowDesc *desc);
+void gondola_window_destroy(GondolaWindow *window);
+void gondola_window_show(GondolaWindow *window);
+void gondola_window_poll_events(GondolaWindow *window, GondolaInput *input);
+void gondola_window_swap_buffers(GondolaWindow *window);
+bool gondola_window_close_requested(const GondolaWindow *window);
+bool gondola_window_resized(const GondolaWindow *window);
+GondolaVec2 gondola_window_size(const GondolaWindow *window);
+void gondola_window_set_title(GondolaWindow *window, const char *title);
+void gondola_window_set_vsync(GondolaWindow *window, bool vsync);
+
+/* Input */
+GondolaInput *gondola_input_create(void);
+void gondola_input_destroy(GondolaInput *input);
+uint32_t gondola_input_key(const GondolaInput *input, uint32_t key);
+/* 0 is left, 1 is right and 2 is middle */
+uint32_t gondola_input_mouse_key(const GondolaInput *input, uint32_t index);
+GondolaVec2 gondola_input_mouse_pos(const GondolaInput *input);
+GondolaVec2 gondola_input_mouse_delta(const GondolaInput *input);
+GondolaVec2 gondola_input_scroll(const GondolaInput *input);
+/* Copies the text typed this frame into `buffer` if it fits, and returns its length in bytes
+ * without the nul terminator. Pass a NULL buffer to find the required size. */
+size_t gondola_input_text(const GondolaInput *input, char *buffer, size_t buffer_len);
+
+/* Draw groups. These require a current OpenGL context. */
+GondolaDrawGroup *gondola_draw_group_create(void);
+void gondola_draw_group_destroy(GondolaDrawGroup *group);
+bool gondola_draw_group_load_font(GondolaDrawGroup *group, uint32_t key, const char *path);
+bool gondola_draw_group_load_texture(GondolaDrawGroup *group, uint32_t key, const char *path);
+void gondola_draw_group_reset(GondolaDrawGroup *group);
+/* `transform` is a 4x4 matrix in column major order */
+void gondola_draw_group_draw(GondolaDrawGroup *group, const float *transform, GondolaVec2 win_size);
+void gondola_draw_group_set_layer(GondolaDrawGroup *group, size_t layer);
+
+void gondola_draw_group_line(GondolaDrawGroup *group, GondolaVec2 a, GondolaVec2 b, float width, GondolaColor color);
+void gondola_draw_group_polyline(GondolaDrawGroup *group, const GondolaVec2 *points, size_t count, float width, GondolaColor color);
+void gondola_draw_group_triangle(GondolaDrawGroup *group, GondolaVec2 a, GondolaVec2 b, GondolaVec2 c, GondolaColor color);
+void gondola_draw_group_aabb(GondolaDrawGroup *group, GondolaVec2 min, GondolaVec2 max, GondolaColor color);
+void gondola_draw_group_rounded_aabb(GondolaDrawGroup *group, GondolaVec2 min, GondolaVec2 max, float corner_radius, GondolaColor color);
+void gondola_draw_group_textured_aabb(GondolaDrawGroup *group, uint32_t texture, GondolaVec2 min, GondolaVec2 max);
+void gondola_draw_group_circle(GondolaDrawGroup *group, GondolaVec2 center, float radius, GondolaColor color);
+void gondola_draw_group_ellipse(GondolaDrawGroup *group, GondolaVec2 center, GondolaVec2 radii, GondolaColor color);
+void gondola_draw_group_ring(GondolaDrawGroup *group, GondolaVec2 center, float inner_radius, float outer_radius, GondolaColor color);
+/* Angles are in radians, from the positive x axis towards the positive y axis */
+void gondola_draw_group_pie(GondolaDrawGroup *group, GondolaVec2 center, float radius, float start_angle, float end_angle, GondolaColor color);
+/* Text is wrapped if `wrap_width` is larger than zero */
+void gondola_draw_group_text(GondolaDrawGroup *group, const char *text, uint32_t font, float size, GondolaVec2 pos, float wrap_width, GondolaColor color);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
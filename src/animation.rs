@@ -0,0 +1,839 @@
+
+//! Animated images, loaded from animated GIF or APNG files. See [`Texture::load_animation`].
+//!
+//! ```rust,ignore
+//! let spinner = Texture::load_animation("assets/spinner.gif")?;
+//! let mut player = AnimationPlayer::new();
+//!
+//! // Each frame
+//! player.advance(&spinner, delta);
+//! spinner.frame(player.frame()).bind(0);
+//! ```
+//!
+//! [`Texture::load_animation`]: ../texture/struct.Texture.html#method.load_animation
+
+use std::fs;
+use std::path::Path;
+
+use png;
+
+use Time;
+use texture::{Texture, TextureFormat, RawImageData, TextureError, TextureErrorKind};
+use image_decoder;
+
+// Used for frames without a delay, or with very short delays, which browsers also slow down
+const DEFAULT_DELAY_MS: u64 = 100;
+
+// Largest canvas width or height we accept. Frames are uploaded as textures, which can't be larger
+// than this on most hardware anyway, and it keeps small corrupt files from claiming huge canvases.
+const MAX_CANVAS_SIZE: u32 = 16384;
+
+/// The decoded frames of a animated image. This can be loaded on any thread, and then turned
+/// into a [`Animation`] on the rendering thread, like [`RawImageData`].
+///
+/// [`Animation`]: struct.Animation.html
+/// [`RawImageData`]: ../texture/struct.RawImageData.html
+pub struct RawAnimationData {
+    frames: Vec<RawImageData>,
+    delays: Vec<Time>,
+}
+
+impl RawAnimationData {
+    /// Decodes a animated GIF or APNG file. Other image files, including PNG files without
+    /// animation, are loaded as a single frame. Does not invoke any OpenGL functions, and can
+    /// thus be called from any thread.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RawAnimationData, TextureError> {
+        let path = path.as_ref();
+        let source = path.to_string_lossy();
+
+        let bytes = fs::read(path).map_err(|err| TextureError::new(Some(source.as_ref()), TextureErrorKind::Io(err)))?;
+        let extension = path.extension().and_then(|e| e.to_str());
+        decode(&bytes, extension).map_err(|kind| TextureError::new(Some(source.as_ref()), kind))
+    }
+
+    /// Can be used in conjunction with the `include_bytes!(..)` in std. `source` is only used for
+    /// context in error messages.
+    pub fn from_bytes(bytes: &[u8], source: &str) -> Result<RawAnimationData, TextureError> {
+        let extension = Path::new(source).extension().and_then(|e| e.to_str());
+        decode(bytes, extension).map_err(|kind| TextureError::new(Some(source), kind))
+    }
+
+    /// The rgba data of each frame. All frames have the size of the whole image.
+    pub fn frames(&self) -> &[RawImageData] {
+        &self.frames
+    }
+
+    /// How long each frame is shown.
+    pub fn delays(&self) -> &[Time] {
+        &self.delays
+    }
+}
+
+/// A sequence of textures, with a delay for each of them. Use a [`AnimationPlayer`] to find the
+/// frame to show at a given time.
+///
+/// [`AnimationPlayer`]: struct.AnimationPlayer.html
+pub struct Animation {
+    frames: Vec<Texture>,
+    delays: Vec<Time>,
+    duration: Time,
+}
+
+impl Animation {
+    /// Creates one texture per frame.
+    pub fn from_raw(data: RawAnimationData) -> Animation {
+        let duration = data.delays.iter().fold(Time::ZERO, |a, &b| a + b);
+        let frames = data.frames.into_iter().map(|frame| {
+            let mut texture = Texture::new();
+            // Frames are allways rgba, so this can not fail
+            texture.load_raw_image_data(frame).unwrap();
+            texture
+        }).collect();
+
+        Animation { frames, delays: data.delays, duration }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// # Panics
+    /// If `index` is not less than `frame_count()`.
+    pub fn frame(&self, index: usize) -> &Texture {
+        &self.frames[index]
+    }
+
+    /// How long the given frame is shown.
+    pub fn delay(&self, index: usize) -> Time {
+        self.delays[index]
+    }
+
+    /// The total length of one loop of the animation.
+    pub fn duration(&self) -> Time {
+        self.duration
+    }
+}
+
+/// Tracks the current frame of a [`Animation`]. The same animation can be shown with several
+/// players, e.g. for multiple spinners which started at different times.
+///
+/// [`Animation`]: struct.Animation.html
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    frame: usize,
+    elapsed: Time,
+    looping: bool,
+    finished: bool,
+}
+
+impl AnimationPlayer {
+    /// Creates a player which starts at the first frame and loops forever.
+    pub fn new() -> AnimationPlayer {
+        AnimationPlayer {
+            frame: 0,
+            elapsed: Time::ZERO,
+            looping: true,
+            finished: false,
+        }
+    }
+
+    /// If looping is disabled, the player stops at the last frame.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Moves forward by `delta`, skipping frames if `delta` is longer than the current frame.
+    pub fn advance(&mut self, animation: &Animation, delta: Time) {
+        if animation.frame_count() == 0 || self.finished {
+            return;
+        }
+
+        self.elapsed += delta;
+        if self.looping && animation.duration() > Time::ZERO && self.elapsed >= animation.duration() {
+            // Skip whole loops, so long pauses do not step through every frame
+            self.elapsed = Time(self.elapsed.0 % animation.duration().0);
+        }
+
+        while self.elapsed >= animation.delay(self.frame) {
+            if self.frame + 1 == animation.frame_count() {
+                if !self.looping {
+                    self.finished = true;
+                    self.elapsed = Time::ZERO;
+                    break;
+                }
+                if animation.duration() == Time::ZERO {
+                    break;
+                }
+            }
+
+            self.elapsed -= animation.delay(self.frame);
+            self.frame = (self.frame + 1) % animation.frame_count();
+        }
+    }
+
+    /// The index of the frame which should currently be shown.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Whether a non-looping player reached the end of the animation.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Goes back to the first frame.
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = Time::ZERO;
+        self.finished = false;
+    }
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> AnimationPlayer {
+        AnimationPlayer::new()
+    }
+}
+
+fn decode(bytes: &[u8], extension: Option<&str>) -> Result<RawAnimationData, TextureErrorKind> {
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return decode_gif(bytes).map_err(TextureErrorKind::Decoding);
+    }
+
+    if bytes.starts_with(&PNG_SIGNATURE) {
+        if let Some(result) = decode_apng(bytes) {
+            return result;
+        }
+    }
+
+    // Not animated, load as a single frame
+    let frame = image_decoder::decode(bytes, extension)?;
+    Ok(RawAnimationData {
+        frames: vec![to_rgba(frame)],
+        delays: vec![Time::from_ms(DEFAULT_DELAY_MS)],
+    })
+}
+
+// Frames are composited into a canvas of the size of the whole image, with these operations
+// applied afterwards
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Dispose {
+    None,
+    Clear,
+    Restore,
+}
+
+struct Compositor {
+    width: usize,
+    height: usize,
+    canvas: Vec<u8>,
+    frames: Vec<RawImageData>,
+    delays: Vec<Time>,
+}
+
+impl Compositor {
+    fn new(width: u32, height: u32) -> Result<Compositor, String> {
+        if width > MAX_CANVAS_SIZE || height > MAX_CANVAS_SIZE {
+            return Err(format!("Image is too large ({}x{})", width, height));
+        }
+        let size = (width as usize).checked_mul(height as usize).and_then(|s| s.checked_mul(4));
+        let size = size.ok_or_else(|| format!("Image is too large ({}x{})", width, height))?;
+
+        Ok(Compositor {
+            width: width as usize,
+            height: height as usize,
+            canvas: vec![0; size],
+            frames: Vec::new(),
+            delays: Vec::new(),
+        })
+    }
+
+    // `pixel(x, y)` gives a rgba color in frame coordinates, or `None` if the pixel is
+    // transparent. Pixels outside the canvas are ignored.
+    fn add_frame<F>(&mut self, x: usize, y: usize, w: usize, h: usize, delay: Time, dispose: Dispose, mut pixel: F)
+      where F: FnMut(usize, usize) -> Option<[u8; 4]>,
+    {
+        let previous = if dispose == Dispose::Restore { Some(self.canvas.clone()) } else { None };
+
+        let x_end = (x + w).min(self.width);
+        let y_end = (y + h).min(self.height);
+
+        for cy in y..y_end {
+            for cx in x..x_end {
+                if let Some(color) = pixel(cx - x, cy - y) {
+                    let i = (cy*self.width + cx) * 4;
+                    self.canvas[i..i + 4].copy_from_slice(&color);
+                }
+            }
+        }
+
+        let (width, height) = (self.width as u32, self.height as u32);
+        self.frames.push(RawImageData::new(width, height, TextureFormat::RGBA_8, self.canvas.clone()));
+        self.delays.push(delay);
+
+        match dispose {
+            Dispose::None => {},
+            Dispose::Clear => for cy in y..y_end {
+                for cx in x..x_end {
+                    let i = (cy*self.width + cx) * 4;
+                    self.canvas[i..i + 4].copy_from_slice(&[0; 4]);
+                }
+            },
+            Dispose::Restore => self.canvas = previous.unwrap(),
+        }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y*self.width + x) * 4;
+        [self.canvas[i], self.canvas[i + 1], self.canvas[i + 2], self.canvas[i + 3]]
+    }
+
+    fn finish(self) -> Result<RawAnimationData, String> {
+        if self.frames.is_empty() {
+            return Err("No frames".to_owned());
+        }
+        Ok(RawAnimationData { frames: self.frames, delays: self.delays })
+    }
+}
+
+// Delays of 0 or 10 ms are common in files which expect to be shown at the browser default
+fn frame_delay(ms: u64) -> Time {
+    if ms <= 10 {
+        Time::from_ms(DEFAULT_DELAY_MS)
+    } else {
+        Time::from_ms(ms)
+    }
+}
+
+fn decode_gif(bytes: &[u8]) -> Result<RawAnimationData, String> {
+    let mut reader = ByteReader { bytes, pos: 6 };
+
+    let width = reader.u16_le()? as u32;
+    let height = reader.u16_le()? as u32;
+    let flags = reader.u8()?;
+    let _background = reader.u8()?;
+    let _aspect = reader.u8()?;
+
+    let global_colors = if flags & 0x80 != 0 {
+        reader.take(3 << ((flags & 0x07) + 1))?
+    } else {
+        &[]
+    };
+
+    let mut compositor = Compositor::new(width, height)?;
+
+    // Set by graphic control extensions, and applies to the next image
+    let mut delay = 0;
+    let mut transparent = None;
+    let mut dispose = Dispose::None;
+
+    loop {
+        match reader.u8()? {
+            // Extension
+            0x21 => {
+                let label = reader.u8()?;
+                if label == 0xf9 {
+                    let block = reader.sub_block()?;
+                    if block.len() < 4 {
+                        return Err("Invalid graphic control extension".to_owned());
+                    }
+                    dispose = match (block[0] >> 2) & 0x07 {
+                        2 => Dispose::Clear,
+                        3 => Dispose::Restore,
+                        _ => Dispose::None,
+                    };
+                    delay = (block[1] as u64 | (block[2] as u64) << 8) * 10;
+                    transparent = if block[0] & 0x01 != 0 { Some(block[3]) } else { None };
+                }
+                reader.skip_sub_blocks()?;
+            },
+
+            // Image
+            0x2c => {
+                let x = reader.u16_le()? as usize;
+                let y = reader.u16_le()? as usize;
+                let w = reader.u16_le()? as usize;
+                let h = reader.u16_le()? as usize;
+                let flags = reader.u8()?;
+
+                let colors = if flags & 0x80 != 0 {
+                    reader.take(3 << ((flags & 0x07) + 1))?
+                } else {
+                    global_colors
+                };
+                let interlaced = flags & 0x40 != 0;
+
+                let min_code_size = reader.u8()?;
+                let mut data = Vec::new();
+                loop {
+                    let block = reader.sub_block()?;
+                    if block.is_empty() { break; }
+                    data.extend_from_slice(block);
+                }
+                let len = w.checked_mul(h).ok_or_else(|| "Frame is too large".to_owned())?;
+                let indices = lzw_decode(&data, min_code_size, len)?;
+
+                // Interlaced images store every 8th row, then the rows in between, and so on
+                let mut stored_row = (0..h).collect::<Vec<_>>();
+                if interlaced {
+                    let passes = [(0, 8), (4, 8), (2, 4), (1, 2)];
+                    let rows = passes.iter().flat_map(|&(start, step)| (start..h).step_by(step));
+                    for (i, row) in rows.enumerate() {
+                        stored_row[row] = i;
+                    }
+                }
+
+                compositor.add_frame(x, y, w, h, frame_delay(delay), dispose, |px, py| {
+                    let index = *indices.get(stored_row[py]*w + px)?;
+                    if Some(index) == transparent {
+                        return None;
+                    }
+                    let color = colors.get(index as usize * 3..index as usize * 3 + 3)?;
+                    Some([color[0], color[1], color[2], 0xff])
+                });
+
+                delay = 0;
+                transparent = None;
+                dispose = Dispose::None;
+            },
+
+            // Trailer
+            0x3b => break,
+
+            other => return Err(format!("Unknown block 0x{:02x}", other)),
+        }
+    }
+
+    compositor.finish()
+}
+
+// Decodes the variable width LZW codes used by GIF. Produces at most `max_len` color indices.
+fn lzw_decode(data: &[u8], min_code_size: u8, max_len: usize) -> Result<Vec<u8>, String> {
+    const MAX_CODES: usize = 4096;
+
+    if min_code_size < 1 || min_code_size > 11 {
+        return Err(format!("Invalid minimum code size {}", min_code_size));
+    }
+
+    let clear = 1usize << min_code_size;
+    let end = clear + 1;
+
+    // Each code is a previous code followed by a single index
+    let mut prefix = vec![0u16; MAX_CODES];
+    let mut suffix = vec![0u8; MAX_CODES];
+    let mut first = vec![0u8; MAX_CODES];
+    let mut length = vec![0u16; MAX_CODES];
+    for i in 0..clear {
+        suffix[i] = i as u8;
+        first[i] = i as u8;
+        length[i] = 1;
+    }
+
+    let mut next = end + 1;
+    let mut code_size = min_code_size as u32 + 1;
+    let mut previous: Option<usize> = None;
+
+    // `max_len` comes from the file, so only use it as a capacity hint if the data could plausibly
+    // fill it
+    let mut out = Vec::with_capacity(max_len.min(data.len().saturating_mul(8)));
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut data = data.iter();
+
+    while out.len() < max_len {
+        while bit_count < code_size {
+            match data.next() {
+                Some(&byte) => {
+                    bits |= (byte as u32) << bit_count;
+                    bit_count += 8;
+                },
+                None => return Ok(out),
+            }
+        }
+        let code = (bits & ((1 << code_size) - 1)) as usize;
+        bits >>= code_size;
+        bit_count -= code_size;
+
+        if code == clear {
+            next = end + 1;
+            code_size = min_code_size as u32 + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+
+        // A code which is not in the table yet is only valid if it is the next code, which is
+        // the previous string followed by its own first index
+        let known = code < next;
+        let entry = match previous {
+            _ if known => code,
+            Some(prev) if code == next => prev,
+            _ => return Err(format!("Invalid code {}", code)),
+        };
+
+        // Write the string for `entry`, from the back
+        let start = out.len();
+        let len = length[entry] as usize;
+        out.resize(start + len, 0);
+        let mut c = entry;
+        for i in (0..len).rev() {
+            out[start + i] = suffix[c];
+            c = prefix[c] as usize;
+        }
+        if !known {
+            out.push(first[entry]);
+        }
+
+        if let Some(prev) = previous {
+            if next < MAX_CODES {
+                prefix[next] = prev as u16;
+                suffix[next] = if known { first[code] } else { first[prev] };
+                first[next] = first[prev];
+                length[next] = length[prev] + 1;
+                next += 1;
+                if next == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+        }
+        previous = Some(code);
+    }
+
+    out.truncate(max_len);
+    Ok(out)
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// A frame control chunk
+struct FrameControl {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    delay: Time,
+    dispose: Dispose,
+    blend: bool,
+    data: Vec<u8>,
+}
+
+// Returns `None` if the file is a png file without animation
+fn decode_apng(bytes: &[u8]) -> Option<Result<RawAnimationData, TextureErrorKind>> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 12 <= bytes.len() {
+        let len = be_u32(&bytes[pos..]) as usize;
+        if pos + 12 + len > bytes.len() {
+            break;
+        }
+        let kind = &bytes[pos + 4..pos + 8];
+        chunks.push((kind, &bytes[pos + 8..pos + 8 + len]));
+        pos += 12 + len;
+    }
+
+    if !chunks.iter().any(|&(kind, _)| kind == b"acTL") {
+        return None;
+    }
+    Some(decode_apng_chunks(&chunks))
+}
+
+fn decode_apng_chunks(chunks: &[(&[u8], &[u8])]) -> Result<RawAnimationData, TextureErrorKind> {
+    let invalid = |message: &str| TextureErrorKind::Decoding(message.to_owned());
+
+    let header = match chunks.first() {
+        Some(&(kind, data)) if kind == b"IHDR" && data.len() == 13 => data,
+        _ => return Err(invalid("Missing header")),
+    };
+
+    // Chunks such as the palette, which all frames share
+    let mut shared = Vec::new();
+    let mut frames: Vec<FrameControl> = Vec::new();
+    let mut in_animation = false;
+
+    for &(kind, data) in chunks[1..].iter() {
+        match kind {
+            b"fcTL" => {
+                if data.len() < 26 {
+                    return Err(invalid("Invalid frame control chunk"));
+                }
+                let delay_num = (data[20] as u64) << 8 | data[21] as u64;
+                let delay_den = match (data[22] as u64) << 8 | data[23] as u64 { 0 => 100, d => d };
+                frames.push(FrameControl {
+                    width: be_u32(&data[4..]),
+                    height: be_u32(&data[8..]),
+                    x: be_u32(&data[12..]),
+                    y: be_u32(&data[16..]),
+                    delay: frame_delay(delay_num * 1000 / delay_den),
+                    dispose: match data[24] {
+                        1 => Dispose::Clear,
+                        2 if !frames.is_empty() => Dispose::Restore,
+                        2 => Dispose::Clear,
+                        _ => Dispose::None,
+                    },
+                    blend: data[25] == 1,
+                    data: Vec::new(),
+                });
+                in_animation = true;
+            },
+            // The default image is only part of the animation if a frame control chunk precedes it
+            b"IDAT" => if in_animation {
+                frames.last_mut().unwrap().data.extend_from_slice(data);
+            },
+            b"fdAT" => match frames.last_mut() {
+                Some(frame) if data.len() >= 4 => frame.data.extend_from_slice(&data[4..]),
+                _ => return Err(invalid("Frame data without frame control chunk")),
+            },
+            b"acTL" | b"IEND" => {},
+            _ => if frames.is_empty() {
+                shared.push((kind, data));
+            },
+        }
+    }
+
+    let (canvas_width, canvas_height) = (be_u32(&header[0..]), be_u32(&header[4..]));
+    let mut compositor = Compositor::new(canvas_width, canvas_height).map_err(TextureErrorKind::Decoding)?;
+
+    for frame in frames.iter() {
+        // Frames have to lie within the canvas, which also bounds what the png decoder allocates
+        if frame.x as u64 + frame.width as u64 > canvas_width as u64
+        || frame.y as u64 + frame.height as u64 > canvas_height as u64
+        {
+            return Err(invalid("Frame lies outside the image"));
+        }
+
+        // Build a standalone png file for the frame, and decode it with the png crate
+        let mut frame_header = header.to_vec();
+        frame_header[0..4].copy_from_slice(&be_bytes(frame.width));
+        frame_header[4..8].copy_from_slice(&be_bytes(frame.height));
+
+        let mut file = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut file, b"IHDR", &frame_header);
+        for &(kind, data) in shared.iter() {
+            write_chunk(&mut file, kind, data);
+        }
+        write_chunk(&mut file, b"IDAT", &frame.data);
+        write_chunk(&mut file, b"IEND", &[]);
+
+        let image = decode_png_frame(&file)?;
+
+        let existing = &compositor;
+        let blended = (0..frame.height as usize).flat_map(|y| (0..frame.width as usize).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let i = (y*frame.width as usize + x) * 4;
+                let src = [image[i], image[i + 1], image[i + 2], image[i + 3]];
+                let (cx, cy) = (frame.x as usize + x, frame.y as usize + y);
+                if frame.blend && cx < existing.width && cy < existing.height {
+                    blend_over(src, existing.pixel(cx, cy))
+                } else {
+                    src
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let width = frame.width as usize;
+        compositor.add_frame(
+            frame.x as usize, frame.y as usize, width, frame.height as usize,
+            frame.delay, frame.dispose,
+            |x, y| Some(blended[y*width + x]),
+        );
+    }
+
+    compositor.finish().map_err(TextureErrorKind::Decoding)
+}
+
+fn decode_png_frame(file: &[u8]) -> Result<Vec<u8>, TextureErrorKind> {
+    let decoder = png::Decoder::new(file);
+    let (info, mut reader) = decoder.read_info().map_err(TextureErrorKind::from_decoding)?;
+    let format = match TextureFormat::from_png(info.color_type, info.bit_depth) {
+        Some(format) => format,
+        None => {
+            let format = format!("{:?}, {:?}", info.color_type, info.bit_depth);
+            return Err(TextureErrorKind::UnsupportedFormat(format));
+        },
+    };
+
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(TextureErrorKind::from_decoding)?;
+
+    let image = to_rgba(RawImageData::new(info.width, info.height, format, buf));
+    Ok(image.data().to_vec())
+}
+
+// Straight alpha "source over" blending, as used by APNG
+fn blend_over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    let sa = src[3] as u32;
+    let da = dst[3] as u32 * (255 - sa) / 255;
+    let a = sa + da;
+    if a == 0 {
+        return [0; 4];
+    }
+    let mut out = [0, 0, 0, a as u8];
+    for i in 0..3 {
+        out[i] = ((src[i] as u32 * sa + dst[i] as u32 * da) / a) as u8;
+    }
+    out
+}
+
+// Expands images with fewer channels, so all frames are rgba
+fn to_rgba(image: RawImageData) -> RawImageData {
+    let (width, height) = image.size();
+    let format = image.format();
+
+    let data = match format {
+        TextureFormat::RGBA_8 => return image,
+        TextureFormat::RGB_8 => image.data().chunks(3).flat_map(|c| vec![c[0], c[1], c[2], 0xff]).collect(),
+        TextureFormat::RG_8  => image.data().chunks(2).flat_map(|c| vec![c[0], c[0], c[0], c[1]]).collect(),
+        TextureFormat::R_8   => image.data().iter().flat_map(|&c| vec![c, c, c, 0xff]).collect(),
+        _ => unreachable!("Raw image data with {:?}", format),
+    };
+    RawImageData::new(width, height, TextureFormat::RGBA_8, data)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    out.extend_from_slice(&be_bytes(data.len() as u32));
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&be_bytes(crc));
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+fn be_bytes(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.bytes.len() {
+            return Err("Unexpected end of file".to_owned());
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16_le(&mut self) -> Result<u16, String> {
+        self.take(2).map(|b| b[0] as u16 | (b[1] as u16) << 8)
+    }
+
+    // A block prefixed with its length. Empty blocks terminate a sequence.
+    fn sub_block(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    fn skip_sub_blocks(&mut self) -> Result<(), String> {
+        while !self.sub_block()?.is_empty() {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gif_frames() {
+        // Packs 3 bit codes, least significant bit first. Each index is preceded by a clear code
+        // (4), so the code size never grows.
+        let pack = |indices: &[u8]| {
+            let mut codes = Vec::new();
+            for &i in indices {
+                codes.push(4);
+                codes.push(i as u32);
+            }
+            codes.push(5);
+
+            let mut bytes = Vec::new();
+            let (mut bits, mut count) = (0u32, 0);
+            for code in codes {
+                bits |= code << count;
+                count += 3;
+                while count >= 8 {
+                    bytes.push(bits as u8);
+                    bits >>= 8;
+                    count -= 8;
+                }
+            }
+            if count > 0 { bytes.push(bits as u8); }
+            bytes
+        };
+
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&[2, 0, 1, 0, 0x81, 0, 0]); // 2x1, 4 global colors
+        gif.extend_from_slice(&[255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0]);
+
+        // Red and green, shown for 50 ms
+        gif.extend_from_slice(&[0x21, 0xf9, 4, 0, 5, 0, 0, 0]);
+        gif.extend_from_slice(&[0x2c, 0, 0, 0, 0, 2, 0, 1, 0, 0, 2]);
+        let data = pack(&[0, 1]);
+        gif.push(data.len() as u8);
+        gif.extend_from_slice(&data);
+        gif.push(0);
+
+        // Only the second pixel, blue, with index 3 being transparent. No delay.
+        gif.extend_from_slice(&[0x21, 0xf9, 4, 1, 0, 0, 3, 0]);
+        gif.extend_from_slice(&[0x2c, 1, 0, 0, 0, 1, 0, 1, 0, 0, 2]);
+        let data = pack(&[2]);
+        gif.push(data.len() as u8);
+        gif.extend_from_slice(&data);
+        gif.extend_from_slice(&[0, 0x3b]);
+
+        let animation = RawAnimationData::from_bytes(&gif, "test.gif").unwrap();
+        assert_eq!(animation.delays(), &[Time::from_ms(50), Time::from_ms(DEFAULT_DELAY_MS)]);
+        assert_eq!(animation.frames()[0].data(), &[255, 0, 0, 255, 0, 255, 0, 255]);
+        assert_eq!(animation.frames()[1].data(), &[255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        // A 65535x65535 logical screen, with nothing else in the file
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0x3b]);
+        assert!(decode_gif(&gif).is_err());
+
+        let mut header = vec![0; 13];
+        header[0..4].copy_from_slice(&be_bytes(100_000));
+        header[4..8].copy_from_slice(&be_bytes(100_000));
+        header[8] = 8;
+        header[9] = 6;
+        let chunks: [(&[u8], &[u8]); 2] = [(b"IHDR", &header), (b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0])];
+        assert!(decode_apng_chunks(&chunks).is_err());
+
+        // A small canvas with a frame which claims to be much larger
+        header[0..4].copy_from_slice(&be_bytes(1));
+        header[4..8].copy_from_slice(&be_bytes(1));
+        let mut control = vec![0; 26];
+        control[4..8].copy_from_slice(&be_bytes(100_000));
+        control[8..12].copy_from_slice(&be_bytes(100_000));
+        let chunks: [(&[u8], &[u8]); 3] = [(b"IHDR", &header), (b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]), (b"fcTL", &control)];
+        assert!(decode_apng_chunks(&chunks).is_err());
+    }
+}
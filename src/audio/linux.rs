@@ -5,11 +5,180 @@
 
 extern crate alsa_sys as alsa;
 
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+
 use super::*;
 use time::Time;
 
 const MAX_WRITE_FRAMES: u64 = 1024;
 
+// Strings returned by `snd_device_name_get_hint` are allocated with malloc
+extern "C" {
+    fn free(ptr: *mut c_void);
+}
+
+pub(super) fn devices() -> Result<Vec<AudioDevice>, AudioError> {
+    hint_devices("Output")
+}
+
+pub(super) fn capture_devices() -> Result<Vec<AudioDevice>, AudioError> {
+    hint_devices("Input")
+}
+
+// Lists pcm devices which support the given direction, either "Input" or "Output"
+fn hint_devices(direction: &str) -> Result<Vec<AudioDevice>, AudioError> {
+    let mut devices = Vec::new();
+
+    unsafe {
+        let mut hints = ptr::null_mut();
+        let interface = b"pcm\0";
+        let result = alsa::snd_device_name_hint(-1, interface.as_ptr() as *const c_char, &mut hints);
+        if result < 0 {
+            return Err(AudioError::BadReturn {
+                function_name: "snd_device_name_hint".to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+
+        let mut hint = hints;
+        while !(*hint).is_null() {
+            let name = hint_string(*hint, b"NAME\0");
+            let description = hint_string(*hint, b"DESC\0");
+            // Devices without an io id support both input and output
+            let io_id = hint_string(*hint, b"IOID\0");
+
+            let supported = io_id.map(|io_id| io_id == direction).unwrap_or(true);
+            if let (true, Some(name)) = (supported, name) {
+                if name != "null" {
+                    // Descriptions are split into several lines
+                    let description = description
+                        .map(|d| d.lines().collect::<Vec<_>>().join(", "))
+                        .unwrap_or_else(|| name.clone());
+
+                    devices.push(AudioDevice {
+                        default: name == "default",
+                        id: name,
+                        name: description,
+                    });
+                }
+            }
+
+            hint = hint.offset(1);
+        }
+
+        alsa::snd_device_name_free_hint(hints);
+    }
+
+    Ok(devices)
+}
+
+unsafe fn hint_string(hint: *const c_void, id: &[u8]) -> Option<String> {
+    let value = alsa::snd_device_name_get_hint(hint, id.as_ptr() as *const c_char);
+    if value.is_null() {
+        return None;
+    }
+
+    let string = CStr::from_ptr(value).to_string_lossy().into_owned();
+    free(value as *mut c_void);
+    Some(string)
+}
+
+// Opens the given device (or `"default"`) and configures it for interleaved `SampleData`. If the
+// device does not support `sample_rate`, it is set to the closest supported rate.
+unsafe fn open_pcm(
+    device: Option<&str>,
+    stream: alsa::snd_pcm_stream_t,
+    channels: u32,
+    sample_rate: &mut u32,
+) -> Result<*mut alsa::snd_pcm_t, AudioError>
+{
+    let device_name = match CString::new(device.unwrap_or("default")) {
+        Ok(name) => name,
+        Err(_) => {
+            let message = format!("Invalid audio device name {:?}", device);
+            return Err(AudioError::Other { message });
+        },
+    };
+
+    let mut pcm_handle = ptr::null_mut();
+    let result = alsa::snd_pcm_open(&mut pcm_handle, device_name.as_ptr(), stream, 0);
+    if result < 0 {
+        return Err(AudioError::BadReturn {
+            function_name: "snd_pcm_open".to_owned().to_owned(),
+            error_code: result as i64,
+            line: line!(),
+            file: file!(), 
+        });
+    }
+
+    match configure_pcm(pcm_handle, channels, sample_rate) {
+        Ok(()) => Ok(pcm_handle),
+        Err(err) => {
+            alsa::snd_pcm_close(pcm_handle);
+            Err(err)
+        },
+    }
+}
+
+// Sets the "hardware" parameters used by `open_pcm`
+unsafe fn configure_pcm(
+    pcm_handle: *mut alsa::snd_pcm_t,
+    channels: u32,
+    sample_rate: &mut u32,
+) -> Result<(), AudioError>
+{
+    let mut hardware = ptr::null_mut();
+    let result = alsa::snd_pcm_hw_params_malloc(&mut hardware);
+    if result < 0 {
+        return Err(AudioError::BadReturn {
+            function_name: "snd_pcm_hw_params_malloc".to_owned().to_owned(),
+            error_code: result as i64,
+            line: line!(),
+            file: file!(), 
+        });
+    }
+    assert!(!hardware.is_null());
+
+    let result = alsa::snd_pcm_hw_params_any(pcm_handle, hardware);
+    if result < 0 {
+        alsa::snd_pcm_hw_params_free(hardware);
+        return Err(AudioError::BadReturn {
+            function_name: "snd_pcm_hw_params_any".to_owned().to_owned(),
+            error_code: result as i64,
+            line: line!(),
+            file: file!(), 
+        });
+    }
+
+    let access = alsa::SND_PCM_ACCESS_RW_INTERLEAVED;
+    let format = if cfg!(target_endian = "big") {
+        alsa::SND_PCM_FORMAT_S16_BE
+    } else {
+        alsa::SND_PCM_FORMAT_S16_LE
+    };
+
+    alsa::snd_pcm_hw_params_set_access(pcm_handle, hardware, access);
+    alsa::snd_pcm_hw_params_set_format(pcm_handle, hardware, format);
+    alsa::snd_pcm_hw_params_set_channels(pcm_handle, hardware, channels);
+    alsa::snd_pcm_hw_params_set_rate_near(pcm_handle, hardware, sample_rate, ptr::null_mut());
+
+    let result = alsa::snd_pcm_hw_params(pcm_handle, hardware);
+    alsa::snd_pcm_hw_params_free(hardware);
+    if result < 0 {
+        return Err(AudioError::BadReturn {
+            function_name: "snd_pcm_hw_params".to_owned().to_owned(),
+            error_code: result as i64,
+            line: line!(),
+            file: file!(), 
+        });
+    }
+
+    Ok(())
+}
+
 pub(super) struct AudioBackend {
     pcm_handle: *mut alsa::snd_pcm_t,
     write_buffer: Vec<i16>,
@@ -17,77 +186,15 @@ pub(super) struct AudioBackend {
 }
 
 impl AudioBackend {
-    pub fn initialize() -> Result<AudioBackend, AudioError> {
-        let mut pcm_handle = ptr::null_mut();
+    /// Opens the ALSA device with the given name, or `"default"` if no name is given.
+    pub fn initialize(device: Option<&str>) -> Result<AudioBackend, AudioError> {
+        let pcm_handle;
         let mut write_buffer = Vec::new();
         let total_frames;
 
         unsafe {
-            let device_name = b"default\0";
-
-            let result = alsa::snd_pcm_open(
-                &mut pcm_handle,
-                device_name.as_ptr() as *const i8,
-                alsa::SND_PCM_STREAM_PLAYBACK, 
-                0
-            );
-            if result < 0 {
-                return Err(AudioError::BadReturn {
-                    function_name: "snd_pcm_open".to_owned().to_owned(),
-                    error_code: result as i64,
-                    line: line!(),
-                    file: file!(), 
-                });
-            }
-
-            // Configure "hardware" stuff
-            let mut hardware = ptr::null_mut();
-            let result = alsa::snd_pcm_hw_params_malloc(&mut hardware);
-            if result < 0 {
-                return Err(AudioError::BadReturn {
-                    function_name: "snd_pcm_hw_params_malloc".to_owned().to_owned(),
-                    error_code: result as i64,
-                    line: line!(),
-                    file: file!(), 
-                });
-            }
-            assert!(!hardware.is_null());
-
-            let result = alsa::snd_pcm_hw_params_any(pcm_handle, hardware);
-            if result < 0 {
-                return Err(AudioError::BadReturn {
-                    function_name: "snd_pcm_hw_params_any".to_owned().to_owned(),
-                    error_code: result as i64,
-                    line: line!(),
-                    file: file!(), 
-                });
-            }
-
-            let access = alsa::SND_PCM_ACCESS_RW_INTERLEAVED;
-            let format = if cfg!(target_endian = "big") {
-                alsa::SND_PCM_FORMAT_S16_BE
-            } else {
-                alsa::SND_PCM_FORMAT_S16_LE
-            };
-            let channels = OUTPUT_CHANNELS;
             let mut sample_rate = OUTPUT_SAMPLE_RATE;
-
-            alsa::snd_pcm_hw_params_set_access(pcm_handle, hardware, access);
-            alsa::snd_pcm_hw_params_set_format(pcm_handle, hardware, format);
-            alsa::snd_pcm_hw_params_set_channels(pcm_handle, hardware, channels);
-            alsa::snd_pcm_hw_params_set_rate_near(pcm_handle, hardware, &mut sample_rate, ptr::null_mut());
-
-            let result = alsa::snd_pcm_hw_params(pcm_handle, hardware);
-            if result < 0 {
-                return Err(AudioError::BadReturn {
-                    function_name: "snd_pcm_hw_params".to_owned().to_owned(),
-                    error_code: result as i64,
-                    line: line!(),
-                    file: file!(), 
-                });
-            }
-
-            alsa::snd_pcm_hw_params_free(hardware);
+            pcm_handle = open_pcm(device, alsa::SND_PCM_STREAM_PLAYBACK, OUTPUT_CHANNELS, &mut sample_rate)?;
 
             // Configure "software" stuff
             let mut software = ptr::null_mut();
@@ -271,6 +378,12 @@ impl AudioBackend {
         return Ok(true); // We wrote some data
     }
 
+    /// The `"default"` device is routed by ALSA (or by pulseaudio, if it is used), so changes to
+    /// the default device are followed without reopening it.
+    pub fn default_device_changed(&self) -> bool {
+        false
+    }
+
     pub fn write_interval(&self) -> Time {
         Time((MAX_WRITE_FRAMES as u64 * Time::NANOSECONDS_PER_SECOND) / OUTPUT_SAMPLE_RATE as u64)
     }
@@ -283,3 +396,96 @@ impl Drop for AudioBackend {
         }
     }
 }
+
+// Number of frames read from capture devices at a time, per second of audio
+const CAPTURE_READS_PER_SECOND: u32 = 100;
+
+pub(super) struct CaptureBackend {
+    pcm_handle: *mut alsa::snd_pcm_t,
+    sample_rate: u32,
+    channels: u32,
+}
+
+impl CaptureBackend {
+    /// Opens the ALSA device with the given name, or `"default"` if no name is given. The device
+    /// might use a different sample rate than the one requested, see `sample_rate`.
+    pub fn initialize(device: Option<&str>, sample_rate: u32, channels: u32) -> Result<CaptureBackend, AudioError> {
+        let mut sample_rate = sample_rate;
+
+        unsafe {
+            let pcm_handle = open_pcm(device, alsa::SND_PCM_STREAM_CAPTURE, channels, &mut sample_rate)?;
+
+            let result = alsa::snd_pcm_prepare(pcm_handle);
+            if result < 0 {
+                alsa::snd_pcm_close(pcm_handle);
+                return Err(AudioError::BadReturn {
+                    function_name: "snd_pcm_prepare".to_owned(),
+                    error_code: result as i64,
+                    line: line!(),
+                    file: file!(),
+                });
+            }
+
+            Ok(CaptureBackend {
+                pcm_handle,
+                sample_rate,
+                channels,
+            })
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Blocks until a chunk of frames has been captured, and appends them to `samples`.
+    pub fn read(&mut self, samples: &mut Vec<SampleData>) -> Result<(), AudioError> {
+        let frames = Ord::max(self.sample_rate / CAPTURE_READS_PER_SECOND, 1) as u64;
+        let start = samples.len();
+        let len = frames as usize * self.channels as usize;
+        samples.resize(start + len, 0);
+
+        unsafe {
+            let mut result = alsa::snd_pcm_readi(
+                self.pcm_handle,
+                samples[start..].as_mut_ptr() as *mut _,
+                frames,
+            );
+
+            if result == -32 {
+                // We did not read data fast enough, and some of it was lost. Recover and drop
+                // this chunk.
+                let recover_result = alsa::snd_pcm_recover(self.pcm_handle, -32, 1);
+                if recover_result < 0 {
+                    return Err(AudioError::BadReturn {
+                        function_name: "snd_pcm_recover".to_owned(),
+                        error_code: recover_result as i64,
+                        line: line!(),
+                        file: file!(),
+                    });
+                }
+                result = 0;
+            } else if result < 0 {
+                return Err(AudioError::BadReturn {
+                    function_name: "snd_pcm_readi".to_owned(),
+                    error_code: result,
+                    line: line!(),
+                    file: file!(),
+                });
+            }
+
+            // Reads can return fewer frames than requested
+            samples.truncate(start + result as usize * self.channels as usize);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for CaptureBackend {
+    fn drop(&mut self) {
+        unsafe {
+            alsa::snd_pcm_close(self.pcm_handle);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! The last stage of the mixer, which converts mixed `f32` samples to the output format.
+
+use std::sync::{Arc, Mutex};
+
+use super::{SampleData, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE};
+
+// The limiter keeps the output below this level, which is about -1 dBFS
+const LIMITER_THRESHOLD: f32 = 0.891;
+// How long it takes the limiter to release after a loud sound, in seconds
+const LIMITER_RELEASE: f32 = 0.1;
+
+/// Statistics about the signal level of the mixer output. Levels are linear, with `1.0` being the
+/// loudest value which can be output without clipping.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MixStats {
+    /// The highest level of any mixed sample, before the limiter was applied.
+    pub peak: f32,
+    /// The number of samples which would have clipped without the limiter.
+    pub clipped_samples: u64,
+    /// The number of frames in which the limiter reduced the volume.
+    pub limited_frames: u64,
+    /// The total number of frames mixed.
+    pub frames: u64,
+}
+
+impl MixStats {
+    /// How far below full scale `peak` is, in decibels. Negative if the mix would have clipped
+    /// without the limiter, and infinite if nothing has been played.
+    pub fn headroom_db(&self) -> f32 {
+        -20.0 * self.peak.log10()
+    }
+}
+
+/// Applies a limiter to mixed samples, which are normalized to `[-1, 1]`, and converts them to
+/// `SampleData`, optionally with dithering.
+pub(super) struct MasterStage {
+    pub dither: bool,
+    gain: f32,
+    release: f32,
+    rng_state: u32,
+    stats: Arc<Mutex<MixStats>>,
+}
+
+impl MasterStage {
+    pub fn new(stats: Arc<Mutex<MixStats>>) -> MasterStage {
+        let release_frames = LIMITER_RELEASE * OUTPUT_SAMPLE_RATE as f32;
+
+        MasterStage {
+            dither: true,
+            gain: 1.0,
+            release: 1.0 - (-1.0 / release_frames).exp(),
+            rng_state: 0x9e3779b9,
+            stats,
+        }
+    }
+
+    pub fn process(&mut self, mixed: &[f32], output: &mut [SampleData]) {
+        assert_eq!(mixed.len(), output.len());
+
+        let channels = OUTPUT_CHANNELS as usize;
+        let scale = SampleData::max_value() as f32;
+        let min = SampleData::min_value() as f32;
+
+        let mut block = MixStats::default();
+
+        for (in_frame, out_frame) in mixed.chunks(channels).zip(output.chunks_mut(channels)) {
+            let mut peak = 0.0f32;
+            for &sample in in_frame.iter() {
+                let level = sample.abs();
+                peak = peak.max(level);
+                if level > 1.0 {
+                    block.clipped_samples += 1;
+                }
+            }
+
+            // Instant attack, so the output never goes above the threshold. The gain then slowly
+            // recovers, which sounds much better than clipping each loud sample.
+            let recovered = self.gain + (1.0 - self.gain)*self.release;
+            let max_gain = if peak > LIMITER_THRESHOLD { LIMITER_THRESHOLD / peak } else { 1.0 };
+            self.gain = recovered.min(max_gain);
+
+            if self.gain < 0.999 {
+                block.limited_frames += 1;
+            }
+            block.peak = block.peak.max(peak);
+            block.frames += 1;
+
+            for (&sample, out) in in_frame.iter().zip(out_frame.iter_mut()) {
+                let mut value = sample * self.gain * scale;
+                if self.dither {
+                    value += self.tpdf_noise();
+                }
+                *out = value.round().max(min).min(scale) as SampleData;
+            }
+        }
+
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.peak = stats.peak.max(block.peak);
+            stats.clipped_samples += block.clipped_samples;
+            stats.limited_frames += block.limited_frames;
+            stats.frames += block.frames;
+        }
+    }
+
+    // Triangular noise between -1 and 1 output steps
+    fn tpdf_noise(&mut self) -> f32 {
+        self.random() - self.random()
+    }
+
+    // Xorshift, uniform between 0 and 1
+    fn random(&mut self) -> f32 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiter() {
+        let stats = Arc::new(Mutex::new(MixStats::default()));
+        let mut master = MasterStage::new(stats.clone());
+        master.dither = false;
+
+        let mixed = [0.5, -0.5, 3.0, -2.0, 0.5, 0.5];
+        let mut output = [0; 6];
+        master.process(&mixed, &mut output);
+
+        assert_eq!(output[0], (0.5 * 32767.0f32).round() as i16);
+        let limit = (LIMITER_THRESHOLD * 32767.0).round() as i16;
+        assert!(output[2] <= limit && output[2] > limit - 2);
+        assert!(output[4] < output[0]); // Still recovering
+
+        let stats = *stats.lock().unwrap();
+        assert_eq!(stats.peak, 3.0);
+        assert_eq!(stats.clipped_samples, 2);
+        assert_eq!(stats.limited_frames, 2);
+        assert_eq!(stats.frames, 3);
+        assert!(stats.headroom_db() < 0.0);
+    }
+}
@@ -10,9 +10,14 @@
 // We currently only output the first channel of a sound file in the mixer. If a stereo sound is
 // submitted, we just ignore the second channel.
 
+use std::fmt;
 use std::ptr;
 use std::thread;
-use std::sync::mpsc;
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use cable_math::Vec3;
 
 use window::Window;
 use time::{Time, Timer};
@@ -29,12 +34,20 @@ mod linux;
 use self::linux::*;
 
 pub mod wav;
+mod master;
+
+pub use self::master::MixStats;
+use self::master::MasterStage;
 
 const OUTPUT_CHANNELS: u32 = 2;
 const OUTPUT_SAMPLE_RATE: u32 = 48000;
 type SampleData = i16;
 type Balance = [f32; OUTPUT_CHANNELS as usize];
 type BufferHandle = usize;
+type EventHandle = u64;
+
+// Mixing is done with samples normalized to [-1, 1]
+const SAMPLE_SCALE: f32 = 32768.0;
 
 #[derive(Clone)]
 pub struct AudioBuffer {
@@ -60,12 +73,136 @@ impl AudioBuffer {
 pub struct Event {
     pub start_frame: u64, // Set internally when the event is actually started
     pub done: bool,
+    end_frame: u64, // Set internally once the event is done
+    handle: EventHandle,
     pub buffer: BufferHandle,
     pub balance: Balance,
     pub speed: f32,
+    /// If set, `balance` is ignored and computed from the position and the listener instead.
+    pub position: Option<Vec3<f32>>,
+}
+
+/// Sent when a sound finishes playing, see [`AudioSystem::poll_finished`].
+///
+/// [`AudioSystem::poll_finished`]: struct.AudioSystem.html#method.poll_finished
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FinishedEvent {
+    /// The handle returned by `AudioSystem::play` or `AudioSystem::play_at_position`.
+    pub handle: EventHandle,
+    /// When the last sample of the sound was played, measured in output time since the audio
+    /// system was started or last restarted.
+    pub time: Time,
+}
+
+/// Where sounds played with [`AudioSystem::play_at_position`] are heard from, usually the camera.
+///
+/// [`AudioSystem::play_at_position`]: struct.AudioSystem.html#method.play_at_position
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Listener {
+    pub position: Vec3<f32>,
+    /// The direction the listener is facing. Does not need to be normalized.
+    pub forward: Vec3<f32>,
+    /// Does not need to be normalized, or to be perpendicular to `forward`.
+    pub up: Vec3<f32>,
+}
+
+impl Default for Listener {
+    /// A listener at the origin, facing down the negative z axis with y pointing up, like a camera
+    /// with an identity view matrix.
+    fn default() -> Listener {
+        Listener {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            forward: Vec3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
 }
 
+/// How the volume of positional sounds decreases with distance from the listener.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Attenuation {
+    /// Sounds have the same volume regardless of distance.
+    None,
+    /// Full volume up to `min_distance`, then fades linearly to silence at `max_distance`.
+    Linear { min_distance: f32, max_distance: f32 },
+    /// Full volume up to `reference_distance`, then falls off with the inverse of the distance.
+    /// Higher `rolloff` makes the volume fall off faster. This sounds more natural than `Linear`,
+    /// but sounds never become completely silent.
+    Inverse { reference_distance: f32, rolloff: f32 },
+}
 
+impl Attenuation {
+    /// The volume, between 0 and 1, of a sound at the given distance from the listener.
+    pub fn gain(&self, distance: f32) -> f32 {
+        match *self {
+            Attenuation::None => 1.0,
+
+            Attenuation::Linear { min_distance, max_distance } => {
+                if distance <= min_distance {
+                    1.0
+                } else if distance >= max_distance {
+                    0.0
+                } else {
+                    1.0 - (distance - min_distance)/(max_distance - min_distance)
+                }
+            },
+
+            Attenuation::Inverse { reference_distance, rolloff } => {
+                if distance <= reference_distance {
+                    1.0
+                } else {
+                    reference_distance / (reference_distance + rolloff*(distance - reference_distance))
+                }
+            },
+        }
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation::Inverse { reference_distance: 1.0, rolloff: 1.0 }
+    }
+}
+
+// Computes the balance for a sound at `position`. There is no doppler effect, so moving sounds do
+// not change pitch.
+fn positional_balance(listener: &Listener, attenuation: Attenuation, position: Vec3<f32>) -> Balance {
+    let offset = position - listener.position;
+    let distance = offset.len();
+    let gain = attenuation.gain(distance);
+
+    let right = Vec3::cross(listener.forward, listener.up);
+    let right_len = right.len();
+
+    // -1 is fully left, 1 is fully right
+    let pan = if distance > 0.0001 && right_len > 0.0001 {
+        Vec3::dot(offset, right) / (distance * right_len)
+    } else {
+        0.0
+    };
+
+    // Constant power panning, so sounds are not quieter when they are straight ahead
+    let angle = (pan + 1.0) * ::std::f32::consts::PI / 4.0;
+    [gain*angle.cos(), gain*angle.sin()]
+}
+
+
+
+/// An audio output device, as returned by [`AudioSystem::devices`].
+///
+/// [`AudioSystem::devices`]: struct.AudioSystem.html#method.devices
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioDevice {
+    /// Pass this to [`AudioSystem::set_device`] to output to this device. Ids stay the same
+    /// between runs, so they can be stored in settings files.
+    ///
+    /// [`AudioSystem::set_device`]: struct.AudioSystem.html#method.set_device
+    pub id: String,
+    /// A human readable name for the device.
+    pub name: String,
+    /// Whether this is currently the default output device of the system.
+    pub default: bool,
+}
 
 pub struct AudioSystem {
     next_buffer_handle: BufferHandle,
@@ -73,14 +210,25 @@ pub struct AudioSystem {
     pub state: AudioSystemState,
     has_printed_error: bool,
 
-    receiver: mpsc::Receiver<AudioError>,
+    window_handle: usize, // Only used on windows
+    next_event_handle: EventHandle,
+    finished: VecDeque<FinishedEvent>,
+    receiver: mpsc::Receiver<MessageFromAudioThread>,
     sender: mpsc::Sender<MessageToAudioThread>,
+    stats: Arc<Mutex<MixStats>>,
+    thread: Option<thread::JoinHandle<MixerState>>,
+    // Returned by the audio thread in `shutdown`, and passed to the new thread in `restart`
+    stopped_mixer: Option<MixerState>,
+    // Added while the audio thread was not running, sent to the new thread in `restart`
+    pending_buffers: Vec<(BufferHandle, AudioBuffer)>,
 }
 
 pub enum AudioSystemState {
     Ok,
     AudioThreadDown,
     CriticalError(AudioError),
+    /// Set by `AudioSystem::shutdown`.
+    Stopped,
 }
 
 impl AudioSystemState {
@@ -94,169 +242,148 @@ impl AudioSystemState {
 
 enum MessageToAudioThread {
     NewEvent { event: Event },
-    AddBuffer { buffer: AudioBuffer },
+    AddBuffer { handle: BufferHandle, buffer: AudioBuffer },
+    SetDevice { id: Option<String> },
+    SetListener { listener: Listener },
+    SetAttenuation { attenuation: Attenuation },
+    SetDither { dither: bool },
+    Shutdown,
 }
 
+enum MessageFromAudioThread {
+    Error(AudioError),
+    Finished(FinishedEvent),
+}
+
+// Finished events are kept until they are polled. If they are never polled, the oldest ones are
+// dropped once there are this many
+const MAX_FINISHED_EVENTS: usize = 256;
+
+// How often we check whether the default device has changed, when following the default device
+const DEFAULT_DEVICE_CHECK_INTERVAL_MS: u64 = 1000;
+
 impl AudioSystem {
     pub fn initialize(window: &Window) -> AudioSystem {
         #[cfg(target_os = "windows")]
         let window_handle = window.window_handle() as usize; // Stupid hack
         #[cfg(not(target_os = "windows"))]
-        let _ = window; // To ignore the warning
-
-        let (thread_sender, receiver) = mpsc::channel();
-        let (sender, thread_receiver) = mpsc::channel();
-
-        thread::spawn(move || {
-            // Initialize backend
-            #[cfg(target_os = "windows")]
-            let backend = AudioBackend::initialize(window_handle);
-            #[cfg(not(target_os = "windows"))]
-            let backend = AudioBackend::initialize();
-
-            let mut backend = match backend {
-                Ok(b) => b,
-                Err(error) => {
-                    let _ = thread_sender.send(error);
-                    return;
-                },
-            };
-
-            let mut frame_counter = 0;
-            let mut timer = Timer::new();
-
-            let mut buffers = Vec::with_capacity(100);
-            let mut events  = Vec::with_capacity(100);
-            let mut mix_scratch_buffer = Vec::new();
-
-            let mut last_write = Time::ZERO;
-            let mut average_write_time = Time::ZERO;
-            let mut total_write_time = Time::ZERO;
-            let mut write_count = 0;
-
-            loop {
-                let mut did_write = false;
-
-                let start = timer.tick().0;
-
-                // Actually update audio output
-                let write_result = backend.write(
-                    &mut frame_counter,
-                    |frame, samples| {
-                        self::mix(
-                            &buffers, &mut events,
-                            &mut mix_scratch_buffer,
-                            frame, samples
-                        );
-                    },
-                );
-
-                match write_result {
-                    Ok(wrote) => {
-                        if wrote {
-                            did_write = true;
-                            last_write = start;
-                        }
-                    },
-
-                    Err(error) => {
-                        // The audio backends should handle non-critical errors themselves. If we
-                        // get an error here, we just report it and abort.
-                        let _ = thread_sender.send(error);
-                        return;
-                    },
-                }
-
-                // Remove events when they are done playing
-                let mut i = 0;
-                while i < events.len() {
-                    if events[i].done {
-                        events.swap_remove(i);
-                    } else {
-                        i += 1;
-                    }
-                }
+        let window_handle = { let _ = window; 0 }; // Not needed on other platforms
 
-                // Add new buffers/events
-                for message in thread_receiver.try_iter() {
-                    use self::MessageToAudioThread::*;
-                    match message {
-                        NewEvent { event } => {
-                            events.push(event);
-                        },
-                        AddBuffer { buffer } => {
-                            buffers.push(buffer);
-                        },
-                    }
-                }
-
-                let end = timer.tick().0;
-                if did_write {
-                    total_write_time += end - start;
-                    write_count += 1;
-                    average_write_time = Time(total_write_time.0 / write_count);
-                }
-
-                // Sleep for a bit, so this loop does not run constantly
-                let write_interval = backend.write_interval();
-                let before_sleep = timer.tick().0;
-                let next_write = last_write + write_interval;
-                let sleep_margin = Time::from_ms(2);
-
-                if average_write_time > write_interval {
-                    // TODO This means the computer we are running on is to slow to mix audio!
-                    println!("Average write time is {} ns, but write interval is {} ns", average_write_time.0, write_interval.0);
-                    return;
-                }
-
-                if next_write > before_sleep + sleep_margin {
-                    let sleep_time = next_write - (before_sleep + sleep_margin);
-                    thread::sleep(sleep_time.into());
-                    let after_sleep = timer.tick().0;
-
-                    if next_write + (write_interval - average_write_time) < after_sleep {
-                        // TODO properly handle this case
-                        // Eh: this triggered a couple of times without any audio discontinuities,
-                        // so somethign is afoot
-                        println!(
-                            "thread::sleep took to long! Should sleep to {} s, but slept until {} s",
-                            next_write.to_secs_f32(), after_sleep.to_secs_f32(),
-                        );
-                    }
-                }
-            }
-        });
+        let stats = Arc::new(Mutex::new(MixStats::default()));
+        let (sender, receiver, thread) = spawn_audio_thread(window_handle, MixerState::default(), stats.clone());
 
         AudioSystem {
             next_buffer_handle: 0,
             state: AudioSystemState::Ok,
             has_printed_error: false,
+            window_handle,
+            next_event_handle: 0,
+            finished: VecDeque::new(),
             sender,
             receiver,
+            stats,
+            thread: Some(thread),
+            stopped_mixer: None,
+            pending_buffers: Vec::new(),
         }
     }
 
-    pub fn tick(&mut self) {
-        if !self.state.is_ok() {
-            return;
+    /// Stops the audio thread and closes the audio device, waiting until the thread has exited.
+    /// Afterwards `state` is `Stopped`, and nothing is played until [`restart`] is called. This
+    /// is also done when the audio system is dropped.
+    ///
+    /// [`restart`]: #method.restart
+    pub fn shutdown(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = self.sender.send(MessageToAudioThread::Shutdown);
+            // The thread has already exited if it ran into an error, in which case this returns
+            // immediately. If it panicked its buffers are lost.
+            self.stopped_mixer = thread.join().ok();
         }
+        self.state = AudioSystemState::Stopped;
+    }
 
-        if let Ok(error) = self.receiver.try_recv() {
-            self.state = AudioSystemState::CriticalError(error);
+    /// Stops the audio thread if it is running, and starts it again with a freshly initialized
+    /// audio backend. Use this to recover when `state` is `CriticalError` or `AudioThreadDown`,
+    /// e.g. after the audio device was unplugged.
+    ///
+    /// Added buffers, the listener and other settings are kept, so existing buffer handles stay
+    /// valid. Sounds which were playing are stopped. If the device chosen with [`set_device`]
+    /// can no longer be opened, the default device is used instead.
+    ///
+    /// [`set_device`]: #method.set_device
+    pub fn restart(&mut self) {
+        self.shutdown();
+
+        // If the thread panicked we start from scratch
+        let mixer = self.stopped_mixer.take().unwrap_or_default();
+
+        let (sender, receiver, thread) = spawn_audio_thread(self.window_handle, mixer, self.stats.clone());
+        self.sender = sender;
+        self.receiver = receiver;
+        self.thread = Some(thread);
+        self.state = AudioSystemState::Ok;
+        self.has_printed_error = false;
+
+        for (handle, buffer) in ::std::mem::replace(&mut self.pending_buffers, Vec::new()) {
+            self.send_buffer(handle, buffer);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        for message in self.receiver.try_iter() {
+            match message {
+                MessageFromAudioThread::Error(error) => if self.state.is_ok() {
+                    self.state = AudioSystemState::CriticalError(error);
+                },
+                MessageFromAudioThread::Finished(finished) => {
+                    if self.finished.len() >= MAX_FINISHED_EVENTS {
+                        self.finished.pop_front();
+                    }
+                    self.finished.push_back(finished);
+                },
+            }
         }
     }
 
-    pub fn play(&mut self, buffer: BufferHandle, balance: Balance, speed: f32) {
+    /// Returns the oldest sound which has finished playing and has not been returned yet. Call
+    /// this repeatedly until it returns `None`, e.g. once per frame, to find all finished sounds.
+    /// This can be used to chain sounds, such as lines of dialogue:
+    ///
+    /// ```rust,ignore
+    /// while let Some(finished) = audio.poll_finished() {
+    ///     if finished.handle == current_line {
+    ///         current_line = audio.play(next_line, [1.0, 1.0], 1.0);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Sounds which were playing when the audio system was restarted are never reported. Only the
+    /// 256 most recent events are kept, so programs which never call this don't leak memory.
+    pub fn poll_finished(&mut self) -> Option<FinishedEvent> {
+        self.tick();
+        self.finished.pop_front()
+    }
+
+    /// Plays the given buffer. Returns a handle which identifies this playback of the buffer in
+    /// [`poll_finished`].
+    ///
+    /// [`poll_finished`]: #method.poll_finished
+    pub fn play(&mut self, buffer: BufferHandle, balance: Balance, speed: f32) -> EventHandle {
+        let handle = self.next_event_handle();
         if !self.state.is_ok() {
-            return;
+            return handle;
         }
 
         let event = Event {
             start_frame: 0,
             done: false,
+            end_frame: 0,
+            handle,
             buffer,
             balance,
             speed,
+            position: None,
         };
 
         let message = MessageToAudioThread::NewEvent { event };
@@ -264,24 +391,128 @@ impl AudioSystem {
         if send_result.is_err() {
             self.state = AudioSystemState::AudioThreadDown;
         }
+
+        handle
     }
 
-    pub fn add_buffer(&mut self, buffer: AudioBuffer) -> BufferHandle {
+    /// Plays the given buffer as if it came from `position`. The sound is panned and attenuated
+    /// based on the current [`Listener`], and keeps following the listener as it moves. Returns a
+    /// handle, like `play`.
+    ///
+    /// [`Listener`]: struct.Listener.html
+    pub fn play_at_position(&mut self, buffer: BufferHandle, position: Vec3<f32>) -> EventHandle {
+        let handle = self.next_event_handle();
+        let event = Event {
+            start_frame: 0,
+            done: false,
+            end_frame: 0,
+            handle,
+            buffer,
+            balance: [1.0; OUTPUT_CHANNELS as usize],
+            speed: 1.0,
+            position: Some(position),
+        };
+
+        self.send(MessageToAudioThread::NewEvent { event });
+        handle
+    }
+
+    fn next_event_handle(&mut self) -> EventHandle {
+        let handle = self.next_event_handle;
+        self.next_event_handle += 1;
+        handle
+    }
+
+    /// Moves the listener for positional sounds. This is usually called once per frame with the
+    /// position and orientation of the camera.
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.send(MessageToAudioThread::SetListener { listener });
+    }
+
+    /// Changes how positional sounds fade with distance. Defaults to `Attenuation::default()`.
+    pub fn set_attenuation(&mut self, attenuation: Attenuation) {
+        self.send(MessageToAudioThread::SetAttenuation { attenuation });
+    }
+
+    /// Enables or disables dithering when converting the mix to the output format. Dithering
+    /// adds a tiny amount of noise, which hides the distortion otherwise caused by rounding quiet
+    /// sounds. It is enabled by default.
+    pub fn set_dither(&mut self, dither: bool) {
+        self.send(MessageToAudioThread::SetDither { dither });
+    }
+
+    /// Level statistics for everything mixed since the audio system was started, or since the
+    /// last call to [`reset_mix_stats`]. Use this to check whether many overlapping sounds make
+    /// the output too loud, in which case the limiter lowers the volume of the whole mix.
+    ///
+    /// [`reset_mix_stats`]: #method.reset_mix_stats
+    pub fn mix_stats(&self) -> MixStats {
+        self.stats.lock().map(|stats| *stats).unwrap_or_default()
+    }
+
+    pub fn reset_mix_stats(&mut self) {
+        if let Ok(mut stats) = self.stats.lock() {
+            *stats = MixStats::default();
+        }
+    }
+
+    fn send(&mut self, message: MessageToAudioThread) {
         if !self.state.is_ok() {
-            return 0;
+            return;
         }
 
-        let message = MessageToAudioThread::AddBuffer { buffer };
         let send_result = self.sender.send(message);
         if send_result.is_err() {
             self.state = AudioSystemState::AudioThreadDown;
         }
+    }
 
+    /// Handles are never reused, even across [`restart`]. If the audio thread is not running the
+    /// buffer is kept until the next [`restart`], so the returned handle can be played afterwards.
+    ///
+    /// [`restart`]: #method.restart
+    pub fn add_buffer(&mut self, buffer: AudioBuffer) -> BufferHandle {
         let handle = self.next_buffer_handle;
         self.next_buffer_handle += 1;
+
+        if self.state.is_ok() {
+            self.send_buffer(handle, buffer);
+        } else {
+            self.pending_buffers.push((handle, buffer));
+        }
+
         return handle;
     }
 
+    fn send_buffer(&mut self, handle: BufferHandle, buffer: AudioBuffer) {
+        let message = MessageToAudioThread::AddBuffer { handle, buffer };
+        if let Err(mpsc::SendError(message)) = self.sender.send(message) {
+            self.state = AudioSystemState::AudioThreadDown;
+
+            // The buffer comes back with the error, so it is not lost
+            if let MessageToAudioThread::AddBuffer { handle, buffer } = message {
+                self.pending_buffers.push((handle, buffer));
+            }
+        }
+    }
+
+    /// Lists the audio output devices which are currently available.
+    pub fn devices(&self) -> Result<Vec<AudioDevice>, AudioError> {
+        self::devices()
+    }
+
+    /// Switches audio output to the device with the given id, as found in [`devices`]. Passing
+    /// `None` outputs to the default device of the system, and switches device whenever the
+    /// default device changes. This is the initial setting.
+    ///
+    /// Playing sounds continue on the new device. If the device can not be opened, a message is
+    /// printed and the current device is kept.
+    ///
+    /// [`devices`]: #method.devices
+    pub fn set_device(&mut self, id: Option<&str>) {
+        self.send(MessageToAudioThread::SetDevice { id: id.map(|id| id.to_owned()) });
+    }
+
     /// If `state` is not `Ok` this prints a detailed error message for the current `state`. If
     /// this function is called multiple times, it will only print once.
     pub fn print_potential_error(&mut self) {
@@ -310,17 +541,463 @@ impl AudioSystem {
                 );
             },
 
-            Ok => return,
+            Ok | Stopped => return,
+        }
+
+        self.has_printed_error = true;
+    }
+}
+
+impl Drop for AudioSystem {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// State of the mixer which is kept when the audio thread is restarted. The audio thread returns
+// this when it exits.
+struct MixerState {
+    // Indexed by handle. Buffers which never reached the audio thread are `None`.
+    buffers: Vec<Option<AudioBuffer>>,
+    listener: Listener,
+    attenuation: Attenuation,
+    dither: bool,
+    // `None` means that we follow the default device of the system
+    device: Option<String>,
+}
+
+impl Default for MixerState {
+    fn default() -> MixerState {
+        MixerState {
+            buffers: Vec::with_capacity(100),
+            listener: Listener::default(),
+            attenuation: Attenuation::default(),
+            dither: true,
+            device: None,
+        }
+    }
+}
+
+fn spawn_audio_thread(
+    window_handle: usize,
+    mixer: MixerState,
+    stats: Arc<Mutex<MixStats>>,
+) -> (mpsc::Sender<MessageToAudioThread>, mpsc::Receiver<MessageFromAudioThread>, thread::JoinHandle<MixerState>)
+{
+    let (thread_sender, receiver) = mpsc::channel();
+    let (sender, thread_receiver) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        let mut mixer = mixer;
+        if let Err(error) = run_audio_thread(window_handle, &mut mixer, thread_receiver, &thread_sender, stats) {
+            let _ = thread_sender.send(MessageFromAudioThread::Error(error));
+        }
+        mixer
+    });
+
+    (sender, receiver, thread)
+}
+
+fn run_audio_thread(
+    window_handle: usize,
+    mixer: &mut MixerState,
+    thread_receiver: mpsc::Receiver<MessageToAudioThread>,
+    thread_sender: &mpsc::Sender<MessageFromAudioThread>,
+    stats: Arc<Mutex<MixStats>>,
+) -> Result<(), AudioError>
+{
+    #[cfg(not(target_os = "windows"))]
+    let _ = window_handle;
+
+    // Initialize backend
+    let open_backend = |device: Option<&str>| {
+        #[cfg(target_os = "windows")]
+        let backend = AudioBackend::initialize(window_handle, device);
+        #[cfg(not(target_os = "windows"))]
+        let backend = AudioBackend::initialize(device);
+        backend
+    };
+
+    let mut last_device_check = Time::ZERO;
+
+    // The previously used device might have been unplugged when restarting
+    let mut backend = match open_backend(mixer.device.as_ref().map(|id| id.as_str())) {
+        Ok(b) => b,
+        Err(_) if mixer.device.is_some() => {
+            mixer.device = None;
+            open_backend(None)?
+        },
+        Err(error) => return Err(error),
+    };
+
+    let mut frame_counter = 0;
+    let mut timer = Timer::new();
+
+    let mut events  = Vec::with_capacity(100);
+    let mut mix_scratch_buffer = Vec::new();
+    let mut master = MasterStage::new(stats);
+    master.dither = mixer.dither;
+
+    let mut last_write = Time::ZERO;
+    let mut average_write_time = Time::ZERO;
+    let mut total_write_time = Time::ZERO;
+    let mut write_count = 0;
+
+    loop {
+        let mut did_write = false;
+
+        let start = timer.tick().0;
+
+        // Actually update audio output
+        let write_result = backend.write(
+            &mut frame_counter,
+            |frame, samples| {
+                self::mix(
+                    &mixer.buffers, &mut events,
+                    &mixer.listener, mixer.attenuation,
+                    &mut master,
+                    &mut mix_scratch_buffer,
+                    frame, samples
+                );
+            },
+        );
+
+        match write_result {
+            Ok(wrote) => {
+                if wrote {
+                    did_write = true;
+                    last_write = start;
+                }
+            },
+
+            // The audio backends should handle non-critical errors themselves. If we get an
+            // error here, we just report it and abort.
+            Err(error) => return Err(error),
+        }
+
+        // Remove events when they are done playing
+        let mut i = 0;
+        while i < events.len() {
+            if events[i].done {
+                let event = events.swap_remove(i);
+                let time = Time((event.end_frame*Time::NANOSECONDS_PER_SECOND) / OUTPUT_SAMPLE_RATE as u64);
+                let finished = FinishedEvent { handle: event.handle, time };
+                let _ = thread_sender.send(MessageFromAudioThread::Finished(finished));
+            } else {
+                i += 1;
+            }
+        }
+
+        // Add new buffers/events
+        let mut switch_to = None;
+        for message in thread_receiver.try_iter() {
+            use self::MessageToAudioThread::*;
+            match message {
+                NewEvent { event } => {
+                    events.push(event);
+                },
+                AddBuffer { handle, buffer } => {
+                    if mixer.buffers.len() <= handle {
+                        mixer.buffers.resize_with(handle + 1, || None);
+                    }
+                    mixer.buffers[handle] = Some(buffer);
+                },
+                SetDevice { id } => {
+                    switch_to = Some(id);
+                },
+                SetListener { listener } => {
+                    mixer.listener = listener;
+                },
+                SetAttenuation { attenuation } => {
+                    mixer.attenuation = attenuation;
+                },
+                SetDither { dither } => {
+                    master.dither = dither;
+                    mixer.dither = dither;
+                },
+                Shutdown => {
+                    return Ok(());
+                },
+            }
+        }
+
+        // Reopen the default device if the user changed it in the system settings
+        if switch_to.is_none() && mixer.device.is_none() &&
+           start - last_device_check > Time::from_ms(DEFAULT_DEVICE_CHECK_INTERVAL_MS)
+        {
+            last_device_check = start;
+            if backend.default_device_changed() {
+                switch_to = Some(None);
+            }
+        }
+
+        if let Some(id) = switch_to {
+            // Close the current device first, as some devices can only be opened once
+            drop(backend);
+
+            backend = match open_backend(id.as_ref().map(|id| id.as_str())) {
+                Ok(b) => {
+                    mixer.device = id;
+                    b
+                },
+                Err(_) => {
+                    println!("Could not open audio device {:?}, keeping the previous device", id);
+                    open_backend(mixer.device.as_ref().map(|id| id.as_str()))?
+                },
+            };
+
+            // The new device might want to be written to at a different rate
+            last_write = Time::ZERO;
+            average_write_time = Time::ZERO;
+            total_write_time = Time::ZERO;
+            write_count = 0;
+            continue;
+        }
+
+        let end = timer.tick().0;
+        if did_write {
+            total_write_time += end - start;
+            write_count += 1;
+            average_write_time = Time(total_write_time.0 / write_count);
+        }
+
+        // Sleep for a bit, so this loop does not run constantly
+        let write_interval = backend.write_interval();
+        let before_sleep = timer.tick().0;
+        let next_write = last_write + write_interval;
+        let sleep_margin = Time::from_ms(2);
+
+        if average_write_time > write_interval {
+            // TODO This means the computer we are running on is to slow to mix audio!
+            println!("Average write time is {} ns, but write interval is {} ns", average_write_time.0, write_interval.0);
+            return Ok(());
+        }
+
+        if next_write > before_sleep + sleep_margin {
+            let sleep_time = next_write - (before_sleep + sleep_margin);
+            thread::sleep(sleep_time.into());
+            let after_sleep = timer.tick().0;
+
+            if next_write + (write_interval - average_write_time) < after_sleep {
+                // TODO properly handle this case
+                // Eh: this triggered a couple of times without any audio discontinuities,
+                // so somethign is afoot
+                println!(
+                    "thread::sleep took to long! Should sleep to {} s, but slept until {} s",
+                    next_write.to_secs_f32(), after_sleep.to_secs_f32(),
+                );
+            }
+        }
+    }
+}
+
+/// Records audio from an input device, e.g. a microphone, on a background thread.
+///
+/// Captured audio is delivered in small chunks (around 10ms each), as interleaved samples. Either
+/// [`open`] the device and [`poll`] for new chunks each frame, or use [`open_with_callback`] to
+/// process chunks on the capture thread as soon as they arrive.
+///
+/// ```rust,ignore
+/// let mut capture = AudioCapture::open(None, 48000, 1)?;
+///
+/// // Each frame
+/// while let Some(buffer) = capture.poll() {
+///     voice_chat.send(&buffer.data);
+/// }
+/// capture.print_potential_error();
+/// ```
+///
+/// Capturing stops when the `AudioCapture` is dropped.
+///
+/// [`open`]: #method.open
+/// [`poll`]: #method.poll
+/// [`open_with_callback`]: #method.open_with_callback
+pub struct AudioCapture {
+    sample_rate: u32,
+    channels: u32,
+
+    pub state: AudioSystemState,
+    has_printed_error: bool,
+
+    buffers: Option<mpsc::Receiver<Vec<SampleData>>>,
+    errors: mpsc::Receiver<AudioError>,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl AudioCapture {
+    /// Lists the audio input devices which are currently available.
+    pub fn devices() -> Result<Vec<AudioDevice>, AudioError> {
+        self::capture_devices()
+    }
+
+    /// Starts capturing from the device with the given id, as found in [`devices`], or from the
+    /// default input device if no id is given. Captured chunks are returned by [`poll`].
+    ///
+    /// If the device does not support `sample_rate`, the closest supported rate is used instead.
+    /// Check [`sample_rate`] to see which rate is actually used.
+    ///
+    /// [`devices`]: #method.devices
+    /// [`poll`]: #method.poll
+    /// [`sample_rate`]: #method.sample_rate
+    pub fn open(device: Option<&str>, sample_rate: u32, channels: u32) -> Result<AudioCapture, AudioError> {
+        let (sender, receiver) = mpsc::channel();
+        let mut capture = AudioCapture::open_with_callback(device, sample_rate, channels, move |samples| {
+            let _ = sender.send(samples.to_vec());
+        })?;
+        capture.buffers = Some(receiver);
+        Ok(capture)
+    }
+
+    /// Like [`open`], but calls `callback` on the capture thread with the samples of each chunk,
+    /// instead of returning them from [`poll`]. This avoids latency, but the callback must return
+    /// quickly so no audio is lost.
+    ///
+    /// [`open`]: #method.open
+    /// [`poll`]: #method.poll
+    pub fn open_with_callback<F>(
+        device: Option<&str>,
+        sample_rate: u32,
+        channels: u32,
+        mut callback: F,
+    ) -> Result<AudioCapture, AudioError>
+      where F: FnMut(&[SampleData]) + Send + 'static,
+    {
+        let device = device.map(|device| device.to_owned());
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+
+        let (status_sender, status_receiver) = mpsc::channel();
+        let (error_sender, error_receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let device = device.as_ref().map(|device| device.as_str());
+            let mut backend = match CaptureBackend::initialize(device, sample_rate, channels) {
+                Ok(b) => b,
+                Err(error) => {
+                    let _ = status_sender.send(Err(error));
+                    return;
+                },
+            };
+            let _ = status_sender.send(Ok(backend.sample_rate()));
+
+            let mut samples = Vec::new();
+            while thread_running.load(Ordering::Relaxed) {
+                samples.clear();
+                if let Err(error) = backend.read(&mut samples) {
+                    let _ = error_sender.send(error);
+                    return;
+                }
+
+                if !samples.is_empty() {
+                    callback(&samples);
+                }
+            }
+        });
+
+        let sample_rate = match status_receiver.recv() {
+            Ok(Ok(sample_rate)) => sample_rate,
+            Ok(Err(error)) => return Err(error),
+            Err(_) => {
+                let message = "Audio capture thread stopped unexpectedly".to_owned();
+                return Err(AudioError::Other { message });
+            },
+        };
+
+        Ok(AudioCapture {
+            sample_rate,
+            channels,
+
+            state: AudioSystemState::Ok,
+            has_printed_error: false,
+
+            buffers: None,
+            errors: error_receiver,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// The sample rate audio is captured at. This can differ from the requested sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// Checks whether the capture thread has run into an error, and updates `state` accordingly.
+    /// This is also done by [`poll`].
+    ///
+    /// [`poll`]: #method.poll
+    pub fn tick(&mut self) {
+        if !self.state.is_ok() {
+            return;
+        }
+
+        match self.errors.try_recv() {
+            Ok(error) => self.state = AudioSystemState::CriticalError(error),
+            Err(mpsc::TryRecvError::Disconnected) => self.state = AudioSystemState::AudioThreadDown,
+            Err(mpsc::TryRecvError::Empty) => {},
+        }
+    }
+
+    /// Returns the oldest chunk of captured audio which has not been returned yet. Call this
+    /// repeatedly until it returns `None` to get all captured audio. Always returns `None` if
+    /// the capture was opened with [`open_with_callback`].
+    ///
+    /// [`open_with_callback`]: #method.open_with_callback
+    pub fn poll(&mut self) -> Option<AudioBuffer> {
+        self.tick();
+
+        let data = match self.buffers {
+            Some(ref buffers) => buffers.try_recv().ok()?,
+            None => return None,
+        };
+
+        Some(AudioBuffer {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            data,
+        })
+    }
+
+    /// If `state` is not `Ok` this prints a detailed error message for the current `state`. If
+    /// this function is called multiple times, it will only print once.
+    pub fn print_potential_error(&mut self) {
+        if self.has_printed_error || self.state.is_ok() {
+            return;
+        }
+
+        match self.state {
+            AudioSystemState::AudioThreadDown => println!("Audio capture thread stopped unexpectedly"),
+            AudioSystemState::CriticalError(ref error) => println!("Critical error in audio capture: {}", error),
+            AudioSystemState::Ok | AudioSystemState::Stopped => {},
         }
 
         self.has_printed_error = true;
     }
 }
 
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        // The thread checks this between each chunk it reads, so this does not block for long
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
 // This is called through a callback from ´backend::write´
 fn mix(
-    buffers: &[AudioBuffer], 
+    buffers: &[Option<AudioBuffer>],
     events: &mut [Event],
+    listener: &Listener,
+    attenuation: Attenuation,
+    master: &mut MasterStage,
     scratch_buffer: &mut Vec<f32>,
 
     target_start_frame: u64,
@@ -338,7 +1015,21 @@ fn mix(
     }
 
     for event in events.iter_mut() {
-        let ref buffer = buffers[event.buffer];
+        let buffer = match buffers.get(event.buffer) {
+            Some(&Some(ref buffer)) => buffer,
+            _ => {
+                // The buffer was lost, e.g. because it was added while the audio thread was down
+                event.done = true;
+                event.end_frame = target_start_frame;
+                continue;
+            },
+        };
+
+        // Recomputed for each block, so positional sounds follow the listener
+        let balance = match event.position {
+            Some(position) => positional_balance(listener, attenuation, position),
+            None => event.balance,
+        };
 
         if event.start_frame == 0 {
             // Start the sound playing now
@@ -362,6 +1053,7 @@ fn mix(
 
         if event_end_frame < target_start_frame {
             event.done = true;
+            event.end_frame = event_end_frame;
         }
 
         let start_frame = Ord::max(event_start_frame, target_start_frame);
@@ -405,11 +1097,11 @@ fn mix(
 
                 // Linearly interpolate to find the proper sample value. In theory, this gives us a
                 // better result, but in practice it doesn't matter: I can't hear the difference.
-                let prev_sample = read_data[prev_read_pos] as f32;
-                let next_sample = read_data[next_read_pos] as f32;
+                let prev_sample = read_data[prev_read_pos] as f32 / SAMPLE_SCALE;
+                let next_sample = read_data[next_read_pos] as f32 / SAMPLE_SCALE;
                 let sample = prev_sample*(1.0 - t) + next_sample*t;
 
-                let volume = event.balance[output_channel];
+                let volume = balance[output_channel];
 
                 let write_pos = (frame as usize)*(OUTPUT_CHANNELS as usize) + output_channel;
                 write_data[write_pos] += sample*volume;
@@ -417,14 +1109,8 @@ fn mix(
         }
     }
 
-    // Write the scratchbuffer back into the provided sample buffer
-    let min = SampleData::min_value() as f32;
-    let max = SampleData::max_value() as f32;
-
-    for (index, &sample) in scratch_buffer.iter().enumerate() {
-        let clipped = clamp(sample, (min, max));
-        samples[index] = clipped as i16;
-    }
+    // Limit and convert the scratchbuffer into the provided sample buffer
+    master.process(scratch_buffer, samples);
 }
 
 #[inline(always)]
@@ -452,3 +1138,87 @@ pub enum AudioError {
         file: &'static str,
     },
 }
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AudioError::Other { ref message } => write!(f, "{}", message),
+            AudioError::BadReturn { ref function_name, error_code, line, file } => {
+                write!(f, "`{}` returned {} unexpectedly at {}:{}", function_name, error_code, file, line)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positional_panning() {
+        let listener = Listener::default();
+        let attenuation = Attenuation::Linear { min_distance: 1.0, max_distance: 11.0 };
+
+        let [left, right] = positional_balance(&listener, attenuation, Vec3::new(1.0, 0.0, 0.0));
+        assert!(left.abs() < 0.0001 && (right - 1.0).abs() < 0.0001);
+
+        let [left, right] = positional_balance(&listener, attenuation, Vec3::new(0.0, 0.0, -6.0));
+        assert!((left - right).abs() < 0.0001);
+        assert!((left*left + right*right - 0.25).abs() < 0.0001);
+
+        let [left, right] = positional_balance(&listener, attenuation, Vec3::new(-20.0, 0.0, 0.0));
+        assert_eq!((left, right), (0.0, 0.0));
+    }
+
+    #[test]
+    fn events_finish() {
+        let buffers = vec![Some(AudioBuffer { channels: 1, sample_rate: OUTPUT_SAMPLE_RATE, data: vec![1000; 100] })];
+        let mut events = vec![Event {
+            start_frame: 0,
+            done: false,
+            end_frame: 0,
+            handle: 7,
+            buffer: 0,
+            balance: [1.0, 1.0],
+            speed: 1.0,
+            position: None,
+        }];
+        let mut master = MasterStage::new(Arc::new(Mutex::new(MixStats::default())));
+        let mut scratch = Vec::new();
+        let mut samples = vec![0; 128*OUTPUT_CHANNELS as usize];
+
+        let mut mix_block = |events: &mut [Event], start: u64, samples: &mut [SampleData]| {
+            mix(&buffers, events, &Listener::default(), Attenuation::default(), &mut master, &mut scratch, start, samples);
+        };
+
+        mix_block(&mut events, 1, &mut samples);
+        assert!(!events[0].done);
+        assert!(samples[0] != 0);
+
+        mix_block(&mut events, 129, &mut samples);
+        assert!(events[0].done);
+        assert_eq!(events[0].end_frame, 101);
+    }
+
+    #[test]
+    fn missing_buffer() {
+        let buffers = vec![None];
+        let mut events = vec![Event {
+            start_frame: 0,
+            done: false,
+            end_frame: 0,
+            handle: 7,
+            buffer: 1,
+            balance: [1.0, 1.0],
+            speed: 1.0,
+            position: None,
+        }];
+        let mut master = MasterStage::new(Arc::new(Mutex::new(MixStats::default())));
+        let mut scratch = Vec::new();
+        let mut samples = vec![0; 128*OUTPUT_CHANNELS as usize];
+
+        mix(&buffers, &mut events, &Listener::default(), Attenuation::default(), &mut master, &mut scratch, 1, &mut samples);
+        assert!(events[0].done);
+        assert_eq!(events[0].end_frame, 1);
+    }
+}
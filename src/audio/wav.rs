@@ -1,146 +1,269 @@
-
 //! Loading .wav files
+//!
+//! Supported encodings are 8, 16, 24 and 32 bit integer PCM, 32 and 64 bit float and IMA-ADPCM.
+//! All of them are converted to 16 bit samples when loading.
 
 use std::fs::File;
 use std::path::Path;
 use std::io::{self, Read};
 use std::error;
 use std::fmt;
-use std::mem;
-use std::slice;
 
 use super::*;
 
-const HEADER_SIZE: usize = 44;
+// Values for the format tag in the "fmt " chunk
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_FLOAT: u16 = 0x0003;
+const FORMAT_IMA_ADPCM: u16 = 0x0011;
+// The actual format is stored in the first two bytes of the sub-format guid
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
 
 pub fn load<P: AsRef<Path>>(path: P) -> Result<AudioBuffer, WavError> {
     let path = path.as_ref();
     let mut file = File::open(path)?;
     let metadata = file.metadata()?;
 
-    let mut header = [0u8; HEADER_SIZE];
-    match file.read_exact(&mut header) {
-        Ok(()) => {},
-        Err(err) => {
-            if err.kind() == io::ErrorKind::UnexpectedEof {
-                return Err(WavError::InvalidHeader);
-            } else {
-                return Err(WavError::Io(err));
-            }
-        },
-    }
+    let mut bytes = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut bytes)?;
 
-    // There are some magic numbers in the header, check for those
-    let mut bad = false;
-    bad |= &header[0..4]   != b"RIFF"; 
-    bad |= &header[8..12]  != b"WAVE"; 
-    bad |= &header[12..15] != b"fmt"; 
-    bad |= &header[36..40] != b"data";
-    if bad {
+    from_bytes(&bytes)
+}
+
+/// Same as [`load`], but reads from data which has already been loaded into memory, e.g. with the
+/// `include_bytes!` macro or from an asset pack.
+///
+/// [`load`]: fn.load.html
+pub fn from_bytes(bytes: &[u8]) -> Result<AudioBuffer, WavError> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
         return Err(WavError::InvalidHeader);
     }
 
-    #[inline(always)]
-    fn get_u32(slice: &[u8]) -> u32 {
-        ((slice[0] as u32) << 0x00) |
-        ((slice[1] as u32) << 0x08) |
-        ((slice[2] as u32) << 0x10) |
-        ((slice[3] as u32) << 0x18)
+    // Find the chunks we care about. Other chunks (e.g. "LIST" chunks with metadata) are skipped.
+    let mut format = None;
+    let mut data = None;
+    let mut fact = None;
+
+    let mut rest = &bytes[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let size = get_u32(&rest[4..]) as usize;
+        rest = &rest[8..];
+
+        // Some writers put a wrong size in the last chunk, so we allow it to be cut short
+        let chunk = &rest[..Ord::min(size, rest.len())];
+
+        match id {
+            b"fmt " => format = Some(chunk),
+            b"data" => data = Some(chunk),
+            b"fact" => fact = Some(chunk),
+            _ => {},
+        }
+
+        // Chunks are padded to an even number of bytes
+        let padded_size = size + (size & 1);
+        rest = &rest[Ord::min(padded_size, rest.len())..];
+    }
+
+    let format = format.ok_or(WavError::MissingChunk("fmt "))?;
+    let data = data.ok_or(WavError::MissingChunk("data"))?;
+
+    if format.len() < 16 {
+        return Err(WavError::InvalidHeader);
     }
 
-    #[inline(always)]
-    fn get_u16(slice: &[u8]) -> u16 {
-        ((slice[0] as u16) << 0x00) |
-        ((slice[1] as u16) << 0x08)
+    let mut format_tag   = get_u16(&format[0..]);
+    let channels         = get_u16(&format[2..]) as usize;
+    let sample_rate      = get_u32(&format[4..]);
+    let block_align      = get_u16(&format[12..]) as usize;
+    let bits_per_sample  = get_u16(&format[14..]);
+
+    if format_tag == FORMAT_EXTENSIBLE {
+        // cbSize (2), valid bits (2), channel mask (4), sub-format guid (16)
+        if format.len() < 40 {
+            return Err(WavError::InvalidHeader);
+        }
+        format_tag = get_u16(&format[24..]);
     }
 
-    let channels         = get_u16(&header[22..]) as usize;
-    let sample_rate      = get_u32(&header[24..]) as usize;
-    let bytes_per_second = get_u32(&header[28..]) as usize;
-    let bytes_per_frame  = get_u16(&header[32..]) as usize;
-    let bits_per_sample  = get_u16(&header[34..]) as usize;
-    let bytes_per_sample = (bits_per_sample / 8) as usize;
-    let file_size        = get_u32(&header[4..]) as usize + 8;
-    let data_bytes       = get_u32(&header[40..]) as usize;
-
-    // Check if the values in the header are coherent
-    let mut bad = false;
-    bad |= bits_per_sample%8 != 0; // Ensure each sample is a whole number of bytes
-    bad |= !(bytes_per_sample == mem::size_of::<u8>() || bytes_per_sample == mem::size_of::<i16>());
-    bad |= bytes_per_second != bytes_per_frame*sample_rate;
-    bad |= bytes_per_sample*channels != bytes_per_frame;
-    bad |= file_size != data_bytes+HEADER_SIZE;
-    bad |= metadata.len() as usize != file_size;
-    bad |= data_bytes % (bytes_per_frame as usize) != 0;
-    bad |= get_u32(&header[16..]) != 16;
-    bad |= get_u16(&header[20..]) != 1; // PCM data
-    if bad {
+    if channels == 0 || sample_rate == 0 || block_align == 0 {
         return Err(WavError::InvalidHeader);
     }
 
-    // Read the data from the file
-    let sample_count = data_bytes / bytes_per_sample;
-    
-    let data = match bytes_per_sample {
-        // i16
-        2 => {
-            let mut samples = Vec::<i16>::with_capacity(sample_count);
-            unsafe { samples.set_len(sample_count) };
-
-            {
-                let slice = &mut samples[..];
-                let ptr = slice.as_mut_ptr() as *mut u8;
-                let len = slice.len() * mem::size_of::<i16>();
-                let byte_slice = unsafe { slice::from_raw_parts_mut(ptr, len) };
-
-                file.read_exact(byte_slice)?;
+    let unsupported = WavError::UnsupportedFormat { format_tag, bits_per_sample };
+
+    let samples = match (format_tag, bits_per_sample) {
+        (FORMAT_PCM, 8) | (FORMAT_PCM, 16) | (FORMAT_PCM, 24) | (FORMAT_PCM, 32) |
+        (FORMAT_FLOAT, 32) | (FORMAT_FLOAT, 64) => {
+            let bytes_per_sample = bits_per_sample as usize / 8;
+            if bytes_per_sample*channels != block_align {
+                return Err(WavError::InvalidHeader);
             }
 
-            if cfg!(target_endian = "big") {
-                // This is slow, but never really happens because x86 chips are little endian
-                for sample in samples.iter_mut() {
-                    *sample = sample.swap_bytes();
-                }
+            // Ignore trailing bytes which do not make up a whole frame
+            let frames = data.len() / block_align;
+            let data = &data[..frames*block_align];
+
+            match (format_tag, bits_per_sample) {
+                (FORMAT_PCM, 8) => data.iter()
+                    .map(|&b| (((b as i32) - 128) * 256) as i16)
+                    .collect(),
+                (FORMAT_PCM, 16) => data.chunks(2)
+                    .map(|b| get_u16(b) as i16)
+                    .collect(),
+                // Keep the most significant bytes
+                (FORMAT_PCM, 24) => data.chunks(3)
+                    .map(|b| get_u16(&b[1..]) as i16)
+                    .collect(),
+                (FORMAT_PCM, 32) => data.chunks(4)
+                    .map(|b| get_u16(&b[2..]) as i16)
+                    .collect(),
+                (FORMAT_FLOAT, 32) => data.chunks(4)
+                    .map(|b| float_to_i16(f32::from_bits(get_u32(b)) as f64))
+                    .collect(),
+                (FORMAT_FLOAT, 64) => data.chunks(8)
+                    .map(|b| float_to_i16(f64::from_bits(get_u32(b) as u64 | (get_u32(&b[4..]) as u64) << 32)))
+                    .collect(),
+                _ => unreachable!(),
             }
+        },
+
+        (FORMAT_IMA_ADPCM, 4) => {
+            // The total frame count is stored in the "fact" chunk, as the last block might
+            // contain padding
+            let frames = match fact {
+                Some(fact) if fact.len() >= 4 => Some(get_u32(fact) as usize),
+                _ => None,
+            };
 
+            let mut samples = decode_ima_adpcm(data, channels, block_align)?;
+            if let Some(frames) = frames {
+                samples.truncate(frames*channels);
+            }
             samples
         },
 
-        // u8
-        1 => {
-            let mut u8_samples = Vec::<u8>::with_capacity(sample_count);
-            unsafe { u8_samples.set_len(sample_count) };
-            file.read_exact(&mut u8_samples[..])?;
+        _ => return Err(unsupported),
+    };
+
+    Ok(AudioBuffer {
+        channels: channels as u32,
+        sample_rate,
+        data: samples,
+    })
+}
 
-            // Convert to i16 samples
-            let min  = i16::min_value();
-            let step = 0x0101;
+#[inline(always)]
+fn get_u32(slice: &[u8]) -> u32 {
+    ((slice[0] as u32) << 0x00) |
+    ((slice[1] as u32) << 0x08) |
+    ((slice[2] as u32) << 0x10) |
+    ((slice[3] as u32) << 0x18)
+}
 
-            let mut i16_samples = Vec::<i16>::with_capacity(sample_count);
-            for &sample in u8_samples.iter() {
-                let converted = min + (sample as i16)*step;
-                i16_samples.push(converted);
-            }
+#[inline(always)]
+fn get_u16(slice: &[u8]) -> u16 {
+    ((slice[0] as u16) << 0x00) |
+    ((slice[1] as u16) << 0x08)
+}
 
-            i16_samples
-        },
+#[inline(always)]
+fn float_to_i16(sample: f64) -> i16 {
+    let sample = sample.max(-1.0).min(1.0);
+    (sample * i16::max_value() as f64) as i16
+}
 
-        _ => unreachable!()
-    };
+const IMA_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408,
+    449, 494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066,
+    2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630,
+    9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
 
-    drop(file); // Closes the file
+const IMA_INDEX_TABLE: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
 
-    return Ok(AudioBuffer {
-        channels: channels as u32,
-        sample_rate: sample_rate as u32,
-        data,
-    });
+// Decoder state for a single channel
+#[derive(Copy, Clone)]
+struct ImaChannel {
+    predictor: i32,
+    step_index: i32,
+}
+
+impl ImaChannel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = IMA_STEP_TABLE[self.step_index as usize];
+
+        let mut diff = step >> 3;
+        if nibble & 1 != 0 { diff += step >> 2; }
+        if nibble & 2 != 0 { diff += step >> 1; }
+        if nibble & 4 != 0 { diff += step; }
+        if nibble & 8 != 0 { diff = -diff; }
+
+        self.predictor = clamp(self.predictor + diff, (i16::min_value() as i32, i16::max_value() as i32));
+        self.step_index = clamp(self.step_index + IMA_INDEX_TABLE[(nibble & 7) as usize], (0, 88));
+
+        self.predictor as i16
+    }
+}
+
+// Each block starts with a four byte header per channel, followed by four byte groups of
+// nibbles, alternating between channels.
+fn decode_ima_adpcm(data: &[u8], channels: usize, block_align: usize) -> Result<Vec<i16>, WavError> {
+    let header_size = 4*channels;
+    if block_align <= header_size || (block_align - header_size) % header_size != 0 {
+        return Err(WavError::InvalidHeader);
+    }
+
+    let frames_per_block = (block_align - header_size)*2/channels + 1;
+    let block_count = (data.len() + block_align - 1) / block_align;
+    let mut samples = Vec::with_capacity(block_count*frames_per_block*channels);
+
+    let mut states = vec![ImaChannel { predictor: 0, step_index: 0 }; channels];
+    let mut block_samples = vec![0i16; frames_per_block*channels];
+
+    for block in data.chunks(block_align) {
+        if block.len() < header_size {
+            break;
+        }
+
+        for (channel, state) in states.iter_mut().enumerate() {
+            let header = &block[channel*4..];
+            state.predictor = get_u16(header) as i16 as i32;
+            state.step_index = clamp(header[2] as i32, (0, 88));
+            block_samples[channel] = state.predictor as i16;
+        }
+
+        // Partial blocks at the end of the data only decode the frames which are present
+        let mut frames = 1;
+        for (group_index, group) in block[header_size..].chunks(4).enumerate() {
+            let channel = group_index % channels;
+            let first_frame = 1 + (group_index / channels)*8;
+
+            for (i, &byte) in group.iter().enumerate() {
+                let frame = first_frame + i*2;
+                let state = &mut states[channel];
+                block_samples[frame*channels + channel] = state.decode(byte & 0x0f);
+                block_samples[(frame + 1)*channels + channel] = state.decode(byte >> 4);
+                frames = Ord::max(frames, frame + 2);
+            }
+        }
+
+        samples.extend_from_slice(&block_samples[..frames*channels]);
+    }
+
+    Ok(samples)
 }
 
 #[derive(Debug)]
 pub enum WavError {
     Io(io::Error),
     InvalidHeader,
+    /// A chunk required to load the file, e.g. `"data"`, was not found.
+    MissingChunk(&'static str),
+    /// The file uses an encoding we can not decode. See the module documentation for which
+    /// encodings are supported.
+    UnsupportedFormat { format_tag: u16, bits_per_sample: u16 },
 }
 
 impl error::Error for WavError {
@@ -148,6 +271,8 @@ impl error::Error for WavError {
         match *self {
             WavError::Io(ref inner) => inner.description(),
             WavError::InvalidHeader => "Invalid WAV header",
+            WavError::MissingChunk(..) => "Missing chunk in WAV file",
+            WavError::UnsupportedFormat { .. } => "Unsupported WAV encoding",
         }
     }
 
@@ -164,6 +289,11 @@ impl fmt::Display for WavError {
         match *self {
             WavError::Io(ref inner) => write!(f, "IO error while loading wav file: {}", inner),
             WavError::InvalidHeader => write!(f, "Invalid header"),
+            WavError::MissingChunk(id) => write!(f, "No \"{}\" chunk in wav file", id),
+            WavError::UnsupportedFormat { format_tag, bits_per_sample } => write!(
+                f, "Unsupported wav encoding: format tag 0x{:04x} with {} bits per sample",
+                format_tag, bits_per_sample,
+            ),
         }
     }
 }
@@ -173,3 +303,65 @@ impl From<io::Error> for WavError {
         WavError::Io(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_wav(format_tag: u16, channels: u16, bits: u16, block_align: u16, data: &[u8]) -> Vec<u8> {
+        fn u16_bytes(v: u16) -> [u8; 2] { [v as u8, (v >> 8) as u8] }
+        fn u32_bytes(v: u32) -> [u8; 4] { [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8] }
+
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&u16_bytes(format_tag));
+        fmt.extend_from_slice(&u16_bytes(channels));
+        fmt.extend_from_slice(&u32_bytes(44100));
+        fmt.extend_from_slice(&u32_bytes(44100 * block_align as u32));
+        fmt.extend_from_slice(&u16_bytes(block_align));
+        fmt.extend_from_slice(&u16_bytes(bits));
+
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&u32_bytes(0)); // Not checked
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&u32_bytes(fmt.len() as u32));
+        wav.extend_from_slice(&fmt);
+        wav.extend_from_slice(b"LIST");
+        wav.extend_from_slice(&u32_bytes(3));
+        wav.extend_from_slice(b"abc\0"); // Padded to an even length
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&u32_bytes(data.len() as u32));
+        wav.extend_from_slice(data);
+        wav
+    }
+
+    #[test]
+    fn encodings() {
+        // 8 bit samples are unsigned
+        let wav = build_wav(FORMAT_PCM, 1, 8, 1, &[0, 128, 255]);
+        assert_eq!(from_bytes(&wav).unwrap().data, vec![-0x8000, 0, 0x7f00]);
+
+        let wav = build_wav(FORMAT_PCM, 1, 24, 3, &[0xff, 0x34, 0x12, 0x00, 0x00, 0x80]);
+        assert_eq!(from_bytes(&wav).unwrap().data, vec![0x1234, -0x8000]);
+
+        let mut floats = Vec::new();
+        for &v in [0.5f32, -1.0, 2.0].iter() {
+            let bits = v.to_bits();
+            floats.extend_from_slice(&[bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8]);
+        }
+        let wav = build_wav(FORMAT_FLOAT, 1, 32, 4, &floats);
+        assert_eq!(from_bytes(&wav).unwrap().data, vec![16383, -32767, 32767]);
+
+        // Header with a predictor of 100 and step index 0, followed by 8 encoded samples
+        let wav = build_wav(FORMAT_IMA_ADPCM, 1, 4, 8, &[100, 0, 0, 0, 0x07, 0x00, 0x00, 0x00]);
+        let buffer = from_bytes(&wav).unwrap();
+        assert_eq!(buffer.data.len(), 9);
+        assert_eq!(&buffer.data[..3], &[100, 111, 113]);
+
+        match from_bytes(&build_wav(0x0055, 1, 0, 1, &[])) {
+            Err(WavError::UnsupportedFormat { format_tag: 0x0055, .. }) => {},
+            _ => panic!(),
+        }
+    }
+}
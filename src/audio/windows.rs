@@ -13,16 +13,279 @@ use time::{Time, Timer};
 // We access all ffi stuff through `ffi::whatever` instead of through each apis specific
 // bindings. This allows us to easily add custom stuff that is missing in bindings.
 mod ffi {
-    #![allow(non_camel_case_types)]
+    #![allow(non_camel_case_types, non_snake_case)]
 
     pub(super) use super::winapi::*;
     pub(super) use super::kernel32::*;
 
-//    pub(super) type LPDSENUMCALLBACK = Option<unsafe extern "system" fn(LPGUID, LPCSTR, LPCSTR, LPVOID) -> BOOL>;
+    pub(super) type LPDSENUMCALLBACKW = Option<unsafe extern "system" fn(LPGUID, LPCWSTR, LPCWSTR, LPVOID) -> BOOL>;
 
     // Direct-sound functions
     pub(super) type DirectSoundCreate = extern "system" fn(LPGUID, *mut LPDIRECTSOUND, LPUNKNOWN) -> HRESULT;
-//    pub(super) type DirectSoundEnumerate = extern "system" fn(LPDSENUMCALLBACK, LPVOID) -> HRESULT;
+    pub(super) type DirectSoundEnumerateW = extern "system" fn(LPDSENUMCALLBACKW, LPVOID) -> HRESULT;
+    pub(super) type GetDeviceID = extern "system" fn(LPCGUID, LPGUID) -> HRESULT;
+    pub(super) type DirectSoundCaptureCreate = extern "system" fn(LPCGUID, *mut LPDIRECTSOUNDCAPTURE, LPUNKNOWN) -> HRESULT;
+    pub(super) type DirectSoundCaptureEnumerateW = extern "system" fn(LPDSENUMCALLBACKW, LPVOID) -> HRESULT;
+
+    // Direct-sound capture interfaces, which are missing from winapi
+    pub(super) const DSCBSTART_LOOPING: DWORD = 0x1;
+
+    #[repr(C)]
+    pub(super) struct DSCBUFFERDESC {
+        pub dwSize: DWORD,
+        pub dwFlags: DWORD,
+        pub dwBufferBytes: DWORD,
+        pub dwReserved: DWORD,
+        pub lpwfxFormat: LPWAVEFORMATEX,
+        pub dwFXCount: DWORD,
+        pub lpDSCFXDesc: LPVOID,
+    }
+
+    #[repr(C)]
+    pub(super) struct IDirectSoundCaptureVtbl {
+        pub parent: IUnknownVtbl,
+        pub CreateCaptureBuffer: unsafe extern "system" fn(
+            This: *mut IDirectSoundCapture,
+            pcDSCBufferDesc: *const DSCBUFFERDESC,
+            ppDSCBuffer: *mut LPDIRECTSOUNDCAPTUREBUFFER,
+            pUnkOuter: LPUNKNOWN,
+        ) -> HRESULT,
+        pub GetCaps: unsafe extern "system" fn(This: *mut IDirectSoundCapture, pDSCCaps: LPVOID) -> HRESULT,
+        pub Initialize: unsafe extern "system" fn(This: *mut IDirectSoundCapture, pcGuidDevice: LPCGUID) -> HRESULT,
+    }
+
+    #[repr(C)]
+    pub(super) struct IDirectSoundCapture {
+        pub lpVtbl: *const IDirectSoundCaptureVtbl,
+    }
+    pub(super) type LPDIRECTSOUNDCAPTURE = *mut IDirectSoundCapture;
+
+    #[repr(C)]
+    pub(super) struct IDirectSoundCaptureBufferVtbl {
+        pub parent: IUnknownVtbl,
+        pub GetCaps: unsafe extern "system" fn(This: *mut IDirectSoundCaptureBuffer, pDSCBCaps: LPVOID) -> HRESULT,
+        pub GetCurrentPosition: unsafe extern "system" fn(
+            This: *mut IDirectSoundCaptureBuffer,
+            pdwCapturePosition: LPDWORD,
+            pdwReadPosition: LPDWORD,
+        ) -> HRESULT,
+        pub GetFormat: unsafe extern "system" fn(
+            This: *mut IDirectSoundCaptureBuffer,
+            pwfxFormat: LPWAVEFORMATEX,
+            dwSizeAllocated: DWORD,
+            pdwSizeWritten: LPDWORD,
+        ) -> HRESULT,
+        pub GetStatus: unsafe extern "system" fn(This: *mut IDirectSoundCaptureBuffer, pdwStatus: LPDWORD) -> HRESULT,
+        pub Initialize: unsafe extern "system" fn(
+            This: *mut IDirectSoundCaptureBuffer,
+            pDirectSoundCapture: LPDIRECTSOUNDCAPTURE,
+            pcDSCBufferDesc: *const DSCBUFFERDESC,
+        ) -> HRESULT,
+        pub Lock: unsafe extern "system" fn(
+            This: *mut IDirectSoundCaptureBuffer,
+            dwOffset: DWORD,
+            dwBytes: DWORD,
+            ppvAudioPtr1: *mut LPVOID,
+            pdwAudioBytes1: LPDWORD,
+            ppvAudioPtr2: *mut LPVOID,
+            pdwAudioBytes2: LPDWORD,
+            dwFlags: DWORD,
+        ) -> HRESULT,
+        pub Start: unsafe extern "system" fn(This: *mut IDirectSoundCaptureBuffer, dwFlags: DWORD) -> HRESULT,
+        pub Stop: unsafe extern "system" fn(This: *mut IDirectSoundCaptureBuffer) -> HRESULT,
+        pub Unlock: unsafe extern "system" fn(
+            This: *mut IDirectSoundCaptureBuffer,
+            pvAudioPtr1: LPVOID,
+            dwAudioBytes1: DWORD,
+            pvAudioPtr2: LPVOID,
+            dwAudioBytes2: DWORD,
+        ) -> HRESULT,
+    }
+
+    #[repr(C)]
+    pub(super) struct IDirectSoundCaptureBuffer {
+        pub lpVtbl: *const IDirectSoundCaptureBufferVtbl,
+    }
+    pub(super) type LPDIRECTSOUNDCAPTUREBUFFER = *mut IDirectSoundCaptureBuffer;
+
+    #[allow(non_upper_case_globals)]
+    pub(super) const DSDEVID_DefaultPlayback: GUID = GUID {
+        Data1: 0xdef00000,
+        Data2: 0x9c6d,
+        Data3: 0x47ed,
+        Data4: [0xaa, 0xf1, 0x4d, 0xda, 0x8f, 0x2b, 0x5c, 0x03],
+    };
+    #[allow(non_upper_case_globals)]
+    pub(super) const DSDEVID_DefaultCapture: GUID = GUID {
+        Data1: 0xdef00001,
+        Data2: 0x9c6d,
+        Data3: 0x47ed,
+        Data4: [0xaa, 0xf1, 0x4d, 0xda, 0x8f, 0x2b, 0x5c, 0x03],
+    };
+}
+
+// The functions we need from "dsound.dll"
+struct DirectSoundLibrary {
+    direct_sound_create: ffi::DirectSoundCreate,
+    direct_sound_enumerate: ffi::DirectSoundEnumerateW,
+    get_device_id: ffi::GetDeviceID,
+    direct_sound_capture_create: ffi::DirectSoundCaptureCreate,
+    direct_sound_capture_enumerate: ffi::DirectSoundCaptureEnumerateW,
+}
+
+impl DirectSoundLibrary {
+    fn load() -> Result<DirectSoundLibrary, AudioError> {
+        let library_name = b"dsound.dll\0";
+        let dsound_lib = unsafe { ffi::LoadLibraryA(library_name.as_ptr() as *const i8) };
+
+        if dsound_lib.is_null() {
+            let message = "Could not load library \"dsound.dll\"".to_owned();
+            return Err(AudioError::Other { message });
+        }
+
+        let load = |name: &str| {
+            let c_name = format!("{}\0", name);
+            let address = unsafe { ffi::GetProcAddress(dsound_lib, c_name.as_ptr() as *const _) };
+
+            if address.is_null() {
+                let message = format!("No `{}` in \"dsound.dll\"", name);
+                Err(AudioError::Other { message })
+            } else {
+                Ok(address)
+            }
+        };
+
+        unsafe {
+            Ok(DirectSoundLibrary {
+                direct_sound_create: mem::transmute(load("DirectSoundCreate")?),
+                direct_sound_enumerate: mem::transmute(load("DirectSoundEnumerateW")?),
+                get_device_id: mem::transmute(load("GetDeviceID")?),
+                direct_sound_capture_create: mem::transmute(load("DirectSoundCaptureCreate")?),
+                direct_sound_capture_enumerate: mem::transmute(load("DirectSoundCaptureEnumerateW")?),
+            })
+        }
+    }
+
+    fn default_device(&self) -> Option<ffi::GUID> {
+        self.device_id(&ffi::DSDEVID_DefaultPlayback)
+    }
+
+    fn default_capture_device(&self) -> Option<ffi::GUID> {
+        self.device_id(&ffi::DSDEVID_DefaultCapture)
+    }
+
+    // Resolves one of the `DSDEVID_*` guids to the guid of an actual device
+    fn device_id(&self, alias: &ffi::GUID) -> Option<ffi::GUID> {
+        let mut guid: ffi::GUID = unsafe { mem::zeroed() };
+        let result = (self.get_device_id)(alias, &mut guid);
+        if result == ffi::DS_OK {
+            Some(guid)
+        } else {
+            None
+        }
+    }
+
+    fn enumerate(&self) -> Result<Vec<(ffi::GUID, String)>, AudioError> {
+        self.enumerate_with(self.direct_sound_enumerate, "DirectSoundEnumerateW")
+    }
+
+    fn enumerate_capture(&self) -> Result<Vec<(ffi::GUID, String)>, AudioError> {
+        self.enumerate_with(self.direct_sound_capture_enumerate, "DirectSoundCaptureEnumerateW")
+    }
+
+    fn enumerate_with(
+        &self,
+        enumerate: ffi::DirectSoundEnumerateW,
+        function_name: &str,
+    ) -> Result<Vec<(ffi::GUID, String)>, AudioError>
+    {
+        unsafe extern "system" fn callback(
+            guid: ffi::LPGUID,
+            description: ffi::LPCWSTR,
+            _module: ffi::LPCWSTR,
+            context: ffi::LPVOID,
+        ) -> ffi::BOOL
+        {
+            let devices = &mut *(context as *mut Vec<(ffi::GUID, String)>);
+
+            // The first device has no guid, and refers to whichever device is the default
+            if !guid.is_null() {
+                let mut len = 0;
+                while *description.offset(len) != 0 {
+                    len += 1;
+                }
+                let description = slice::from_raw_parts(description, len as usize);
+                devices.push((*guid, String::from_utf16_lossy(description)));
+            }
+
+            ffi::TRUE
+        }
+
+        let mut devices: Vec<(ffi::GUID, String)> = Vec::new();
+        let result = enumerate(Some(callback), &mut devices as *mut _ as ffi::LPVOID);
+        if result != ffi::DS_OK {
+            return Err(AudioError::BadReturn {
+                function_name: function_name.to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+
+        Ok(devices)
+    }
+}
+
+fn guid_to_string(guid: &ffi::GUID) -> String {
+    let d = guid.Data4;
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        guid.Data1, guid.Data2, guid.Data3,
+        d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7],
+    )
+}
+
+fn guids_equal(a: &ffi::GUID, b: &ffi::GUID) -> bool {
+    a.Data1 == b.Data1 && a.Data2 == b.Data2 && a.Data3 == b.Data3 && a.Data4 == b.Data4
+}
+
+pub(super) fn devices() -> Result<Vec<AudioDevice>, AudioError> {
+    let library = DirectSoundLibrary::load()?;
+    let default = library.default_device();
+    let devices = library.enumerate()?;
+    Ok(to_audio_devices(devices, default))
+}
+
+pub(super) fn capture_devices() -> Result<Vec<AudioDevice>, AudioError> {
+    let library = DirectSoundLibrary::load()?;
+    let default = library.default_capture_device();
+    let devices = library.enumerate_capture()?;
+    Ok(to_audio_devices(devices, default))
+}
+
+fn to_audio_devices(devices: Vec<(ffi::GUID, String)>, default: Option<ffi::GUID>) -> Vec<AudioDevice> {
+    devices.into_iter()
+        .map(|(guid, name)| AudioDevice {
+            id: guid_to_string(&guid),
+            name,
+            default: default.map(|d| guids_equal(&d, &guid)).unwrap_or(false),
+        })
+        .collect()
+}
+
+// Finds the guid of the device with the given id, as returned by `guid_to_string`
+fn find_device(devices: Vec<(ffi::GUID, String)>, id: &str) -> Result<ffi::GUID, AudioError> {
+    let guid = devices.into_iter()
+        .map(|(guid, _)| guid)
+        .find(|guid| guid_to_string(guid) == id);
+
+    match guid {
+        Some(guid) => Ok(guid),
+        None => {
+            let message = format!("No audio device with id {}", id);
+            Err(AudioError::Other { message })
+        },
+    }
 }
 
 const BUFFER_SIZE_IN_FRAMES: usize = 2 * (OUTPUT_SAMPLE_RATE as usize);
@@ -39,35 +302,36 @@ pub(super) struct AudioBackend {
     last_write: Option<(usize, usize)>, // Start and length
     cumulative_play_cursor_jump: usize,
 
+    dsound: &'static mut ffi::IDirectSound,
+    primary_buffer: &'static mut ffi::IDirectSoundBuffer,
     secondary_buffer: &'static mut ffi::IDirectSoundBuffer,
+
+    library: DirectSoundLibrary,
+    // The device which was the default when we opened it, if we are following the default device
+    opened_default: Option<ffi::GUID>,
 }
 
 impl AudioBackend {
-    pub fn initialize(window_handle: usize) -> Result<AudioBackend, AudioError> {
-        // Load library
-        let library_name = b"dsound.dll\0";
-        let dsound_lib = unsafe { ffi::LoadLibraryA(library_name.as_ptr() as *const i8) };
-
-        if dsound_lib.is_null() {
-            let message = "Could not load library \"dsound.dll\"".to_owned();
-            return Err(AudioError::Other { message });
-        }
-
-        // Create DirectSound object
-        let direct_sound_create = {
-            let name = b"DirectSoundCreate\0";
-            let address = unsafe { ffi::GetProcAddress(dsound_lib, name.as_ptr() as *const _) };
-
-            if address.is_null() {
-                let message = "No `DirectSoundCreate` in \"dsound.dll\"".to_owned();
-                return Err(AudioError::Other { message });
-            } else {
-                unsafe { mem::transmute::<_, ffi::DirectSoundCreate>(address) }
-            }
+    /// Opens the device with the given id, as returned by `devices`, or the default device if no
+    /// id is given.
+    pub fn initialize(window_handle: usize, device: Option<&str>) -> Result<AudioBackend, AudioError> {
+        let library = DirectSoundLibrary::load()?;
+
+        let (mut device_guid, opened_default) = match device {
+            Some(id) => (find_device(library.enumerate()?, id)?, None),
+
+            None => {
+                // A null guid also gives the default device, but we need to know which device
+                // it is so we can detect when the default changes
+                let default = library.default_device();
+                let guid = default.unwrap_or(ffi::DSDEVID_DefaultPlayback);
+                (guid, default)
+            },
         };
 
+        // Create DirectSound object
         let mut dsound: ffi::LPDIRECTSOUND = ptr::null_mut();
-        let result = direct_sound_create(ptr::null_mut(), &mut dsound, ptr::null_mut());
+        let result = (library.direct_sound_create)(&mut device_guid, &mut dsound, ptr::null_mut());
         if result != ffi::DS_OK {
             return Err(AudioError::BadReturn {
                 function_name: "DirectSoundCreate".to_owned().to_owned(),
@@ -270,7 +534,11 @@ impl AudioBackend {
             write_chunk_size,
             last_write: None,
             cumulative_play_cursor_jump: 0,
+            dsound,
+            primary_buffer,
             secondary_buffer,
+            library,
+            opened_default,
         })
     }
 
@@ -452,6 +720,14 @@ impl AudioBackend {
         return Ok(true);
     }
 
+    /// Only returns true if we opened the default device, and the default device has changed since.
+    pub fn default_device_changed(&self) -> bool {
+        match (self.opened_default, self.library.default_device()) {
+            (Some(opened), Some(current)) => !guids_equal(&opened, &current),
+            _ => false,
+        }
+    }
+
     /// The time between each consecutive write. If one write occured at t0, the next call to write
     /// must be somewhere between `t0 + interval` and `t0 + 2*interval`. The data must be written by
     /// `t0 + 2*interval`
@@ -463,3 +739,244 @@ impl AudioBackend {
         Time(frames_per_write*Time::NANOSECONDS_PER_SECOND/(OUTPUT_SAMPLE_RATE as u64))
     }
 }
+
+impl Drop for AudioBackend {
+    fn drop(&mut self) {
+        unsafe {
+            self.secondary_buffer.Stop();
+            self.secondary_buffer.Release();
+            self.primary_buffer.Release();
+            self.dsound.Release();
+        }
+    }
+}
+
+// Size of the capture ring buffer
+const CAPTURE_BUFFER_SIZE_IN_MS: usize = 500;
+// How long we sleep while waiting for more audio to be captured
+const CAPTURE_POLL_INTERVAL_MS: u64 = 5;
+
+pub(super) struct CaptureBackend {
+    sample_rate: u32,
+    // In bytes
+    buffer_size: usize,
+    last_read_cursor: usize,
+
+    capture: *mut ffi::IDirectSoundCapture,
+    buffer: *mut ffi::IDirectSoundCaptureBuffer,
+}
+
+impl CaptureBackend {
+    /// Opens the capture device with the given id, as returned by `capture_devices`, or the default
+    /// capture device if no id is given.
+    pub fn initialize(device: Option<&str>, sample_rate: u32, channels: u32) -> Result<CaptureBackend, AudioError> {
+        let library = DirectSoundLibrary::load()?;
+
+        let device_guid = match device {
+            Some(id) => find_device(library.enumerate_capture()?, id)?,
+            None => ffi::DSDEVID_DefaultCapture,
+        };
+
+        let mut capture: ffi::LPDIRECTSOUNDCAPTURE = ptr::null_mut();
+        let result = (library.direct_sound_capture_create)(&device_guid, &mut capture, ptr::null_mut());
+        if result != ffi::DS_OK {
+            return Err(AudioError::BadReturn {
+                function_name: "DirectSoundCaptureCreate".to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+        assert!(!capture.is_null());
+
+        // Direct sound does not convert between sample rates for us, so if the requested rate is
+        // not supported we try the common rates, starting with the closest one
+        let mut rates = CAPTURE_SAMPLE_RATES.iter()
+            .cloned()
+            .filter(|&rate| rate != sample_rate)
+            .collect::<Vec<u32>>();
+        rates.sort_by_key(|&rate| (rate as i64 - sample_rate as i64).abs());
+        rates.insert(0, sample_rate);
+
+        let mut first_error = None;
+        let mut created = None;
+        for &rate in rates.iter() {
+            match create_capture_buffer(capture, rate, channels) {
+                Ok((buffer, buffer_size)) => {
+                    created = Some((buffer, buffer_size, rate));
+                    break;
+                },
+                Err(result) => if first_error.is_none() {
+                    first_error = Some(result);
+                },
+            }
+        }
+
+        let (buffer, buffer_size, sample_rate) = match created {
+            Some(created) => created,
+            None => {
+                unsafe { ((*(*capture).lpVtbl).parent.Release)(capture as *mut _) };
+                return Err(AudioError::BadReturn {
+                    function_name: "DirectSoundCapture->CreateCaptureBuffer".to_owned(),
+                    error_code: first_error.unwrap_or(0) as i64,
+                    line: line!(),
+                    file: file!(),
+                });
+            },
+        };
+
+        let backend = CaptureBackend {
+            sample_rate,
+            buffer_size,
+            last_read_cursor: 0,
+            capture,
+            buffer,
+        };
+
+        let result = unsafe { ((*(*buffer).lpVtbl).Start)(buffer, ffi::DSCBSTART_LOOPING) };
+        if result != ffi::DS_OK {
+            return Err(AudioError::BadReturn {
+                function_name: "DirectSoundCaptureBuffer->Start".to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+
+        Ok(backend)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Waits until some frames have been captured, and appends them to `samples`. Returns without
+    /// reading anything if no frames are captured within a few milliseconds.
+    pub fn read(&mut self, samples: &mut Vec<SampleData>) -> Result<(), AudioError> {
+        let vtbl = unsafe { &*(*self.buffer).lpVtbl };
+
+        let mut read_cursor = 0;
+        let result = unsafe { (vtbl.GetCurrentPosition)(self.buffer, ptr::null_mut(), &mut read_cursor) };
+        if result != ffi::DS_OK {
+            return Err(AudioError::BadReturn {
+                function_name: "DirectSoundCaptureBuffer->GetCurrentPosition".to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+        let read_cursor = read_cursor as usize;
+
+        // Everything up to the read cursor is safe to read
+        let read_len = if read_cursor >= self.last_read_cursor {
+            read_cursor - self.last_read_cursor
+        } else {
+            read_cursor + (self.buffer_size - self.last_read_cursor)
+        };
+
+        if read_len == 0 {
+            thread::sleep(Time::from_ms(CAPTURE_POLL_INTERVAL_MS).into());
+            return Ok(());
+        }
+
+        let mut len1 = 0;
+        let mut ptr1 = ptr::null_mut();
+        let mut len2 = 0;
+        let mut ptr2 = ptr::null_mut();
+        let result = unsafe { (vtbl.Lock)(
+            self.buffer,
+            self.last_read_cursor as u32, read_len as u32,
+            &mut ptr1, &mut len1,
+            &mut ptr2, &mut len2,
+            0,
+        )};
+        if result != ffi::DS_OK {
+            return Err(AudioError::BadReturn {
+                function_name: "DirectSoundCaptureBuffer->Lock".to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+
+        let bytes_per_sample = mem::size_of::<SampleData>();
+        unsafe {
+            samples.extend_from_slice(slice::from_raw_parts(ptr1 as *const SampleData, len1 as usize / bytes_per_sample));
+            if !ptr2.is_null() {
+                samples.extend_from_slice(slice::from_raw_parts(ptr2 as *const SampleData, len2 as usize / bytes_per_sample));
+            }
+        }
+
+        let result = unsafe { (vtbl.Unlock)(self.buffer, ptr1, len1, ptr2, len2) };
+        if result != ffi::DS_OK {
+            return Err(AudioError::BadReturn {
+                function_name: "DirectSoundCaptureBuffer->Unlock".to_owned(),
+                error_code: result as i64,
+                line: line!(),
+                file: file!(),
+            });
+        }
+
+        self.last_read_cursor = read_cursor;
+
+        Ok(())
+    }
+}
+
+// Tried in order of distance to the requested rate if the device does not support it
+const CAPTURE_SAMPLE_RATES: [u32; 9] = [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000];
+
+// Creates a capture buffer with the given format. Returns the buffer and its size in bytes, or
+// the error code if the device does not support the format.
+fn create_capture_buffer(
+    capture: *mut ffi::IDirectSoundCapture,
+    sample_rate: u32,
+    channels: u32,
+) -> Result<(*mut ffi::IDirectSoundCaptureBuffer, usize), ffi::HRESULT> {
+    let bytes_per_sample = mem::size_of::<SampleData>();
+    let bytes_per_frame  = bytes_per_sample * channels as usize;
+    let bytes_per_second = bytes_per_frame * sample_rate as usize;
+    let buffer_size      = bytes_per_second * CAPTURE_BUFFER_SIZE_IN_MS / 1000;
+
+    let mut wave_format = ffi::WAVEFORMATEX {
+        wFormatTag:      ffi::WAVE_FORMAT_PCM,
+        nChannels:       channels as u16,
+        nSamplesPerSec:  sample_rate,
+        nAvgBytesPerSec: bytes_per_second as u32,
+        nBlockAlign:     bytes_per_frame as u16,
+        wBitsPerSample:  8 * bytes_per_sample as u16,
+        cbSize: 0,
+        .. unsafe { mem::zeroed() }
+    };
+
+    let buffer_description = ffi::DSCBUFFERDESC {
+        dwSize: mem::size_of::<ffi::DSCBUFFERDESC>() as u32,
+        dwFlags: 0,
+        dwBufferBytes: buffer_size as u32,
+        dwReserved: 0,
+        lpwfxFormat: &mut wave_format,
+        dwFXCount: 0,
+        lpDSCFXDesc: ptr::null_mut(),
+    };
+
+    let mut buffer: ffi::LPDIRECTSOUNDCAPTUREBUFFER = ptr::null_mut();
+    let result = unsafe {
+        ((*(*capture).lpVtbl).CreateCaptureBuffer)(capture, &buffer_description, &mut buffer, ptr::null_mut())
+    };
+    if result != ffi::DS_OK {
+        return Err(result);
+    }
+    assert!(!buffer.is_null());
+
+    Ok((buffer, buffer_size))
+}
+
+impl Drop for CaptureBackend {
+    fn drop(&mut self) {
+        unsafe {
+            ((*(*self.buffer).lpVtbl).Stop)(self.buffer);
+            ((*(*self.buffer).lpVtbl).parent.Release)(self.buffer as *mut _);
+            ((*(*self.capture).lpVtbl).parent.Release)(self.capture as *mut _);
+        }
+    }
+}
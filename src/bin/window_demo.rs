@@ -2,7 +2,7 @@
 extern crate gondola;
 extern crate cable_math;
 
-use gondola::{Window, WindowCommon, CursorType, Timer, Time, Input, Key, GamepadButton};
+use gondola::{Window, WindowCommon, CursorType, Timer, Time, FramePacer, VsyncMode, Input, Key, GamepadButton};
 use gondola::Color;
 use gondola::draw_group::{self, StateCmd};
 use gondola::graphics;
@@ -16,8 +16,16 @@ fn main() {
     let mut timer = Timer::new();
     let mut input = Input::new();
 
-    let mut window = Window::new("This is hopefully still a window");
-    window.set_vsync(true);
+    let mut window = match Window::new("This is hopefully still a window") {
+        Ok(window) => window,
+        Err(err) => {
+            gondola::show_error_dialog("Could not start", &err.to_string());
+            return;
+        },
+    };
+    let vsync = window.set_vsync_mode(VsyncMode::Adaptive);
+    let mut pacer = FramePacer::new(60.0);
+    pacer.set_vsync(vsync != VsyncMode::Off);
 
     let mut audio = AudioSystem::initialize(&window);
     let hit_buffer = match wav::load("hit.wav") {
@@ -127,7 +135,7 @@ fn main() {
         audio.tick();
         audio.print_potential_error();
 
-        window.swap_buffers();
+        window.swap_buffers_paced(&mut pacer);
         graphics::print_errors();
 
         if window.close_requested() {
@@ -31,6 +31,7 @@ mod primitives;
 mod vertex_buffer;
 mod primitive_buffer;
 mod texture_buffer;
+mod validate;
 
 pub use self::primitives::*;
 pub use self::vertex_buffer::*;
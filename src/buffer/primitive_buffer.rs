@@ -6,6 +6,8 @@ use std::marker::PhantomData;
 use gl;
 use gl::types::*;
 
+use shader::{self, Shader};
+use graphics::{self, ResourceKind, ObjectKind};
 use super::*;
 
 /// A GPU buffer which holds a set of primitives (floats, bytes or integers). These primitives
@@ -19,6 +21,11 @@ pub struct PrimitiveBuffer<T: VertexData> {
 
     primitive_count: usize, // Used space, in units of T
     allocated: usize, // Allocated space, in units of T
+    growth_factor: f32,
+    reallocations: usize,
+
+    debug_label: Option<String>, // Reapplied when the buffer is reallocated
+    generation: usize, // See `graphics::is_current_context`
 }
 
 /// Contains information on how to render a group of primitive buffers. In most cases simply using
@@ -102,6 +109,36 @@ impl VertexArray {
         }
     }
 
+    /// Same as [`add_data_source`], but binds the data source to the vertex attribute with the
+    /// given name in `shader`, instead of using a raw attribute index. The attribute location is
+    /// queried from the shader, so this keeps working when attributes in the shader are
+    /// reordered.
+    ///
+    /// This reports an error through [`shader::set_diagnostics`] and does nothing if `shader` has
+    /// no active attribute with the given name. Note that the vertex array only stores attribute locations, so it should only be
+    /// used with shaders which place the attribute at the same location.
+    ///
+    /// [`add_data_source`]: #method.add_data_source
+    /// [`shader::set_diagnostics`]: ../shader/fn.set_diagnostics.html
+    pub fn add_named_data_source<T>(
+        &mut self,
+        shader:  &Shader,
+        name:    &str,
+        source:  &PrimitiveBuffer<T>,
+        size:    u32,
+        stride:  u32,
+        offset:  u32,
+        divisor: u32,
+    )
+      where T: VertexData
+    {
+        if let Some(index) = shader.attribute_location(name) {
+            self.add_data_source(source, index, size, stride, offset, divisor);
+        } else {
+            shader::report_diagnostic(&format!("Invalid attribute name: {}", name), false);
+        }
+    }
+
     /// Registers the given primitive buffer to be used as a index buffer (also referred to as
     /// element buffer) for this vertex array.  After this call, calls to [`draw_elements`] are 
     /// safe. Note that `T` must have a primitive type ([`VertexData::Primitive`]) which is 
@@ -135,6 +172,7 @@ impl VertexArray {
                 (range.end - range.start) as GLsizei
             );
         }
+        graphics::record_draw_call(range.end - range.start);
     }
 
     pub fn draw_instanced(&self, mode: PrimitiveMode, range: Range<usize>, instances: usize) {
@@ -147,6 +185,7 @@ impl VertexArray {
                 instances as GLsizei
             );
         }
+        graphics::record_draw_call((range.end - range.start) * instances);
     }
 
     /// Draws the given type of primitives with the data in graphics buffers bound to this vertex
@@ -162,10 +201,82 @@ impl VertexArray {
                 gl::BindVertexArray(self.array);
                 gl::DrawElements(mode as GLenum, count as GLsizei, index_type, ptr::null());
             }
+            graphics::record_draw_call(count);
         } else {
             panic!("VertexArray::draw_elements called without a valid index buffer set!");
         }
     }
+
+    /// Draws like [`draw`], while capturing the outputs of the bound shader into the given
+    /// buffers with transform feedback. The buffer at index `i` in `targets` is bound to
+    /// transform feedback binding point `i`. With [`TransformFeedbackMode::Separate`] each
+    /// output of the shader goes to its own buffer, in the order the outputs were given when
+    /// building the shader. With [`TransformFeedbackMode::Interleaved`] a single target should be
+    /// given.
+    ///
+    /// The targets need to have enough capacity for all outputs, as they are not resized. If
+    /// `rasterization` is set to false the fragment shader will not be run and no data will be
+    /// written to the bound framebuffer.
+    ///
+    /// [`draw`]: #method.draw
+    /// [`TransformFeedbackMode::Separate`]: ../shader/enum.TransformFeedbackMode.html#variant.Separate
+    /// [`TransformFeedbackMode::Interleaved`]: ../shader/enum.TransformFeedbackMode.html#variant.Interleaved
+    pub fn transform_feedback(
+        &self,
+        mode: PrimitiveMode,
+        range: Range<usize>,
+        targets: &[&FeedbackTarget],
+        rasterization: bool,
+    ) {
+        unsafe {
+            gl::BindVertexArray(self.array);
+        }
+        capture_feedback(mode, range, targets, rasterization);
+    }
+}
+
+/// A buffer which can capture the output of a shader with transform feedback. This is
+/// implemented for [`PrimitiveBuffer`] and [`VertexBuffer`], and allows capturing into buffers
+/// with different element types at once. See [`VertexArray::transform_feedback`].
+///
+/// [`PrimitiveBuffer`]: struct.PrimitiveBuffer.html
+/// [`VertexBuffer`]: struct.VertexBuffer.html
+/// [`VertexArray::transform_feedback`]: struct.VertexArray.html#method.transform_feedback
+pub trait FeedbackTarget {
+    /// The OpenGL name of the underlying buffer object.
+    fn feedback_buffer(&self) -> GLuint;
+}
+
+impl<T: VertexData> FeedbackTarget for PrimitiveBuffer<T> {
+    fn feedback_buffer(&self) -> GLuint { self.buffer }
+}
+
+// Expects the vertex array to be bound already
+pub(super) fn capture_feedback(
+    mode: PrimitiveMode,
+    range: Range<usize>,
+    targets: &[&FeedbackTarget],
+    rasterization: bool,
+) {
+    assert!(
+        targets.iter().all(|target| target.feedback_buffer() != 0),
+        "Transform feedback target has no allocated storage"
+    );
+
+    unsafe {
+        if !rasterization { gl::Enable(gl::RASTERIZER_DISCARD); }
+
+        for (index, target) in targets.iter().enumerate() {
+            gl::BindBufferBase(gl::TRANSFORM_FEEDBACK_BUFFER, index as GLuint, target.feedback_buffer());
+        }
+
+        gl::BeginTransformFeedback(mode.gl_base_primitive() as GLenum);
+        gl::DrawArrays(mode as GLenum, range.start as GLint, (range.end - range.start) as GLsizei);
+        gl::EndTransformFeedback();
+
+        if !rasterization { gl::Disable(gl::RASTERIZER_DISCARD); }
+    }
+    graphics::record_draw_call(range.end - range.start);
 }
 
 impl<T: VertexData> PrimitiveBuffer<T> {
@@ -178,6 +289,10 @@ impl<T: VertexData> PrimitiveBuffer<T> {
             target, usage,
             allocated: 0,
             primitive_count: 0,
+            growth_factor: 1.0,
+            reallocations: 0,
+            debug_label: None,
+            generation: graphics::context_generation(),
         }
     }
 
@@ -196,6 +311,8 @@ impl<T: VertexData> PrimitiveBuffer<T> {
                 usage as GLenum
             );
         }
+        graphics::note_allocation(ResourceKind::Buffer, bytes);
+        graphics::register_object(ObjectKind::Buffer, buffer);
 
         PrimitiveBuffer {
             phantom: PhantomData,
@@ -205,6 +322,10 @@ impl<T: VertexData> PrimitiveBuffer<T> {
             usage,
             allocated: initial_capacity,
             primitive_count: 0,
+            growth_factor: 1.0,
+            reallocations: 0,
+            debug_label: None,
+            generation: graphics::context_generation(),
         }
     }
 
@@ -227,6 +348,9 @@ impl<T: VertexData> PrimitiveBuffer<T> {
                 BufferUsage::StaticDraw as GLenum
             );
         }
+        graphics::record_upload(bytes);
+        graphics::note_allocation(ResourceKind::Buffer, bytes);
+        graphics::register_object(ObjectKind::Buffer, buffer);
 
         PrimitiveBuffer {
             phantom: PhantomData,
@@ -236,6 +360,10 @@ impl<T: VertexData> PrimitiveBuffer<T> {
             usage: BufferUsage::StaticDraw,
             allocated: data.len(),
             primitive_count: data.len(),
+            growth_factor: 1.0,
+            reallocations: 0,
+            debug_label: None,
+            generation: graphics::context_generation(),
         }
     }
     
@@ -267,7 +395,10 @@ impl<T: VertexData> PrimitiveBuffer<T> {
         let end = index + data.len();
 
         let full_override = start == 0 && end >= self.primitive_count;
-        self.ensure_allocated(end, !full_override);
+        if end > self.allocated {
+            let grown = (self.allocated as f32 * self.growth_factor) as usize;
+            self.reallocate(usize::max(end, grown), !full_override);
+        }
 
         if end > self.primitive_count {
             self.primitive_count = end;
@@ -282,8 +413,41 @@ impl<T: VertexData> PrimitiveBuffer<T> {
                 mem::transmute(&data[0])
             );
         }
+        graphics::record_upload(data.len() * mem::size_of::<T>());
     }
     
+    /// Overwrites part of the data in this buffer, starting at the given index. Unlike `put`,
+    /// this never changes the length of the buffer or reallocates it, so it is suitable for
+    /// streaming data into a buffer which is already in use, e.g. through a [`TextureBuffer`].
+    ///
+    /// # Panics
+    /// If `start + data.len()` is greater than `self.len()`.
+    ///
+    /// [`TextureBuffer`]: struct.TextureBuffer.html
+    pub fn update_range(&mut self, start: usize, data: &[T]) {
+        let end = start + data.len();
+        assert!(
+            end <= self.primitive_count,
+            "Range {}..{} is out of bounds for buffer of length {}",
+            start, end, self.primitive_count
+        );
+
+        if data.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl::BindBuffer(self.target as GLenum, self.buffer);
+            gl::BufferSubData(
+                self.target as GLenum,
+                (start * mem::size_of::<T>()) as GLintptr,
+                (data.len() * mem::size_of::<T>()) as GLsizeiptr,
+                data.as_ptr() as *const _,
+            );
+        }
+        graphics::record_upload(data.len() * mem::size_of::<T>());
+    }
+
     /// Sets the number of vertices that can be stored in this buffer without reallocating memory.
     /// If the buffer already has capacity for the given number of vertices no space will be
     /// allocated.
@@ -291,33 +455,97 @@ impl<T: VertexData> PrimitiveBuffer<T> {
     pub fn ensure_allocated(&mut self, new_size: usize, retain_old_data: bool) {
         // Only reallocate if necessary
         if new_size > self.allocated {
-            let bytes = new_size * mem::size_of::<T>();
+            self.reallocate(new_size, retain_old_data);
+        }
+    }
 
-            let mut new_vbo = 0;
+    /// Ensures that at least `additional` more elements can be stored behind the current contents
+    /// of this buffer without reallocating. Unlike [`put`], this does not apply the growth factor.
+    ///
+    /// [`put`]: #method.put
+    pub fn reserve_exact(&mut self, additional: usize) {
+        let needed = self.primitive_count + additional;
+        self.ensure_allocated(needed, true);
+    }
 
-            unsafe {
-                gl::GenBuffers(1, &mut new_vbo);
-                gl::BindBuffer(BufferTarget::Array as GLenum, new_vbo);
-                gl::BufferData(BufferTarget::Array as GLenum, bytes as GLsizeiptr, ptr::null(), self.usage as GLenum);
+    /// Reallocates the internal buffer so its capacity matches its length, freeing any unused GPU
+    /// memory.
+    pub fn shrink_to_fit(&mut self) {
+        if self.allocated > self.primitive_count {
+            let len = self.primitive_count;
+            self.reallocate(len, true);
+        }
+    }
 
+    /// Sets how much the buffer grows when [`put`] needs more space. See
+    /// [`VertexBuffer::set_growth_factor`].
+    ///
+    /// [`put`]: #method.put
+    /// [`VertexBuffer::set_growth_factor`]: struct.VertexBuffer.html#method.set_growth_factor
+    pub fn set_growth_factor(&mut self, growth_factor: f32) {
+        assert!(growth_factor >= 1.0, "Growth factor must be at least 1.0, got {}", growth_factor);
+        self.growth_factor = growth_factor;
+    }
+
+    pub fn growth_factor(&self) -> f32 {
+        self.growth_factor
+    }
+
+    /// The number of times the internal buffer has been reallocated, either to grow or to shrink
+    /// it.
+    pub fn reallocations(&self) -> usize {
+        self.reallocations
+    }
+
+    fn reallocate(&mut self, new_size: usize, retain_old_data: bool) {
+        let bytes = new_size * mem::size_of::<T>();
+
+        let mut new_vbo = 0;
+
+        unsafe {
+            gl::GenBuffers(1, &mut new_vbo);
+            gl::BindBuffer(BufferTarget::Array as GLenum, new_vbo);
+            gl::BufferData(BufferTarget::Array as GLenum, bytes as GLsizeiptr, ptr::null(), self.usage as GLenum);
+
+            if self.buffer != 0 {
                 // Copy old data
-                if retain_old_data && self.buffer != 0 {
+                if retain_old_data {
+                    let copied = usize::min(self.primitive_count, new_size);
                     gl::BindBuffer(BufferTarget::CopyRead as GLenum, self.buffer);
                     gl::CopyBufferSubData(
                         BufferTarget::CopyRead as GLenum,
                         BufferTarget::Array as GLenum,
                         0, 0,
-                        (self.primitive_count*mem::size_of::<T>()) as GLsizeiptr
+                        (copied*mem::size_of::<T>()) as GLsizeiptr
                     );
-                    gl::DeleteBuffers(1, &mut self.buffer);
                 }
+                gl::DeleteBuffers(1, &mut self.buffer);
+                self.reallocations += 1;
+                graphics::record_draw_stats(|stats| stats.buffer_reallocations += 1);
+                graphics::note_reallocation(ResourceKind::Buffer, self.bytes_allocated(), bytes);
+                graphics::move_object(ObjectKind::Buffer, self.buffer, new_vbo);
+            } else {
+                graphics::note_allocation(ResourceKind::Buffer, bytes);
+                graphics::register_object(ObjectKind::Buffer, new_vbo);
             }
+        }
+
+        self.buffer = new_vbo;
+        self.allocated = new_size;
 
-            self.buffer = new_vbo;
-            self.allocated = new_size
+        if let Some(ref label) = self.debug_label {
+            graphics::set_object_label(ObjectKind::Buffer, self.buffer, label);
         }
     }
 
+    /// Sets a name for this buffer, which is shown by `graphics::live_objects` and, if `KHR_debug`
+    /// is supported, in OpenGL debug messages and graphics debuggers. The name is kept when the
+    /// buffer is reallocated.
+    pub fn set_debug_label(&mut self, name: &str) {
+        graphics::set_object_label(ObjectKind::Buffer, self.buffer, name);
+        self.debug_label = Some(name.to_owned());
+    }
+
     /// Empties this buffer by setting its length to 0.
     pub fn clear(&mut self) {
         self.primitive_count = 0;
@@ -367,8 +595,14 @@ impl<T: VertexData> PrimitiveBuffer<T> {
 
 impl<T: VertexData> Drop for PrimitiveBuffer<T> {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteBuffers(1, &mut self.buffer);
+        if graphics::is_current_context(self.generation) {
+            unsafe {
+                gl::DeleteBuffers(1, &mut self.buffer);
+            }
+            graphics::unregister_object(ObjectKind::Buffer, self.buffer);
+        }
+        if self.buffer != 0 {
+            graphics::note_free(ResourceKind::Buffer, self.bytes_allocated());
         }
     }
 }
@@ -239,6 +239,12 @@ pub trait Vertex: Sized {
     fn gen_transform_feedback_outputs(name_prefix: &str) -> Vec<String>;
     fn gen_transform_feedback_decl(name_prefix: &str) -> String;
     fn set_as_vertex_attrib(&self);
+
+    /// The attribute bindings for the fields of this vertex, together with the names of the
+    /// fields. In debug builds this is used to check that the layout of the vertex matches the
+    /// inputs of the shader it is drawn with. The default implementation returns no bindings,
+    /// which disables the check.
+    fn attrib_bindings() -> Vec<(&'static str, AttribBinding)> { Vec::new() }
 }
 
 /// This trait marks types which can be stored in a GPU buffer.  All fields of a 
@@ -47,7 +47,12 @@ impl<T: VertexData> TextureBuffer<T> {
     /// `access_primitives` specifies the number of primitives that will be accessible per texel in
     /// a shader. This must be between 1 and 4 (both inclusive), and `T::primitives()` must be 
     /// divisible by it. For example, if your vertex data has 10 primitives `access_primitives` can
-    /// be 1 and 2.
+    /// be 1 and 2. Three primitives per texel are only supported for 32 bit types.
+    ///
+    /// Both float and integer data is supported. See [`glsl_sampler_type`] for the type of
+    /// sampler to use in shaders.
+    ///
+    /// [`glsl_sampler_type`]: #method.glsl_sampler_type
     pub fn from_buffer(access_primitives: usize, buffer: PrimitiveBuffer<T>) -> TextureBuffer<T> {
         assert!(access_primitives > 0 && access_primitives <= 4, 
                 "access_primitives ({}) must be equal to the number of primitives in a valid image format (R, RG, RGB or RGBA)",
@@ -57,14 +62,9 @@ impl<T: VertexData> TextureBuffer<T> {
                 T::primitives(), access_primitives);
 
         let mut texture = 0;
-        let format = match (T::Primitive::GL_ENUM, access_primitives) {
-            (gl::FLOAT, 1) => gl::R32F,
-            (gl::FLOAT, 2) => gl::RG32F,
-            (gl::FLOAT, 3) => gl::RGB32F,
-            (gl::FLOAT, 4) => gl::RGBA32F,
-            // I cant be bothered to implement other types as I probably never will use them. This
-            // should be trivial to extend if you get a panic.
-            _ => panic!(
+        let format = match texture_buffer_format(T::Primitive::GL_ENUM, access_primitives) {
+            Some(format) => format,
+            None => panic!(
                 "Invalid vertex data for texture buffer (access_primitives: {}, type: {})",
                 access_primitives, T::Primitive::RUST_NAME
             ),
@@ -84,6 +84,17 @@ impl<T: VertexData> TextureBuffer<T> {
         }
     }
 
+    /// The type of sampler which should be used to access this buffer in shaders. This is
+    /// `samplerBuffer` for float data, `isamplerBuffer` for signed integer data and
+    /// `usamplerBuffer` for unsigned integer data.
+    pub fn glsl_sampler_type() -> &'static str {
+        match T::Primitive::GL_ENUM {
+            gl::FLOAT => "samplerBuffer",
+            gl::INT | gl::SHORT | gl::BYTE => "isamplerBuffer",
+            _ => "usamplerBuffer",
+        }
+    }
+
     /// Binds this buffer to the given texture unit. Note that this binds the texture to the 
     /// `gl::TEXTURE_BUFFER` target.
     pub fn bind_texture(&self, unit: u32) {
@@ -101,6 +112,47 @@ impl<T: VertexData> TextureBuffer<T> {
     }
 }
 
+// Finds the internal format used when accessing buffer data as a texture. Note that the three
+// component formats require gl 4.0 or `ARB_texture_buffer_object_rgb32`, and are only available
+// for 32 bit types. Integer types are not normalized, so they must be accessed through
+// `isamplerBuffer` or `usamplerBuffer`.
+fn texture_buffer_format(primitive: GLenum, access_primitives: usize) -> Option<GLenum> {
+    Some(match (primitive, access_primitives) {
+        (gl::FLOAT, 1) => gl::R32F,
+        (gl::FLOAT, 2) => gl::RG32F,
+        (gl::FLOAT, 3) => gl::RGB32F,
+        (gl::FLOAT, 4) => gl::RGBA32F,
+
+        (gl::INT, 1) => gl::R32I,
+        (gl::INT, 2) => gl::RG32I,
+        (gl::INT, 3) => gl::RGB32I,
+        (gl::INT, 4) => gl::RGBA32I,
+
+        (gl::UNSIGNED_INT, 1) => gl::R32UI,
+        (gl::UNSIGNED_INT, 2) => gl::RG32UI,
+        (gl::UNSIGNED_INT, 3) => gl::RGB32UI,
+        (gl::UNSIGNED_INT, 4) => gl::RGBA32UI,
+
+        (gl::SHORT, 1) => gl::R16I,
+        (gl::SHORT, 2) => gl::RG16I,
+        (gl::SHORT, 4) => gl::RGBA16I,
+
+        (gl::UNSIGNED_SHORT, 1) => gl::R16UI,
+        (gl::UNSIGNED_SHORT, 2) => gl::RG16UI,
+        (gl::UNSIGNED_SHORT, 4) => gl::RGBA16UI,
+
+        (gl::BYTE, 1) => gl::R8I,
+        (gl::BYTE, 2) => gl::RG8I,
+        (gl::BYTE, 4) => gl::RGBA8I,
+
+        (gl::UNSIGNED_BYTE, 1) => gl::R8UI,
+        (gl::UNSIGNED_BYTE, 2) => gl::RG8UI,
+        (gl::UNSIGNED_BYTE, 4) => gl::RGBA8UI,
+
+        _ => return None,
+    })
+}
+
 impl<T: VertexData> Deref for TextureBuffer<T> {
     type Target = PrimitiveBuffer<T>;
     fn deref(&self) -> &PrimitiveBuffer<T> {
@@ -0,0 +1,153 @@
+
+//! Debug-mode validation of vertex layouts against the currently bound shader.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use gl;
+use gl::types::*;
+
+use util;
+use shader;
+use super::*;
+
+thread_local! {
+    // (program, vertex type) pairs we have already validated. Without this we would query and
+    // print the same mismatches on every draw call.
+    static VALIDATED: RefCell<HashSet<(GLuint, &'static str)>> = RefCell::new(HashSet::new());
+}
+
+/// Compares the attributes of `T` with the active attributes of the currently bound shader,
+/// reporting each mismatch through `shader::set_diagnostics`. Each combination of shader and
+/// vertex type is only checked once. This does nothing in release builds, or if `T` does not
+/// provide its attribute bindings (See [`Vertex::attrib_bindings`]).
+///
+/// [`Vertex::attrib_bindings`]: trait.Vertex.html#method.attrib_bindings
+pub(super) fn validate_vertex_layout<T: Vertex>() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let mut program = 0;
+    unsafe { gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program) };
+    let program = program as GLuint;
+    if program == 0 {
+        return;
+    }
+
+    let type_name = ::std::any::type_name::<T>();
+    let first_time = VALIDATED.with(|validated| validated.borrow_mut().insert((program, type_name)));
+    if !first_time {
+        return;
+    }
+
+    let bindings = T::attrib_bindings();
+    if bindings.is_empty() {
+        return;
+    }
+
+    for (name, location, kind) in active_attributes(program) {
+        let field = bindings.iter().find(|&&(_, ref binding)| binding.index as GLint == location);
+
+        if let Some(&(field_name, ref binding)) = field {
+            if let Some(expected) = attrib_kind(binding) {
+                if expected != kind {
+                    shader::report_diagnostic(&format!(
+                        "Vertex layout mismatch: Field `{}` of `{}` is passed as a `{}`, but \
+                        shader attribute `{}` at location {} is a `{}`",
+                        field_name, type_name, glsl_type_name(expected),
+                        name, location, glsl_type_name(kind),
+                    ), false);
+                }
+            }
+        } else {
+            shader::report_diagnostic(&format!(
+                "Vertex layout mismatch: Shader attribute `{}` at location {} has no \
+                corresponding field in `{}`",
+                name, location, type_name,
+            ), false);
+        }
+    }
+}
+
+fn active_attributes(program: GLuint) -> Vec<(String, GLint, GLenum)> {
+    let mut result = Vec::new();
+
+    unsafe {
+        let mut attribute_count = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut attribute_count);
+
+        for index in 0..attribute_count {
+            const MAX_NAME_LENGTH: usize = 512;
+
+            let mut name_length = 0;
+            let mut name_buffer = [0u8; MAX_NAME_LENGTH];
+
+            let mut size = 0;
+            let mut kind = 0;
+
+            gl::GetActiveAttrib(
+                program, index as u32,
+                MAX_NAME_LENGTH as i32,
+                &mut name_length,
+                &mut size,
+                &mut kind,
+                name_buffer.as_mut_ptr() as *mut i8,
+            );
+
+            // Builtins such as `gl_VertexID` are reported as active attributes, but have no location
+            let location = gl::GetAttribLocation(program, name_buffer.as_ptr() as *const i8);
+            if location < 0 {
+                continue;
+            }
+
+            let name = util::ascii_to_string(&name_buffer[.. (name_length as usize)]);
+            result.push((name, location, kind));
+        }
+    }
+
+    result
+}
+
+// The glsl type a shader should use to receive data from the given binding
+fn attrib_kind(binding: &AttribBinding) -> Option<GLenum> {
+    let signed = match binding.primitive_type {
+        gl::INT | gl::SHORT | gl::BYTE => true,
+        _ => false,
+    };
+
+    let kinds = if !binding.integer {
+        [gl::FLOAT, gl::FLOAT_VEC2, gl::FLOAT_VEC3, gl::FLOAT_VEC4]
+    } else if signed {
+        [gl::INT, gl::INT_VEC2, gl::INT_VEC3, gl::INT_VEC4]
+    } else {
+        [gl::UNSIGNED_INT, gl::UNSIGNED_INT_VEC2, gl::UNSIGNED_INT_VEC3, gl::UNSIGNED_INT_VEC4]
+    };
+
+    if binding.primitives >= 1 && binding.primitives <= 4 {
+        Some(kinds[binding.primitives - 1])
+    } else {
+        None
+    }
+}
+
+fn glsl_type_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::FLOAT             => "float",
+        gl::FLOAT_VEC2        => "vec2",
+        gl::FLOAT_VEC3        => "vec3",
+        gl::FLOAT_VEC4        => "vec4",
+        gl::INT               => "int",
+        gl::INT_VEC2          => "ivec2",
+        gl::INT_VEC3          => "ivec3",
+        gl::INT_VEC4          => "ivec4",
+        gl::UNSIGNED_INT      => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::FLOAT_MAT2        => "mat2",
+        gl::FLOAT_MAT3        => "mat3",
+        gl::FLOAT_MAT4        => "mat4",
+        _                     => "unknown type",
+    }
+}
@@ -6,7 +6,11 @@ use std::marker::PhantomData;
 use gl;
 use gl::types::*;
 
+use graphics::{self, ResourceKind, ObjectKind};
+
 use super::*;
+use super::validate::validate_vertex_layout;
+use super::primitive_buffer::capture_feedback;
 
 /// A GPU buffer which holds a list of a custom vertex type. This struct also has utility methods
 /// for rendering the vertices as primitives.
@@ -55,12 +59,17 @@ pub struct VertexBuffer<T: Vertex> {
 
     vertex_count: usize, // Used space, in number of vertices
     allocated: usize, // Allocated space, in number of vertices
+    growth_factor: f32,
+    reallocations: usize,
 
     primitive_mode: PrimitiveMode,
     usage: BufferUsage,
 
     vbo: GLuint,
     vao: GLuint,
+
+    debug_label: Option<String>, // Reapplied when the buffer is reallocated
+    generation: usize, // See `graphics::is_current_context`
 }
 
 /// A GPU buffer which, similarly to [`VertexBuffer`], holds a list of a custom vertex type. Differently
@@ -136,9 +145,13 @@ impl<T: Vertex> VertexBuffer<T> {
             phantom: PhantomData,
             vertex_count: 0,
             allocated: 0,
+            growth_factor: 1.0,
+            reallocations: 0,
 
             primitive_mode, usage,
             vbo, vao,
+            debug_label: None,
+            generation: graphics::context_generation(),
         }
     }
 
@@ -155,6 +168,8 @@ impl<T: Vertex> VertexBuffer<T> {
             gl::BindVertexArray(buffer.vao);
             T::setup_attrib_pointers(0);
         }
+        graphics::note_allocation(ResourceKind::Buffer, bytes);
+        graphics::register_object(ObjectKind::Buffer, buffer.vbo);
 
         buffer.vertex_count = 0;
         buffer.allocated = initial_capacity;
@@ -179,10 +194,13 @@ impl<T: Vertex> VertexBuffer<T> {
                 mem::transmute(&vertices[0]),
                 usage as GLenum
             );
+            graphics::record_upload(bytes);
 
             gl::BindVertexArray(buffer.vao);
             T::setup_attrib_pointers(0);
         }
+        graphics::note_allocation(ResourceKind::Buffer, bytes);
+        graphics::register_object(ObjectKind::Buffer, buffer.vbo);
 
         buffer.vertex_count = vertex_count;
         buffer.allocated    = vertex_count;
@@ -215,7 +233,10 @@ impl<T: Vertex> VertexBuffer<T> {
         let end = index + data.len();
 
         let full_override = start == 0 && end >= self.vertex_count;
-        self.ensure_allocated(end, !full_override);
+        if end > self.allocated {
+            let grown = (self.allocated as f32 * self.growth_factor) as usize;
+            self.reallocate(usize::max(end, grown), !full_override);
+        }
 
         if end > self.vertex_count {
             self.vertex_count = end;
@@ -230,6 +251,7 @@ impl<T: Vertex> VertexBuffer<T> {
                 mem::transmute(&data[0])
             );
         }
+        graphics::record_upload(data.len() * mem::size_of::<T>());
     }
 
     /// Empties this buffer, setting its length to 0. This does nothing to the data
@@ -255,41 +277,110 @@ impl<T: Vertex> VertexBuffer<T> {
     /// If `retain_old_data` is `false` this will zero out all data if it decides to reallocate
     pub fn ensure_allocated(&mut self, new_capacity: usize, retain_old_data: bool) {
         if new_capacity > self.allocated {
-            let mut new_buffer = 0;
-            let bytes = new_capacity * mem::size_of::<T>();
+            self.reallocate(new_capacity, retain_old_data);
+        }
+    }
 
-            unsafe {
-                gl::GenBuffers(1, &mut new_buffer);
-                gl::BindBuffer(BufferTarget::Array as GLenum, new_buffer);
-                gl::BufferData(BufferTarget::Array as GLenum, bytes as GLsizeiptr, ptr::null(), self.usage as GLenum);
+    /// Ensures that at least `additional` more vertices can be stored behind the current contents
+    /// of this buffer without reallocating. Unlike [`put`], this does not apply the growth factor,
+    /// so the buffer is resized to exactly `len() + additional` if it is too small.
+    ///
+    /// [`put`]: #method.put
+    pub fn reserve_exact(&mut self, additional: usize) {
+        let needed = self.vertex_count + additional;
+        self.ensure_allocated(needed, true);
+    }
+
+    /// Reallocates the internal buffer so its capacity matches its length, freeing any unused GPU
+    /// memory.
+    pub fn shrink_to_fit(&mut self) {
+        if self.allocated > self.vertex_count {
+            let len = self.vertex_count;
+            self.reallocate(len, true);
+        }
+    }
+
+    /// Sets how much the buffer grows when [`put`] needs more space. When growing, the new
+    /// capacity is the larger of the needed capacity and the old capacity times this factor. The
+    /// default is `1.0`, which only allocates as much space as is needed. Buffers which grow
+    /// frequently should use a larger factor, e.g. `2.0`, to reduce the number of reallocations.
+    ///
+    /// [`put`]: #method.put
+    pub fn set_growth_factor(&mut self, growth_factor: f32) {
+        assert!(growth_factor >= 1.0, "Growth factor must be at least 1.0, got {}", growth_factor);
+        self.growth_factor = growth_factor;
+    }
+
+    pub fn growth_factor(&self) -> f32 {
+        self.growth_factor
+    }
+
+    /// The number of times the internal buffer has been reallocated, either to grow or to shrink
+    /// it. Each reallocation creates a new GPU buffer and frees the old one.
+    pub fn reallocations(&self) -> usize {
+        self.reallocations
+    }
 
-                gl::BindVertexArray(self.vao);
-                T::setup_attrib_pointers(0);
+    fn reallocate(&mut self, new_capacity: usize, retain_old_data: bool) {
+        let mut new_buffer = 0;
+        let bytes = new_capacity * mem::size_of::<T>();
 
+        unsafe {
+            gl::GenBuffers(1, &mut new_buffer);
+            gl::BindBuffer(BufferTarget::Array as GLenum, new_buffer);
+            gl::BufferData(BufferTarget::Array as GLenum, bytes as GLsizeiptr, ptr::null(), self.usage as GLenum);
+
+            gl::BindVertexArray(self.vao);
+            T::setup_attrib_pointers(0);
+
+            if self.vbo != 0 {
                 // Copy old data
-                if retain_old_data && self.vbo != 0 {
+                if retain_old_data {
+                    let copied = usize::min(self.vertex_count, new_capacity);
                     gl::BindBuffer(BufferTarget::CopyRead as GLenum, self.vbo);
                     gl::CopyBufferSubData(
                         BufferTarget::CopyRead as GLenum,
                         BufferTarget::Array as GLenum,
                         0, 0,
-                        (self.vertex_count * mem::size_of::<T>()) as GLsizeiptr
+                        (copied * mem::size_of::<T>()) as GLsizeiptr
                     );
-                    gl::DeleteBuffers(1, &mut self.vbo);
                 }
+                gl::DeleteBuffers(1, &mut self.vbo);
+                self.reallocations += 1;
+                graphics::record_draw_stats(|stats| stats.buffer_reallocations += 1);
+                graphics::note_reallocation(ResourceKind::Buffer, self.allocated * mem::size_of::<T>(), bytes);
+                graphics::move_object(ObjectKind::Buffer, self.vbo, new_buffer);
+            } else {
+                graphics::note_allocation(ResourceKind::Buffer, bytes);
+                graphics::register_object(ObjectKind::Buffer, new_buffer);
             }
+        }
 
-            self.vbo = new_buffer;
-            self.allocated = new_capacity
+        self.vbo = new_buffer;
+        self.allocated = new_capacity;
+
+        if let Some(ref label) = self.debug_label {
+            graphics::set_object_label(ObjectKind::Buffer, self.vbo, label);
         }
     }
 
+    /// Sets a name for this buffer, which is shown by `graphics::live_objects` and, if `KHR_debug`
+    /// is supported, in OpenGL debug messages and graphics debuggers. The name is kept when the
+    /// buffer is reallocated.
+    pub fn set_debug_label(&mut self, name: &str) {
+        graphics::set_object_label(ObjectKind::Buffer, self.vbo, name);
+        self.debug_label = Some(name.to_owned());
+    }
+
     /// Draws the contents of this vertex buffer with the primitive mode specified at construction.
     pub fn draw(&self) {
+        validate_vertex_layout::<T>();
+
         unsafe {
             gl::BindVertexArray(self.vao);
             gl::DrawArrays(self.primitive_mode as GLenum, 0, self.vertex_count as GLsizei);
         }
+        graphics::record_draw_call(self.vertex_count);
     }
 
     /// Draws a subrange of the contents of this vertex buffer with the primitive mode specified at
@@ -311,10 +402,63 @@ impl<T: Vertex> VertexBuffer<T> {
             of buffer (len = {})", range.start, range.end, self.vertex_count
         );
 
+        validate_vertex_layout::<T>();
+
         unsafe {
             gl::BindVertexArray(self.vao);
             gl::DrawArrays(self.primitive_mode as GLenum, range.start as GLint, (range.end - range.start) as GLsizei);
         }
+        graphics::record_draw_call(range.end - range.start);
+    }
+
+    /// Draws several ranges of vertices from this buffer with a single draw call, using
+    /// `glMultiDrawArrays`. This is equivalent to calling [`draw_range`] for each range, but has
+    /// less overhead when drawing many small ranges, e.g. the visible chunks of a tilemap. Empty
+    /// ranges are skipped.
+    ///
+    /// # Panics
+    /// If any range has its start after its end, or extends beyond the end of the buffer.
+    ///
+    /// [`draw_range`]: #method.draw_range
+    pub fn draw_ranges(&self, ranges: &[Range<usize>]) {
+        let mut firsts = Vec::with_capacity(ranges.len());
+        let mut counts = Vec::with_capacity(ranges.len());
+        let mut vertices = 0;
+
+        for range in ranges.iter() {
+            assert!(
+                range.start <= range.end,
+                "Call to draw_ranges with invalid range {}..{}, start must not lie after end!",
+                range.start, range.end
+            );
+            assert!(
+                range.end <= self.vertex_count,
+                "Call to draw_ranges with invalid range {}..{}, end or range lies beyond end \
+                of buffer (len = {})", range.start, range.end, self.vertex_count
+            );
+
+            if range.start < range.end {
+                firsts.push(range.start as GLint);
+                counts.push((range.end - range.start) as GLsizei);
+                vertices += range.end - range.start;
+            }
+        }
+
+        if firsts.is_empty() {
+            return;
+        }
+
+        validate_vertex_layout::<T>();
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::MultiDrawArrays(
+                self.primitive_mode as GLenum,
+                firsts.as_ptr(), counts.as_ptr(),
+                firsts.len() as GLsizei,
+            );
+        }
+        graphics::record_draw_call(vertices);
     }
 
     /// Draws the contents of this vertex buffer, feeding transform feedback data into the given
@@ -323,6 +467,8 @@ impl<T: Vertex> VertexBuffer<T> {
     pub fn transform_feedback_into<U>(&self, target: &mut VertexBuffer<U>, rasterization: bool) 
       where U: Vertex,
     {
+        validate_vertex_layout::<T>();
+
         unsafe {
             if !rasterization { gl::Enable(gl::RASTERIZER_DISCARD); }
 
@@ -334,9 +480,30 @@ impl<T: Vertex> VertexBuffer<T> {
 
             if !rasterization { gl::Disable(gl::RASTERIZER_DISCARD); }
         }
+        graphics::record_draw_call(self.vertex_count);
+    }
+
+    /// Same as [`transform_feedback_into`], but captures into several buffers at once. This is
+    /// intended for shaders built with [`TransformFeedbackMode::Separate`], where each output is
+    /// written to its own buffer. See [`VertexArray::transform_feedback`] for details.
+    ///
+    /// [`transform_feedback_into`]: #method.transform_feedback_into
+    /// [`TransformFeedbackMode::Separate`]: ../shader/enum.TransformFeedbackMode.html#variant.Separate
+    /// [`VertexArray::transform_feedback`]: struct.VertexArray.html#method.transform_feedback
+    pub fn transform_feedback_into_targets(&self, targets: &[&FeedbackTarget], rasterization: bool) {
+        validate_vertex_layout::<T>();
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+        }
+        capture_feedback(self.primitive_mode, 0..self.vertex_count, targets, rasterization);
     }
 }
 
+impl<T: Vertex> FeedbackTarget for VertexBuffer<T> {
+    fn feedback_buffer(&self) -> GLuint { self.vbo }
+}
+
 impl<T: Vertex, E: VertexData> IndexedVertexBuffer<T, E> 
   where E::Primitive: GlIndex,
 {
@@ -459,10 +626,23 @@ impl<T: Vertex, E: VertexData> IndexedVertexBuffer<T, E>
         self.vertices.ensure_allocated(new_size, retain_old_data);
     }
 
+    /// Reallocates the vertex and index buffers so their capacities match their lengths.
+    pub fn shrink_to_fit(&mut self) {
+        self.vertices.shrink_to_fit();
+        self.indices.shrink_to_fit();
+    }
+
+    /// The number of times the vertex and index buffers have been reallocated.
+    pub fn reallocations(&self) -> usize {
+        self.vertices.reallocations() + self.indices.reallocations()
+    }
+
 
     /// Draws the contents of this vertex buffer with the primitive mode specified
     /// at construction and the index/element buffer.
     pub fn draw(&self) {
+        validate_vertex_layout::<T>();
+
         unsafe {
             gl::BindVertexArray(self.vertices.vao);
             gl::DrawElements(
@@ -472,14 +652,21 @@ impl<T: Vertex, E: VertexData> IndexedVertexBuffer<T, E>
                 ptr::null(),
             );
         }
+        graphics::record_draw_call(self.indices.len() * E::primitives());
     }
 }
 
 impl <T: Vertex> Drop for VertexBuffer<T> {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteBuffers(1, &mut self.vbo);
-            gl::DeleteVertexArrays(1, &mut self.vao);
+        if graphics::is_current_context(self.generation) {
+            unsafe {
+                gl::DeleteBuffers(1, &mut self.vbo);
+                gl::DeleteVertexArrays(1, &mut self.vao);
+            }
+            graphics::unregister_object(ObjectKind::Buffer, self.vbo);
+        }
+        if self.vbo != 0 {
+            graphics::note_free(ResourceKind::Buffer, self.allocated * mem::size_of::<T>());
         }
     }
 }
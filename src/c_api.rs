@@ -0,0 +1,608 @@
+
+//! A C api for creating windows, polling input and drawing with a [`DrawGroup`]. Enabled by the
+//! `ffi` feature. The matching header is `include/gondola.h`.
+//!
+//! Windows, inputs and draw groups are passed to C as opaque pointers, which are created and
+//! destroyed through this api. All functions expect valid pointers, and must be called from the
+//! thread which created the window. Draw group functions ignore null groups, unknown keys and
+//! out of range layers, recording an error for `gondola_last_error` instead. Panics are not
+//! caught, so they abort the program.
+//!
+//! To link to this from C, build a `staticlib` or `cdylib` crate which depends on gondola with
+//! the `ffi` feature enabled. The functions are exported from the final library.
+//!
+//! [`DrawGroup`]: ../draw_group/struct.DrawGroup.html
+
+use std::ptr;
+use std::slice;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+use cable_math::{Vec2, Mat4};
+
+use Color;
+use input::{Input, Key, KeyState, KEY_COUNT};
+use window::{Window, WindowCommon, WindowBuilder};
+use draw_group::{DrawGroup, ColorMode, Join, Cap, LAYER_COUNT};
+
+/// The draw group exposed to C. Fonts and textures are identified by integer keys.
+pub type CDrawGroup = DrawGroup<u32, u32, u32>;
+
+/// Passed by value.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GondolaVec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Passed by value. Components are between `0` and `1`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GondolaColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Settings for `gondola_window_create`. Use `gondola_window_desc_default` to get the defaults.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GondolaWindowDesc {
+    pub title: *const c_char,
+    pub size: GondolaVec2,
+    pub resizable: bool,
+    pub srgb: bool,
+    pub samples: u32,
+    pub decorations: bool,
+}
+
+// Values of `KeyState`, as defined in the header
+pub const GONDOLA_KEY_STATE_UP: u32 = 0;
+pub const GONDOLA_KEY_STATE_PRESSED: u32 = 1;
+pub const GONDOLA_KEY_STATE_PRESSED_REPEAT: u32 = 2;
+pub const GONDOLA_KEY_STATE_DOWN: u32 = 3;
+pub const GONDOLA_KEY_STATE_RELEASED: u32 = 4;
+
+impl From<GondolaVec2> for Vec2<f32> {
+    fn from(v: GondolaVec2) -> Vec2<f32> { Vec2::new(v.x, v.y) }
+}
+
+impl From<Vec2<f32>> for GondolaVec2 {
+    fn from(v: Vec2<f32>) -> GondolaVec2 { GondolaVec2 { x: v.x, y: v.y } }
+}
+
+impl From<GondolaColor> for Color {
+    fn from(c: GondolaColor) -> Color { Color::rgba(c.r, c.g, c.b, c.a) }
+}
+
+fn key_state_to_c(state: KeyState) -> u32 {
+    match state {
+        KeyState::Up            => GONDOLA_KEY_STATE_UP,
+        KeyState::Pressed       => GONDOLA_KEY_STATE_PRESSED,
+        KeyState::PressedRepeat => GONDOLA_KEY_STATE_PRESSED_REPEAT,
+        KeyState::Down          => GONDOLA_KEY_STATE_DOWN,
+        KeyState::Released      => GONDOLA_KEY_STATE_RELEASED,
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error<E: ToString>(error: E) {
+    // Interior nul bytes would truncate the message anyways
+    let message = error.to_string().replace('\0', " ");
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error("Got a null string");
+        return None;
+    }
+
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(err) => {
+            set_last_error(err);
+            None
+        },
+    }
+}
+
+unsafe fn group_mut<'a>(group: *mut CDrawGroup) -> Option<&'a mut CDrawGroup> {
+    if group.is_null() {
+        set_last_error("Got a null draw group");
+        None
+    } else {
+        Some(&mut *group)
+    }
+}
+
+/// The message of the last error on this thread, as a nul terminated utf8 string. The string is
+/// valid until the next function which can fail is called. Empty if no error has occurred.
+#[no_mangle]
+pub extern "C" fn gondola_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+// Windows
+
+#[no_mangle]
+pub extern "C" fn gondola_window_desc_default() -> GondolaWindowDesc {
+    let builder = WindowBuilder::new("");
+
+    GondolaWindowDesc {
+        title: ptr::null(),
+        size: builder.size.into(),
+        resizable: builder.resizable,
+        srgb: builder.srgb,
+        samples: builder.samples,
+        decorations: builder.decorations,
+    }
+}
+
+/// Creates a window and a OpenGL context, and makes the context current. Returns null on failure,
+/// see `gondola_last_error`. The window is hidden until `gondola_window_show` is called.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_create(desc: *const GondolaWindowDesc) -> *mut Window {
+    let desc = &*desc;
+    let title = if desc.title.is_null() {
+        ""
+    } else {
+        match to_str(desc.title) {
+            Some(title) => title,
+            None => return ptr::null_mut(),
+        }
+    };
+
+    let builder = WindowBuilder::new(title)
+        .size(desc.size.into())
+        .resizable(desc.resizable)
+        .srgb(desc.srgb)
+        .samples(desc.samples)
+        .decorations(desc.decorations);
+
+    match Window::from_builder(&builder) {
+        Ok(window) => Box::into_raw(Box::new(window)),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        },
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_destroy(window: *mut Window) {
+    if !window.is_null() {
+        drop(Box::from_raw(window));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_show(window: *mut Window) {
+    (*window).show();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_poll_events(window: *mut Window, input: *mut Input) {
+    (*window).poll_events(&mut *input);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_swap_buffers(window: *mut Window) {
+    (*window).swap_buffers();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_close_requested(window: *const Window) -> bool {
+    (*window).close_requested()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_resized(window: *const Window) -> bool {
+    (*window).resized()
+}
+
+/// The size of the area which can be drawn to, in pixels.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_size(window: *const Window) -> GondolaVec2 {
+    (*window).screen_region().size().into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_set_title(window: *mut Window, title: *const c_char) {
+    if let Some(title) = to_str(title) {
+        (*window).change_title(title);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_window_set_vsync(window: *mut Window, vsync: bool) {
+    (*window).set_vsync(vsync);
+}
+
+// Input
+
+#[no_mangle]
+pub extern "C" fn gondola_input_create() -> *mut Input {
+    Box::into_raw(Box::new(Input::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_destroy(input: *mut Input) {
+    if !input.is_null() {
+        drop(Box::from_raw(input));
+    }
+}
+
+/// The state of the given key, one of the `GONDOLA_KEY_STATE_*` values. `key` is the index of the
+/// key in `Key`, see the header for a list. Invalid keys are always up.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_key(input: *const Input, key: u32) -> u32 {
+    if key as usize >= KEY_COUNT {
+        return GONDOLA_KEY_STATE_UP;
+    }
+
+    // `Key` is `repr(u8)`, with variants numbered from zero
+    let key: Key = ::std::mem::transmute(key as u8);
+    key_state_to_c((*input).key(key))
+}
+
+/// The state of the given mouse button. `0` is left, `1` is right and `2` is middle.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_mouse_key(input: *const Input, index: u32) -> u32 {
+    match (*input).mouse_keys.get(index as usize) {
+        Some(&state) => key_state_to_c(state),
+        None => GONDOLA_KEY_STATE_UP,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_mouse_pos(input: *const Input) -> GondolaVec2 {
+    (*input).mouse_pos.into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_mouse_delta(input: *const Input) -> GondolaVec2 {
+    (*input).mouse_delta.into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_scroll(input: *const Input) -> GondolaVec2 {
+    (*input).scroll.into()
+}
+
+/// Copies the text typed since the last call to `gondola_window_poll_events` into `buffer`, as a
+/// nul terminated utf8 string. Returns the length of the text in bytes, without the nul
+/// terminator. If the buffer is too small the text is not copied, so this can be called with a
+/// null buffer to find the required size.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_input_text(input: *const Input, buffer: *mut c_char, buffer_len: usize) -> usize {
+    let text = (*input).type_buffer.as_bytes();
+
+    if !buffer.is_null() && buffer_len > text.len() {
+        let buffer = slice::from_raw_parts_mut(buffer as *mut u8, buffer_len);
+        buffer[..text.len()].copy_from_slice(text);
+        buffer[text.len()] = 0;
+    }
+
+    text.len()
+}
+
+// Draw groups
+
+/// Creates a new draw group. Requires a current OpenGL context.
+#[no_mangle]
+pub extern "C" fn gondola_draw_group_create() -> *mut CDrawGroup {
+    Box::into_raw(Box::new(DrawGroup::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_destroy(group: *mut CDrawGroup) {
+    if !group.is_null() {
+        drop(Box::from_raw(group));
+    }
+}
+
+/// Loads a `.ttf` font, which can then be used by passing `key` to `gondola_draw_group_text`.
+/// Returns false on failure, see `gondola_last_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_load_font(group: *mut CDrawGroup, key: u32, path: *const c_char) -> bool {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return false,
+    };
+    let path = match to_str(path) {
+        Some(path) => path,
+        None => return false,
+    };
+
+    match group.load_truetype_font(key, path) {
+        Ok(()) => true,
+        Err(err) => {
+            set_last_error(err);
+            false
+        },
+    }
+}
+
+/// Loads an image, which can then be used by passing `key` to `gondola_draw_group_textured_aabb`.
+/// Returns false on failure, see `gondola_last_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_load_texture(group: *mut CDrawGroup, key: u32, path: *const c_char) -> bool {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return false,
+    };
+    let path = match to_str(path) {
+        Some(path) => path,
+        None => return false,
+    };
+
+    match group.load_texture(key, path) {
+        Ok(()) => true,
+        Err(err) => {
+            set_last_error(err);
+            false
+        },
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_reset(group: *mut CDrawGroup) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.reset();
+}
+
+/// Draws everything added since the last reset. `transform` points to a 4x4 matrix with 16
+/// floats in column major order. Nothing is drawn if `transform` is null.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_draw(group: *mut CDrawGroup, transform: *const f32, win_size: GondolaVec2) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    if transform.is_null() {
+        return;
+    }
+
+    let m = slice::from_raw_parts(transform, 16);
+    let transform = Mat4::from_col_nested([
+        [m[0],  m[1],  m[2],  m[3]],
+        [m[4],  m[5],  m[6],  m[7]],
+        [m[8],  m[9],  m[10], m[11]],
+        [m[12], m[13], m[14], m[15]],
+    ]);
+
+    group.draw(transform, win_size.into());
+}
+
+/// Does nothing if `layer` is out of range, see `gondola_last_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_set_layer(group: *mut CDrawGroup, layer: usize) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    if layer >= LAYER_COUNT {
+        set_last_error(format!("Layer {} is out of range, there are {} layers", layer, LAYER_COUNT));
+        return;
+    }
+    group.set_layer(layer);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_line(
+    group: *mut CDrawGroup,
+    a: GondolaVec2, b: GondolaVec2,
+    width: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.line(a.into(), b.into(), width, color.into());
+}
+
+/// Draws connected line segments, with round joins and caps.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_polyline(
+    group: *mut CDrawGroup,
+    points: *const GondolaVec2, count: usize,
+    width: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    // `from_raw_parts` needs a non-null pointer, even for empty slices
+    if count == 0 || points.is_null() {
+        return;
+    }
+
+    let points = slice::from_raw_parts(points, count).iter()
+        .map(|&p| p.into())
+        .collect::<Vec<Vec2<f32>>>();
+    group.polyline(&points, width, Join::Round, Cap::Round, color.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_triangle(
+    group: *mut CDrawGroup,
+    a: GondolaVec2, b: GondolaVec2, c: GondolaVec2,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.triangle([a.into(), b.into(), c.into()], color.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_aabb(
+    group: *mut CDrawGroup,
+    min: GondolaVec2, max: GondolaVec2,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.aabb(min.into(), max.into(), color.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_rounded_aabb(
+    group: *mut CDrawGroup,
+    min: GondolaVec2, max: GondolaVec2,
+    corner_radius: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.rounded_aabb(min.into(), max.into(), corner_radius, color.into());
+}
+
+/// Does nothing if no texture was loaded with `key`, see `gondola_last_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_textured_aabb(
+    group: *mut CDrawGroup,
+    texture: u32,
+    min: GondolaVec2, max: GondolaVec2,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    if group.get_texture(texture).is_none() {
+        set_last_error(format!("No texture was loaded with key {}", texture));
+        return;
+    }
+    group.textured_aabb(texture, min.into(), max.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_circle(
+    group: *mut CDrawGroup,
+    center: GondolaVec2, radius: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.circle(center.into(), radius, color.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_ellipse(
+    group: *mut CDrawGroup,
+    center: GondolaVec2, radii: GondolaVec2,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.ellipse(center.into(), radii.into(), color.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_ring(
+    group: *mut CDrawGroup,
+    center: GondolaVec2, inner_radius: f32, outer_radius: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.ring(center.into(), inner_radius, outer_radius, color.into());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_pie(
+    group: *mut CDrawGroup,
+    center: GondolaVec2, radius: f32,
+    start_angle: f32, end_angle: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    group.pie(center.into(), radius, start_angle, end_angle, color.into());
+}
+
+/// Draws utf8 text with a font loaded by `gondola_draw_group_load_font`, positioned as by
+/// `DrawGroup::truetype_text`. Text is wrapped if `wrap_width` is larger than zero. Does nothing
+/// if no font was loaded with `font`, see `gondola_last_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gondola_draw_group_text(
+    group: *mut CDrawGroup,
+    text: *const c_char,
+    font: u32, size: f32,
+    pos: GondolaVec2,
+    wrap_width: f32,
+    color: GondolaColor,
+) {
+    let group = match group_mut(group) {
+        Some(group) => group,
+        None => return,
+    };
+    if group.get_truetype_font(font).is_none() {
+        set_last_error(format!("No font was loaded with key {}", font));
+        return;
+    }
+    if let Some(text) = to_str(text) {
+        let wrap_width = if wrap_width > 0.0 { Some(wrap_width) } else { None };
+        group.truetype_text(text, font, size, pos.into(), wrap_width, color.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_keys() {
+        // The key enum in the header has to list the keys in the same order as `Key`
+        let header = include_str!("../include/gondola.h");
+        let keys = header.split("enum {").nth(2).unwrap().split("};").next().unwrap();
+        let names = keys.split(',').map(str::trim).filter(|k| !k.is_empty()).collect::<Vec<_>>();
+
+        assert_eq!(names.len(), KEY_COUNT);
+        assert_eq!(names[Key::Space as usize], "GONDOLA_KEY_SPACE");
+        assert_eq!(names[Key::Up as usize], "GONDOLA_KEY_UP");
+        assert_eq!(names[Key::F12 as usize], "GONDOLA_KEY_F12");
+    }
+
+    #[test]
+    fn invalid_draw_group_arguments() {
+        let last_error = || unsafe { CStr::from_ptr(gondola_last_error()).to_str().unwrap().to_owned() };
+
+        unsafe { gondola_draw_group_set_layer(ptr::null_mut(), 0) };
+        assert_eq!(last_error(), "Got a null draw group");
+
+        let mut group = CDrawGroup::new_software(ColorMode::Straight);
+        unsafe { gondola_draw_group_set_layer(&mut group, LAYER_COUNT) };
+        assert!(last_error().contains("out of range"));
+
+        let zero = GondolaVec2 { x: 0.0, y: 0.0 };
+        unsafe { gondola_draw_group_textured_aabb(&mut group, 7, zero, zero) };
+        assert!(last_error().contains("No texture"));
+    }
+}
@@ -1,5 +1,15 @@
 
 //! A color type, with utility methods for modifying colors and parsing colors from hex integers and strings. 
+//!
+//! Colors are assumed to be in the sRGB color space, which is what hex colors from image editors
+//! and the web are specified in. Operations which should happen in linear space, such as
+//! [`Color::darken`] and [`Color::lighten`], convert to linear space internally. Use
+//! [`Color::to_linear`] when passing colors to shaders which do lighting or blending in linear
+//! space.
+//!
+//! [`Color::darken`]: struct.Color.html#method.darken
+//! [`Color::lighten`]: struct.Color.html#method.lighten
+//! [`Color::to_linear`]: struct.Color.html#method.to_linear
 
 use std::str::FromStr;
 
@@ -19,6 +29,26 @@ pub struct Color {
 }
 
 impl Color {
+    pub const TRANSPARENT: Color = Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 };
+    pub const BLACK: Color       = Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 };
+    pub const WHITE: Color       = Color { r: 1.0, g: 1.0, b: 1.0, a: 1.0 };
+    /// `#808080`
+    pub const GRAY: Color        = Color { r: 128.0/255.0, g: 128.0/255.0, b: 128.0/255.0, a: 1.0 };
+    /// `#c0c0c0`
+    pub const LIGHT_GRAY: Color  = Color { r: 192.0/255.0, g: 192.0/255.0, b: 192.0/255.0, a: 1.0 };
+    /// `#404040`
+    pub const DARK_GRAY: Color   = Color { r: 64.0/255.0, g: 64.0/255.0, b: 64.0/255.0, a: 1.0 };
+    pub const RED: Color         = Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
+    pub const GREEN: Color       = Color { r: 0.0, g: 1.0, b: 0.0, a: 1.0 };
+    pub const BLUE: Color        = Color { r: 0.0, g: 0.0, b: 1.0, a: 1.0 };
+    pub const YELLOW: Color      = Color { r: 1.0, g: 1.0, b: 0.0, a: 1.0 };
+    pub const CYAN: Color        = Color { r: 0.0, g: 1.0, b: 1.0, a: 1.0 };
+    pub const MAGENTA: Color     = Color { r: 1.0, g: 0.0, b: 1.0, a: 1.0 };
+    /// `#ffa500`
+    pub const ORANGE: Color      = Color { r: 1.0, g: 165.0/255.0, b: 0.0, a: 1.0 };
+    /// `#800080`
+    pub const PURPLE: Color      = Color { r: 128.0/255.0, g: 0.0, b: 128.0/255.0, a: 1.0 };
+
     /// Creates a new, completly opaque (alpha = 1), color.
     ///
     /// All parameters are clamped so that they are between 0 and 1, both inclusive.
@@ -159,6 +189,68 @@ impl Color {
         }
     }
 
+    /// Creates a new color based on this color, with the alpha component replaced by the given
+    /// value. `alpha` is clamped to be between 0 and 1, both inclusive.
+    pub fn with_alpha(self, alpha: f32) -> Color {
+        Color { a: clamp(alpha, 0.0, 1.0), .. self }
+    }
+
+    /// Darkens this color by moving it towards black. An `amount` of 0 gives the same color, and
+    /// an amount of 1 gives black. The interpolation happens in linear space, so that the
+    /// perceived change is even. Alpha is not modified.
+    pub fn darken(self, amount: f32) -> Color {
+        let amount = clamp(amount, 0.0, 1.0);
+        self.to_linear().lerp(Color::BLACK.with_alpha(self.a), amount).to_srgb()
+    }
+
+    /// Lightens this color by moving it towards white. An `amount` of 0 gives the same color, and
+    /// an amount of 1 gives white. The interpolation happens in linear space. Alpha is not
+    /// modified.
+    pub fn lighten(self, amount: f32) -> Color {
+        let amount = clamp(amount, 0.0, 1.0);
+        self.to_linear().lerp(Color::WHITE.with_alpha(self.a), amount).to_srgb()
+    }
+
+    /// Converts this color from sRGB to linear space. Alpha is not modified, as it is always
+    /// linear.
+    pub fn to_linear(self) -> Color {
+        Color {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+            a: self.a,
+        }
+    }
+
+    /// Converts this color from linear space to sRGB. This is the inverse of [`to_linear`].
+    ///
+    /// [`to_linear`]: struct.Color.html#method.to_linear
+    pub fn to_srgb(self) -> Color {
+        Color {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a,
+        }
+    }
+
+    /// The relative luminance of this color, as defined by WCAG. 0 is black and 1 is white.
+    /// Alpha is ignored.
+    pub fn luminance(self) -> f32 {
+        let linear = self.to_linear();
+        0.2126*linear.r + 0.7152*linear.g + 0.0722*linear.b
+    }
+
+    /// The WCAG contrast ratio between this color and the given other color, ranging from 1 (no
+    /// contrast) to 21 (black on white). WCAG recommends a ratio of at least 4.5 for normal text.
+    /// Alpha is ignored.
+    pub fn contrast_ratio(self, other: Color) -> f32 {
+        let a = self.luminance();
+        let b = other.luminance();
+        let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+        (lighter + 0.05) / (darker + 0.05)
+    }
+
     /// Linearly interpolates between this color and the given other color. `t` should be between
     /// 0 and 1. Values outside of this range will lead to extrapolation.
     pub fn lerp(self, other: Color, t: f32) -> Color {
@@ -171,6 +263,26 @@ impl Color {
     }
 }
 
+/// Converts a single sRGB color component to linear space.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a single linear color component to sRGB. This is the inverse of [`srgb_to_linear`].
+///
+/// [`srgb_to_linear`]: fn.srgb_to_linear.html
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055*value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 // Does not properly handle NaN, which should not really matter
 fn clamp(value: f32, min: f32, max: f32) -> f32 {
     if value < min {
@@ -189,6 +301,7 @@ impl VertexData for Color {
 
 impl UniformValue for Color {
     const KIND: UniformKind = UniformKind::VEC4_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(color: &Color, location: GLint) {
         gl::Uniform4f(location, color.r, color.g, color.b, color.a);
@@ -225,9 +338,15 @@ mod serialize {
     use serde::{Serialize, Deserialize, Serializer, Deserializer};
     use serde::de::{Visitor, Error};
 
+    // Serialized as "#rrggbb", or as "#rrggbbaa" if the color is not completly opaque
     impl Serialize for Color {
         fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-            s.serialize_str(&self.to_hex())
+            if self.a >= 1.0 {
+                s.serialize_str(&self.to_hex())
+            } else {
+                let alpha = (clamp(self.a, 0.0, 1.0) * 255.0).round() as u8;
+                s.serialize_str(&format!("{}{:02x}", self.to_hex(), alpha))
+            }
         }
     }
 
@@ -242,13 +361,19 @@ mod serialize {
         type Value = Color;
 
         fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            f.write_str("A string representing a valid hex color")
+            f.write_str("A string representing a valid hex color, optionally with alpha")
         }
 
         fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
-            match Color::hex_str(v) {
-                Some(color) => Ok(color),
-                None =>        Err(E::custom(format!("\"{}\" is not a valid color string", v))),
+            let error = || E::custom(format!("\"{}\" is not a valid color string", v));
+
+            let digits = if v.starts_with('#') { &v[1..] } else { v };
+            if digits.len() == 8 {
+                let color = Color::hex_str(&digits[..6]).ok_or_else(error)?;
+                let alpha = u8::from_str_radix(&digits[6..], 16).map_err(|_| error())?;
+                Ok(Color { a: alpha as f32 / 255.0, .. color })
+            } else {
+                Color::hex_str(v).ok_or_else(error)
             }
         }
     }
@@ -260,11 +385,22 @@ mod tests {
 
     #[test]
     fn test_to_hex() {
-        assert_eq!("#ffa3b1", Color::hex("#ffa3b1").unwrap().to_hex());
-        assert_eq!("#a300f1", Color::hex("#a300f1").unwrap().to_hex());
-        assert_eq!("#000000", Color::hex("#000000").unwrap().to_hex());
-        assert_eq!("#000001", Color::hex("#000001").unwrap().to_hex());
-        assert_eq!("#100000", Color::hex("#100000").unwrap().to_hex());
+        assert_eq!("#ffa3b1", Color::hex_str("#ffa3b1").unwrap().to_hex());
+        assert_eq!("#a300f1", Color::hex_str("#a300f1").unwrap().to_hex());
+        assert_eq!("#000000", Color::hex_str("#000000").unwrap().to_hex());
+        assert_eq!("#000001", Color::hex_str("#000001").unwrap().to_hex());
+        assert_eq!("#100000", Color::hex_str("#100000").unwrap().to_hex());
+    }
+
+    #[test]
+    fn linear_and_contrast() {
+        assert_eq!(Color::hex_int(0x808080), Color::GRAY);
+        assert_eq!(Color::hex_int(0xffa500), Color::ORANGE);
+        assert!((Color::ORANGE.to_linear().to_srgb().g - Color::ORANGE.g).abs() < 0.0001);
+        assert!((Color::BLACK.contrast_ratio(Color::WHITE) - 21.0).abs() < 0.001);
+        assert_eq!(Color::RED.darken(1.0), Color::BLACK);
+        assert!((Color::RED.lighten(0.0).r - 1.0).abs() < 0.0001);
+        assert!(Color::RED.lighten(0.5).g > 0.5);
     }
 }
 
@@ -0,0 +1,228 @@
+
+//! Immediate mode drawing of world-space debug geometry, see [`DebugDraw3d`].
+//!
+//! [`DebugDraw3d`]: struct.DebugDraw3d.html
+
+use std::f32;
+
+use cable_math::{Vec3, Mat4};
+
+use Color;
+use graphics::{self, DepthFunction};
+use shader::{self, Shader};
+use buffer::{AttribBinding, Vertex, PrimitiveMode, BufferUsage, VertexBuffer};
+
+/// The number of line segments used to approximate circles in spheres.
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// Batches world-space lines, wireframe boxes, spheres and axes for debugging 3d scenes. Add
+/// primitives at any point during a frame, and then call [`draw`] once to draw and clear them.
+///
+/// Primitives are depth tested against the current depth buffer by default, so they are hidden
+/// behind scene geometry. Use [`set_depth_test`] to draw them on top of everything instead.
+///
+/// [`draw`]: #method.draw
+/// [`set_depth_test`]: #method.set_depth_test
+pub struct DebugDraw3d {
+    depth_test: bool,
+    depth_tested: Vec<DebugVert>,
+    overlay: Vec<DebugVert>,
+
+    shader: Shader,
+    buffer: VertexBuffer<DebugVert>,
+}
+
+impl DebugDraw3d {
+    pub fn new() -> DebugDraw3d {
+        DebugDraw3d {
+            depth_test: true,
+            depth_tested: Vec::with_capacity(1024),
+            overlay: Vec::with_capacity(1024),
+
+            shader: shader::build_builtin(VERT_SRC, "", FRAG_SRC),
+            buffer: VertexBuffer::with_capacity(PrimitiveMode::Lines, BufferUsage::DynamicDraw, 1024),
+        }
+    }
+
+    /// Decides whether primitives added after this call are hidden behind geometry which has
+    /// already been drawn to the depth buffer. Defaults to `true`.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test;
+    }
+
+    /// Draws a single line between the two given points.
+    pub fn line(&mut self, a: Vec3<f32>, b: Vec3<f32>, color: Color) {
+        let target = if self.depth_test { &mut self.depth_tested } else { &mut self.overlay };
+        target.push(DebugVert { pos: a, color });
+        target.push(DebugVert { pos: b, color });
+    }
+
+    /// Draws the edges of the axis aligned box spanning the two given corners.
+    pub fn aabb(&mut self, min: Vec3<f32>, max: Vec3<f32>, color: Color) {
+        let corner = |i: usize| Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+
+        // Each edge connects two corners which differ in exactly one axis
+        for i in 0..8 {
+            for &axis in [1, 2, 4].iter() {
+                if i & axis == 0 {
+                    self.line(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+
+    /// Draws a wireframe sphere, approximated by three circles around the x, y and z axes.
+    pub fn sphere(&mut self, center: Vec3<f32>, radius: f32, color: Color) {
+        self.circle(center, Vec3::X*radius, Vec3::Y*radius, color);
+        self.circle(center, Vec3::Y*radius, Vec3::Z*radius, color);
+        self.circle(center, Vec3::Z*radius, Vec3::X*radius, color);
+    }
+
+    /// Draws a circle around `center`, in the plane spanned by `a` and `b`. `a` and `b` should be
+    /// perpendicular, and their length gives the radius of the circle along each axis.
+    pub fn circle(&mut self, center: Vec3<f32>, a: Vec3<f32>, b: Vec3<f32>, color: Color) {
+        let point = |i: usize| {
+            let angle = (i as f32 / CIRCLE_SEGMENTS as f32) * 2.0 * f32::consts::PI;
+            center + a*angle.cos() + b*angle.sin()
+        };
+
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Draws the x, y and z axes of a coordinate system in red, green and blue respectively.
+    pub fn axes(&mut self, origin: Vec3<f32>, size: f32) {
+        self.line(origin, origin + Vec3::X*size, Color::RED);
+        self.line(origin, origin + Vec3::Y*size, Color::GREEN);
+        self.line(origin, origin + Vec3::Z*size, Color::BLUE);
+    }
+
+    /// Draws the axes of the given transform, which is typically the model matrix of some object.
+    pub fn transform_axes(&mut self, transform: Mat4<f32>, size: f32) {
+        let origin = Vec3::new(transform.a14, transform.a24, transform.a34);
+        let x = Vec3::new(transform.a11, transform.a21, transform.a31);
+        let y = Vec3::new(transform.a12, transform.a22, transform.a32);
+        let z = Vec3::new(transform.a13, transform.a23, transform.a33);
+
+        self.line(origin, origin + x*size, Color::RED);
+        self.line(origin, origin + y*size, Color::GREEN);
+        self.line(origin, origin + z*size, Color::BLUE);
+    }
+
+    /// Draws all primitives added since the last call to this function, and then removes them.
+    /// `view_projection` transforms from world space to clip space. This binds a custom shader and
+    /// leaves depth testing disabled.
+    pub fn draw(&mut self, view_projection: Mat4<f32>) {
+        let tested_count = self.depth_tested.len();
+        let overlay_count = self.overlay.len();
+        if tested_count + overlay_count == 0 {
+            return;
+        }
+
+        self.buffer.clear();
+        self.buffer.put(0, &self.depth_tested);
+        self.buffer.put(tested_count, &self.overlay);
+
+        self.shader.bind();
+        self.shader.set_uniform("view_projection", view_projection);
+
+        if tested_count > 0 {
+            graphics::set_depth_testing(true);
+            graphics::set_depth_function(DepthFunction::LessOrEqual);
+            self.buffer.draw_range(0..tested_count);
+        }
+
+        graphics::set_depth_testing(false);
+        if overlay_count > 0 {
+            self.buffer.draw_range(tested_count..(tested_count + overlay_count));
+        }
+
+        self.depth_tested.clear();
+        self.overlay.clear();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+#[allow(dead_code)] // Fields are only read on the GPU
+struct DebugVert {
+    pos: Vec3<f32>,
+    color: Color,
+}
+
+impl Vertex for DebugVert {
+    fn setup_attrib_pointers(divisor: usize) {
+        for (_, mut binding) in DebugVert::attrib_bindings() {
+            binding.divisor = divisor;
+            binding.enable();
+        }
+    }
+
+    fn attrib_bindings() -> Vec<(&'static str, AttribBinding)> {
+        use std::mem;
+
+        use gl;
+
+        let stride = mem::size_of::<DebugVert>();
+        let divisor = 0;
+
+        let pos = AttribBinding {
+            index: 0,
+            primitives: 3,
+            primitive_type: gl::FLOAT,
+            normalized: false,
+            integer: false,
+            stride, offset: 0, divisor,
+        };
+
+        let color = AttribBinding {
+            index: 1,
+            primitives: 4,
+            primitive_type: gl::FLOAT,
+            normalized: false,
+            integer: false,
+            stride, offset: mem::size_of::<Vec3<f32>>(), divisor,
+        };
+
+        vec![("pos", pos), ("color", color)]
+    }
+
+    // Not used, we manualy declare inputs in the shader
+    fn gen_shader_input_decl(_name_prefix: &str) -> String { String::new() }
+    fn gen_transform_feedback_decl(_name_prefix: &str) -> String { String::new() }
+    fn gen_transform_feedback_outputs(_name_prefix: &str) -> Vec<String> { Vec::new() }
+    fn set_as_vertex_attrib(&self) {}
+}
+
+const VERT_SRC: &'static str = "
+    #version 330 core
+
+    layout(location = 0) in vec3 in_pos;
+    layout(location = 1) in vec4 in_color;
+
+    out vec4 v_color;
+
+    uniform mat4 view_projection;
+
+    void main() {
+        gl_Position = view_projection * vec4(in_pos, 1.0);
+        v_color = in_color;
+    }
+";
+
+const FRAG_SRC: &'static str = "
+    #version 330 core
+
+    in vec4 v_color;
+
+    out vec4 color;
+
+    void main() {
+        color = v_color;
+    }
+";
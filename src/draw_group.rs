@@ -5,17 +5,19 @@ use std::f32;
 use std::io;
 use std::path::Path;
 use std::hash::Hash;
+use std::ops::Range;
 use std::collections::HashMap;
 
-use cable_math::{Vec2, Mat4};
+use cable_math::{Vec2, Vec4, Mat4};
 
 use Color;
-use graphics; 
+use graphics::{self, StencilFunction, StencilOp, BlendSettings, BlendFactor, BlendFunction};
 use Region;
 use shader::{ShaderPrototype, Shader};
 use texture::{Texture, TextureFormat};
-use buffer::{AttribBinding, Vertex, PrimitiveMode, BufferUsage, VertexBuffer};
+use buffer::{AttribBinding, Vertex, VertexData, PrimitiveMode, BufferUsage, VertexBuffer, TextureBuffer};
 use font::{BitmapFont, TruetypeFont};
+use raster::{self, Canvas, RasterVert, StencilMode};
 
 // This could be a const generic in the future, but that is not implemented in rust yet
 pub const LAYER_COUNT: usize = 2;
@@ -30,9 +32,25 @@ pub const LAYER_COUNT: usize = 2;
 /// `TexKey` is some type used to identify truetype_fonts. Depending on how many unique textures you plan to
 /// have it might be more reasonable to use something like a string type here. Internally, a hash
 /// map is used to map from `TexKey`s to actual textures.
+///
+/// A draw group created with [`new_software`] does not use OpenGL at all, and can only be drawn
+/// with [`rasterize`]. This gives the same output on all machines, so it can be used to test
+/// drawing code against reference images without a OpenGL context.
+///
+/// [`new_software`]: #method.new_software
+/// [`rasterize`]: #method.rasterize
 pub struct DrawGroup<TruetypeFontKey, BitmapFontKey, TexKey> {
     current_layer: usize,
+    sort_key: u32,
     layers: [Layer<TruetypeFontKey, BitmapFontKey, TexKey>; LAYER_COUNT],
+    // Overrides the transform passed to `draw` and `rasterize` for single layers
+    layer_transforms: [Option<Mat4<f32>>; LAYER_COUNT],
+
+    // See `set_damage_tracking`. `damage_snapshot` is `None` when the next damage covers the
+    // whole window, and `damage_region` limits drawing to the region returned by `damage`.
+    damage_tracking: bool,
+    damage_snapshot: Option<DamageSnapshot<TruetypeFontKey, BitmapFontKey, TexKey>>,
+    damage_region: Option<Region>,
 
     // This contains all pushed clip regions that have not yet been popped. 
     // This stack is built up while pushing state commands into the draw group.
@@ -41,23 +59,65 @@ pub struct DrawGroup<TruetypeFontKey, BitmapFontKey, TexKey> {
     // as `working_clip_stack` while state commands are played back.
     draw_clip_stack: Vec<Region>,
 
-    shader: Shader,
+    // The number of masks which have been pushed but not yet popped, and whether we are currently
+    // between a `BeginMask` and a `PushMask`. Used to check that mask commands are balanced.
+    working_mask_depth: usize,
+    building_mask: bool,
+    // The vertex ranges of all active masks. Like `draw_clip_stack` this is only used while
+    // drawing, so masks can be removed from the stencil buffer again when they are popped.
+    draw_mask_stack: Vec<(usize, usize)>,
+
+    pixel_snap: PixelSnap,
+
     truetype_fonts: HashMap<TruetypeFontKey, TruetypeFont>,
     bitmap_fonts: HashMap<BitmapFontKey, BitmapFont>,
     textures: HashMap<TexKey, Texture>,
-    white_texture: Texture,
+    canvas_textures: HashMap<TexKey, Canvas>,
+    // Parameters of shapes drawn with the sdf functions, indexed by the `uv.x` of their vertices
+    shapes: Vec<ShapeInstance>,
 
     changed: bool,
+    color_mode: ColorMode,
+    text_gamma: f32,
+    subpixel_text: bool,
+    max_vertices: Option<usize>,
+    // `None` for software draw groups
+    gl: Option<GlState>,
+}
+
+struct GlState {
+    shader: Shader,
+    white_texture: Texture,
     buffer: VertexBuffer<Vert>,
+    shape_buffer: TextureBuffer<ShapeInstance>,
+    // Whether `buffer` contains the vertices of all layers. This is not the case after drawing in
+    // chunks, see `DrawGroup::set_max_vertices`.
+    uploaded: bool,
 }
 
 #[derive(Debug, Clone)]
 struct Layer<TruetypeFontKey, BitmapFontKey, TexKey> {
     vertices: Vec<Vert>,
     state_changes: Vec<StateChange<TruetypeFontKey, BitmapFontKey, TexKey>>,
+
+    // The sort key of the vertices currently being added to this layer, and whether any
+    // `StateCmd::SortKey` has been added since the last reset. If so, `vertices` and
+    // `state_changes` are reordered into the `sorted_*` fields before drawing.
+    sort_key: u32,
+    sorted: bool,
+    sorted_vertices: Vec<Vert>,
+    sorted_state_changes: Vec<StateChange<TruetypeFontKey, BitmapFontKey, TexKey>>,
 }
 
-#[derive(Debug, Copy, Clone)]
+// The contents of a draw group when `DrawGroup::damage` was last called
+struct DamageSnapshot<TruetypeFontKey, BitmapFontKey, TexKey> {
+    layers: Vec<(Vec<Vert>, Vec<StateChange<TruetypeFontKey, BitmapFontKey, TexKey>>)>,
+    shapes: Vec<ShapeInstance>,
+    transforms: [Mat4<f32>; LAYER_COUNT],
+    win_size: Vec2<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 struct StateChange<TruetypeFontKey, BitmapFontKey, TexKey> {
     at_vertex: usize,
     cmd: StateCmd<TruetypeFontKey, BitmapFontKey, TexKey>,
@@ -81,9 +141,31 @@ pub enum StateCmd<TruetypeFontKey, BitmapFontKey, TexKey> {
     /// `PopClip` commands than `PushClip` commands are added the draw group will panic.
     PopClip,
 
+    /// Starts a new mask. Primitives added after this command, up until the next `PushMask`, are
+    /// not drawn but instead define the shape of the mask.
+    BeginMask,
+    /// Finishes the mask started by `BeginMask` and adds it to the mask stack. Subsequent
+    /// primitives are only drawn where they overlap all masks on the stack. Masks are stored in
+    /// the stencil buffer, so the framebuffer being drawn to needs to have one.
+    PushMask,
+    /// Removes the most recently pushed mask. If more `PopMask` commands than `PushMask` commands
+    /// are added the draw group will panic.
+    PopMask,
+
     /// Clears the current clip region (Or the entire viewport if there is no clip region)
     /// to the given color.
     Clear(Color),
+
+    /// Changes the sort key of subsequent primitives. Within a layer, primitives with a lower
+    /// sort key are drawn before primitives with a higher sort key, regardless of the order in
+    /// which they were added. Primitives with the same key are drawn in the order they were added.
+    /// The initial sort key is `0`. See [`DrawGroup::set_sort_key`].
+    ///
+    /// Clip, mask and clear commands act as barriers: Primitives are never moved past them, so
+    /// primitives inside a clip region stay inside it.
+    ///
+    /// [`DrawGroup::set_sort_key`]: struct.DrawGroup.html#method.set_sort_key
+    SortKey(u32),
 }
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -92,6 +174,342 @@ pub enum SamplerId<TruetypeFontKey, BitmapFontKey, TexKey> {
     Texture(TexKey),
     TruetypeFont(TruetypeFontKey),
     BitmapFont(BitmapFontKey),
+    /// Shapes drawn with [`DrawGroup::sdf_rounded_aabb`] and [`DrawGroup::sdf_circle`], whose
+    /// coverage is computed in the fragment shader.
+    ///
+    /// [`DrawGroup::sdf_rounded_aabb`]: struct.DrawGroup.html#method.sdf_rounded_aabb
+    /// [`DrawGroup::sdf_circle`]: struct.DrawGroup.html#method.sdf_circle
+    Shape,
+}
+
+/// Decides how [`DrawGroup::polyline`] connects consecutive segments.
+///
+/// [`DrawGroup::polyline`]: struct.DrawGroup.html#method.polyline
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Join {
+    /// Extends the outer edges of both segments until they meet in a sharp corner. The value is
+    /// the miter limit, which has the same meaning as `stroke-miterlimit` in svg: If the distance
+    /// from the point to the tip of the corner exceeds the limit times half the line width, a
+    /// bevel join is used instead. `4.0` is a reasonable limit.
+    Miter(f32),
+    /// Cuts the corner off with a straight edge.
+    Bevel,
+    /// Rounds the corner off with a circular arc.
+    Round,
+}
+
+/// Decides how [`DrawGroup::polyline`] ends the first and last segment.
+///
+/// [`DrawGroup::polyline`]: struct.DrawGroup.html#method.polyline
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Cap {
+    /// Ends the line exactly at its end point.
+    Butt,
+    /// Extends the line by half its width past its end point.
+    Square,
+    /// Ends the line in a half circle centered on its end point.
+    Round,
+}
+
+/// Decides how [`DrawGroup`] snaps the positions of text and axis aligned boxes to the pixel
+/// grid. Set with [`DrawGroup::set_pixel_snap`].
+///
+/// [`DrawGroup`]: struct.DrawGroup.html
+/// [`DrawGroup::set_pixel_snap`]: struct.DrawGroup.html#method.set_pixel_snap
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelSnap {
+    /// Positions are used as given. Use this for smoothly moving or scrolling content, at the
+    /// cost of it being slightly blurred at fractional positions.
+    Off,
+    /// Positions are rounded to the nearest pixel edge. This keeps text and filled boxes sharp.
+    Round,
+    /// Positions are moved to the nearest pixel center. This keeps lines with odd widths, e.g. 1px
+    /// borders drawn with `line_aabb`, from being smeared over two pixels.
+    HalfPixel,
+}
+
+impl PixelSnap {
+    /// Snaps the given point according to this mode.
+    pub fn apply(self, pos: Vec2<f32>) -> Vec2<f32> {
+        match self {
+            PixelSnap::Off => pos,
+            PixelSnap::Round => pos.round(),
+            PixelSnap::HalfPixel => Vec2::new(pos.x.floor() + 0.5, pos.y.floor() + 0.5),
+        }
+    }
+}
+
+impl Default for PixelSnap {
+    fn default() -> PixelSnap { PixelSnap::Round }
+}
+
+/// Additional effects for [`DrawGroup::truetype_text_with_effects`]. The default value has no
+/// effects.
+///
+/// [`DrawGroup::truetype_text_with_effects`]: struct.DrawGroup.html#method.truetype_text_with_effects
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TextEffects {
+    /// The thickness and color of an outline around each glyph. Thick outlines look blocky, as
+    /// they are approximated by offset copies of the glyphs. Around `size / 10` works well.
+    pub outline: Option<(f32, Color)>,
+    /// A shadow drawn behind both the text and its outline. The shadow has the shape of the
+    /// glyphs, without the outline.
+    pub shadow: Option<TextShadow>,
+}
+
+/// A drop shadow, see [`TextEffects`].
+///
+/// [`TextEffects`]: struct.TextEffects.html
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextShadow {
+    pub offset: Vec2<f32>,
+    pub color: Color,
+    /// Roughly how far the shadow is smeared out. `0.0` gives a sharp shadow.
+    pub blur: f32,
+}
+
+/// The look of shapes drawn with [`DrawGroup::sdf_rounded_aabb`] and [`DrawGroup::sdf_circle`].
+///
+/// [`DrawGroup::sdf_rounded_aabb`]: struct.DrawGroup.html#method.sdf_rounded_aabb
+/// [`DrawGroup::sdf_circle`]: struct.DrawGroup.html#method.sdf_circle
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShapeStyle {
+    pub color: Color,
+    /// The width and color of a border along the inside of the edge of the shape.
+    pub border: Option<(f32, Color)>,
+    /// A drop shadow drawn behind the shape. The shadow has the same shape, and is blurred by
+    /// `blur` units.
+    pub shadow: Option<TextShadow>,
+}
+
+impl ShapeStyle {
+    /// A solid shape without border or shadow.
+    pub fn new(color: Color) -> ShapeStyle {
+        ShapeStyle { color, border: None, shadow: None }
+    }
+
+    pub fn with_border(self, width: f32, color: Color) -> ShapeStyle {
+        ShapeStyle { border: Some((width, color)), .. self }
+    }
+
+    pub fn with_shadow(self, offset: Vec2<f32>, color: Color, blur: f32) -> ShapeStyle {
+        ShapeStyle { shadow: Some(TextShadow { offset, color, blur }), .. self }
+    }
+}
+
+/// A single glyph of text drawn with [`DrawGroup::truetype_text_with_glyph_fn`]. The callback
+/// can change `offset` and `color` to animate individual glyphs.
+///
+/// [`DrawGroup::truetype_text_with_glyph_fn`]: struct.DrawGroup.html#method.truetype_text_with_glyph_fn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphQuad {
+    /// The index of the glyph in the text. Whitespace does not produce glyphs, so this is not
+    /// the index of the character in the string.
+    pub index: usize,
+    /// The area covered by the glyph, before `offset` is applied.
+    pub rect: Region,
+    pub offset: Vec2<f32>,
+    pub color: Color,
+}
+
+/// Common per glyph animations, for use with [`DrawGroup::truetype_text_with_glyph_fn`].
+///
+/// ```rust,ignore
+/// let wave = GlyphAnimation::Wave { amplitude: 3.0, wavelength: 8.0, speed: 1.5 };
+/// group.truetype_text_with_glyph_fn(
+///     "Hello!", Font::Dialogue, 16.0, pos, None, Color::WHITE, TextEffects::default(),
+///     |glyph| wave.apply(glyph, time.to_secs_f32()),
+/// );
+/// ```
+///
+/// [`DrawGroup::truetype_text_with_glyph_fn`]: struct.DrawGroup.html#method.truetype_text_with_glyph_fn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GlyphAnimation {
+    /// Moves glyphs up and down along a sine wave. `wavelength` is given in glyphs, and `speed`
+    /// in waves per second.
+    Wave { amplitude: f32, wavelength: f32, speed: f32 },
+    /// Moves glyphs randomly by up to `amount` in each direction. The offsets change `rate`
+    /// times per second.
+    Shake { amount: f32, rate: f32 },
+    /// Cycles the hue of glyphs through the rainbow, keeping the alpha of their color.
+    /// `wavelength` is given in glyphs, and `speed` in cycles per second.
+    ColorCycle { wavelength: f32, speed: f32 },
+}
+
+impl GlyphAnimation {
+    /// Applies this animation to the given glyph. `time` is in seconds.
+    pub fn apply(&self, glyph: &mut GlyphQuad, time: f32) {
+        let index = glyph.index as f32;
+
+        match *self {
+            GlyphAnimation::Wave { amplitude, wavelength, speed } => {
+                let phase = index/wavelength - time*speed;
+                glyph.offset.y += amplitude * (phase * 2.0 * f32::consts::PI).sin();
+            },
+
+            GlyphAnimation::Shake { amount, rate } => {
+                let step = (time*rate).floor() as u32;
+                let seed = (glyph.index as u32).wrapping_mul(0x9e37_79b9) ^ step.wrapping_mul(0x85eb_ca6b);
+                let hash = |salt: u32| {
+                    // Integer hash, mapped to -1..1
+                    let mut x = seed ^ salt;
+                    x ^= x >> 16;
+                    x = x.wrapping_mul(0x7feb_352d);
+                    x ^= x >> 15;
+                    (x as f32 / ::std::u32::MAX as f32)*2.0 - 1.0
+                };
+                glyph.offset.x += hash(0x68e3_1da4) * amount;
+                glyph.offset.y += hash(0xb529_7a4d) * amount;
+            },
+
+            GlyphAnimation::ColorCycle { wavelength, speed } => {
+                let hue = (index/wavelength + time*speed).rem_euclid(1.0);
+                glyph.color = Color::hsl(hue, 1.0, 0.5).with_alpha(glyph.color.a);
+            },
+        }
+    }
+}
+
+/// Truetype text which is laid out once, and then drawn repeatedly with
+/// [`DrawGroup::shaped_text`]. This avoids shaping and laying out static labels every frame.
+///
+/// The glyph quads are regenerated on the next draw if the text or size is changed, if
+/// [`invalidate`] is called, or if the font's glyph cache changed so the cached uv coordinates
+/// might be stale. A shaped text should only be drawn with the draw group which created it.
+///
+/// ```rust,ignore
+/// let label = group.shape_truetype_text("Start game", Font::Menu, 24.0, None);
+///
+/// // Each frame
+/// group.shaped_text(&mut label, Vec2::new(20.0, 40.0), Color::WHITE);
+/// ```
+///
+/// [`DrawGroup::shaped_text`]: struct.DrawGroup.html#method.shaped_text
+/// [`invalidate`]: #method.invalidate
+#[derive(Debug, Clone)]
+pub struct ShapedText<TruetypeFontKey> {
+    text: String,
+    font: TruetypeFontKey,
+    size: f32,
+    wrap_width: Option<f32>,
+
+    // Positions relative to the origin of the text, and uv coordinates
+    glyphs: Vec<(Vec2<f32>, Vec2<f32>)>,
+    // `None` if the glyphs need to be regenerated
+    atlas_generation: Option<u64>,
+}
+
+impl<TruetypeFontKey> ShapedText<TruetypeFontKey> {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn font(&self) -> &TruetypeFontKey {
+        &self.font
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Changes the text. Glyphs are only regenerated if the text actually differs.
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text.clear();
+            self.text.push_str(text);
+            self.invalidate();
+        }
+    }
+
+    pub fn set_size(&mut self, size: f32) {
+        if self.size != size {
+            self.size = size;
+            self.invalidate();
+        }
+    }
+
+    pub fn set_wrap_width(&mut self, wrap_width: Option<f32>) {
+        if self.wrap_width != wrap_width {
+            self.wrap_width = wrap_width;
+            self.invalidate();
+        }
+    }
+
+    /// Forces the glyphs to be regenerated the next time this text is drawn.
+    pub fn invalidate(&mut self) {
+        self.atlas_generation = None;
+    }
+}
+
+/// How a [`DrawGroup`] interprets the colors of vertices and textures. The mode is chosen when
+/// the group is created with [`DrawGroup::with_color_mode`].
+///
+/// `DrawGroup::draw` does not change the blend settings, so [`blend_settings`] should be passed
+/// to `graphics::set_blending` before drawing.
+///
+/// [`DrawGroup`]: struct.DrawGroup.html
+/// [`DrawGroup::with_color_mode`]: struct.DrawGroup.html#method.with_color_mode
+/// [`blend_settings`]: #method.blend_settings
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Colors are written to the framebuffer unchanged, with straight alpha. This is the default,
+    /// and is intended for framebuffers without srgb conversion.
+    Straight,
+    /// Vertex colors and textures are in srgb, as given by most color pickers and image editors,
+    /// and are converted to linear before blending. Use this when drawing to a srgb framebuffer,
+    /// e.g. with `WindowBuilder::srgb`, so colors still appear as given. Alpha is straight.
+    Srgb,
+    /// Vertex colors are linear, and their color channels are already multiplied by alpha.
+    /// Textures are still expected to have straight alpha, and are premultiplied when sampled.
+    PremultipliedLinear,
+}
+
+impl Default for ColorMode {
+    fn default() -> ColorMode {
+        ColorMode::Straight
+    }
+}
+
+impl ColorMode {
+    /// The blend settings which composite colors correctly in this mode.
+    pub fn blend_settings(self) -> BlendSettings {
+        match self {
+            ColorMode::Straight | ColorMode::Srgb => BlendSettings::default(),
+            ColorMode::PremultipliedLinear => BlendSettings {
+                src_color: BlendFactor::One,
+                dst_color: BlendFactor::OneMinusSrcAlpha,
+                src_alpha: BlendFactor::One,
+                dst_alpha: BlendFactor::OneMinusSrcAlpha,
+                function:  BlendFunction::Add,
+            },
+        }
+    }
+
+    // Defines `vec4 vertex_color(vec4 color)` and `vec4 texel_color(vec4 texel)`, which convert
+    // vertex colors and texture samples to the color space used for blending, and
+    // `vec3 straight_rgb(vec4 color)`, which removes premultiplied alpha from a color
+    fn glsl_functions(self) -> &'static str {
+        match self {
+            ColorMode::Straight => "
+                vec4 vertex_color(vec4 color) { return color; }
+                vec4 texel_color(vec4 texel) { return texel; }
+                vec3 straight_rgb(vec4 color) { return color.rgb; }
+            ",
+            ColorMode::Srgb => "
+                vec3 srgb_to_linear(vec3 c) {
+                    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), c));
+                }
+                vec4 vertex_color(vec4 color) { return vec4(srgb_to_linear(color.rgb), color.a); }
+                vec4 texel_color(vec4 texel) { return vec4(srgb_to_linear(texel.rgb), texel.a); }
+                vec3 straight_rgb(vec4 color) { return color.rgb; }
+            ",
+            ColorMode::PremultipliedLinear => "
+                vec4 vertex_color(vec4 color) { return color; }
+                vec4 texel_color(vec4 texel) { return vec4(texel.rgb * texel.a, texel.a); }
+                vec3 straight_rgb(vec4 color) { return color.a > 0.0 ? color.rgb / color.a : vec3(0.0); }
+            ",
+        }
+    }
 }
 
 impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFontKey, TexKey>
@@ -100,16 +518,55 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         TexKey: Eq + Hash + Copy,
 {
     pub fn new() -> Self {
-        let shader = build_shader();
+        DrawGroup::with_color_mode(ColorMode::default())
+    }
+
+    /// Creates a draw group which interprets colors according to the given mode. See
+    /// [`ColorMode`].
+    ///
+    /// [`ColorMode`]: enum.ColorMode.html
+    pub fn with_color_mode(color_mode: ColorMode) -> Self {
+        let shader = build_shader(color_mode, false);
 
         let mut white_texture = Texture::new();
         white_texture.load_data(&[0xff, 0xff, 0xff], 1, 1, TextureFormat::RGB_8);
 
+        let buffer = VertexBuffer::with_capacity(PrimitiveMode::Triangles, BufferUsage::DynamicDraw, 2048);
+
+        let mut group = DrawGroup::with_gl_state(Some(GlState {
+            shader, white_texture, buffer,
+            shape_buffer: TextureBuffer::with_capacity(4, BufferUsage::DynamicDraw, 64),
+            uploaded: false,
+        }));
+        group.color_mode = color_mode;
+        group
+    }
+
+    /// Creates a draw group which does not use OpenGL, and can only be drawn with [`rasterize`].
+    /// Colors are interpreted according to `color_mode`, as for [`with_color_mode`]. Textures
+    /// have to be added with [`include_canvas_texture`]. Text is not supported, as fonts are
+    /// cached on the GPU, so text primitives are skipped when rasterizing.
+    ///
+    /// [`rasterize`]: #method.rasterize
+    /// [`with_color_mode`]: #method.with_color_mode
+    /// [`include_canvas_texture`]: #method.include_canvas_texture
+    pub fn new_software(color_mode: ColorMode) -> Self {
+        let mut group = DrawGroup::with_gl_state(None);
+        group.color_mode = color_mode;
+        group
+    }
+
+    fn with_gl_state(gl: Option<GlState>) -> Self {
         // Rust hates me, yada yada. It is not possible to use the [Layer { ... }; 2] syntax though
         let layers = unsafe {
             let layer: Layer<TruetypeFontKey, BitmapFontKey, TexKey> = Layer {
                 vertices: Vec::with_capacity(2048),
                 state_changes: Vec::with_capacity(256),
+
+                sort_key: 0,
+                sorted: false,
+                sorted_vertices: Vec::new(),
+                sorted_state_changes: Vec::new(),
             };
 
             use std::mem;
@@ -126,26 +583,130 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
 
         DrawGroup {
             current_layer: 0,
+            sort_key: 0,
             layers,
+            layer_transforms: [None; LAYER_COUNT],
+
+            damage_tracking: false,
+            damage_snapshot: None,
+            damage_region: None,
 
             working_clip_stack: Vec::with_capacity(10), 
             draw_clip_stack:    Vec::with_capacity(10),
 
-            shader,
-            white_texture, 
+            working_mask_depth: 0,
+            building_mask: false,
+            draw_mask_stack: Vec::with_capacity(10),
+
+            pixel_snap: PixelSnap::default(),
+
             truetype_fonts: HashMap::new(),
             bitmap_fonts: HashMap::new(),
             textures: HashMap::new(),
+            canvas_textures: HashMap::new(),
+            shapes: Vec::new(),
 
             changed: false,
-            buffer: VertexBuffer::with_capacity(PrimitiveMode::Triangles, BufferUsage::DynamicDraw, 2048),
+            color_mode: ColorMode::default(),
+            text_gamma: 1.0,
+            subpixel_text: false,
+            max_vertices: None,
+            gl,
+        }
+    }
+
+    /// The color mode this draw group was created with.
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Adjusts the coverage of truetype glyphs based on the luminance of the text color, to
+    /// compensate for blending in a non-linear color space. Without this, small light text on
+    /// dark backgrounds looks too thin, and dark text on light backgrounds too bold. Coverage is
+    /// raised to the power `gamma` for black text, and `1.0 / gamma` for white text.
+    ///
+    /// `1.0`, the default, disables the adjustment. Values between `1.4` and `2.2` work well
+    /// with `ColorMode::Straight`. With `ColorMode::Srgb`, blending is already linear, so this
+    /// is usually not needed.
+    pub fn set_text_gamma(&mut self, gamma: f32) {
+        self.text_gamma = gamma;
+    }
+
+    /// The gamma set with [`set_text_gamma`].
+    ///
+    /// [`set_text_gamma`]: #method.set_text_gamma
+    pub fn text_gamma(&self) -> f32 {
+        self.text_gamma
+    }
+
+    /// Enables or disables LCD subpixel antialiasing for truetype text, which gives sharper small
+    /// text on screens with horizontal RGB subpixels. This switches all fonts in this group,
+    /// including fonts added later, to subpixel glyphs (see `TruetypeFont::set_subpixel`), and
+    /// clears their glyph caches, so this should be called before adding any text.
+    ///
+    /// Subpixel text is blended with dual-source blending, so `draw` changes the blend settings
+    /// while drawing text, and resets them to `color_mode().blend_settings()` afterwards. Text
+    /// should be drawn onto opaque backgrounds, as there is no meaningful alpha for subpixel
+    /// coverage.
+    pub fn set_subpixel_text(&mut self, enabled: bool) {
+        if enabled == self.subpixel_text {
+            return;
         }
+        self.subpixel_text = enabled;
+
+        for font in self.truetype_fonts.values_mut() {
+            font.set_subpixel(enabled);
+        }
+        if let Some(ref mut gl) = self.gl {
+            gl.shader = build_shader(self.color_mode, enabled);
+        }
+    }
+
+    /// Whether subpixel text is enabled, see [`set_subpixel_text`].
+    ///
+    /// [`set_subpixel_text`]: #method.set_subpixel_text
+    pub fn subpixel_text(&self) -> bool {
+        self.subpixel_text
+    }
+
+    /// Limits how many vertices are uploaded to the GPU at once. If the layers of this group
+    /// together contain more than `max` vertices when drawing, each layer is uploaded and drawn
+    /// in chunks of at most `max` vertices, so the vertex buffer never grows beyond `max`
+    /// vertices. Pathological frames which emit huge amounts of geometry then become slower
+    /// instead of allocating hundreds of megabytes of GPU memory. The chunks are uploaded again
+    /// on every call to [`draw`], even if nothing changed. `max` is rounded down to whole
+    /// triangles. `None`, the default, disables the limit.
+    ///
+    /// # Panics
+    /// If `max` is less than three vertices.
+    ///
+    /// [`draw`]: #method.draw
+    pub fn set_max_vertices(&mut self, max: Option<usize>) {
+        if let Some(max) = max {
+            assert!(max >= 3, "`DrawGroup::set_max_vertices` needs room for at least one triangle");
+        }
+        self.max_vertices = max.map(|max| max - max%3);
+    }
+
+    /// The limit set with [`set_max_vertices`], rounded down to whole triangles.
+    ///
+    /// [`set_max_vertices`]: #method.set_max_vertices
+    pub fn max_vertices(&self) -> Option<usize> {
+        self.max_vertices
+    }
+
+    /// Whether this draw group was created with [`new_software`].
+    ///
+    /// [`new_software`]: #method.new_software
+    pub fn is_software(&self) -> bool {
+        self.gl.is_none()
     }
 
     /// Loads a `.ttf` font from the given path and associates it with the given key.
     pub fn load_truetype_font<P: AsRef<Path>>(&mut self, key: TruetypeFontKey, path: P) -> io::Result<()> {
         let path = path.as_ref();
-        let font = TruetypeFont::from_file(path)?;
+        let mut font = TruetypeFont::from_file(path)?;
+        font.set_subpixel(self.subpixel_text);
 
         self.truetype_fonts.insert(key, font);
 
@@ -163,7 +724,8 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
     }
 
     /// Associates the given font with the given key.
-    pub fn include_truetype_font(&mut self, key: TruetypeFontKey, font: TruetypeFont) { 
+    pub fn include_truetype_font(&mut self, key: TruetypeFontKey, mut font: TruetypeFont) { 
+        font.set_subpixel(self.subpixel_text);
         self.truetype_fonts.insert(key, font);
     }
 
@@ -177,21 +739,54 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         self.textures.insert(key, texture);
     }
 
+    /// Associates the given canvas with the given key. Canvas textures are used by [`rasterize`],
+    /// and ignored by [`draw`].
+    ///
+    /// [`rasterize`]: #method.rasterize
+    /// [`draw`]: #method.draw
+    pub fn include_canvas_texture(&mut self, key: TexKey, texture: Canvas) {
+        self.canvas_textures.insert(key, texture);
+    }
+
     /// Removes all vertices and state commands in this group.
     pub fn reset(&mut self) {
+        self.shapes.clear();
         for layer in 0..LAYER_COUNT {
             self.layers[layer].vertices.clear();
             self.layers[layer].state_changes.clear();
+            self.layers[layer].sort_key = 0;
+            self.layers[layer].sorted = false;
         }
 
         self.changed = true;
+        self.sort_key = 0;
         self.working_clip_stack.clear();
+        self.working_mask_depth = 0;
+        self.building_mask = false;
     }
 
-    /// Draws all data in this group. This binds a custom shader! `win_size` is just used to reset
-    /// the scissor region after rendering.
+    /// Draws all data in this group. This binds a custom shader! `transform` is used for all
+    /// layers which do not have their own transform, see [`set_layer_transform`]. `win_size` is
+    /// just used to reset the scissor region after rendering.
+    ///
+    /// Draw calls, uploads and state changes done here are counted in [`graphics::DrawStats`]
+    /// if recording is enabled.
+    ///
+    /// [`graphics::DrawStats`]: ../graphics/struct.DrawStats.html
+    /// [`set_layer_transform`]: #method.set_layer_transform
+    ///
+    /// # Panics
+    /// If this is a software draw group. Use [`rasterize`] instead.
+    ///
+    /// [`rasterize`]: #method.rasterize
     pub fn draw(&mut self, transform: Mat4<f32>, win_size: Vec2<f32>) {
+        let gl = match self.gl {
+            Some(ref mut gl) => gl,
+            None => panic!("`DrawGroup::draw` called on a software draw group, use `rasterize` instead"),
+        };
+
         self.draw_clip_stack.clear();
+        self.draw_mask_stack.clear();
 
         let total_vert_count: usize = self.layers
             .iter()
@@ -206,75 +801,157 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
             offset += self.layers[layer].vertices.len();
         }
 
+        // Past the limit, layers are uploaded in chunks while drawing instead
+        let chunk = match self.max_vertices {
+            Some(max) if total_vert_count > max => Some(max),
+            _ => None,
+        };
+
         if self.changed {
             self.changed = false;
+            gl.uploaded = false;
 
-            self.buffer.clear();
-            self.buffer.ensure_allocated(total_vert_count, false);
-            for layer in 0..LAYER_COUNT {
-                self.buffer.put(layer_offsets_in_buffer[layer], &self.layers[layer].vertices);
+            gl.shape_buffer.clear();
+            gl.shape_buffer.put(0, &self.shapes);
+
+            for layer in self.layers.iter_mut() {
+                if layer.sorted {
+                    sort_layer(
+                        &layer.vertices, &layer.state_changes,
+                        &mut layer.sorted_vertices, &mut layer.sorted_state_changes,
+                    );
+                }
             }
         }
 
-        self.shader.bind(); 
-        self.shader.set_uniform("transform", transform);
-
-        for layer in 0..LAYER_COUNT {
-            graphics::set_scissor(None, win_size);
-            self.white_texture.bind(0);
-            self.shader.set_uniform("layer", layer as f32 / LAYER_COUNT as f32);
+        match chunk {
+            Some(max) => {
+                gl.uploaded = false;
+                gl.buffer.clear();
+                gl.buffer.ensure_allocated(max, false);
+            },
 
-            let mut draw_cursor = 0;
-            let ref mut buffer = self.buffer;
+            None => if !gl.uploaded {
+                gl.uploaded = true;
+                gl.buffer.clear();
+                gl.buffer.ensure_allocated(total_vert_count, false);
+                for layer in 0..LAYER_COUNT {
+                    gl.buffer.put(layer_offsets_in_buffer[layer], self.layers[layer].draw_data().0);
+                }
+            },
+        }
 
-            // Draws all data between region start and the given position
-            let mut flush = |to: usize| {
-                if draw_cursor == to { return; }
+        gl.shader.bind(); 
+        gl.shader.set_uniform("text_gamma", self.text_gamma);
+        gl.shader.set_uniform("shape_data", 1);
+        if !self.shapes.is_empty() {
+            gl.shape_buffer.bind_texture(1);
+        }
 
-                let offset = layer_offsets_in_buffer[layer];
+        let color_mode = self.color_mode;
+        let subpixel_text = self.subpixel_text;
+        let mut drawing_glyphs = false;
 
-                let start = draw_cursor + offset;
-                let end = to + offset;
-                buffer.draw_range(start..end);
+        // See `set_damage_tracking`
+        let damage = if self.damage_tracking { self.damage_region } else { None };
 
-                draw_cursor = to;
+        for layer in 0..LAYER_COUNT {
+            graphics::set_scissor(damage, win_size);
+            gl.white_texture.bind(0);
+            gl.shader.set_uniform("transform", self.layer_transforms[layer].unwrap_or(transform));
+            gl.shader.set_uniform("layer", layer as f32 / LAYER_COUNT as f32);
+            gl.shader.set_uniform("glyphs", 0);
+            gl.shader.set_uniform("shapes", 0);
+            if drawing_glyphs && subpixel_text {
+                graphics::set_blending(Some(color_mode.blend_settings()));
+            }
+            drawing_glyphs = false;
+
+            let vertices = self.layers[layer].draw_data().0;
+            let mut drawer = match chunk {
+                Some(max) => LayerDrawer {
+                    buffer: &mut gl.buffer, vertices,
+                    resident: 0..0, offset: 0, chunk: max,
+                    cursor: 0, skipping: false,
+                },
+                None => LayerDrawer {
+                    buffer: &mut gl.buffer, vertices,
+                    resident: 0..vertices.len(), offset: layer_offsets_in_buffer[layer], chunk: vertices.len(),
+                    cursor: 0, skipping: false,
+                },
             };
 
+            let mut mask_start = 0;
+
             let mut current_tex = SamplerId::Solid;
 
             // Process state changes. `flush` whenever we actually change state
-            for &StateChange { at_vertex, cmd } in self.layers[layer].state_changes.iter() {
+            for &StateChange { at_vertex, cmd } in self.layers[layer].draw_data().1.iter() {
                 match cmd {
                     StateCmd::TextureChange(new_tex) => {
                         if new_tex != current_tex {
-                            flush(at_vertex);
+                            drawer.flush(at_vertex);
 
                             current_tex = new_tex;
-                            match current_tex {
-                                SamplerId::Solid             => self.white_texture.bind(0),
-                                SamplerId::TruetypeFont(key) => self.truetype_fonts[&key].texture().bind(0),
-                                SamplerId::BitmapFont(key)   => self.bitmap_fonts[&key].texture.bind(0),
-                                SamplerId::Texture(key)      => self.textures[&key].bind(0),
+                            graphics::record_draw_stats(|stats| stats.texture_changes += 1);
+                            // Vertices using a texture or font which was never loaded are skipped
+                            let texture = match current_tex {
+                                SamplerId::Solid             => Some(&gl.white_texture),
+                                SamplerId::TruetypeFont(key) => self.truetype_fonts.get(&key).map(|f| f.texture()),
+                                SamplerId::BitmapFont(key)   => self.bitmap_fonts.get(&key).map(|f| &f.texture),
+                                SamplerId::Texture(key)      => self.textures.get(&key),
+                                SamplerId::Shape             => Some(&gl.white_texture),
+                            };
+                            match texture {
+                                Some(texture) => texture.bind(0),
+                                None => {},
+                            }
+                            drawer.skipping = texture.is_none();
+
+                            let shapes = match current_tex {
+                                SamplerId::Shape => true,
+                                _ => false,
+                            };
+                            gl.shader.set_uniform("shapes", shapes as i32);
+
+                            let glyphs = match current_tex {
+                                SamplerId::TruetypeFont(_) => true,
+                                _ => false,
+                            };
+                            if glyphs != drawing_glyphs {
+                                drawing_glyphs = glyphs;
+                                gl.shader.set_uniform("glyphs", glyphs as i32);
+                                if subpixel_text {
+                                    let blending = if glyphs {
+                                        SUBPIXEL_BLEND_SETTINGS
+                                    } else {
+                                        color_mode.blend_settings()
+                                    };
+                                    graphics::set_blending(Some(blending));
+                                }
                             }
                         }
                     },
 
                     StateCmd::Clear(color) => {
-                        flush(at_vertex);
+                        drawer.flush(at_vertex);
+                        graphics::record_draw_stats(|stats| stats.state_changes += 1);
 
                         // Keep in mind that clearing is affected by scissoring
                         graphics::clear(Some(color), true, false);
                     },
 
                     StateCmd::PushClip(region) => {
-                        flush(at_vertex);
+                        drawer.flush(at_vertex);
+                        graphics::record_draw_stats(|stats| stats.state_changes += 1);
 
                         self.draw_clip_stack.push(region);
-                        graphics::set_scissor(Some(region), win_size);
+                        graphics::set_scissor(Some(clip_to_damage(region, damage)), win_size);
                     },
 
                     StateCmd::PopClip => {
-                        flush(at_vertex);
+                        drawer.flush(at_vertex);
+                        graphics::record_draw_stats(|stats| stats.state_changes += 1);
 
                         // `pop` returns an option, and thus never panics. We check for unbalanced
                         // push/pops when adding state commands, so at this point we can assume that
@@ -282,19 +959,209 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
                         self.draw_clip_stack.pop();
 
                         if let Some(&region) = self.draw_clip_stack.last() {
-                            graphics::set_scissor(Some(region), win_size);
+                            graphics::set_scissor(Some(clip_to_damage(region, damage)), win_size);
                         } else {
-                            graphics::set_scissor(None, win_size);
+                            graphics::set_scissor(damage, win_size);
+                        }
+                    },
+
+                    // Each mask increments the stencil buffer where it overlaps all previous
+                    // masks, so we only draw where the stencil value equals the mask depth.
+                    StateCmd::BeginMask => {
+                        drawer.flush(at_vertex);
+                        graphics::record_draw_stats(|stats| stats.state_changes += 1);
+
+                        let depth = self.draw_mask_stack.len() as u8;
+                        if depth == 0 {
+                            // Keep in mind that clearing is affected by scissoring
+                            graphics::clear(None, false, true);
+                            graphics::set_stencil_testing(true);
+                        }
+
+                        graphics::set_color_writing(false);
+                        graphics::set_stencil_function(StencilFunction::Equal, depth, 0xff);
+                        graphics::set_stencil_operation(StencilOp::Keep, StencilOp::Keep, StencilOp::Increment);
+
+                        mask_start = at_vertex;
+                    },
+
+                    StateCmd::PushMask => {
+                        drawer.flush(at_vertex);
+                        graphics::record_draw_stats(|stats| stats.state_changes += 1);
+
+                        self.draw_mask_stack.push((mask_start, at_vertex));
+                        let depth = self.draw_mask_stack.len() as u8;
+
+                        graphics::set_color_writing(true);
+                        graphics::set_stencil_function(StencilFunction::Equal, depth, 0xff);
+                        graphics::set_stencil_operation(StencilOp::Keep, StencilOp::Keep, StencilOp::Keep);
+                    },
+
+                    StateCmd::PopMask => {
+                        drawer.flush(at_vertex);
+                        graphics::record_draw_stats(|stats| stats.state_changes += 1);
+
+                        // Balanced push/pops are checked when adding state commands
+                        if let Some(range) = self.draw_mask_stack.pop() {
+                            let depth = self.draw_mask_stack.len() as u8;
+
+                            graphics::set_color_writing(false);
+                            graphics::set_stencil_function(StencilFunction::Equal, depth + 1, 0xff);
+                            graphics::set_stencil_operation(StencilOp::Keep, StencilOp::Keep, StencilOp::Decrement);
+                            drawer.draw(range.0..range.1);
+
+                            graphics::set_color_writing(true);
+                            graphics::set_stencil_function(StencilFunction::Equal, depth, 0xff);
+                            graphics::set_stencil_operation(StencilOp::Keep, StencilOp::Keep, StencilOp::Keep);
+
+                            if depth == 0 {
+                                graphics::set_stencil_testing(false);
+                            }
                         }
                     },
+
+                    // Already applied when reordering the layer
+                    StateCmd::SortKey(_) => {},
                 }
             }
 
-            flush(self.layers[layer].vertices.len()); 
+            drawer.flush(vertices.len());
+        }
+
+        if drawing_glyphs && subpixel_text {
+            graphics::set_blending(Some(color_mode.blend_settings()));
         }
 
         Texture::unbind(0);
         graphics::set_scissor(None, win_size);
+        graphics::set_color_writing(true);
+        graphics::set_stencil_testing(false);
+    }
+
+    /// Draws all data in this group into the given canvas on the cpu, without using OpenGL.
+    /// `transform` maps to normalized device coordinates, like for [`draw`], and the canvas
+    /// covers the whole viewport. Clip regions are in pixels, with `(0, 0)` at the top left.
+    ///
+    /// The output only depends on the input, so it is identical on all machines. Textures are
+    /// sampled without filtering, and text and sdf shapes are skipped. This works both for software draw groups
+    /// and for draw groups created with `new`, as long as textures were added with
+    /// [`include_canvas_texture`]. With `ColorMode::Srgb` the canvas is treated like a srgb
+    /// framebuffer, so blending happens in linear space and the results are stored as srgb.
+    ///
+    /// ```rust,ignore
+    /// let mut group = DrawGroup::<(), (), ()>::new_software(ColorMode::Straight);
+    /// group.aabb(Vec2::new(2.0, 2.0), Vec2::new(6.0, 6.0), Color::rgb(1.0, 0.0, 0.0));
+    ///
+    /// let mut canvas = Canvas::new(Vec2::new(8, 8));
+    /// group.rasterize(Mat4::ortho(0.0, 8.0, 0.0, 8.0, -1.0, 1.0), &mut canvas);
+    /// assert_eq!(canvas.pixel(3, 3), [255, 0, 0, 255]);
+    /// ```
+    ///
+    /// # Panics
+    /// If a texture which was not added with `include_canvas_texture` is used.
+    ///
+    /// [`draw`]: #method.draw
+    /// [`include_canvas_texture`]: #method.include_canvas_texture
+    pub fn rasterize(&mut self, transform: Mat4<f32>, target: &mut Canvas) {
+        let size = target.size().as_f32();
+
+        for layer in self.layers.iter_mut() {
+            if layer.sorted {
+                sort_layer(
+                    &layer.vertices, &layer.state_changes,
+                    &mut layer.sorted_vertices, &mut layer.sorted_state_changes,
+                );
+            }
+        }
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            let (vertices, state_changes) = layer.draw_data();
+
+            // Same as the vertex shader and viewport transform, but with y pointing down
+            let transform = self.layer_transforms[index].unwrap_or(transform);
+            let to_pixels = |vert: &Vert| {
+                let clip = transform * Vec4::new(vert.pos.x, vert.pos.y, 0.0, 1.0);
+                let pos = Vec2::new(
+                    (clip.x/clip.w + 1.0) * 0.5 * size.x,
+                    (1.0 - clip.y/clip.w) * 0.5 * size.y,
+                );
+                RasterVert { pos, uv: vert.uv, color: vert.color }
+            };
+
+            let mut clip_stack: Vec<Region> = Vec::new();
+            let mut mask_stack: Vec<(usize, usize)> = Vec::new();
+            let mut mask_start = 0;
+            let mut stencil = StencilMode::Off;
+            let mut texture = SamplerId::Solid;
+            let mut cursor = 0;
+
+            // Draws all vertices from `cursor` up to the given vertex with the current state
+            let flush = |
+                to: usize,
+                texture: SamplerId<_, _, _>, clip: Option<Region>, stencil: StencilMode,
+                target: &mut Canvas, cursor: &mut usize,
+            | {
+                let canvas = match texture {
+                    SamplerId::Solid => None,
+                    SamplerId::Texture(key) => match self.canvas_textures.get(&key) {
+                        Some(canvas) => Some(canvas),
+                        None => panic!("Textures used with `DrawGroup::rasterize` must be added with `include_canvas_texture`"),
+                    },
+                    SamplerId::TruetypeFont(_) | SamplerId::BitmapFont(_) | SamplerId::Shape => {
+                        *cursor = to;
+                        return;
+                    },
+                };
+
+                for triangle in vertices[*cursor..to].chunks(3) {
+                    if triangle.len() == 3 {
+                        let verts = [to_pixels(&triangle[0]), to_pixels(&triangle[1]), to_pixels(&triangle[2])];
+                        raster::triangle(target, verts, canvas, clip, stencil, self.color_mode);
+                    }
+                }
+                *cursor = to;
+            };
+
+            for &StateChange { at_vertex, cmd } in state_changes.iter() {
+                flush(at_vertex, texture, clip_stack.last().cloned(), stencil, target, &mut cursor);
+
+                match cmd {
+                    StateCmd::TextureChange(new_texture) => texture = new_texture,
+                    StateCmd::Clear(color) => target.clear(color, clip_stack.last().cloned()),
+                    StateCmd::PushClip(region) => clip_stack.push(region),
+                    StateCmd::PopClip => { clip_stack.pop(); },
+
+                    StateCmd::BeginMask => {
+                        let depth = mask_stack.len() as u8;
+                        if depth == 0 {
+                            target.clear_stencil(clip_stack.last().cloned());
+                        }
+                        stencil = StencilMode::Increment(depth);
+                        mask_start = at_vertex;
+                    },
+                    StateCmd::PushMask => {
+                        mask_stack.push((mask_start, at_vertex));
+                        stencil = StencilMode::Test(mask_stack.len() as u8);
+                    },
+                    StateCmd::PopMask => {
+                        if let Some((start, end)) = mask_stack.pop() {
+                            let depth = mask_stack.len() as u8;
+
+                            let mut redraw_cursor = start;
+                            // Only the stencil buffer is touched, so the texture does not matter
+                            let decrement = StencilMode::Decrement(depth + 1);
+                            flush(end, SamplerId::Solid, clip_stack.last().cloned(), decrement, target, &mut redraw_cursor);
+
+                            stencil = if depth == 0 { StencilMode::Off } else { StencilMode::Test(depth) };
+                        }
+                    },
+
+                    StateCmd::SortKey(_) => {},
+                }
+            }
+
+            flush(vertices.len(), texture, clip_stack.last().cloned(), stencil, target, &mut cursor);
+        }
     }
 
     pub fn push_state_cmd(&mut self, cmd: StateCmd<TruetypeFontKey, BitmapFontKey, TexKey>) {
@@ -303,8 +1170,13 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         // Slight optimization. This is not necessary, as the `draw` function also checks for
         // duplicate values in a more sophisticated way. This just keeps the size of `state_changes`
         // a bit smaller.
+        // Mask commands are never skipped, as e.g. two consecutive `PopMask`s pop two masks.
+        let is_mask_cmd = match cmd {
+            StateCmd::BeginMask | StateCmd::PushMask | StateCmd::PopMask => true,
+            _ => false,
+        };
         if let Some(&StateChange { cmd: last_cmd, .. }) = layer.state_changes.last() {
-            if last_cmd == cmd {
+            if last_cmd == cmd && !is_mask_cmd {
                 return;
             }
         }
@@ -321,6 +1193,42 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
                 self.working_clip_stack.pop();
             },
 
+            StateCmd::BeginMask => {
+                if self.building_mask {
+                    panic!("`StateCmd::BeginMask` added twice without a `StateCmd::PushMask` in between");
+                }
+
+                self.building_mask = true;
+            },
+            StateCmd::PushMask => {
+                if !self.building_mask {
+                    panic!("`StateCmd::PushMask` added without a preceding `StateCmd::BeginMask`");
+                }
+                if self.working_mask_depth >= 255 {
+                    panic!("Can not push more than 255 masks, as masks are stored in a 8 bit stencil buffer");
+                }
+
+                self.building_mask = false;
+                self.working_mask_depth += 1;
+            },
+            StateCmd::PopMask => {
+                if self.working_mask_depth == 0 || self.building_mask {
+                    panic!("Unbalanced `StateCmd::PushMask` and `StateCmd::PopMask`");
+                }
+
+                self.working_mask_depth -= 1;
+            },
+
+            StateCmd::SortKey(key) => {
+                self.sort_key = key;
+                if layer.sort_key == key {
+                    return;
+                }
+
+                layer.sort_key = key;
+                layer.sorted = true;
+            },
+
             _ => {},
         }
 
@@ -340,6 +1248,211 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         );
 
         self.current_layer = layer;
+
+        // The sort key applies to all layers
+        let sort_key = self.sort_key;
+        self.push_state_cmd(StateCmd::SortKey(sort_key));
+    }
+
+    /// Sets the transform used for the given layer, instead of the transform passed to [`draw`]
+    /// and [`rasterize`]. This allows drawing e.g. the world with a camera transform and a hud
+    /// in screen space from a single group. `None`, the default, uses the global transform. Layer
+    /// transforms are kept when calling [`reset`].
+    ///
+    /// ```rust,ignore
+    /// draw_group.set_layer_transform(1, Some(Mat4::ortho(0.0, win_size.x, 0.0, win_size.y, -1.0, 1.0)));
+    ///
+    /// // Each frame
+    /// draw_group.draw(camera.transform(), win_size);
+    /// ```
+    ///
+    /// [`draw`]: #method.draw
+    /// [`rasterize`]: #method.rasterize
+    /// [`reset`]: #method.reset
+    pub fn set_layer_transform(&mut self, layer: usize, transform: Option<Mat4<f32>>) {
+        assert!(
+            layer < LAYER_COUNT,
+            "Can not use layers greater than or equal to LAYER_COUNT ({} >= {})",
+            layer, LAYER_COUNT
+        );
+
+        self.layer_transforms[layer] = transform;
+    }
+
+    /// The transform set for the given layer with [`set_layer_transform`], if any.
+    ///
+    /// [`set_layer_transform`]: #method.set_layer_transform
+    pub fn layer_transform(&self, layer: usize) -> Option<Mat4<f32>> {
+        self.layer_transforms[layer]
+    }
+
+    /// Enables damage tracking, for tools which only redraw the parts of the window which changed
+    /// instead of drawing every frame. Call [`damage`] after adding all primitives for a frame
+    /// to find the region which changed since the last frame. [`draw`] then only draws inside
+    /// that region, so the rest of the previous frame is kept. Disabled by default.
+    ///
+    /// This assumes the group is rebuilt each frame, with [`reset`], and that the back buffer
+    /// keeps its contents between frames. Use [`WindowCommon::swap_buffers_with_damage`] to
+    /// present, and call [`invalidate_damage`] if it reports that the contents were lost.
+    ///
+    /// ```rust,ignore
+    /// draw_group.set_damage_tracking(true);
+    ///
+    /// // Each frame
+    /// draw_group.reset();
+    /// build_ui(&mut draw_group);
+    ///
+    /// if let Some(damage) = draw_group.damage(transform, win_size) {
+    ///     graphics::set_scissor(Some(damage), win_size);
+    ///     graphics::clear(Some(background), true, false);
+    ///     draw_group.draw(transform, win_size);
+    ///
+    ///     if !window.swap_buffers_with_damage(&[damage]) {
+    ///         draw_group.invalidate_damage();
+    ///     }
+    /// }
+    /// window.wait_events(&mut input, None);
+    /// ```
+    ///
+    /// [`damage`]: #method.damage
+    /// [`draw`]: #method.draw
+    /// [`reset`]: #method.reset
+    /// [`invalidate_damage`]: #method.invalidate_damage
+    /// [`WindowCommon::swap_buffers_with_damage`]: ../window/trait.WindowCommon.html#method.swap_buffers_with_damage
+    pub fn set_damage_tracking(&mut self, enabled: bool) {
+        self.damage_tracking = enabled;
+        self.damage_snapshot = None;
+        self.damage_region = None;
+    }
+
+    pub fn damage_tracking(&self) -> bool {
+        self.damage_tracking
+    }
+
+    /// Finds the region which looks different from when this was last called, by comparing the
+    /// triangles and state commands of all layers. The region is in pixels with `(0, 0)` at the
+    /// top left, like clip regions. Returns `None` if nothing changed. The first call, and calls
+    /// after [`invalidate_damage`] or after `transform`, `win_size` or a layer transform changed,
+    /// return the whole window. Changes to the contents of textures are not detected.
+    ///
+    /// If damage tracking is enabled, subsequent calls to [`draw`] only draw inside the returned
+    /// region. See [`set_damage_tracking`].
+    ///
+    /// [`invalidate_damage`]: #method.invalidate_damage
+    /// [`draw`]: #method.draw
+    /// [`set_damage_tracking`]: #method.set_damage_tracking
+    pub fn damage(&mut self, transform: Mat4<f32>, win_size: Vec2<f32>) -> Option<Region> {
+        let mut transforms = [transform; LAYER_COUNT];
+        for layer in 0..LAYER_COUNT {
+            if let Some(transform) = self.layer_transforms[layer] {
+                transforms[layer] = transform;
+            }
+        }
+
+        let window = Region { min: Vec2::ZERO, max: win_size };
+
+        let damage = match self.damage_snapshot {
+            Some(ref snapshot) if snapshot.win_size == win_size && snapshot.transforms == transforms => {
+                let mut min = Vec2::new(f32::INFINITY, f32::INFINITY);
+                let mut max = Vec2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+                let mut localized = true;
+
+                for layer in 0..LAYER_COUNT {
+                    let transform = transforms[layer];
+                    let (ref old_vertices, ref old_state_changes) = snapshot.layers[layer];
+                    let current = &self.layers[layer];
+
+                    localized &= find_damage(
+                        (old_vertices, old_state_changes), (&current.vertices, &current.state_changes),
+                        &snapshot.shapes, &self.shapes,
+                        |vert| {
+                            // Same as the vertex shader and viewport transform, but with y pointing down
+                            let clip = transform * Vec4::new(vert.pos.x, vert.pos.y, 0.0, 1.0);
+                            let pos = Vec2::new(
+                                (clip.x/clip.w + 1.0) * 0.5 * win_size.x,
+                                (1.0 - clip.y/clip.w) * 0.5 * win_size.y,
+                            );
+                            min = Vec2::new(min.x.min(pos.x), min.y.min(pos.y));
+                            max = Vec2::new(max.x.max(pos.x), max.y.max(pos.y));
+                        },
+                    );
+                }
+
+                if !localized {
+                    Some(window)
+                } else if min.x > max.x {
+                    None
+                } else {
+                    // Whole pixels, with a margin for antialiasing
+                    let region = Region {
+                        min: Vec2::new((min.x - 1.0).floor(), (min.y - 1.0).floor()),
+                        max: Vec2::new((max.x + 1.0).ceil(), (max.y + 1.0).ceil()),
+                    }.overlap(window);
+
+                    if region.width() > 0.0 && region.height() > 0.0 {
+                        Some(region)
+                    } else {
+                        None
+                    }
+                }
+            },
+
+            _ => Some(window),
+        };
+
+        // Remember the current contents, reusing the allocations of the last snapshot
+        let mut snapshot = self.damage_snapshot.take().unwrap_or_else(|| DamageSnapshot {
+            layers: vec![(Vec::new(), Vec::new()); LAYER_COUNT],
+            shapes: Vec::new(),
+            transforms,
+            win_size,
+        });
+        for (layer, &mut (ref mut vertices, ref mut state_changes)) in self.layers.iter().zip(snapshot.layers.iter_mut()) {
+            vertices.clone_from(&layer.vertices);
+            state_changes.clone_from(&layer.state_changes);
+        }
+        snapshot.shapes.clone_from(&self.shapes);
+        snapshot.transforms = transforms;
+        snapshot.win_size = win_size;
+        self.damage_snapshot = Some(snapshot);
+
+        // Nothing is drawn if nothing changed
+        self.damage_region = Some(damage.unwrap_or_default());
+
+        damage
+    }
+
+    /// Makes the next call to [`damage`] return the whole window, e.g. because the back buffer
+    /// lost its contents.
+    ///
+    /// [`damage`]: #method.damage
+    pub fn invalidate_damage(&mut self) {
+        self.damage_snapshot = None;
+    }
+
+    /// Changes the sort key of subsequent primitives, so primitives can be added in any order
+    /// within a layer. This is a shorthand for pushing a [`StateCmd::SortKey`], see it for more
+    /// info. The key stays in effect until it is changed again, also when changing layers, and is
+    /// reset to `0` by [`reset`].
+    ///
+    /// ```rust,ignore
+    /// draw_group.set_sort_key(10);
+    /// draw_group.aabb(tooltip.min, tooltip.max, Color::BLACK); // Drawn on top of the button
+    /// draw_group.set_sort_key(0);
+    /// draw_group.aabb(button.min, button.max, Color::WHITE);
+    /// ```
+    ///
+    /// [`StateCmd::SortKey`]: enum.StateCmd.html#variant.SortKey
+    /// [`reset`]: #method.reset
+    pub fn set_sort_key(&mut self, key: u32) {
+        self.push_state_cmd(StateCmd::SortKey(key));
+    }
+
+    /// The sort key set with [`set_sort_key`].
+    ///
+    /// [`set_sort_key`]: #method.set_sort_key
+    pub fn sort_key(&self) -> u32 {
+        self.sort_key
     }
 
     /// Retrieves a reference to the font, or panics if no font has been registered for the given key.
@@ -358,6 +1471,40 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         &self.textures[&key]
     }
 
+    /// Retrieves a reference to the font, or `None` if no font has been registered for the given
+    /// key.
+    pub fn get_truetype_font(&self, key: TruetypeFontKey) -> Option<&TruetypeFont> {
+        self.truetype_fonts.get(&key)
+    }
+
+    /// Retrieves a reference to the font, or `None` if no font has been registered for the given
+    /// key.
+    pub fn get_bitmap_font(&self, key: BitmapFontKey) -> Option<&BitmapFont> {
+        self.bitmap_fonts.get(&key)
+    }
+
+    /// Retrieves a reference to the texture, or `None` if no texture has been registered for the
+    /// given key.
+    pub fn get_texture(&self, key: TexKey) -> Option<&Texture> {
+        self.textures.get(&key)
+    }
+
+    /// Decides how positions of text and axis aligned boxes are snapped to the pixel grid. This
+    /// affects all primitives added after this call, so it can either be set once for the whole
+    /// group or changed around individual calls. Defaults to [`PixelSnap::Round`].
+    ///
+    /// [`PixelSnap::Round`]: enum.PixelSnap.html#variant.Round
+    pub fn set_pixel_snap(&mut self, snap: PixelSnap) {
+        self.pixel_snap = snap;
+    }
+
+    /// The snapping mode set with [`set_pixel_snap`].
+    ///
+    /// [`set_pixel_snap`]: #method.set_pixel_snap
+    pub fn pixel_snap(&self) -> PixelSnap {
+        self.pixel_snap
+    }
+
     /// Retrieves the current clipping rectangle. The returned region is the region to which
     /// vertices will be constrained during drawing. If the clipping stack is empty, this returns 
     /// `None`. The clipping region is changed by pushing [`StateCmd::PushClip`][0] and 
@@ -652,6 +1799,82 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         }
     }
 
+    /// Draws a filled ellipse centered at the given position, with the given radii along the x
+    /// and y axis. The number of segments depends on the size of the ellipse.
+    pub fn ellipse(&mut self, center: Vec2<f32>, radii: Vec2<f32>, color: Color) {
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::Solid));
+        let uv = Vec2::ZERO;
+
+        let segments = segment_count(radii.x.max(radii.y), 2.0*f32::consts::PI);
+        let step = Vec2::polar(1.0, 2.0*f32::consts::PI / segments as f32);
+        let scale = |v: Vec2<f32>| Vec2::new(v.x*radii.x, v.y*radii.y);
+
+        let mut a = Vec2::new(1.0, 0.0);
+        for _ in 0..segments {
+            let b = Vec2::complex_mul(a, step);
+            self.add_vertices(&[
+                Vert { pos: center, uv, color },
+                Vert { pos: center + scale(a), uv, color },
+                Vert { pos: center + scale(b), uv, color },
+            ]);
+            a = b;
+        }
+    }
+
+    /// Draws a filled ring, i.e. a circle with a hole in the middle, centered at the given
+    /// position. The number of segments depends on the size of the ring.
+    pub fn ring(&mut self, center: Vec2<f32>, inner_radius: f32, outer_radius: f32, color: Color) {
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::Solid));
+        let uv = Vec2::ZERO;
+
+        let segments = segment_count(outer_radius, 2.0*f32::consts::PI);
+        let step = Vec2::polar(1.0, 2.0*f32::consts::PI / segments as f32);
+
+        let mut a = Vec2::new(1.0, 0.0);
+        for _ in 0..segments {
+            let b = Vec2::complex_mul(a, step);
+            self.add_vertices(&[
+                Vert { pos: center + a*inner_radius, uv, color },
+                Vert { pos: center + a*outer_radius, uv, color },
+                Vert { pos: center + b*outer_radius, uv, color },
+                Vert { pos: center + a*inner_radius, uv, color },
+                Vert { pos: center + b*outer_radius, uv, color },
+                Vert { pos: center + b*inner_radius, uv, color },
+            ]);
+            a = b;
+        }
+    }
+
+    /// Draws a filled slice of a circle, between the two given angles. Angles are in radians, and
+    /// measured from the positive x axis towards the positive y axis, which is clockwise on
+    /// screen. If the angles are more than a full turn apart, a full circle is drawn. The number
+    /// of segments depends on the size of the slice.
+    pub fn pie(&mut self, center: Vec2<f32>, radius: f32, start_angle: f32, end_angle: f32, color: Color) {
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::Solid));
+        let uv = Vec2::ZERO;
+
+        let angle = (end_angle - start_angle)
+            .max(-2.0*f32::consts::PI)
+            .min(2.0*f32::consts::PI);
+        if angle == 0.0 {
+            return;
+        }
+
+        let segments = segment_count(radius, angle);
+        let step = Vec2::polar(1.0, angle / segments as f32);
+
+        let mut a = Vec2::polar(1.0, start_angle);
+        for _ in 0..segments {
+            let b = Vec2::complex_mul(a, step);
+            self.add_vertices(&[
+                Vert { pos: center, uv, color },
+                Vert { pos: center + a*radius, uv, color },
+                Vert { pos: center + b*radius, uv, color },
+            ]);
+            a = b;
+        }
+    }
+
     /// Generates vertices for a line with a arrowhead at `b`.
     pub fn arrow(
         &mut self,
@@ -799,8 +2022,165 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         ]);
     }
 
+    /// Draws a thick line through all the given points, connecting segments with the given join
+    /// and ending the line with the given cap. Repeated points are ignored.
+    ///
+    /// Each segment is drawn as a separate quad, and joins are only added on the outer side of
+    /// corners. This means that segments overlap slightly on the inner side of corners, which is
+    /// visible when using translucent colors.
+    pub fn polyline(&mut self, points: &[Vec2<f32>], width: f32, join: Join, cap: Cap, color: Color) {
+        // Repeated points have no direction, so we can't find normals for them
+        let mut unique_points = Vec::with_capacity(points.len());
+        for &point in points.iter() {
+            match unique_points.last() {
+                Some(&last) if (point - last).len() < 1e-6 => continue,
+                _ => unique_points.push(point),
+            }
+        }
+        let points = unique_points;
+
+        if points.len() < 2 {
+            return;
+        }
+
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::Solid));
+
+        let half_width = width / 2.0;
+        let uv = Vec2::ZERO;
+
+        for i in 0..(points.len() - 1) {
+            let a = points[i];
+            let b = points[i + 1];
+            let normal = (b - a).normalize().left() * half_width;
+
+            self.add_vertices(&[
+                Vert { pos: a - normal, uv, color },
+                Vert { pos: b - normal, uv, color },
+                Vert { pos: b + normal, uv, color },
+                Vert { pos: a - normal, uv, color },
+                Vert { pos: b + normal, uv, color },
+                Vert { pos: a + normal, uv, color },
+            ]);
+        }
+
+        for i in 1..(points.len() - 1) {
+            let in_dir = (points[i] - points[i - 1]).normalize();
+            let out_dir = (points[i + 1] - points[i]).normalize();
+            self.polyline_join(points[i], in_dir, out_dir, half_width, join, color);
+        }
+
+        let last = points.len() - 1;
+        let start_dir = (points[0] - points[1]).normalize();
+        let end_dir = (points[last] - points[last - 1]).normalize();
+        self.polyline_cap(points[0], start_dir, half_width, cap, color);
+        self.polyline_cap(points[last], end_dir, half_width, cap, color);
+    }
+
+    fn polyline_join(
+        &mut self,
+        point: Vec2<f32>,
+        in_dir: Vec2<f32>, out_dir: Vec2<f32>,
+        half_width: f32,
+        join: Join,
+        color: Color,
+    ) {
+        let uv = Vec2::ZERO;
+
+        let cross = Vec2::cross(in_dir, out_dir);
+        if cross.abs() < 1e-6 && Vec2::dot(in_dir, out_dir) > 0.0 {
+            return; // Straight continuation, the segments already connect
+        }
+
+        // The join is only needed on the outer side of the corner
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let from = in_dir.left() * (half_width * side);
+        let to = out_dir.left() * (half_width * side);
+
+        let bevel = [
+            Vert { pos: point, uv, color },
+            Vert { pos: point + from, uv, color },
+            Vert { pos: point + to, uv, color },
+        ];
+
+        match join {
+            Join::Bevel => self.add_vertices(&bevel),
+
+            Join::Miter(limit) => {
+                let sum = from + to;
+                let sum_len = sum.len();
+                // Cosine of half the angle between the two normals
+                let cos = sum_len / (2.0 * half_width);
+
+                if cos < 1e-6 || 1.0/cos > limit {
+                    self.add_vertices(&bevel);
+                } else {
+                    let tip = point + sum * (half_width / (sum_len * cos));
+                    self.add_vertices(&[
+                        Vert { pos: point, uv, color },
+                        Vert { pos: point + from, uv, color },
+                        Vert { pos: tip, uv, color },
+                        Vert { pos: point, uv, color },
+                        Vert { pos: tip, uv, color },
+                        Vert { pos: point + to, uv, color },
+                    ]);
+                }
+            },
+
+            Join::Round => {
+                let angle = Vec2::cross(from, to).atan2(Vec2::dot(from, to));
+                self.arc_fan(point, from, angle, color);
+            },
+        }
+    }
+
+    fn polyline_cap(&mut self, point: Vec2<f32>, dir: Vec2<f32>, half_width: f32, cap: Cap, color: Color) {
+        let uv = Vec2::ZERO;
+        let normal = dir.left() * half_width;
+
+        match cap {
+            Cap::Butt => {},
+
+            Cap::Square => {
+                let end = point + dir*half_width;
+                self.add_vertices(&[
+                    Vert { pos: point - normal, uv, color },
+                    Vert { pos: end - normal, uv, color },
+                    Vert { pos: end + normal, uv, color },
+                    Vert { pos: point - normal, uv, color },
+                    Vert { pos: end + normal, uv, color },
+                    Vert { pos: point + normal, uv, color },
+                ]);
+            },
+
+            // Rotating clockwise from the normal sweeps through `dir`
+            Cap::Round => self.arc_fan(point, normal, -f32::consts::PI, color),
+        }
+    }
+
+    // Draws a circular sector around `center`, starting at `center + from` and rotating `angle`
+    // radians counterclockwise.
+    fn arc_fan(&mut self, center: Vec2<f32>, from: Vec2<f32>, angle: f32, color: Color) {
+        let uv = Vec2::ZERO;
+
+        // Same resolution as the circles drawn with `SIN_COS`
+        let steps = (angle.abs() / (f32::consts::PI / 20.0)).ceil().max(1.0);
+        let step = Vec2::polar(1.0, angle / steps);
+
+        let mut a = from;
+        for _ in 0..(steps as usize) {
+            let b = Vec2::complex_mul(a, step);
+            self.add_vertices(&[
+                Vert { pos: center, uv, color },
+                Vert { pos: center + a, uv, color },
+                Vert { pos: center + b, uv, color },
+            ]);
+            a = b;
+        }
+    }
+
     /// Draws borders for an axis align bounding box.
     pub fn line_aabb(&mut self, min: Vec2<f32>, max: Vec2<f32>, width: f32, color: Color) {
+        let (min, max) = (self.pixel_snap.apply(min), self.pixel_snap.apply(max));
         let points = [
             Vec2::new(min.x, min.y),
             Vec2::new(max.x, min.y),
@@ -813,8 +2193,124 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         ); 
     }
 
+    /// Draws borders for an axis aligned bounding box with rounded corners. This is the outline of
+    /// `rounded_aabb` with the same parameters.
+    pub fn rounded_line_aabb(
+        &mut self,
+        min: Vec2<f32>, max: Vec2<f32>,
+        corner_radius: f32,
+        width: f32,
+        color: Color
+    ) {
+        if corner_radius <= 0.0 {
+            self.line_aabb(min, max, width, color);
+            return;
+        }
+
+        let points = rounded_aabb_outline(min, max, corner_radius);
+        self.closed_line_loop(&points, width, color);
+    }
+
+    /// Draws stippled borders for an axis aligned bounding box. The stipple pattern continues
+    /// around corners.
+    pub fn stippled_line_aabb(
+        &mut self,
+        min: Vec2<f32>, max: Vec2<f32>,
+        width: f32, stipple_length: f32, stipple_spacing: f32,
+        color: Color
+    ) {
+        let points = [
+            Vec2::new(min.x, min.y),
+            Vec2::new(max.x, min.y),
+            Vec2::new(max.x, max.y),
+            Vec2::new(min.x, max.y),
+            Vec2::new(min.x, min.y),
+        ];
+        self.stippled_path(&points, width, stipple_length, stipple_spacing, color);
+    }
+
+    /// Draws stippled borders for an axis aligned bounding box with rounded corners. The stipple
+    /// pattern continues around corners.
+    pub fn stippled_rounded_line_aabb(
+        &mut self,
+        min: Vec2<f32>, max: Vec2<f32>,
+        corner_radius: f32,
+        width: f32, stipple_length: f32, stipple_spacing: f32,
+        color: Color
+    ) {
+        if corner_radius <= 0.0 {
+            self.stippled_line_aabb(min, max, width, stipple_length, stipple_spacing, color);
+            return;
+        }
+
+        let mut points = rounded_aabb_outline(min, max, corner_radius);
+        let first = points[0];
+        points.push(first);
+        self.stippled_path(&points, width, stipple_length, stipple_spacing, color);
+    }
+
+    // Draws stipples along the given open path. Stipples are not interrupted at corners, so
+    // a single stipple can span multiple segments.
+    fn stippled_path(
+        &mut self,
+        points: &[Vec2<f32>],
+        width: f32, stipple_length: f32, stipple_spacing: f32,
+        color: Color
+    ) {
+        // Otherwise the walk below would stop advancing, or never reach the end
+        let valid = stipple_length.is_finite() && stipple_spacing.is_finite() &&
+                    stipple_length > 0.0 && stipple_spacing >= 0.0;
+        if !valid || points.len() < 2 {
+            return;
+        }
+
+        let mut stipple = Vec::new();
+        let mut drawing = true;
+        let mut remaining = stipple_length; // Of the current stipple or gap
+
+        for i in 0..(points.len() - 1) {
+            let a = points[i];
+            let b = points[i + 1];
+            let len = (b - a).len();
+            if !(len > 0.0 && len.is_finite()) {
+                continue;
+            }
+            let dir = (b - a) / len;
+
+            let mut t = 0.0;
+            while t < len {
+                if drawing && stipple.is_empty() {
+                    stipple.push(a + dir*t);
+                }
+
+                let step = remaining.min(len - t);
+                t += step;
+                remaining -= step;
+
+                if drawing {
+                    stipple.push(a + dir*t);
+                }
+
+                if remaining <= 0.0 {
+                    if drawing {
+                        self.polyline(&stipple, width, Join::Miter(4.0), Cap::Butt, color);
+                        stipple.clear();
+                    }
+
+                    drawing = !drawing;
+                    remaining = if drawing { stipple_length } else { stipple_spacing };
+                }
+            }
+        }
+
+        if !stipple.is_empty() {
+            self.polyline(&stipple, width, Join::Miter(4.0), Cap::Butt, color);
+        }
+    }
+
     /// Draws a solid axis-aligned bounding box.
     pub fn aabb(&mut self, min: Vec2<f32>, max: Vec2<f32>, color: Color) {
+        let (min, max) = (self.pixel_snap.apply(min), self.pixel_snap.apply(max));
         self.push_state_cmd(StateCmd::TextureChange(SamplerId::Solid));
         let uv = Vec2::ZERO;
 
@@ -836,6 +2332,7 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
             return;
         }
 
+        let (min, max) = (self.pixel_snap.apply(min), self.pixel_snap.apply(max));
         self.push_state_cmd(StateCmd::TextureChange(SamplerId::Solid));
         let uv = Vec2::ZERO;
 
@@ -894,19 +2391,104 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         }
     }
 
+    /// Draws an axis-aligned bounding box with rounded corners, computing its coverage in the
+    /// fragment shader instead of tessellating the corners. Unlike [`rounded_aabb`], the corners
+    /// stay smooth and antialiased at any size and scale. The box can have a border and a drop
+    /// shadow, see [`ShapeStyle`]. `corner_radius` is clamped to half the size of the box.
+    ///
+    /// Shapes are drawn as quads which extend one unit past the edge of the shape, plus the blur
+    /// of the shadow, so antialiasing assumes that units are roughly pixels. Software draw groups
+    /// skip these shapes when rasterizing.
+    ///
+    /// [`rounded_aabb`]: #method.rounded_aabb
+    /// [`ShapeStyle`]: struct.ShapeStyle.html
+    pub fn sdf_rounded_aabb(&mut self, min: Vec2<f32>, max: Vec2<f32>, corner_radius: f32, style: ShapeStyle) {
+        let (min, max) = (self.pixel_snap.apply(min), self.pixel_snap.apply(max));
+        let half_size = (max - min) / 2.0;
+        let corner_radius = corner_radius.max(0.0).min(half_size.x.min(half_size.y));
+        self.sdf_shape((min + max) / 2.0, half_size, corner_radius, style);
+    }
+
+    /// Draws a circle whose coverage is computed in the fragment shader, so its edge is smooth
+    /// and antialiased at any size and scale. See [`sdf_rounded_aabb`].
+    ///
+    /// [`sdf_rounded_aabb`]: #method.sdf_rounded_aabb
+    pub fn sdf_circle(&mut self, center: Vec2<f32>, radius: f32, style: ShapeStyle) {
+        self.sdf_shape(center, Vec2::new(radius, radius), radius, style);
+    }
+
+    fn sdf_shape(&mut self, center: Vec2<f32>, half_size: Vec2<f32>, corner_radius: f32, style: ShapeStyle) {
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::Shape));
+
+        if let Some(shadow) = style.shadow {
+            let blur = shadow.blur.max(0.0);
+            self.shape_quad(ShapeInstance {
+                rect: Vec4::new(center.x + shadow.offset.x, center.y + shadow.offset.y, half_size.x, half_size.y),
+                params: Vec4::new(corner_radius, 0.0, blur, 0.0),
+                border_color: shadow.color,
+            }, shadow.color);
+        }
+
+        let (border_width, border_color) = style.border.unwrap_or((0.0, style.color));
+        self.shape_quad(ShapeInstance {
+            rect: Vec4::new(center.x, center.y, half_size.x, half_size.y),
+            params: Vec4::new(corner_radius, border_width.max(0.0), 0.0, 0.0),
+            border_color,
+        }, style.color);
+    }
+
+    // Adds a quad covering the given shape, and its antialiased edge
+    fn shape_quad(&mut self, shape: ShapeInstance, color: Color) {
+        let center = Vec2::new(shape.rect.x, shape.rect.y);
+        let extent = Vec2::new(shape.rect.z, shape.rect.w) + Vec2::new(1.0, 1.0)*(shape.params.z + 1.0);
+        let (min, max) = (center - extent, center + extent);
+
+        let uv = Vec2::new(self.shapes.len() as f32, 0.0);
+        self.shapes.push(shape);
+
+        self.add_vertices(&[
+            Vert { pos: Vec2::new(min.x, min.y), uv, color },
+            Vert { pos: Vec2::new(max.x, min.y), uv, color },
+            Vert { pos: Vec2::new(max.x, max.y), uv, color },
+
+            Vert { pos: Vec2::new(min.x, min.y), uv, color },
+            Vert { pos: Vec2::new(max.x, max.y), uv, color },
+            Vert { pos: Vec2::new(min.x, max.y), uv, color },
+        ]);
+    }
+
     /// Draws a textured axis-aligned bounding box.
     pub fn textured_aabb(&mut self, texture: TexKey, min: Vec2<f32>, max: Vec2<f32>) {
+        let uv_region = Region { min: Vec2::new(0.0, 0.0), max: Vec2::new(1.0, 1.0) };
+        self.textured_aabb_region(texture, uv_region, min, max, Color::rgb(1.0, 1.0, 1.0));
+    }
+
+    /// Draws a axis-aligned bounding box textured with the part of the given texture covered by
+    /// `uv_region`. The texture is multiplied by `tint`. This is intended for drawing many
+    /// images packed into a single texture, e.g. icons, as all of them can be drawn without
+    /// switching textures. Use [`Texture::uv_region`] to find the uv region of a part of a texture.
+    ///
+    /// [`Texture::uv_region`]: ../texture/struct.Texture.html#method.uv_region
+    pub fn textured_aabb_region(
+        &mut self,
+        texture: TexKey,
+        uv_region: Region,
+        min: Vec2<f32>, max: Vec2<f32>,
+        tint: Color,
+    ) {
+        let (min, max) = (self.pixel_snap.apply(min), self.pixel_snap.apply(max));
         self.push_state_cmd(StateCmd::TextureChange(SamplerId::Texture(texture)));
-        let color = Color::rgb(1.0, 1.0, 1.0);
+        let color = tint;
+        let (uv_min, uv_max) = (uv_region.min, uv_region.max);
 
         self.add_vertices(&[
-            Vert { pos: Vec2::new(min.x, min.y), color, uv: Vec2::new(0.0, 0.0) },
-            Vert { pos: Vec2::new(max.x, min.y), color, uv: Vec2::new(1.0, 0.0) },
-            Vert { pos: Vec2::new(max.x, max.y), color, uv: Vec2::new(1.0, 1.0) },
+            Vert { pos: Vec2::new(min.x, min.y), color, uv: Vec2::new(uv_min.x, uv_min.y) },
+            Vert { pos: Vec2::new(max.x, min.y), color, uv: Vec2::new(uv_max.x, uv_min.y) },
+            Vert { pos: Vec2::new(max.x, max.y), color, uv: Vec2::new(uv_max.x, uv_max.y) },
 
-            Vert { pos: Vec2::new(min.x, min.y), color, uv: Vec2::new(0.0, 0.0) },
-            Vert { pos: Vec2::new(max.x, max.y), color, uv: Vec2::new(1.0, 1.0) },
-            Vert { pos: Vec2::new(min.x, max.y), color, uv: Vec2::new(0.0, 1.0) },
+            Vert { pos: Vec2::new(min.x, min.y), color, uv: Vec2::new(uv_min.x, uv_min.y) },
+            Vert { pos: Vec2::new(max.x, max.y), color, uv: Vec2::new(uv_max.x, uv_max.y) },
+            Vert { pos: Vec2::new(min.x, max.y), color, uv: Vec2::new(uv_min.x, uv_max.y) },
         ]);
     }
 
@@ -919,34 +2501,550 @@ impl<TruetypeFontKey, BitmapFontKey, TexKey> DrawGroup<TruetypeFontKey, BitmapFo
         wrap_width: Option<f32>,
         color: Color
     ) {
+        self.truetype_text_with_effects(text, font, size, pos, wrap_width, color, TextEffects::default());
+    }
+
+    /// Same as `truetype_text`, but additionally draws an outline and/or a drop shadow behind the
+    /// text. See [`TextEffects`] for details.
+    ///
+    /// Effects are drawn by repeating the glyphs of the text with different offsets and colors,
+    /// so they add a fair amount of vertices. The text is only laid out once though.
+    ///
+    /// [`TextEffects`]: struct.TextEffects.html
+    pub fn truetype_text_with_effects(
+        &mut self,
+        text: &str,
+        font: TruetypeFontKey,
+        size: f32,
+        pos: Vec2<f32>,
+        wrap_width: Option<f32>,
+        color: Color,
+        effects: TextEffects,
+    ) {
+        // Nothing is drawn with fonts which have not been loaded
+        let pos = self.pixel_snap.apply(pos);
+        let mut glyphs = Vec::new();
+        match self.truetype_fonts.get_mut(&font) {
+            Some(font) => font.cache(text, size, 1.0, pos, wrap_width, |pos, uv| glyphs.push((pos, uv))),
+            None => return,
+        }
+
         self.push_state_cmd(StateCmd::TextureChange(SamplerId::TruetypeFont(font)));
 
-        let ref mut vertices = self.layers[self.current_layer].vertices;
-        let callback = |pos, uv| vertices.push(Vert { pos, uv, color });
+        self.add_glyphs(&glyphs, Vec2::ZERO, color, None, effects);
+    }
 
-        self.truetype_fonts.get_mut(&font).unwrap().cache(
-            text,
-            size, 1.0, 
-            pos.round(), // By rounding we avoid a lot of nasty subpixel issues.
+    /// Same as `truetype_text_with_effects`, but calls `glyph_fn` for each glyph before it is
+    /// added, so glyphs can be moved or recolored individually, e.g. for animated dialogue text.
+    /// Outlines and shadows follow the moved glyphs, but keep their own colors. See
+    /// [`GlyphAnimation`] for some common animations.
+    ///
+    /// [`GlyphAnimation`]: enum.GlyphAnimation.html
+    pub fn truetype_text_with_glyph_fn<F>(
+        &mut self,
+        text: &str,
+        font: TruetypeFontKey,
+        size: f32,
+        pos: Vec2<f32>,
+        wrap_width: Option<f32>,
+        color: Color,
+        effects: TextEffects,
+        mut glyph_fn: F,
+    )
+      where F: FnMut(&mut GlyphQuad),
+    {
+        let pos = self.pixel_snap.apply(pos);
+        let mut glyphs = Vec::new();
+        match self.truetype_fonts.get_mut(&font) {
+            Some(font) => font.cache(text, size, 1.0, pos, wrap_width, |pos, uv| glyphs.push((pos, uv))),
+            None => return,
+        }
+
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::TruetypeFont(font)));
+
+        let mut colors = Vec::with_capacity(glyphs.len() / 6);
+        for (index, quad) in glyphs.chunks_mut(6).enumerate() {
+            // The first and third vertex are opposite corners
+            let rect = Region { min: quad[0].0, max: quad[2].0 };
+            let mut glyph = GlyphQuad { index, rect, offset: Vec2::ZERO, color };
+            glyph_fn(&mut glyph);
+
+            for &mut (ref mut pos, _) in quad.iter_mut() {
+                *pos = *pos + glyph.offset;
+            }
+            colors.push(glyph.color);
+        }
+
+        self.add_glyphs(&glyphs, Vec2::ZERO, color, Some(&colors), effects);
+    }
+
+    /// Lays out text so it can be drawn repeatedly with [`shaped_text`]. See [`ShapedText`].
+    ///
+    /// [`shaped_text`]: #method.shaped_text
+    /// [`ShapedText`]: struct.ShapedText.html
+    pub fn shape_truetype_text(
+        &mut self,
+        text: &str,
+        font: TruetypeFontKey,
+        size: f32,
+        wrap_width: Option<f32>,
+    ) -> ShapedText<TruetypeFontKey> {
+        let mut shaped = ShapedText {
+            text: text.to_owned(),
+            font,
+            size,
             wrap_width,
-            callback,
-        ); 
+            glyphs: Vec::new(),
+            atlas_generation: None,
+        };
+        self.update_shaped_text(&mut shaped);
+        shaped
+    }
+
+    /// Draws text which was laid out with [`shape_truetype_text`]. This gives the same result as
+    /// `truetype_text`, as long as `pos` lies on whole pixels or pixel snapping is enabled.
+    ///
+    /// [`shape_truetype_text`]: #method.shape_truetype_text
+    pub fn shaped_text(&mut self, shaped: &mut ShapedText<TruetypeFontKey>, pos: Vec2<f32>, color: Color) {
+        self.shaped_text_with_effects(shaped, pos, color, TextEffects::default());
+    }
+
+    /// Same as `shaped_text`, but additionally draws an outline and/or a drop shadow. See
+    /// [`TextEffects`].
+    ///
+    /// [`TextEffects`]: struct.TextEffects.html
+    pub fn shaped_text_with_effects(
+        &mut self,
+        shaped: &mut ShapedText<TruetypeFontKey>,
+        pos: Vec2<f32>,
+        color: Color,
+        effects: TextEffects,
+    ) {
+        if !self.update_shaped_text(shaped) {
+            return;
+        }
+        self.push_state_cmd(StateCmd::TextureChange(SamplerId::TruetypeFont(shaped.font)));
+        self.add_glyphs(&shaped.glyphs, self.pixel_snap.apply(pos), color, None, effects);
+    }
+
+    // Regenerates the glyphs of the given text if they are stale. Returns false if the font has
+    // not been loaded.
+    fn update_shaped_text(&mut self, shaped: &mut ShapedText<TruetypeFontKey>) -> bool {
+        let font = match self.truetype_fonts.get_mut(&shaped.font) {
+            Some(font) => font,
+            None => return false,
+        };
+        if shaped.atlas_generation == Some(font.atlas_generation()) {
+            return true;
+        }
+
+        shaped.glyphs.clear();
+        let ref mut glyphs = shaped.glyphs;
+        font.cache(
+            &shaped.text,
+            shaped.size, 1.0,
+            Vec2::ZERO,
+            shaped.wrap_width,
+            |pos, uv| glyphs.push((pos, uv)),
+        );
+        shaped.atlas_generation = Some(font.atlas_generation());
+        true
+    }
+
+    // Adds glyph quads from `TruetypeFont::cache`, offset by `offset`, with the given effects. If
+    // `glyph_colors` is given, it contains the color of each glyph, overriding `color`.
+    fn add_glyphs(
+        &mut self,
+        glyphs: &[(Vec2<f32>, Vec2<f32>)],
+        offset: Vec2<f32>,
+        color: Color,
+        glyph_colors: Option<&[Color]>,
+        effects: TextEffects,
+    ) {
+        let ref mut vertices = self.layers[self.current_layer].vertices;
+        let mut add_copy = |effect_offset: Vec2<f32>, color: Color| {
+            let offset = offset + effect_offset;
+            vertices.extend(glyphs.iter().map(|&(pos, uv)| Vert { pos: pos + offset, uv, color }));
+        };
+
+        if let Some(shadow) = effects.shadow {
+            if shadow.blur > 0.0 {
+                // Approximate a blur by smearing translucent copies around the shadow
+                let mut faded = shadow.color;
+                faded.a *= 0.25;
+                for dir in RING.iter() {
+                    add_copy(shadow.offset + *dir*shadow.blur, faded);
+                }
+
+                let mut center = shadow.color;
+                center.a *= 0.5;
+                add_copy(shadow.offset, center);
+            } else {
+                add_copy(shadow.offset, shadow.color);
+            }
+        }
+
+        if let Some((thickness, outline_color)) = effects.outline {
+            for dir in RING.iter() {
+                add_copy(*dir*thickness, outline_color);
+            }
+        }
+
+        match glyph_colors {
+            None => add_copy(Vec2::ZERO, color),
+            Some(colors) => vertices.extend(glyphs.iter().enumerate().map(|(i, &(pos, uv))| {
+                Vert { pos: pos + offset, uv, color: colors[i / 6] }
+            })),
+        }
     }
 
     pub fn bitmap_text(&mut self, text: &str, font: BitmapFontKey, pos: Vec2<f32>, color: Color) {
+        // Nothing is drawn with fonts which have not been loaded
+        if !self.bitmap_fonts.contains_key(&font) {
+            return;
+        }
         self.push_state_cmd(StateCmd::TextureChange(SamplerId::BitmapFont(font)));
 
         let ref mut vertices = self.layers[self.current_layer].vertices;
         let callback = |pos, uv| vertices.push(Vert { pos, uv, color });
 
-        self.bitmap_fonts.get_mut(&font).unwrap().cache(
-            text,
-            pos.round(), // By rounding we avoid a lot of nasty subpixel issues.
-            callback,
-        ); 
+        if let Some(bitmap_font) = self.bitmap_fonts.get_mut(&font) {
+            bitmap_font.cache(text, self.pixel_snap.apply(pos), callback);
+        }
+    }
+}
+
+impl<TruetypeFontKey, BitmapFontKey, TexKey> Layer<TruetypeFontKey, BitmapFontKey, TexKey> {
+    // The vertices and state changes which should actually be drawn
+    fn draw_data(&self) -> (&[Vert], &[StateChange<TruetypeFontKey, BitmapFontKey, TexKey>]) {
+        if self.sorted {
+            (&self.sorted_vertices, &self.sorted_state_changes)
+        } else {
+            (&self.vertices, &self.state_changes)
+        }
+    }
+}
+
+// Draws ranges of the vertices of a single layer. Vertices which are not in the vertex buffer yet
+// are uploaded in chunks as they are needed, see `DrawGroup::set_max_vertices`.
+struct LayerDrawer<'a> {
+    buffer: &'a mut VertexBuffer<Vert>,
+    vertices: &'a [Vert],
+    // The vertices which are currently in `buffer`, and where the first of them is stored
+    resident: Range<usize>,
+    offset: usize,
+    chunk: usize,
+    // Vertices before this have been drawn
+    cursor: usize,
+    // Set while the current texture is missing, in which case `flush` moves the cursor without
+    // drawing
+    skipping: bool,
+}
+
+impl<'a> LayerDrawer<'a> {
+    // Draws all vertices between the cursor and the given vertex
+    fn flush(&mut self, to: usize) {
+        if self.cursor == to { return; }
+
+        let from = self.cursor;
+        if !self.skipping {
+            self.draw(from..to);
+        }
+        self.cursor = to;
+    }
+
+    // Draws the given range without moving the cursor. Also used to draw masks again when
+    // removing them from the stencil buffer.
+    fn draw(&mut self, mut range: Range<usize>) {
+        while range.start < range.end {
+            if range.start < self.resident.start || range.start >= self.resident.end {
+                // Ranges start at state changes, which are always between triangles, and `chunk`
+                // is a multiple of three, so chunks never split triangles
+                let end = usize::min(range.start + self.chunk, self.vertices.len());
+                self.buffer.put(0, &self.vertices[range.start..end]);
+                self.resident = range.start..end;
+                self.offset = 0;
+            }
+
+            let end = usize::min(range.end, self.resident.end);
+            let start_in_buffer = self.offset + range.start - self.resident.start;
+            self.buffer.draw_range(start_in_buffer..(start_in_buffer + end - range.start));
+            range.start = end;
+        }
+    }
+}
+
+// A run of vertices with the same sort key, along with the state changes which happen inside it
+struct SortChunk<TruetypeFontKey, BitmapFontKey, TexKey> {
+    key: u32,
+    texture: SamplerId<TruetypeFontKey, BitmapFontKey, TexKey>,
+    start: usize,
+    end: usize,
+    first_state_change: usize,
+    state_change_count: usize,
+}
+
+// Calls `damaged` with the vertices of all triangles which look different in `new` than in `old`,
+// from both versions. Returns false if the changes affect the whole window, which is the case if
+// a clear command changed.
+fn find_damage<TruetypeFontKey, BitmapFontKey, TexKey, F>(
+    old: (&[Vert], &[StateChange<TruetypeFontKey, BitmapFontKey, TexKey>]),
+    new: (&[Vert], &[StateChange<TruetypeFontKey, BitmapFontKey, TexKey>]),
+    old_shapes: &[ShapeInstance],
+    new_shapes: &[ShapeInstance],
+    mut damaged: F,
+) -> bool
+  where TruetypeFontKey: PartialEq + Copy,
+        BitmapFontKey: PartialEq + Copy,
+        TexKey: PartialEq + Copy,
+        F: FnMut(&Vert),
+{
+    let (old_vertices, old_state_changes) = old;
+    let (new_vertices, new_state_changes) = new;
+
+    // Vertices after the first state change which differs might be drawn differently, e.g. with
+    // another texture or clip region, even if they did not change themselves
+    let first_difference = old_state_changes.iter()
+        .zip(new_state_changes.iter())
+        .position(|(a, b)| a != b)
+        .unwrap_or(usize::min(old_state_changes.len(), new_state_changes.len()));
+
+    let mut changed_from = usize::MAX;
+    for change in old_state_changes[first_difference..].iter().chain(new_state_changes[first_difference..].iter()) {
+        if let StateCmd::Clear(_) = change.cmd {
+            return false;
+        }
+        changed_from = usize::min(changed_from, change.at_vertex);
+    }
+
+    let same = |a: &Vert, b: &Vert| a.pos == b.pos && a.uv == b.uv && a.color == b.color;
+
+    // The sdf shapes referenced by unchanged quads might have changed
+    let mut state_changes = new_state_changes.iter().peekable();
+    let mut drawing_shapes = false;
+
+    let len = usize::max(old_vertices.len(), new_vertices.len());
+    for start in (0..len).step_by(3) {
+        while let Some(change) = state_changes.peek().cloned() {
+            if change.at_vertex > start {
+                break;
+            }
+            if let StateCmd::TextureChange(sampler) = change.cmd {
+                drawing_shapes = match sampler {
+                    SamplerId::Shape => true,
+                    _ => false,
+                };
+            }
+            state_changes.next();
+        }
+
+        let old = &old_vertices[start.min(old_vertices.len())..(start + 3).min(old_vertices.len())];
+        let new = &new_vertices[start.min(new_vertices.len())..(start + 3).min(new_vertices.len())];
+
+        let mut changed = start >= changed_from ||
+            old.len() != new.len() ||
+            old.iter().zip(new.iter()).any(|(a, b)| !same(a, b));
+
+        if !changed && drawing_shapes {
+            let index = new[0].uv.x as usize;
+            changed = old_shapes.get(index) != new_shapes.get(index);
+        }
+
+        if changed {
+            for vert in old.iter().chain(new.iter()) {
+                damaged(vert);
+            }
+        }
+    }
+
+    true
+}
+
+// Limits a clip region to the damaged region, see `DrawGroup::set_damage_tracking`
+fn clip_to_damage(region: Region, damage: Option<Region>) -> Region {
+    match damage {
+        Some(damage) => {
+            let overlap = region.overlap(damage);
+            Region {
+                min: overlap.min,
+                max: Vec2::new(overlap.max.x.max(overlap.min.x), overlap.max.y.max(overlap.min.y)),
+            }
+        },
+        None => region,
+    }
+}
+
+// Stable sorts the vertices in a layer by their sort key. Clip, mask and clear commands split the
+// layer into segments, which are sorted individually. Each chunk of vertices gets its own texture
+// change, as the texture which was active when it was added might be different after sorting.
+fn sort_layer<TruetypeFontKey, BitmapFontKey, TexKey>(
+    vertices: &[Vert],
+    state_changes: &[StateChange<TruetypeFontKey, BitmapFontKey, TexKey>],
+    out_vertices: &mut Vec<Vert>,
+    out_state_changes: &mut Vec<StateChange<TruetypeFontKey, BitmapFontKey, TexKey>>,
+)
+  where TruetypeFontKey: Copy,
+        BitmapFontKey: Copy,
+        TexKey: Copy,
+{
+    out_vertices.clear();
+    out_state_changes.clear();
+
+    let mut segment: Vec<SortChunk<TruetypeFontKey, BitmapFontKey, TexKey>> = Vec::new();
+
+    let emit_segment = |
+        segment: &mut Vec<SortChunk<TruetypeFontKey, BitmapFontKey, TexKey>>,
+        out_vertices: &mut Vec<Vert>,
+        out_state_changes: &mut Vec<StateChange<TruetypeFontKey, BitmapFontKey, TexKey>>,
+    | {
+        // `sort_by_key` is stable, so chunks with the same key keep their order
+        segment.sort_by_key(|chunk| chunk.key);
+
+        for chunk in segment.drain(..) {
+            let base = out_vertices.len();
+
+            out_state_changes.push(StateChange {
+                at_vertex: base,
+                cmd: StateCmd::TextureChange(chunk.texture),
+            });
+            let range = chunk.first_state_change..(chunk.first_state_change + chunk.state_change_count);
+            for change in state_changes[range].iter() {
+                out_state_changes.push(StateChange {
+                    at_vertex: base + (change.at_vertex - chunk.start),
+                    cmd: change.cmd,
+                });
+            }
+
+            out_vertices.extend_from_slice(&vertices[chunk.start..chunk.end]);
+        }
+    };
+
+    let mut chunk = SortChunk {
+        key: 0,
+        texture: SamplerId::Solid,
+        start: 0,
+        end: 0,
+        first_state_change: 0,
+        state_change_count: 0,
+    };
+    let mut texture = SamplerId::Solid;
+
+    for (i, change) in state_changes.iter().enumerate() {
+        match change.cmd {
+            StateCmd::TextureChange(new_texture) => {
+                texture = new_texture;
+                chunk.state_change_count += 1;
+            },
+
+            StateCmd::SortKey(key) => {
+                chunk.end = change.at_vertex;
+                segment.push(chunk);
+
+                chunk = SortChunk {
+                    key, texture,
+                    start: change.at_vertex,
+                    end: change.at_vertex,
+                    first_state_change: i + 1,
+                    state_change_count: 0,
+                };
+            },
+
+            StateCmd::PushClip(..) | StateCmd::PopClip |
+            StateCmd::BeginMask | StateCmd::PushMask | StateCmd::PopMask |
+            StateCmd::Clear(..) =>
+            {
+                chunk.end = change.at_vertex;
+                let key = chunk.key;
+                segment.push(chunk);
+                emit_segment(&mut segment, out_vertices, out_state_changes);
+
+                out_state_changes.push(StateChange {
+                    at_vertex: out_vertices.len(),
+                    cmd: change.cmd,
+                });
+
+                chunk = SortChunk {
+                    key, texture,
+                    start: change.at_vertex,
+                    end: change.at_vertex,
+                    first_state_change: i + 1,
+                    state_change_count: 0,
+                };
+            },
+        }
+    }
+
+    chunk.end = vertices.len();
+    segment.push(chunk);
+    emit_segment(&mut segment, out_vertices, out_state_changes);
+}
+
+// Points along the outline of a rounded aabb, in clockwise order (y-down). The first point is
+// not repeated at the end.
+fn rounded_aabb_outline(min: Vec2<f32>, max: Vec2<f32>, corner_radius: f32) -> Vec<Vec2<f32>> {
+    let r = corner_radius
+        .min((max.x - min.x) / 2.0)
+        .min((max.y - min.y) / 2.0);
+
+    let top_left = Vec2::new(min.x + r, min.y + r);
+    let top_right = Vec2::new(max.x - r, min.y + r);
+    let bottom_right = Vec2::new(max.x - r, max.y - r);
+    let bottom_left = Vec2::new(min.x + r, max.y - r);
+
+    let mut points: Vec<Vec2<f32>> = Vec::with_capacity(SIN_COS.len() * 4);
+    {
+        let mut push = |p: Vec2<f32>| {
+            // Corners touch when the radius is half the size of the aabb
+            if points.last().map_or(true, |&last| (p - last).len() > 1e-4) {
+                points.push(p);
+            }
+        };
+
+        for a in SIN_COS.iter() { push(top_left + Vec2::new(-a.x, -a.y)*r); }
+        for a in SIN_COS.iter() { push(top_right + Vec2::new(a.y, -a.x)*r); }
+        for a in SIN_COS.iter() { push(bottom_right + Vec2::new(a.x, a.y)*r); }
+        for a in SIN_COS.iter() { push(bottom_left + Vec2::new(-a.y, a.x)*r); }
+    }
+
+    if points.len() > 1 && (points[0] - points[points.len() - 1]).len() <= 1e-4 {
+        points.pop();
     }
+
+    points
+}
+
+// The number of segments needed to approximate an arc with the given radius and angle, so that
+// the segments never deviate more than a quarter pixel from the actual arc.
+fn segment_count(radius: f32, angle: f32) -> usize {
+    const MAX_ERROR: f32 = 0.25;
+    const MAX_SEGMENTS: usize = 512;
+
+    let radius = radius.abs();
+    let angle = angle.abs();
+
+    // At least one segment per quarter turn, so small shapes still look somewhat round
+    let min_segments = (angle / (f32::consts::PI / 2.0)).ceil().max(1.0) as usize;
+    if radius <= MAX_ERROR {
+        return min_segments;
+    }
+
+    let segment_angle = 2.0 * (1.0 - MAX_ERROR / radius).acos();
+    let segments = (angle / segment_angle).ceil() as usize;
+    segments.max(min_segments).min(MAX_SEGMENTS)
 }
 
+/// Eight evenly spaced unit vectors
+const RING: [Vec2<f32>; 8] = [
+    Vec2 { x:  1.00000000, y:  0.00000000 },
+    Vec2 { x:  0.70710677, y:  0.70710677 },
+    Vec2 { x:  0.00000000, y:  1.00000000 },
+    Vec2 { x: -0.70710677, y:  0.70710677 },
+    Vec2 { x: -1.00000000, y:  0.00000000 },
+    Vec2 { x: -0.70710677, y: -0.70710677 },
+    Vec2 { x:  0.00000000, y: -1.00000000 },
+    Vec2 { x:  0.70710677, y: -0.70710677 },
+];
+
 /// For angles from 0 to π/2
 const SIN_COS: [Vec2<f32>; 11] = [
     Vec2 { x: 1.00000000, y: 0.00000000 },
@@ -970,44 +3068,68 @@ pub struct Vert {
     pub color: Color,
 }
 
+// Parameters of a shape drawn with `DrawGroup::sdf_rounded_aabb` or `DrawGroup::sdf_circle`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ShapeInstance {
+    rect: Vec4<f32>, // center.x, center.y, half_size.x, half_size.y
+    params: Vec4<f32>, // corner radius, border width, blur, unused
+    border_color: Color,
+}
+
+// Read as three `vec4` texels per shape
+impl VertexData for ShapeInstance {
+    type Primitive = f32;
+}
+
 // We cannot use the custom derive from within this crate :/
 impl Vertex for Vert {
     fn setup_attrib_pointers(divisor: usize) {
+        for (_, mut binding) in Vert::attrib_bindings() {
+            binding.divisor = divisor;
+            binding.enable();
+        }
+    }
+
+    fn attrib_bindings() -> Vec<(&'static str, AttribBinding)> {
         use std::mem;
 
         use gl;
 
         let stride = mem::size_of::<Vert>();
         let mut offset = 0;
+        let divisor = 0;
 
-        AttribBinding {
+        let pos = AttribBinding {
             index: 0,
             primitives: 2,
             primitive_type: gl::FLOAT,
             normalized: false,
             integer: false,
             stride, offset, divisor,
-        }.enable();
+        };
         offset += mem::size_of::<Vec2<f32>>();
 
-        AttribBinding {
+        let uv = AttribBinding {
             index: 1,
             primitives: 2,
             primitive_type: gl::FLOAT,
             normalized: false,
             integer: false,
             stride, offset, divisor,
-        }.enable();
+        };
         offset += mem::size_of::<Vec2<f32>>();
 
-        AttribBinding {
+        let color = AttribBinding {
             index: 2,
             primitives: 4,
             primitive_type: gl::FLOAT,
             normalized: false,
             integer: false,
             stride, offset, divisor,
-        }.enable();
+        };
+
+        vec![("pos", pos), ("uv", uv), ("color", color)]
     }
 
     // Not used, we manualy declare inputs in the shader
@@ -1017,43 +3139,127 @@ impl Vertex for Vert {
     fn set_as_vertex_attrib(&self) {}
 }
 
+// `COLOR_FUNCTIONS` is replaced with `ColorMode::glsl_functions`
 const VERT_SRC: &'static str = "
     #version 330 core
 
+    COLOR_FUNCTIONS
+
     layout(location = 0) in vec2 in_pos;
     layout(location = 1) in vec2 in_uv;
     layout(location = 2) in vec4 in_color;
 
     out vec4 v_color;
     out vec2 v_uv;
+    out vec2 v_pos;
+    out float v_luminance;
 
     uniform mat4 transform;
     uniform float layer = 0.0;
 
     void main() {
         gl_Position = transform * vec4(in_pos, layer, 1.0);
-        v_color = in_color;
+        v_color = vertex_color(in_color);
         v_uv = in_uv;
+        v_pos = in_pos;
+        v_luminance = dot(straight_rgb(in_color), vec3(0.2126, 0.7152, 0.0722));
     }
 ";
 
 const FRAG_SRC: &'static str = "
     #version 330 core
+    TEXT_DEFINES
 
     in vec2 v_uv;
+    in vec2 v_pos;
     in vec4 v_color;
+    in float v_luminance;
 
-    out vec4 color;
+    layout(location = 0, index = 0) out vec4 color;
+    #ifdef SUBPIXEL
+    layout(location = 0, index = 1) out vec4 blend_weights;
+    #endif
 
     uniform sampler2D texture_sampler;
+    // Set while drawing truetype glyphs
+    uniform int glyphs = 0;
+    uniform float text_gamma = 1.0;
+    // Set while drawing sdf shapes, whose parameters are read from `shape_data`
+    uniform int shapes = 0;
+    uniform samplerBuffer shape_data;
+
+    COLOR_FUNCTIONS
+
+    // See `DrawGroup::set_text_gamma`
+    vec3 adjust_coverage(vec3 coverage) {
+        return pow(coverage, vec3(pow(text_gamma, 1.0 - 2.0*v_luminance)));
+    }
+
+    // Signed distance to the edge of a box centered at the origin, negative inside
+    float rounded_box(vec2 p, vec2 half_size, float radius) {
+        vec2 q = abs(p) - half_size + radius;
+        return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - radius;
+    }
+
+    // Smoothly goes from one inside to zero outside, over `width` units around the edge
+    float inside(float dist, float width) {
+        return 1.0 - smoothstep(-0.5*width, 0.5*width, dist);
+    }
 
     void main() {
-        color = v_color * texture(texture_sampler, v_uv);
+        vec4 texel = texture(texture_sampler, v_uv);
+
+        if (shapes != 0) {
+            // See `ShapeInstance`
+            int index = int(v_uv.x + 0.5);
+            vec4 rect   = texelFetch(shape_data, index*3 + 0);
+            vec4 params = texelFetch(shape_data, index*3 + 1);
+            vec4 border = texelFetch(shape_data, index*3 + 2);
+
+            float dist = rounded_box(v_pos - rect.xy, rect.zw, params.x);
+            float aa = max(fwidth(dist), 0.0001);
+
+            float coverage = inside(dist, max(aa, params.z));
+            float fill = inside(dist + params.y, aa);
+            color = mix(vertex_color(border), v_color, fill) * texel_color(vec4(1.0, 1.0, 1.0, coverage));
+        } else if (glyphs == 0) {
+            color = v_color * texel_color(texel);
+        } else {
+    #ifdef SUBPIXEL
+            // Blended with `SUBPIXEL_BLEND_SETTINGS`
+            vec3 coverage = adjust_coverage(texel.rgb) * v_color.a;
+            color = vec4(straight_rgb(v_color), (coverage.r + coverage.g + coverage.b) / 3.0);
+            blend_weights = vec4(coverage, color.a);
+            return;
+    #else
+            float coverage = adjust_coverage(vec3(texel.a)).x;
+            color = v_color * texel_color(vec4(1.0, 1.0, 1.0, coverage));
+    #endif
+        }
+
+    #ifdef SUBPIXEL
+        blend_weights = vec4(color.a);
+    #endif
     }
 ";
 
-fn build_shader() -> Shader {
-    let proto = ShaderPrototype::new_prototype(VERT_SRC, "", FRAG_SRC);
+// Blends the text color with the per channel coverage output as the second color from the
+// fragment shader
+const SUBPIXEL_BLEND_SETTINGS: BlendSettings = BlendSettings {
+    src_color: BlendFactor::Src1Color,
+    dst_color: BlendFactor::OneMinusSrc1Color,
+    src_alpha: BlendFactor::One,
+    dst_alpha: BlendFactor::OneMinusSrcAlpha,
+    function:  BlendFunction::Add,
+};
+
+fn build_shader(color_mode: ColorMode, subpixel_text: bool) -> Shader {
+    let functions = color_mode.glsl_functions();
+    let defines = if subpixel_text { "#define SUBPIXEL" } else { "" };
+    let vert_src = VERT_SRC.replace("COLOR_FUNCTIONS", functions);
+    let frag_src = FRAG_SRC.replace("COLOR_FUNCTIONS", functions).replace("TEXT_DEFINES", defines);
+
+    let proto = ShaderPrototype::new_prototype(&vert_src, "", &frag_src);
     match proto.build() {
         Ok(shader) => {
             shader
@@ -1067,3 +3273,166 @@ fn build_shader() -> Shader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_keys() {
+        let vert = |x: f32| Vert { pos: Vec2::new(x, 0.0), uv: Vec2::ZERO, color: Color::WHITE };
+        let change = |at_vertex, cmd| StateChange::<u32, u32, u32> { at_vertex, cmd };
+        let region = Region { min: Vec2::ZERO, max: Vec2::new(1.0, 1.0) };
+
+        let vertices = (0..5).map(|i| vert(i as f32)).collect::<Vec<_>>();
+        let state_changes = vec![
+            change(0, StateCmd::SortKey(2)),
+            change(0, StateCmd::TextureChange(SamplerId::Texture(7))),
+            change(1, StateCmd::SortKey(1)),
+            change(2, StateCmd::TextureChange(SamplerId::Solid)),
+            change(3, StateCmd::PushClip(region)),
+            change(3, StateCmd::SortKey(0)),
+            change(4, StateCmd::SortKey(1)),
+        ];
+
+        let mut out_vertices = Vec::new();
+        let mut out_state_changes = Vec::new();
+        sort_layer(&vertices, &state_changes, &mut out_vertices, &mut out_state_changes);
+
+        let order = out_vertices.iter().map(|v| v.pos.x as usize).collect::<Vec<_>>();
+        assert_eq!(order, vec![1, 2, 0, 3, 4]);
+
+        // Each chunk starts with the texture it was added with
+        assert!(out_state_changes.contains(&change(0, StateCmd::TextureChange(SamplerId::Texture(7)))));
+        assert!(out_state_changes.contains(&change(2, StateCmd::TextureChange(SamplerId::Texture(7)))));
+        assert!(out_state_changes.contains(&change(1, StateCmd::TextureChange(SamplerId::Solid))));
+        assert!(out_state_changes.contains(&change(3, StateCmd::PushClip(region))));
+    }
+
+    #[test]
+    fn software_rasterization() {
+        let red = Color::rgb(1.0, 0.0, 0.0);
+        let blue = Color::rgba(0.0, 0.0, 1.0, 0.5);
+
+        let mut group = DrawGroup::<u32, u32, u32>::new_software(ColorMode::Straight);
+        let checker = Canvas::from_rgba(Vec2::new(2, 1), &[0, 255, 0, 255, 255, 255, 255, 255]);
+        group.include_canvas_texture(0, checker);
+
+        group.push_state_cmd(StateCmd::Clear(Color::rgb(0.0, 0.0, 0.0)));
+        group.aabb(Vec2::new(0.0, 0.0), Vec2::new(4.0, 4.0), red);
+        group.aabb(Vec2::new(2.0, 2.0), Vec2::new(6.0, 6.0), blue);
+
+        let clip = Region { min: Vec2::new(0.0, 6.0), max: Vec2::new(8.0, 7.0) };
+        group.push_state_cmd(StateCmd::PushClip(clip));
+        group.textured_aabb(0, Vec2::new(0.0, 0.0), Vec2::new(8.0, 8.0));
+        group.push_state_cmd(StateCmd::PopClip);
+
+        let mut canvas = Canvas::new(Vec2::new(8, 8));
+        group.rasterize(Mat4::ortho(0.0, 8.0, 0.0, 8.0, -1.0, 1.0), &mut canvas);
+
+        assert_eq!(canvas.pixel(1, 1), [255, 0, 0, 255]);
+        assert_eq!(canvas.pixel(3, 3), [128, 0, 128, 255]);
+        assert_eq!(canvas.pixel(5, 5), [0, 0, 128, 255]);
+        assert_eq!(canvas.pixel(7, 0), [0, 0, 0, 255]);
+        // Only the row inside the clip region is textured
+        assert_eq!(canvas.pixel(1, 6), [0, 255, 0, 255]);
+        assert_eq!(canvas.pixel(6, 6), [255, 255, 255, 255]);
+        assert_eq!(canvas.pixel(1, 7), [0, 0, 0, 255]);
+
+        // Adjacent triangles do not overlap, so a translucent quad is blended evenly
+        let total = canvas.pixels().iter().filter(|&&p| p == [0, 0, 128, 255]).count();
+        assert_eq!(total, 16 - 4);
+    }
+
+    #[test]
+    fn srgb_rasterization() {
+        let mut group = DrawGroup::<u32, u32, u32>::new_software(ColorMode::Srgb);
+        group.push_state_cmd(StateCmd::Clear(Color::rgb(0.0, 0.0, 0.0)));
+        group.aabb(Vec2::new(0.0, 0.0), Vec2::new(2.0, 2.0), Color::rgb(0.5, 0.5, 0.5));
+        group.aabb(Vec2::new(2.0, 0.0), Vec2::new(4.0, 2.0), Color::rgba(1.0, 1.0, 1.0, 0.5));
+
+        let mut canvas = Canvas::new(Vec2::new(4, 2));
+        group.rasterize(Mat4::ortho(0.0, 4.0, 0.0, 2.0, -1.0, 1.0), &mut canvas);
+
+        // Opaque colors come out as given, blending happens in linear space
+        assert_eq!(canvas.pixel(0, 0), [128, 128, 128, 255]);
+        assert_eq!(canvas.pixel(3, 1), [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn invalid_stipples() {
+        let mut group = DrawGroup::<u32, u32, u32>::new_software(ColorMode::Straight);
+        let (min, max) = (Vec2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+
+        group.stippled_line_aabb(min, max, 1.0, 2.0, -1.0, Color::WHITE);
+        group.stippled_line_aabb(min, max, 1.0, 0.0, 2.0, Color::WHITE);
+        group.stippled_line_aabb(min, max, 1.0, f32::NAN, 2.0, Color::WHITE);
+        group.stippled_line_aabb(min, max, 1.0, 2.0, f32::INFINITY, Color::WHITE);
+        assert!(group.layers[0].vertices.is_empty());
+
+        // Segments of infinite length are skipped
+        group.stippled_line_aabb(min, Vec2::new(f32::INFINITY, 10.0), 1.0, 2.0, 2.0, Color::WHITE);
+
+        group.stippled_line_aabb(min, max, 1.0, 2.0, 0.0, Color::WHITE);
+        assert!(!group.layers[0].vertices.is_empty());
+    }
+
+    #[test]
+    fn adaptive_segments() {
+        let full = 2.0*f32::consts::PI;
+
+        assert_eq!(segment_count(0.1, full), 4);
+        assert_eq!(segment_count(10.0, 0.01), 1);
+        assert!(segment_count(100.0, full) > segment_count(10.0, full));
+        assert_eq!(segment_count(1e9, full), 512);
+    }
+
+    #[test]
+    fn sdf_shapes() {
+        let mut group = DrawGroup::<u32, u32, u32>::new_software(ColorMode::Straight);
+        let style = ShapeStyle::new(Color::WHITE).with_shadow(Vec2::new(1.0, 1.0), Color::BLACK, 2.0);
+        group.sdf_rounded_aabb(Vec2::new(0.0, 0.0), Vec2::new(10.0, 4.0), 5.0, style);
+
+        // The shadow is drawn first, and the corner radius is clamped to the size of the box
+        assert_eq!(group.shapes.len(), 2);
+        assert_eq!(group.shapes[0].rect, Vec4::new(6.0, 3.0, 5.0, 2.0));
+        assert_eq!(group.shapes[1].params.x, 2.0);
+
+        // Quads extend past the shape by the blur, and one unit for antialiasing
+        let vertices = &group.layers[0].vertices;
+        assert_eq!(vertices.len(), 12);
+        assert_eq!(vertices[0].pos, Vec2::new(-2.0, -2.0));
+        assert_eq!(vertices[2].pos, Vec2::new(14.0, 8.0));
+        assert_eq!(vertices[6].pos, Vec2::new(-1.0, -1.0));
+        assert_eq!(vertices[6].uv.x, 1.0);
+    }
+
+    #[test]
+    fn damage() {
+        let mut group = DrawGroup::<u32, u32, u32>::new_software(ColorMode::Straight);
+        group.set_damage_tracking(true);
+        let win_size = Vec2::new(128.0, 128.0);
+        let transform = Mat4::ortho(0.0, 128.0, 0.0, 128.0, -1.0, 1.0);
+
+        let frame = |group: &mut DrawGroup<u32, u32, u32>, x: f32, color: Color| {
+            group.reset();
+            group.aabb(Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0), Color::WHITE);
+            group.aabb(Vec2::new(x, 50.0), Vec2::new(x + 10.0, 60.0), color);
+            group.damage(transform, win_size)
+        };
+
+        let window = Region { min: Vec2::ZERO, max: win_size };
+        assert_eq!(frame(&mut group, 40.0, Color::WHITE), Some(window));
+        assert_eq!(frame(&mut group, 40.0, Color::WHITE), None);
+
+        // Both the old and the new position are damaged, with a margin for antialiasing
+        let moved = Region { min: Vec2::new(39.0, 49.0), max: Vec2::new(56.0, 61.0) };
+        assert_eq!(frame(&mut group, 45.0, Color::WHITE), Some(moved));
+
+        let recolored = Region { min: Vec2::new(44.0, 49.0), max: Vec2::new(56.0, 61.0) };
+        assert_eq!(frame(&mut group, 45.0, Color::BLACK), Some(recolored));
+
+        group.invalidate_damage();
+        assert_eq!(frame(&mut group, 45.0, Color::BLACK), Some(window));
+    }
+}
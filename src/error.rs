@@ -0,0 +1,82 @@
+
+//! A single error type covering all the things which can go wrong in this crate, see [`Error`].
+//!
+//! [`Error`]: enum.Error.html
+
+use std::io;
+use std::fmt;
+use std::error;
+
+use texture::TextureError;
+use shader::ShaderError;
+use framebuffer::FramebufferError;
+use window::WindowError;
+#[cfg(feature = "audio")]
+use audio::wav::WavError;
+
+/// Wraps the error types of the different modules in this crate. All of them convert into this
+/// type, so functions which for example load both textures and shaders can use `?` with a return
+/// type of `Result<_, gondola::Error>`.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Texture(TextureError),
+    Shader(ShaderError),
+    Framebuffer(FramebufferError),
+    Window(WindowError),
+    #[cfg(feature = "audio")]
+    Wav(WavError),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err)          => Some(err),
+            Error::Texture(ref err)     => Some(err),
+            Error::Shader(ref err)      => Some(err),
+            Error::Framebuffer(ref err) => Some(err),
+            Error::Window(ref err)      => Some(err),
+            #[cfg(feature = "audio")]
+            Error::Wav(ref err)         => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err)          => write!(f, "IO error: {}", err),
+            Error::Texture(ref err)     => write!(f, "Texture error: {}", err),
+            Error::Shader(ref err)      => write!(f, "Shader error: {}", err),
+            Error::Framebuffer(ref err) => fmt::Display::fmt(err, f),
+            Error::Window(ref err)      => fmt::Display::fmt(err, f),
+            #[cfg(feature = "audio")]
+            Error::Wav(ref err)         => write!(f, "Wav error: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error { Error::Io(err) }
+}
+
+impl From<TextureError> for Error {
+    fn from(err: TextureError) -> Error { Error::Texture(err) }
+}
+
+impl From<ShaderError> for Error {
+    fn from(err: ShaderError) -> Error { Error::Shader(err) }
+}
+
+impl From<FramebufferError> for Error {
+    fn from(err: FramebufferError) -> Error { Error::Framebuffer(err) }
+}
+
+impl From<WindowError> for Error {
+    fn from(err: WindowError) -> Error { Error::Window(err) }
+}
+
+#[cfg(feature = "audio")]
+impl From<WavError> for Error {
+    fn from(err: WavError) -> Error { Error::Wav(err) }
+}
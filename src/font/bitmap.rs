@@ -1,7 +1,12 @@
 
+use std::io::{self, Read, Write};
+
+use rusttype::{self, Scale, point};
+
 use cable_math::Vec2;
 
-use texture::Texture;
+use texture::{Texture, TextureFormat, SwizzleComp};
+use pack::{read_u32, write_u32};
 
 pub struct BitmapFont {
     pub texture: Texture,
@@ -13,6 +18,9 @@ pub struct BitmapFont {
     pub unkown_glyph_substitute: u32,
 
     pub char_size: Vec2<u32>,
+    /// The horizontal advance of each glyph, starting at `first_glyph`. If this is empty, all
+    /// glyphs advance by `char_size.x`.
+    pub glyph_advances: Vec<u32>,
 }
 
 impl BitmapFont {
@@ -56,7 +64,305 @@ impl BitmapFont {
             callback(offset + Vec2::new(size.x, size.y), uv + Vec2::new(uv_size.x, uv_size.y));
             callback(offset + Vec2::new(0.0, size.y),    uv + Vec2::new(0.0, uv_size.y));
 
-            offset.x += self.char_size.x as f32;
+            let advance = self.glyph_advances.get(index as usize).cloned().unwrap_or(self.char_size.x);
+            offset.x += advance as f32;
         }
     }
 }
+
+/// A bitmap font which has been baked from a truetype font, but not yet uploaded to the GPU.
+/// This can be created and saved without a OpenGL context, e.g. in a build script, and later be
+/// loaded and turned into a [`BitmapFont`].
+///
+/// # Example
+/// ```rust,no_run
+/// use gondola::font::BakedBitmapFont;
+/// use std::fs::File;
+///
+/// // In a build script
+/// let data = std::fs::read("assets/font.ttf").unwrap();
+/// let baked = BakedBitmapFont::bake(data, 16.0, 32, 95).unwrap();
+/// baked.write(File::create("assets/font.bmf").unwrap()).unwrap();
+///
+/// // In the game
+/// let baked = BakedBitmapFont::read(File::open("assets/font.bmf").unwrap()).unwrap();
+/// let font = baked.to_bitmap_font();
+/// ```
+///
+/// [`BitmapFont`]: struct.BitmapFont.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct BakedBitmapFont {
+    /// Coverage values, one byte per pixel, row by row.
+    pub pixels: Vec<u8>,
+    pub size: Vec2<u32>,
+
+    pub first_glyph: u32,
+    pub glyph_count: u32,
+    pub tile_size: Vec2<u32>,
+    pub tile_count: Vec2<u32>,
+    pub unkown_glyph_substitute: u32,
+    pub char_size: Vec2<u32>,
+    pub glyph_advances: Vec<u32>,
+}
+
+const MAGIC: &[u8; 4] = b"GBMF";
+const VERSION: u32 = 1;
+
+// Glyphs are layed out in rows of this many tiles
+const TILES_PER_ROW: u32 = 16;
+
+impl BakedBitmapFont {
+    /// Rasterizes the glyphs for the characters `first_glyph .. first_glyph + glyph_count` of
+    /// the given truetype or opentype font at the given size in pixels. Returns `None` if the data
+    /// does not contain a valid font. Characters past `char::MAX` are left out. See also
+    /// `TruetypeFont::bake_bitmap_font`.
+    ///
+    /// Kerning is not preserved, and the parts of glyphs which extend to the left of their origin
+    /// (e.g. in some italic fonts) are clipped.
+    pub fn bake<B>(font_data: B, size: f32, first_glyph: u32, glyph_count: u32) -> Option<BakedBitmapFont>
+      where B: Into<rusttype::SharedBytes<'static>>,
+    {
+        let font = rusttype::FontCollection::from_bytes(font_data).font_at(0)?;
+        Some(bake(&font, size, first_glyph, glyph_count))
+    }
+
+    /// Uploads the baked glyphs to a texture. This requires a OpenGL context.
+    pub fn to_bitmap_font(&self) -> BitmapFont {
+        let mut texture = Texture::new();
+        texture.load_data(&self.pixels, self.size.x, self.size.y, TextureFormat::R_8);
+        texture.set_swizzle_mask((SwizzleComp::One, SwizzleComp::One, SwizzleComp::One, SwizzleComp::Red));
+
+        BitmapFont {
+            texture,
+            first_glyph: self.first_glyph,
+            glyph_count: self.glyph_count,
+            tile_size: self.tile_size,
+            tile_count: self.tile_count,
+            unkown_glyph_substitute: self.unkown_glyph_substitute,
+            char_size: self.char_size,
+            glyph_advances: self.glyph_advances.clone(),
+        }
+    }
+
+    /// Writes this font in a simple binary format, which can be read with `BakedBitmapFont::read`.
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u32(&mut out, VERSION)?;
+
+        let values = [
+            self.size.x, self.size.y,
+            self.first_glyph, self.glyph_count,
+            self.tile_size.x, self.tile_size.y,
+            self.tile_count.x, self.tile_count.y,
+            self.unkown_glyph_substitute,
+            self.char_size.x, self.char_size.y,
+            self.glyph_advances.len() as u32,
+        ];
+        for &value in values.iter() {
+            write_u32(&mut out, value)?;
+        }
+        for &advance in self.glyph_advances.iter() {
+            write_u32(&mut out, advance)?;
+        }
+
+        out.write_all(&self.pixels)?;
+        out.flush()
+    }
+
+    /// Reads a font written by `BakedBitmapFont::write`.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<BakedBitmapFont> {
+        let invalid_data = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a baked bitmap font"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            let message = format!("Unsupported bitmap font version {}, expected {}", version, VERSION);
+            return Err(invalid_data(&message));
+        }
+
+        let mut values = [0u32; 12];
+        for value in values.iter_mut() {
+            *value = read_u32(&mut reader)?;
+        }
+
+        let size = Vec2::new(values[0], values[1]);
+        let glyph_count = values[3];
+        let tile_count = Vec2::new(values[6], values[7]);
+        let advance_count = values[11];
+
+        if advance_count != 0 && advance_count != glyph_count {
+            return Err(invalid_data("Number of glyph advances does not match number of glyphs"));
+        }
+        let tiles = match tile_count.x.checked_mul(tile_count.y) {
+            Some(tiles) => tiles,
+            None => return Err(invalid_data("Invalid tile count")),
+        };
+        if glyph_count > tiles {
+            return Err(invalid_data("Not enough tiles for all glyphs"));
+        }
+
+        let tile_size = Vec2::new(values[4], values[5]);
+        let tiles_fit =
+            tile_count.x.checked_mul(tile_size.x).map_or(false, |width| width <= size.x) &&
+            tile_count.y.checked_mul(tile_size.y).map_or(false, |height| height <= size.y);
+        if !tiles_fit {
+            return Err(invalid_data("Tiles extend past the edge of the bitmap"));
+        }
+
+        // Values read so far are not trusted for preallocating, the data might be truncated
+        let mut glyph_advances = Vec::new();
+        for _ in 0..advance_count {
+            glyph_advances.push(read_u32(&mut reader)?);
+        }
+
+        let pixel_count = match (size.x as usize).checked_mul(size.y as usize) {
+            Some(count) => count,
+            None => return Err(invalid_data("Invalid bitmap size")),
+        };
+        let mut pixels = Vec::new();
+        reader.take(pixel_count as u64).read_to_end(&mut pixels)?;
+        if pixels.len() != pixel_count {
+            return Err(invalid_data("Truncated pixel data"));
+        }
+
+        Ok(BakedBitmapFont {
+            pixels,
+            size,
+            first_glyph: values[2],
+            glyph_count,
+            tile_size,
+            tile_count,
+            unkown_glyph_substitute: values[8],
+            char_size: Vec2::new(values[9], values[10]),
+            glyph_advances,
+        })
+    }
+}
+
+pub(super) fn bake(font: &rusttype::Font, size: f32, first_glyph: u32, glyph_count: u32) -> BakedBitmapFont {
+    // There are no characters past `char::MAX`, so the range is cut off there
+    let last_char = ::std::char::MAX as u32 + 1;
+    let glyph_count = match first_glyph.checked_add(glyph_count) {
+        Some(end) if end <= last_char => glyph_count,
+        _ => last_char.saturating_sub(first_glyph),
+    };
+
+    let scale = Scale::uniform(size);
+    let v_metrics = font.v_metrics(scale);
+    let ascent = v_metrics.ascent.ceil();
+    let line_height = (ascent - v_metrics.descent.floor()) as u32;
+
+    let glyphs = (first_glyph .. first_glyph + glyph_count)
+        .map(|c| {
+            ::std::char::from_u32(c)
+                .and_then(|c| font.glyph(c))
+                .map(|glyph| glyph.scaled(scale).positioned(point(0.0, ascent)))
+        })
+        .collect::<Vec<_>>();
+
+    let glyph_advances = glyphs.iter()
+        .map(|glyph| glyph.as_ref().map_or(0, |g| g.unpositioned().h_metrics().advance_width.round() as u32))
+        .collect::<Vec<u32>>();
+
+    let mut tile_width = glyph_advances.iter().cloned().max().unwrap_or(0);
+    for glyph in glyphs.iter().filter_map(Option::as_ref) {
+        if let Some(bounds) = glyph.pixel_bounding_box() {
+            tile_width = tile_width.max(bounds.max.x.max(0) as u32);
+        }
+    }
+    let tile_size = Vec2::new(tile_width.max(1), line_height.max(1));
+
+    let tile_count = Vec2::new(
+        TILES_PER_ROW.min(glyph_count.max(1)),
+        (glyph_count + TILES_PER_ROW - 1) / TILES_PER_ROW,
+    );
+    let size = Vec2::new(tile_count.x * tile_size.x, tile_count.y.max(1) * tile_size.y);
+
+    let mut pixels = vec![0u8; (size.x * size.y) as usize];
+
+    for (index, glyph) in glyphs.iter().enumerate() {
+        let glyph = match *glyph {
+            Some(ref glyph) => glyph,
+            None => continue,
+        };
+        let bounds = match glyph.pixel_bounding_box() {
+            Some(bounds) => bounds,
+            None => continue, // E.g. spaces
+        };
+
+        let index = index as u32;
+        let tile_min = Vec2::new(
+            (index % tile_count.x) * tile_size.x,
+            (index / tile_count.x) * tile_size.y,
+        );
+
+        glyph.draw(|x, y, coverage| {
+            let x = bounds.min.x + x as i32;
+            let y = bounds.min.y + y as i32;
+            if x < 0 || y < 0 || x >= tile_size.x as i32 || y >= tile_size.y as i32 {
+                return;
+            }
+
+            let x = tile_min.x + x as u32;
+            let y = tile_min.y + y as u32;
+            pixels[(x + y*size.x) as usize] = (coverage * 255.0).round() as u8;
+        });
+    }
+
+    let unkown_glyph_substitute = ('?' as u32)
+        .checked_sub(first_glyph)
+        .filter(|&index| index < glyph_count)
+        .unwrap_or(0);
+
+    BakedBitmapFont {
+        pixels,
+        size,
+        first_glyph,
+        glyph_count,
+        tile_size,
+        tile_count,
+        unkown_glyph_substitute,
+        char_size: Vec2::new(tile_size.x, line_height),
+        glyph_advances,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let font = BakedBitmapFont {
+            pixels: (0..24).collect(),
+            size: Vec2::new(6, 4),
+            first_glyph: 32,
+            glyph_count: 3,
+            tile_size: Vec2::new(2, 4),
+            tile_count: Vec2::new(3, 1),
+            unkown_glyph_substitute: 0,
+            char_size: Vec2::new(2, 4),
+            glyph_advances: vec![1, 2, 2],
+        };
+
+        let mut bytes = Vec::new();
+        font.write(&mut bytes).unwrap();
+        assert_eq!(BakedBitmapFont::read(bytes.as_slice()).unwrap(), font);
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(BakedBitmapFont::read(truncated).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Huge sizes are rejected instead of being allocated
+        let mut huge = bytes.clone();
+        huge[8..16].copy_from_slice(&[0xff; 8]);
+        assert_eq!(BakedBitmapFont::read(huge.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        bytes[0] = b'X';
+        assert!(BakedBitmapFont::read(bytes.as_slice()).is_err());
+    }
+}
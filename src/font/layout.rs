@@ -0,0 +1,295 @@
+
+//! Caret and selection positioning for editable text, see [`TextLayout`].
+//!
+//! [`TextLayout`]: struct.TextLayout.html
+
+use std::ops::Range;
+
+use rusttype::{self, Scale, GlyphId};
+
+use cable_math::Vec2;
+
+use Region;
+use super::truetype::TAB_WIDTH;
+use super::line_break;
+
+/// The position of each cluster of a piece of text, as returned by
+/// [`TruetypeFont::layout`]. This is used to position carets and draw selections in editable
+/// text. All positions are relative to the baseline of the first line at the start of the text,
+/// which is the same as the `offset` passed when drawing text.
+///
+/// Text is laid out in logical order, without the reordering done by
+/// [`font::apply_presentation_forms`], so positions are only correct for left-to-right text.
+///
+/// [`TruetypeFont::layout`]: struct.TruetypeFont.html#method.layout
+/// [`font::apply_presentation_forms`]: fn.apply_presentation_forms.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    /// All clusters in the text, in order.
+    pub clusters: Vec<Cluster>,
+    /// The distance from the baseline to the top of a line. Usually positive.
+    pub ascent: f32,
+    /// The distance from the baseline to the bottom of a line. Usually negative.
+    pub descent: f32,
+    /// The distance between the baselines of two consecutive lines.
+    pub line_height: f32,
+    /// The length of the text in bytes.
+    pub len: usize,
+    /// Where the caret is placed after the last cluster.
+    pub end: Vec2<f32>,
+    /// The line on which `end` lies.
+    pub end_line: usize,
+}
+
+/// A single base character together with any combining marks which follow it. A caret can not be
+/// placed inside a cluster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    /// The byte range of this cluster in the text.
+    pub range: Range<usize>,
+    /// The position of the caret before this cluster, on the baseline.
+    pub pos: Vec2<f32>,
+    /// The horizontal advance of this cluster, including kerning.
+    pub advance: f32,
+    /// The index of the line this cluster is on.
+    pub line: usize,
+}
+
+impl TextLayout {
+    /// Finds the cluster containing the given byte index. Indices past the end of the text map to
+    /// the end of the text, which is `None`.
+    fn cluster_at(&self, index: usize) -> Option<&Cluster> {
+        self.clusters.iter().find(|cluster| index < cluster.range.end)
+    }
+
+    /// The baseline position of a caret placed before the given byte index. Indices inside a
+    /// cluster are moved to the start of that cluster.
+    pub fn caret_position(&self, index: usize) -> Vec2<f32> {
+        match self.cluster_at(index) {
+            Some(cluster) => cluster.pos,
+            None => self.end,
+        }
+    }
+
+    /// The line on which a caret placed before the given byte index lies.
+    pub fn caret_line(&self, index: usize) -> usize {
+        match self.cluster_at(index) {
+            Some(cluster) => cluster.line,
+            None => self.end_line,
+        }
+    }
+
+    /// A rectangle spanning the height of the line, for drawing a caret before the given byte
+    /// index. `width` is the width of the caret.
+    pub fn caret_rect(&self, index: usize, width: f32) -> Region {
+        let pos = self.caret_position(index);
+        Region {
+            min: Vec2::new(pos.x, pos.y - self.ascent),
+            max: Vec2::new(pos.x + width, pos.y - self.descent),
+        }
+    }
+
+    /// The rectangles covering the given byte range, one for each line the range spans. Empty
+    /// ranges give no rectangles.
+    pub fn selection_rects(&self, range: Range<usize>) -> Vec<Region> {
+        let mut rects: Vec<(usize, Region)> = Vec::new();
+
+        for cluster in self.clusters.iter() {
+            if cluster.range.end <= range.start || cluster.range.start >= range.end {
+                continue;
+            }
+
+            let min = Vec2::new(cluster.pos.x, cluster.pos.y - self.ascent);
+            let max = Vec2::new(cluster.pos.x + cluster.advance, cluster.pos.y - self.descent);
+
+            match rects.last_mut() {
+                Some(&mut (line, ref mut rect)) if line == cluster.line => {
+                    rect.min.x = f32::min(rect.min.x, min.x);
+                    rect.max.x = f32::max(rect.max.x, max.x);
+                },
+                _ => rects.push((cluster.line, Region { min, max })),
+            }
+        }
+
+        rects.into_iter().map(|(_, rect)| rect).collect()
+    }
+
+    /// Finds the byte index at which a caret should be placed when clicking at the given
+    /// position. Positions above or below the text select the first or last line.
+    pub fn index_at(&self, pos: Vec2<f32>) -> usize {
+        let line = {
+            let line = (pos.y + self.ascent) / self.line_height;
+            if line < 0.0 { 0 } else { line as usize }
+        };
+        let line = usize::min(line, self.end_line);
+
+        for cluster in self.clusters.iter().filter(|cluster| cluster.line == line) {
+            if pos.x < cluster.pos.x + cluster.advance/2.0 {
+                return cluster.range.start;
+            }
+        }
+
+        // Past the end of the line. Place the caret before the newline, or at the end of the text.
+        match self.clusters.iter().find(|cluster| cluster.line > line) {
+            Some(next) => {
+                let last_on_line = self.clusters.iter()
+                    .filter(|cluster| cluster.line == line)
+                    .last();
+
+                match last_on_line {
+                    Some(last) if is_line_break(last) => last.range.start,
+                    Some(last) => last.range.end,
+                    None => next.range.start,
+                }
+            },
+            None => self.len,
+        }
+    }
+}
+
+// Newlines have no advance. The caret goes before them rather than after them.
+fn is_line_break(cluster: &Cluster) -> bool {
+    cluster.advance == 0.0
+}
+
+// Combining marks and joiners are placed in the same cluster as the preceding character
+pub(super) fn extends_cluster(c: char) -> bool {
+    match c as u32 {
+        0x0300 ..= 0x036f |
+        0x0483 ..= 0x0489 |
+        0x0591 ..= 0x05bd |
+        0x0610 ..= 0x061a | 0x064b ..= 0x065f | 0x0670 |
+        0x1ab0 ..= 0x1aff |
+        0x1dc0 ..= 0x1dff |
+        0x200c ..= 0x200d |
+        0x20d0 ..= 0x20ff |
+        0xfe00 ..= 0xfe0f |
+        0xfe20 ..= 0xfe2f => true,
+        _ => false,
+    }
+}
+
+pub(super) fn layout(
+    font: &rusttype::Font,
+    text: &str,
+    text_size: f32,
+    wrap_width: Option<f32>,
+) -> TextLayout
+{
+    let scale = Scale::uniform(text_size);
+    let v_metrics = font.v_metrics(scale);
+    let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+
+    let mut clusters: Vec<Cluster> = Vec::with_capacity(text.len());
+    let mut caret = Vec2::ZERO;
+    let mut line = 0;
+    let mut prev_glyph: Option<GlyphId> = None;
+
+    // This mirrors the wrapping done when drawing text
+    let breaks = match wrap_width {
+        Some(width) => line_break::wrap_points(font, text, scale, width),
+        None => Vec::new(),
+    };
+    let mut breaks = breaks.into_iter().peekable();
+
+    for (index, c) in text.char_indices() {
+        let end = index + c.len_utf8();
+
+        if extends_cluster(c) && !clusters.is_empty() {
+            clusters.last_mut().unwrap().range.end = end;
+            continue;
+        }
+
+        if breaks.peek() == Some(&index) {
+            breaks.next();
+            caret.x = 0.0;
+            caret.y += line_height;
+            line += 1;
+            prev_glyph = None;
+        }
+
+        let start_pos = caret;
+        let start_line = line;
+
+        if c == '\n' {
+            caret.x = 0.0;
+            caret.y += line_height;
+            line += 1;
+            prev_glyph = None;
+        } else if c == '\t' {
+            // Align to next tab stop
+            let tab_width = TAB_WIDTH*text_size;
+            caret.x = ((caret.x / tab_width) + 1.0).round() * tab_width;
+        } else if !c.is_control() {
+            if let Some(glyph) = font.glyph(c) {
+                let mut advance = 0.0;
+                if let Some(prev) = prev_glyph.take() {
+                    advance += font.pair_kerning(scale, prev, glyph.id());
+                }
+                prev_glyph = Some(glyph.id());
+                advance += glyph.scaled(scale).h_metrics().advance_width;
+                caret.x += advance;
+            }
+        }
+
+        let advance = if start_line == line { caret.x - start_pos.x } else { 0.0 };
+        clusters.push(Cluster {
+            range: index..end,
+            pos: start_pos,
+            advance,
+            line: start_line,
+        });
+    }
+
+    TextLayout {
+        clusters,
+        ascent: v_metrics.ascent,
+        descent: v_metrics.descent,
+        line_height,
+        len: text.len(),
+        end: caret,
+        end_line: line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster(range: Range<usize>, x: f32, advance: f32, line: usize) -> Cluster {
+        Cluster { range, pos: Vec2::new(x, line as f32 * 10.0), advance, line }
+    }
+
+    #[test]
+    fn carets_and_selections() {
+        // "ab\ncd", with each glyph being 5 units wide
+        let layout = TextLayout {
+            clusters: vec![
+                cluster(0..1, 0.0, 5.0, 0),
+                cluster(1..2, 5.0, 5.0, 0),
+                cluster(2..3, 10.0, 0.0, 0),
+                cluster(3..4, 0.0, 5.0, 1),
+                cluster(4..5, 5.0, 5.0, 1),
+            ],
+            ascent: 8.0,
+            descent: -2.0,
+            line_height: 10.0,
+            len: 5,
+            end: Vec2::new(10.0, 10.0),
+            end_line: 1,
+        };
+
+        assert_eq!(layout.caret_position(1), Vec2::new(5.0, 0.0));
+        assert_eq!(layout.caret_position(5), Vec2::new(10.0, 10.0));
+        assert_eq!(layout.caret_line(3), 1);
+
+        let rects = layout.selection_rects(1..4);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[0], Region { min: Vec2::new(5.0, -8.0), max: Vec2::new(10.0, 2.0) });
+        assert_eq!(rects[1], Region { min: Vec2::new(0.0, 2.0), max: Vec2::new(5.0, 12.0) });
+
+        assert_eq!(layout.index_at(Vec2::new(6.0, 0.0)), 1);
+        assert_eq!(layout.index_at(Vec2::new(100.0, 0.0)), 2);
+        assert_eq!(layout.index_at(Vec2::new(100.0, 10.0)), 5);
+    }
+}
@@ -0,0 +1,205 @@
+
+//! Finding where text may be wrapped. This approximates the unicode line breaking algorithm
+//! (UAX #14): Latin text breaks after spaces and hyphens, CJK text can break between any two
+//! ideographs, and non-breaking spaces, opening and closing punctuation are respected.
+
+use rusttype::{self, Scale, GlyphId};
+
+use super::truetype::TAB_WIDTH;
+use super::layout::extends_cluster;
+
+/// Whether a line may be wrapped between the two given characters.
+pub(super) fn can_break_between(before: char, after: char) -> bool {
+    // Spaces hang at the end of lines, and combining marks stay with their base
+    if is_space(after) || extends_cluster(after) {
+        return false;
+    }
+    if is_glue(before) || is_glue(after) {
+        return false;
+    }
+    if is_space(before) || before == ZERO_WIDTH_SPACE || before == SOFT_HYPHEN {
+        return true;
+    }
+
+    // Closing punctuation and small kana can not start a line, and opening punctuation can not
+    // end a line
+    if is_closing(after) || is_opening(before) {
+        return false;
+    }
+
+    // "well-known" breaks after the hyphen, but "-5" and "10-20" stay together
+    if is_hyphen(before) {
+        return !after.is_numeric();
+    }
+
+    is_ideographic(before) || is_ideographic(after)
+}
+
+/// Finds the byte indices at which `text` should be wrapped so no line is wider than
+/// `wrap_width`. Lines are broken at the last opportunity which fits. Words which are wider than
+/// a whole line are broken between characters. The returned indices are sorted, and do not
+/// include explicit newlines.
+pub(super) fn wrap_points(font: &rusttype::Font, text: &str, scale: Scale, wrap_width: f32) -> Vec<usize> {
+    let mut breaks = Vec::new();
+
+    let mut width = 0.0;
+    let mut line_start = 0;
+    // The last position where the current line can be broken, and the width of the line up to it
+    let mut last_opportunity: Option<(usize, f32)> = None;
+
+    let mut prev_char: Option<char> = None;
+    let mut prev_glyph: Option<GlyphId> = None;
+
+    for (index, c) in text.char_indices() {
+        if c == '\n' {
+            width = 0.0;
+            line_start = index + 1;
+            last_opportunity = None;
+            prev_char = None;
+            prev_glyph = None;
+            continue;
+        }
+
+        if let Some(prev) = prev_char {
+            if index > line_start && can_break_between(prev, c) {
+                last_opportunity = Some((index, width));
+            }
+        }
+        prev_char = Some(c);
+
+        let advance = if c == '\t' {
+            let tab_width = TAB_WIDTH*scale.x;
+            ((width / tab_width) + 1.0).round()*tab_width - width
+        } else if c.is_control() {
+            0.0
+        } else if let Some(glyph) = font.glyph(c) {
+            let mut advance = 0.0;
+            if let Some(prev) = prev_glyph.take() {
+                advance += font.pair_kerning(scale, prev, glyph.id());
+            }
+            prev_glyph = Some(glyph.id());
+            advance + glyph.scaled(scale).h_metrics().advance_width
+        } else {
+            0.0
+        };
+
+        if width + advance > wrap_width && !is_space(c) {
+            match last_opportunity.take() {
+                Some((at, width_before)) => {
+                    breaks.push(at);
+                    line_start = at;
+                    width -= width_before;
+                },
+                None if index > line_start && !extends_cluster(c) => {
+                    breaks.push(index);
+                    line_start = index;
+                    width = 0.0;
+                    prev_glyph = None;
+                },
+                None => {},
+            }
+        }
+
+        width += advance;
+    }
+
+    breaks
+}
+
+const ZERO_WIDTH_SPACE: char = '\u{200b}';
+const SOFT_HYPHEN: char = '\u{ad}';
+
+fn is_space(c: char) -> bool {
+    match c {
+        ' ' | '\t' | '\u{1680}' | '\u{2000}' ..= '\u{2006}' | '\u{2008}' ..= '\u{200a}' |
+        '\u{205f}' | '\u{3000}' => true,
+        _ => false,
+    }
+}
+
+// Characters which prevent breaks on both sides, e.g. non-breaking spaces
+fn is_glue(c: char) -> bool {
+    match c {
+        '\u{a0}' | '\u{2007}' | '\u{202f}' | '\u{2060}' | '\u{feff}' | '\u{2011}' => true,
+        _ => false,
+    }
+}
+
+fn is_hyphen(c: char) -> bool {
+    match c {
+        '-' | '\u{2010}' | '\u{2012}' | '\u{2013}' => true,
+        _ => false,
+    }
+}
+
+fn is_opening(c: char) -> bool {
+    match c {
+        '(' | '[' | '{' | '\u{2018}' | '\u{201c}' |
+        '\u{3008}' | '\u{300a}' | '\u{300c}' | '\u{300e}' | '\u{3010}' | '\u{3014}' | '\u{3016}' |
+        '\u{ff08}' | '\u{ff3b}' | '\u{ff5b}' => true,
+        _ => false,
+    }
+}
+
+// Closing punctuation, and other characters which should not start a line
+fn is_closing(c: char) -> bool {
+    match c {
+        ')' | ']' | '}' | '.' | ',' | ':' | ';' | '!' | '?' | '%' | '\u{2019}' | '\u{201d}' |
+        '\u{2026}' |
+        // Ideographic comma and full stop, closing brackets
+        '\u{3001}' | '\u{3002}' | '\u{3009}' | '\u{300b}' | '\u{300d}' | '\u{300f}' | '\u{3011}' |
+        '\u{3015}' | '\u{3017}' | '\u{3005}' |
+        // Small kana and the prolonged sound mark
+        '\u{3041}' | '\u{3043}' | '\u{3045}' | '\u{3047}' | '\u{3049}' | '\u{3063}' | '\u{3083}' |
+        '\u{3085}' | '\u{3087}' | '\u{30a1}' | '\u{30a3}' | '\u{30a5}' | '\u{30a7}' | '\u{30a9}' |
+        '\u{30c3}' | '\u{30e3}' | '\u{30e5}' | '\u{30e7}' | '\u{30fc}' | '\u{30fb}' |
+        // Fullwidth punctuation
+        '\u{ff01}' | '\u{ff09}' | '\u{ff0c}' | '\u{ff0e}' | '\u{ff1a}' | '\u{ff1b}' | '\u{ff1f}' |
+        '\u{ff3d}' | '\u{ff5d}' => true,
+        _ => false,
+    }
+}
+
+// Characters between which lines can be broken freely
+fn is_ideographic(c: char) -> bool {
+    match c {
+        '\u{2e80}' ..= '\u{2fff}' | // CJK radicals
+        '\u{3001}' ..= '\u{303f}' | // CJK punctuation
+        '\u{3040}' ..= '\u{30ff}' | // Hiragana and katakana
+        '\u{3100}' ..= '\u{31ff}' |
+        '\u{3400}' ..= '\u{4dbf}' | // CJK extension A
+        '\u{4e00}' ..= '\u{9fff}' | // CJK unified ideographs
+        '\u{ac00}' ..= '\u{d7af}' | // Hangul syllables
+        '\u{f900}' ..= '\u{faff}' |
+        '\u{ff01}' ..= '\u{ff60}' | // Fullwidth forms
+        '\u{20000}' ..= '\u{3ffff}' => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opportunities(text: &str) -> Vec<usize> {
+        let chars = text.char_indices().collect::<Vec<_>>();
+        chars.windows(2)
+            .filter(|pair| can_break_between(pair[0].1, pair[1].1))
+            .map(|pair| pair[1].0)
+            .collect()
+    }
+
+    #[test]
+    fn break_opportunities() {
+        assert_eq!(opportunities("hello big world"), vec![6, 10]);
+        assert_eq!(opportunities("a  b"), vec![3]);
+        assert_eq!(opportunities("10\u{a0}km"), Vec::<usize>::new());
+        assert_eq!(opportunities("well-known -5 1-2"), vec![5, 11, 14]);
+        assert_eq!(opportunities("(a) b"), vec![4]);
+
+        // Between ideographs, but not before the full stop or after the opening bracket
+        let text = "日本語。「は」";
+        let expected = vec!["本", "語", "「"].iter().map(|s| text.find(s).unwrap()).collect::<Vec<_>>();
+        assert_eq!(opportunities(text), expected);
+    }
+}
@@ -1,6 +1,11 @@
 
 mod truetype;
 mod bitmap;
+mod presentation_forms;
+mod layout;
+mod line_break;
 
 pub use self::truetype::*;
 pub use self::bitmap::*;
+pub use self::layout::*;
+pub use self::presentation_forms::apply_presentation_forms;
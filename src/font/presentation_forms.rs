@@ -0,0 +1,332 @@
+
+//! Arabic presentation-form substitution and line reordering for right-to-left text. This is a
+//! character level substitution, not text shaping. Two things are done:
+//!
+//!  - Arabic letters are replaced with their contextual forms (isolated, initial, medial or
+//!    final) from the "Arabic Presentation Forms-B" block. Lam-alef is the only ligature which
+//!    is formed.
+//!  - Each line is reordered from logical to visual order, following a simplified version of the
+//!    unicode bidirectional algorithm without explicit embeddings. Numbers inside right-to-left
+//!    text keep their left-to-right order, and brackets are mirrored.
+//!
+//! No opentype tables are read, so other ligatures, mark positioning and contextual kerning are
+//! not supported, and fonts without glyphs for the presentation forms show disconnected letters.
+//! Scripts which need shaping, e.g. indic scripts, are not handled at all.
+
+use std::borrow::Cow;
+
+/// Converts the given text from logical order to visual order, and replaces arabic letters with
+/// their contextual forms. Text which contains no right-to-left characters is returned as is.
+///
+/// Lines are reordered separately, but automatic wrapping is not taken into account. Long
+/// right-to-left paragraphs should be split into lines manually.
+pub fn apply_presentation_forms<'a>(text: &'a str) -> Cow<'a, str> {
+    if !text.chars().any(is_rtl) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut result = String::with_capacity(text.len());
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+
+        let line = join_arabic(line);
+        reorder_line(&line, &mut result);
+    }
+
+    Cow::Owned(result)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Class {
+    Left,
+    Right,
+    Number,
+    Neutral,
+}
+
+fn is_rtl(c: char) -> bool {
+    classify(c) == Class::Right
+}
+
+fn classify(c: char) -> Class {
+    match c as u32 {
+        0x30 ..= 0x39 | 0x660 ..= 0x669 | 0x6f0 ..= 0x6f9 => Class::Number,
+
+        0x0590 ..= 0x08ff | 0xfb1d ..= 0xfdff | 0xfe70 ..= 0xfefe => Class::Right,
+
+        _ if c.is_alphabetic() => Class::Left,
+        _ => Class::Neutral,
+    }
+}
+
+// Reorders a single line from logical to visual order. This implements the weak, neutral and
+// implicit rules of the bidi algorithm in a simplified form, followed by rule L2.
+fn reorder_line(line: &[char], out: &mut String) {
+    let classes = line.iter().map(|&c| classify(c)).collect::<Vec<_>>();
+
+    let rtl_paragraph = classes.iter()
+        .find(|&&class| class == Class::Left || class == Class::Right)
+        .map_or(false, |&class| class == Class::Right);
+    let base_level = if rtl_paragraph { 1 } else { 0 };
+
+    // Numbers following left-to-right text are treated as left-to-right text (W7)
+    let mut resolved = classes.clone();
+    let mut last_strong = if rtl_paragraph { Class::Right } else { Class::Left };
+    for class in resolved.iter_mut() {
+        match *class {
+            Class::Left | Class::Right => last_strong = *class,
+            Class::Number if last_strong == Class::Left => *class = Class::Left,
+            _ => {},
+        }
+    }
+
+    // Neutrals between characters of the same direction take that direction, all other neutrals
+    // take the paragraph direction (N1, N2). Numbers count as right-to-left here.
+    let direction = |class: Class| -> Option<Class> {
+        match class {
+            Class::Left => Some(Class::Left),
+            Class::Right | Class::Number => Some(Class::Right),
+            Class::Neutral => None,
+        }
+    };
+    let embedding = if rtl_paragraph { Class::Right } else { Class::Left };
+
+    let mut i = 0;
+    while i < resolved.len() {
+        if resolved[i] != Class::Neutral {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < resolved.len() && resolved[i] == Class::Neutral {
+            i += 1;
+        }
+
+        let before = if start == 0 { Some(embedding) } else { direction(resolved[start - 1]) };
+        let after = if i == resolved.len() { Some(embedding) } else { direction(resolved[i]) };
+        let class = if before == after { before.unwrap_or(embedding) } else { embedding };
+
+        for neutral in resolved[start..i].iter_mut() {
+            *neutral = class;
+        }
+    }
+
+    // Resolve levels (I1, I2)
+    let levels = resolved.iter().map(|&class| {
+        match (base_level, class) {
+            (0, Class::Left) => 0,
+            (0, Class::Right) => 1,
+            (0, Class::Number) => 2,
+            (_, Class::Left) => 2,
+            (_, Class::Number) => 2,
+            (_, _) => 1,
+        }
+    }).collect::<Vec<u8>>();
+
+    // Reverse runs, from the highest level down to the lowest odd level (L2)
+    let mut visual = (0..line.len()).collect::<Vec<usize>>();
+    let max_level = levels.iter().cloned().max().unwrap_or(0);
+    let mut level = max_level;
+    while level >= 1 {
+        let mut i = 0;
+        while i < visual.len() {
+            if levels[visual[i]] < level {
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            while i < visual.len() && levels[visual[i]] >= level {
+                i += 1;
+            }
+            visual[start..i].reverse();
+        }
+
+        level -= 1;
+    }
+
+    for index in visual {
+        let c = line[index];
+        if levels[index] % 2 == 1 {
+            out.push(mirror(c));
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')', ')' => '(',
+        '[' => ']', ']' => '[',
+        '{' => '}', '}' => '{',
+        '<' => '>', '>' => '<',
+        '«' => '»', '»' => '«',
+        _ => c,
+    }
+}
+
+// The first codepoint of the presentation forms for arabic letters, and whether the letter
+// can join to the following letter. Forms are stored in the order isolated, final, initial
+// and medial, with only the first two being present for letters which only join to the
+// preceding letter.
+fn arabic_forms(c: char) -> Option<(u32, bool)> {
+    let forms = match c as u32 {
+        0x0621 => (0xfe80, false),
+        0x0622 => (0xfe81, false),
+        0x0623 => (0xfe83, false),
+        0x0624 => (0xfe85, false),
+        0x0625 => (0xfe87, false),
+        0x0626 => (0xfe89, true),
+        0x0627 => (0xfe8d, false),
+        0x0628 => (0xfe8f, true),
+        0x0629 => (0xfe93, false),
+        0x062a => (0xfe95, true),
+        0x062b => (0xfe99, true),
+        0x062c => (0xfe9d, true),
+        0x062d => (0xfea1, true),
+        0x062e => (0xfea5, true),
+        0x062f => (0xfea9, false),
+        0x0630 => (0xfeab, false),
+        0x0631 => (0xfead, false),
+        0x0632 => (0xfeaf, false),
+        0x0633 => (0xfeb1, true),
+        0x0634 => (0xfeb5, true),
+        0x0635 => (0xfeb9, true),
+        0x0636 => (0xfebd, true),
+        0x0637 => (0xfec1, true),
+        0x0638 => (0xfec5, true),
+        0x0639 => (0xfec9, true),
+        0x063a => (0xfecd, true),
+        0x0641 => (0xfed1, true),
+        0x0642 => (0xfed5, true),
+        0x0643 => (0xfed9, true),
+        0x0644 => (0xfedd, true),
+        0x0645 => (0xfee1, true),
+        0x0646 => (0xfee5, true),
+        0x0647 => (0xfee9, true),
+        0x0648 => (0xfeed, false),
+        0x0649 => (0xfeef, false),
+        0x064a => (0xfef1, true),
+        _ => return None,
+    };
+    Some(forms)
+}
+
+const TATWEEL: char = '\u{0640}';
+const LAM: char = '\u{0644}';
+
+// Harakat and other combining marks are skipped when looking for neighbouring letters
+fn is_transparent(c: char) -> bool {
+    match c as u32 {
+        0x0610 ..= 0x061a | 0x064b ..= 0x065f | 0x0670 | 0x06d6 ..= 0x06ed => true,
+        _ => false,
+    }
+}
+
+fn joins_following(c: char) -> bool {
+    c == TATWEEL || arabic_forms(c).map_or(false, |(_, dual)| dual)
+}
+
+fn joins_preceding(c: char) -> bool {
+    // Hamza is the only letter in the table which never joins
+    c == TATWEEL || (arabic_forms(c).is_some() && c != '\u{0621}')
+}
+
+fn lam_alef_ligature(alef: char) -> Option<u32> {
+    match alef as u32 {
+        0x0622 => Some(0xfef5),
+        0x0623 => Some(0xfef7),
+        0x0625 => Some(0xfef9),
+        0x0627 => Some(0xfefb),
+        _ => None,
+    }
+}
+
+// Replaces arabic letters with their contextual presentation forms
+fn join_arabic(line: &str) -> Vec<char> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut result = Vec::with_capacity(chars.len());
+
+    let neighbour = |mut i: isize, step: isize| -> Option<char> {
+        loop {
+            i += step;
+            if i < 0 || i as usize >= chars.len() {
+                return None;
+            }
+
+            let c = chars[i as usize];
+            if !is_transparent(c) {
+                return Some(c);
+            }
+        }
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        let (base, dual) = match arabic_forms(c) {
+            Some(forms) => forms,
+            None => {
+                result.push(c);
+                i += 1;
+                continue;
+            },
+        };
+
+        let joins_prev = neighbour(i as isize, -1).map_or(false, joins_following);
+
+        if c == LAM {
+            if let Some(ligature) = chars.get(i + 1).and_then(|&alef| lam_alef_ligature(alef)) {
+                let form = if joins_prev { ligature + 1 } else { ligature };
+                result.push(::std::char::from_u32(form).unwrap());
+                i += 2;
+                continue;
+            }
+        }
+
+        let joins_next = dual && neighbour(i as isize, 1).map_or(false, joins_preceding);
+
+        let form = if c == '\u{0621}' {
+            base
+        } else {
+            match (joins_prev, joins_next) {
+                (false, false) => base,
+                (true,  false) => base + 1,
+                (false, true)  => base + 2,
+                (true,  true)  => base + 3,
+            }
+        };
+
+        result.push(::std::char::from_u32(form).unwrap());
+        i += 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reorder() {
+        assert_eq!(apply_presentation_forms("hello world"), "hello world");
+        // Hebrew "shalom", with a number following it
+        assert_eq!(apply_presentation_forms("abc \u{5e9}\u{5dc}\u{5d5}\u{5dd} 123"), "abc 123 \u{5dd}\u{5d5}\u{5dc}\u{5e9}");
+        assert_eq!(apply_presentation_forms("\u{5d0}\u{5d1} (x)"), "(x) \u{5d1}\u{5d0}");
+    }
+
+    #[test]
+    fn contextual_forms() {
+        // Beh beh beh: initial, medial, final. Reversed for display.
+        assert_eq!(apply_presentation_forms("\u{628}\u{628}\u{628}"), "\u{fe90}\u{fe92}\u{fe91}");
+        // Lam alef
+        assert_eq!(apply_presentation_forms("\u{644}\u{627}"), "\u{fefb}");
+    }
+}
@@ -20,12 +20,20 @@ use rusttype::gpu_cache::*;
 use cable_math::Vec2;
 
 use texture::{Texture, SwizzleComp, TextureFormat};
+use super::presentation_forms;
+use super::line_break;
+use super::bitmap::{self, BakedBitmapFont};
+use super::layout::{self, TextLayout};
 
 const CACHE_TEX_SIZE: u32 = 1024; // More than 99% of GPUs support this texture size: http://feedback.wildfiregames.com/report/opengl/feature/GL_MAX_TEXTURE_SIZE
 
 // There might be some official spec for how tabs should work. Note that this is multiplied by the
 // current font size.
-const TAB_WIDTH: f32 = 1.5;
+pub(super) const TAB_WIDTH: f32 = 1.5;
+
+// The FIR filter applied across subpixels to reduce color fringes, the same as FreeType's default
+// LCD filter
+const LCD_FILTER: [f32; 5] = [8.0/256.0, 77.0/256.0, 86.0/256.0, 77.0/256.0, 8.0/256.0];
 
 /// A single font style. This is not used directly for text rendering, but rather specifies how
 /// text should be layed out according to a given font. It also provides rasterized glyphs that are
@@ -34,6 +42,8 @@ pub struct TruetypeFont {
     font: rusttype::Font<'static>,
     gpu_cache: Cache,
     cache_texture: Texture,
+    atlas_generation: u64,
+    subpixel: bool,
 }
 
 impl TruetypeFont {
@@ -48,35 +58,42 @@ impl TruetypeFont {
         let mut data = Vec::new();
         file.read_to_end(&mut data)?;
 
-        let font_collection = rusttype::FontCollection::from_bytes(data);
-        let font = font_collection.font_at(0).unwrap();
-
-        Ok(TruetypeFont::with_rusttype_font(font))
+        TruetypeFont::from_bytes(data)
     }
 
     /// Constructs a font from raw data bytes. This can be used in conjunction with the
-    /// `include_bytes!(...)` macro. This function expects fonts in the same format as
-    /// `TruetypeFont::from_file`.
-    pub fn from_bytes(bytes: &'static [u8]) -> TruetypeFont {
+    /// `include_bytes!(...)` macro, or with a `Vec<u8>` read from some other source. This function
+    /// expects fonts in the same format as `TruetypeFont::from_file`. Returns an error of kind
+    /// `InvalidData` if the bytes do not contain a font.
+    pub fn from_bytes<B>(bytes: B) -> io::Result<TruetypeFont> where B: Into<rusttype::SharedBytes<'static>> {
         let font_collection = rusttype::FontCollection::from_bytes(bytes);
-        let font = font_collection.font_at(0).unwrap();
-
-        TruetypeFont::with_rusttype_font(font)
+        match font_collection.font_at(0) {
+            Some(font) => Ok(TruetypeFont::with_rusttype_font(font)),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Not a valid truetype or opentype font")),
+        }
     }
 
     fn with_rusttype_font(font: rusttype::Font<'static>) -> TruetypeFont {
         let gpu_cache = Cache::new(CACHE_TEX_SIZE, CACHE_TEX_SIZE, 0.5, 0.5);
+        let cache_texture = create_cache_texture(false);
 
-        let mut cache_texture = Texture::new();
-        cache_texture.initialize(CACHE_TEX_SIZE, CACHE_TEX_SIZE, TextureFormat::R_8);
-        cache_texture.set_swizzle_mask((SwizzleComp::One, SwizzleComp::One, SwizzleComp::One, SwizzleComp::Red));
+        TruetypeFont { font, gpu_cache, cache_texture, atlas_generation: 0, subpixel: false }
+    }
 
-        TruetypeFont { font, gpu_cache, cache_texture }
+    /// Rasterizes the glyphs for the characters `first_glyph .. first_glyph + glyph_count` at the
+    /// given size, so they can be drawn as a bitmap font. E.g. `bake_bitmap_font(16.0, 32, 95)`
+    /// bakes all printable ascii characters. See [`BakedBitmapFont`] for more info.
+    ///
+    /// [`BakedBitmapFont`]: struct.BakedBitmapFont.html
+    pub fn bake_bitmap_font(&self, size: f32, first_glyph: u32, glyph_count: u32) -> BakedBitmapFont {
+        bitmap::bake(&self.font, size, first_glyph, glyph_count)
     }
 
     /// Calculates the width in pixels of the given string if it where to be rendered at the given
     /// size. This takes newlines into acount. 
     pub fn width(&self, text: &str, text_size: f32) -> f32 {
+        let text = presentation_forms::apply_presentation_forms(text);
+
         let mut prev_glyph: Option<GlyphId> = None; 
         let mut caret = Vec2::ZERO;
         let mut max_x = 0.0;
@@ -120,10 +137,12 @@ impl TruetypeFont {
     }
 
     /// Calculates the dimensions, in pixels, of the given string if it where to be rendered at the
-    /// given size. This takes newlines into acount. 
+    /// given size. This takes newlines and wrapping into acount. 
     /// Returns the size of the string, in addition to the ascent of the first line. If the text is
     /// offset downwards by this amount the top of the text will be at the previous baseline.
     pub fn dimensions(&self, text: &str, text_size: f32, wrap_width: Option<f32>) -> (Vec2<f32>, f32) {
+        let text = presentation_forms::apply_presentation_forms(text);
+
         let mut prev_glyph: Option<GlyphId> = None; 
         let mut first_line = true;
         let mut first_ascent = 0.0;
@@ -134,7 +153,22 @@ impl TruetypeFont {
         let v_metrics = self.font.v_metrics(scale);
         let vertical_advance = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap; 
 
-        for c in text.chars() {
+        let breaks = match wrap_width {
+            Some(width) => line_break::wrap_points(&self.font, &text, scale, width),
+            None => Vec::new(),
+        };
+        let mut breaks = breaks.into_iter().peekable();
+
+        for (index, c) in text.char_indices() {
+            // Wrap if line is to long
+            if breaks.peek() == Some(&index) {
+                breaks.next();
+                max_x = f32::max(max_x, caret.x);
+                caret.x = 0.0;
+                caret.y += vertical_advance;
+                prev_glyph = None;
+            }
+
             let glyph = if let Some(glyph) = self.font.glyph(c) {
                 glyph
             } else {
@@ -169,16 +203,6 @@ impl TruetypeFont {
             let glyph = glyph.scaled(scale);
             caret.x += glyph.h_metrics().advance_width;
 
-            // Wrap if line is to long
-            if let Some(width) = wrap_width {
-                if caret.x > width {
-                    max_x = f32::max(max_x, caret.x);
-                    caret.x = 0.0;
-                    caret.y += vertical_advance;
-                    prev_glyph = None;
-                }
-            }
-
             if first_line {
                 if let Some(bounding) = glyph.exact_bounding_box() {
                     first_ascent = f32::max(first_ascent, -bounding.min.y);
@@ -326,6 +350,14 @@ impl TruetypeFont {
         None
     }
 
+    /// Lays out the given text and returns the position of each cluster, for placing carets and
+    /// drawing selections in editable text. See [`TextLayout`] for more info.
+    ///
+    /// [`TextLayout`]: struct.TextLayout.html
+    pub fn layout(&self, text: &str, text_size: f32, wrap_width: Option<f32>) -> TextLayout {
+        layout::layout(&self.font, text, text_size, wrap_width)
+    }
+
     /// Retrieves height metrics for this font at the given size. This includes the max ascent,
     /// descent and the recommended line gap.
     pub fn height_metrics(&self, text_size: f32) -> HeightMetrics {
@@ -355,12 +387,58 @@ impl TruetypeFont {
         v_metrics.line_gap
     }
 
+    /// Incremented whenever new glyphs are written to the glyph cache texture. Writing glyphs can
+    /// evict other glyphs, so uv coordinates from earlier calls to [`cache`] are only known to be
+    /// valid while this stays the same.
+    ///
+    /// [`cache`]: #method.cache
+    pub fn atlas_generation(&self) -> u64 {
+        self.atlas_generation
+    }
+
     pub fn texture(&self) -> &Texture {
         &self.cache_texture
     }
 
+    /// Whether glyphs are rasterized for LCD subpixels, see [`set_subpixel`].
+    ///
+    /// [`set_subpixel`]: #method.set_subpixel
+    pub fn subpixel(&self) -> bool {
+        self.subpixel
+    }
+
+    /// Switches between grayscale glyphs, which is the default, and glyphs with separate coverage
+    /// for the red, green and blue subpixels of horizontal RGB LCD screens. For subpixel glyphs,
+    /// the color channels of [`texture`] contain the coverage of each subpixel, and alpha
+    /// contains the average coverage. For grayscale glyphs, the color channels are white.
+    ///
+    /// Drawing subpixel glyphs correctly needs dual-source blending, see
+    /// `DrawGroup::set_subpixel_text`. Changing this clears the glyph cache.
+    ///
+    /// [`texture`]: #method.texture
+    pub fn set_subpixel(&mut self, subpixel: bool) {
+        if subpixel == self.subpixel {
+            return;
+        }
+
+        self.subpixel = subpixel;
+        self.gpu_cache = Cache::new(CACHE_TEX_SIZE, CACHE_TEX_SIZE, 0.5, 0.5);
+        self.cache_texture = create_cache_texture(subpixel);
+        self.atlas_generation += 1;
+    }
+
     /// Passes pairs of positions and uv coordinates to the callback. Three pairs are one triangle,
     /// two triangles form one glyph.
+    ///
+    /// If `wrap_width` is given, lines are wrapped at the last point where the unicode line
+    /// breaking rules allow it, e.g. after spaces and hyphens or between CJK ideographs. Words
+    /// longer than a whole line are broken between characters.
+    ///
+    /// Text is not shaped. Before layout, right-to-left text is reordered and arabic letters are
+    /// replaced with their presentation forms, see [`font::apply_presentation_forms`] for the
+    /// limitations of this.
+    ///
+    /// [`font::apply_presentation_forms`]: fn.apply_presentation_forms.html
     pub fn cache<F>(
         &mut self,
         text:       &str,
@@ -373,21 +451,52 @@ impl TruetypeFont {
     )
       where F: FnMut(Vec2<f32>, Vec2<f32>),
     {
-        let mut iter = PlacementIter::new(text, &self.font, Scale::uniform(text_size), offset);
-        iter.wrap_width = wrap_width;
+        let text = presentation_forms::apply_presentation_forms(text);
+
+        let mut iter = PlacementIter::new(&text, &self.font, Scale::uniform(text_size), offset);
+        if let Some(width) = wrap_width {
+            iter.breaks = line_break::wrap_points(&self.font, &text, Scale::uniform(text_size), width);
+        }
 
         // Cache stuff on gpu
         for PlacementInfo { ref glyph, .. } in iter.clone() {
             self.gpu_cache.queue_glyph(0, glyph.clone());
         }
         let ref mut tex = self.cache_texture;
+        let subpixel = self.subpixel;
+        let mut uploaded = false;
         self.gpu_cache.cache_queued(|rect, data| {
-            tex.load_data_to_region(
-                data,
-                rect.min.x, rect.min.y,
-                rect.width(), rect.height()
-            );
+            uploaded = true;
+            // Subpixel glyphs are rasterized separately below
+            if !subpixel {
+                tex.load_data_to_region(
+                    data,
+                    rect.min.x, rect.min.y,
+                    rect.width(), rect.height()
+                );
+            }
         }).unwrap();
+        if uploaded {
+            self.atlas_generation += 1;
+        }
+
+        // The cache does not tell us which glyphs it added, so we upload all glyphs in the text
+        // again. This only happens when new glyphs are cached, which is rare after the first
+        // few frames.
+        if uploaded && subpixel {
+            for PlacementInfo { ref glyph, .. } in iter.clone() {
+                if let Ok(Some((uv, _))) = self.gpu_cache.rect_for(0, glyph) {
+                    let size = CACHE_TEX_SIZE as f32;
+                    let x = (uv.min.x*size).round() as u32;
+                    let y = (uv.min.y*size).round() as u32;
+                    let width = (uv.max.x*size).round() as u32 - x;
+                    let height = (uv.max.y*size).round() as u32 - y;
+
+                    let data = rasterize_subpixel(glyph, width, height);
+                    tex.load_data_to_region(&data, x, y, width, height);
+                }
+            }
+        }
 
         // Output vertices
         for PlacementInfo { ref glyph, .. } in iter {
@@ -409,12 +518,84 @@ impl TruetypeFont {
     }
 }
 
+fn create_cache_texture(subpixel: bool) -> Texture {
+    let mut texture = Texture::new();
+    if subpixel {
+        texture.initialize(CACHE_TEX_SIZE, CACHE_TEX_SIZE, TextureFormat::RGBA_8);
+    } else {
+        texture.initialize(CACHE_TEX_SIZE, CACHE_TEX_SIZE, TextureFormat::R_8);
+        texture.set_swizzle_mask((SwizzleComp::One, SwizzleComp::One, SwizzleComp::One, SwizzleComp::Red));
+    }
+    texture
+}
+
+// Rasterizes the given glyph at three times the horizontal resolution, and returns rgba texels
+// with the coverage of each subpixel, for a region of the given size starting at the pixel
+// bounding box of the glyph
+fn rasterize_subpixel(glyph: &PositionedGlyph, width: u32, height: u32) -> Vec<u8> {
+    let sub_width = width as usize*3;
+    let mut coverage = vec![0.0; sub_width*height as usize];
+
+    if let Some(bounds) = glyph.pixel_bounding_box() {
+        let scale = glyph.scale();
+        let position = glyph.position();
+        let wide = glyph.unpositioned().unscaled().standalone()
+            .scaled(Scale { x: scale.x*3.0, y: scale.y })
+            .positioned(point(position.x*3.0, position.y));
+
+        if let Some(wide_bounds) = wide.pixel_bounding_box() {
+            let dx = wide_bounds.min.x - bounds.min.x*3;
+            let dy = wide_bounds.min.y - bounds.min.y;
+            wide.draw(|x, y, v| {
+                let x = x as i32 + dx;
+                let y = y as i32 + dy;
+                if x >= 0 && y >= 0 && (x as usize) < sub_width && (y as u32) < height {
+                    coverage[y as usize*sub_width + x as usize] = v;
+                }
+            });
+        }
+    }
+
+    filter_subpixels(&coverage, width as usize)
+}
+
+// Applies `LCD_FILTER` to rows of subpixel coverage, and packs each three subpixels into a rgba
+// texel, with the average coverage in alpha
+fn filter_subpixels(coverage: &[f32], width: usize) -> Vec<u8> {
+    let sub_width = width*3;
+    let quantize = |v: f32| (v.max(0.0).min(1.0)*255.0).round() as u8;
+
+    let mut data = Vec::with_capacity(coverage.len() / 3 * 4);
+    for row in coverage.chunks(sub_width) {
+        for texel in 0..width {
+            let mut rgb = [0.0; 3];
+            for (channel, value) in rgb.iter_mut().enumerate() {
+                let center = (texel*3 + channel) as isize;
+                for (i, weight) in LCD_FILTER.iter().enumerate() {
+                    let x = center + i as isize - 2;
+                    if x >= 0 && (x as usize) < sub_width {
+                        *value += row[x as usize]*weight;
+                    }
+                }
+            }
+
+            data.push(quantize(rgb[0]));
+            data.push(quantize(rgb[1]));
+            data.push(quantize(rgb[2]));
+            data.push(quantize((rgb[0] + rgb[1] + rgb[2]) / 3.0));
+        }
+    }
+    data
+}
+
 impl Clone for TruetypeFont {
     /// Produces a copy of this font. Note that this creates a new internal glyph cache
     fn clone(&self) -> TruetypeFont {
         // Cloning a rusttype font is cheap as data is internally stored in a
         // `Arc<Box<&[u8]>>`, which is cheap to clone.
-        TruetypeFont::with_rusttype_font(self.font.clone())
+        let mut font = TruetypeFont::with_rusttype_font(self.font.clone());
+        font.set_subpixel(self.subpixel);
+        font
     }
 }
 
@@ -431,7 +612,9 @@ struct PlacementIter<'a> {
     prev_glyph: Option<GlyphId>,
     vertical_advance: f32,
 
-    wrap_width: Option<f32>,
+    // Byte indices at which lines are wrapped, in order
+    breaks: Vec<usize>,
+    next_break: usize,
 }
 struct PlacementInfo<'a> {
     glyph: PositionedGlyph<'a>, 
@@ -462,7 +645,8 @@ impl<'a> PlacementIter<'a> {
             prev_glyph: None,
             vertical_advance: vertical_advance,
 
-            wrap_width: None,
+            breaks: Vec::new(),
+            next_break: 0,
         }
     }
 }
@@ -472,6 +656,12 @@ impl<'a> Iterator for PlacementIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(c) = self.text.next() {
+            if self.breaks.get(self.next_break) == Some(&self.str_index) {
+                self.next_break += 1;
+                self.caret.x = self.offset.x;
+                self.caret.y += self.vertical_advance;
+                self.prev_glyph = None;
+            }
             self.str_index += c.len_utf8();
 
             // Move to new line
@@ -513,13 +703,6 @@ impl<'a> Iterator for PlacementIter<'a> {
 
             self.caret.x += advance;
 
-            if let Some(width) = self.wrap_width {
-                if self.caret.x + advance > self.offset.x + width {
-                    self.caret.x = self.offset.x + advance;
-                    self.caret.y += self.vertical_advance;
-                }
-            }
-
             let glyph = glyph.positioned(point(self.caret.x - advance, self.caret.y));
 
 
@@ -571,3 +754,32 @@ impl HeightMetrics {
         self.ascent - self.descent + self.line_gap
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subpixel_filter() {
+        // A single fully covered green subpixel, in the middle of two texels
+        let mut coverage = vec![0.0; 6];
+        coverage[1] = 1.0;
+
+        let data = filter_subpixels(&coverage, 2);
+        assert_eq!(data.len(), 8);
+        assert_eq!(&data[..3], &[77, 86, 77]);
+        assert_eq!(&data[4..7], &[8, 0, 0]);
+
+        // Full coverage stays full, except at the edges where the filter is cut off
+        let data = filter_subpixels(&vec![1.0; 9], 3);
+        assert_eq!(&data[4..8], &[255, 255, 255, 255]);
+        assert!(data[0] < 255 && data[10] < 255);
+    }
+    #[test]
+    fn invalid_font_data() {
+        match TruetypeFont::from_bytes(vec![0u8; 16]) {
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("Loaded a font from garbage"),
+        }
+    }
+}
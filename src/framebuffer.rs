@@ -4,14 +4,49 @@
 use gl;
 use std::fmt;
 use std::error;
+use std::cell::Cell;
 use gl::types::*;
 
+use Region;
 use color::Color;
 use texture::TextureFormat;
+use graphics::{self, ResourceKind, ObjectKind};
+use material;
 use buffer::{VertexData, GlPrimitive};
 
 use cable_math::Vec2;
 
+thread_local! {
+    // The viewport of the backbuffer, while a framebuffer is bound
+    static WINDOW_VIEWPORT: Cell<Option<[GLint; 4]>> = Cell::new(None);
+}
+
+/// Sets the viewport used when drawing to the backbuffer. If a framebuffer is currently bound, the
+/// viewport is only applied once it is unbound. Used when the window is resized.
+pub(crate) fn set_window_viewport(region: Region) {
+    let saved = WINDOW_VIEWPORT.with(|v| {
+        if v.get().is_some() {
+            v.set(Some([
+                region.min.x as GLint, region.min.y as GLint,
+                region.max.x as GLint, region.max.y as GLint,
+            ]));
+            true
+        } else {
+            false
+        }
+    });
+
+    if !saved {
+        graphics::viewport(region);
+    }
+}
+
+/// Called when the context is replaced. No framebuffer is bound in the new context, so the next
+/// call to `set_window_viewport` applies the viewport immediately.
+pub(crate) fn note_context_changed() {
+    WINDOW_VIEWPORT.with(|v| v.set(None));
+}
+
 /// Set to 8, which 97% of all cards support, acording to the [wildfiregames report][1]
 /// [1]: http://feedback.wildfiregames.com/report/opengl/feature/GL_MAX_COLOR_ATTACHMENTS_EXT
 pub const MAX_COLOR_ATTACHMENTS: usize = 8;
@@ -33,6 +68,9 @@ pub struct FramebufferProperties {
     pub color_formats: Vec<TextureFormat>,
     /// If `true` a depthbuffer will be added to framebuffers
     pub depth_buffer: bool,
+    /// If `true` a 8 bit stencil buffer will be added to framebuffers. If both this and
+    /// `depth_buffer` are set, a single combined depth-stencil buffer is used.
+    pub stencil_buffer: bool,
 }
 
 impl Default for FramebufferProperties {
@@ -42,6 +80,7 @@ impl Default for FramebufferProperties {
             multisample: None,
             color_formats: vec![TextureFormat::RGB_8],
             depth_buffer: false,
+            stencil_buffer: false,
         }
     }
 }
@@ -53,6 +92,7 @@ impl FramebufferProperties {
             multisample: None,
             color_formats: vec![TextureFormat::RGB_8],
             depth_buffer: false,
+            stencil_buffer: false,
         }
     }
 
@@ -69,6 +109,8 @@ pub struct Framebuffer {
     color_attachments: [Option<ColorAttachmentData>; MAX_COLOR_ATTACHMENTS],
     depth_buffer: Option<GLuint>,
     pub size: Vec2<u32>,
+    bytes: usize, // Approximate size of all attachments
+    generation: usize, // See `graphics::is_current_context`
 }
 
 // This struct must NOT be Clone or Copy
@@ -76,6 +118,7 @@ pub struct ColorAttachmentData {
     handle: GLuint,
     format: TextureFormat,
     multisampled: bool,
+    generation: usize,
 }
 
 impl Framebuffer {
@@ -99,6 +142,10 @@ impl Framebuffer {
 
         let mut error: Option<FramebufferError> = None;
 
+        let pixels = properties.size.x as usize * properties.size.y as usize;
+        let samples = properties.multisample.unwrap_or(1).max(1);
+        let mut bytes = 0;
+
         unsafe {
             gl::GenFramebuffers(1, &mut framebuffer);
             gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
@@ -122,6 +169,7 @@ impl Framebuffer {
                     let mut texture = 0;
                     gl::GenTextures(1, &mut texture);
                     gl::BindTexture(texture_target, texture);
+                    material::note_textures_unknown();
                     if let Some(level) = properties.multisample {
                         gl::TexImage2DMultisample(
                             texture_target,
@@ -147,10 +195,12 @@ impl Framebuffer {
                     }
 
                     gl::FramebufferTexture(gl::FRAMEBUFFER, attachment, texture, 0);
+                    bytes += pixels * samples * format.bytes_per_pixel();
                     color_attachments[i] = Some(ColorAttachmentData {
                         handle: texture,
                         format: format,
                         multisampled: properties.multisample.is_some(),
+                        generation: graphics::context_generation(),
                     });
                 } else {
                     draw_buffers[i] = gl::NONE;
@@ -160,8 +210,15 @@ impl Framebuffer {
 
             gl::DrawBuffers(MAX_COLOR_ATTACHMENTS as GLsizei, draw_buffers.as_ptr());
 
-            // Add depth buffer
-            if properties.depth_buffer {
+            // Add depth and/or stencil buffer
+            // Drivers usually store `DEPTH_COMPONENT` with 24 bits, padded to 32
+            let depth_stencil_format = match (properties.depth_buffer, properties.stencil_buffer) {
+                (true, true)   => Some((gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL_ATTACHMENT, 4)),
+                (true, false)  => Some((gl::DEPTH_COMPONENT, gl::DEPTH_ATTACHMENT, 4)),
+                (false, true)  => Some((gl::STENCIL_INDEX8, gl::STENCIL_ATTACHMENT, 1)),
+                (false, false) => None,
+            };
+            if let Some((internal_format, attachment, bytes_per_pixel)) = depth_stencil_format {
                 let mut depth_buffer_handle = 0;
                 gl::GenRenderbuffers(1, &mut depth_buffer_handle);
                 gl::BindRenderbuffer(gl::RENDERBUFFER, depth_buffer_handle);
@@ -169,20 +226,21 @@ impl Framebuffer {
                     gl::RenderbufferStorageMultisample(
                         gl::RENDERBUFFER,
                         level as GLsizei,
-                        gl::DEPTH_COMPONENT, 
+                        internal_format,
                         properties.size.x as GLint,
                         properties.size.y as GLint
                     );
                 } else {
                     gl::RenderbufferStorage(
                         gl::RENDERBUFFER,
-                        gl::DEPTH_COMPONENT, 
+                        internal_format,
                         properties.size.x as GLint,
                         properties.size.y as GLint
                     );
                 }
-                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, depth_buffer_handle);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, depth_buffer_handle);
                 depth_buffer = Some(depth_buffer_handle);
+                bytes += pixels * samples * bytes_per_pixel;
             }
 
             // Check if framebuffer was sucessfully constructed
@@ -202,33 +260,63 @@ impl Framebuffer {
         if let Some(error) = error {
             return Err(error);
         } else {
+            graphics::note_allocation(ResourceKind::Framebuffer, bytes);
+            graphics::register_object(ObjectKind::Framebuffer, framebuffer);
+
             return Ok(
                 Framebuffer {
                     framebuffer: framebuffer,
                     color_attachments: color_attachments,
                     depth_buffer: depth_buffer,
                     size: properties.size,
+                    bytes,
+                    generation: graphics::context_generation(),
                 }
             );
         }
     }
 
     /// Binds this framebuffer. Subsequent draw operations will modify this framebuffer
-    /// rather than the backbuffer. Note that you probably want to modify the viewport
-    /// to fit this framebuffers size.
+    /// rather than the backbuffer. The viewport is set to cover the whole framebuffer, and the
+    /// viewport of the window is restored by [`unbind`]. To draw to only part of the framebuffer,
+    /// call `graphics::viewport` after binding.
+    ///
+    /// [`unbind`]: #method.unbind
     pub fn bind(&self) {
         unsafe {
+            // Only save the viewport when switching away from the backbuffer, so binding several
+            // framebuffers in a row still restores the window viewport
+            if WINDOW_VIEWPORT.with(Cell::get).is_none() {
+                let mut viewport = [0; 4];
+                gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+                WINDOW_VIEWPORT.with(|v| v.set(Some(viewport)));
+            }
+
             gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.size.x as GLint, self.size.y as GLint);
         }
     }
     
-    /// Binds framebuffer 0, resulting in draw operations drawing to the backbuffer.
+    /// Binds framebuffer 0, resulting in draw operations drawing to the backbuffer. This restores
+    /// the viewport which was set before the first call to [`bind`].
+    ///
+    /// [`bind`]: #method.bind
     pub fn unbind(&self) {
         unsafe {
             gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            if let Some(viewport) = WINDOW_VIEWPORT.with(Cell::take) {
+                gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            }
         }
     }
 
+    /// Sets a name for this framebuffer, which is shown by `graphics::live_objects` and, if
+    /// `KHR_debug` is supported, in OpenGL debug messages and graphics debuggers.
+    pub fn set_debug_label(&mut self, name: &str) {
+        graphics::set_object_label(ObjectKind::Framebuffer, self.framebuffer, name);
+    }
+
     /// Moves the contents of this framebuffer to the given framebuffer, resolving multisampling
     /// if present. Note that this also unbinds this framebuffer
     pub fn blit_to_framebuffer(&self, other: &Framebuffer, buffers: Blit) {
@@ -350,6 +438,8 @@ impl Framebuffer {
         unsafe {
             gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
             gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + index as u32);
+            // Rows of single- and two-channel formats are not necessarily 4 byte aligned
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
             gl::ReadPixels(
                 pos.x as GLint, pos.y as GLint, 
                 size.x as GLsizei, size.y as GLsizei,
@@ -367,11 +457,7 @@ impl Framebuffer {
 
 // The max value that `FramebufferProperties::multisample` may have
 pub fn max_samples() -> usize {
-    let mut result = 0;
-    unsafe {
-        gl::GetIntegerv(gl::MAX_SAMPLES, &mut result);
-    }
-    result as usize
+    graphics::capabilities().max_samples as usize
 }
 
 impl ColorAttachmentData {
@@ -384,25 +470,35 @@ impl ColorAttachmentData {
             let target = if self.multisampled { gl::TEXTURE_2D_MULTISAMPLE } else { gl::TEXTURE_2D };
             gl::BindTexture(target, self.handle);
         }
+        if !self.multisampled {
+            material::note_texture(unit, self.handle);
+        }
     }
 }
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteFramebuffers(1, &self.framebuffer);
-            if let Some(depth_buffer) = self.depth_buffer {
-                gl::DeleteRenderbuffers(1, &depth_buffer);
+        if graphics::is_current_context(self.generation) {
+            unsafe {
+                gl::DeleteFramebuffers(1, &self.framebuffer);
+                if let Some(depth_buffer) = self.depth_buffer {
+                    gl::DeleteRenderbuffers(1, &depth_buffer);
+                }
+                // Color attachments are managed by the `ColorAttachmentData` struct, and are automatically deleted
             }
-            // Color attachments are managed by the `ColorAttachmentData` struct, and are automatically deleted
+            graphics::unregister_object(ObjectKind::Framebuffer, self.framebuffer);
         }
+        graphics::note_free(ResourceKind::Framebuffer, self.bytes);
     }
 }
 
 impl Drop for ColorAttachmentData {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteTextures(1, &self.handle);
+        if graphics::is_current_context(self.generation) {
+            unsafe {
+                gl::DeleteTextures(1, &self.handle);
+            }
+            material::note_textures_unknown();
         }
     }
 }
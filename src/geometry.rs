@@ -0,0 +1,217 @@
+
+//! 2D intersection and overlap tests, for picking and simple physics.
+//!
+//! Rays and segments are given as a start point and a direction. The direction does not need to
+//! be normalized, and the `t` of a [`Hit`] is measured in multiples of it. For a segment from `a`
+//! to `b`, pass `b - a` as the direction, and `t` will be between 0 and 1.
+//!
+//! [`Hit`]: struct.Hit.html
+
+use cable_math::Vec2;
+
+use Region;
+
+/// Where a ray or moving object hits something.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hit {
+    /// How far along the direction the hit happened.
+    pub t: f32,
+    /// The point at which the hit happened.
+    pub point: Vec2<f32>,
+    /// The normal of the surface which was hit. This is zero if the ray started inside the
+    /// object.
+    pub normal: Vec2<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Circle {
+    pub center: Vec2<f32>,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub fn new(center: Vec2<f32>, radius: f32) -> Circle {
+        Circle { center, radius }
+    }
+
+    pub fn contains(&self, p: Vec2<f32>) -> bool {
+        (p - self.center).len_sqr() < self.radius*self.radius
+    }
+
+    pub fn overlaps(&self, other: Circle) -> bool {
+        let r = self.radius + other.radius;
+        (other.center - self.center).len_sqr() < r*r
+    }
+
+    pub fn overlaps_region(&self, region: Region) -> bool {
+        let closest = region.clip(self.center);
+        self.contains(closest)
+    }
+
+    /// The smallest region containing this circle.
+    pub fn bounds(&self) -> Region {
+        let r = Vec2::new(self.radius, self.radius);
+        Region { min: self.center - r, max: self.center + r }
+    }
+}
+
+/// Checks if two regions overlap. Regions which only touch along an edge do not overlap.
+pub fn regions_overlap(a: Region, b: Region) -> bool {
+    a.min.x < b.max.x && a.max.x > b.min.x &&
+    a.min.y < b.max.y && a.max.y > b.min.y
+}
+
+/// Finds where a ray first hits the given region. Returns a hit with `t = 0` if the ray starts
+/// inside the region. Hits behind the start of the ray are ignored.
+pub fn ray_region(origin: Vec2<f32>, dir: Vec2<f32>, region: Region) -> Option<Hit> {
+    let mut t_enter = 0.0;
+    let mut t_exit = ::std::f32::INFINITY;
+    let mut normal = Vec2::ZERO;
+
+    // Slab test, one axis at a time
+    let axes = [
+        (origin.x, dir.x, region.min.x, region.max.x, Vec2::new(1.0, 0.0)),
+        (origin.y, dir.y, region.min.y, region.max.y, Vec2::new(0.0, 1.0)),
+    ];
+    for &(o, d, min, max, axis) in axes.iter() {
+        if d == 0.0 {
+            if o < min || o > max {
+                return None;
+            }
+            continue;
+        }
+
+        let mut near = (min - o) / d;
+        let mut far = (max - o) / d;
+        let mut face_normal = -axis;
+        if near > far {
+            ::std::mem::swap(&mut near, &mut far);
+            face_normal = axis;
+        }
+
+        if near > t_enter {
+            t_enter = near;
+            normal = face_normal;
+        }
+        t_exit = f32::min(t_exit, far);
+
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+
+    Some(Hit { t: t_enter, point: origin + dir*t_enter, normal })
+}
+
+/// Finds where the segment from `a` to `b` first hits the given region.
+pub fn segment_region(a: Vec2<f32>, b: Vec2<f32>, region: Region) -> Option<Hit> {
+    ray_region(a, b - a, region).filter(|hit| hit.t <= 1.0)
+}
+
+/// Finds where the region `moving` first touches `target` when it is moved by `delta`. `t` is
+/// the fraction of `delta` which can be moved before touching, and `point` is where the center of
+/// `moving` is at that time. Returns a hit with `t = 0` if the regions already overlap.
+pub fn sweep_region(moving: Region, delta: Vec2<f32>, target: Region) -> Option<Hit> {
+    // Grow the target by the size of the moving region, and trace the center instead
+    let half_size = moving.size() / 2.0;
+    let expanded = Region {
+        min: target.min - half_size,
+        max: target.max + half_size,
+    };
+    let center = moving.center();
+    segment_region(center, center + delta, expanded)
+}
+
+/// Finds where a ray first hits the given circle. Returns a hit with `t = 0` if the ray starts
+/// inside the circle.
+pub fn ray_circle(origin: Vec2<f32>, dir: Vec2<f32>, circle: Circle) -> Option<Hit> {
+    let offset = origin - circle.center;
+    let c = offset.len_sqr() - circle.radius*circle.radius;
+    if c <= 0.0 {
+        return Some(Hit { t: 0.0, point: origin, normal: Vec2::ZERO });
+    }
+
+    let a = dir.len_sqr();
+    let b = Vec2::dot(offset, dir);
+    let discriminant = b*b - a*c;
+    if a == 0.0 || b > 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    let point = origin + dir*t;
+    Some(Hit { t, point, normal: (point - circle.center) / circle.radius })
+}
+
+/// Finds the point where the segments `a0` to `a1` and `b0` to `b1` cross. Parallel segments
+/// never intersect.
+pub fn segment_segment(a0: Vec2<f32>, a1: Vec2<f32>, b0: Vec2<f32>, b1: Vec2<f32>) -> Option<Vec2<f32>> {
+    let r = a1 - a0;
+    let s = b1 - b0;
+    let denom = Vec2::cross(r, s);
+    if denom == 0.0 {
+        return None;
+    }
+
+    let t = Vec2::cross(b0 - a0, s) / denom;
+    let u = Vec2::cross(b0 - a0, r) / denom;
+    if t >= 0.0 && t <= 1.0 && u >= 0.0 && u <= 1.0 {
+        Some(a0 + r*t)
+    } else {
+        None
+    }
+}
+
+/// Finds the point on the segment from `a` to `b` which is closest to `p`.
+pub fn closest_point_on_segment(a: Vec2<f32>, b: Vec2<f32>, p: Vec2<f32>) -> Vec2<f32> {
+    let ab = b - a;
+    let len_sqr = ab.len_sqr();
+    if len_sqr == 0.0 {
+        return a;
+    }
+
+    let t = Vec2::dot(p - a, ab) / len_sqr;
+    a + ab*t.max(0.0).min(1.0)
+}
+
+/// Finds the point on the border or inside of the given region which is closest to `p`. This is
+/// the same as `region.clip(p)`.
+pub fn closest_point_in_region(region: Region, p: Vec2<f32>) -> Vec2<f32> {
+    region.clip(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersections() {
+        let region = Region { min: Vec2::new(0.0, 0.0), max: Vec2::new(10.0, 10.0) };
+
+        let hit = ray_region(Vec2::new(-5.0, 5.0), Vec2::new(1.0, 0.0), region).unwrap();
+        assert_eq!(hit.t, 5.0);
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+        assert!(ray_region(Vec2::new(-5.0, 5.0), Vec2::new(-1.0, 0.0), region).is_none());
+        assert!(segment_region(Vec2::new(-5.0, 5.0), Vec2::new(-1.0, 5.0), region).is_none());
+        assert_eq!(ray_region(Vec2::new(5.0, 5.0), Vec2::new(0.0, 1.0), region).unwrap().t, 0.0);
+
+        let moving = Region { min: Vec2::new(2.0, 20.0), max: Vec2::new(4.0, 22.0) };
+        let hit = sweep_region(moving, Vec2::new(0.0, -20.0), region).unwrap();
+        assert_eq!(hit.t, 0.5);
+        assert_eq!(hit.normal, Vec2::new(0.0, 1.0));
+
+        let crossing = segment_segment(
+            Vec2::new(0.0, 0.0), Vec2::new(2.0, 2.0),
+            Vec2::new(0.0, 2.0), Vec2::new(2.0, 0.0),
+        );
+        assert_eq!(crossing, Some(Vec2::new(1.0, 1.0)));
+
+        let circle = Circle::new(Vec2::new(12.0, 5.0), 3.0);
+        assert!(circle.overlaps_region(region));
+        assert!(!Circle::new(Vec2::new(13.0, 13.0), 3.0).overlaps_region(region));
+        assert_eq!(ray_circle(Vec2::new(0.0, 5.0), Vec2::new(1.0, 0.0), circle).unwrap().t, 9.0);
+
+        let closest = closest_point_on_segment(Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), Vec2::new(6.0, 3.0));
+        assert_eq!(closest, Vec2::new(4.0, 0.0));
+    }
+}
@@ -1,12 +1,22 @@
 
 //! Wrappers for unsafe OpenGL calls
 
+use std::rc::Rc;
+use std::ffi::CStr;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::backtrace::Backtrace;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use gl;
 use gl::types::*;
 
 use cable_math::Vec2;
 
-use {Color, Region};
+use {Color, Region, Time};
+use material;
+use framebuffer;
 
 /// Sets the OpenGL viewport
 ///
@@ -48,6 +58,163 @@ pub fn set_scissor(region: Option<Region>, win_size: Vec2<f32>) {
     }
 } 
 
+/// Limits and features of the OpenGL implementation. These are queried once, when the window
+/// (and thus the OpenGL context) is created. Retrieve them with [`capabilities`].
+///
+/// [`capabilities`]: fn.capabilities.html
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// The `(major, minor)` version of the context.
+    pub version: (u32, u32),
+    /// Whether this is a OpenGL ES context.
+    pub gles: bool,
+    pub vendor: String,
+    pub renderer: String,
+
+    /// The maximum width and height of a texture.
+    pub max_texture_size: u32,
+    /// The maximum number of texels in a texture buffer.
+    pub max_texture_buffer_size: u32,
+    /// The number of texture units available across all shader stages.
+    pub max_texture_units: u32,
+    /// The maximum size, in bytes, of a uniform block.
+    pub max_uniform_block_size: u32,
+    /// The number of binding indices for uniform buffers. See `Shader::bind_uniform_block`.
+    pub max_uniform_buffer_bindings: u32,
+    /// The number of vertex attributes available in vertex shaders.
+    pub max_vertex_attribs: u32,
+    /// The maximum number of samples for multisampled framebuffers.
+    pub max_samples: u32,
+    /// The maximum number of color attachments on a single framebuffer.
+    pub max_color_attachments: u32,
+
+    extensions: Vec<String>,
+}
+
+impl Capabilities {
+    /// Checks whether the given extension is supported, e.g. `"GL_ARB_texture_buffer_object_rgb32"`.
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+
+    /// All extensions supported by the OpenGL implementation.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Checks whether the version of the context is at least `major.minor`. Note that OpenGL and
+    /// OpenGL ES use different version numbers, see `gles`.
+    pub fn version_at_least(&self, major: u32, minor: u32) -> bool {
+        self.version >= (major, minor)
+    }
+
+    /// Whether immutable texture storage (`glTexStorage2D`) is available. This is core in OpenGL
+    /// 4.2 and OpenGL ES 3.0.
+    pub fn texture_storage_supported(&self) -> bool {
+        if self.gles {
+            self.version_at_least(3, 0)
+        } else {
+            self.version_at_least(4, 2) || self.has_extension("GL_ARB_texture_storage")
+        }
+    }
+
+    /// Whether debug output (`glDebugMessageCallback`) is available. This is core in OpenGL 4.3
+    /// and OpenGL ES 3.2.
+    pub fn debug_output_supported(&self) -> bool {
+        if self.gles {
+            self.version_at_least(3, 2)
+        } else {
+            self.version_at_least(4, 3) || self.has_extension("GL_KHR_debug")
+        }
+    }
+
+    fn query() -> Capabilities {
+        fn integer(name: GLenum) -> u32 {
+            let mut value = 0;
+            unsafe { gl::GetIntegerv(name, &mut value) };
+            value.max(0) as u32
+        }
+
+        fn string(raw: *const GLubyte) -> String {
+            if raw.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(raw as *const _) }.to_string_lossy().into_owned()
+            }
+        }
+
+        let version_string = string(unsafe { gl::GetString(gl::VERSION) });
+
+        let extension_count = integer(gl::NUM_EXTENSIONS);
+        let extensions = (0..extension_count)
+            .map(|i| string(unsafe { gl::GetStringi(gl::EXTENSIONS, i) }))
+            .collect();
+
+        Capabilities {
+            version: (integer(gl::MAJOR_VERSION), integer(gl::MINOR_VERSION)),
+            gles: version_string.starts_with("OpenGL ES"),
+            vendor: string(unsafe { gl::GetString(gl::VENDOR) }),
+            renderer: string(unsafe { gl::GetString(gl::RENDERER) }),
+
+            max_texture_size: integer(gl::MAX_TEXTURE_SIZE),
+            max_texture_buffer_size: integer(gl::MAX_TEXTURE_BUFFER_SIZE),
+            max_texture_units: integer(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS),
+            max_uniform_block_size: integer(gl::MAX_UNIFORM_BLOCK_SIZE),
+            max_uniform_buffer_bindings: integer(gl::MAX_UNIFORM_BUFFER_BINDINGS),
+            max_vertex_attribs: integer(gl::MAX_VERTEX_ATTRIBS),
+            max_samples: integer(gl::MAX_SAMPLES),
+            max_color_attachments: integer(gl::MAX_COLOR_ATTACHMENTS),
+
+            extensions,
+        }
+    }
+}
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+/// Retrieves the capabilities of the OpenGL implementation. 
+///
+/// # Panics
+/// If no window has been created yet.
+pub fn capabilities() -> &'static Capabilities {
+    CAPABILITIES.get().expect("`graphics::capabilities` called before a window was created")
+}
+
+// Called by the window once the OpenGL context is current
+pub(crate) fn load_capabilities() {
+    CAPABILITIES.get_or_init(Capabilities::query);
+    LIVE_CONTEXTS.fetch_add(1, Ordering::SeqCst);
+}
+
+// Called by the window when a debug context was requested. Messages are printed as they are
+// generated, which makes it easy to find the offending call with a debugger.
+pub(crate) fn enable_debug_output() {
+    if !capabilities().debug_output_supported() || !gl::DebugMessageCallback::is_loaded() {
+        return;
+    }
+
+    extern "system" fn callback(
+        _source: GLenum, _kind: GLenum, id: GLuint, severity: GLenum,
+        length: GLsizei, message: *const GLchar,
+        _user_data: *mut ::std::os::raw::c_void,
+    ) {
+        let severity = match severity {
+            gl::DEBUG_SEVERITY_HIGH   => "high",
+            gl::DEBUG_SEVERITY_MEDIUM => "medium",
+            gl::DEBUG_SEVERITY_LOW    => "low",
+            _ => return, // Notifications are mostly noise
+        };
+
+        let message = unsafe { ::std::slice::from_raw_parts(message as *const u8, length.max(0) as usize) };
+        println!("OpenGL debug message ({} severity, id {}): {}", severity, id, String::from_utf8_lossy(message));
+    }
+
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(Some(callback), ::std::ptr::null());
+    }
+}
+
 /// Prints all OpenGL errors.
 pub fn print_errors() {
     unsafe {
@@ -137,6 +304,110 @@ pub fn clear(color: Option<Color>, depth: bool, stencil: bool) {
     }
 }
 
+/// Makes sure all previously issued commands are sent to the GPU, without waiting for them to
+/// finish. This is needed before waiting for a [`FenceSync`] from another context.
+///
+/// [`FenceSync`]: struct.FenceSync.html
+pub fn flush() {
+    unsafe { gl::Flush() };
+}
+
+/// Blocks until all previously issued commands have finished executing. This stalls both the CPU
+/// and the GPU, so prefer a [`FenceSync`] to find out when specific commands have finished.
+///
+/// [`FenceSync`]: struct.FenceSync.html
+pub fn finish() {
+    unsafe { gl::Finish() };
+}
+
+/// A marker in the OpenGL command stream, which becomes signaled once all commands issued before
+/// it have finished executing on the GPU. This can be used to find out when the GPU is done
+/// reading from a region of a buffer before overwriting it, or when data read back into a pixel
+/// pack buffer is available, without stalling like [`finish`].
+///
+/// Fences are shared between contexts which share objects, so they can also be used to hand
+/// resources from one thread to another.
+///
+/// ```rust,ignore
+/// // After drawing from a region of a streaming buffer
+/// region_fences[frame % 3] = Some(FenceSync::new());
+///
+/// // Before overwriting the region again
+/// if let Some(fence) = region_fences[frame % 3].take() {
+///     fence.client_wait(None);
+/// }
+/// ```
+///
+/// [`finish`]: fn.finish.html
+pub struct FenceSync {
+    sync: GLsync,
+}
+
+// Sync objects are shared between contexts
+unsafe impl Send for FenceSync {}
+
+/// The result of waiting for a [`FenceSync`].
+///
+/// [`FenceSync`]: struct.FenceSync.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// All commands before the fence have finished.
+    Signaled,
+    /// The timeout passed before the fence was signaled.
+    TimedOut,
+    /// An error occured, e.g. because the context was lost.
+    Failed,
+}
+
+impl FenceSync {
+    /// Inserts a new fence into the command stream of the current context.
+    pub fn new() -> FenceSync {
+        let sync = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        FenceSync { sync }
+    }
+
+    /// Checks whether the fence is signaled, without blocking or flushing.
+    pub fn is_signaled(&self) -> bool {
+        let mut status = 0;
+        unsafe {
+            gl::GetSynciv(self.sync, gl::SYNC_STATUS, 1, ::std::ptr::null_mut(), &mut status);
+        }
+        status as GLenum == gl::SIGNALED
+    }
+
+    /// Blocks the calling thread until the fence is signaled, or until `timeout` has passed.
+    /// Commands issued in the current context are flushed first, so this does not block forever if
+    /// the fence was created in the current context and has not been flushed yet.
+    pub fn client_wait(&self, timeout: Option<Time>) -> SyncStatus {
+        let timeout = match timeout {
+            Some(time) => time.0,
+            None => gl::TIMEOUT_IGNORED,
+        };
+
+        let result = unsafe { gl::ClientWaitSync(self.sync, gl::SYNC_FLUSH_COMMANDS_BIT, timeout) };
+        match result {
+            gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => SyncStatus::Signaled,
+            gl::TIMEOUT_EXPIRED => SyncStatus::TimedOut,
+            _ => SyncStatus::Failed,
+        }
+    }
+
+    /// Makes the GPU wait for this fence before executing further commands from the current
+    /// context. Unlike [`client_wait`], this returns immediately. This is useful when the fence
+    /// was created in another context.
+    ///
+    /// [`client_wait`]: #method.client_wait
+    pub fn server_wait(&self) {
+        unsafe { gl::WaitSync(self.sync, 0, gl::TIMEOUT_IGNORED) };
+    }
+}
+
+impl Drop for FenceSync {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteSync(self.sync) };
+    }
+}
+
 /// Toggles depth testing. This only has an effect if the currently bound framebuffer
 /// has a depthbuffer (The backbuffer always has a depthbuffer).
 pub fn set_depth_testing(enabled: bool) {
@@ -182,6 +453,96 @@ pub enum DepthFunction {
     GreaterOrEqual  = gl::GEQUAL,
 }
 
+/// Toggles stencil testing. This only has an effect if the currently bound framebuffer has a
+/// stencil buffer. When stencil testing is disabled the stencil buffer is also not written to.
+pub fn set_stencil_testing(enabled: bool) {
+    unsafe {
+        if enabled {
+            gl::Enable(gl::STENCIL_TEST);
+        } else {
+            gl::Disable(gl::STENCIL_TEST);
+        }
+    }
+}
+
+/// Sets the function used to check if a fragment passes the stencil test. Both `reference` and
+/// the stored stencil value are combined with `mask` using a bitwise and before being compared.
+/// The initial value is `Always`, with a reference of `0` and a mask of `0xff`.
+pub fn set_stencil_function(function: StencilFunction, reference: u8, mask: u8) {
+    unsafe {
+        gl::StencilFunc(function as GLenum, reference as GLint, mask as GLuint);
+    }
+}
+
+/// Sets how the stencil buffer is modified when a fragment fails the stencil test, passes the
+/// stencil test but fails the depth test, or passes both tests. The initial value is `Keep` for
+/// all three cases.
+pub fn set_stencil_operation(stencil_fail: StencilOp, depth_fail: StencilOp, pass: StencilOp) {
+    unsafe {
+        gl::StencilOp(stencil_fail as GLenum, depth_fail as GLenum, pass as GLenum);
+    }
+}
+
+/// Sets which bits of the stencil buffer can be written to. The initial value is `0xff`.
+pub fn set_stencil_write_mask(mask: u8) {
+    unsafe {
+        gl::StencilMask(mask as GLuint);
+    }
+}
+
+/// Toggles writing to the color buffers. This is mostly useful to only write to the depth or
+/// stencil buffer, e.g. when rendering masks.
+pub fn set_color_writing(enabled: bool) {
+    let enabled = if enabled { gl::TRUE } else { gl::FALSE };
+    unsafe {
+        gl::ColorMask(enabled, enabled, enabled, enabled);
+    }
+}
+
+#[repr(u32)] // GLenum is u32
+#[derive(Copy, Clone, Debug)]
+pub enum StencilFunction {
+    /// The stencil test never passes.
+    Never           = gl::NEVER,
+    /// The stencil test always passes.
+    Always          = gl::ALWAYS,
+    /// Only passes if the reference value is equal to the stored value.
+    Equal           = gl::EQUAL,
+    /// Only passes if the reference value is not equal to the stored value.
+    NotEqual        = gl::NOTEQUAL,
+
+    /// Only passes if the reference value is less than the stored value.
+    Less            = gl::LESS,
+    /// Only passes if the reference value is less than or equal to the stored value.
+    LessOrEqual     = gl::LEQUAL,
+
+    /// Only passes if the reference value is greater than the stored value.
+    Greater         = gl::GREATER,
+    /// Only passes if the reference value is greater than or equal to the stored value.
+    GreaterOrEqual  = gl::GEQUAL,
+}
+
+#[repr(u32)] // GLenum is u32
+#[derive(Copy, Clone, Debug)]
+pub enum StencilOp {
+    /// Keeps the stored value.
+    Keep            = gl::KEEP,
+    /// Sets the stored value to `0`.
+    Zero            = gl::ZERO,
+    /// Replaces the stored value with the reference value.
+    Replace         = gl::REPLACE,
+    /// Increments the stored value, clamping at the maximum value.
+    Increment       = gl::INCR,
+    /// Increments the stored value, wrapping to `0` at the maximum value.
+    IncrementWrap   = gl::INCR_WRAP,
+    /// Decrements the stored value, clamping at `0`.
+    Decrement       = gl::DECR,
+    /// Decrements the stored value, wrapping to the maximum value at `0`.
+    DecrementWrap   = gl::DECR_WRAP,
+    /// Bitwise inverts the stored value.
+    Invert          = gl::INVERT,
+}
+
 /// If passed `Some` enables the given blend settings. If passed `None` disables
 /// blending.
 pub fn set_blending(blending: Option<BlendSettings>) {
@@ -251,6 +612,12 @@ pub enum BlendFactor {
     OneMinusConstantColor   = gl::ONE_MINUS_CONSTANT_COLOR,
     ConstantAlpha           = gl::CONSTANT_ALPHA,
     OneMinusConstantAlpha   = gl::ONE_MINUS_CONSTANT_ALPHA,
+    /// The second output of the fragment shader, declared with `layout(location = 0, index = 1)`.
+    /// This is known as dual-source blending.
+    Src1Color               = gl::SRC1_COLOR,
+    OneMinusSrc1Color       = gl::ONE_MINUS_SRC1_COLOR,
+    Src1Alpha               = gl::SRC1_ALPHA,
+    OneMinusSrc1Alpha       = gl::ONE_MINUS_SRC1_ALPHA,
 }
 
 #[repr(u32)] // GLenum is u32
@@ -282,3 +649,456 @@ pub enum PolygonMode {
     Point = gl::POINT, 
     Fill  = gl::FILL,
 }
+
+/// Counters for work submitted to the GPU. Recording is disabled by default, and is enabled with
+/// [`set_draw_stats_enabled`]. Call [`take_draw_stats`] once per frame to retrieve the counters for
+/// that frame.
+///
+/// Only work submitted through this library is counted, e.g. through [`VertexBuffer`] or
+/// [`DrawGroup`].
+///
+/// [`set_draw_stats_enabled`]: fn.set_draw_stats_enabled.html
+/// [`take_draw_stats`]: fn.take_draw_stats.html
+/// [`VertexBuffer`]: ../buffer/struct.VertexBuffer.html
+/// [`DrawGroup`]: ../draw_group/struct.DrawGroup.html
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    /// The number of draw calls issued.
+    pub draw_calls: usize,
+    /// The number of vertices (or indices, for indexed draws) submitted in draw calls.
+    pub vertices: usize,
+    /// The number of times a `DrawGroup` switched textures while drawing.
+    pub texture_changes: usize,
+    /// The number of other state changes done by a `DrawGroup` while drawing, e.g. changes to the
+    /// clip region.
+    pub state_changes: usize,
+    /// The number of bytes uploaded to GPU buffers.
+    pub bytes_uploaded: usize,
+    /// The number of times a GPU buffer was reallocated because it had to grow or shrink.
+    pub buffer_reallocations: usize,
+}
+
+thread_local! {
+    static DRAW_STATS_ENABLED: Cell<bool> = Cell::new(false);
+    static DRAW_STATS: Cell<DrawStats> = Cell::new(DrawStats::default());
+}
+
+/// Toggles recording of [`DrawStats`] on the current thread.
+///
+/// [`DrawStats`]: struct.DrawStats.html
+pub fn set_draw_stats_enabled(enabled: bool) {
+    DRAW_STATS_ENABLED.with(|e| e.set(enabled));
+}
+
+/// Returns the [`DrawStats`] recorded since the last call to this function, and resets all
+/// counters to zero.
+///
+/// [`DrawStats`]: struct.DrawStats.html
+pub fn take_draw_stats() -> DrawStats {
+    DRAW_STATS.with(|stats| stats.replace(DrawStats::default()))
+}
+
+pub(crate) fn record_draw_stats<F: FnOnce(&mut DrawStats)>(f: F) {
+    if DRAW_STATS_ENABLED.with(|e| e.get()) {
+        DRAW_STATS.with(|cell| {
+            let mut stats = cell.get();
+            f(&mut stats);
+            cell.set(stats);
+        });
+    }
+}
+
+pub(crate) fn record_draw_call(vertices: usize) {
+    record_draw_stats(|stats| {
+        stats.draw_calls += 1;
+        stats.vertices += vertices;
+    });
+}
+
+pub(crate) fn record_upload(bytes: usize) {
+    record_draw_stats(|stats| stats.bytes_uploaded += bytes);
+}
+
+/// The approximate amount of GPU memory used by one kind of resource.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The number of live objects.
+    pub count: usize,
+    /// The sum of the sizes of all live objects, in bytes.
+    pub bytes: usize,
+}
+
+/// The approximate amount of GPU memory used by all live resources, see [`memory_report`].
+///
+/// Sizes are computed from the dimensions and formats passed to OpenGL when storage is allocated.
+/// Drivers may add padding, mipmaps generated by OpenGL are not counted, and resources created
+/// directly through OpenGL are not tracked at all, so this is a lower bound on actual usage.
+///
+/// [`memory_report`]: fn.memory_report.html
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// All live [`Texture`]s, including textures which have not been given any storage yet.
+    ///
+    /// [`Texture`]: ../texture/struct.Texture.html
+    pub textures: ResourceUsage,
+    /// All vertex and primitive buffers which have allocated storage.
+    pub buffers: ResourceUsage,
+    /// All live framebuffers, including their color attachments and depth/stencil buffers.
+    pub framebuffers: ResourceUsage,
+}
+
+impl MemoryReport {
+    /// The total number of bytes used by all resources.
+    pub fn total_bytes(&self) -> usize {
+        self.textures.bytes + self.buffers.bytes + self.framebuffers.bytes
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ResourceKind {
+    Texture,
+    Buffer,
+    Framebuffer,
+}
+
+// Shared between threads, as resources are sometimes dropped on a different thread than the one
+// they were created on.
+static MEMORY: Mutex<MemoryReport> = Mutex::new(MemoryReport {
+    textures: ResourceUsage { count: 0, bytes: 0 },
+    buffers: ResourceUsage { count: 0, bytes: 0 },
+    framebuffers: ResourceUsage { count: 0, bytes: 0 },
+});
+
+/// Returns the approximate amount of GPU memory used by all live textures, buffers and
+/// framebuffers created through this library. Useful for tracking down resource leaks, e.g. by
+/// logging the report once in a while and checking whether any counter keeps growing.
+pub fn memory_report() -> MemoryReport {
+    MEMORY.lock().map(|report| *report).unwrap_or_default()
+}
+
+fn update_memory<F: FnOnce(&mut ResourceUsage)>(kind: ResourceKind, f: F) {
+    if let Ok(mut report) = MEMORY.lock() {
+        let usage = match kind {
+            ResourceKind::Texture     => &mut report.textures,
+            ResourceKind::Buffer      => &mut report.buffers,
+            ResourceKind::Framebuffer => &mut report.framebuffers,
+        };
+        f(usage);
+    }
+}
+
+/// Should be called when a resource is created, with the size of its storage.
+pub(crate) fn note_allocation(kind: ResourceKind, bytes: usize) {
+    update_memory(kind, |usage| {
+        usage.count += 1;
+        usage.bytes += bytes;
+    });
+}
+
+/// Should be called when the storage of a live resource is reallocated with a different size.
+pub(crate) fn note_reallocation(kind: ResourceKind, old_bytes: usize, new_bytes: usize) {
+    update_memory(kind, |usage| {
+        usage.bytes = usage.bytes.saturating_sub(old_bytes) + new_bytes;
+    });
+}
+
+/// Should be called when a resource is deleted, with the size passed to the last call to
+/// `note_allocation` or `note_reallocation` for that resource.
+pub(crate) fn note_free(kind: ResourceKind, bytes: usize) {
+    update_memory(kind, |usage| {
+        usage.count = usage.count.saturating_sub(1);
+        usage.bytes = usage.bytes.saturating_sub(bytes);
+    });
+}
+
+/// A kind of OpenGL object which is tracked by the registry returned by [`live_objects`], and
+/// which can be given a debug label.
+///
+/// [`live_objects`]: fn.live_objects.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Texture,
+    /// Vertex buffers and primitive buffers.
+    Buffer,
+    Shader,
+    Framebuffer,
+}
+
+impl ObjectKind {
+    // The identifier passed to `glObjectLabel`
+    fn gl_identifier(self) -> GLenum {
+        match self {
+            ObjectKind::Texture     => gl::TEXTURE,
+            ObjectKind::Buffer      => gl::BUFFER,
+            ObjectKind::Shader      => gl::PROGRAM,
+            ObjectKind::Framebuffer => gl::FRAMEBUFFER,
+        }
+    }
+}
+
+/// A OpenGL object which has been created but not yet dropped, see [`live_objects`].
+///
+/// [`live_objects`]: fn.live_objects.html
+#[derive(Debug, Clone)]
+pub struct LiveObject {
+    pub kind: ObjectKind,
+    /// The OpenGL name of this object.
+    pub id: GLuint,
+    /// The label set with `set_debug_label`, if any.
+    pub label: Option<String>,
+    /// Where this object was created. This is only captured if the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variables are set, see `Backtrace::capture`.
+    pub backtrace: Arc<Backtrace>,
+}
+
+// Only used in debug builds
+static LIVE_OBJECTS: OnceLock<Mutex<HashMap<(ObjectKind, GLuint), LiveObject>>> = OnceLock::new();
+static LIVE_CONTEXTS: AtomicUsize = AtomicUsize::new(0);
+
+fn with_live_objects<F: FnOnce(&mut HashMap<(ObjectKind, GLuint), LiveObject>)>(f: F) {
+    if cfg!(debug_assertions) {
+        let objects = LIVE_OBJECTS.get_or_init(|| Mutex::new(HashMap::new()));
+        if let Ok(mut objects) = objects.lock() {
+            f(&mut objects);
+        }
+    }
+}
+
+/// Returns all textures, buffers, shaders and framebuffers which have been created through this
+/// library but not yet dropped, sorted by kind and id. This is useful for finding objects which
+/// are never dropped.
+///
+/// Objects are only tracked in debug builds. In release builds this always returns an empty list.
+pub fn live_objects() -> Vec<LiveObject> {
+    let mut result = Vec::new();
+    with_live_objects(|objects| result.extend(objects.values().cloned()));
+    result.sort_by_key(|object| (object.kind as usize, object.id));
+    result
+}
+
+/// Prints all objects returned by [`live_objects`], including where they were created if
+/// backtraces are enabled.
+///
+/// [`live_objects`]: fn.live_objects.html
+pub fn print_live_objects() {
+    let objects = live_objects();
+    println!("{} live OpenGL objects", objects.len());
+    for object in objects.iter() {
+        println!("{}", describe_object(object.kind, object.id, object.label.as_ref().map(String::as_str)));
+        print_backtrace(&object.backtrace);
+    }
+}
+
+fn describe_object(kind: ObjectKind, id: GLuint, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{:?} {} (\"{}\")", kind, id, label),
+        None => format!("{:?} {}", kind, id),
+    }
+}
+
+fn print_backtrace(backtrace: &Backtrace) {
+    use std::backtrace::BacktraceStatus;
+    if backtrace.status() == BacktraceStatus::Captured {
+        println!("Created at:\n{}", backtrace);
+    }
+}
+
+// Called by the window when its OpenGL context is destroyed
+pub(crate) fn note_context_destroyed() {
+    LIVE_CONTEXTS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Should be called when a OpenGL object is created.
+pub(crate) fn register_object(kind: ObjectKind, id: GLuint) {
+    if id == 0 {
+        return;
+    }
+
+    with_live_objects(|objects| {
+        objects.insert((kind, id), LiveObject {
+            kind, id,
+            label: None,
+            backtrace: Arc::new(Backtrace::capture()),
+        });
+    });
+}
+
+/// Should be called when a OpenGL object is deleted. In debug builds this warns if the object is
+/// deleted after the OpenGL context was destroyed, as the deletion then does nothing.
+pub(crate) fn unregister_object(kind: ObjectKind, id: GLuint) {
+    if id == 0 {
+        return;
+    }
+
+    with_live_objects(|objects| {
+        let object = objects.remove(&(kind, id));
+
+        if LIVE_CONTEXTS.load(Ordering::SeqCst) == 0 {
+            let label = object.as_ref().and_then(|o| o.label.as_ref()).map(String::as_str);
+            println!("Warning: {} was dropped after the OpenGL context was destroyed", describe_object(kind, id, label));
+            if let Some(ref object) = object {
+                print_backtrace(&object.backtrace);
+            }
+        }
+    });
+}
+
+/// Should be called when a object is replaced by a new OpenGL object, e.g. when a buffer is
+/// reallocated. The new object keeps the label and creation backtrace of the old object.
+pub(crate) fn move_object(kind: ObjectKind, old_id: GLuint, new_id: GLuint) {
+    with_live_objects(|objects| {
+        if let Some(mut object) = objects.remove(&(kind, old_id)) {
+            object.id = new_id;
+            objects.insert((kind, new_id), object);
+        }
+    });
+}
+
+/// Sets the debug label of the given object. The label is shown by [`live_objects`], and is
+/// passed to `glObjectLabel` if `KHR_debug` is supported, so it also shows up in debug messages
+/// and in tools such as apitrace or RenderDoc.
+///
+/// [`live_objects`]: fn.live_objects.html
+pub(crate) fn set_object_label(kind: ObjectKind, id: GLuint, label: &str) {
+    if id == 0 {
+        return;
+    }
+
+    if capabilities().debug_output_supported() && gl::ObjectLabel::is_loaded() {
+        unsafe {
+            gl::ObjectLabel(kind.gl_identifier(), id, label.len() as GLsizei, label.as_ptr() as *const GLchar);
+        }
+    }
+
+    with_live_objects(|objects| {
+        if let Some(object) = objects.get_mut(&(kind, id)) {
+            object.label = Some(label.to_owned());
+        }
+    });
+}
+
+// Incremented whenever a lost context is replaced. Objects remember the generation they were
+// created in, so objects from a lost context are not deleted in the new context, which might have
+// reused their names for other objects.
+static CONTEXT_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn context_generation() -> usize {
+    CONTEXT_GENERATION.load(Ordering::SeqCst)
+}
+
+/// Whether objects created in the given generation belong to the current context, and thus
+/// should be deleted when dropped.
+pub(crate) fn is_current_context(generation: usize) -> bool {
+    generation == context_generation()
+}
+
+/// Whether the current context has been lost, e.g. because the graphics driver was updated or
+/// crashed. This only works if the context was created with a reset notification strategy.
+pub(crate) fn context_reset_detected() -> bool {
+    gl::GetGraphicsResetStatus::is_loaded() && unsafe { gl::GetGraphicsResetStatus() } != gl::NO_ERROR
+}
+
+/// Identifies a callback registered with [`add_rebuild_callback`].
+///
+/// [`add_rebuild_callback`]: fn.add_rebuild_callback.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RebuildCallbackId(usize);
+
+thread_local! {
+    static REBUILD_CALLBACKS: RefCell<Vec<(RebuildCallbackId, Rc<RefCell<dyn FnMut()>>)>> = RefCell::new(Vec::new());
+    static NEXT_REBUILD_CALLBACK: Cell<usize> = Cell::new(0);
+}
+
+/// Registers a callback which is called after a lost OpenGL context has been replaced by
+/// `WindowCommon::recreate_context`. Textures, shaders, buffers and framebuffers created before
+/// the context was lost can not be used anymore, and should be replaced in these callbacks, e.g.
+/// by loading them from disk again. Dropping the old objects is safe.
+///
+/// Callbacks are called in the order they were added, on the thread which added them.
+///
+/// ```rust,ignore
+/// let background = Rc::new(RefCell::new(Texture::from_file("background.png")?));
+///
+/// let handle = background.clone();
+/// graphics::add_rebuild_callback(move || {
+///     *handle.borrow_mut() = Texture::from_file("background.png").unwrap();
+/// });
+/// ```
+pub fn add_rebuild_callback<F: FnMut() + 'static>(callback: F) -> RebuildCallbackId {
+    let id = RebuildCallbackId(NEXT_REBUILD_CALLBACK.with(|next| next.replace(next.get() + 1)));
+    REBUILD_CALLBACKS.with(|callbacks| {
+        callbacks.borrow_mut().push((id, Rc::new(RefCell::new(callback))));
+    });
+    id
+}
+
+/// Removes a callback added with [`add_rebuild_callback`]. Does nothing if the callback was
+/// already removed.
+///
+/// [`add_rebuild_callback`]: fn.add_rebuild_callback.html
+pub fn remove_rebuild_callback(id: RebuildCallbackId) {
+    REBUILD_CALLBACKS.with(|callbacks| callbacks.borrow_mut().retain(|&(other, _)| other != id));
+}
+
+// Called by the window once a new context has replaced a lost context, and has been made current.
+// Forgets all state tracked for the old context.
+pub(crate) fn note_context_replaced() {
+    CONTEXT_GENERATION.fetch_add(1, Ordering::SeqCst);
+    with_live_objects(|objects| objects.clear());
+    material::note_context_changed();
+    framebuffer::note_context_changed();
+}
+
+// Called by the window once the new context has been set up
+pub(crate) fn run_rebuild_callbacks() {
+    // Callbacks might add or remove other callbacks, so we can't keep `REBUILD_CALLBACKS` borrowed
+    let callbacks = REBUILD_CALLBACKS.with(|callbacks| {
+        callbacks.borrow().iter().map(|&(_, ref callback)| callback.clone()).collect::<Vec<_>>()
+    });
+    for callback in callbacks {
+        (&mut *callback.borrow_mut())();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_accounting() {
+        // Counters are global, so compare against whatever was allocated before
+        let before = memory_report().textures;
+
+        note_allocation(ResourceKind::Texture, 0);
+        note_reallocation(ResourceKind::Texture, 0, 4096);
+        note_allocation(ResourceKind::Texture, 1024);
+        let during = memory_report().textures;
+        assert_eq!(during.count, before.count + 2);
+        assert_eq!(during.bytes, before.bytes + 5120);
+
+        note_free(ResourceKind::Texture, 4096);
+        note_free(ResourceKind::Texture, 1024);
+        assert_eq!(memory_report().textures, before);
+    }
+
+    #[test]
+    fn rebuild_callbacks() {
+        let calls = Rc::new(Cell::new(0));
+
+        let counter = calls.clone();
+        let first = add_rebuild_callback(move || counter.set(counter.get() + 1));
+        let counter = calls.clone();
+        let second = add_rebuild_callback(move || counter.set(counter.get() + 10));
+
+        run_rebuild_callbacks();
+        assert_eq!(calls.get(), 11);
+
+        remove_rebuild_callback(first);
+        run_rebuild_callbacks();
+        assert_eq!(calls.get(), 21);
+
+        remove_rebuild_callback(second);
+        run_rebuild_callbacks();
+        assert_eq!(calls.get(), 21);
+    }
+}
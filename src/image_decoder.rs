@@ -0,0 +1,359 @@
+
+//! Decoding of image files for [`Texture::from_file`] and [`RawImageData`]. PNG, TGA and BMP
+//! files are supported out of the box. Other formats, e.g. JPEG, can be supported by implementing
+//! [`ImageDecoder`] and passing it to [`register_decoder`].
+//!
+//! ```rust,ignore
+//! struct JpegDecoder;
+//!
+//! impl ImageDecoder for JpegDecoder {
+//!     fn can_decode(&self, bytes: &[u8], _extension: Option<&str>) -> bool {
+//!         bytes.starts_with(&[0xff, 0xd8, 0xff])
+//!     }
+//!
+//!     fn decode(&self, bytes: &[u8]) -> Result<RawImageData, String> {
+//!         let mut decoder = jpeg_decoder::Decoder::new(bytes);
+//!         let pixels = decoder.decode().map_err(|err| err.to_string())?;
+//!         let info = decoder.info().unwrap();
+//!         Ok(RawImageData::new(info.width as u32, info.height as u32, TextureFormat::RGB_8, pixels))
+//!     }
+//! }
+//!
+//! image_decoder::register_decoder(JpegDecoder);
+//! let texture = Texture::from_file("assets/photo.jpg")?;
+//! ```
+//!
+//! [`Texture::from_file`]: ../texture/struct.Texture.html#method.from_file
+//! [`RawImageData`]: ../texture/struct.RawImageData.html
+//! [`ImageDecoder`]: trait.ImageDecoder.html
+//! [`register_decoder`]: fn.register_decoder.html
+
+use std::sync::{Arc, Mutex};
+
+use png;
+
+use texture::{RawImageData, TextureFormat, TextureErrorKind};
+
+/// Decodes image files of some format into raw pixel data. See the [module level
+/// documentation](index.html) for an example.
+pub trait ImageDecoder: Send + Sync {
+    /// Whether the given data is in a format this decoder understands. This should usually check
+    /// for a magic number at the start of `bytes`. `extension` is the lowercase file extension,
+    /// if the data comes from a file.
+    fn can_decode(&self, bytes: &[u8], extension: Option<&str>) -> bool;
+
+    /// Decodes the given data. On failure, returns a description of the problem.
+    fn decode(&self, bytes: &[u8]) -> Result<RawImageData, String>;
+}
+
+static DECODERS: Mutex<Vec<Arc<dyn ImageDecoder>>> = Mutex::new(Vec::new());
+
+/// Adds a decoder for a additional image format. Decoders are tried in the reverse order they
+/// were registered in, before the built in decoders. This means registered decoders can also
+/// replace the built in decoders for PNG, TGA and BMP files.
+pub fn register_decoder<D: ImageDecoder + 'static>(decoder: D) {
+    DECODERS.lock().unwrap().push(Arc::new(decoder));
+}
+
+/// Decodes the given image file with the first decoder which accepts it.
+pub(crate) fn decode(bytes: &[u8], extension: Option<&str>) -> Result<RawImageData, TextureErrorKind> {
+    let extension = extension.map(|e| e.to_lowercase());
+    let extension = extension.as_ref().map(|e| e.as_str());
+
+    // Clone the list, so decoders can register other decoders without deadlocking
+    let registered = DECODERS.lock().unwrap().clone();
+    for decoder in registered.iter().rev() {
+        if decoder.can_decode(bytes, extension) {
+            return decoder.decode(bytes).map_err(TextureErrorKind::Decoding);
+        }
+    }
+
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        decode_png(bytes)
+    } else if bytes.starts_with(b"BM") {
+        decode_bmp(bytes).map_err(TextureErrorKind::Decoding)
+    } else if extension == Some("tga") || (extension.is_none() && is_tga(bytes)) {
+        // TGA files have no magic number
+        decode_tga(bytes).map_err(TextureErrorKind::Decoding)
+    } else {
+        Err(TextureErrorKind::UnknownFormat)
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<RawImageData, TextureErrorKind> {
+    let decoder = png::Decoder::new(bytes);
+    let (info, mut reader) = decoder.read_info().map_err(TextureErrorKind::from_decoding)?;
+
+    let format = match TextureFormat::from_png(info.color_type, info.bit_depth) {
+        Some(format) => format,
+        None => {
+            let format = format!("{:?}, {:?}", info.color_type, info.bit_depth);
+            return Err(TextureErrorKind::UnsupportedFormat(format));
+        },
+    };
+
+    // Read data into buffer (This is what makes texture loading slow)
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(TextureErrorKind::from_decoding)?;
+
+    Ok(RawImageData::new(info.width, info.height, format, buf))
+}
+
+// Whether the header looks like a TGA file we can decode
+fn is_tga(bytes: &[u8]) -> bool {
+    bytes.len() >= 18 &&
+    bytes[1] == 0 &&
+    [2, 3, 10, 11].contains(&bytes[2]) &&
+    [8, 16, 24, 32].contains(&bytes[16])
+}
+
+/// Decodes uncompressed and run length encoded true color and grayscale TGA files. Color mapped
+/// files are not supported.
+fn decode_tga(bytes: &[u8]) -> Result<RawImageData, String> {
+    if bytes.len() < 18 {
+        return Err("Truncated header".to_owned());
+    }
+
+    let id_length = bytes[0] as usize;
+    let color_map_type = bytes[1];
+    let image_type = bytes[2];
+    let width = u16_le(bytes, 12) as u32;
+    let height = u16_le(bytes, 14) as u32;
+    let depth = bytes[16];
+    let descriptor = bytes[17];
+
+    if color_map_type != 0 {
+        return Err("Color mapped images are not supported".to_owned());
+    }
+
+    let (format, rle) = match (image_type, depth) {
+        (2, 24) | (10, 24) => (TextureFormat::RGB_8, image_type == 10),
+        (2, 32) | (10, 32) => (TextureFormat::RGBA_8, image_type == 10),
+        (3, 8) | (11, 8)   => (TextureFormat::R_8, image_type == 11),
+        (3, 16) | (11, 16) => (TextureFormat::RG_8, image_type == 11),
+        _ => return Err(format!("Unsupported image type {} with {} bits per pixel", image_type, depth)),
+    };
+
+    let pixel_size = format.bytes_per_pixel();
+    let mut data = &bytes[(18 + id_length).min(bytes.len())..];
+    let truncated = || "Truncated pixel data".to_owned();
+
+    let size = (width as usize).checked_mul(height as usize)
+        .and_then(|count| count.checked_mul(pixel_size))
+        .ok_or_else(|| format!("Invalid size {}x{}", width, height))?;
+
+    let mut pixels;
+    if rle {
+        // A single packet expands to at most 128 pixels, so don't trust the header beyond that
+        pixels = Vec::with_capacity(size.min(data.len().saturating_mul(128)));
+        while pixels.len() < size {
+            let (&packet, rest) = data.split_first().ok_or_else(truncated)?;
+            let count = (packet & 0x7f) as usize + 1;
+            let raw_size = if packet & 0x80 != 0 { pixel_size } else { count * pixel_size };
+            if rest.len() < raw_size {
+                return Err(truncated());
+            }
+
+            if packet & 0x80 != 0 {
+                for _ in 0..count {
+                    pixels.extend_from_slice(&rest[..pixel_size]);
+                }
+            } else {
+                pixels.extend_from_slice(&rest[..raw_size]);
+            }
+            data = &rest[raw_size..];
+        }
+        pixels.truncate(size);
+    } else {
+        if data.len() < size {
+            return Err(truncated());
+        }
+        pixels = data[..size].to_vec();
+    }
+
+    // Pixels are stored as BGR(A)
+    if pixel_size >= 3 {
+        for pixel in pixels.chunks_mut(pixel_size) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    // Rows are stored bottom to top, unless bit 5 of the descriptor is set
+    if descriptor & 0x20 == 0 {
+        flip_rows(&mut pixels, width as usize * pixel_size);
+    }
+
+    Ok(RawImageData::new(width, height, format, pixels))
+}
+
+/// Decodes uncompressed 8, 24 and 32 bit BMP files.
+fn decode_bmp(bytes: &[u8]) -> Result<RawImageData, String> {
+    if bytes.len() < 54 {
+        return Err("Truncated header".to_owned());
+    }
+
+    let data_offset = u32_le(bytes, 10) as usize;
+    let header_size = u32_le(bytes, 14) as usize;
+    let width = u32_le(bytes, 18) as i32;
+    let height = u32_le(bytes, 22) as i32;
+    let depth = u16_le(bytes, 28);
+    let compression = u32_le(bytes, 30);
+
+    if header_size < 40 {
+        return Err("Old OS/2 bitmaps are not supported".to_owned());
+    }
+    if width <= 0 || height == 0 {
+        return Err(format!("Invalid size {}x{}", width, height));
+    }
+
+    // Negative heights mean rows are stored top to bottom
+    let top_down = height < 0;
+    let width = width as usize;
+    let height = (height as i64).abs() as usize;
+
+    const BI_RGB: u32 = 0;
+    const BI_BITFIELDS: u32 = 3;
+
+    // Bit masks for the red, green, blue and alpha channels of 32 bit images
+    let masks = match (depth, compression) {
+        (32, BI_RGB) => [0xff_0000, 0xff00, 0xff, 0],
+        (32, BI_BITFIELDS) => {
+            if bytes.len() < 14 + 40 + 12 {
+                return Err("Truncated header".to_owned());
+            }
+            let alpha = if header_size >= 56 { u32_le(bytes, 14 + 40 + 12) } else { 0 };
+            [u32_le(bytes, 14 + 40), u32_le(bytes, 14 + 40 + 4), u32_le(bytes, 14 + 40 + 8), alpha]
+        },
+        (8, BI_RGB) | (24, BI_RGB) => [0; 4],
+        _ => return Err(format!("Unsupported compression {} with {} bits per pixel", compression, depth)),
+    };
+
+    let format = if depth == 32 && masks[3] != 0 { TextureFormat::RGBA_8 } else { TextureFormat::RGB_8 };
+    let pixel_size = format.bytes_per_pixel();
+
+    // The color table directly follows the header, and has blue, green, red and a unused byte
+    let palette = if depth == 8 {
+        let count = match u32_le(bytes, 46) { 0 => 256, n => n as usize };
+        let start = 14 + header_size;
+        let end = start + count.min(256)*4;
+        if bytes.len() < end {
+            return Err("Truncated color table".to_owned());
+        }
+        bytes[start..end].chunks(4).map(|c| [c[2], c[1], c[0]]).collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+
+    // Rows are padded to four bytes
+    let invalid_size = || format!("Invalid size {}x{}", width, height);
+    let stride = width.checked_mul(depth as usize)
+        .and_then(|bits| (bits/8).checked_add(3))
+        .map(|bytes| bytes & !3)
+        .ok_or_else(invalid_size)?;
+    let data_size = stride.checked_mul(height).ok_or_else(invalid_size)?;
+    if data_offset > bytes.len() || bytes.len() - data_offset < data_size {
+        return Err("Truncated pixel data".to_owned());
+    }
+
+    let size = width.checked_mul(height)
+        .and_then(|count| count.checked_mul(pixel_size))
+        .ok_or_else(invalid_size)?;
+    let mut pixels = Vec::with_capacity(size);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row = &bytes[data_offset + row*stride..];
+
+        for x in 0..width {
+            match depth {
+                8 => {
+                    let color = palette.get(row[x] as usize).ok_or("Color index outside color table")?;
+                    pixels.extend_from_slice(color);
+                },
+                24 => pixels.extend_from_slice(&[row[x*3 + 2], row[x*3 + 1], row[x*3]]),
+                _ => {
+                    let value = u32_le(row, x*4);
+                    for &mask in masks[..pixel_size].iter() {
+                        pixels.push(extract_channel(value, mask));
+                    }
+                },
+            }
+        }
+    }
+
+    Ok(RawImageData::new(width as u32, height as u32, format, pixels))
+}
+
+// Scales the bits selected by `mask` to a 8 bit value
+fn extract_channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = value & mask;
+    let max = mask >> mask.trailing_zeros();
+    ((bits >> mask.trailing_zeros()) as u64 * 255 / max as u64) as u8
+}
+
+fn flip_rows(pixels: &mut [u8], stride: usize) {
+    if stride == 0 {
+        return;
+    }
+    let rows = pixels.len() / stride;
+    for y in 0..rows/2 {
+        let (top, bottom) = pixels.split_at_mut((rows - 1 - y) * stride);
+        top[y*stride..(y + 1)*stride].swap_with_slice(&mut bottom[..stride]);
+    }
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> u16 {
+    bytes[at] as u16 | (bytes[at + 1] as u16) << 8
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> u32 {
+    u16_le(bytes, at) as u32 | (u16_le(bytes, at + 2) as u32) << 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tga_and_bmp() {
+        // 2x2 run length encoded 24 bit TGA, bottom row first
+        let mut tga = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0];
+        tga.extend_from_slice(&[0x81, 0, 0, 255]); // Two red pixels
+        tga.extend_from_slice(&[0x01, 255, 0, 0, 0, 255, 0]); // Blue, green
+        let image = decode(&tga, Some("TGA")).unwrap();
+        assert_eq!(image.size(), (2, 2));
+        assert_eq!(image.format(), TextureFormat::RGB_8);
+        assert_eq!(image.data(), &[0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 0, 0]);
+
+        // 2x1 24 bit BMP, with rows padded to four bytes
+        let mut bmp = vec![0; 54];
+        bmp[0..2].copy_from_slice(b"BM");
+        bmp[10] = 54;
+        bmp[14] = 40;
+        bmp[18] = 2;
+        bmp[22] = 1;
+        bmp[28] = 24;
+        bmp.extend_from_slice(&[255, 0, 0, 0, 0, 255, 0, 0]);
+        let image = decode(&bmp, None).unwrap();
+        assert_eq!(image.size(), (2, 1));
+        assert_eq!(image.data(), &[0, 0, 255, 255, 0, 0]);
+
+        // Sizes which don't match the data are rejected before allocating anything
+        let mut huge_bmp = bmp.clone();
+        huge_bmp[18..22].copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+        huge_bmp[22..26].copy_from_slice(&0x8000_0000u32.to_le_bytes());
+        assert!(decode(&huge_bmp, None).is_err());
+
+        let mut huge_tga = tga.clone();
+        huge_tga[2] = 2;
+        huge_tga[12..16].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(decode(&huge_tga, Some("tga")).is_err());
+
+        match decode(&[1, 2, 3], None) {
+            Err(TextureErrorKind::UnknownFormat) => {},
+            other => panic!("Expected UnknownFormat, got {:?}", other.map(|i| i.size())),
+        }
+    }
+}
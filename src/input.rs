@@ -1,10 +1,16 @@
 
 //! Provides utilities for tracking the state of various input devices
 
+use std::ops::{BitOr, BitOrAssign};
+
 use cable_math::Vec2;
 
+use time::Time;
+
 const MOUSE_KEYS: usize = 5;
 const KEYBOARD_KEYS: usize = 256; // This MUST be `u8::max_value() + 1`
+// How far, in pixels, the mouse can move between two clicks for them to count as a double click
+const DOUBLE_CLICK_DISTANCE: f32 = 4.0;
 
 /// Passed to `Window::poll_events` each frame to get updated.
 #[derive(Clone)]
@@ -19,7 +25,24 @@ pub struct Input {
     /// person cameras in games.
     pub raw_mouse_delta: Vec2<f32>,
 
-    /// Units scrolled in the last frame. 1.0 corresponds to one tick of the wheel
+    /// Units scrolled in the last frame. 1.0 corresponds to one tick of the wheel. Positive `y`
+    /// is scrolling up (away from the user), positive `x` is scrolling right. Horizontal scrolling
+    /// comes from tilting the wheel, or from scrolling sideways on a touchpad.
+    ///
+    /// Touchpads and some mice report fractions of a tick, so scrolling can be smooth. On linux
+    /// this requires XInput 2.1.
+    pub scroll: Vec2<f32>,
+
+    /// How much the user zoomed with a pinch gesture in the last frame, as a factor. `1.0` means
+    /// no zoom, values above `1.0` mean zooming in (fingers moving apart). Multiply the zoom level
+    /// of e.g. a map by this.
+    ///
+    /// Pinch gestures are reported for touchpads on linux (requires XInput 2.4) and for touch
+    /// screens on windows. On windows, touchpad pinches are reported as scrolling while control
+    /// is held down, so handle that as zooming too.
+    pub zoom: f32,
+    /// The vertical component of `scroll`.
+    #[deprecated(note = "only contains vertical scrolling, use `Input::scroll` instead")]
     pub mouse_scroll: f32,
 
     /// The state of mouse keys. 0 is left, 1 is right, 2 is middle. 3 and 4 are usually the keys
@@ -30,44 +53,82 @@ pub struct Input {
     /// internally.
     pub mouse_keys: [KeyState; MOUSE_KEYS],
 
-    /// The state of keyboard keys. Can also be accessed more ergonomically throug the
-    /// `Input::key()` method
+    /// The longest time between two clicks for them to count as a double click. See
+    /// [`Input::click_count`]. Defaults to 500ms. On windows this is replaced by the system
+    /// double click time each time events are polled.
+    ///
+    /// [`Input::click_count`]: struct.Input.html#method.click_count
+    pub double_click_time: Time,
+    click_counts: [u32; MOUSE_KEYS],
+    last_clicks: [Option<(Time, Vec2<f32>)>; MOUSE_KEYS],
+
+    /// The state of keyboard keys, indexed by platform specific scancodes. Use `Input::key()`
+    /// instead, which works the same on all platforms.
+    #[deprecated(note = "indices are platform specific scancodes, use `Input::key` instead")]
     pub keys: [KeyState; KEYBOARD_KEYS],
+    key_states: [KeyState; KEY_COUNT],
 
     /// Cleared each frame. Contains typed characters in the order they where typed
     pub type_buffer: String,
 
+    /// All events received this frame, in the order they were received. The other fields only
+    /// contain the accumulated state at the end of the frame, which loses information when e.g.
+    /// a key is pressed and released within a single frame.
+    pub events: Vec<Event>,
+
     pub window_has_keyboard_focus: bool, 
+    /// Whether the mouse is currently over the window. Use this to clear hover highlights when
+    /// the mouse leaves the window, as `mouse_pos` keeps the last position inside the window.
+    pub mouse_inside_window: bool,
     pub received_events_this_frame: bool, 
 
     #[cfg(feature = "gamepad")]
     pub gamepads: [Gamepad; 4],
+    #[cfg(feature = "gamepad")]
+    gamepad_connection_events: Vec<GamepadConnectionEvent>,
 }
 
 impl Input {
+    #[allow(deprecated)]
     pub fn new() -> Input {
         Input {
             mouse_pos: Vec2::ZERO,
             mouse_delta: Vec2::ZERO,
             raw_mouse_delta: Vec2::ZERO,
+            scroll: Vec2::ZERO,
+            zoom: 1.0,
             mouse_scroll: 0.0,
             mouse_keys: [KeyState::Up; MOUSE_KEYS],
+            double_click_time: Time::from_ms(500),
+            click_counts: [0; MOUSE_KEYS],
+            last_clicks: [None; MOUSE_KEYS],
             keys: [KeyState::Up; KEYBOARD_KEYS],
+            key_states: [KeyState::Up; KEY_COUNT],
             type_buffer: String::with_capacity(10),
+            events: Vec::new(),
             window_has_keyboard_focus: false,
+            mouse_inside_window: false,
             received_events_this_frame: false,
 
             #[cfg(feature = "gamepad")]
             gamepads: [Default::default(), Default::default(), Default::default(), Default::default()],
+            #[cfg(feature = "gamepad")]
+            gamepad_connection_events: Vec::new(),
         }
     }
 
     // Called by `Window::poll_events` in the platform layer
+    #[allow(deprecated)]
     pub(crate) fn refresh(&mut self) {
         self.mouse_delta = Vec2::ZERO; 
         self.raw_mouse_delta = Vec2::ZERO; 
+        self.scroll = Vec2::ZERO;
+        self.zoom = 1.0;
         self.mouse_scroll = 0.0;
         self.type_buffer.clear();
+        self.events.clear();
+        #[cfg(feature = "gamepad")]
+        self.gamepad_connection_events.clear();
 
         for state in self.mouse_keys.iter_mut() {
             if *state == KeyState::Released { *state = KeyState::Up; }
@@ -75,7 +136,7 @@ impl Input {
             assert!(*state != KeyState::PressedRepeat);
         }
 
-        for state in self.keys.iter_mut() {
+        for state in self.keys.iter_mut().chain(self.key_states.iter_mut()) {
             if *state == KeyState::Released       { *state = KeyState::Up; }
             if *state == KeyState::Pressed        { *state = KeyState::Down; }
             if *state == KeyState::PressedRepeat  { *state = KeyState::Down; }
@@ -95,19 +156,242 @@ impl Input {
                 gamepad.right   = Vec2::ZERO;
                 gamepad.left_trigger = 0.0;
                 gamepad.right_trigger = 0.0;
+                gamepad.rumble_request = None;
             }
         }
 
         self.received_events_this_frame = false; 
     }
 
-    /// The state of the given keyboard key. Note that `Key` represent scancodes.
-    /// See [`Key`](enum.Key.html) for more info
+    /// The number of consecutive clicks ending with the given mouse key being pressed this frame,
+    /// or zero if the key was not pressed this frame. Clicks are consecutive if they are less than
+    /// `double_click_time` apart, and the mouse does not move much between them. Mouse keys are
+    /// indexed as in `mouse_keys`.
+    pub fn click_count(&self, mouse_key: usize) -> u32 {
+        if self.mouse_keys[mouse_key] == KeyState::Pressed {
+            self.click_counts[mouse_key]
+        } else {
+            0
+        }
+    }
+
+    /// Whether the given mouse key was double clicked this frame. See `click_count`.
+    pub fn double_clicked(&self, mouse_key: usize) -> bool {
+        self.click_count(mouse_key) == 2
+    }
+
+    /// Whether the given mouse key was triple clicked this frame. See `click_count`.
+    pub fn triple_clicked(&self, mouse_key: usize) -> bool {
+        self.click_count(mouse_key) == 3
+    }
+
+    // Called by `Window::poll_events` in the platform layer when a mouse key is pressed. `time` is
+    // the timestamp of the event, which only has to be consistent between calls.
+    pub(crate) fn register_click(&mut self, mouse_key: usize, time: Time) {
+        let pos = self.mouse_pos;
+
+        let consecutive = match self.last_clicks[mouse_key] {
+            Some((last_time, last_pos)) => {
+                time >= last_time && time - last_time <= self.double_click_time &&
+                (pos - last_pos).len() <= DOUBLE_CLICK_DISTANCE
+            },
+            None => false,
+        };
+
+        if consecutive {
+            self.click_counts[mouse_key] += 1;
+        } else {
+            self.click_counts[mouse_key] = 1;
+        }
+        self.last_clicks[mouse_key] = Some((time, pos));
+    }
+
+    /// The state of the given keyboard key. Note that `Key` represents a position on the
+    /// keyboard, rather than a symbol. See [`Key`](enum.Key.html) for more info
     pub fn key(&self, key: Key) -> KeyState {
-        self.keys[key as usize]
+        self.key_states[key as usize]
+    }
+
+    /// The modifier keys which are currently held down. The left and right variants of each
+    /// modifier are treated the same.
+    pub fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::NONE;
+        if self.key(Key::LCtrl).down() || self.key(Key::RCtrl).down() {
+            modifiers |= Modifiers::CTRL;
+        }
+        if self.key(Key::LShift).down() || self.key(Key::RShift).down() {
+            modifiers |= Modifiers::SHIFT;
+        }
+        if self.key(Key::LAlt).down() || self.key(Key::RAlt).down() {
+            modifiers |= Modifiers::ALT;
+        }
+        if self.key(Key::RMeta).down() {
+            modifiers |= Modifiers::META;
+        }
+        modifiers
+    }
+
+    /// Whether the given shortcut was triggered this frame, e.g.
+    /// `input.shortcut(Modifiers::CTRL | Modifiers::SHIFT, Key::S)` for "Ctrl+Shift+S".
+    ///
+    /// The shortcut triggers when `key` is pressed while exactly the given modifiers are held, so
+    /// "Ctrl+S" does not trigger for "Ctrl+Shift+S". The modifiers can be pressed in any order,
+    /// and may be pressed in the same frame as `key`. Key repeats are ignored, see
+    /// [`shortcut_repeat`] for shortcuts which should repeat while held, e.g. undo.
+    ///
+    /// [`shortcut_repeat`]: #method.shortcut_repeat
+    pub fn shortcut(&self, modifiers: Modifiers, key: Key) -> bool {
+        self.key(key).pressed() && self.modifiers() == modifiers
+    }
+
+    /// Same as [`shortcut`], but also triggers for key repeats while `key` is held down.
+    ///
+    /// [`shortcut`]: #method.shortcut
+    pub fn shortcut_repeat(&self, modifiers: Modifiers, key: Key) -> bool {
+        self.key(key).pressed_repeat() && self.modifiers() == modifiers
+    }
+
+    /// Gamepads which were plugged in or unplugged this frame, in the order it happened. Indices
+    /// refer to `gamepads`. A gamepad which is connected when the window is created is reported
+    /// as connected in the first frame.
+    #[cfg(feature = "gamepad")]
+    pub fn gamepad_connected_events(&self) -> &[GamepadConnectionEvent] {
+        &self.gamepad_connection_events
+    }
+
+    // Called by `Window::poll_events` in the platform layer each time a gamepad is checked for
+    #[cfg(feature = "gamepad")]
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub(crate) fn set_gamepad_connected(&mut self, index: usize, connected: bool) {
+        if self.gamepads[index].connected != connected {
+            self.gamepads[index].connected = connected;
+            self.gamepad_connection_events.push(GamepadConnectionEvent { index, connected });
+            self.received_events_this_frame = true;
+        }
+    }
+
+    // Called by `Window::poll_events` in the platform layer when a key is pressed or released
+    #[allow(deprecated)]
+    pub(crate) fn update_key(&mut self, scancode: u8, down: bool) {
+        fn update(state: &mut KeyState, down: bool) {
+            *state = if down {
+                if state.down() {
+                    KeyState::PressedRepeat
+                } else {
+                    KeyState::Pressed
+                }
+            } else {
+                KeyState::Released
+            };
+        }
+
+        update(&mut self.keys[scancode as usize], down);
+        if let Some(key) = scancode_to_key(scancode) {
+            update(&mut self.key_states[key as usize], down);
+            self.events.push(Event::Key(key, self.key_states[key as usize]));
+        }
+    }
+
+    // Called by `Window::poll_events` in the platform layer when a mouse key is pressed or
+    // released. See `register_click` for `time`.
+    pub(crate) fn update_mouse_key(&mut self, mouse_key: usize, down: bool, time: Time) {
+        let state = if down { KeyState::Pressed } else { KeyState::Released };
+        self.mouse_keys[mouse_key] = state;
+
+        if down {
+            self.register_click(mouse_key, time);
+        }
+
+        self.events.push(Event::MouseKey(mouse_key, state, self.mouse_pos));
+    }
+
+    // Called by `Window::poll_events` in the platform layer when the mouse wheel is scrolled
+    #[allow(deprecated)]
+    pub(crate) fn add_scroll(&mut self, delta: Vec2<f32>) {
+        self.scroll += delta;
+        self.mouse_scroll += delta.y;
+        self.events.push(Event::Scroll(delta));
+    }
+
+    // Called by `Window::poll_events` in the platform layer when a pinch gesture changes
+    pub(crate) fn add_zoom(&mut self, factor: f32) {
+        self.zoom *= factor;
+        self.events.push(Event::Zoom(factor));
+    }
+}
+
+
+/// A set of modifier keys, used for [`Input::shortcut`]. Combine modifiers with `|`.
+///
+/// [`Input::shortcut`]: struct.Input.html#method.shortcut
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers  = Modifiers(0);
+    pub const CTRL: Modifiers  = Modifiers(1 << 0);
+    pub const SHIFT: Modifiers = Modifiers(1 << 1);
+    pub const ALT: Modifiers   = Modifiers(1 << 2);
+    /// The windows or command key. Only the right meta key is tracked, as `Key` has no left
+    /// meta key.
+    pub const META: Modifiers  = Modifiers(1 << 3);
+
+    /// Whether all modifiers in `other` are also in `self`.
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+    fn bitor(self, rhs: Modifiers) -> Modifiers {
+        Modifiers(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, rhs: Modifiers) {
+        self.0 |= rhs.0;
     }
 }
 
+/// A single event, as stored in [`Input::events`].
+///
+/// [`Input::events`]: struct.Input.html#structfield.events
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A key was pressed, repeated or released. The state is either `Pressed`, `PressedRepeat` or
+    /// `Released`. Keys which have no corresponding `Key` are not reported.
+    Key(Key, KeyState),
+    /// A mouse key was pressed or released at the given position. The state is either `Pressed`
+    /// or `Released`. Mouse keys are indexed as in `Input::mouse_keys`.
+    MouseKey(usize, KeyState, Vec2<f32>),
+    /// The mouse moved to the given position, in window space.
+    MouseMove(Vec2<f32>),
+    /// The mouse wheel was scrolled. 1.0 corresponds to one tick of the wheel. See `Input::scroll`
+    /// for which directions are positive.
+    Scroll(Vec2<f32>),
+    /// The user zoomed with a pinch gesture. See `Input::zoom`.
+    Zoom(f32),
+    /// Text was typed. The same text is also appended to `Input::type_buffer`.
+    Text(String),
+    MouseEnter,
+    MouseLeave,
+    FocusGained,
+    FocusLost,
+    /// The window was moved. Contains the new position of the window on the screen.
+    Moved(Vec2<f32>),
+    /// The window was resized. Contains the new size.
+    Resized(Vec2<f32>),
+    /// The scale factor of the window changed. Contains the new scale factor, see
+    /// `WindowCommon::scale_factor`.
+    ScaleFactorChanged(f32),
+    CloseRequested,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum KeyState {
@@ -155,83 +439,111 @@ impl KeyState {
     pub fn released(self) -> bool { self == KeyState::Released }
 }
 
-/// Codes for most keys. Note that these are scancodes, so they refer to a position
-/// on the keyboard, rather than a specific symbol. These can be used as parameters
-/// to [`InputManager::key`](struct.InputManager.html#method.key). The names are
-/// based on the american keyboard layout.
+/// Codes for most keys. Note that these refer to a position on the keyboard, rather than a
+/// specific symbol. These can be used as parameters to [`Input::key`]. The names are based on the
+/// american keyboard layout, so e.g. `Key::Z` refers to the key to the left of `Key::X`, even on
+/// a AZERTY keyboard where that key is labeled "W". Use [`WindowCommon::key_for_char`] if you want
+/// to find the key which types a given character on the users current layout.
 ///
-/// Scancodes are target specific, so the values asigned to each enum name might vary from platform
-/// to platform. On some platforms not all keys are available. Check the source code for more
-/// detailed information on this.
-#[derive(Debug, Copy, Clone)]
-#[cfg(target_os = "linux")]
+/// The values of this enum are the same on all platforms. Some keys are not available on all
+/// platforms though, in which case they are never pressed. Check the source code of
+/// `scancode_to_key` for more detailed information on this.
+///
+/// [`Input::key`]: struct.Input.html#method.key
+/// [`WindowCommon::key_for_char`]: trait.WindowCommon.html#tymethod.key_for_char
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Key {
-    Key1 = 0xa, Key2 = 0xb, Key3 = 0xc, Key4 = 0xd, Key5 = 0xe, 
-    Key6 = 0xf, Key7 = 0x10, Key8 = 0x11, Key9 = 0x12, Key0 = 0x13,
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
 
-    Q = 0x18, W = 0x19, E = 0x1a, R = 0x1b, T = 0x1c, Y = 0x1d, U = 0x1e, I = 0x1f, O = 0x20, P = 0x21,
-    A = 0x26, S = 0x27, D = 0x28, F = 0x29, G = 0x2a, H = 0x2b, J = 0x2c, K = 0x2d, L = 0x2e,
-    Z = 0x34, X = 0x35, C = 0x36, V = 0x37, B = 0x38, N = 0x39, M = 0x3a,
+    Q, W, E, R, T, Y, U, I, O, P,
+    A, S, D, F, G, H, J, K, L,
+    Z, X, C, V, B, N, M,
 
-    Space = 0x41,
+    Space,
 
-    Escape = 0x9, Grave  = 0x31, Tab = 0x17, CapsLock  = 0x42,
-    LShift = 0x32, LCtrl = 0x25, LAlt = 0x40,
-    RAlt  = 0x6c, RMeta  = 0x86, RCtrl = 0x69, RShift = 0x3e, Return = 0x24, Back = 0x16,
+    Escape, Grave, Tab, CapsLock,
+    LShift, LCtrl, LAlt,
+    RAlt, RMeta, RCtrl, RShift, Return, Back,
 
-    Right = 0x72, Left = 0x71, Down = 0x74, Up = 0x6f,
+    Right, Left, Down, Up,
 
-    Insert = 0x76, Delete = 0x77, Home = 0x6e, End = 0x73, PageUp = 0x70, PageDown = 0x75,
+    Insert, Delete, Home, End, PageUp, PageDown,
 
-    F1 = 0x43, F2 = 0x44, F3 = 0x45, F4 = 0x46,  F5 = 0x47,  F6 = 0x48, 
-    F7 = 0x49, F8 = 0x4a, F9 = 0x4b, F10 = 0x4c, F11 = 0x5f, F12 = 0x60,
+    F1, F2, F3, F4,  F5,  F6,
+    F7, F8, F9, F10, F11, F12,
 }
 
-/// Codes for most keys. Note that these are scancodes, so they refer to a position on the
-/// keyboard, rather than a specific symbol. These can be used as parameters to
-/// [`InputManager::key`](struct.InputManager.html#method.key). The names are based on the american
-/// keyboard layout.  
-///
-/// Scancodes are target specific, so the values asigned to each enum name might vary from platform
-/// to platform. On some platforms not all keys are available. Check the source code for more
-/// detailed information on this.
-#[derive(Debug, Copy, Clone)]
-#[cfg(target_os = "windows")]
-#[repr(u8)]
-pub enum Key {
-    Key1 = 0x2, Key2 = 0x3, Key3 = 0x4, Key4 = 0x5, Key5 = 0x6,
-    Key6 = 0x7, Key7 = 0x8, Key8 = 0x9, Key9 = 0xa, Key0 = 0xb,
-
-    Q = 0x10, W = 0x11, E = 0x12, R = 0x13, T = 0x14, Y = 0x15, U = 0x16, I = 0x17, O = 0x18, P = 0x19,
-    A = 0x1e, S = 0x1f, D = 0x20, F = 0x21, G = 0x22, H = 0x23, J = 0x24, K = 0x25, L = 0x26, 
-    Z = 0x2c, X = 0x2d, C = 0x2e, V = 0x2f, B = 0x30, N = 0x31, M = 0x32,
-
-    Space = 0x39,
-
-    Escape = 0x1, 
-    //    Grave  = 0x31, 
-    Tab = 0xf,
-    //    CapsLock  = 0x42, 
-    LShift = 0x2a,
-    LCtrl = 0x1d,
-    //    LAlt = 0x40,
-    //    RAlt  = 0x6c,
-    //    RMeta  = 0x86,
-    //    RCtrl = 0x1d, // Same scancode as LCtrl :/
-    RShift = 0x36,
-    Return = 0x1c,
-    Back = 0xe,
-
-    Right = 0x4d, Left = 0x4b, Down = 0x50, Up = 0x48,
-
-    Insert = 0x52, Delete = 0x53, Home = 0x47, End = 0x4f, PageUp = 0x49, PageDown = 0x51,
-
-    F1 = 0x3b, F2 = 0x3c, F3 = 0x3d, F4 = 0x3e,  F5 = 0x3f,  F6 = 0x40,
-    F7 = 0x41, F8 = 0x42, F9 = 0x43, F10 = 0x44, F11 = 0x57, F12 = 0x58,
+pub(crate) const KEY_COUNT: usize = Key::F12 as usize + 1;
+
+/// Translates a platform specific scancode to a `Key`. Returns `None` for keys which are not
+/// represented in `Key`.
+#[cfg(target_os = "linux")]
+pub(crate) fn scancode_to_key(scancode: u8) -> Option<Key> {
+    use self::Key::*;
+
+    // These are X11 keycodes, which are the evdev scancodes offset by 8
+    Some(match scancode {
+        0xa => Key1, 0xb => Key2, 0xc => Key3, 0xd => Key4, 0xe => Key5,
+        0xf => Key6, 0x10 => Key7, 0x11 => Key8, 0x12 => Key9, 0x13 => Key0,
+
+        0x18 => Q, 0x19 => W, 0x1a => E, 0x1b => R, 0x1c => T,
+        0x1d => Y, 0x1e => U, 0x1f => I, 0x20 => O, 0x21 => P,
+        0x26 => A, 0x27 => S, 0x28 => D, 0x29 => F, 0x2a => G,
+        0x2b => H, 0x2c => J, 0x2d => K, 0x2e => L,
+        0x34 => Z, 0x35 => X, 0x36 => C, 0x37 => V, 0x38 => B, 0x39 => N, 0x3a => M,
+
+        0x41 => Space,
+
+        0x9 => Escape, 0x31 => Grave, 0x17 => Tab, 0x42 => CapsLock,
+        0x32 => LShift, 0x25 => LCtrl, 0x40 => LAlt,
+        0x6c => RAlt, 0x86 => RMeta, 0x69 => RCtrl, 0x3e => RShift, 0x24 => Return, 0x16 => Back,
+
+        0x72 => Right, 0x71 => Left, 0x74 => Down, 0x6f => Up,
+
+        0x76 => Insert, 0x77 => Delete, 0x6e => Home, 0x73 => End, 0x70 => PageUp, 0x75 => PageDown,
+
+        0x43 => F1, 0x44 => F2, 0x45 => F3, 0x46 => F4,  0x47 => F5,  0x48 => F6,
+        0x49 => F7, 0x4a => F8, 0x4b => F9, 0x4c => F10, 0x5f => F11, 0x60 => F12,
+
+        _ => return None,
+    })
 }
 
+/// Translates a platform specific scancode to a `Key`. Returns `None` for keys which are not
+/// represented in `Key`.
+#[cfg(target_os = "windows")]
+pub(crate) fn scancode_to_key(scancode: u8) -> Option<Key> {
+    use self::Key::*;
+
+    // These are "scan code set 1" codes. `RAlt`, `RCtrl` and `RMeta` are not available, as
+    // they share scancodes with other keys and are only distinguished by the extended-key flag.
+    Some(match scancode {
+        0x2 => Key1, 0x3 => Key2, 0x4 => Key3, 0x5 => Key4, 0x6 => Key5,
+        0x7 => Key6, 0x8 => Key7, 0x9 => Key8, 0xa => Key9, 0xb => Key0,
+
+        0x10 => Q, 0x11 => W, 0x12 => E, 0x13 => R, 0x14 => T,
+        0x15 => Y, 0x16 => U, 0x17 => I, 0x18 => O, 0x19 => P,
+        0x1e => A, 0x1f => S, 0x20 => D, 0x21 => F, 0x22 => G,
+        0x23 => H, 0x24 => J, 0x25 => K, 0x26 => L,
+        0x2c => Z, 0x2d => X, 0x2e => C, 0x2f => V, 0x30 => B, 0x31 => N, 0x32 => M,
 
+        0x39 => Space,
+
+        0x1 => Escape, 0x29 => Grave, 0xf => Tab, 0x3a => CapsLock,
+        0x2a => LShift, 0x1d => LCtrl, 0x38 => LAlt,
+        0x36 => RShift, 0x1c => Return, 0xe => Back,
+
+        0x4d => Right, 0x4b => Left, 0x50 => Down, 0x48 => Up,
+
+        0x52 => Insert, 0x53 => Delete, 0x47 => Home, 0x4f => End, 0x49 => PageUp, 0x51 => PageDown,
+
+        0x3b => F1, 0x3c => F2, 0x3d => F3, 0x3e => F4,  0x3f => F5,  0x40 => F6,
+        0x41 => F7, 0x42 => F8, 0x43 => F9, 0x44 => F10, 0x57 => F11, 0x58 => F12,
+
+        _ => return None,
+    })
+}
 
 #[cfg(feature = "gamepad")]
 #[derive(Clone, Default)]
@@ -245,6 +557,88 @@ pub struct Gamepad {
 
     pub left_trigger:  f32,
     pub right_trigger: f32,
+
+    /// How raw stick and trigger values are converted to `left`, `right`, `left_trigger` and
+    /// `right_trigger`. Changes apply the next time events are polled.
+    pub config: InputConfig,
+
+    // Set by `set_rumble`, consumed by `Window::poll_events`
+    pub(crate) rumble_request: Option<(f32, f32, Time)>,
+}
+
+/// Dead zones, response curves and thresholds for the analog inputs of a [`Gamepad`]. The same
+/// normalization is applied on all platforms.
+///
+/// [`Gamepad`]: struct.Gamepad.html
+#[cfg(feature = "gamepad")]
+#[derive(Debug, Copy, Clone)]
+pub struct InputConfig {
+    /// Sticks which are pushed less than this distance from the center read as zero. The dead
+    /// zone is radial, so it does not snap diagonal movement to the axes. Defaults to `0.3`.
+    pub stick_dead_zone: f32,
+    /// Same as `stick_dead_zone`, but for the triggers. Defaults to `0.3`.
+    pub trigger_dead_zone: f32,
+    /// Whether values outside the dead zones are rescaled, so they start at zero instead of
+    /// jumping from zero to the size of the dead zone. Defaults to `false`.
+    pub rescale: bool,
+    /// Applied to the length of stick vectors after the dead zone. Defaults to `Linear`.
+    pub stick_curve: ResponseCurve,
+    /// Applied to trigger values after the dead zone. Defaults to `Linear`.
+    pub trigger_curve: ResponseCurve,
+    /// How far a stick axis or trigger has to be pushed before the digital buttons (e.g.
+    /// `GamepadButton::LeftUp` or `GamepadButton::LeftTrigger`) are pressed. This is compared to
+    /// the raw values, so it does not depend on the dead zones, `rescale` or the response curves.
+    /// Defaults to `0.8`.
+    pub digital_threshold: f32,
+}
+
+#[cfg(feature = "gamepad")]
+impl Default for InputConfig {
+    fn default() -> InputConfig {
+        InputConfig {
+            stick_dead_zone: 0.3,
+            trigger_dead_zone: 0.3,
+            rescale: false,
+            stick_curve: ResponseCurve::Linear,
+            trigger_curve: ResponseCurve::Linear,
+            digital_threshold: 0.8,
+        }
+    }
+}
+
+/// Maps an analog input between 0 and 1 to an output between 0 and 1. See [`InputConfig`].
+///
+/// [`InputConfig`]: struct.InputConfig.html
+#[cfg(feature = "gamepad")]
+#[derive(Debug, Copy, Clone)]
+pub enum ResponseCurve {
+    Linear,
+    /// Gives finer control for small movements, e.g. for aiming.
+    Squared,
+    /// The function should map 0 to 0 and 1 to 1.
+    Custom(fn(f32) -> f32),
+}
+
+#[cfg(feature = "gamepad")]
+impl ResponseCurve {
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Squared => value*value,
+            ResponseCurve::Custom(f) => f(value),
+        }
+    }
+}
+
+/// A gamepad being plugged in or unplugged, see [`Input::gamepad_connected_events`].
+///
+/// [`Input::gamepad_connected_events`]: struct.Input.html#method.gamepad_connected_events
+#[cfg(feature = "gamepad")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GamepadConnectionEvent {
+    /// The index into `Input::gamepads`.
+    pub index: usize,
+    pub connected: bool,
 }
 
 #[cfg(feature = "gamepad")]
@@ -288,4 +682,240 @@ impl Gamepad {
     pub fn button(&self, button: GamepadButton) -> KeyState {
         self.buttons[button as usize]
     }
+
+    /// Makes the gamepad vibrate for the given duration, starting the next time
+    /// `Window::poll_events` is called. `low_freq` and `high_freq` control the speed of the
+    /// low-frequency (left) and high-frequency (right) motors, and should be between 0 and 1.
+    /// Calling this again before `duration` has passed replaces the current vibration. Pass 0 for
+    /// both speeds to stop vibrating.
+    ///
+    /// This currently only has an effect on windows.
+    pub fn set_rumble(&mut self, low_freq: f32, high_freq: f32, duration: Time) {
+        self.rumble_request = Some((low_freq, high_freq, duration));
+    }
+
+    // Called by `Window::poll_events` in the platform layer with the raw state of the sticks, with
+    // axes between -1 and 1, and triggers, between 0 and 1. Applies `config`, and updates the
+    // digital stick and trigger buttons.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))] // Only windows supports gamepads so far
+    pub(crate) fn update_axes(&mut self, left: Vec2<f32>, right: Vec2<f32>, left_trigger: f32, right_trigger: f32) {
+        let config = self.config;
+
+        // Removes the dead zone, and optionally rescales the remaining range to 0..1
+        let normalize = |value: f32, dead_zone: f32, curve: ResponseCurve| {
+            if value < dead_zone {
+                0.0
+            } else if config.rescale {
+                let value = ((value - dead_zone) / (1.0 - dead_zone)).min(1.0);
+                curve.apply(value)
+            } else {
+                curve.apply(value.min(1.0))
+            }
+        };
+        let normalize_stick = |stick: Vec2<f32>| {
+            let len = stick.len();
+            let scaled = normalize(len, config.stick_dead_zone, config.stick_curve);
+            if scaled > 0.0 { stick * (scaled / len) } else { Vec2::ZERO }
+        };
+
+        self.left = normalize_stick(left);
+        self.right = normalize_stick(right);
+        self.left_trigger = normalize(left_trigger, config.trigger_dead_zone, config.trigger_curve);
+        self.right_trigger = normalize(right_trigger, config.trigger_dead_zone, config.trigger_curve);
+
+        use self::GamepadButton::*;
+        let v = config.digital_threshold;
+        self.update_button(LeftUp,    left.y  > v);
+        self.update_button(LeftDown,  left.y  < -v);
+        self.update_button(LeftRight, left.x  > v);
+        self.update_button(LeftLeft,  left.x  < -v);
+        self.update_button(RightUp,    right.y > v);
+        self.update_button(RightDown,  right.y < -v);
+        self.update_button(RightRight, right.x > v);
+        self.update_button(RightLeft,  right.x < -v);
+        self.update_button(LeftTrigger,  left_trigger  > v);
+        self.update_button(RightTrigger, right_trigger > v);
+    }
+
+    // Called by `Window::poll_events` in the platform layer with the current state of a button
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub(crate) fn update_button(&mut self, button: GamepadButton, down: bool) {
+        let ref mut state = self.buttons[button as usize];
+
+        if down && !state.down() {
+            *state = KeyState::Pressed;
+        }
+
+        if !down && state.down() {
+            *state = KeyState::Released;
+        }
+    }
+}
+
+// Custom serialization, used for storing key bindings in settings files. Keys and buttons are
+// serialized by name, e.g. "LShift".
+#[cfg(feature = "serialize")]
+mod serialize {
+    use super::*;
+
+    use std::mem;
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+    use serde::de::Error;
+
+    impl Serialize for Key {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&format!("{:?}", self))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Key {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let name = String::deserialize(d)?;
+
+            for i in 0..KEY_COUNT {
+                // `Key` is `repr(u8)` and has no explicit discriminants, so all values below
+                // `KEY_COUNT` are valid.
+                let key: Key = unsafe { mem::transmute(i as u8) };
+                if format!("{:?}", key) == name {
+                    return Ok(key);
+                }
+            }
+
+            Err(D::Error::custom(format!("\"{}\" is not a valid key", name)))
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    impl Serialize for GamepadButton {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&format!("{:?}", self))
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    impl<'de> Deserialize<'de> for GamepadButton {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let name = String::deserialize(d)?;
+
+            for i in 0..GAMEPAD_BUTTON_COUNT {
+                // Same reasoning as for `Key`
+                let button: GamepadButton = unsafe { mem::transmute(i as u8) };
+                if format!("{:?}", button) == name {
+                    return Ok(button);
+                }
+            }
+
+            Err(D::Error::custom(format!("\"{}\" is not a valid gamepad button", name)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn click_count() {
+        let mut input = Input::new();
+
+        let click = |input: &mut Input, ms: u64| {
+            input.refresh();
+            input.mouse_keys[0] = KeyState::Pressed;
+            input.register_click(0, Time::from_ms(ms));
+            input.click_count(0)
+        };
+
+        assert_eq!(click(&mut input, 1000), 1);
+        assert_eq!(click(&mut input, 1200), 2);
+        assert_eq!(click(&mut input, 1400), 3);
+        assert_eq!(click(&mut input, 2000), 1);
+
+        input.mouse_pos = Vec2::new(100.0, 0.0);
+        assert_eq!(click(&mut input, 2100), 1);
+
+        input.refresh();
+        assert_eq!(input.click_count(0), 0);
+    }
+
+    #[test]
+    fn events_keep_order() {
+        let mut input = Input::new();
+        input.refresh();
+
+        input.update_mouse_key(1, true, Time::from_ms(0));
+        input.update_mouse_key(1, false, Time::from_ms(10));
+
+        // Only the release shows up in the accumulated state
+        assert_eq!(input.mouse_keys[1], KeyState::Released);
+        assert_eq!(input.events, vec![
+            Event::MouseKey(1, KeyState::Pressed, Vec2::ZERO),
+            Event::MouseKey(1, KeyState::Released, Vec2::ZERO),
+        ]);
+
+        input.refresh();
+        assert!(input.events.is_empty());
+    }
+
+    #[cfg(feature = "gamepad")]
+    #[test]
+    fn gamepad_normalization() {
+        // By default only the dead zone is applied
+        let mut gamepad = Gamepad::default();
+        gamepad.update_axes(Vec2::new(0.2, 0.0), Vec2::new(0.5, 0.0), 0.3, 0.85);
+        assert_eq!(gamepad.left, Vec2::ZERO);
+        assert_eq!(gamepad.right, Vec2::new(0.5, 0.0));
+        assert_eq!(gamepad.left_trigger, 0.3);
+        assert_eq!(gamepad.button(GamepadButton::RightTrigger), KeyState::Pressed);
+
+        let mut gamepad = Gamepad::default();
+        gamepad.config.rescale = true;
+        gamepad.config.stick_curve = ResponseCurve::Squared;
+
+        gamepad.update_axes(Vec2::new(0.2, 0.0), Vec2::new(0.0, -1.0), 0.65, 1.0);
+        assert_eq!(gamepad.left, Vec2::ZERO);
+        assert_eq!(gamepad.right, Vec2::new(0.0, -1.0));
+        assert!((gamepad.left_trigger - 0.5).abs() < 1e-6);
+        assert_eq!(gamepad.button(GamepadButton::RightDown), KeyState::Pressed);
+        assert_eq!(gamepad.button(GamepadButton::LeftRight), KeyState::Up);
+        assert_eq!(gamepad.button(GamepadButton::LeftTrigger), KeyState::Up);
+        assert_eq!(gamepad.button(GamepadButton::RightTrigger), KeyState::Pressed);
+
+        // Halfway between the dead zone and the edge, squared
+        gamepad.update_axes(Vec2::new(0.65, 0.0), Vec2::ZERO, 0.0, 0.0);
+        assert!((gamepad.left.x - 0.25).abs() < 1e-6);
+        assert_eq!(gamepad.button(GamepadButton::RightDown), KeyState::Released);
+    }
+
+    #[cfg(feature = "gamepad")]
+    #[test]
+    fn gamepad_connections() {
+        let mut input = Input::new();
+        input.set_gamepad_connected(1, true);
+        input.set_gamepad_connected(1, true);
+        input.set_gamepad_connected(0, false);
+        assert_eq!(input.gamepad_connected_events(), &[GamepadConnectionEvent { index: 1, connected: true }]);
+        assert!(input.gamepads[1].connected);
+
+        input.refresh();
+        assert!(input.gamepad_connected_events().is_empty());
+        input.set_gamepad_connected(1, false);
+        assert_eq!(input.gamepad_connected_events(), &[GamepadConnectionEvent { index: 1, connected: false }]);
+    }
+
+    #[test]
+    fn shortcuts() {
+        let mut input = Input::new();
+        let ctrl_shift = Modifiers::CTRL | Modifiers::SHIFT;
+
+        input.key_states[Key::LShift as usize] = KeyState::Down;
+        input.key_states[Key::RCtrl as usize] = KeyState::Pressed;
+        input.key_states[Key::S as usize] = KeyState::Pressed;
+        assert_eq!(input.modifiers(), ctrl_shift);
+        assert!(input.shortcut(ctrl_shift, Key::S));
+        assert!(!input.shortcut(Modifiers::CTRL, Key::S));
+
+        input.key_states[Key::S as usize] = KeyState::PressedRepeat;
+        assert!(!input.shortcut(ctrl_shift, Key::S));
+        assert!(input.shortcut_repeat(ctrl_shift, Key::S));
+    }
 }
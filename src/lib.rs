@@ -11,7 +11,7 @@
 //! use gondola::{Window, WindowCommon, InputManager};
 //!
 //! let mut input = InputManager::new();
-//! let mut window = Window::new("My title");
+//! let mut window = Window::new("My title").unwrap();
 //!
 //! while !window.close_requested {
 //!     window.poll_events(input);
@@ -32,6 +32,7 @@ extern crate rusttype;
 extern crate cable_math;
 
 mod util;
+mod error;
 
 mod color;
 mod input;
@@ -40,6 +41,8 @@ mod time;
 mod region;
 
 pub mod texture;
+pub mod image_decoder;
+pub mod animation;
 #[macro_use]
 pub mod shader;
 pub mod buffer;
@@ -47,14 +50,35 @@ pub mod graphics;
 pub mod framebuffer;
 pub mod font;
 pub mod draw_group;
+pub mod debug_draw;
+pub mod point_sprites;
+pub mod text_batch;
+pub mod skinning;
+pub mod scroll;
+pub mod pack;
+pub mod noise;
+pub mod geometry;
+pub mod picking;
+pub mod upload;
+pub mod material;
+pub mod raster;
+pub mod tween;
 //pub mod ui; // Temporarily disabled. Broken due to changes in font code. Should be rewritten to use draw_group
 
 #[cfg(feature = "audio")]
 pub mod audio;
 
+#[cfg(feature = "ffi")]
+pub mod c_api;
+
 pub use color::*;
 pub use input::*;
 pub use window::*;
 pub use time::*;
 pub use region::*;
+pub use error::Error;
 pub use draw_group::DrawGroup;
+pub use debug_draw::DebugDraw3d;
+pub use point_sprites::PointSprites;
+pub use text_batch::TextBatch;
+pub use scroll::ScrollView;
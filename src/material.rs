@@ -0,0 +1,323 @@
+
+//! Combining a shader with its uniforms and textures, see [`Material`].
+//!
+//! [`Material`]: struct.Material.html
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use gl::types::*;
+
+use cable_math::{Vec2, Vec3, Vec4, Mat4};
+
+use graphics;
+use shader::{Shader, UniformKind};
+use texture::{Texture, Sampler};
+
+/// A uniform value stored in a [`Material`].
+///
+/// [`Material`]: struct.Material.html
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MaterialValue {
+    F32(f32),
+    Vec2(Vec2<f32>),
+    Vec3(Vec3<f32>),
+    Vec4(Vec4<f32>),
+    Mat4(Mat4<f32>),
+    I32(i32),
+    U32(u32),
+}
+
+impl MaterialValue {
+    /// The kind of uniform this value can be assigned to.
+    pub fn kind(&self) -> UniformKind {
+        match *self {
+            MaterialValue::F32(..)  => UniformKind::F32,
+            MaterialValue::Vec2(..) => UniformKind::VEC2_F32,
+            MaterialValue::Vec3(..) => UniformKind::VEC3_F32,
+            MaterialValue::Vec4(..) => UniformKind::VEC4_F32,
+            MaterialValue::Mat4(..) => UniformKind::MAT4_F32,
+            MaterialValue::I32(..)  => UniformKind::I32,
+            MaterialValue::U32(..)  => UniformKind::U32,
+        }
+    }
+
+    fn set(&self, shader: &Shader, name: &str) {
+        match *self {
+            MaterialValue::F32(v)  => shader.set_uniform(name, v),
+            MaterialValue::Vec2(v) => shader.set_uniform(name, v),
+            MaterialValue::Vec3(v) => shader.set_uniform(name, v),
+            MaterialValue::Vec4(v) => shader.set_uniform(name, v),
+            MaterialValue::Mat4(v) => shader.set_uniform(name, v),
+            MaterialValue::I32(v)  => shader.set_uniform(name, v),
+            MaterialValue::U32(v)  => shader.set_uniform(name, v),
+        }
+    }
+}
+
+impl From<f32> for MaterialValue { fn from(v: f32) -> MaterialValue { MaterialValue::F32(v) } }
+impl From<Vec2<f32>> for MaterialValue { fn from(v: Vec2<f32>) -> MaterialValue { MaterialValue::Vec2(v) } }
+impl From<Vec3<f32>> for MaterialValue { fn from(v: Vec3<f32>) -> MaterialValue { MaterialValue::Vec3(v) } }
+impl From<Vec4<f32>> for MaterialValue { fn from(v: Vec4<f32>) -> MaterialValue { MaterialValue::Vec4(v) } }
+impl From<Mat4<f32>> for MaterialValue { fn from(v: Mat4<f32>) -> MaterialValue { MaterialValue::Mat4(v) } }
+impl From<i32> for MaterialValue { fn from(v: i32) -> MaterialValue { MaterialValue::I32(v) } }
+impl From<u32> for MaterialValue { fn from(v: u32) -> MaterialValue { MaterialValue::U32(v) } }
+
+static NEXT_MATERIAL_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A shader together with the uniform values and textures it should be drawn with.
+///
+/// [`apply`] binds everything needed to draw with the material, but skips state which is already
+/// set. The shader and textures are only bound if something else has been bound since, and
+/// uniforms are only uploaded if the material has changed, or if another material or a call to
+/// `Shader::set_uniform` has changed the uniforms of the shader. This means that drawing many
+/// objects with the same material, or alternating between a few materials, is cheap.
+///
+/// State is only tracked for bindings done through this library. If you bind shaders or textures
+/// directly through OpenGL, call [`invalidate_bound_state`] afterwards.
+///
+/// ```rust,ignore
+/// let mut material = Material::new(&shader);
+/// material.set("tint", Vec4::new(1.0, 0.5, 0.0, 1.0));
+/// material.set("roughness", 0.4);
+/// material.add_texture("diffuse", &diffuse);
+/// material.add_texture_with_sampler("normal_map", &normal_map, &sampler);
+///
+/// for mesh in meshes.iter() {
+///     material.apply();
+///     shader.set_uniform("model", mesh.transform);
+///     mesh.buffer.draw();
+/// }
+/// ```
+///
+/// [`apply`]: #method.apply
+/// [`invalidate_bound_state`]: fn.invalidate_bound_state.html
+pub struct Material<'a> {
+    shader: &'a Shader,
+    uniforms: Vec<(String, MaterialValue)>,
+    textures: Vec<(String, &'a Texture, Option<&'a Sampler>)>,
+
+    id: usize,
+    version: usize,
+}
+
+impl<'a> Material<'a> {
+    pub fn new(shader: &'a Shader) -> Material<'a> {
+        Material {
+            shader,
+            uniforms: Vec::new(),
+            textures: Vec::new(),
+
+            id: NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed),
+            version: 0,
+        }
+    }
+
+    pub fn shader(&self) -> &'a Shader {
+        self.shader
+    }
+
+    /// Sets the value of the uniform with the given name. This replaces any previous value for
+    /// the same uniform.
+    pub fn set<V: Into<MaterialValue>>(&mut self, uniform_name: &str, value: V) {
+        let value = value.into();
+        self.version += 1;
+
+        for &mut (ref name, ref mut old_value) in self.uniforms.iter_mut() {
+            if name == uniform_name {
+                *old_value = value;
+                return;
+            }
+        }
+
+        self.uniforms.push((uniform_name.to_owned(), value));
+    }
+
+    /// The value of the uniform with the given name, if it has been set.
+    pub fn get(&self, uniform_name: &str) -> Option<MaterialValue> {
+        self.uniforms.iter()
+            .find(|&&(ref name, _)| name == uniform_name)
+            .map(|&(_, value)| value)
+    }
+
+    /// All uniform values in this material, in the order they were first set.
+    pub fn uniforms(&self) -> &[(String, MaterialValue)] {
+        &self.uniforms
+    }
+
+    /// Adds a texture which will be bound to the sampler uniform with the given name. Textures are
+    /// bound to texture units `0` through `n - 1`, in the order they were added.
+    pub fn add_texture(&mut self, uniform_name: &str, texture: &'a Texture) {
+        self.version += 1;
+        self.textures.push((uniform_name.to_owned(), texture, None));
+    }
+
+    /// Like [`add_texture`], but samples the texture with the given sampler.
+    ///
+    /// [`add_texture`]: #method.add_texture
+    pub fn add_texture_with_sampler(&mut self, uniform_name: &str, texture: &'a Texture, sampler: &'a Sampler) {
+        self.version += 1;
+        self.textures.push((uniform_name.to_owned(), texture, Some(sampler)));
+    }
+
+    /// Binds the shader and textures of this material, and uploads its uniform values. State
+    /// which is already set is skipped, see the type level documentation.
+    ///
+    /// # Panics
+    /// If the material contains more textures than there are texture units.
+    pub fn apply(&self) {
+        let max_units = graphics::capabilities().max_texture_units as usize;
+        assert!(
+            self.textures.len() <= max_units,
+            "Material contains {} textures, but only {} texture units are available",
+            self.textures.len(), max_units,
+        );
+
+        let program = self.shader.program;
+
+        let (bind_program, upload_uniforms) = BOUND_STATE.with(|state| {
+            let state = state.borrow();
+            let uniforms_current = state.uniforms.iter()
+                .any(|&entry| entry == (program, self.id, self.version));
+            (state.program != program, !uniforms_current)
+        });
+
+        if bind_program {
+            self.shader.bind();
+        }
+
+        for (unit, &(_, texture, sampler)) in self.textures.iter().enumerate() {
+            let unit = unit as u32;
+
+            let sampler_handle = sampler.map(|s| s.sampler).unwrap_or(0);
+            let (bind_texture, bind_sampler) = BOUND_STATE.with(|state| {
+                let state = state.borrow();
+                (
+                    state.texture(unit) != Some(texture.texture),
+                    state.sampler(unit) != Some(sampler_handle),
+                )
+            });
+
+            if bind_texture {
+                texture.bind(unit);
+            }
+            if bind_sampler {
+                match sampler {
+                    Some(sampler) => sampler.bind(unit),
+                    None => Sampler::unbind(unit),
+                }
+            }
+        }
+
+        if upload_uniforms {
+            for &(ref name, value) in self.uniforms.iter() {
+                value.set(self.shader, name);
+            }
+            for (unit, &(ref name, _, _)) in self.textures.iter().enumerate() {
+                self.shader.set_uniform(name, unit as i32);
+            }
+
+            // Done after setting uniforms, as `set_uniform` clears the entry for the program
+            BOUND_STATE.with(|state| {
+                let mut state = state.borrow_mut();
+                state.uniforms.retain(|&(p, _, _)| p != program);
+                state.uniforms.push((program, self.id, self.version));
+            });
+        }
+    }
+}
+
+/// Forgets which shaders, textures and uniform values [`Material::apply`] thinks are currently
+/// set, so the next call to it rebinds everything. Call this after binding shaders or textures
+/// directly through OpenGL, rather than through this library.
+///
+/// [`Material::apply`]: struct.Material.html#method.apply
+pub fn invalidate_bound_state() {
+    BOUND_STATE.with(|state| *state.borrow_mut() = BoundState::new());
+}
+
+// Tracks state bound through this library, so `Material::apply` can skip redundant bindings.
+// `None` means that the binding is unknown.
+struct BoundState {
+    program: GLuint,
+    textures: Vec<Option<GLuint>>,
+    samplers: Vec<Option<GLuint>>,
+    // (program, material id, material version) of the material which last uploaded uniforms to
+    // each program
+    uniforms: Vec<(GLuint, usize, usize)>,
+}
+
+impl BoundState {
+    fn new() -> BoundState {
+        BoundState {
+            program: 0,
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            uniforms: Vec::new(),
+        }
+    }
+
+    fn texture(&self, unit: u32) -> Option<GLuint> {
+        self.textures.get(unit as usize).cloned().unwrap_or(None)
+    }
+
+    fn sampler(&self, unit: u32) -> Option<GLuint> {
+        self.samplers.get(unit as usize).cloned().unwrap_or(None)
+    }
+}
+
+thread_local! {
+    static BOUND_STATE: RefCell<BoundState> = RefCell::new(BoundState::new());
+}
+
+fn set_unit(list: &mut Vec<Option<GLuint>>, unit: u32, handle: Option<GLuint>) {
+    let unit = unit as usize;
+    if list.len() <= unit {
+        list.resize(unit + 1, None);
+    }
+    list[unit] = handle;
+}
+
+pub(crate) fn note_program(program: GLuint) {
+    BOUND_STATE.with(|state| state.borrow_mut().program = program);
+}
+
+/// Called when the uniforms of the given program are changed outside of `Material::apply`.
+pub(crate) fn note_uniforms_changed(program: GLuint) {
+    BOUND_STATE.with(|state| state.borrow_mut().uniforms.retain(|&(p, _, _)| p != program));
+}
+
+/// Called when a program is deleted, as its handle can be reused afterwards.
+pub(crate) fn note_program_deleted(program: GLuint) {
+    BOUND_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if state.program == program {
+            state.program = 0;
+        }
+        state.uniforms.retain(|&(p, _, _)| p != program);
+    });
+}
+
+/// Called when a texture is bound to `GL_TEXTURE_2D` on the given unit.
+pub(crate) fn note_texture(unit: u32, texture: GLuint) {
+    BOUND_STATE.with(|state| set_unit(&mut state.borrow_mut().textures, unit, Some(texture)));
+}
+
+pub(crate) fn note_sampler(unit: u32, sampler: GLuint) {
+    BOUND_STATE.with(|state| set_unit(&mut state.borrow_mut().samplers, unit, Some(sampler)));
+}
+
+/// Called when the context is replaced, as none of the old bindings apply to the new context.
+pub(crate) fn note_context_changed() {
+    BOUND_STATE.with(|state| *state.borrow_mut() = BoundState::new());
+}
+
+/// Called when texture bindings change in a way we don't track, e.g. when a texture is bound to
+/// whichever unit is active to upload data to it, or when textures or samplers are deleted.
+pub(crate) fn note_textures_unknown() {
+    BOUND_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.textures.clear();
+        state.samplers.clear();
+    });
+}
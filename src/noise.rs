@@ -0,0 +1,435 @@
+
+//! Deterministic random numbers and coherent noise for procedural content.
+//!
+//! [`Rng`] is a small and fast seedable random number generator. [`Noise`] provides value, Perlin
+//! and simplex noise in two and three dimensions, and [`Fractal`] combines several octaves of any
+//! of these into fractal brownian motion or turbulence.
+//!
+//! Everything in this module only depends on the seed it was created with, so the same seed
+//! gives the same output on every platform and every run. This makes it possible to store only
+//! the seed of a generated level.
+//!
+//! ```rust,ignore
+//! let noise = Noise::new(1234);
+//! let fractal = Fractal::new(5);
+//!
+//! let height = fractal.fbm(Vec2::new(x, y) * 0.01, |p| noise.simplex2(p));
+//! ```
+//!
+//! [`Rng`]: struct.Rng.html
+//! [`Noise`]: struct.Noise.html
+//! [`Fractal`]: struct.Fractal.html
+
+use std::ops::Mul;
+
+use cable_math::{Vec2, Vec3};
+
+/// A seedable pseudo random number generator. This uses the PCG32 algorithm, which is fast and has
+/// good statistical properties, but is not suitable for cryptographic purposes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+const PCG_INCREMENT: u64 = 1442695040888963407;
+
+impl Rng {
+    /// Creates a new generator. Generators created with the same seed produce the same sequence
+    /// of numbers.
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(PCG_INCREMENT);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let high = self.next_u32() as u64;
+        let low = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    /// A random number in the range `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        // f32 has 24 bits of precision
+        (self.next_u32() >> 8) as f32 * (1.0 / (1 << 24) as f32)
+    }
+
+    /// A random number in the range `[min, max)`.
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min)*self.next_f32()
+    }
+
+    /// A random integer in the range `[min, max)`. Panics if `max <= min`.
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        assert!(max > min, "Invalid range {}..{}", min, max);
+
+        let len = (max as i64 - min as i64) as u64;
+        let offset = (self.next_u32() as u64 * len) >> 32;
+        (min as i64 + offset as i64) as i32
+    }
+
+    /// Returns `true` with the given probability, which should be between 0 and 1.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    /// A random point inside the unit circle.
+    pub fn in_unit_circle(&mut self) -> Vec2<f32> {
+        loop {
+            let p = Vec2::new(self.range_f32(-1.0, 1.0), self.range_f32(-1.0, 1.0));
+            if p.len_sqr() <= 1.0 {
+                return p;
+            }
+        }
+    }
+
+    /// Randomly reorders the given slice.
+    pub fn shuffle<T>(&mut self, slice: &mut [T]) {
+        for i in (1..slice.len()).rev() {
+            let j = self.range_i32(0, i as i32 + 1) as usize;
+            slice.swap(i, j);
+        }
+    }
+}
+
+/// Coherent noise functions. All functions return values in roughly `[-1, 1]`, and change
+/// smoothly as the input changes. Features have a size of roughly one unit, so scale the input to
+/// control the size of features.
+///
+/// Value noise is the cheapest, but has a blocky look. Perlin noise looks more natural. Simplex
+/// noise looks similar to Perlin noise, but has fewer directional artifacts and is faster in three
+/// dimensions.
+#[derive(Clone)]
+pub struct Noise {
+    perm: [u8; 512],
+}
+
+impl Noise {
+    /// Creates a new set of noise functions. The output only depends on the seed.
+    pub fn new(seed: u64) -> Noise {
+        let mut values = [0u8; 256];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+        Rng::new(seed).shuffle(&mut values);
+
+        // Repeating the permutation avoids wrapping indices when hashing
+        let mut perm = [0u8; 512];
+        perm[..256].copy_from_slice(&values);
+        perm[256..].copy_from_slice(&values);
+
+        Noise { perm }
+    }
+
+    pub fn value2(&self, pos: Vec2<f32>) -> f32 {
+        let cell = pos.floor();
+        let (x, y) = (cell.x as i32, cell.y as i32);
+        let u = fade(pos.x - cell.x);
+        let v = fade(pos.y - cell.y);
+
+        let a = lerp(self.lattice_value(self.hash2(x, y)), self.lattice_value(self.hash2(x + 1, y)), u);
+        let b = lerp(self.lattice_value(self.hash2(x, y + 1)), self.lattice_value(self.hash2(x + 1, y + 1)), u);
+        lerp(a, b, v)
+    }
+
+    pub fn value3(&self, pos: Vec3<f32>) -> f32 {
+        let cell = pos.floor();
+        let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let u = fade(pos.x - cell.x);
+        let v = fade(pos.y - cell.y);
+        let w = fade(pos.z - cell.z);
+
+        let value = |dx, dy, dz| self.lattice_value(self.hash3(x + dx, y + dy, z + dz));
+
+        let a = lerp(lerp(value(0, 0, 0), value(1, 0, 0), u), lerp(value(0, 1, 0), value(1, 1, 0), u), v);
+        let b = lerp(lerp(value(0, 0, 1), value(1, 0, 1), u), lerp(value(0, 1, 1), value(1, 1, 1), u), v);
+        lerp(a, b, w)
+    }
+
+    /// Perlin noise. This is always zero at integer coordinates.
+    pub fn perlin2(&self, pos: Vec2<f32>) -> f32 {
+        let cell = pos.floor();
+        let (xi, yi) = (cell.x as i32, cell.y as i32);
+        let (x, y) = (pos.x - cell.x, pos.y - cell.y);
+        let u = fade(x);
+        let v = fade(y);
+
+        let a = lerp(grad2(self.hash2(xi, yi), x, y), grad2(self.hash2(xi + 1, yi), x - 1.0, y), u);
+        let b = lerp(grad2(self.hash2(xi, yi + 1), x, y - 1.0), grad2(self.hash2(xi + 1, yi + 1), x - 1.0, y - 1.0), u);
+        lerp(a, b, v)
+    }
+
+    /// Perlin noise. This is always zero at integer coordinates.
+    pub fn perlin3(&self, pos: Vec3<f32>) -> f32 {
+        let cell = pos.floor();
+        let (xi, yi, zi) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let (x, y, z) = (pos.x - cell.x, pos.y - cell.y, pos.z - cell.z);
+        let u = fade(x);
+        let v = fade(y);
+        let w = fade(z);
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let hash = self.hash3(xi + dx, yi + dy, zi + dz);
+            grad3(hash, x - dx as f32, y - dy as f32, z - dz as f32)
+        };
+
+        let a = lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), u), lerp(corner(0, 1, 0), corner(1, 1, 0), u), v);
+        let b = lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), u), lerp(corner(0, 1, 1), corner(1, 1, 1), u), v);
+        lerp(a, b, w)
+    }
+
+    pub fn simplex2(&self, pos: Vec2<f32>) -> f32 {
+        const F2: f32 = 0.36602540378; // (sqrt(3) - 1) / 2
+        const G2: f32 = 0.21132486540; // (3 - sqrt(3)) / 6
+
+        // Find the simplex (triangle) containing the point
+        let s = (pos.x + pos.y)*F2;
+        let i = (pos.x + s).floor();
+        let j = (pos.y + s).floor();
+        let t = (i + j)*G2;
+        let x0 = pos.x - (i - t);
+        let y0 = pos.y - (j - t);
+        let (i, j) = (i as i32, j as i32);
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let x1 = x0 - i1 as f32 + G2;
+        let y1 = y0 - j1 as f32 + G2;
+        let x2 = x0 - 1.0 + 2.0*G2;
+        let y2 = y0 - 1.0 + 2.0*G2;
+
+        let corner = |hash, x: f32, y: f32| {
+            let t = 0.5 - x*x - y*y;
+            if t < 0.0 { 0.0 } else { t*t*t*t * grad2(hash, x, y) }
+        };
+
+        let n0 = corner(self.hash2(i, j), x0, y0);
+        let n1 = corner(self.hash2(i + i1, j + j1), x1, y1);
+        let n2 = corner(self.hash2(i + 1, j + 1), x2, y2);
+
+        70.0*(n0 + n1 + n2)
+    }
+
+    pub fn simplex3(&self, pos: Vec3<f32>) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+
+        // Find the simplex (tetrahedron) containing the point
+        let s = (pos.x + pos.y + pos.z)*F3;
+        let i = (pos.x + s).floor();
+        let j = (pos.y + s).floor();
+        let k = (pos.z + s).floor();
+        let t = (i + j + k)*G3;
+        let x0 = pos.x - (i - t);
+        let y0 = pos.y - (j - t);
+        let z0 = pos.z - (k - t);
+        let (i, j, k) = (i as i32, j as i32, k as i32);
+
+        let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+            if y0 >= z0      { ((1, 0, 0), (1, 1, 0)) }
+            else if x0 >= z0 { ((1, 0, 0), (1, 0, 1)) }
+            else             { ((0, 0, 1), (1, 0, 1)) }
+        } else {
+            if y0 < z0       { ((0, 0, 1), (0, 1, 1)) }
+            else if x0 < z0  { ((0, 1, 0), (0, 1, 1)) }
+            else             { ((0, 1, 0), (1, 1, 0)) }
+        };
+
+        let corner = |di: i32, dj: i32, dk: i32, offset: f32| {
+            let x = x0 - di as f32 + offset;
+            let y = y0 - dj as f32 + offset;
+            let z = z0 - dk as f32 + offset;
+            let t = 0.6 - x*x - y*y - z*z;
+            if t < 0.0 {
+                0.0
+            } else {
+                t*t*t*t * grad3(self.hash3(i + di, j + dj, k + dk), x, y, z)
+            }
+        };
+
+        let n0 = corner(0, 0, 0, 0.0);
+        let n1 = corner(i1, j1, k1, G3);
+        let n2 = corner(i2, j2, k2, 2.0*G3);
+        let n3 = corner(1, 1, 1, 3.0*G3);
+
+        32.0*(n0 + n1 + n2 + n3)
+    }
+
+    fn hash2(&self, x: i32, y: i32) -> u8 {
+        let a = self.perm[(x & 255) as usize] as usize;
+        self.perm[a + (y & 255) as usize]
+    }
+
+    fn hash3(&self, x: i32, y: i32, z: i32) -> u8 {
+        let a = self.hash2(x, y) as usize;
+        self.perm[a + (z & 255) as usize]
+    }
+
+    fn lattice_value(&self, hash: u8) -> f32 {
+        hash as f32 / 255.0 * 2.0 - 1.0
+    }
+}
+
+/// Combines several octaves of a noise function. Each octave has a higher frequency and a lower
+/// amplitude than the previous one, which adds finer detail.
+///
+/// ```rust,ignore
+/// let fractal = Fractal::new(4);
+/// let value = fractal.fbm(pos, |p| noise.perlin3(p));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fractal {
+    /// The number of noise samples combined.
+    pub octaves: u32,
+    /// How much the frequency is multiplied by for each octave. Defaults to `2.0`.
+    pub lacunarity: f32,
+    /// How much the amplitude is multiplied by for each octave. Defaults to `0.5`.
+    pub gain: f32,
+}
+
+impl Fractal {
+    pub fn new(octaves: u32) -> Fractal {
+        Fractal {
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    /// Fractal brownian motion. The octaves are added together, and the result is scaled so it
+    /// stays in the same range as `noise`.
+    pub fn fbm<P, F>(&self, pos: P, noise: F) -> f32
+      where P: Copy + Mul<f32, Output = P>,
+            F: Fn(P) -> f32,
+    {
+        self.sum(pos, noise)
+    }
+
+    /// Like [`fbm`], but the absolute values of the octaves are added, giving sharp creases where
+    /// the noise crosses zero. The result is in roughly `[0, 1]`.
+    ///
+    /// [`fbm`]: #method.fbm
+    pub fn turbulence<P, F>(&self, pos: P, noise: F) -> f32
+      where P: Copy + Mul<f32, Output = P>,
+            F: Fn(P) -> f32,
+    {
+        self.sum(pos, |p| noise(p).abs())
+    }
+
+    fn sum<P, F>(&self, pos: P, noise: F) -> f32
+      where P: Copy + Mul<f32, Output = P>,
+            F: Fn(P) -> f32,
+    {
+        let mut result = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+
+        for _ in 0..self.octaves {
+            result += noise(pos*frequency)*amplitude;
+            total_amplitude += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+
+        if total_amplitude > 0.0 {
+            result / total_amplitude
+        } else {
+            0.0
+        }
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t*t*t*(t*(t*6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a)*t
+}
+
+fn grad2(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 =>  x + y,
+        1 => -x + y,
+        2 =>  x - y,
+        3 => -x - y,
+        4 =>  x,
+        5 => -x,
+        6 =>  y,
+        _ => -y,
+    }
+}
+
+fn grad3(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+    // The 12 edges of a cube, with four of them repeated
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_is_deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        let a_values: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+        let b_values: Vec<u32> = (0..16).map(|_| b.next_u32()).collect();
+        let c_values: Vec<u32> = (0..16).map(|_| c.next_u32()).collect();
+        assert_eq!(a_values, b_values);
+        assert!(a_values != c_values);
+
+        for _ in 0..1000 {
+            let i = a.range_i32(-3, 5);
+            assert!(i >= -3 && i < 5);
+            let f = a.range_f32(2.0, 4.0);
+            assert!(f >= 2.0 && f < 4.0);
+        }
+    }
+
+    #[test]
+    fn noise_range() {
+        let noise = Noise::new(7);
+        let fractal = Fractal::new(4);
+
+        assert_eq!(noise.perlin2(Vec2::new(3.0, -2.0)), 0.0);
+        assert_eq!(noise.perlin3(Vec3::new(-1.0, 5.0, 2.0)), 0.0);
+
+        for i in 0..500 {
+            let p = Vec3::new(i as f32 * 0.173 - 40.0, i as f32 * 0.311, i as f32 * -0.071);
+            let q = Vec2::new(p.x, p.y);
+
+            for &value in &[
+                noise.value2(q), noise.value3(p),
+                noise.perlin2(q), noise.perlin3(p),
+                noise.simplex2(q), noise.simplex3(p),
+                fractal.fbm(q, |p| noise.simplex2(p)),
+            ] {
+                assert!(value >= -1.1 && value <= 1.1, "{} out of range", value);
+            }
+
+            let turbulence = fractal.turbulence(p, |p| noise.perlin3(p));
+            assert!(turbulence >= 0.0 && turbulence <= 1.1);
+        }
+
+        assert_eq!(Noise::new(7).simplex2(Vec2::new(0.3, 0.8)), noise.simplex2(Vec2::new(0.3, 0.8)));
+    }
+}
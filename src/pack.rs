@@ -0,0 +1,394 @@
+//! A simple archive format, used to ship all assets of a game in a single file.
+//!
+//! Packs are created with [`PackBuilder`], usually from a small build script which calls
+//! [`pack_directory`]. At runtime, a [`Pack`] is opened once and assets are loaded from it by
+//! name. Names are the paths of the packed files relative to the packed directory, using `/` as
+//! a separator on all platforms.
+//!
+//! # Format
+//! A pack starts with the magic bytes `GPAK`, followed by a `u32` format version and a `u32`
+//! entry count. Then follows the table of contents, where each entry consists of a `u32` name
+//! length, the utf-8 name, a `u64` offset from the start of the file and a `u64` size. The data
+//! for all entries follows the table of contents. All integers are little endian.
+//!
+//! # Example
+//! ```rust,no_run
+//! use gondola::pack::{self, Pack};
+//!
+//! // In a build script
+//! pack::pack_directory("assets", "assets.pak").unwrap();
+//!
+//! // In the game
+//! let pack = Pack::open("assets.pak").unwrap();
+//! let shader = pack.load_shader("shaders/sprite.glsl").unwrap();
+//! ```
+//!
+//! [`PackBuilder`]: struct.PackBuilder.html
+//! [`pack_directory`]: fn.pack_directory.html
+//! [`Pack`]: struct.Pack.html
+
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::fs::{self, File};
+use std::path::Path;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use texture::{Texture, TextureError};
+use shader::{ShaderPrototype, ShaderError};
+use font::TruetypeFont;
+#[cfg(feature = "audio")]
+use audio::AudioBuffer;
+#[cfg(feature = "audio")]
+use audio::wav::{self, WavError};
+
+const MAGIC: &[u8; 4] = b"GPAK";
+const VERSION: u32 = 1;
+
+/// Collects files, and writes them into a pack. See the [module level documentation] for more
+/// info.
+///
+/// [module level documentation]: index.html
+pub struct PackBuilder {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> PackBuilder {
+        PackBuilder { entries: Vec::new() }
+    }
+
+    /// Adds the given data to the pack. If an entry with the same name has already been added it
+    /// is replaced.
+    pub fn add<S: Into<String>>(&mut self, name: S, data: Vec<u8>) {
+        let name = name.into();
+
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.0 == name) {
+            entry.1 = data;
+            return;
+        }
+
+        self.entries.push((name, data));
+    }
+
+    /// Reads the given file and adds it to the pack.
+    pub fn add_file<S, P>(&mut self, name: S, path: P) -> io::Result<()>
+      where S: Into<String>,
+            P: AsRef<Path>,
+    {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        self.add(name, data);
+        Ok(())
+    }
+
+    /// Recursively adds all files in the given directory to the pack. Entries are named by their
+    /// path relative to `dir`, e.g. `shaders/sprite.glsl`.
+    pub fn add_directory<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
+        self.add_directory_with_prefix(dir.as_ref(), "")
+    }
+
+    fn add_directory_with_prefix(&mut self, dir: &Path, prefix: &str) -> io::Result<()> {
+        // Sort entries, so the same directory always gives the same pack
+        let mut children = Vec::new();
+        for child in fs::read_dir(dir)? {
+            children.push(child?);
+        }
+        children.sort_by_key(|child| child.file_name());
+
+        for child in children {
+            let file_name = match child.file_name().into_string() {
+                Ok(name) => name,
+                Err(name) => {
+                    let message = format!("{:?} is not a valid utf-8 name", name);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                },
+            };
+            let name = format!("{}{}", prefix, file_name);
+
+            if child.file_type()?.is_dir() {
+                self.add_directory_with_prefix(&child.path(), &format!("{}/", name))?;
+            } else {
+                self.add_file(name, child.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the pack to the given writer.
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u32(&mut out, VERSION)?;
+        write_u32(&mut out, self.entries.len() as u32)?;
+
+        let mut toc_size = 4 + 4 + 4;
+        for &(ref name, _) in self.entries.iter() {
+            toc_size += 4 + name.len() + 8 + 8;
+        }
+
+        let mut offset = toc_size as u64;
+        for &(ref name, ref data) in self.entries.iter() {
+            write_u32(&mut out, name.len() as u32)?;
+            out.write_all(name.as_bytes())?;
+            write_u64(&mut out, offset)?;
+            write_u64(&mut out, data.len() as u64)?;
+
+            offset += data.len() as u64;
+        }
+
+        for &(_, ref data) in self.entries.iter() {
+            out.write_all(data)?;
+        }
+
+        out.flush()
+    }
+
+    /// Writes the pack to the given file, replacing the file if it already exists.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(io::BufWriter::new(file))
+    }
+}
+
+/// Packs all files in `dir` into a single pack at `output`. This is intended to be called from a
+/// build script or a small command line tool.
+pub fn pack_directory<P, Q>(dir: P, output: Q) -> io::Result<()>
+  where P: AsRef<Path>,
+        Q: AsRef<Path>,
+{
+    let mut builder = PackBuilder::new();
+    builder.add_directory(dir)?;
+    builder.write_to_file(output)
+}
+
+/// A pack opened for reading. Only the table of contents is kept in memory, entries are read
+/// from disk when they are requested. See the [module level documentation] for more info.
+///
+/// [module level documentation]: index.html
+pub struct Pack {
+    // Reading an entry seeks first, so the lock keeps reads from different threads apart
+    file: Mutex<File>,
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    offset: u64,
+    size: u64,
+}
+
+impl Pack {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Pack> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+
+        let mut reader = io::BufReader::new(&file);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a pack file"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(&format!("Unsupported pack version {}, expected {}", version, VERSION)));
+        }
+
+        // Each entry takes at least 20 bytes, so sizes read from the file can be checked before
+        // allocating anything
+        let count = read_u32(&mut reader)?;
+        if count as u64 * 20 > file_size {
+            return Err(invalid_data(&format!("Pack claims to have {} entries, but is too small", count)));
+        }
+        let mut entries = HashMap::with_capacity(count as usize);
+
+        for _ in 0..count {
+            let name_len = read_u32(&mut reader)?;
+            if name_len as u64 > file_size {
+                return Err(invalid_data("Entry name extends past the end of the pack"));
+            }
+            let mut name = vec![0u8; name_len as usize];
+            reader.read_exact(&mut name)?;
+            let name = match String::from_utf8(name) {
+                Ok(name) => name,
+                Err(_) => return Err(invalid_data("Entry name is not valid utf-8")),
+            };
+
+            let offset = read_u64(&mut reader)?;
+            let size = read_u64(&mut reader)?;
+            let in_bounds = match offset.checked_add(size) {
+                Some(end) => end <= file_size,
+                None => false,
+            };
+            if !in_bounds {
+                return Err(invalid_data(&format!("Entry \"{}\" extends past the end of the pack", name)));
+            }
+
+            entries.insert(name, Entry { offset, size });
+        }
+
+        drop(reader);
+
+        Ok(Pack { file: Mutex::new(file), entries })
+    }
+
+    /// Checks whether this pack contains an entry with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// The names of all entries in this pack, in no particular order.
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.keys().map(String::as_str).collect()
+    }
+
+    /// Reads the entry with the given name. Returns an error of kind `NotFound` if there is no
+    /// such entry.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = match self.entries.get(name) {
+            Some(entry) => entry,
+            None => {
+                let message = format!("No entry named \"{}\" in pack", name);
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            },
+        };
+
+        // We seek before every read, so a read which panicked can't have left the file in a bad
+        // state
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.seek(SeekFrom::Start(entry.offset))?;
+
+        let mut data = vec![0u8; entry.size as usize];
+        file.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+
+    /// Loads a png image from this pack. See `Texture::from_bytes`.
+    pub fn load_texture(&self, name: &str) -> Result<Texture, TextureError> {
+        let data = self.read(name)?;
+        Texture::from_bytes(&data, name)
+    }
+
+    /// Loads a shader in the `-- VERT/FRAG/GEOM` format from this pack. See
+    /// `ShaderPrototype::from_file`.
+    pub fn load_shader(&self, name: &str) -> Result<ShaderPrototype, ShaderError> {
+        let data = self.read(name)?;
+        ShaderPrototype::from_reader(data.as_slice())
+    }
+
+    /// Loads a truetype or opentype font from this pack. See `TruetypeFont::from_file`.
+    pub fn load_truetype_font(&self, name: &str) -> io::Result<TruetypeFont> {
+        let data = self.read(name)?;
+        TruetypeFont::from_bytes(data)
+    }
+
+    /// Loads a `.wav` file from this pack. See `audio::wav::load`.
+    #[cfg(feature = "audio")]
+    pub fn load_wav(&self, name: &str) -> Result<AudioBuffer, WavError> {
+        let data = self.read(name)?;
+        wav::from_bytes(&data)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+
+    let mut value = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        value |= (byte as u32) << (i*8);
+    }
+    Ok(value)
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let low  = read_u32(reader)? as u64;
+    let high = read_u32(reader)? as u64;
+    Ok(low | (high << 32))
+}
+
+pub(crate) fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (i*8)) as u8;
+    }
+    writer.write_all(&bytes)
+}
+
+pub(crate) fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    write_u32(writer, value as u32)?;
+    write_u32(writer, (value >> 32) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn round_trip() {
+        let mut builder = PackBuilder::new();
+        builder.add("a.txt", b"Hello".to_vec());
+        builder.add("dir/b.bin", vec![0, 1, 2, 3, 255]);
+        builder.add("empty", Vec::new());
+        builder.add("a.txt", b"Replaced".to_vec());
+
+        let path = env::temp_dir().join("gondola_pack_round_trip.pak");
+        builder.write_to_file(&path).unwrap();
+
+        let pack = Pack::open(&path).unwrap();
+        assert_eq!(pack.names().len(), 3);
+        assert_eq!(pack.read("a.txt").unwrap(), b"Replaced");
+        assert_eq!(pack.read("dir/b.bin").unwrap(), vec![0, 1, 2, 3, 255]);
+        assert_eq!(pack.read("empty").unwrap(), Vec::<u8>::new());
+        assert_eq!(pack.read("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        drop(pack);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn entry_past_end() {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        write_u32(&mut data, VERSION).unwrap();
+        write_u32(&mut data, 1).unwrap();
+        write_u32(&mut data, 1).unwrap();
+        data.push(b'a');
+        write_u64(&mut data, 8).unwrap();
+        write_u64(&mut data, u64::max_value()).unwrap();
+
+        let path = env::temp_dir().join("gondola_pack_entry_past_end.pak");
+        fs::write(&path, &data).unwrap();
+
+        let error = Pack::open(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn huge_header_values() {
+        let path = env::temp_dir().join("gondola_pack_huge_header_values.pak");
+
+        for &(count, name_len) in [(u32::max_value(), 1), (1, u32::max_value())].iter() {
+            let mut data = Vec::new();
+            data.extend_from_slice(MAGIC);
+            write_u32(&mut data, VERSION).unwrap();
+            write_u32(&mut data, count).unwrap();
+            write_u32(&mut data, name_len).unwrap();
+            data.extend_from_slice(&[0; 20]);
+            fs::write(&path, &data).unwrap();
+
+            let error = Pack::open(&path).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
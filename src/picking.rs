@@ -0,0 +1,241 @@
+
+//! Object selection by rendering object ids to an offscreen buffer, see [`ObjectPicker`].
+//!
+//! [`ObjectPicker`]: struct.ObjectPicker.html
+
+use std::ptr;
+
+use gl;
+use gl::types::*;
+
+use cable_math::{Vec2, Mat4};
+
+use texture::TextureFormat;
+use shader::{self, Shader};
+use framebuffer::{Framebuffer, FramebufferProperties, FramebufferError};
+
+/// Glsl for fragment shaders which write object ids. Include this in a custom fragment shader and
+/// assign `out_object_id = object_id;` to use it with an [`ObjectPicker`]. This is needed for
+/// objects which use custom vertex formats, e.g. skinned meshes.
+///
+/// [`ObjectPicker`]: struct.ObjectPicker.html
+pub const ID_OUTPUT_GLSL: &'static str = "
+uniform uint object_id;
+layout(location = 0) out uint out_object_id;
+";
+
+/// Finds which object is under a given pixel by rendering the id of each object to an integer
+/// framebuffer. Id `0` is reserved for the background, so objects should use ids starting at `1`.
+///
+/// Reading pixels back from the GPU normally stalls until all drawing has finished. [`pick`]
+/// avoids this by starting an asynchronous read every time it is called, and returning the
+/// result of the read started by the previous call. Call it once per frame after drawing ids.
+///
+/// ```rust,ignore
+/// picker.begin();
+/// for object in objects.iter() {
+///     picker.set_object(view_projection * object.transform, object.id);
+///     object.mesh.draw();
+/// }
+/// picker.end();
+///
+/// // The id under the mouse one frame ago
+/// hovered = picker.pick(input.mouse_pos);
+/// ```
+///
+/// [`pick`]: #method.pick
+pub struct ObjectPicker {
+    framebuffer: Framebuffer,
+    shader: Shader,
+
+    pixel_buffers: [GLuint; 2],
+    pending: [bool; 2],
+    next_buffer: usize,
+}
+
+impl ObjectPicker {
+    /// Creates a new picker. `size` should be the size of the window, in pixels.
+    pub fn new(size: Vec2<u32>) -> Result<ObjectPicker, FramebufferError> {
+        let mut pixel_buffers = [0; 2];
+        unsafe {
+            gl::GenBuffers(2, pixel_buffers.as_mut_ptr());
+            for &buffer in pixel_buffers.iter() {
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer);
+                gl::BufferData(gl::PIXEL_PACK_BUFFER, 4, ptr::null(), gl::STREAM_READ);
+            }
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+
+        Ok(ObjectPicker {
+            framebuffer: build_framebuffer(size)?,
+            shader: shader::build_builtin(VERT_SRC, "", FRAG_SRC),
+
+            pixel_buffers,
+            pending: [false; 2],
+            next_buffer: 0,
+        })
+    }
+
+    /// Recreates the id buffer with a new size. Should be called when the window is resized.
+    /// This discards any pending results from [`pick`].
+    ///
+    /// [`pick`]: #method.pick
+    pub fn resize(&mut self, size: Vec2<u32>) -> Result<(), FramebufferError> {
+        if size != self.framebuffer.size {
+            self.framebuffer = build_framebuffer(size)?;
+            self.pending = [false; 2];
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> Vec2<u32> {
+        self.framebuffer.size
+    }
+
+    /// The framebuffer ids are drawn to. Color attachment `0` has the format
+    /// `TextureFormat::R_U32`.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// The shader used to draw ids. It takes a `vec3` position at location 0, and has the uniforms
+    /// `mvp` and `object_id`. Usually you can use [`set_object`] instead of setting these
+    /// directly.
+    ///
+    /// [`set_object`]: #method.set_object
+    pub fn shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    /// Binds and clears the id framebuffer, which also sets the viewport to cover it, and binds
+    /// the id shader. Depth testing is left as is, and should usually be enabled.
+    pub fn begin(&self) {
+        self.framebuffer.bind();
+
+        unsafe {
+            let background = [0u32; 4];
+            gl::ClearBufferuiv(gl::COLOR, 0, background.as_ptr());
+            let depth = 1.0f32;
+            gl::ClearBufferfv(gl::DEPTH, 0, &depth);
+        }
+
+        self.shader.bind();
+    }
+
+    /// Sets the transform and id used for the next draw calls.
+    pub fn set_object(&self, mvp: Mat4<f32>, id: u32) {
+        self.shader.set_uniform("mvp", mvp);
+        self.shader.set_uniform("object_id", id);
+    }
+
+    /// Binds the backbuffer again, and restores the viewport of the window.
+    pub fn end(&self) {
+        self.framebuffer.unbind();
+    }
+
+    /// Starts reading the id at the given position, and returns the id found by the previous call
+    /// to this function. `pos` is in pixels, with `(0, 0)` at the top left, so the mouse position
+    /// can be passed directly. Returns `None` if there was no object under the cursor, or if the
+    /// position was outside the picker.
+    pub fn pick(&mut self, pos: Vec2<f32>) -> Option<u32> {
+        let current = self.next_buffer;
+        let previous = 1 - current;
+
+        self.pending[current] = false;
+        if let Some(pixel) = self.to_pixel(pos) {
+            self.framebuffer.bind();
+            unsafe {
+                gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pixel_buffers[current]);
+                gl::ReadPixels(
+                    pixel.x as GLint, pixel.y as GLint, 1, 1,
+                    gl::RED_INTEGER, gl::UNSIGNED_INT,
+                    ptr::null_mut(), // Offset into the pixel pack buffer
+                );
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            }
+            self.framebuffer.unbind();
+            self.pending[current] = true;
+        }
+
+        let mut result = None;
+        if self.pending[previous] {
+            let mut id = 0u32;
+            unsafe {
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pixel_buffers[previous]);
+                let data = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, 4, gl::MAP_READ_BIT);
+                if !data.is_null() {
+                    id = *(data as *const u32);
+                    gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+                }
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            }
+            self.pending[previous] = false;
+
+            if id != 0 {
+                result = Some(id);
+            }
+        }
+
+        self.next_buffer = previous;
+        result
+    }
+
+    /// Like [`pick`], but returns the id currently at the given position. This waits for all
+    /// drawing to finish, which can take several milliseconds.
+    ///
+    /// [`pick`]: #method.pick
+    pub fn pick_immediate(&self, pos: Vec2<f32>) -> Option<u32> {
+        let pixel = self.to_pixel(pos)?;
+        let id = self.framebuffer.get_pixel_data::<u32>(0, pixel, Vec2::new(1, 1))[0];
+        if id == 0 { None } else { Some(id) }
+    }
+
+    // Converts from top-left to bottom-left origin
+    fn to_pixel(&self, pos: Vec2<f32>) -> Option<Vec2<u32>> {
+        let size = self.framebuffer.size;
+        let (x, y) = (pos.x.floor(), pos.y.floor());
+        if x < 0.0 || y < 0.0 || x >= size.x as f32 || y >= size.y as f32 {
+            return None;
+        }
+        Some(Vec2::new(x as u32, size.y - 1 - y as u32))
+    }
+}
+
+impl Drop for ObjectPicker {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(2, self.pixel_buffers.as_ptr());
+        }
+    }
+}
+
+fn build_framebuffer(size: Vec2<u32>) -> Result<Framebuffer, FramebufferError> {
+    let mut properties = FramebufferProperties::new(size);
+    properties.color_formats = vec![TextureFormat::R_U32];
+    properties.depth_buffer = true;
+    properties.build()
+}
+
+const VERT_SRC: &'static str = "
+    #version 330 core
+
+    layout(location = 0) in vec3 pos;
+
+    uniform mat4 mvp;
+
+    void main() {
+        gl_Position = mvp * vec4(pos, 1.0);
+    }
+";
+
+const FRAG_SRC: &'static str = "
+    #version 330 core
+
+    uniform uint object_id;
+    layout(location = 0) out uint out_object_id;
+
+    void main() {
+        out_object_id = object_id;
+    }
+";
@@ -0,0 +1,246 @@
+
+//! Camera facing textured quads for particles and similar effects, see [`PointSprites`].
+//!
+//! [`PointSprites`]: struct.PointSprites.html
+
+use cable_math::{Vec2, Vec3, Mat4};
+
+use gl;
+
+use {Color, Region};
+use texture::{Texture, TextureFormat};
+use shader::{self, Shader};
+use buffer::{AttribBinding, Vertex, PrimitiveMode, BufferUsage, VertexBuffer};
+
+/// Batches points which are drawn as camera facing quads. Each point has its own size, color and
+/// uv region, so a single texture atlas can be used for many kinds of particles. The points are
+/// expanded to quads in a geometry shader, so only one vertex is uploaded per point.
+///
+/// Add points at any point during a frame, and then call [`draw`] once to draw and clear them.
+/// Blending and depth testing are left as they are, so set them up before drawing. For
+/// particles, additive blending with depth testing but without depth writes usually works well.
+///
+/// [`draw`]: #method.draw
+pub struct PointSprites {
+    points: Vec<SpriteVert>,
+    size_in_pixels: bool,
+
+    shader: Shader,
+    buffer: VertexBuffer<SpriteVert>,
+    white_texture: Texture,
+}
+
+impl PointSprites {
+    pub fn new() -> PointSprites {
+        let mut white_texture = Texture::new();
+        white_texture.load_data(&[0xff, 0xff, 0xff], 1, 1, TextureFormat::RGB_8);
+
+        PointSprites {
+            points: Vec::with_capacity(1024),
+            size_in_pixels: false,
+
+            shader: shader::build_builtin(VERT_SRC, GEOM_SRC, FRAG_SRC),
+            buffer: VertexBuffer::with_capacity(PrimitiveMode::Points, BufferUsage::DynamicDraw, 1024),
+            white_texture,
+        }
+    }
+
+    /// Decides whether point sizes are given in world units, so points get smaller as they move
+    /// away from the camera, or in pixels, like `gl_PointSize`. Defaults to world units. This
+    /// applies to all points drawn by the next call to [`draw`].
+    ///
+    /// [`draw`]: #method.draw
+    pub fn set_size_in_pixels(&mut self, size_in_pixels: bool) {
+        self.size_in_pixels = size_in_pixels;
+    }
+
+    /// Adds a point which is covered by the entire texture passed to [`draw`].
+    ///
+    /// [`draw`]: #method.draw
+    pub fn point(&mut self, pos: Vec3<f32>, size: f32, color: Color) {
+        let uv_region = Region { min: Vec2::new(0.0, 0.0), max: Vec2::new(1.0, 1.0) };
+        self.point_region(pos, size, color, uv_region);
+    }
+
+    /// Adds a point which is covered by the part of the texture given by `uv_region`. Use
+    /// [`Texture::uv_region`] to find the uv region of a part of a texture.
+    ///
+    /// [`Texture::uv_region`]: ../texture/struct.Texture.html#method.uv_region
+    pub fn point_region(&mut self, pos: Vec3<f32>, size: f32, color: Color, uv_region: Region) {
+        self.points.push(SpriteVert {
+            pos, size, color,
+            uv_min: uv_region.min,
+            uv_max: uv_region.max,
+        });
+    }
+
+    /// The number of points added since the last call to [`draw`].
+    ///
+    /// [`draw`]: #method.draw
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Draws all points added since the last call to this function, and then removes them. The
+    /// points are multiplied with `texture`, or drawn as solid squares if it is `None`. This binds
+    /// a custom shader.
+    pub fn draw(&mut self, view: Mat4<f32>, projection: Mat4<f32>, texture: Option<&Texture>) {
+        if self.points.is_empty() {
+            return;
+        }
+
+        self.buffer.clear();
+        self.buffer.put(0, &self.points);
+
+        let mut viewport = [0i32; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+        let viewport_size = Vec2::new(viewport[2] as f32, viewport[3] as f32);
+
+        self.shader.bind();
+        self.shader.set_uniform("view", view);
+        self.shader.set_uniform("projection", projection);
+        self.shader.set_uniform("viewport_size", viewport_size);
+        self.shader.set_uniform("size_in_pixels", if self.size_in_pixels { 1 } else { 0 });
+
+        texture.unwrap_or(&self.white_texture).bind(0);
+        self.buffer.draw_range(0..self.points.len());
+
+        self.points.clear();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+#[allow(dead_code)] // Fields are only read on the GPU
+struct SpriteVert {
+    pos: Vec3<f32>,
+    size: f32,
+    color: Color,
+    uv_min: Vec2<f32>,
+    uv_max: Vec2<f32>,
+}
+
+impl Vertex for SpriteVert {
+    fn setup_attrib_pointers(divisor: usize) {
+        for (_, mut binding) in SpriteVert::attrib_bindings() {
+            binding.divisor = divisor;
+            binding.enable();
+        }
+    }
+
+    fn attrib_bindings() -> Vec<(&'static str, AttribBinding)> {
+        use std::mem;
+
+        let stride = mem::size_of::<SpriteVert>();
+        let divisor = 0;
+
+        let float_attrib = |index, primitives, offset| AttribBinding {
+            index, primitives,
+            primitive_type: gl::FLOAT,
+            normalized: false,
+            integer: false,
+            stride, offset, divisor,
+        };
+
+        let pos_offset = 0;
+        let size_offset = pos_offset + mem::size_of::<Vec3<f32>>();
+        let color_offset = size_offset + mem::size_of::<f32>();
+        let uv_min_offset = color_offset + mem::size_of::<Color>();
+        let uv_max_offset = uv_min_offset + mem::size_of::<Vec2<f32>>();
+
+        vec![
+            ("pos",    float_attrib(0, 3, pos_offset)),
+            ("size",   float_attrib(1, 1, size_offset)),
+            ("color",  float_attrib(2, 4, color_offset)),
+            ("uv_min", float_attrib(3, 2, uv_min_offset)),
+            ("uv_max", float_attrib(4, 2, uv_max_offset)),
+        ]
+    }
+
+    // Not used, we manualy declare inputs in the shader
+    fn gen_shader_input_decl(_name_prefix: &str) -> String { String::new() }
+    fn gen_transform_feedback_decl(_name_prefix: &str) -> String { String::new() }
+    fn gen_transform_feedback_outputs(_name_prefix: &str) -> Vec<String> { Vec::new() }
+    fn set_as_vertex_attrib(&self) {}
+}
+
+const VERT_SRC: &'static str = "
+    #version 330 core
+
+    layout(location = 0) in vec3 in_pos;
+    layout(location = 1) in float in_size;
+    layout(location = 2) in vec4 in_color;
+    layout(location = 3) in vec2 in_uv_min;
+    layout(location = 4) in vec2 in_uv_max;
+
+    out vec4 v_color;
+    out float v_size;
+    out vec4 v_uv;
+
+    uniform mat4 view;
+
+    void main() {
+        gl_Position = view * vec4(in_pos, 1.0);
+        v_color = in_color;
+        v_size = in_size;
+        v_uv = vec4(in_uv_min, in_uv_max);
+    }
+";
+
+const GEOM_SRC: &'static str = "
+    #version 330 core
+
+    layout(points) in;
+    layout(triangle_strip, max_vertices = 4) out;
+
+    in vec4 v_color[];
+    in float v_size[];
+    in vec4 v_uv[];
+
+    out vec4 g_color;
+    out vec2 g_uv;
+
+    uniform mat4 projection;
+    uniform vec2 viewport_size;
+    uniform int size_in_pixels;
+
+    void main() {
+        vec4 center = gl_in[0].gl_Position;
+        vec4 clip_center = projection * center;
+        vec2 half_size = vec2(v_size[0] * 0.5);
+
+        // Corners in the order expected by a triangle strip. uvs have y pointing down.
+        vec2 corners[4] = vec2[](vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(-1.0, 1.0), vec2(1.0, 1.0));
+        vec2 uvs[4] = vec2[](v_uv[0].xw, v_uv[0].zw, v_uv[0].xy, v_uv[0].zy);
+
+        for (int i = 0; i < 4; ++i) {
+            if (size_in_pixels != 0) {
+                vec2 offset = corners[i] * half_size * 2.0 / viewport_size;
+                gl_Position = clip_center + vec4(offset * clip_center.w, 0.0, 0.0);
+            } else {
+                gl_Position = projection * (center + vec4(corners[i] * half_size, 0.0, 0.0));
+            }
+
+            g_color = v_color[0];
+            g_uv = uvs[i];
+            EmitVertex();
+        }
+
+        EndPrimitive();
+    }
+";
+
+const FRAG_SRC: &'static str = "
+    #version 330 core
+
+    in vec4 g_color;
+    in vec2 g_uv;
+
+    out vec4 color;
+
+    uniform sampler2D tex;
+
+    void main() {
+        color = g_color * texture(tex, g_uv);
+    }
+";
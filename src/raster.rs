@@ -0,0 +1,279 @@
+
+//! A small deterministic software rasterizer, see [`Canvas`]. This is used to render a
+//! [`DrawGroup`] without OpenGL, e.g. to compare the output of drawing code against reference
+//! images in tests.
+//!
+//! [`Canvas`]: struct.Canvas.html
+//! [`DrawGroup`]: ../draw_group/struct.DrawGroup.html
+
+use cable_math::Vec2;
+
+use Color;
+use Region;
+use color::{srgb_to_linear, linear_to_srgb};
+use draw_group::ColorMode;
+
+/// A rgba image with 8 bits per channel and a 8 bit stencil buffer, stored in cpu memory. Rows
+/// are stored from top to bottom. Software draw groups draw into a canvas with
+/// `DrawGroup::rasterize`, and also use canvases as textures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    size: Vec2<u32>,
+    pixels: Vec<[u8; 4]>,
+    stencil: Vec<u8>,
+}
+
+impl Canvas {
+    /// Creates a fully transparent canvas.
+    pub fn new(size: Vec2<u32>) -> Canvas {
+        let count = (size.x * size.y) as usize;
+        Canvas {
+            size,
+            pixels: vec![[0; 4]; count],
+            stencil: vec![0; count],
+        }
+    }
+
+    /// Creates a canvas from rgba data, e.g. to use as a texture.
+    ///
+    /// # Panics
+    /// If `rgba` does not contain exactly four bytes per pixel.
+    pub fn from_rgba(size: Vec2<u32>, rgba: &[u8]) -> Canvas {
+        assert_eq!(rgba.len(), (size.x * size.y * 4) as usize, "Expected four bytes per pixel");
+
+        let mut canvas = Canvas::new(size);
+        for (pixel, data) in canvas.pixels.iter_mut().zip(rgba.chunks(4)) {
+            pixel.copy_from_slice(data);
+        }
+        canvas
+    }
+
+    pub fn size(&self) -> Vec2<u32> {
+        self.size
+    }
+
+    /// All pixels, row by row from the top.
+    pub fn pixels(&self) -> &[[u8; 4]] {
+        &self.pixels
+    }
+
+    /// The pixel at the given position, with `(0, 0)` at the top left.
+    ///
+    /// # Panics
+    /// If the position is outside the canvas.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(x < self.size.x && y < self.size.y, "({}, {}) is outside the canvas", x, y);
+        self.pixels[(y*self.size.x + x) as usize]
+    }
+
+    /// Sets all pixels in the given region, or the whole canvas if `region` is `None`.
+    pub fn clear(&mut self, color: Color, region: Option<Region>) {
+        let color = to_rgba8(color);
+        let (min, max) = self.clip_bounds(region);
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                self.pixels[(y*self.size.x + x) as usize] = color;
+            }
+        }
+    }
+
+    pub(crate) fn clear_stencil(&mut self, region: Option<Region>) {
+        let (min, max) = self.clip_bounds(region);
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                self.stencil[(y*self.size.x + x) as usize] = 0;
+            }
+        }
+    }
+
+    // Nearest neighbour sampling, clamped to the edges
+    fn sample(&self, uv: Vec2<f32>) -> [f32; 4] {
+        let x = ((uv.x * self.size.x as f32).floor().max(0.0) as u32).min(self.size.x - 1);
+        let y = ((uv.y * self.size.y as f32).floor().max(0.0) as u32).min(self.size.y - 1);
+        let pixel = self.pixels[(y*self.size.x + x) as usize];
+
+        let mut result = [0.0; 4];
+        for i in 0..4 {
+            result[i] = pixel[i] as f32 / 255.0;
+        }
+        result
+    }
+
+    // The pixel range covered by the given region, clamped to the canvas
+    fn clip_bounds(&self, region: Option<Region>) -> (Vec2<u32>, Vec2<u32>) {
+        let size = self.size;
+        match region {
+            None => (Vec2::ZERO, size),
+            Some(region) => {
+                let clamp = |v: f32, max: u32| (v.round().max(0.0) as u32).min(max);
+                let min = Vec2::new(clamp(region.min.x, size.x), clamp(region.min.y, size.y));
+                let max = Vec2::new(clamp(region.max.x, size.x), clamp(region.max.y, size.y));
+                (min, Vec2::new(max.x.max(min.x), max.y.max(min.y)))
+            },
+        }
+    }
+}
+
+/// How a triangle interacts with the stencil buffer, mirroring the stencil state used by
+/// `DrawGroup::draw` for masks.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum StencilMode {
+    Off,
+    /// Only draw where the stencil value equals the given value.
+    Test(u8),
+    /// Increment the stencil value where it equals the given value, without drawing.
+    Increment(u8),
+    /// Decrement the stencil value where it equals the given value, without drawing.
+    Decrement(u8),
+}
+
+/// A vertex in pixel coordinates.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RasterVert {
+    pub pos: Vec2<f32>,
+    pub uv: Vec2<f32>,
+    pub color: Color,
+}
+
+/// Draws a triangle with alpha blending. Pixels are covered if their center is inside the
+/// triangle. Pixels exactly on a edge are only covered by one of the triangles sharing that edge,
+/// so there are no gaps or double blended pixels between adjacent triangles.
+///
+/// Colors are converted and blended in the same way as the draw group shader and
+/// `ColorMode::blend_settings` would for the given mode.
+pub(crate) fn triangle(
+    target: &mut Canvas,
+    verts: [RasterVert; 3],
+    texture: Option<&Canvas>,
+    clip: Option<Region>,
+    stencil: StencilMode,
+    color_mode: ColorMode,
+) {
+    let [mut a, mut b, mut c] = verts;
+    if color_mode == ColorMode::Srgb {
+        a.color = a.color.to_linear();
+        b.color = b.color.to_linear();
+        c.color = c.color.to_linear();
+    }
+
+    let mut area = edge(a.pos, b.pos, c.pos);
+    if area == 0.0 || !area.is_finite() {
+        return;
+    }
+    if area < 0.0 {
+        ::std::mem::swap(&mut a, &mut b);
+        area = -area;
+    }
+
+    // Bounding box, clamped to the clip region
+    let (clip_min, clip_max) = target.clip_bounds(clip);
+    let min_x = a.pos.x.min(b.pos.x).min(c.pos.x).floor().max(clip_min.x as f32) as u32;
+    let min_y = a.pos.y.min(b.pos.y).min(c.pos.y).floor().max(clip_min.y as f32) as u32;
+    let max_x = (a.pos.x.max(b.pos.x).max(c.pos.x).ceil().max(0.0) as u32).min(clip_max.x);
+    let max_y = (a.pos.y.max(b.pos.y).max(c.pos.y).ceil().max(0.0) as u32).min(clip_max.y);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+            let wa = edge(b.pos, c.pos, p);
+            let wb = edge(c.pos, a.pos, p);
+            let wc = edge(a.pos, b.pos, p);
+            if !covers(wa, b.pos, c.pos) || !covers(wb, c.pos, a.pos) || !covers(wc, a.pos, b.pos) {
+                continue;
+            }
+
+            let index = (y*target.size.x + x) as usize;
+
+            match stencil {
+                StencilMode::Off => {},
+                StencilMode::Test(value) => {
+                    if target.stencil[index] != value { continue; }
+                },
+                StencilMode::Increment(value) => {
+                    if target.stencil[index] == value {
+                        target.stencil[index] = value.saturating_add(1);
+                    }
+                    continue;
+                },
+                StencilMode::Decrement(value) => {
+                    if target.stencil[index] == value {
+                        target.stencil[index] = value.saturating_sub(1);
+                    }
+                    continue;
+                },
+            }
+
+            let (la, lb, lc) = (wa / area, wb / area, wc / area);
+            let color = [
+                a.color.r*la + b.color.r*lb + c.color.r*lc,
+                a.color.g*la + b.color.g*lb + c.color.g*lc,
+                a.color.b*la + b.color.b*lb + c.color.b*lc,
+                a.color.a*la + b.color.a*lb + c.color.a*lc,
+            ];
+            let mut texel = match texture {
+                Some(texture) => texture.sample(a.uv*la + b.uv*lb + c.uv*lc),
+                None => [1.0; 4],
+            };
+            match color_mode {
+                ColorMode::Straight => {},
+                ColorMode::Srgb => for i in 0..3 {
+                    texel[i] = srgb_to_linear(texel[i]);
+                },
+                ColorMode::PremultipliedLinear => for i in 0..3 {
+                    texel[i] *= texel[3];
+                },
+            }
+
+            let mut src = [0.0; 4];
+            for i in 0..4 {
+                src[i] = color[i] * texel[i];
+            }
+            blend(&mut target.pixels[index], src, color_mode);
+        }
+    }
+}
+
+// Twice the signed area of the triangle `a, b, p`
+fn edge(a: Vec2<f32>, b: Vec2<f32>, p: Vec2<f32>) -> f32 {
+    (b.x - a.x)*(p.y - a.y) - (b.y - a.y)*(p.x - a.x)
+}
+
+// Whether a point with the given edge function value is covered. Points exactly on a edge are
+// only covered for one direction of the edge, so only one of two adjacent triangles covers them.
+fn covers(w: f32, a: Vec2<f32>, b: Vec2<f32>) -> bool {
+    if w != 0.0 {
+        return w > 0.0;
+    }
+    let d = b - a;
+    d.y > 0.0 || (d.y == 0.0 && d.x < 0.0)
+}
+
+// Standard "source over" alpha blending, the same as
+// `glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA)` for the color channels. With
+// `PremultipliedLinear` the source color is not multiplied by alpha, like with
+// `glBlendFunc(GL_ONE, ...)`. With `Srgb` the destination is decoded before blending and the
+// result is encoded again, like a srgb framebuffer does.
+fn blend(dst: &mut [u8; 4], src: [f32; 4], color_mode: ColorMode) {
+    let alpha = src[3].max(0.0).min(1.0);
+    let src_factor = if color_mode == ColorMode::PremultipliedLinear { 1.0 } else { alpha };
+    for i in 0..3 {
+        let d = dst[i] as f32 / 255.0;
+        dst[i] = if color_mode == ColorMode::Srgb {
+            let result = src[i]*src_factor + srgb_to_linear(d)*(1.0 - alpha);
+            quantize(linear_to_srgb(result.max(0.0).min(1.0)))
+        } else {
+            quantize(src[i]*src_factor + d*(1.0 - alpha))
+        };
+    }
+    let d = dst[3] as f32 / 255.0;
+    dst[3] = quantize(alpha + d*(1.0 - alpha));
+}
+
+fn quantize(v: f32) -> u8 {
+    (v.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+fn to_rgba8(color: Color) -> [u8; 4] {
+    [quantize(color.r), quantize(color.g), quantize(color.b), quantize(color.a)]
+}
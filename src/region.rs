@@ -111,3 +111,25 @@ impl Region {
         return pos;
     }
 }
+
+// Custom serialization
+#[cfg(feature = "serialize")]
+mod serialize {
+    use super::*;
+
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+
+    // Serialized as a `(min, max)` tuple
+    impl Serialize for Region {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            (self.min, self.max).serialize(s)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Region {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let (min, max) = <(Vec2<f32>, Vec2<f32>)>::deserialize(d)?;
+            Ok(Region { min, max })
+        }
+    }
+}
@@ -0,0 +1,244 @@
+
+//! Scrollable viewports with inertia, see [`ScrollView`].
+//!
+//! [`ScrollView`]: struct.ScrollView.html
+
+use std::ops::Range;
+use std::hash::Hash;
+
+use cable_math::Vec2;
+
+use {Region, Input, Time, DrawGroup};
+use draw_group::StateCmd;
+
+/// Below this speed, in pixels per second, scrolling stops completely.
+const MIN_VELOCITY: f32 = 1.0;
+
+/// A rectangular window onto some larger content, which can be scrolled with the mouse wheel or by
+/// dragging. Scrolling keeps going for a short time after the wheel is turned or a drag is
+/// released, and then smoothly comes to a halt.
+///
+/// The view works in two coordinate systems: Screen space, which is the space `region` is given
+/// in, and content space, in which `(0, 0)` is the top left corner of the content. Use
+/// [`content_to_screen`] when drawing the content, and [`visible_range`] to only draw the items
+/// of a long list which are actually visible.
+///
+/// ```rust,ignore
+/// scroll.update(&input, delta);
+///
+/// scroll.begin(&mut draw_group);
+/// for i in scroll.visible_range(ITEM_HEIGHT, items.len()) {
+///     let pos = scroll.content_to_screen(Vec2::new(0.0, i as f32 * ITEM_HEIGHT));
+///     draw_group.truetype_text(&items[i], Font::Regular, 14.0, pos, None, Color::WHITE);
+/// }
+/// scroll.end(&mut draw_group);
+/// ```
+///
+/// [`content_to_screen`]: #method.content_to_screen
+/// [`visible_range`]: #method.visible_range
+#[derive(Debug, Clone)]
+pub struct ScrollView {
+    /// The area of the screen in which the content is shown.
+    pub region: Region,
+    /// The total size of the content. The view can not be scrolled past the end of the content.
+    pub content_size: Vec2<f32>,
+    /// How far one tick of the mouse wheel scrolls, in pixels. Defaults to `60.0`.
+    pub wheel_step: f32,
+    /// How quickly scrolling slows down. Higher values make scrolling stop sooner. Defaults to
+    /// `8.0`, which makes the velocity drop to a tenth in a bit less than a third of a second.
+    pub friction: f32,
+
+    offset: Vec2<f32>,
+    velocity: Vec2<f32>,
+    dragging: bool,
+}
+
+impl ScrollView {
+    pub fn new(region: Region, content_size: Vec2<f32>) -> ScrollView {
+        ScrollView {
+            region,
+            content_size,
+            wheel_step: 60.0,
+            friction: 8.0,
+
+            offset: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+            dragging: false,
+        }
+    }
+
+    /// Applies mouse input and advances the scrolling animation. Should be called once per frame
+    /// with the time since the last frame. The mouse wheel scrolls vertically while the mouse is
+    /// inside `region`. Dragging with the left mouse button, starting inside `region`, scrolls in
+    /// both directions.
+    pub fn update(&mut self, input: &Input, delta: Time) {
+        let dt = delta.to_secs_f32();
+        let hovered = self.region.contains(input.mouse_pos);
+
+        if hovered && input.mouse_keys[0].pressed() {
+            self.dragging = true;
+        }
+        if !input.mouse_keys[0].down() {
+            self.dragging = false;
+        }
+
+        if self.dragging {
+            // The content sticks to the mouse. The velocity is kept so the content keeps moving
+            // when the mouse is released.
+            self.offset -= input.mouse_delta;
+            if dt > 0.0 {
+                self.velocity = -input.mouse_delta / dt;
+            }
+        } else {
+            if hovered && input.scroll != Vec2::ZERO {
+                // With exponential decay, the total distance covered is `velocity / friction`, so
+                // this moves the content by exactly `wheel_step` per tick. Scrolling up moves
+                // towards the top of the content, scrolling right towards the right.
+                self.velocity.y -= input.scroll.y * self.wheel_step * self.friction;
+                self.velocity.x += input.scroll.x * self.wheel_step * self.friction;
+            }
+
+            self.offset += self.velocity * dt;
+            self.velocity *= (-self.friction * dt).exp();
+            if self.velocity.len() < MIN_VELOCITY {
+                self.velocity = Vec2::ZERO;
+            }
+        }
+
+        self.clamp();
+    }
+
+    /// Immediately scrolls so the given point in content space is at the top left of the view.
+    /// This stops any ongoing scrolling.
+    pub fn scroll_to(&mut self, offset: Vec2<f32>) {
+        self.offset = offset;
+        self.velocity = Vec2::ZERO;
+        self.clamp();
+    }
+
+    /// Scrolls by the smallest amount which makes the given region in content space visible. If
+    /// the region is larger than the view, its top left corner is made visible.
+    pub fn scroll_into_view(&mut self, target: Region) {
+        let size = self.region.size();
+        let mut offset = self.offset;
+
+        if target.max.x > offset.x + size.x { offset.x = target.max.x - size.x; }
+        if target.max.y > offset.y + size.y { offset.y = target.max.y - size.y; }
+        if target.min.x < offset.x { offset.x = target.min.x; }
+        if target.min.y < offset.y { offset.y = target.min.y; }
+
+        self.scroll_to(offset);
+    }
+
+    /// The point in content space which is shown at the top left corner of `region`.
+    pub fn offset(&self) -> Vec2<f32> {
+        self.offset
+    }
+
+    /// Whether the content is currently being dragged with the mouse.
+    pub fn dragging(&self) -> bool {
+        self.dragging
+    }
+
+    /// The largest possible offset. This is zero along axes in which the content fits in the view.
+    pub fn max_offset(&self) -> Vec2<f32> {
+        let size = self.region.size();
+        Vec2::new(
+            f32::max(self.content_size.x - size.x, 0.0),
+            f32::max(self.content_size.y - size.y, 0.0),
+        )
+    }
+
+    /// Converts a point in content space to screen space.
+    pub fn content_to_screen(&self, pos: Vec2<f32>) -> Vec2<f32> {
+        self.region.min + pos - self.offset
+    }
+
+    /// Converts a point in screen space, e.g. the mouse position, to content space.
+    pub fn screen_to_content(&self, pos: Vec2<f32>) -> Vec2<f32> {
+        pos - self.region.min + self.offset
+    }
+
+    /// The part of the content which is currently visible, in content space.
+    pub fn visible_content(&self) -> Region {
+        Region {
+            min: self.offset,
+            max: self.offset + self.region.size(),
+        }
+    }
+
+    /// For content consisting of `count` rows of height `item_height`, stacked vertically starting
+    /// at the top of the content, this finds the indices of the rows which are at least partially
+    /// visible.
+    pub fn visible_range(&self, item_height: f32, count: usize) -> Range<usize> {
+        if item_height <= 0.0 {
+            return 0..count;
+        }
+
+        let visible = self.visible_content();
+        let start = (visible.min.y / item_height).floor().max(0.0) as usize;
+        let end = (visible.max.y / item_height).ceil().max(0.0) as usize;
+
+        usize::min(start, count)..usize::min(end, count)
+    }
+
+    /// Pushes a clip region for `region`, so content drawn after this call does not spill out of
+    /// the view. Must be followed by a call to [`end`] once the content has been drawn.
+    ///
+    /// [`end`]: #method.end
+    pub fn begin<T, B, X>(&self, draw_group: &mut DrawGroup<T, B, X>)
+      where T: Eq + Hash + Copy,
+            B: Eq + Hash + Copy,
+            X: Eq + Hash + Copy,
+    {
+        draw_group.push_state_cmd(StateCmd::PushClip(self.region));
+    }
+
+    /// Pops the clip region pushed by [`begin`].
+    ///
+    /// [`begin`]: #method.begin
+    pub fn end<T, B, X>(&self, draw_group: &mut DrawGroup<T, B, X>)
+      where T: Eq + Hash + Copy,
+            B: Eq + Hash + Copy,
+            X: Eq + Hash + Copy,
+    {
+        draw_group.push_state_cmd(StateCmd::PopClip);
+    }
+
+    fn clamp(&mut self) {
+        let max = self.max_offset();
+
+        if self.offset.x < 0.0 || self.offset.x > max.x {
+            self.offset.x = self.offset.x.max(0.0).min(max.x);
+            self.velocity.x = 0.0;
+        }
+        if self.offset.y < 0.0 || self.offset.y > max.y {
+            self.offset.y = self.offset.y.max(0.0).min(max.y);
+            self.velocity.y = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_and_visible_range() {
+        let region = Region { min: Vec2::new(10.0, 20.0), max: Vec2::new(110.0, 120.0) };
+        let mut view = ScrollView::new(region, Vec2::new(100.0, 1000.0));
+
+        view.scroll_to(Vec2::new(0.0, 250.0));
+        assert_eq!(view.content_to_screen(Vec2::new(0.0, 250.0)), Vec2::new(10.0, 20.0));
+        assert_eq!(view.screen_to_content(Vec2::new(10.0, 20.0)), Vec2::new(0.0, 250.0));
+        assert_eq!(view.visible_range(20.0, 50), 12..18);
+
+        // Clamped to the end of the content
+        view.scroll_to(Vec2::new(50.0, 5000.0));
+        assert_eq!(view.offset(), Vec2::new(0.0, 900.0));
+        assert_eq!(view.visible_range(20.0, 40), 40..40);
+
+        view.scroll_into_view(Region { min: Vec2::new(0.0, 400.0), max: Vec2::new(10.0, 420.0) });
+        assert_eq!(view.offset(), Vec2::new(0.0, 400.0));
+    }
+}
@@ -0,0 +1,60 @@
+
+use std::cell::Cell;
+
+/// Decides what happens when a shader is used incorrectly, e.g. when setting a uniform which
+/// does not exist or setting a uniform to a value of the wrong type. Set through
+/// [`set_diagnostics`].
+///
+/// [`set_diagnostics`]: fn.set_diagnostics.html
+#[derive(Debug, Copy, Clone)]
+pub enum Diagnostics {
+    /// Prints invalid uniform and attribute names, and panics when a uniform is set to a value of
+    /// the wrong type. This is the initial policy. Invalid names are not considered fatal, as
+    /// it is convenient to ignore a uniform while refactoring a shader.
+    Default,
+    /// Panics on all errors.
+    Panic,
+    /// Passes a message describing each error to the given function.
+    Log(fn(&str)),
+    /// Ignores all errors. Incorrect calls have no effect.
+    Silent,
+}
+
+thread_local! {
+    // OpenGL calls are only valid on the thread which created the context, so there is no point in
+    // sharing this between threads.
+    static DIAGNOSTICS: Cell<Diagnostics> = Cell::new(Diagnostics::Default);
+}
+
+/// Sets how incorrect usage of shaders on the current thread is reported. See [`Diagnostics`]
+/// for the available options. This also applies to vertex layout checks done when drawing
+/// vertex buffers.
+///
+/// [`Diagnostics`]: enum.Diagnostics.html
+pub fn set_diagnostics(diagnostics: Diagnostics) {
+    DIAGNOSTICS.with(|d| d.set(diagnostics));
+}
+
+/// Retrieves the policy set with [`set_diagnostics`].
+///
+/// [`set_diagnostics`]: fn.set_diagnostics.html
+pub fn diagnostics() -> Diagnostics {
+    DIAGNOSTICS.with(|d| d.get())
+}
+
+/// Reports a error according to the current policy. `fatal` errors panic with the default
+/// policy, others are printed.
+pub(crate) fn report(message: &str, fatal: bool) {
+    match diagnostics() {
+        Diagnostics::Default => {
+            if fatal {
+                panic!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        },
+        Diagnostics::Panic => panic!("{}", message),
+        Diagnostics::Log(log) => log(message),
+        Diagnostics::Silent => {},
+    }
+}
@@ -8,33 +8,75 @@
 use std::{mem, ptr, str, fmt, error, io};
 use std::fs::File;
 use std::path::Path;
-use std::io::{BufRead, BufReader};
+use std::io::{Read, BufRead, BufReader};
+use std::str::FromStr;
 use std::ffi::CString;
 use std::borrow::Borrow;
+use std::cell::RefCell;
 
 use gl;
 use gl::types::*;
 
 use util;
+use material;
+use graphics::{self, ObjectKind};
 use buffer::Vertex;
+use skinning;
 
 mod uniform;
-pub use self::uniform::{UniformValue, UniformKind, UniformBinding};
+mod diagnostics;
+pub use self::uniform::{UniformValue, UniformKind, UniformBinding, Uniforms};
+pub use self::uniform::{set_uniform_caching, uniform_caching};
+pub use self::diagnostics::{Diagnostics, set_diagnostics, diagnostics};
+pub(crate) use self::diagnostics::report as report_diagnostic;
 
 /// A shader that has not yet been fully compiled
 pub struct ShaderPrototype {
     vert_src: String,
     frag_src: String,
     geom_src: String,
+    // Used to point errors reported by the driver to the original source
+    vert_lines: SourceMap,
+    frag_lines: SourceMap,
+    geom_lines: SourceMap,
+    file: Option<String>,
     transform_feedback_outputs: Option<Vec<String>>,
+    transform_feedback_mode: TransformFeedbackMode,
+}
+
+/// Decides how the outputs of a shader are laid out when they are captured with transform
+/// feedback.
+#[repr(u32)] // GLenum is u32
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransformFeedbackMode {
+    /// All outputs are written to a single buffer, one vertex after the other. This is the mode
+    /// used with [`VertexBuffer::transform_feedback_into`].
+    ///
+    /// [`VertexBuffer::transform_feedback_into`]: ../buffer/struct.VertexBuffer.html#method.transform_feedback_into
+    Interleaved = gl::INTERLEAVED_ATTRIBS,
+    /// Each output is written to its own buffer. The first output goes to the buffer at index 0,
+    /// the second output to the buffer at index 1, and so on. See
+    /// [`VertexArray::transform_feedback`].
+    ///
+    /// [`VertexArray::transform_feedback`]: ../buffer/struct.VertexArray.html#method.transform_feedback
+    Separate = gl::SEPARATE_ATTRIBS,
 }
 
 impl ShaderPrototype {
     /// Loads a shader from a file. The file should contain all the shader stages, with
     /// each shader stage prepended by `-- name`, where name is one of `VERT`, `FRAG`
-    /// or `GEOM`.
+    /// or `GEOM`. `#pragma stage(vertex)`, `#pragma stage(fragment)` and
+    /// `#pragma stage(geometry)` can be used instead.
+    ///
+    /// Code in a `-- COMMON` (or `#pragma stage(common)`) section is inserted at the top of
+    /// every stage, after the `#version` directive if the stage has one. This can be used for
+    /// helper functions and constants which are needed in multiple stages.
+    ///
     /// # Example file
     /// ```glsl
+    /// -- COMMON
+    /// #version 330 core
+    ///
     /// -- VERT
     /// in vec2 position;
     /// void main() {
@@ -46,28 +88,58 @@ impl ShaderPrototype {
     ///     color = vec4(1.0, 0.0, 0.0, 1.0); // Draw in red
     /// }
     /// ```
+    ///
+    /// Errors reported by the driver when building the shader refer to lines in this file.
     pub fn from_file<P>(path: P) -> Result<ShaderPrototype, ShaderError> where P: AsRef<Path> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mut prototype = ShaderPrototype::from_reader(file)?;
+        prototype.file = Some(path.display().to_string());
+        Ok(prototype)
+    }
+
+    /// Same as [`from_file`], but reads the shader from any source. This can be used to load
+    /// shaders which are embedded in the executable or stored in an asset pack. Use `str::parse`
+    /// to load a shader from a string.
+    ///
+    /// [`from_file`]: #method.from_file
+    pub fn from_reader<R>(reader: R) -> Result<ShaderPrototype, ShaderError> where R: Read {
         let mut vert_src = String::new();
         let mut frag_src = String::new();
         let mut geom_src = String::new();
+        let mut common_src = String::new();
 
-        enum Target { Vert, Frag, Geom }
+        // The line on which each stage starts
+        let mut vert_line = 1;
+        let mut frag_line = 1;
+        let mut geom_line = 1;
+        let mut common_line = 1;
+
+        enum Target { Vert, Frag, Geom, Common }
         let mut current = None;
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
+        let reader = BufReader::new(reader);
+        for (index, line) in reader.lines().enumerate() {
             let line = line?;
             let line = line.trim();
 
-            if line.starts_with("--") {
-                let value = line[2..].trim();
+            let marker = if line.starts_with("--") {
+                Some(line[2..].trim())
+            } else if line.starts_with("#pragma stage") {
+                Some(line["#pragma stage".len()..].trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace()))
+            } else {
+                None
+            };
+
+            if let Some(value) = marker {
+                let next_line = index + 2;
                 match value {
-                    "VERT" => current = Some(Target::Vert),
-                    "FRAG" => current = Some(Target::Frag),
-                    "GEOM" => current = Some(Target::Geom),
+                    "VERT" | "vertex"   => { current = Some(Target::Vert); vert_line = next_line; },
+                    "FRAG" | "fragment" => { current = Some(Target::Frag); frag_line = next_line; },
+                    "GEOM" | "geometry" => { current = Some(Target::Geom); geom_line = next_line; },
+                    "COMMON" | "common" => { current = Some(Target::Common); common_line = next_line; },
                     _ => {
-                        let message = format!("Expected 'VERT', 'FRAG' or 'GEOM', found {}", &line[2..]);
+                        let message = format!("Expected 'VERT', 'FRAG', 'GEOM' or 'COMMON', found {}", value);
                         return Err(ShaderError::FileFormat(message));
                     }
                 }
@@ -85,17 +157,40 @@ impl ShaderPrototype {
                         geom_src.push_str(line);
                         geom_src.push('\n');
                     },
+                    Some(Target::Common) => {
+                        common_src.push_str(line);
+                        common_src.push('\n');
+                    },
                     None => (),
                 }
             }
         }
 
-        Ok(ShaderPrototype {
+        let mut prototype = ShaderPrototype {
             vert_src,
             geom_src,
             frag_src,
+            vert_lines: SourceMap::new(vert_line),
+            frag_lines: SourceMap::new(frag_line),
+            geom_lines: SourceMap::new(geom_line),
+            file: None,
             transform_feedback_outputs: None,
-        })
+            transform_feedback_mode: TransformFeedbackMode::Interleaved,
+        };
+
+        if !common_src.is_empty() {
+            let ShaderPrototype {
+                ref mut vert_src, ref mut vert_lines,
+                ref mut frag_src, ref mut frag_lines,
+                ref mut geom_src, ref mut geom_lines,
+                ..
+            } = prototype;
+            insert_common(vert_src, vert_lines, &common_src, common_line);
+            insert_common(frag_src, frag_lines, &common_src, common_line);
+            insert_common(geom_src, geom_lines, &common_src, common_line);
+        }
+
+        Ok(prototype)
     }
 
     /// Creates a new shader prototype from the given string code literals.
@@ -104,7 +199,12 @@ impl ShaderPrototype {
             vert_src: vert_src.to_owned(),
             geom_src: geom_src.to_owned(),
             frag_src: frag_src.to_owned(),
+            vert_lines: SourceMap::new(1),
+            frag_lines: SourceMap::new(1),
+            geom_lines: SourceMap::new(1),
+            file: None,
             transform_feedback_outputs: None,
+            transform_feedback_mode: TransformFeedbackMode::Interleaved,
         }
     }
 
@@ -116,16 +216,16 @@ impl ShaderPrototype {
         if self.geom_src.is_empty() {
             let vert_out = create_inputs(&self.vert_src, false);
             if !self.frag_src.is_empty() {
-                prepend_code(&mut self.frag_src, &vert_out);
+                prepend_code(&mut self.frag_src, &mut self.frag_lines, &vert_out);
             }
         } else {
             if !self.frag_src.is_empty() {
                 let geom_out = create_inputs(&self.geom_src, false);
-                prepend_code(&mut self.frag_src, &geom_out);
+                prepend_code(&mut self.frag_src, &mut self.frag_lines, &geom_out);
             }
             
             let vert_out = create_inputs(&self.vert_src, true);
-            prepend_code(&mut self.geom_src, &vert_out);
+            prepend_code(&mut self.geom_src, &mut self.geom_lines, &vert_out);
         }
     }
 
@@ -134,7 +234,7 @@ impl ShaderPrototype {
     /// [`VertexBuffer`]: ../buffer/struct.VertexBuffer.html
     pub fn with_input_vert<T>(&mut self, name_prefix: &str) where T: Vertex {
         let input = <T as Vertex>::gen_shader_input_decl(name_prefix);
-        prepend_code(&mut self.vert_src, &input);
+        prepend_code(&mut self.vert_src, &mut self.vert_lines, &input);
     }
 
     /// Adds output declarations for the given vertex to this shader. This is intended for usage
@@ -144,47 +244,146 @@ impl ShaderPrototype {
     /// [1]: ../buffer/struct.VertexBuffer.html#method.transform_feedback_into
     pub fn with_transform_output_vert<T>(&mut self, name_prefix: &str) where T: Vertex {
         let output = <T as Vertex>::gen_transform_feedback_decl(name_prefix);
-        prepend_code(&mut self.vert_src, &output);
+        prepend_code(&mut self.vert_src, &mut self.vert_lines, &output);
 
         self.transform_feedback_outputs = Some(<T as Vertex>::gen_transform_feedback_outputs(name_prefix));
     }
 
-    /// Converts this prototype into a shader
+    /// Adds the inputs of [`SkinnedVert`] and the skinning code from [`SKINNING_GLSL`] to the
+    /// vertex shader. The vertex shader can then call `skin_matrix()` to get the blended bone
+    /// transform for the current vertex. See the [`skinning`] module for an example.
+    ///
+    /// [`SkinnedVert`]: ../skinning/struct.SkinnedVert.html
+    /// [`SKINNING_GLSL`]: ../skinning/constant.SKINNING_GLSL.html
+    /// [`skinning`]: ../skinning/index.html
+    pub fn with_skinning(&mut self) {
+        // Inputs need to be declared before the skinning code, and `prepend_code` inserts at the
+        // top of the shader
+        prepend_code(&mut self.vert_src, &mut self.vert_lines, skinning::SKINNING_GLSL);
+        self.with_input_vert::<skinning::SkinnedVert>("");
+    }
+
+    /// Captures the given outputs with transform feedback, in the given mode. Unlike
+    /// [`with_transform_output_vert`] this does not declare the outputs, so they should already
+    /// be declared in the shader source. This replaces any previously set outputs.
+    ///
+    /// With [`TransformFeedbackMode::Separate`], each output is written to its own buffer, so
+    /// e.g. a particle system can keep positions and velocities in distinct buffers.
+    ///
+    /// [`with_transform_output_vert`]: #method.with_transform_output_vert
+    /// [`TransformFeedbackMode::Separate`]: enum.TransformFeedbackMode.html#variant.Separate
+    pub fn with_transform_outputs(&mut self, outputs: &[&str], mode: TransformFeedbackMode) {
+        self.transform_feedback_outputs = Some(outputs.iter().map(|s| s.to_string()).collect());
+        self.transform_feedback_mode = mode;
+    }
+
+    /// Changes how outputs added with [`with_transform_output_vert`] or
+    /// [`with_transform_outputs`] are captured. Defaults to
+    /// [`TransformFeedbackMode::Interleaved`].
+    ///
+    /// [`with_transform_output_vert`]: #method.with_transform_output_vert
+    /// [`with_transform_outputs`]: #method.with_transform_outputs
+    /// [`TransformFeedbackMode::Interleaved`]: enum.TransformFeedbackMode.html#variant.Interleaved
+    pub fn set_transform_feedback_mode(&mut self, mode: TransformFeedbackMode) {
+        self.transform_feedback_mode = mode;
+    }
+
+    /// Converts this prototype into a shader. Line numbers in compile errors are remapped to
+    /// the original source, skipping code inserted by e.g. [`propagate_outputs`], and are
+    /// prefixed with the name of the stage.
+    ///
+    /// [`propagate_outputs`]: #method.propagate_outputs
     pub fn build(&self) -> Result<Shader, ShaderError> {
-        let vert_src = self.vert_src.as_str();
-        let frag_src = if self.frag_src.is_empty() { None } else { Some(self.frag_src.as_str()) };
-        let geom_src = if self.geom_src.is_empty() { None } else { Some(self.geom_src.as_str()) };
+        let file = self.file.as_ref().map(|f| f.as_str());
+        let vert = Stage { src: &self.vert_src, lines: &self.vert_lines, file };
+        let frag = Stage { src: &self.frag_src, lines: &self.frag_lines, file };
+        let geom = Stage { src: &self.geom_src, lines: &self.geom_lines, file };
+
+        let frag = if frag.src.is_empty() { None } else { Some(frag) };
+        let geom = if geom.src.is_empty() { None } else { Some(geom) };
+
+        Shader::new(
+            vert, geom, frag, file,
+            self.transform_feedback_outputs.clone(), self.transform_feedback_mode,
+        )
+    }
+}
+
+impl FromStr for ShaderPrototype {
+    type Err = ShaderError;
 
-        Shader::new(vert_src, geom_src, frag_src, self.transform_feedback_outputs.clone())
+    /// Loads a shader from a string in the same format as [`from_file`]
+    ///
+    /// [`from_file`]: #method.from_file
+    fn from_str(src: &str) -> Result<ShaderPrototype, ShaderError> {
+        ShaderPrototype::from_reader(src.as_bytes())
     }
 }
 
+/// A vertex attribute of a shader, see [`Shader::attributes`].
+///
+/// [`Shader::attributes`]: struct.Shader.html#method.attributes
+#[derive(Debug, Clone)]
+pub struct AttributeBinding {
+    pub name: String,
+    pub location: GLint,
+    /// The glsl type of the attribute, e.g. `gl::FLOAT_VEC3`.
+    pub kind: GLenum,
+    /// The number of elements, if the attribute is an array. `1` otherwise.
+    pub size: usize,
+}
+
+/// A uniform block of a shader, see [`Shader::uniform_blocks`].
+///
+/// [`Shader::uniform_blocks`]: struct.Shader.html#method.uniform_blocks
+#[derive(Debug, Clone)]
+pub struct UniformBlockBinding {
+    pub name: String,
+    /// The index passed to `glUniformBlockBinding`.
+    pub index: GLuint,
+    /// The minimum size of a buffer bound to this block, in bytes.
+    pub data_size: usize,
+    /// The names of the active uniforms in this block.
+    pub members: Vec<String>,
+}
+
 /// A OpenGL shader that is ready for use
 pub struct Shader {
-    program: GLuint,
+    pub(crate) program: GLuint,
     uniforms: Vec<UniformBinding>,
+    attributes: Vec<AttributeBinding>,
+    uniform_blocks: Vec<UniformBlockBinding>,
+    // Addresses of uniform lists which have been passed to `validate_uniforms`
+    validated_uniforms: RefCell<Vec<usize>>,
+    // The last value set for each uniform location, see `set_uniform_caching`
+    uniform_cache: RefCell<Vec<(GLint, uniform::CachedValue)>>,
+    generation: usize, // See `graphics::is_current_context`
 }
 
 impl Shader {
     fn new(
-        vert_src: &str,
-        geom_src: Option<&str>,
-        frag_src: Option<&str>,
-        transform_feedback_outputs: Option<Vec<String>>
+        vert: Stage,
+        geom: Option<Stage>,
+        frag: Option<Stage>,
+        file: Option<&str>,
+        transform_feedback_outputs: Option<Vec<String>>,
+        transform_feedback_mode: TransformFeedbackMode,
     ) -> Result<Shader, ShaderError> 
     {
         let program;
         let mut uniforms;
+        let mut attributes;
+        let mut uniform_blocks;
 
         unsafe {
             program = gl::CreateProgram();
 
-            let vert_shader = compile(vert_src, gl::VERTEX_SHADER)?;
+            let vert_shader = compile(vert, gl::VERTEX_SHADER)?;
             gl::AttachShader(program, vert_shader);
 
             let geom_shader = {
-                if let Some(geom_src) = geom_src {
-                    let geom_shader = compile(geom_src, gl::GEOMETRY_SHADER)?;
+                if let Some(geom) = geom {
+                    let geom_shader = compile(geom, gl::GEOMETRY_SHADER)?;
                     gl::AttachShader(program, geom_shader);
 
                     Some(geom_shader)
@@ -194,8 +393,8 @@ impl Shader {
             };
 
             let frag_shader = {
-                if let Some(frag_src) = frag_src {
-                    let frag_shader = compile(frag_src, gl::FRAGMENT_SHADER)?;
+                if let Some(frag) = frag {
+                    let frag_shader = compile(frag, gl::FRAGMENT_SHADER)?;
                     gl::AttachShader(program, frag_shader);
 
                     Some(frag_shader)
@@ -212,7 +411,7 @@ impl Shader {
                     .map(|n| n.as_ptr())
                     .collect::<Vec<_>>();
 
-                gl::TransformFeedbackVaryings(program, name_ptrs.len() as GLsizei, name_ptrs.as_ptr(), gl::INTERLEAVED_ATTRIBS);
+                gl::TransformFeedbackVaryings(program, name_ptrs.len() as GLsizei, name_ptrs.as_ptr(), transform_feedback_mode as GLenum);
             }
 
             gl::LinkProgram(program);
@@ -241,13 +440,10 @@ impl Shader {
                 gl::DeleteProgram(program);
 
                 let message = str::from_utf8(&buffer).expect("Shader log was not valid UTF-8").to_string();
-                let message = format!(
-                    "{}\nFor source:\n-- VERT\n{}\n-- FRAG\n{}\n-- GEOM\n{}",
-                    message,
-                    vert_src,
-                    geom_src.unwrap_or(""),
-                    frag_src.unwrap_or(""),
-                );
+                let message = match file {
+                    Some(file) => format!("In {}:\n{}", file, message),
+                    None => message,
+                };
                 return Err(ShaderError::Link(message));
             } 
 
@@ -288,14 +484,110 @@ impl Shader {
 
                 uniforms.push(UniformBinding { name, location, kind });
             }
+
+            // Load attributes
+            let mut attribute_count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut attribute_count);
+
+            attributes = Vec::with_capacity(attribute_count as usize);
+
+            for index in 0..attribute_count {
+                const MAX_NAME_LENGTH: usize = 512;
+
+                let mut name_length = 0;
+                let mut name_buffer = [0u8; MAX_NAME_LENGTH];
+
+                let mut size = 0;
+                let mut kind = 0;
+
+                gl::GetActiveAttrib(
+                    program, index as u32,
+                    MAX_NAME_LENGTH as i32,
+                    &mut name_length,
+                    &mut size,
+                    &mut kind,
+                    name_buffer.as_mut_ptr() as *mut i8,
+                );
+
+                let location = gl::GetAttribLocation(
+                    program,
+                    name_buffer.as_ptr() as *const i8
+                );
+
+                let name = util::ascii_to_string(&name_buffer[.. (name_length as usize)]);
+
+                attributes.push(AttributeBinding { name, location, kind, size: size as usize });
+            }
+
+            // Load uniform blocks
+            let mut block_count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut block_count);
+
+            uniform_blocks = Vec::with_capacity(block_count as usize);
+
+            for index in 0..block_count {
+                const MAX_NAME_LENGTH: usize = 512;
+
+                let mut name_length = 0;
+                let mut name_buffer = [0u8; MAX_NAME_LENGTH];
+
+                gl::GetActiveUniformBlockName(
+                    program, index as u32,
+                    MAX_NAME_LENGTH as i32,
+                    &mut name_length,
+                    name_buffer.as_mut_ptr() as *mut i8,
+                );
+                let name = util::ascii_to_string(&name_buffer[.. (name_length as usize)]);
+
+                let mut data_size = 0;
+                gl::GetActiveUniformBlockiv(program, index as u32, gl::UNIFORM_BLOCK_DATA_SIZE, &mut data_size);
+
+                let mut member_count = 0;
+                gl::GetActiveUniformBlockiv(program, index as u32, gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS, &mut member_count);
+
+                let mut member_indices = vec![0 as GLint; member_count as usize];
+                if member_count > 0 {
+                    gl::GetActiveUniformBlockiv(
+                        program, index as u32,
+                        gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+                        member_indices.as_mut_ptr(),
+                    );
+                }
+
+                // Uniform indices are the same as the indices into `uniforms`
+                let members = member_indices.iter()
+                    .filter_map(|&i| uniforms.get(i as usize))
+                    .map(|binding| binding.name.clone())
+                    .collect();
+
+                uniform_blocks.push(UniformBlockBinding {
+                    name,
+                    index: index as u32,
+                    data_size: data_size as usize,
+                    members,
+                });
+            }
         }
 
+        graphics::register_object(ObjectKind::Shader, program);
+
         Ok(Shader {
             program,
             uniforms,
+            attributes,
+            uniform_blocks,
+            validated_uniforms: RefCell::new(Vec::new()),
+            uniform_cache: RefCell::new(Vec::new()),
+            generation: graphics::context_generation(),
         })
     }
 
+    /// Sets a name for this shader, which is shown by `graphics::live_objects` and, if
+    /// `KHR_debug` is supported, in OpenGL debug messages and graphics debuggers.
+    pub fn set_debug_label(&mut self, name: &str) {
+        graphics::set_object_label(ObjectKind::Shader, self.program, name);
+    }
+
     /// Binds this shader, replacing the previously bound shader. Subsequent draw calls
     /// will use this shader. Note that there is no method provided to unbind a shader,
     /// as it should never be necesarry.
@@ -303,6 +595,94 @@ impl Shader {
         unsafe {
             gl::UseProgram(self.program);
         }
+        material::note_program(self.program);
+    }
+
+    /// Rebuilds this shader from the given prototype, e.g. after its source file changed on
+    /// disk. Uniforms which exist with the same name and type in the new shader keep their
+    /// current values, and uniform blocks keep their binding indices, so values tweaked at
+    /// runtime survive reloading. For uniform arrays, only the first element is kept.
+    ///
+    /// If the new shader fails to compile or link, the error is returned and this shader is
+    /// left unchanged. This binds the new shader.
+    pub fn rebuild(&mut self, prototype: &ShaderPrototype) -> Result<(), ShaderError> {
+        let mut new = prototype.build()?;
+        new.bind();
+
+        for old in self.uniforms.iter().filter(|u| u.location >= 0) {
+            let binding = new.uniforms.iter().find(|u| {
+                u.location >= 0 && u.name == old.name && u.kind == old.kind
+            });
+            if let Some(binding) = binding {
+                unsafe { copy_uniform(self.program, old.location, binding.location, old.kind); }
+            }
+        }
+
+        for old in self.uniform_blocks.iter() {
+            if let Some(block) = new.uniform_blocks.iter().find(|b| b.name == old.name) {
+                unsafe {
+                    let mut binding_index = 0;
+                    gl::GetActiveUniformBlockiv(self.program, old.index, gl::UNIFORM_BLOCK_BINDING, &mut binding_index);
+                    gl::UniformBlockBinding(new.program, block.index, binding_index as GLuint);
+                }
+            }
+        }
+
+        material::note_uniforms_changed(new.program);
+
+        // The old program is deleted when `new` is dropped
+        mem::swap(self, &mut new);
+        Ok(())
+    }
+
+    /// All active uniforms in this shader. Uniforms which are not used by the shader are usually
+    /// optimized away by the driver, and will not be listed. Uniforms inside uniform blocks are
+    /// included, and have a location of `-1`.
+    ///
+    /// Together with [`try_set_uniform`], this can be used to e.g. generate UI for tweaking
+    /// material parameters.
+    ///
+    /// [`try_set_uniform`]: #method.try_set_uniform
+    pub fn uniforms(&self) -> &[UniformBinding] {
+        &self.uniforms
+    }
+
+    /// All active vertex attributes in this shader.
+    pub fn attributes(&self) -> &[AttributeBinding] {
+        &self.attributes
+    }
+
+    /// All active uniform blocks in this shader. See [`bind_uniform_block`].
+    ///
+    /// [`bind_uniform_block`]: #method.bind_uniform_block
+    pub fn uniform_blocks(&self) -> &[UniformBlockBinding] {
+        &self.uniform_blocks
+    }
+
+    // Binds this shader and sets the uniform at the given location, unless it already has the
+    // given value
+    fn set_uniform_value<T: UniformValue>(&self, location: GLint, value: &T) {
+        self.bind();
+
+        let new = if uniform::uniform_caching() { uniform::cached_value(value) } else { None };
+        {
+            let mut cache = self.uniform_cache.borrow_mut();
+            let index = cache.iter().position(|&(l, _)| l == location);
+            match (index, new) {
+                (Some(index), Some(new)) => {
+                    if cache[index].1 == new {
+                        return;
+                    }
+                    cache[index].1 = new;
+                },
+                (Some(index), None) => { cache.swap_remove(index); },
+                (None, Some(new)) => cache.push((location, new)),
+                (None, None) => {},
+            }
+        }
+
+        unsafe { T::set_uniform(value, location); }
+        material::note_uniforms_changed(self.program);
     }
 
     fn get_uniform_binding(&self, name: &str) -> Option<&UniformBinding> {
@@ -330,7 +710,9 @@ impl Shader {
     /// is an array this can be used to set a specific element of that array. For example, if the
     /// shader contains `uniform vec3 positions[2];`, `set_uniform_with_offset(1, "positions", ...)`
     /// will modify the second elment of the positions array.  This prints a warning if no uniform 
-    /// with the given name exists.
+    /// with the given name exists. See [`set_diagnostics`] to change how errors are reported.
+    ///
+    /// [`set_diagnostics`]: fn.set_diagnostics.html
     ///
     /// This binds this shader if the given uniform exists!
     pub fn set_uniform_with_offset<T, U>(&self, uniform_name: &str, offset: usize, value: U) 
@@ -340,20 +722,72 @@ impl Shader {
         if let Some(binding) = self.get_uniform_binding(uniform_name) {
             let value_kind = T::KIND;
             if binding.kind != value_kind {
-                panic!(
+                let message = format!(
                     "Tried to set uniform \"{}\" to a `{}`, but the uniform has type `{}`",
                     binding.name, value_kind, binding.kind,
                 );
+                diagnostics::report(&message, true);
             } else {
-                self.bind();
-                unsafe { T::set_uniform(value.borrow(), binding.location + offset as GLint); }
+                self.set_uniform_value(binding.location + offset as GLint, value.borrow());
             }
         } else {
-            // The reason we simply print a error here is because it sometimes is convenient to
-            // ignore a uniform while refactoring a shader. panicking or returning some result would
-            // force changing rust code when glsl code is changed, which slows down the development
-            // process.
-            println!("Invalid uniform name: {}", uniform_name); 
+            // See `Diagnostics::Default` for why this is not fatal
+            diagnostics::report(&format!("Invalid uniform name: {}", uniform_name), false);
+        }
+    }
+
+    /// Sets the uniform with the given name to the given value, if it exists and has a matching
+    /// type. Unlike [`set_uniform`], this does not report any errors. Returns whether the uniform
+    /// was set.
+    ///
+    /// This binds this shader if the given uniform exists!
+    ///
+    /// [`set_uniform`]: #method.set_uniform
+    pub fn try_set_uniform<T>(&self, uniform_name: &str, value: &T) -> bool
+      where T: UniformValue,
+    {
+        match self.get_uniform_binding(uniform_name) {
+            Some(binding) if binding.kind == T::KIND => {
+                self.set_uniform_value(binding.location, value);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Checks that this shader has a uniform with the given name and type for each entry in the
+    /// given list, and reports any mismatches. See [`set_diagnostics`] to change how they are
+    /// reported. Each list is only checked the first time it is passed to a given shader, so this
+    /// is cheap to call every frame. Implementations of [`Uniforms`] generated by
+    /// `#[derive(Uniforms)]` call this from `apply`.
+    ///
+    /// [`set_diagnostics`]: fn.set_diagnostics.html
+    /// [`Uniforms`]: trait.Uniforms.html
+    pub fn validate_uniforms(&self, uniforms: &'static [(&'static str, UniformKind)]) {
+        let key = uniforms.as_ptr() as usize;
+        {
+            let mut validated = self.validated_uniforms.borrow_mut();
+            if validated.contains(&key) {
+                return;
+            }
+            validated.push(key);
+        }
+
+        for &(name, kind) in uniforms.iter() {
+            match self.get_uniform_binding(name) {
+                Some(binding) if binding.kind != kind => {
+                    let message = format!(
+                        "Uniform \"{}\" is a `{}`, but the uniform has type `{}`",
+                        name, kind, binding.kind,
+                    );
+                    diagnostics::report(&message, true);
+                },
+                Some(_) => {},
+                None => {
+                    // See `Diagnostics::Default` for why this is not fatal
+                    diagnostics::report(&format!("Invalid uniform name: {}", name), false);
+                },
+            }
         }
     }
 
@@ -368,20 +802,36 @@ impl Shader {
         if let Some(binding) = self.get_uniform_binding(uniform_name) {
             let value_kind = T::KIND;
             if binding.kind != value_kind {
-                panic!(
+                let message = format!(
                     "Tried to set uniform \"{}\" to a `{}`, but the uniform has type `{}`",
                     binding.name, value_kind, binding.kind,
                 );
+                diagnostics::report(&message, true);
             } else {
+                let range = binding.location .. binding.location + slice.len() as GLint;
+                self.uniform_cache.borrow_mut().retain(|&(location, _)| !range.contains(&location));
+
                 self.bind();
                 unsafe { T::set_uniform_slice(slice, binding.location); }
+                material::note_uniforms_changed(self.program);
             }
         } else {
-            // The reason we simply print a error here is because it sometimes is convenient to
-            // ignore a uniform while refactoring a shader. panicking or returning some result would
-            // force changing rust code when glsl code is changed, which slows down the development
-            // process.
-            println!("Invalid uniform name: {}", uniform_name); 
+            // See `Diagnostics::Default` for why this is not fatal
+            diagnostics::report(&format!("Invalid uniform name: {}", uniform_name), false);
+        }
+    }
+
+    /// Queries the location of the vertex attribute with the given name. Returns `None` if the
+    /// shader has no active attribute with that name, e.g. because it is unused and was optimized
+    /// away by the driver.
+    pub fn attribute_location(&self, attribute_name: &str) -> Option<u32> {
+        let c_str = CString::new(attribute_name).unwrap();
+        let location = unsafe { gl::GetAttribLocation(self.program, c_str.as_ptr()) };
+
+        if location < 0 {
+            None
+        } else {
+            Some(location as u32)
         }
     }
 
@@ -430,7 +880,7 @@ impl Shader {
             let c_str = CString::new(block_name).unwrap();
             let block_index = gl::GetUniformBlockIndex(self.program, c_str.as_ptr());
             if block_index == gl::INVALID_INDEX {
-                println!("Invalid uniform");
+                diagnostics::report(&format!("Invalid uniform block name: {}", block_name), false);
             } else {
                 gl::UniformBlockBinding(self.program, block_index, binding_index as GLuint);
             }
@@ -440,35 +890,289 @@ impl Shader {
 
 impl Drop for Shader {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteProgram(self.program);
+        if graphics::is_current_context(self.generation) {
+            unsafe {
+                gl::DeleteProgram(self.program);
+            }
+            material::note_program_deleted(self.program);
+            graphics::unregister_object(ObjectKind::Shader, self.program);
         }
     }
 }
 
+// Copies the value of a uniform in `program` to a uniform in the currently bound program.
+// `kind` comes straight from `glGetActiveUniform`, so it can be any glsl type. Types which we
+// don't know the size of are skipped, as reading them could overflow the scratch buffers.
+unsafe fn copy_uniform(program: GLuint, from: GLint, to: GLint, kind: UniformKind) {
+    let mut floats = [0.0f32; 16];
+    let mut ints = [0i32; 16];
+    let mut uints = [0u32; 16];
+
+    let kind = kind as GLenum;
+    match kind {
+        gl::FLOAT | gl::FLOAT_VEC2 | gl::FLOAT_VEC3 | gl::FLOAT_VEC4 | gl::FLOAT_MAT4 => {
+            gl::GetUniformfv(program, from, floats.as_mut_ptr());
+            match kind {
+                gl::FLOAT      => gl::Uniform1fv(to, 1, floats.as_ptr()),
+                gl::FLOAT_VEC2 => gl::Uniform2fv(to, 1, floats.as_ptr()),
+                gl::FLOAT_VEC3 => gl::Uniform3fv(to, 1, floats.as_ptr()),
+                gl::FLOAT_VEC4 => gl::Uniform4fv(to, 1, floats.as_ptr()),
+                _              => gl::UniformMatrix4fv(to, 1, gl::FALSE, floats.as_ptr()),
+            }
+        },
+        gl::UNSIGNED_INT | gl::UNSIGNED_INT_VEC2 | gl::UNSIGNED_INT_VEC3 | gl::UNSIGNED_INT_VEC4 => {
+            gl::GetUniformuiv(program, from, uints.as_mut_ptr());
+            match kind {
+                gl::UNSIGNED_INT      => gl::Uniform1uiv(to, 1, uints.as_ptr()),
+                gl::UNSIGNED_INT_VEC2 => gl::Uniform2uiv(to, 1, uints.as_ptr()),
+                gl::UNSIGNED_INT_VEC3 => gl::Uniform3uiv(to, 1, uints.as_ptr()),
+                _                     => gl::Uniform4uiv(to, 1, uints.as_ptr()),
+            }
+        },
+        gl::INT | gl::INT_VEC2 | gl::INT_VEC3 | gl::INT_VEC4 => {
+            gl::GetUniformiv(program, from, ints.as_mut_ptr());
+            match kind {
+                gl::INT      => gl::Uniform1iv(to, 1, ints.as_ptr()),
+                gl::INT_VEC2 => gl::Uniform2iv(to, 1, ints.as_ptr()),
+                gl::INT_VEC3 => gl::Uniform3iv(to, 1, ints.as_ptr()),
+                _            => gl::Uniform4iv(to, 1, ints.as_ptr()),
+            }
+        },
+        // Samplers and bools are not in `UniformKind`, but are read and set as a single int
+        _ if is_sampler(kind) || kind == gl::BOOL => {
+            gl::GetUniformiv(program, from, ints.as_mut_ptr());
+            gl::Uniform1iv(to, 1, ints.as_ptr());
+        },
+        _ => {},
+    }
+}
+
+fn is_sampler(kind: GLenum) -> bool {
+    match kind {
+        gl::SAMPLER_1D | gl::SAMPLER_2D | gl::SAMPLER_3D | gl::SAMPLER_CUBE |
+        gl::SAMPLER_1D_SHADOW | gl::SAMPLER_2D_SHADOW | gl::SAMPLER_CUBE_SHADOW |
+        gl::SAMPLER_1D_ARRAY | gl::SAMPLER_2D_ARRAY | gl::SAMPLER_CUBE_MAP_ARRAY |
+        gl::SAMPLER_1D_ARRAY_SHADOW | gl::SAMPLER_2D_ARRAY_SHADOW | gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW |
+        gl::SAMPLER_2D_MULTISAMPLE | gl::SAMPLER_2D_MULTISAMPLE_ARRAY |
+        gl::SAMPLER_2D_RECT | gl::SAMPLER_2D_RECT_SHADOW | gl::SAMPLER_BUFFER |
+
+        gl::INT_SAMPLER_1D | gl::INT_SAMPLER_2D | gl::INT_SAMPLER_3D | gl::INT_SAMPLER_CUBE |
+        gl::INT_SAMPLER_1D_ARRAY | gl::INT_SAMPLER_2D_ARRAY | gl::INT_SAMPLER_CUBE_MAP_ARRAY |
+        gl::INT_SAMPLER_2D_MULTISAMPLE | gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY |
+        gl::INT_SAMPLER_2D_RECT | gl::INT_SAMPLER_BUFFER |
+
+        gl::UNSIGNED_INT_SAMPLER_1D | gl::UNSIGNED_INT_SAMPLER_2D | gl::UNSIGNED_INT_SAMPLER_3D |
+        gl::UNSIGNED_INT_SAMPLER_CUBE | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY |
+        gl::UNSIGNED_INT_SAMPLER_2D_ARRAY | gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY |
+        gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY |
+        gl::UNSIGNED_INT_SAMPLER_2D_RECT | gl::UNSIGNED_INT_SAMPLER_BUFFER => true,
+
+        _ => false,
+    }
+}
+
 /// Prepends the given section of code to the beginning of the given piece of
 /// shader src. Note that code is inserted after the `#version ...`
-/// preprocessor, if present.
-fn prepend_code(src: &mut String, code: &str) {
-    let insert_index =
-        if let Some(preprocessor_index) = src.find("#version") {
-            if let Some(newline_index) = src[preprocessor_index..].find('\n') {
-                newline_index + preprocessor_index
-            } else {
-                // We might want to warn the user in this case. A shader with a
-                // #version preprocessor but no newline will (I think) never
-                // be valid, unless the code inserted here makes it valid
-                src.len() 
-            }
-        } else {
-            0
-        };
+/// preprocessor, if present. The inserted lines are recorded in `lines`.
+fn prepend_code(src: &mut String, lines: &mut SourceMap, code: &str) {
+    let insert_index = version_end(src);
+
+    let after_line = src[..insert_index].lines().count();
+    lines.inserted.push((after_line, code.matches('\n').count() + 2, None));
 
     src.insert(insert_index, '\n');
     src.insert_str(insert_index + 1, code);
     src.insert(insert_index + 1 + code.len(), '\n');
 }
 
+/// Inserts the code from a `-- COMMON` section, which ends with a newline and starts on
+/// `common_line` in the file, into the given stage. Nothing is inserted into empty stages.
+fn insert_common(src: &mut String, lines: &mut SourceMap, common: &str, common_line: usize) {
+    if src.is_empty() {
+        return;
+    }
+
+    let insert_index = version_end(src);
+    let after_line = src[..insert_index].lines().count();
+    lines.inserted.push((after_line, common.matches('\n').count(), Some(common_line)));
+
+    if insert_index == 0 {
+        src.insert_str(0, common);
+    } else {
+        // Move the trailing newline to the front, so the `#version` line is not extended
+        src.insert_str(insert_index, &common[..common.len() - 1]);
+        src.insert(insert_index, '\n');
+    }
+}
+
+// The index at which code can be inserted at the top of a shader, after the `#version ...`
+// preprocessor if present
+fn version_end(src: &str) -> usize {
+    if let Some(preprocessor_index) = src.find("#version") {
+        if let Some(newline_index) = src[preprocessor_index..].find('\n') {
+            newline_index + preprocessor_index
+        } else {
+            // We might want to warn the user in this case. A shader with a
+            // #version preprocessor but no newline will (I think) never
+            // be valid, unless the code inserted here makes it valid
+            src.len() 
+        }
+    } else {
+        0
+    }
+}
+
+/// Maps lines in the source of a stage, as passed to OpenGL, to lines in the file or string the
+/// stage was loaded from.
+#[derive(Debug, Clone)]
+struct SourceMap {
+    // The line in the file on which the stage starts
+    first_line: usize,
+    // Code inserted by `prepend_code` and `insert_common`, as the line it was inserted after, the
+    // number of inserted lines and the line in the file it was copied from, if any. Stored in
+    // the order in which it was inserted.
+    inserted: Vec<(usize, usize, Option<usize>)>,
+}
+
+impl SourceMap {
+    fn new(first_line: usize) -> SourceMap {
+        SourceMap { first_line, inserted: Vec::new() }
+    }
+
+    /// The line in the original source, or `None` if the line was inserted.
+    fn original_line(&self, line: usize) -> Option<usize> {
+        let mut line = line;
+        for &(after, count, source) in self.inserted.iter().rev() {
+            if line > after + count {
+                line -= count;
+            } else if line > after {
+                return source.map(|first| first + (line - after - 1));
+            }
+        }
+        Some(line + self.first_line - 1)
+    }
+}
+
+/// The source of a single shader stage, passed to `compile`.
+#[derive(Copy, Clone)]
+struct Stage<'a> {
+    src: &'a str,
+    lines: &'a SourceMap,
+    file: Option<&'a str>,
+}
+
+fn stage_name(shader_type: GLenum) -> &'static str {
+    match shader_type {
+        gl::VERTEX_SHADER   => "VERT",
+        gl::GEOMETRY_SHADER => "GEOM",
+        gl::FRAGMENT_SHADER => "FRAG",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Rewrites line references in a info log from the driver, e.g. `0(12) : error ...` (Nvidia),
+/// `0:12(5): error: ...` (Mesa) or `ERROR: 0:12: ...` (AMD), to refer to the original source
+/// and the given stage.
+fn remap_log(log: &str, stage: &str, file: Option<&str>, lines: &SourceMap) -> String {
+    let mut result = String::with_capacity(log.len());
+
+    for line in log.lines() {
+        let reference = find_line_reference(line);
+        let (start, end, number, column) = match reference {
+            Some(reference) => reference,
+            None => {
+                result.push_str(line);
+                result.push('\n');
+                continue;
+            },
+        };
+
+        result.push_str(&line[..start]);
+        match lines.original_line(number) {
+            Some(number) => {
+                if let Some(file) = file {
+                    result.push_str(file);
+                    result.push(':');
+                }
+                result.push_str(&number.to_string());
+                if let Some(column) = column {
+                    result.push(':');
+                    result.push_str(&column.to_string());
+                }
+                result.push_str(&format!(" ({})", stage));
+            },
+            None => {
+                result.push_str(&format!("generated code ({})", stage));
+            },
+        }
+        result.push_str(&line[end..]);
+        result.push('\n');
+    }
+
+    result
+}
+
+// Finds a reference to a line at the start of a line in a info log, after a optional prefix like
+// `ERROR: `. Returns the byte range of the reference, the line and the column, if given.
+fn find_line_reference(line: &str) -> Option<(usize, usize, usize, Option<usize>)> {
+    fn digits(s: &str) -> usize {
+        s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len())
+    }
+
+    let mut start = 0;
+    if let Some(colon) = line.find(": ") {
+        if colon > 0 && line[..colon].chars().all(|c| c.is_ascii_uppercase()) {
+            start = colon + 2;
+        }
+    }
+
+    // The index of the source string, which is always 0 for us
+    let mut end = start + digits(&line[start..]);
+    if end == start {
+        return None;
+    }
+
+    let parens = line[end..].starts_with('(');
+    if !parens && !line[end..].starts_with(':') {
+        return None;
+    }
+    end += 1;
+
+    let number_len = digits(&line[end..]);
+    if number_len == 0 {
+        return None;
+    }
+    let number = line[end..end + number_len].parse().ok()?;
+    end += number_len;
+
+    if parens {
+        if !line[end..].starts_with(')') {
+            return None;
+        }
+        end += 1;
+    }
+
+    // Mesa also gives the column
+    let mut column = None;
+    if !parens && line[end..].starts_with('(') {
+        let column_len = digits(&line[end + 1..]);
+        if column_len > 0 && line[end + 1 + column_len..].starts_with(')') {
+            column = line[end + 1..end + 1 + column_len].parse().ok();
+            end += column_len + 2;
+        }
+    }
+
+    Some((start, end, number, column))
+}
+
+// Builds one of the shaders used internally by this crate. Those are fixed, so failing to
+// build them is a bug, and the panic includes the error.
+pub(crate) fn build_builtin(vert_src: &str, geom_src: &str, frag_src: &str) -> Shader {
+    ShaderPrototype::new_prototype(vert_src, geom_src, frag_src)
+        .build()
+        .unwrap_or_else(|err| panic!("Invalid built-in shader: {}", err))
+}
+
 /// Finds all variables marked as `out` in the given glsl shader and generates
 /// corresponding ´in´ declarations for the next shader stage. These declarations
 /// can be inserted into the next stage with `prepend_code()`.
@@ -547,11 +1251,11 @@ pub fn create_inputs(src: &str, for_geom: bool) -> String {
     result
 }
 
-fn compile(src: &str, shader_type: GLenum) -> Result<GLuint, ShaderError> {
+fn compile(stage: Stage, shader_type: GLenum) -> Result<GLuint, ShaderError> {
     unsafe {
         let shader = gl::CreateShader(shader_type);
 
-        let c_str = CString::new(src.as_bytes()).unwrap();
+        let c_str = CString::new(stage.src.as_bytes()).unwrap();
         gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
         gl::CompileShader(shader);
 
@@ -568,9 +1272,13 @@ fn compile(src: &str, shader_type: GLenum) -> Result<GLuint, ShaderError> {
 
             gl::DeleteShader(shader);
 
-            let message = str::from_utf8(&buffer).ok().expect("Shader log is not valid utf8").to_string();
-            let message = format!("{}For source: \"\n{}\"",
-                                  message, src);
+            let log = str::from_utf8(&buffer).ok().expect("Shader log is not valid utf8");
+            let name = stage_name(shader_type);
+            let log = remap_log(log, name, stage.file, stage.lines);
+            let message = match stage.file {
+                Some(file) => format!("In {} stage of {}:\n{}", name, file, log),
+                None => format!("In {} stage:\n{}", name, log),
+            };
             return Err(ShaderError::Compile(message));
         } else {
             return Ok(shader);
@@ -730,5 +1438,71 @@ mod tests {
         let geom_inputs = create_inputs(shader, true);
         assert_eq!("in vec4 color[]; flat in ivec2 tile[]; in vec2 tex[];", geom_inputs);
     }
-}
 
+    #[test]
+    fn stages() {
+        let src = "
+            -- VERT
+            void main() {}
+            -- FRAG
+            out vec4 color;
+        ";
+
+        let prototype: ShaderPrototype = src.parse().unwrap();
+        assert_eq!("void main() {}\n", prototype.vert_src);
+        assert_eq!("out vec4 color;\n\n", prototype.frag_src);
+        assert!(prototype.geom_src.is_empty());
+
+        assert_eq!(prototype.vert_lines.first_line, 3);
+        assert_eq!(prototype.frag_lines.first_line, 5);
+
+        let invalid = "-- VERTEX\nvoid main() {}";
+        match invalid.parse::<ShaderPrototype>() {
+            Err(ShaderError::FileFormat(_)) => {},
+            _ => panic!("Expected a file format error"),
+        }
+    }
+
+    #[test]
+    fn common_section() {
+        let src = "
+            -- COMMON
+            float twice(float x) { return x*2.0; }
+            #pragma stage(vertex)
+            #version 330 core
+            void main() {}
+            #pragma stage(fragment)
+            out vec4 color;
+        ";
+
+        let prototype: ShaderPrototype = src.parse().unwrap();
+        assert_eq!("#version 330 core\nfloat twice(float x) { return x*2.0; }\nvoid main() {}\n", prototype.vert_src);
+        assert_eq!("float twice(float x) { return x*2.0; }\nout vec4 color;\n\n", prototype.frag_src);
+        assert!(prototype.geom_src.is_empty());
+
+        assert_eq!(prototype.vert_lines.original_line(1), Some(5));
+        assert_eq!(prototype.vert_lines.original_line(2), Some(3));
+        assert_eq!(prototype.vert_lines.original_line(3), Some(6));
+        assert_eq!(prototype.frag_lines.original_line(2), Some(8));
+    }
+
+    #[test]
+    fn error_lines() {
+        let mut src = "#version 330 core\nvoid main() {\n    oops\n}\n".to_string();
+        let mut lines = SourceMap::new(10);
+        prepend_code(&mut src, &mut lines, "in vec2 a;\nin vec2 b;");
+        prepend_code(&mut src, &mut lines, "in vec4 c;");
+
+        assert_eq!(src.lines().nth(7), Some("    oops"));
+        assert_eq!(lines.original_line(1), Some(10));
+        assert_eq!(lines.original_line(2), None);
+        assert_eq!(lines.original_line(8), Some(12));
+
+        let nvidia = remap_log("0(8) : error C0000: syntax error", "FRAG", Some("a.glsl"), &lines);
+        assert_eq!(nvidia, "a.glsl:12 (FRAG) : error C0000: syntax error\n");
+        let mesa = remap_log("0:8(5): error: syntax error", "FRAG", None, &lines);
+        assert_eq!(mesa, "12:5 (FRAG): error: syntax error\n");
+        let amd = remap_log("ERROR: 0:3: 'c' : redefinition", "VERT", None, &lines);
+        assert_eq!(amd, "ERROR: generated code (VERT): 'c' : redefinition\n");
+    }
+}
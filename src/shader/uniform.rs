@@ -1,10 +1,17 @@
 
-use std::fmt;
+use std::{fmt, mem, ptr};
+use std::cell::Cell;
 
 use gl;
 use gl::types::*;
 use cable_math::{Mat4, Vec2, Vec3, Vec4};
 
+use super::Shader;
+
+/// A uniform of a shader, see [`Shader::uniforms`].
+///
+/// [`Shader::uniforms`]: struct.Shader.html#method.uniforms
+#[derive(Debug, Clone)]
 pub struct UniformBinding {
     pub name: String,
     pub location: GLint,
@@ -14,11 +21,86 @@ pub struct UniformBinding {
 /// Everything which implements this trait can be stured into the uniform value of a shader.
 pub trait UniformValue: Sized {
     const KIND: UniformKind;
+    /// Whether this type consists only of 32 bit components, without any padding, and is at
+    /// most 64 bytes large. `Shader` remembers the last value set for uniforms of such types, and
+    /// skips setting a uniform to the value it already has. Defaults to `false`.
+    const CACHEABLE: bool = false;
 
     unsafe fn set_uniform(data: &Self, location: GLint); 
     unsafe fn set_uniform_slice(slice: &[Self], location: GLint);
 }
 
+thread_local! {
+    static CACHING: Cell<bool> = Cell::new(true);
+}
+
+/// Enables or disables skipping redundant uniform updates on the current thread. When enabled,
+/// which is the default, setting a uniform through `Shader` to the value it was last set to does
+/// not call into OpenGL. Disable this if uniforms are modified with raw OpenGL calls, or to work
+/// around drivers which lose uniform values.
+pub fn set_uniform_caching(enabled: bool) {
+    CACHING.with(|c| c.set(enabled));
+}
+
+/// Whether redundant uniform updates are skipped, see [`set_uniform_caching`].
+///
+/// [`set_uniform_caching`]: fn.set_uniform_caching.html
+pub fn uniform_caching() -> bool {
+    CACHING.with(|c| c.get())
+}
+
+/// The raw components of a uniform value, and how many of them are used.
+pub(crate) type CachedValue = ([u32; 16], usize);
+
+/// The components of the given value, or `None` if values of the type can not be cached.
+pub(crate) fn cached_value<T: UniformValue>(value: &T) -> Option<CachedValue> {
+    let size = mem::size_of::<T>();
+    if !T::CACHEABLE || size > 64 || size % 4 != 0 {
+        return None;
+    }
+
+    let mut words = [0u32; 16];
+    unsafe {
+        ptr::copy_nonoverlapping(value as *const T as *const u8, words.as_mut_ptr() as *mut u8, size);
+    }
+    Some((words, size / 4))
+}
+
+/// A group of uniform values which are set together, e.g. all the per-frame parameters of a
+/// shader. Instead of setting each uniform by name, the values are stored in a struct and set with
+/// a single call to `apply`.
+///
+/// This trait can be automatically derived for a struct with `#[derive(Uniforms)]`. All fields
+/// need to implement [`UniformValue`], and by default each field sets the uniform with the same
+/// name. Use `#[uniform(name = "...")]` to set a uniform with a different name. The first time a
+/// struct is applied to a shader, its fields are checked against the uniforms of the shader, and
+/// any missing uniforms or mismatched types are reported (See [`Shader::validate_uniforms`]).
+///
+/// ```rust,ignore
+/// extern crate gondola;
+/// #[macro_use]
+/// extern crate gondola_derive;
+///
+/// use gondola::shader::Uniforms; // We need to use the trait to derive it
+///
+/// #[derive(Uniforms)]
+/// struct LightUniforms {
+///     mvp: Mat4<f32>,
+///     #[uniform(name = "light_dir")]
+///     direction: Vec3<f32>,
+///     intensity: f32,
+/// }
+///
+/// light_uniforms.apply(&shader);
+/// ```
+///
+/// [`UniformValue`]: trait.UniformValue.html
+/// [`Shader::validate_uniforms`]: struct.Shader.html#method.validate_uniforms
+pub trait Uniforms {
+    /// Sets all uniforms in this group on the given shader.
+    fn apply(&self, shader: &Shader);
+}
+
 #[repr(u32)] // GLenum is u32
 #[allow(non_camel_case_types)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -47,6 +129,7 @@ pub enum UniformKind {
 // Implementations for vectors and matricies
 impl UniformValue for Vec2<f32> { 
     const KIND: UniformKind = UniformKind::VEC2_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec2<f32>, location: GLint) {
         gl::Uniform2f(location, vec.x, vec.y); 
@@ -59,6 +142,7 @@ impl UniformValue for Vec2<f32> {
 
 impl UniformValue for Vec2<i32> { 
     const KIND: UniformKind = UniformKind::VEC2_I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec2<i32>, location: GLint) {
         gl::Uniform2i(location, vec.x, vec.y); 
@@ -71,6 +155,7 @@ impl UniformValue for Vec2<i32> {
 
 impl UniformValue for Vec2<u32> {
     const KIND: UniformKind = UniformKind::VEC2_U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec2<u32>, location: GLint) {
         gl::Uniform2ui(location, vec.x, vec.y); 
@@ -83,6 +168,7 @@ impl UniformValue for Vec2<u32> {
 
 impl UniformValue for Vec3<f32> { 
     const KIND: UniformKind = UniformKind::VEC3_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec3<f32>, location: GLint) {
         gl::Uniform3f(location, vec.x, vec.y, vec.z); 
@@ -95,6 +181,7 @@ impl UniformValue for Vec3<f32> {
 
 impl UniformValue for Vec3<i32> { 
     const KIND: UniformKind = UniformKind::VEC3_I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec3<i32>, location: GLint) {
         gl::Uniform3i(location, vec.x, vec.y, vec.z); 
@@ -107,6 +194,7 @@ impl UniformValue for Vec3<i32> {
 
 impl UniformValue for Vec3<u32> {
     const KIND: UniformKind = UniformKind::VEC3_U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec3<u32>, location: GLint) {
         gl::Uniform3ui(location, vec.x, vec.y, vec.z); 
@@ -119,6 +207,7 @@ impl UniformValue for Vec3<u32> {
 
 impl UniformValue for Vec4<f32> { 
     const KIND: UniformKind = UniformKind::VEC4_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec4<f32>, location: GLint) {
         gl::Uniform4f(location, vec.x, vec.y, vec.z, vec.w); 
@@ -131,6 +220,7 @@ impl UniformValue for Vec4<f32> {
 
 impl UniformValue for Vec4<i32> { 
     const KIND: UniformKind = UniformKind::VEC4_I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec4<i32>, location: GLint) {
         gl::Uniform4i(location, vec.x, vec.y, vec.z, vec.w); 
@@ -143,6 +233,7 @@ impl UniformValue for Vec4<i32> {
 
 impl UniformValue for Vec4<u32> {
     const KIND: UniformKind = UniformKind::VEC4_U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(vec: &Vec4<u32>, location: GLint) {
         gl::Uniform4ui(location, vec.x, vec.y, vec.z, vec.w); 
@@ -155,6 +246,7 @@ impl UniformValue for Vec4<u32> {
 
 impl UniformValue for Mat4<f32> {
     const KIND: UniformKind = UniformKind::MAT4_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(mat: &Mat4<f32>, location: GLint) {
         gl::UniformMatrix4fv(location, 1, false as GLboolean, &(mat.a11) as *const GLfloat); 
@@ -168,6 +260,7 @@ impl UniformValue for Mat4<f32> {
 // Implementations for f32, i32 and u32 single values and tuples.
 impl UniformValue for f32 {
     const KIND: UniformKind = UniformKind::F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &f32, location: GLint) {
         gl::Uniform1f(location, *value); 
@@ -180,6 +273,7 @@ impl UniformValue for f32 {
 
 impl UniformValue for (f32, f32) {
     const KIND: UniformKind = UniformKind::VEC2_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform2f(location, value.0, value.1); 
@@ -192,6 +286,7 @@ impl UniformValue for (f32, f32) {
 
 impl UniformValue for (f32, f32, f32) {
     const KIND: UniformKind = UniformKind::VEC3_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform3f(location, value.0, value.1, value.2); 
@@ -204,6 +299,7 @@ impl UniformValue for (f32, f32, f32) {
 
 impl UniformValue for (f32, f32, f32, f32) {
     const KIND: UniformKind = UniformKind::VEC4_F32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform4f(location, value.0, value.1, value.2, value.3); 
@@ -216,6 +312,7 @@ impl UniformValue for (f32, f32, f32, f32) {
 
 impl UniformValue for i32 {
     const KIND: UniformKind = UniformKind::I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &i32, location: GLint) {
         gl::Uniform1i(location, *value); 
@@ -228,6 +325,7 @@ impl UniformValue for i32 {
 
 impl UniformValue for (i32, i32) {
     const KIND: UniformKind = UniformKind::VEC2_I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform2i(location, value.0, value.1); 
@@ -240,6 +338,7 @@ impl UniformValue for (i32, i32) {
 
 impl UniformValue for (i32, i32, i32) {
     const KIND: UniformKind = UniformKind::VEC3_I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform3i(location, value.0, value.1, value.2); 
@@ -252,6 +351,7 @@ impl UniformValue for (i32, i32, i32) {
 
 impl UniformValue for (i32, i32, i32, i32) {
     const KIND: UniformKind = UniformKind::VEC4_I32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform4i(location, value.0, value.1, value.2, value.3); 
@@ -264,6 +364,7 @@ impl UniformValue for (i32, i32, i32, i32) {
 
 impl UniformValue for u32 {
     const KIND: UniformKind = UniformKind::U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &u32, location: GLint) {
         gl::Uniform1ui(location, *value); 
@@ -276,6 +377,7 @@ impl UniformValue for u32 {
 
 impl UniformValue for (u32, u32) {
     const KIND: UniformKind = UniformKind::VEC2_U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform2ui(location, value.0, value.1); 
@@ -288,6 +390,7 @@ impl UniformValue for (u32, u32) {
 
 impl UniformValue for (u32, u32, u32) {
     const KIND: UniformKind = UniformKind::VEC3_U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform3ui(location, value.0, value.1, value.2); 
@@ -300,6 +403,7 @@ impl UniformValue for (u32, u32, u32) {
 
 impl UniformValue for (u32, u32, u32, u32) {
     const KIND: UniformKind = UniformKind::VEC4_U32;
+    const CACHEABLE: bool = true;
 
     unsafe fn set_uniform(value: &Self, location: GLint) {
         gl::Uniform4ui(location, value.0, value.1, value.2, value.3); 
@@ -336,3 +440,18 @@ impl fmt::Display for UniformKind {
         f.write_str(name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_values() {
+        let a = cached_value(&Vec3::new(1.0f32, 2.0, 3.0)).unwrap();
+        assert_eq!(a.1, 3);
+        assert_eq!(&a.0[..3], &[1.0f32.to_bits(), 2.0f32.to_bits(), 3.0f32.to_bits()]);
+        assert!(cached_value(&(1.0f32, 2.0f32, 3.0f32)) == Some(a));
+        assert!(cached_value(&Vec3::new(1.0f32, 2.0, 4.0)) != Some(a));
+        assert_eq!(cached_value(&Mat4::<f32>::IDENTITY).unwrap().1, 16);
+    }
+}
@@ -0,0 +1,236 @@
+
+//! Utilities for drawing skeletal meshes, where each vertex is moved by a weighted blend of up to
+//! four bone transforms.
+//!
+//! The bone transforms are stored in a uniform buffer, see [`BoneMatrices`]. Vertices use
+//! [`SkinnedVert`], which stores which bones affect the vertex and by how much. A shader which
+//! uses both can be created with [`ShaderPrototype::with_skinning`], which adds the vertex inputs
+//! and a `skin_matrix()` function to the vertex shader:
+//!
+//! ```glsl
+//! uniform mat4 view_projection;
+//!
+//! void main() {
+//!     mat4 skin = skin_matrix();
+//!     gl_Position = view_projection * skin * vec4(pos, 1.0);
+//! }
+//! ```
+//!
+//! ```rust,ignore
+//! let mut proto = ShaderPrototype::from_file("skinned.glsl")?;
+//! proto.with_skinning();
+//! let shader = proto.build()?;
+//! shader.bind_uniform_block(skinning::BONE_BLOCK_NAME, 0);
+//!
+//! let mut bones = BoneMatrices::new();
+//! bones.set(&pose);
+//! bones.bind_base(0);
+//! ```
+//!
+//! [`BoneMatrices`]: struct.BoneMatrices.html
+//! [`SkinnedVert`]: struct.SkinnedVert.html
+//! [`ShaderPrototype::with_skinning`]: ../shader/struct.ShaderPrototype.html#method.with_skinning
+
+use std::mem;
+
+use cable_math::{Vec2, Vec3, Vec4, Mat4};
+
+use buffer::{AttribBinding, Vertex, VertexData, GlPrimitive, PrimitiveBuffer, BufferTarget, BufferUsage};
+
+/// The largest number of bones a single skeleton can have. This is the size of the bone array in
+/// the uniform block.
+pub const MAX_BONES: usize = 64;
+
+/// The name of the uniform block declared by [`SKINNING_GLSL`]. Pass this to
+/// `Shader::bind_uniform_block`.
+///
+/// [`SKINNING_GLSL`]: constant.SKINNING_GLSL.html
+pub const BONE_BLOCK_NAME: &'static str = "bone_block";
+
+/// The glsl code added to vertex shaders by [`ShaderPrototype::with_skinning`]. This declares the
+/// bone uniform block and `mat4 skin_matrix()`, which blends the bones of the current vertex. It
+/// expects the inputs of [`SkinnedVert`] to be declared before it.
+///
+/// [`ShaderPrototype::with_skinning`]: ../shader/struct.ShaderPrototype.html#method.with_skinning
+/// [`SkinnedVert`]: struct.SkinnedVert.html
+pub const SKINNING_GLSL: &'static str = "
+layout(std140) uniform bone_block {
+    mat4 bones[64];
+};
+
+mat4 skin_matrix() {
+    return
+        bones[bone_indices.x] * bone_weights.x +
+        bones[bone_indices.y] * bone_weights.y +
+        bones[bone_indices.z] * bone_weights.z +
+        bones[bone_indices.w] * bone_weights.w;
+}
+";
+
+/// A vertex which is moved by up to four bones. In glsl, the fields are available as `pos`,
+/// `normal`, `uv`, `bone_indices` (a `uvec4`) and `bone_weights` (a `vec4`).
+///
+/// The weights of a vertex should add up to one. Unused bone slots should have a weight of zero.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkinnedVert {
+    pub pos: Vec3<f32>,
+    pub normal: Vec3<f32>,
+    pub uv: Vec2<f32>,
+    /// Indices into the array given to [`BoneMatrices::set`].
+    ///
+    /// [`BoneMatrices::set`]: struct.BoneMatrices.html#method.set
+    pub bone_indices: [u8; 4],
+    pub bone_weights: Vec4<f32>,
+}
+
+impl Vertex for SkinnedVert {
+    fn setup_attrib_pointers(divisor: usize) {
+        for (_, mut binding) in SkinnedVert::attrib_bindings() {
+            binding.divisor = divisor;
+            binding.enable();
+        }
+    }
+
+    fn attrib_bindings() -> Vec<(&'static str, AttribBinding)> {
+        fn binding<T: VertexData>(index: usize, offset: usize) -> AttribBinding {
+            AttribBinding {
+                index,
+                primitives: T::primitives(),
+                primitive_type: <T::Primitive as GlPrimitive>::GL_ENUM,
+                normalized: false,
+                integer: <T::Primitive as GlPrimitive>::IS_INTEGER,
+                stride: mem::size_of::<SkinnedVert>(),
+                offset,
+                divisor: 0,
+            }
+        }
+
+        let pos = 0;
+        let normal = pos + mem::size_of::<Vec3<f32>>();
+        let uv = normal + mem::size_of::<Vec3<f32>>();
+        let bone_indices = uv + mem::size_of::<Vec2<f32>>();
+        let bone_weights = bone_indices + mem::size_of::<[u8; 4]>();
+
+        vec![
+            ("pos",          binding::<Vec3<f32>>(0, pos)),
+            ("normal",       binding::<Vec3<f32>>(1, normal)),
+            ("uv",           binding::<Vec2<f32>>(2, uv)),
+            ("bone_indices", binding::<[u8; 4]>(3, bone_indices)),
+            ("bone_weights", binding::<Vec4<f32>>(4, bone_weights)),
+        ]
+    }
+
+    fn gen_shader_input_decl(name_prefix: &str) -> String {
+        let types = [
+            Vec3::<f32>::get_glsl_type(),
+            Vec3::<f32>::get_glsl_type(),
+            Vec2::<f32>::get_glsl_type(),
+            <[u8; 4]>::get_glsl_type(),
+            Vec4::<f32>::get_glsl_type(),
+        ];
+
+        let mut result = String::from("\n");
+        for (&(name, ref binding), glsl_type) in SkinnedVert::attrib_bindings().iter().zip(types.iter()) {
+            result.push_str(&format!(
+                "layout(location = {}) in {} {}{};\n",
+                binding.index, glsl_type, name_prefix, name,
+            ));
+        }
+        result
+    }
+
+    fn gen_transform_feedback_decl(name_prefix: &str) -> String {
+        let decl = SkinnedVert::gen_shader_input_decl(name_prefix);
+        decl.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| format!("out {}\n", line.splitn(2, " in ").nth(1).unwrap()))
+            .collect()
+    }
+
+    fn gen_transform_feedback_outputs(name_prefix: &str) -> Vec<String> {
+        SkinnedVert::attrib_bindings().iter()
+            .map(|&(name, _)| format!("{}{}", name_prefix, name))
+            .collect()
+    }
+
+    fn set_as_vertex_attrib(&self) {
+        self.pos.set_as_vertex_attrib(0);
+        self.normal.set_as_vertex_attrib(1);
+        self.uv.set_as_vertex_attrib(2);
+        // Integer attributes can not be set through `VertexData`
+        unsafe {
+            use gl;
+            let i = self.bone_indices;
+            gl::VertexAttribI4ui(3, i[0] as u32, i[1] as u32, i[2] as u32, i[3] as u32);
+        }
+        self.bone_weights.set_as_vertex_attrib(4);
+    }
+}
+
+/// A uniform buffer containing the bone transforms of a skeleton, laid out as expected by the
+/// uniform block in [`SKINNING_GLSL`].
+///
+/// [`SKINNING_GLSL`]: constant.SKINNING_GLSL.html
+pub struct BoneMatrices {
+    buffer: PrimitiveBuffer<Mat4<f32>>,
+}
+
+impl BoneMatrices {
+    pub fn new() -> BoneMatrices {
+        BoneMatrices {
+            buffer: PrimitiveBuffer::with_capacity(BufferTarget::Uniform, BufferUsage::DynamicDraw, MAX_BONES),
+        }
+    }
+
+    /// Replaces the bone transforms. Each matrix should transform from the bind pose of the mesh
+    /// to the current pose of the bone, i.e. it should already include the inverse bind matrix.
+    /// Panics if more than [`MAX_BONES`] matrices are given.
+    ///
+    /// [`MAX_BONES`]: constant.MAX_BONES.html
+    pub fn set(&mut self, matrices: &[Mat4<f32>]) {
+        assert!(
+            matrices.len() <= MAX_BONES,
+            "Too many bones ({}), at most {} are supported", matrices.len(), MAX_BONES
+        );
+
+        // `mat4` has the same layout in std140 as `Mat4<f32>`, so no padding is needed
+        self.buffer.clear();
+        self.buffer.put_at_start(matrices);
+    }
+
+    /// The number of bones set by the last call to [`set`].
+    ///
+    /// [`set`]: #method.set
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Binds the bone matrices to the given uniform buffer binding index. Shaders which had
+    /// [`BONE_BLOCK_NAME`] bound to the same index with `Shader::bind_uniform_block` will read
+    /// their bones from this buffer.
+    ///
+    /// [`BONE_BLOCK_NAME`]: constant.BONE_BLOCK_NAME.html
+    pub fn bind_base(&self, index: usize) {
+        self.buffer.bind_base(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_declarations() {
+        let inputs = SkinnedVert::gen_shader_input_decl("");
+        assert!(inputs.contains("layout(location = 3) in uvec4 bone_indices;"));
+        assert!(inputs.contains("layout(location = 4) in vec4 bone_weights;"));
+
+        let outputs = SkinnedVert::gen_transform_feedback_decl("out_");
+        assert!(outputs.contains("out vec3 out_pos;\n"));
+        assert_eq!(SkinnedVert::gen_transform_feedback_outputs("out_")[2], "out_uv");
+
+        let stride = SkinnedVert::attrib_bindings()[0].1.stride;
+        assert_eq!(stride, mem::size_of::<f32>()*(3 + 3 + 2 + 1 + 4));
+    }
+}
@@ -0,0 +1,173 @@
+
+//! Drawing very large amounts of truetype text, see [`TextBatch`].
+//!
+//! [`TextBatch`]: struct.TextBatch.html
+
+use cable_math::{Vec2, Vec4, Mat4};
+
+use Color;
+use font::TruetypeFont;
+use shader::{self, Shader};
+use buffer::{VertexData, VertexArray, TextureBuffer, PrimitiveMode, BufferUsage};
+
+/// Batches truetype text, uploading a single instance per glyph instead of six vertices. The
+/// quads of the glyphs are expanded in the vertex shader, which reads the glyph instances from a
+/// [`TextureBuffer`]. Use this instead of `DrawGroup::truetype_text` when drawing tens of
+/// thousands of glyphs each frame, e.g. for on-screen debug logs, where building vertices
+/// becomes the bottleneck.
+///
+/// All text in a batch must use the same font. Add text at any point during a frame, and then
+/// call [`draw`] once to draw and clear it. Blending is left as it is, so enable alpha blending
+/// before drawing.
+///
+/// ```rust,ignore
+/// let mut batch = TextBatch::new();
+///
+/// // Each frame
+/// for (i, line) in log.lines().enumerate() {
+///     batch.text(&mut font, line, 14.0, Vec2::new(4.0, 16.0*(i + 1) as f32), None, Color::WHITE);
+/// }
+/// batch.draw(&font, transform);
+/// ```
+///
+/// Like with `DrawGroup`, caching new glyphs can evict glyphs used by text added earlier in the
+/// same frame if the glyph cache of the font is full. This only happens with many different
+/// sizes or characters.
+///
+/// [`TextureBuffer`]: ../buffer/struct.TextureBuffer.html
+/// [`draw`]: #method.draw
+pub struct TextBatch {
+    glyphs: Vec<GlyphInstance>,
+    quad_scratch: Vec<(Vec2<f32>, Vec2<f32>)>,
+
+    shader: Shader,
+    buffer: TextureBuffer<GlyphInstance>,
+    // Empty, all data is read from `buffer` in the shader
+    vertex_array: VertexArray,
+}
+
+impl TextBatch {
+    pub fn new() -> TextBatch {
+        TextBatch {
+            glyphs: Vec::with_capacity(1024),
+            quad_scratch: Vec::new(),
+
+            shader: shader::build_builtin(VERT_SRC, "", FRAG_SRC),
+            buffer: TextureBuffer::with_capacity(4, BufferUsage::DynamicDraw, 1024),
+            vertex_array: VertexArray::new(),
+        }
+    }
+
+    /// Adds the given text, with the top left of the first line at `pos`. The text is laid out
+    /// and wrapped in the same way as with `DrawGroup::truetype_text`.
+    pub fn text(
+        &mut self,
+        font: &mut TruetypeFont,
+        text: &str,
+        size: f32,
+        pos: Vec2<f32>,
+        wrap_width: Option<f32>,
+        color: Color,
+    ) {
+        let ref mut quads = self.quad_scratch;
+        quads.clear();
+        font.cache(text, size, 1.0, pos, wrap_width, |pos, uv| quads.push((pos, uv)));
+
+        // `cache` outputs two triangles per glyph, where the first and third vertex are the top
+        // left and bottom right corners
+        self.glyphs.extend(quads.chunks(6).map(|quad| {
+            let (min, uv_min) = quad[0];
+            let (max, uv_max) = quad[2];
+            GlyphInstance {
+                rect: Vec4::new(min.x, min.y, max.x, max.y),
+                uv: Vec4::new(uv_min.x, uv_min.y, uv_max.x, uv_max.y),
+                color,
+            }
+        }));
+    }
+
+    /// The number of glyphs added since the last call to [`draw`].
+    ///
+    /// [`draw`]: #method.draw
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Draws all text added since the last call to this function, and then removes it. `font`
+    /// should be the font the text was added with. `transform` is applied to the positions of
+    /// glyphs, in the same way as in `DrawGroup::draw`. This binds a custom shader.
+    pub fn draw(&mut self, font: &TruetypeFont, transform: Mat4<f32>) {
+        if self.glyphs.is_empty() {
+            return;
+        }
+
+        self.buffer.clear();
+        self.buffer.put(0, &self.glyphs);
+
+        self.shader.bind();
+        self.shader.set_uniform("transform", transform);
+        self.shader.set_uniform("glyphs", 1);
+
+        font.texture().bind(0);
+        self.buffer.bind_texture(1);
+        self.vertex_array.draw_instanced(PrimitiveMode::Triangles, 0..6, self.glyphs.len());
+
+        self.glyphs.clear();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct GlyphInstance {
+    rect: Vec4<f32>, // min.x, min.y, max.x, max.y
+    uv: Vec4<f32>,
+    color: Color,
+}
+
+// Read as three `vec4` texels per glyph
+impl VertexData for GlyphInstance {
+    type Primitive = f32;
+}
+
+const VERT_SRC: &'static str = "
+    #version 330 core
+
+    out vec4 v_color;
+    out vec2 v_uv;
+
+    uniform mat4 transform;
+    uniform samplerBuffer glyphs;
+
+    void main() {
+        vec4 rect  = texelFetch(glyphs, gl_InstanceID*3 + 0);
+        vec4 uv    = texelFetch(glyphs, gl_InstanceID*3 + 1);
+        vec4 color = texelFetch(glyphs, gl_InstanceID*3 + 2);
+
+        // Two triangles, in the same order as `TruetypeFont::cache` outputs them
+        vec2 corners[6] = vec2[](
+            vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+            vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+        );
+        vec2 corner = corners[gl_VertexID];
+
+        gl_Position = transform * vec4(mix(rect.xy, rect.zw, corner), 0.0, 1.0);
+        v_uv = mix(uv.xy, uv.zw, corner);
+        v_color = color;
+    }
+";
+
+const FRAG_SRC: &'static str = "
+    #version 330 core
+
+    in vec4 v_color;
+    in vec2 v_uv;
+
+    out vec4 color;
+
+    uniform sampler2D tex;
+
+    void main() {
+        // Only alpha, so subpixel fonts are drawn with their average coverage
+        color = vec4(v_color.rgb, v_color.a * texture(tex, v_uv).a);
+    }
+";
@@ -6,40 +6,68 @@ use std::ptr;
 use std::fmt;
 use std::error;
 use std::path::Path;
-use std::borrow::Cow;
-use std::fs::File;
+use std::fs;
 use png;
 use gl;
 use gl::types::*;
+use cable_math::Vec2;
+
+use Color;
+use Region;
+use graphics::{self, ResourceKind, ObjectKind};
+use material;
+use shader::Shader;
+use image_decoder;
+use animation::{Animation, RawAnimationData};
 
 /// A wraper around a OpenGL texture object which can be modified
 #[derive(Debug)]
 pub struct Texture {
-    texture: GLuint,
+    pub(crate) texture: GLuint,
     pub format: TextureFormat,
     pub width: u32,
     pub height: u32,
+    bytes: usize, // Size of the storage, as reported to `graphics::note_allocation`
+    generation: usize, // See `graphics::is_current_context`
 }
 
 impl Texture { 
     /// Creates a texture from a raw OpenGL handle and some additional data. Intended for internal
     /// use only, use with care!
     pub fn wrap_gl_texture(texture: GLuint, format: TextureFormat, width: u32, height: u32) -> Texture {
+        let bytes = format.storage_size(width, height);
+        graphics::note_allocation(ResourceKind::Texture, bytes);
+        graphics::register_object(ObjectKind::Texture, texture);
+
         Texture {
             texture: texture,
             format: format,
             width: width,
             height: height,
+            bytes,
+            generation: graphics::context_generation(),
         }
     }
 
-    /// Creates a texture from a image file.
+    /// Creates a texture from a image file. PNG, TGA and BMP files are supported, and other
+    /// formats can be added with [`image_decoder::register_decoder`].
+    ///
+    /// [`image_decoder::register_decoder`]: ../image_decoder/fn.register_decoder.html
     pub fn from_file<P>(path: P) -> Result<Texture, TextureError> where P: AsRef<Path> {
         let mut texture = Texture::new();
         texture.load_file(path)?;
         Ok(texture)
     }
 
+    /// Loads a animated GIF or APNG file, with one texture per frame. Use a [`AnimationPlayer`]
+    /// to pick the frame to show. Other image files are loaded as a single frame.
+    ///
+    /// [`AnimationPlayer`]: ../animation/struct.AnimationPlayer.html
+    pub fn load_animation<P: AsRef<Path>>(path: P) -> Result<Animation, TextureError> {
+        let data = RawAnimationData::from_file(path)?;
+        Ok(Animation::from_raw(data))
+    }
+
     /// Creates a texturer from the bytes in a image file. The bytes can be sourced with the
     /// `include_bytes!` macro. `source` is only used for context in error messages.
     pub fn from_bytes(bytes: &[u8], source: &str) -> Result<Texture, TextureError> {
@@ -64,15 +92,21 @@ impl Texture {
         unsafe {
             gl::GenTextures(1, &mut texture);
             gl::BindTexture(gl::TEXTURE_2D, texture);
+            material::note_textures_unknown();
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
         }
 
+        graphics::note_allocation(ResourceKind::Texture, 0);
+        graphics::register_object(ObjectKind::Texture, texture);
+
         Texture {
             texture: texture,
             format: TextureFormat::RGB_8,
             width: 0,
             height: 0,
+            bytes: 0,
+            generation: graphics::context_generation(),
         }
     }
 
@@ -88,27 +122,8 @@ impl Texture {
     /// texture.load_file("assets/test.png").expect("Failed to load texture");
     /// ```
     pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), TextureError> {
-        let path = path.as_ref();
-        let RawImageData { info, buf } = RawImageData::from_file(path)?;
-        let texture_format = match (info.color_type, info.bit_depth) {
-            (png::ColorType::RGBA, png::BitDepth::Eight) => TextureFormat::RGBA_8,
-            (png::ColorType::RGB, png::BitDepth::Eight)  => TextureFormat::RGB_8,
-            other => {
-                let message = format!(
-                    "Unsuported texture format ({:?}, {:?}) in \"{}\" ({}:{})",
-                    other.0, other.1,
-                    path.to_string_lossy(),
-                    file!(), line!()
-                );
-
-                return Err(TextureError { 
-                    source: Some(path.to_string_lossy().into()),
-                    error: io::Error::new(io::ErrorKind::Other, message) 
-                });
-            }
-        };
-        self.load_data(&buf, info.width, info.height, texture_format);
-        Ok(())
+        let data = RawImageData::from_file(path)?;
+        self.load_raw_image_data(data)
     }
 
     /// Attempts to load the given raw image data into this texture. For more info see
@@ -116,27 +131,27 @@ impl Texture {
     ///
     /// [`RawImageData`]: struct.RawImageData.html
     pub fn load_raw_image_data(&mut self, data: RawImageData) -> Result<(), TextureError> {
-        let texture_format = match (data.info.color_type, data.info.bit_depth) {
-            (png::ColorType::RGBA, png::BitDepth::Eight) => TextureFormat::RGBA_8,
-            (png::ColorType::RGB, png::BitDepth::Eight)  => TextureFormat::RGB_8,
-            other => {
-                let message = format!(
-                    "Unsuported texture format ({:?}, {:?}) ({}:{})",
-                    other.0, other.1, file!(), line!()
-                );
-                return Err(TextureError { source: None, error: io::Error::new(io::ErrorKind::Other, message) });
-            }
-        };
-        self.load_data(&data.buf, data.info.width, data.info.height, texture_format);
+        let texture_format = data.format;
+        self.load_data(&data.buf, data.width, data.height, texture_format);
+        if let Some(swizzle) = texture_format.grayscale_swizzle() {
+            self.set_swizzle_mask(swizzle);
+        }
         Ok(())
     }
 
     /// Directly loads some color data into a texture. This function does not check to ensure that
     /// the data is in the correct format, so you have to manually ensure that it is valid. This
     /// function is intended for creating small debug textures.
+    ///
+    /// Rows are expected to be tightly packed, so single- and two-channel formats can have any
+    /// width. Use [`load_float_data`] for floating point formats.
+    ///
+    /// [`load_float_data`]: #method.load_float_data
     pub fn load_data(&mut self, data: &[u8], width: u32, height: u32, format: TextureFormat) {
         unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            material::note_textures_unknown();
             gl::TexImage2D(gl::TEXTURE_2D, 0, // Mipmap level
                            format as GLint, // Internal format
                            width as GLsizei, height as GLsizei, 0, // Size and border
@@ -144,9 +159,35 @@ impl Texture {
                            gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid);
         }
 
-        self.width = width;
-        self.height = height;
-        self.format = format;
+        self.set_storage(width, height, format);
+    }
+
+    /// Loads floating point data into this texture, e.g. for heightmaps or lookup tables. `data`
+    /// should contain `format.components()` values per pixel. Data for half-float formats is
+    /// converted by OpenGL.
+    ///
+    /// # Panics
+    /// If `data` does not contain exactly one value per component for each pixel.
+    pub fn load_float_data(&mut self, data: &[f32], width: u32, height: u32, format: TextureFormat) {
+        let expected = (width * height) as usize * format.components();
+        assert!(
+            data.len() == expected,
+            "Expected {} values for a {}x{} texture with format {:?}, but got {}",
+            expected, width, height, format, data.len()
+        );
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            material::note_textures_unknown();
+            gl::TexImage2D(gl::TEXTURE_2D, 0, // Mipmap level
+                           format as GLint, // Internal format
+                           width as GLsizei, height as GLsizei, 0, // Size and border
+                           format.unsized_format(), // Data format
+                           gl::FLOAT, data.as_ptr() as *const GLvoid);
+        }
+
+        self.set_storage(width, height, format);
     }
 
     /// Sets the data in a sub-region of this texture. The data is expected to be in the
@@ -169,6 +210,7 @@ impl Texture {
             gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
 
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            material::note_textures_unknown();
             gl::TexSubImage2D(gl::TEXTURE_2D, 0,
                               x as GLint, y as GLint,
                               width as GLsizei, height as GLsizei,
@@ -182,6 +224,7 @@ impl Texture {
     pub fn initialize(&mut self, width: u32, height: u32, format: TextureFormat) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            material::note_textures_unknown();
             gl::TexImage2D(gl::TEXTURE_2D, 0, // Mipmap level
                            format as GLint, // Internal format
                            width as GLsizei, height as GLsizei, 0, // Size and border
@@ -189,9 +232,37 @@ impl Texture {
                            gl::UNSIGNED_BYTE, ptr::null());
         }
 
+        self.set_storage(width, height, format);
+    }
+
+    /// Sets a name for this texture, which is shown by `graphics::live_objects` and, if
+    /// `KHR_debug` is supported, in OpenGL debug messages and graphics debuggers.
+    pub fn set_debug_label(&mut self, name: &str) {
+        graphics::set_object_label(ObjectKind::Texture, self.texture, name);
+    }
+
+    // Updates the size and format after the storage of this texture has been reallocated
+    fn set_storage(&mut self, width: u32, height: u32, format: TextureFormat) {
+        let bytes = format.storage_size(width, height);
+        graphics::note_reallocation(ResourceKind::Texture, self.bytes, bytes);
+
         self.width = width;
         self.height = height;
         self.format = format;
+        self.bytes = bytes;
+    }
+
+    /// Converts a region of this texture given in pixels to a region in texture coordinates,
+    /// where the whole texture spans `0.0` to `1.0`. This is used to draw a single image from a
+    /// texture containing many images, e.g. with [`DrawGroup::textured_aabb_region`].
+    ///
+    /// [`DrawGroup::textured_aabb_region`]: ../draw_group/struct.DrawGroup.html#method.textured_aabb_region
+    pub fn uv_region(&self, pixels: Region) -> Region {
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        Region {
+            min: Vec2::new(pixels.min.x / size.x, pixels.min.y / size.y),
+            max: Vec2::new(pixels.max.x / size.x, pixels.max.y / size.y),
+        }
     }
 
     /// Binds this texture to the given texture unit.
@@ -200,6 +271,7 @@ impl Texture {
             gl::ActiveTexture(gl::TEXTURE0 + unit);
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
         }
+        material::note_texture(unit, self.texture);
     }
 
     /// Unbinds the texture at the given texture unit.
@@ -208,6 +280,7 @@ impl Texture {
             gl::ActiveTexture(gl::TEXTURE0 + unit);
             gl::BindTexture(gl::TEXTURE_2D, 0);
         }
+        material::note_texture(unit, 0);
     }
 
     /// Sets the filter that is applied when this texture is rendered at a size larger
@@ -249,13 +322,220 @@ impl Texture {
 
 impl Drop for Texture {
     fn drop(&mut self) {
+        if graphics::is_current_context(self.generation) {
+            unsafe {
+                gl::DeleteTextures(1, &self.texture);
+            }
+            material::note_textures_unknown();
+            graphics::unregister_object(ObjectKind::Texture, self.texture);
+        }
+        graphics::note_free(ResourceKind::Texture, self.bytes);
+    }
+}
+
+/// A OpenGL sampler object. Samplers store filtering and wrapping state separately from
+/// textures. When a sampler is bound to a texture unit, its state overrides the state of whichever
+/// texture is bound to that unit. This allows sampling the same texture in different ways, and
+/// sharing sampling settings between many textures.
+#[derive(Debug)]
+pub struct Sampler {
+    pub(crate) sampler: GLuint,
+}
+
+impl Sampler {
+    /// Creates a new sampler which uses nearest filtering and repeats textures, matching the
+    /// defaults of a newly created [`Texture`].
+    ///
+    /// [`Texture`]: struct.Texture.html
+    pub fn new() -> Sampler {
+        let mut sampler = 0;
+        unsafe { gl::GenSamplers(1, &mut sampler) };
+
+        let mut sampler = Sampler { sampler };
+        sampler.set_filter(TextureFilter::Nearest, TextureFilter::Nearest);
+        sampler
+    }
+
+    /// Sets the filters used when textures are rendered at sizes larger or smaller than their
+    /// native size. See [`Texture::set_filter`].
+    ///
+    /// [`Texture::set_filter`]: struct.Texture.html#method.set_filter
+    pub fn set_filter(&mut self, mag: TextureFilter, min: TextureFilter) {
+        unsafe {
+            gl::SamplerParameteri(self.sampler, gl::TEXTURE_MAG_FILTER, mag as GLint);
+            gl::SamplerParameteri(self.sampler, gl::TEXTURE_MIN_FILTER, min as GLint);
+        }
+    }
+
+    /// Sets the filters used when textures are rendered at sizes larger or smaller than their
+    /// native size, and the filter used to choose between mipmap levels when minifying.
+    pub fn set_mipmap_filter(&mut self, mag: TextureFilter, min: TextureFilter, mipmap: TextureFilter) {
+        unsafe {
+            gl::SamplerParameteri(self.sampler, gl::TEXTURE_MAG_FILTER, mag as GLint);
+            gl::SamplerParameteri(self.sampler, gl::TEXTURE_MIN_FILTER, TextureFilter::mipmap_filter(min, mipmap) as GLint);
+        }
+    }
+
+    /// Sets how texture coordinates outside of the `0.0` to `1.0` range are handled, separately
+    /// for the horizontal (`s`) and vertical (`t`) axis.
+    pub fn set_wrap(&mut self, s: TextureWrap, t: TextureWrap) {
+        unsafe {
+            gl::SamplerParameteri(self.sampler, gl::TEXTURE_WRAP_S, s as GLint);
+            gl::SamplerParameteri(self.sampler, gl::TEXTURE_WRAP_T, t as GLint);
+        }
+    }
+
+    /// Sets the color used for texture coordinates outside of the texture when using
+    /// `TextureWrap::ClampToBorder`.
+    pub fn set_border_color(&mut self, color: Color) {
+        let color = [color.r, color.g, color.b, color.a];
         unsafe {
-            gl::DeleteTextures(1, &self.texture);
+            gl::SamplerParameterfv(self.sampler, gl::TEXTURE_BORDER_COLOR, color.as_ptr());
+        }
+    }
+
+    /// Sets the maximum degree of anisotropic filtering, which improves the quality of textures
+    /// viewed at steep angles. `1.0` disables anisotropic filtering. The value is clamped to the
+    /// maximum supported by the OpenGL implementation.
+    ///
+    /// Anisotropic filtering is not part of OpenGL 3.3. If `GL_EXT_texture_filter_anisotropic`
+    /// is not supported this does nothing and returns `false`.
+    pub fn set_anisotropy(&mut self, anisotropy: f32) -> bool {
+        if !graphics::capabilities().has_extension("GL_EXT_texture_filter_anisotropic") {
+            return false;
         }
+
+        unsafe {
+            let mut max = 1.0;
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+
+            let anisotropy = anisotropy.max(1.0).min(max);
+            gl::SamplerParameterf(self.sampler, TEXTURE_MAX_ANISOTROPY, anisotropy);
+        }
+
+        true
+    }
+
+    /// Binds this sampler to the given texture unit. The sampler is used for whichever texture
+    /// is bound to that unit until [`Sampler::unbind`] is called.
+    ///
+    /// [`Sampler::unbind`]: struct.Sampler.html#method.unbind
+    pub fn bind(&self, unit: u32) {
+        unsafe { gl::BindSampler(unit, self.sampler) };
+        material::note_sampler(unit, self.sampler);
+    }
+
+    /// Unbinds the sampler at the given texture unit, so that the state of the bound texture is
+    /// used again.
+    pub fn unbind(unit: u32) {
+        unsafe { gl::BindSampler(unit, 0) };
+        material::note_sampler(unit, 0);
     }
 }
 
-/// Raw image data loaded from a png file. This data can then be loaded into a texture 
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteSamplers(1, &self.sampler);
+        }
+        material::note_textures_unknown();
+    }
+}
+
+// From `GL_EXT_texture_filter_anisotropic`, which the `gl` crate does not generate bindings for
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84fe;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84ff;
+
+/// A set of textures which are used together by a shader, e.g. the diffuse, normal and specular
+/// maps of a material. Binding the set binds each texture to its own texture unit, together with
+/// its sampler if it has one, and sets the matching sampler uniforms in the shader.
+///
+/// # Example
+/// ```rust,no_run
+/// # use gondola::texture::*;
+/// # use gondola::shader::Shader;
+/// # fn draw(shader: &Shader, diffuse: &Texture, normal: &Texture, sampler: &Sampler) {
+/// let mut material = TextureSet::new();
+/// material.add("diffuse", diffuse);
+/// material.add_with_sampler("normal_map", normal, sampler);
+///
+/// material.bind(shader);
+/// // Draw stuff ...
+/// material.unbind();
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TextureSet<'a> {
+    entries: Vec<(&'a str, &'a Texture, Option<&'a Sampler>)>,
+}
+
+impl<'a> TextureSet<'a> {
+    pub fn new() -> TextureSet<'a> {
+        TextureSet { entries: Vec::new() }
+    }
+
+    /// Adds a texture which will be bound to the sampler uniform with the given name. The
+    /// texture is sampled using its own filter settings.
+    pub fn add(&mut self, uniform_name: &'a str, texture: &'a Texture) {
+        self.entries.push((uniform_name, texture, None));
+    }
+
+    /// Adds a texture which will be bound to the sampler uniform with the given name, and
+    /// sampled using the given sampler.
+    pub fn add_with_sampler(&mut self, uniform_name: &'a str, texture: &'a Texture, sampler: &'a Sampler) {
+        self.entries.push((uniform_name, texture, Some(sampler)));
+    }
+
+    /// The number of textures in this set.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Binds the textures in this set to texture units `0` through `len() - 1`, in the order
+    /// they were added, and sets the sampler uniforms in the given shader to the matching units.
+    /// This binds the given shader.
+    ///
+    /// # Panics
+    /// If the set contains more textures than there are texture units.
+    pub fn bind(&self, shader: &Shader) {
+        let max_units = graphics::capabilities().max_texture_units as usize;
+        assert!(
+            self.entries.len() <= max_units,
+            "TextureSet contains {} textures, but only {} texture units are available",
+            self.entries.len(), max_units,
+        );
+
+        shader.bind();
+
+        for (unit, &(uniform_name, texture, sampler)) in self.entries.iter().enumerate() {
+            let unit = unit as u32;
+
+            texture.bind(unit);
+            match sampler {
+                Some(sampler) => sampler.bind(unit),
+                None => Sampler::unbind(unit),
+            }
+
+            shader.set_uniform(uniform_name, unit as i32);
+        }
+    }
+
+    /// Unbinds all textures and samplers bound by [`bind`].
+    ///
+    /// [`bind`]: struct.TextureSet.html#method.bind
+    pub fn unbind(&self) {
+        for unit in 0..(self.entries.len() as u32) {
+            Texture::unbind(unit);
+            Sampler::unbind(unit);
+        }
+    }
+}
+
+/// Raw image data loaded from a image file. This data can then be loaded into a texture 
 /// using [`Texture::load_raw_image_data`]. When loading very large textures it can be
 /// beneficial to load the raw image data from the texture on a separate thread, and then
 /// pass it to a texture in the main thread for performance reasons.
@@ -267,61 +547,78 @@ impl Drop for Texture {
 /// [`Texture::load_raw_image_data`]: struct.Texture.html#method.load_raw_image_data
 /// [`RawImageData::from_file`]: struct.RawImageData.html#method.from_file
 pub struct RawImageData {
-    info: png::OutputInfo,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
     buf: Vec<u8>,
 }
 
 impl RawImageData {
+    /// Wraps already decoded pixel data, e.g. in a custom [`ImageDecoder`]. Rows are tightly
+    /// packed and stored from top to bottom.
+    ///
+    /// # Panics
+    /// If `format` does not use a single byte per component, or if `buf` does not contain exactly
+    /// `width * height` pixels.
+    ///
+    /// [`ImageDecoder`]: ../image_decoder/trait.ImageDecoder.html
+    pub fn new(width: u32, height: u32, format: TextureFormat, buf: Vec<u8>) -> RawImageData {
+        assert!(
+            format.bytes_per_pixel() == format.components(),
+            "{:?} does not use a single byte per component", format
+        );
+        let expected = format.storage_size(width, height);
+        assert!(
+            buf.len() == expected,
+            "Expected {} bytes for a {}x{} image with format {:?}, but got {}",
+            expected, width, height, format, buf.len()
+        );
+
+        RawImageData { width, height, format, buf }
+    }
+
     /// Does not invoke any OpenGL functions, and can thus be called from any thread.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RawImageData, TextureError> {
         let path = path.as_ref();
+        let source = path.to_string_lossy();
 
-        // Open file
-        let file = match File::open(path) {
-            Ok(file) => file,
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
             Err(err) => return Err(TextureError { 
-                source: Some(path.to_string_lossy().into()),
-                error: err 
+                source: Some(source.into()),
+                kind: TextureErrorKind::Io(err),
             }),
         };
 
-        let decoder = png::Decoder::new(file);
-
-        RawImageData::from_decoder(decoder, path.to_string_lossy().into())
+        let extension = path.extension().and_then(|e| e.to_str());
+        image_decoder::decode(&bytes, extension).map_err(|kind| TextureError {
+            source: Some(source.into()),
+            kind,
+        })
     }
 
-    /// Can be used in conjunction with the `include_bytes!(..)` in std.
+    /// Can be used in conjunction with the `include_bytes!(..)` in std. If `source` is a file
+    /// name, its extension is used to help detect the image format.
     pub fn from_bytes(bytes: &[u8], source: &str) -> Result<RawImageData, TextureError> {
-        RawImageData::from_decoder(png::Decoder::new(bytes), source.into())
+        let extension = Path::new(source).extension().and_then(|e| e.to_str());
+        image_decoder::decode(bytes, extension).map_err(|kind| TextureError {
+            source: Some(source.into()),
+            kind,
+        })
     }
 
-    fn from_decoder<R: io::Read>(
-        decoder: png::Decoder<R>,
-        source: Cow<str>,
-    ) -> Result<RawImageData, TextureError> 
-    {
-        let (info, mut reader) = match decoder.read_info() {
-            Ok(result) => result,
-            Err(err) => return Err(TextureError { 
-                source: Some(source.into()),
-                error: err.into() 
-            }),
-        };
+    /// The width and height of the image, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
 
-        // Read data into buffer (This is what makes texture loading slow)
-        let mut buf = vec![0; info.buffer_size()];
-        match reader.next_frame(&mut buf) {
-            Ok(()) => {},
-            Err(err) => return Err(TextureError {
-                source: Some(source.into()),
-                error: err.into() 
-            }),
-        };
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
 
-        Ok(RawImageData {
-            info: info,
-            buf: buf,
-        })
+    /// The pixel data, row by row from the top.
+    pub fn data(&self) -> &[u8] {
+        &self.buf
     }
 }
 
@@ -352,18 +649,24 @@ impl TextureFilter {
 /// Represents a OpenGL texture format.
 #[repr(u32)] // GLenum is u32
 #[allow(non_camel_case_types, dead_code)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TextureFormat {
     RGBA_F32 = gl::RGBA32F,
     RGBA_F16 = gl::RGBA16F,
     RGB_F32  = gl::RGB32F,
     RGB_F16  = gl::RGB16F,
+    RG_F32   = gl::RG32F,
+    RG_F16   = gl::RG16F,
     R_F32    = gl::R32F,
     R_F16    = gl::R16F,
 
     RGBA_8   = gl::RGBA8,
     RGB_8    = gl::RGB8,
+    RG_8     = gl::RG8,
     R_8      = gl::R8,
+
+    /// Unsigned integer format. Can not be sampled with a `sampler2D`, use a `usampler2D` instead.
+    R_U32    = gl::R32UI,
 }
 impl TextureFormat {
     /// Retrieves the unsized version of the given format
@@ -371,25 +674,29 @@ impl TextureFormat {
         match *self {
             TextureFormat::RGBA_F32 | TextureFormat::RGBA_F16 | TextureFormat::RGBA_8 => gl::RGBA,
             TextureFormat::RGB_F32 | TextureFormat::RGB_F16 | TextureFormat::RGB_8 => gl::RGB,
+            TextureFormat::RG_F32 | TextureFormat::RG_F16 | TextureFormat::RG_8 => gl::RG,
             TextureFormat::R_F32 | TextureFormat::R_F16 | TextureFormat::R_8 => gl::RED,
+            TextureFormat::R_U32 => gl::RED_INTEGER,
         }
     }
 
     /// The OpenGL primitive associated with this color format.
     pub fn gl_primitive_enum(&self) -> GLenum {
         match *self {
-            TextureFormat::RGBA_F32 | TextureFormat::RGB_F32 | TextureFormat::R_F32 => gl::FLOAT,
-            TextureFormat::RGBA_F16 | TextureFormat::RGB_F16 | TextureFormat::R_F16 => gl::FLOAT,
-            TextureFormat::RGBA_8 | TextureFormat::RGB_8 | TextureFormat::R_8 => gl::UNSIGNED_BYTE,
+            TextureFormat::RGBA_F32 | TextureFormat::RGB_F32 | TextureFormat::RG_F32 | TextureFormat::R_F32 => gl::FLOAT,
+            TextureFormat::RGBA_F16 | TextureFormat::RGB_F16 | TextureFormat::RG_F16 | TextureFormat::R_F16 => gl::FLOAT,
+            TextureFormat::RGBA_8 | TextureFormat::RGB_8 | TextureFormat::RG_8 | TextureFormat::R_8 => gl::UNSIGNED_BYTE,
+            TextureFormat::R_U32 => gl::UNSIGNED_INT,
         }
     }
 
     /// The name of the OpenGL primitive associated with this color format.
     pub fn gl_primitive_enum_name(&self) -> &'static str {
         match *self {
-            TextureFormat::RGBA_F32 | TextureFormat::RGB_F32 | TextureFormat::R_F32 => "GLfloat",
-            TextureFormat::RGBA_F16 | TextureFormat::RGB_F16 | TextureFormat::R_F16 => "GLfloat",
-            TextureFormat::RGBA_8 | TextureFormat::RGB_8 | TextureFormat::R_8 => "GLbyte",
+            TextureFormat::RGBA_F32 | TextureFormat::RGB_F32 | TextureFormat::RG_F32 | TextureFormat::R_F32 => "GLfloat",
+            TextureFormat::RGBA_F16 | TextureFormat::RGB_F16 | TextureFormat::RG_F16 | TextureFormat::R_F16 => "GLfloat",
+            TextureFormat::RGBA_8 | TextureFormat::RGB_8 | TextureFormat::RG_8 | TextureFormat::R_8 => "GLbyte",
+            TextureFormat::R_U32 => "GLuint",
         }
     }
 
@@ -398,11 +705,72 @@ impl TextureFormat {
         match *self {
             TextureFormat::RGBA_F32 | TextureFormat::RGBA_F16 | TextureFormat::RGBA_8 => 4,
             TextureFormat::RGB_F32 | TextureFormat::RGB_F16 | TextureFormat::RGB_8 => 3,
-            TextureFormat::R_F32 | TextureFormat::R_F16 | TextureFormat::R_8 => 1,
+            TextureFormat::RG_F32 | TextureFormat::RG_F16 | TextureFormat::RG_8 => 2,
+            TextureFormat::R_F32 | TextureFormat::R_F16 | TextureFormat::R_8 | TextureFormat::R_U32 => 1,
+        }
+    }
+
+    /// The number of bytes used to store a single pixel in this format. Drivers may pad some
+    /// formats, e.g. storing `RGB_8` with four bytes per pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        let component_size = match *self {
+            TextureFormat::RGBA_F32 | TextureFormat::RGB_F32 | TextureFormat::RG_F32 | TextureFormat::R_F32 => 4,
+            TextureFormat::RGBA_F16 | TextureFormat::RGB_F16 | TextureFormat::RG_F16 | TextureFormat::R_F16 => 2,
+            TextureFormat::RGBA_8 | TextureFormat::RGB_8 | TextureFormat::RG_8 | TextureFormat::R_8 => 1,
+            TextureFormat::R_U32 => 4,
+        };
+        component_size * self.components()
+    }
+
+    // The approximate size of a single image with this format, in bytes
+    pub(crate) fn storage_size(&self, width: u32, height: u32) -> usize {
+        width as usize * height as usize * self.bytes_per_pixel()
+    }
+
+    /// Whether this format stores floating point data. Data for these formats should be
+    /// uploaded with [`Texture::load_float_data`].
+    ///
+    /// [`Texture::load_float_data`]: struct.Texture.html#method.load_float_data
+    pub fn is_float(&self) -> bool {
+        self.gl_primitive_enum() == gl::FLOAT
+    }
+
+    /// A swizzle mask which makes textures with fewer than three components appear as grayscale
+    /// images, rather than as red or red-green images. For `R_*` formats shaders see `(r, r, r,
+    /// 1.0)`, and for `RG_*` formats shaders see `(r, r, r, g)`, treating the second component
+    /// as alpha. Returns `None` for other formats.
+    pub fn grayscale_swizzle(&self) -> Option<(SwizzleComp, SwizzleComp, SwizzleComp, SwizzleComp)> {
+        use self::SwizzleComp::*;
+        match self.components() {
+            1 => Some((Red, Red, Red, One)),
+            2 => Some((Red, Red, Red, Green)),
+            _ => None,
+        }
+    }
+
+    // The format used to store png images with the given color type
+    pub(crate) fn from_png(color_type: png::ColorType, bit_depth: png::BitDepth) -> Option<TextureFormat> {
+        match (color_type, bit_depth) {
+            (png::ColorType::RGBA, png::BitDepth::Eight)           => Some(TextureFormat::RGBA_8),
+            (png::ColorType::RGB, png::BitDepth::Eight)            => Some(TextureFormat::RGB_8),
+            (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight) => Some(TextureFormat::RG_8),
+            (png::ColorType::Grayscale, png::BitDepth::Eight)      => Some(TextureFormat::R_8),
+            _ => None,
         }
     }
 }
 
+/// Decides how texture coordinates outside of the `0.0` to `1.0` range are handled. See
+/// [`Sampler::set_wrap`](struct.Sampler.html#method.set_wrap).
+#[repr(u32)] // GLenum is u32
+#[derive(Debug, Copy, Clone)]
+pub enum TextureWrap {
+    Repeat          = gl::REPEAT,
+    MirroredRepeat  = gl::MIRRORED_REPEAT,
+    ClampToEdge     = gl::CLAMP_TO_EDGE,
+    ClampToBorder   = gl::CLAMP_TO_BORDER,
+}
+
 /// Components that a texture can be mapped to through swizzling. See
 /// [`set_swizzle_mask`](struct.Texture.html#method.set_swizzle_mask)
 /// for more info.
@@ -421,27 +789,87 @@ pub enum SwizzleComp {
 #[derive(Debug)]
 pub struct TextureError {
     source: Option<String>,
-    error: io::Error,
+    kind: TextureErrorKind,
+}
+
+/// The different reasons for which loading a texture can fail. See [`TextureError::kind`].
+///
+/// [`TextureError::kind`]: struct.TextureError.html#method.kind
+#[derive(Debug)]
+pub enum TextureErrorKind {
+    /// The image could not be read, e.g. because the file does not exist.
+    Io(io::Error),
+    /// The image was read, but is not a valid image file. Contains a description of the problem.
+    Decoding(String),
+    /// The image is a valid png file, but its color type and bit depth (given in the contained
+    /// string) can not be used as a texture.
+    UnsupportedFormat(String),
+    /// The image is not in any format known by the built in or registered decoders. See
+    /// [`image_decoder`](../image_decoder/index.html).
+    UnknownFormat,
+}
+
+impl TextureErrorKind {
+    pub(crate) fn from_decoding(err: png::DecodingError) -> TextureErrorKind {
+        match err {
+            png::DecodingError::IoError(err) => TextureErrorKind::Io(err),
+            other => TextureErrorKind::Decoding(format!("{}", other)),
+        }
+    }
+}
+
+impl TextureError {
+    pub(crate) fn new(source: Option<&str>, kind: TextureErrorKind) -> TextureError {
+        TextureError { source: source.map(|s| s.to_owned()), kind }
+    }
+
+    /// The file path or name of the texture which failed to load, if it is known.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_ref().map(|s| s.as_str())
+    }
+
+    /// Why loading the texture failed.
+    pub fn kind(&self) -> &TextureErrorKind {
+        &self.kind
+    }
 }
 
 impl error::Error for TextureError {
     fn description(&self) -> &str {
-        self.error.description()
+        match self.kind {
+            TextureErrorKind::Io(ref err) => err.description(),
+            TextureErrorKind::Decoding(_) => "Invalid image data",
+            TextureErrorKind::UnsupportedFormat(_) => "Unsupported texture format",
+            TextureErrorKind::UnknownFormat => "Unknown image format",
+        }
     }
 
     fn cause(&self) -> Option<&error::Error> {
-        self.error.cause()
+        match self.kind {
+            TextureErrorKind::Io(ref err) => Some(err),
+            _ => None,
+        }
     }
 }
 
 impl fmt::Display for TextureError {
-    fn fmt(&self, mut f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(ref source) = self.source {
             write!(f, "For texture \"{}\": ", source)?;
         }
 
-        self.error.fmt(&mut f)?;
-        Ok(())
+        match self.kind {
+            TextureErrorKind::Io(ref err) => write!(f, "{}", err),
+            TextureErrorKind::Decoding(ref message) => write!(f, "Invalid image data: {}", message),
+            TextureErrorKind::UnsupportedFormat(ref format) => write!(f, "Unsupported texture format ({})", format),
+            TextureErrorKind::UnknownFormat => write!(f, "Unknown image format"),
+        }
+    }
+}
+
+impl From<io::Error> for TextureError {
+    fn from(err: io::Error) -> TextureError {
+        TextureError { source: None, kind: TextureErrorKind::Io(err) }
     }
 }
 
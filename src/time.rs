@@ -1,6 +1,7 @@
 
 use std::time::{Instant, Duration};
 use std::ops::{Add, Sub, AddAssign, SubAssign};
+use std::{thread, hint};
 
 /// Utility to track time in a program
 #[derive(Clone)]
@@ -38,6 +39,320 @@ impl Timer {
     }
 }
 
+/// Limits the frame rate of a program. `thread::sleep` alone is to imprecise for this, as it
+/// usually oversleeps by up to a few milliseconds. The pacer instead sleeps until shortly before
+/// the end of the frame, and then spins for the remaining time.
+///
+/// Call [`wait`] once per frame, just before swapping buffers. Alternatively,
+/// `WindowCommon::swap_buffers_paced` does both, and also measures how long the swap takes.
+///
+/// If vsync is enabled, the driver already blocks in `swap_buffers`. In that case, call
+/// [`set_vsync(true)`] to skip spinning, so the pacer only adds sleeps for targets below the
+/// refresh rate. The return value of `WindowCommon::set_vsync_mode` tells whether vsync is in
+/// effect.
+///
+/// [`wait`]: #method.wait
+/// [`set_vsync(true)`]: #method.set_vsync
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    target: Option<Time>,
+    spin_margin: Time,
+    vsync: bool,
+
+    frame_start: Instant,
+    frame_time: Time,
+    swap_time: Time,
+}
+
+impl FramePacer {
+    /// Creates a new pacer which limits the frame rate to the given number of frames per second.
+    pub fn new(target_fps: f32) -> FramePacer {
+        let mut pacer = FramePacer::unlimited();
+        pacer.set_target_fps(Some(target_fps));
+        pacer
+    }
+
+    /// Creates a new pacer which does not limit the frame rate, but still measures frame times.
+    pub fn unlimited() -> FramePacer {
+        FramePacer {
+            target: None,
+            spin_margin: Time::from_ms(2),
+            vsync: false,
+
+            frame_start: Instant::now(),
+            frame_time: Time::ZERO,
+            swap_time: Time::ZERO,
+        }
+    }
+
+    /// Changes the frame rate limit. `None` disables the limit.
+    pub fn set_target_fps(&mut self, target_fps: Option<f32>) {
+        self.target = target_fps
+            .filter(|&fps| fps > 0.0)
+            .map(|fps| Time::from_secs_f32(1.0 / fps));
+    }
+
+    /// The current frame rate limit, or `None` if the frame rate is not limited.
+    pub fn target_fps(&self) -> Option<f32> {
+        self.target.map(|target| 1.0 / target.to_secs_f32())
+    }
+
+    /// Sets how long before the end of a frame the pacer stops sleeping and starts spinning. A
+    /// larger margin uses more cpu time, but is more robust against imprecise sleeps. Defaults to
+    /// 2 ms.
+    pub fn set_spin_margin(&mut self, margin: Time) {
+        self.spin_margin = margin;
+    }
+
+    /// Tells the pacer whether vsync is enabled. See the [struct level documentation] for more
+    /// info.
+    ///
+    /// [struct level documentation]: struct.FramePacer.html
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.vsync = vsync;
+    }
+
+    /// Blocks until the current frame has taken as long as the target frame time. Returns the
+    /// duration of the frame, from the end of the previous call to `wait` to the end of this call.
+    pub fn wait(&mut self) -> Time {
+        if let Some(target) = self.target {
+            let deadline = self.frame_start + Duration::from(target);
+            let now = Instant::now();
+
+            if now < deadline {
+                let remaining = Time::from(deadline - now);
+
+                if remaining > self.spin_margin {
+                    thread::sleep((remaining - self.spin_margin).into());
+                }
+
+                // With vsync the swap itself takes care of the last bit of waiting
+                if !self.vsync {
+                    while Instant::now() < deadline {
+                        hint::spin_loop();
+                    }
+                }
+            }
+        }
+
+        let now = Instant::now();
+        self.frame_time = (now - self.frame_start).into();
+        self.frame_start = now;
+        self.frame_time
+    }
+
+    /// Records how long the last buffer swap took. Used by `WindowCommon::swap_buffers_paced`.
+    pub fn record_swap_time(&mut self, swap_time: Time) {
+        self.swap_time = swap_time;
+    }
+
+    /// The duration of the last frame, as returned by `wait`.
+    pub fn frame_time(&self) -> Time {
+        self.frame_time
+    }
+
+    /// How long the last buffer swap took, if it was recorded. A long swap time relative to the
+    /// frame time indicates that the driver is blocking for vsync.
+    pub fn swap_time(&self) -> Time {
+        self.swap_time
+    }
+}
+
+/// A time line which can be slowed down, sped up or paused, e.g. for slow-motion effects or
+/// pause menus. A clock does not measure time itself, but is advanced by a delta from a parent.
+/// The delta of the root clock should come from a [`Timer`]. Clocks can be chained to build
+/// hierarchies, where scaling or pausing a clock affects all clocks below it.
+///
+/// ```rust,ignore
+/// let (_, real_delta) = timer.tick();
+///
+/// let delta = global.advance(real_delta);
+/// let gameplay_delta = gameplay.advance(delta); // Paused while the pause menu is open
+/// let ui_delta = ui.advance(delta);             // Keeps running in the pause menu
+/// ```
+///
+/// [`Timer`]: struct.Timer.html
+#[derive(Debug, Clone)]
+pub struct Clock {
+    scale: f32,
+    paused: bool,
+
+    time: Time,
+    delta: Time,
+    // Nanoseconds lost to rounding, carried over to the next delta so slow clocks do not drift
+    remainder: f64,
+}
+
+impl Clock {
+    /// Creates a new clock which runs at normal speed.
+    pub fn new() -> Clock {
+        Clock {
+            scale: 1.0,
+            paused: false,
+
+            time: Time::ZERO,
+            delta: Time::ZERO,
+            remainder: 0.0,
+        }
+    }
+
+    /// Advances the clock by `parent_delta` multiplied by its scale, and returns the scaled delta.
+    /// Returns `Time::ZERO` while the clock is paused.
+    pub fn advance(&mut self, parent_delta: Time) -> Time {
+        if self.paused {
+            self.delta = Time::ZERO;
+            return self.delta;
+        }
+
+        let nanos = parent_delta.0 as f64 * self.scale as f64 + self.remainder;
+        let whole = nanos.floor();
+        self.remainder = nanos - whole;
+
+        self.delta = Time(whole as u64);
+        self.time += self.delta;
+        self.delta
+    }
+
+    /// The total time this clock has advanced by.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// The delta returned by the last call to `advance`.
+    pub fn delta(&self) -> Time {
+        self.delta
+    }
+
+    /// Sets how fast the clock runs relative to its parent. `0.5` is half speed, `2.0` is double
+    /// speed. Negative values are treated as `0.0`.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// The scale which is actually applied, `0.0` while paused.
+    pub fn effective_scale(&self) -> f32 {
+        if self.paused { 0.0 } else { self.scale }
+    }
+
+    /// Stops the clock. Unlike setting the scale to zero, this keeps the scale, so it is restored
+    /// by [`resume`].
+    ///
+    /// [`resume`]: #method.resume
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Sets the time of the clock back to zero.
+    pub fn reset(&mut self) {
+        self.time = Time::ZERO;
+        self.delta = Time::ZERO;
+        self.remainder = 0.0;
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+/// Runs updates at a fixed rate, independent of the frame rate. Time is accumulated with
+/// [`accumulate`], usually with a delta from a [`Clock`], and then consumed in fixed steps by
+/// calling [`step`] until it returns false.
+///
+/// If updates take longer than the time they simulate, the accumulated time grows without bound.
+/// To avoid this, at most [`max_steps`] steps are taken per call to `accumulate`, and the rest
+/// of the accumulated time is dropped.
+///
+/// ```rust,ignore
+/// fixed.accumulate(gameplay.advance(delta));
+/// while fixed.step() {
+///     physics.update(fixed.step_size());
+/// }
+/// renderer.draw(&physics, fixed.alpha());
+/// ```
+///
+/// [`accumulate`]: #method.accumulate
+/// [`step`]: #method.step
+/// [`max_steps`]: #method.set_max_steps
+/// [`Clock`]: struct.Clock.html
+#[derive(Debug, Clone)]
+pub struct FixedStep {
+    step_size: Time,
+    max_steps: u32,
+    accumulator: Time,
+}
+
+impl FixedStep {
+    /// Creates a new accumulator which steps the given number of times per second.
+    pub fn new(steps_per_second: f32) -> FixedStep {
+        FixedStep::with_step_size(Time::from_secs_f32(1.0 / steps_per_second))
+    }
+
+    pub fn with_step_size(step_size: Time) -> FixedStep {
+        assert!(step_size > Time::ZERO, "Step size must be larger than zero");
+
+        FixedStep {
+            step_size,
+            max_steps: 8,
+            accumulator: Time::ZERO,
+        }
+    }
+
+    /// Adds time which should be simulated. Time beyond `max_steps` steps is dropped.
+    pub fn accumulate(&mut self, delta: Time) {
+        let limit = Time(self.step_size.0 * self.max_steps as u64);
+        self.accumulator = (self.accumulator + delta).min(limit);
+    }
+
+    /// Consumes one step, if enough time has been accumulated. Returns whether a step should be
+    /// taken.
+    pub fn step(&mut self) -> bool {
+        if self.accumulator >= self.step_size {
+            self.accumulator -= self.step_size;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn step_size(&self) -> Time {
+        self.step_size
+    }
+
+    /// Sets the maximum number of steps which can be accumulated. Defaults to 8.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps.max(1);
+    }
+
+    /// How far the accumulated time is into the next step, between `0` and `1`. Used to
+    /// interpolate between the last two simulated states when rendering.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.0 as f32 / self.step_size.0 as f32
+    }
+
+    /// Drops all accumulated time.
+    pub fn reset(&mut self) {
+        self.accumulator = Time::ZERO;
+    }
+}
+
 /// Time, stored as nanoseconds
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Time(pub u64); 
@@ -144,3 +459,56 @@ impl From<Time> for Duration {
         Duration::new(secs, nanos as u32)
     }
 }
+
+// Custom serialization
+#[cfg(feature = "serialize")]
+mod serialize {
+    use super::*;
+
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+
+    // Serialized as a number of nanoseconds
+    impl Serialize for Time {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize(s)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Time {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            u64::deserialize(d).map(Time)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_hierarchy() {
+        let mut global = Clock::new();
+        let mut gameplay = Clock::new();
+        let mut fixed = FixedStep::with_step_size(Time::from_ms(10));
+
+        global.set_scale(0.5);
+        let delta = global.advance(Time::from_ms(100));
+        assert_eq!(delta, Time::from_ms(50));
+
+        fixed.accumulate(gameplay.advance(delta));
+        let mut steps = 0;
+        while fixed.step() { steps += 1; }
+        assert_eq!(steps, 5);
+        assert_eq!(gameplay.time(), Time::from_ms(50));
+
+        gameplay.pause();
+        assert_eq!(gameplay.advance(global.advance(Time::from_ms(100))), Time::ZERO);
+        assert_eq!(global.time(), Time::from_ms(100));
+
+        // Accumulated time is limited to `max_steps` steps
+        fixed.accumulate(Time::from_secs(1));
+        let mut steps = 0;
+        while fixed.step() { steps += 1; }
+        assert_eq!(steps, 8);
+    }
+}
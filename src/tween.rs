@@ -0,0 +1,426 @@
+
+//! Easing functions and tweens, for animating values such as positions, colors and opacity over
+//! time, e.g. in user interfaces.
+//!
+//! ```rust,ignore
+//! use gondola::tween::{Tween, Easing, Animate};
+//!
+//! // Slide in, and then fade out after a second
+//! let mut slide = Tween::new(Vec2::new(-200.0, 20.0), Vec2::new(20.0, 20.0), Time::from_ms(300), Easing::CubicOut);
+//! let mut fade = Tween::hold(1.0, Time::from_secs(1))
+//!     .then(Tween::new(1.0, 0.0, Time::from_ms(500), Easing::QuadIn));
+//!
+//! // Each frame
+//! slide.advance(delta);
+//! fade.advance(delta);
+//! group.aabb(slide.value(), slide.value() + size, Color::WHITE.with_alpha(fade.value()));
+//! ```
+
+use std::f32::consts::PI;
+
+use cable_math::{Vec2, Vec3, Vec4};
+
+use Color;
+use Time;
+
+/// Values which can be linearly interpolated, and thus animated with a [`Tween`].
+///
+/// [`Tween`]: struct.Tween.html
+pub trait Lerp: Copy {
+    /// `t = 0` gives `a`, and `t = 1` gives `b`. Easing functions like `Easing::BackOut` give
+    /// values outside of `0..1`, so this should extrapolate.
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        a*(1.0 - t) + b*t
+    }
+}
+
+impl Lerp for Vec2<f32> {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        Vec2::lerp(a, b, t)
+    }
+}
+
+impl Lerp for Vec3<f32> {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        Vec3::lerp(a, b, t)
+    }
+}
+
+impl Lerp for Vec4<f32> {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        Vec4::lerp(a, b, t)
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(a: Color, b: Color, t: f32) -> Color {
+        Color::lerp(a, b, t)
+    }
+}
+
+/// Maps the linear progress of a tween to eased progress. All functions map `0` to `0` and `1` to
+/// `1`. `In` functions start slowly, `Out` functions end slowly and `InOut` functions do both.
+/// See <https://easings.net> for plots of most of these.
+#[derive(Debug, Copy, Clone)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    /// Moves slightly backwards before starting.
+    BackIn,
+    /// Overshoots the target slightly before settling.
+    BackOut,
+    BackInOut,
+    /// Overshoots the target and springs back and forth around it.
+    ElasticOut,
+    /// Bounces off the target like a dropped ball.
+    BounceOut,
+    Custom(fn(f32) -> f32),
+}
+
+impl Easing {
+    /// Eases the given progress, which is clamped to `0..1`.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+
+        // Constants for `Back`, see easings.net
+        const C1: f32 = 1.70158;
+        const C2: f32 = C1*1.525;
+        const C3: f32 = C1 + 1.0;
+
+        match self {
+            Easing::Linear => t,
+
+            Easing::QuadIn => t*t,
+            Easing::QuadOut => 1.0 - (1.0 - t)*(1.0 - t),
+            Easing::QuadInOut => if t < 0.5 {
+                2.0*t*t
+            } else {
+                1.0 - (2.0 - 2.0*t).powi(2)/2.0
+            },
+
+            Easing::CubicIn => t*t*t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => if t < 0.5 {
+                4.0*t*t*t
+            } else {
+                1.0 - (2.0 - 2.0*t).powi(3)/2.0
+            },
+
+            Easing::SineIn => 1.0 - (t*PI/2.0).cos(),
+            Easing::SineOut => (t*PI/2.0).sin(),
+            Easing::SineInOut => (1.0 - (t*PI).cos())/2.0,
+
+            Easing::ExpoIn => if t == 0.0 { 0.0 } else { 2f32.powf(10.0*t - 10.0) },
+            Easing::ExpoOut => if t == 1.0 { 1.0 } else { 1.0 - 2f32.powf(-10.0*t) },
+            Easing::ExpoInOut => if t == 0.0 || t == 1.0 {
+                t
+            } else if t < 0.5 {
+                2f32.powf(20.0*t - 10.0)/2.0
+            } else {
+                (2.0 - 2f32.powf(10.0 - 20.0*t))/2.0
+            },
+
+            Easing::BackIn => C3*t*t*t - C1*t*t,
+            Easing::BackOut => 1.0 + C3*(t - 1.0).powi(3) + C1*(t - 1.0).powi(2),
+            Easing::BackInOut => if t < 0.5 {
+                (2.0*t).powi(2)*((C2 + 1.0)*2.0*t - C2)/2.0
+            } else {
+                ((2.0*t - 2.0).powi(2)*((C2 + 1.0)*(2.0*t - 2.0) + C2) + 2.0)/2.0
+            },
+
+            Easing::ElasticOut => if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                2f32.powf(-10.0*t)*((10.0*t - 0.75)*(2.0*PI/3.0)).sin() + 1.0
+            },
+
+            Easing::BounceOut => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+
+                if t < 1.0/D {
+                    N*t*t
+                } else if t < 2.0/D {
+                    let t = t - 1.5/D;
+                    N*t*t + 0.75
+                } else if t < 2.5/D {
+                    let t = t - 2.25/D;
+                    N*t*t + 0.9375
+                } else {
+                    let t = t - 2.625/D;
+                    N*t*t + 0.984375
+                }
+            },
+
+            Easing::Custom(f) => f(t),
+        }
+    }
+}
+
+impl Default for Easing {
+    fn default() -> Easing {
+        Easing::Linear
+    }
+}
+
+/// Something which changes over time, such as a [`Tween`]. The combinators [`then`] and
+/// [`with`] build larger animations out of smaller ones.
+///
+/// [`Tween`]: struct.Tween.html
+/// [`then`]: #method.then
+/// [`with`]: #method.with
+pub trait Animate {
+    type Value;
+
+    /// Moves the animation forward by `delta`. Returns the part of `delta` which was left over
+    /// after the animation finished, which is `Time::ZERO` if it is still running.
+    fn advance(&mut self, delta: Time) -> Time;
+    /// The value at the current point of the animation.
+    fn value(&self) -> Self::Value;
+    fn finished(&self) -> bool;
+    /// Moves the animation back to its start.
+    fn reset(&mut self);
+
+    /// Plays `next` after this animation has finished.
+    fn then<B>(self, next: B) -> Sequence<Self, B>
+      where Self: Sized,
+            B: Animate<Value = Self::Value>,
+    {
+        Sequence { first: self, second: next }
+    }
+
+    /// Plays `other` at the same time as this animation. The value is a tuple of both values, and
+    /// the combined animation finishes once both have finished.
+    fn with<B>(self, other: B) -> Parallel<Self, B>
+      where Self: Sized,
+            B: Animate,
+    {
+        Parallel { a: self, b: other }
+    }
+}
+
+/// Interpolates from one value to another over a fixed duration, with a [`Easing`] function.
+///
+/// [`Easing`]: enum.Easing.html
+#[derive(Debug, Copy, Clone)]
+pub struct Tween<T> {
+    from: T,
+    to: T,
+    duration: Time,
+    elapsed: Time,
+    easing: Easing,
+}
+
+impl<T: Lerp> Tween<T> {
+    pub fn new(from: T, to: T, duration: Time, easing: Easing) -> Tween<T> {
+        Tween { from, to, duration, elapsed: Time::ZERO, easing }
+    }
+
+    /// A tween which stays at the given value for the given duration. This can be used to delay
+    /// a later part of a [`Sequence`].
+    ///
+    /// [`Sequence`]: struct.Sequence.html
+    pub fn hold(value: T, duration: Time) -> Tween<T> {
+        Tween::new(value, value, duration, Easing::Linear)
+    }
+
+    /// Restarts this tween from its current value towards the given value. This avoids jumps
+    /// when the target changes while the tween is still running, e.g. when the mouse leaves a
+    /// button before its hover animation has finished.
+    pub fn retarget(&mut self, to: T) {
+        self.from = self.value();
+        self.to = to;
+        self.elapsed = Time::ZERO;
+    }
+
+    /// The linear progress of this tween, between `0` and `1`, before easing is applied.
+    pub fn progress(&self) -> f32 {
+        if self.duration == Time::ZERO {
+            1.0
+        } else {
+            (self.elapsed.0 as f64 / self.duration.0 as f64) as f32
+        }
+    }
+
+    pub fn from(&self) -> T {
+        self.from
+    }
+
+    pub fn to(&self) -> T {
+        self.to
+    }
+
+    pub fn duration(&self) -> Time {
+        self.duration
+    }
+}
+
+impl<T: Lerp> Animate for Tween<T> {
+    type Value = T;
+
+    fn advance(&mut self, delta: Time) -> Time {
+        let remaining = self.duration - self.elapsed;
+        if delta >= remaining {
+            self.elapsed = self.duration;
+            delta - remaining
+        } else {
+            self.elapsed += delta;
+            Time::ZERO
+        }
+    }
+
+    fn value(&self) -> T {
+        T::lerp(self.from, self.to, self.easing.apply(self.progress()))
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = Time::ZERO;
+    }
+}
+
+/// Plays one animation after another, see [`Animate::then`]. Chain `then` to build longer
+/// sequences.
+///
+/// [`Animate::then`]: trait.Animate.html#method.then
+#[derive(Debug, Copy, Clone)]
+pub struct Sequence<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Animate for Sequence<A, B>
+  where A: Animate,
+        B: Animate<Value = A::Value>,
+{
+    type Value = A::Value;
+
+    fn advance(&mut self, delta: Time) -> Time {
+        if self.first.finished() {
+            self.second.advance(delta)
+        } else {
+            let left = self.first.advance(delta);
+            if self.first.finished() {
+                self.second.advance(left)
+            } else {
+                Time::ZERO
+            }
+        }
+    }
+
+    fn value(&self) -> A::Value {
+        if self.first.finished() {
+            self.second.value()
+        } else {
+            self.first.value()
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.first.finished() && self.second.finished()
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+}
+
+/// Plays two animations at the same time, see [`Animate::with`].
+///
+/// [`Animate::with`]: trait.Animate.html#method.with
+#[derive(Debug, Copy, Clone)]
+pub struct Parallel<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A: Animate, B: Animate> Animate for Parallel<A, B> {
+    type Value = (A::Value, B::Value);
+
+    fn advance(&mut self, delta: Time) -> Time {
+        let left_a = self.a.advance(delta);
+        let left_b = self.b.advance(delta);
+        left_a.min(left_b)
+    }
+
+    fn value(&self) -> Self::Value {
+        (self.a.value(), self.b.value())
+    }
+
+    fn finished(&self) -> bool {
+        self.a.finished() && self.b.finished()
+    }
+
+    fn reset(&mut self) {
+        self.a.reset();
+        self.b.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing_endpoints() {
+        let all = [
+            Easing::Linear, Easing::QuadIn, Easing::QuadOut, Easing::QuadInOut,
+            Easing::CubicIn, Easing::CubicOut, Easing::CubicInOut,
+            Easing::SineIn, Easing::SineOut, Easing::SineInOut,
+            Easing::ExpoIn, Easing::ExpoOut, Easing::ExpoInOut,
+            Easing::BackIn, Easing::BackOut, Easing::BackInOut,
+            Easing::ElasticOut, Easing::BounceOut,
+        ];
+        for &easing in all.iter() {
+            assert!(easing.apply(0.0).abs() < 1e-3, "{:?} does not start at 0", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-3, "{:?} does not end at 1", easing);
+        }
+
+        assert!(Easing::QuadIn.apply(0.5) < 0.5);
+        assert!(Easing::QuadOut.apply(0.5) > 0.5);
+        assert!((Easing::QuadInOut.apply(0.5) - 0.5).abs() < 1e-6);
+        assert!(Easing::BackOut.apply(0.7) > 1.0);
+    }
+
+    #[test]
+    fn sequences() {
+        let mut tween = Tween::new(0.0, 10.0, Time::from_ms(100), Easing::Linear);
+        tween.advance(Time::from_ms(50));
+        assert!((tween.value() - 5.0).abs() < 1e-4);
+        assert_eq!(tween.advance(Time::from_ms(80)), Time::from_ms(30));
+        assert!(tween.finished());
+
+        let mut sequence = Tween::new(0.0, 10.0, Time::from_ms(100), Easing::Linear)
+            .then(Tween::new(10.0, 0.0, Time::from_ms(100), Easing::Linear));
+        // Time left over from the first tween carries over into the second
+        sequence.advance(Time::from_ms(150));
+        assert!((sequence.value() - 5.0).abs() < 1e-4);
+        assert!(!sequence.finished());
+        sequence.reset();
+        assert_eq!(sequence.value(), 0.0);
+
+        let mut parallel = Tween::new(0.0, 1.0, Time::from_ms(100), Easing::Linear)
+            .with(Tween::new(Color::BLACK, Color::WHITE, Time::from_ms(200), Easing::Linear));
+        assert_eq!(parallel.advance(Time::from_ms(250)), Time::from_ms(50));
+        assert_eq!(parallel.value(), (1.0, Color::WHITE));
+    }
+}
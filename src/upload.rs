@@ -0,0 +1,152 @@
+
+//! Loading resources on a background thread, see [`Uploader`].
+//!
+//! [`Uploader`]: struct.Uploader.html
+
+use std::thread;
+use std::sync::mpsc;
+
+use graphics::{self, FenceSync, SyncStatus};
+use window::{SharedContext, WindowError};
+
+type Job<T> = Box<dyn FnOnce() -> T + Send>;
+
+/// Runs jobs on a background thread with a shared OpenGL context, e.g. to decode and upload
+/// textures without stalling the render thread. Results are returned by [`poll`] once the GPU has
+/// finished processing the commands issued by the job, so they can be used right away.
+///
+/// Dropping the uploader waits for the current job to finish.
+///
+/// ```rust,ignore
+/// let mut uploader = Uploader::new(window.create_shared_context()?)?;
+/// uploader.submit(|| Texture::from_file("assets/grass.png"));
+///
+/// // Each frame
+/// while let Some(result) = uploader.poll() {
+///     textures.push(result?);
+/// }
+/// ```
+///
+/// [`poll`]: #method.poll
+pub struct Uploader<T: Send + 'static> {
+    jobs: Option<mpsc::Sender<Job<T>>>,
+    results: mpsc::Receiver<(T, FenceSync)>,
+    waiting: Vec<(T, FenceSync)>,
+    in_flight: usize,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> Uploader<T> {
+    /// Starts the background thread, and makes `context` current on it. Fails if the context can
+    /// not be made current, or if the thread could not start.
+    pub fn new(context: SharedContext) -> Result<Uploader<T>, WindowError> {
+        let (job_sender, job_receiver) = mpsc::channel::<Job<T>>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let (status_sender, status_receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            if let Err(err) = context.make_current() {
+                let _ = status_sender.send(Err(err));
+                return;
+            }
+            let _ = status_sender.send(Ok(()));
+
+            for job in job_receiver.iter() {
+                let result = job();
+                let fence = FenceSync::new();
+                // The main thread never sees the fence signaled unless it is flushed
+                graphics::flush();
+                if result_sender.send((result, fence)).is_err() {
+                    break;
+                }
+            }
+
+            context.release();
+        });
+
+        match status_receiver.recv() {
+            Ok(Ok(())) => {},
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Err(WindowError::CreationFailed("Uploader thread exited during startup".to_owned())),
+        }
+
+        Ok(Uploader {
+            jobs: Some(job_sender),
+            results: result_receiver,
+            waiting: Vec::new(),
+            in_flight: 0,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queues a job to run on the background thread. Jobs run in the order they are submitted.
+    pub fn submit<F>(&mut self, job: F)
+      where F: FnOnce() -> T + Send + 'static,
+    {
+        if let Some(ref jobs) = self.jobs {
+            jobs.send(Box::new(job)).expect("Uploader thread has stopped");
+            self.in_flight += 1;
+        }
+    }
+
+    /// Returns the result of the oldest finished job, if its GPU commands have completed. Results
+    /// are returned in the order the jobs were submitted. Call this repeatedly until it returns
+    /// `None` to get all finished results.
+    pub fn poll(&mut self) -> Option<T> {
+        while let Ok(result) = self.results.try_recv() {
+            self.waiting.push(result);
+        }
+
+        let ready = match self.waiting.first() {
+            Some(&(_, ref fence)) => fence.is_signaled(),
+            None => false,
+        };
+
+        if ready {
+            self.in_flight -= 1;
+            Some(self.waiting.remove(0).0)
+        } else {
+            None
+        }
+    }
+
+    /// Blocks until all submitted jobs have finished, and returns their results. If waiting for
+    /// the GPU fails, e.g. because the context was lost, the results are returned along with
+    /// `SyncStatus::Failed`. They should not be used for drawing in that case.
+    pub fn finish(&mut self) -> (Vec<T>, SyncStatus) {
+        let mut status = SyncStatus::Signaled;
+        let mut finished = Vec::with_capacity(self.in_flight);
+
+        while self.waiting.len() < self.in_flight {
+            match self.results.recv() {
+                Ok(result) => self.waiting.push(result),
+                Err(_) => break,
+            }
+        }
+
+        for (result, fence) in self.waiting.drain(..) {
+            if fence.client_wait(None) == SyncStatus::Failed {
+                status = SyncStatus::Failed;
+            }
+            finished.push(result);
+        }
+        self.in_flight = 0;
+
+        (finished, status)
+    }
+
+    /// The number of submitted jobs whose results have not been returned yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
+
+impl<T: Send + 'static> Drop for Uploader<T> {
+    fn drop(&mut self) {
+        // Closing the channel makes the thread exit once the current job finishes
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
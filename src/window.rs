@@ -1,19 +1,51 @@
 
+use std::time::Instant;
+use std::error;
+use std::fmt;
+
 use cable_math::Vec2;
 
 use Region;
-use input::{KeyState, Input};
+use time::{Time, FramePacer};
+use input::{KeyState, Key, Input, Event, scancode_to_key};
 #[cfg(feature = "gamepad")]
-use input::{Gamepad, GamepadButton};
+use input::GamepadButton;
+use gl;
 use graphics;
+use framebuffer;
 
-// Since most of the lib is written expecting gl 3.3 we currently don't allow customizing this.
+/// The kind of OpenGL context a window should create. See [`WindowBuilder::gl`].
+///
+/// Note that most of this library is written expecting OpenGL 3.3, so requesting an older version
+/// or a compatibility profile is only useful if you do not use the rest of the library. The
+/// version which was actually created can be found through [`graphics::capabilities`], which
+/// should be used to check for features from newer versions.
+///
+/// [`graphics::capabilities`]: graphics/fn.capabilities.html
+/// [`WindowBuilder::gl`]: struct.WindowBuilder.html#method.gl
 #[derive(Debug, Copy, Clone)]
 pub struct GlRequest {
-    version: (u32, u32),
-    core: bool,
-    debug: bool,
-    forward_compatible: bool,
+    /// The major and minor version. Defaults to 3.3.
+    pub version: (u32, u32),
+    /// Whether to create a core or a compatibility profile context. Defaults to core. Ignored
+    /// when `gles` is set.
+    pub core: bool,
+    /// Whether to create a OpenGL ES context, e.g. with version 3.0. Defaults to `false`. This
+    /// requires `GLX_EXT_create_context_es2_profile` or `WGL_EXT_create_context_es2_profile`.
+    /// Note that the shaders used internally by `DrawGroup` and `ui` target desktop OpenGL, and
+    /// will not compile on OpenGL ES.
+    pub gles: bool,
+    /// Whether to create a debug context. When the context supports `KHR_debug`, debug messages
+    /// from the driver are printed. Defaults to `true` in debug builds.
+    pub debug: bool,
+    pub forward_compatible: bool,
+    /// Whether to ask the driver to report when the context is lost, e.g. because the graphics
+    /// driver was updated or crashed. See [`WindowCommon::context_lost`]. Defaults to `true`.
+    /// Ignored if `GLX_ARB_create_context_robustness` or `WGL_ARB_create_context_robustness` is
+    /// not supported.
+    ///
+    /// [`WindowCommon::context_lost`]: trait.WindowCommon.html#tymethod.context_lost
+    pub robust: bool,
 }
 
 impl Default for GlRequest {
@@ -21,8 +53,46 @@ impl Default for GlRequest {
         GlRequest {
             version: (3, 3),
             core: true,
+            gles: false,
             debug: cfg!(debug_assertions),
             forward_compatible: false,
+            robust: true,
+        }
+    }
+}
+
+/// Controls how buffer swaps are synchronized with the display. See
+/// [`WindowCommon::set_vsync_mode`].
+///
+/// [`WindowCommon::set_vsync_mode`]: trait.WindowCommon.html#tymethod.set_vsync_mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VsyncMode {
+    /// Buffers are swapped as soon as possible, which can cause tearing.
+    Off,
+    /// Buffer swaps wait for the next vertical blank. This corresponds to a swap interval of 1.
+    On,
+    /// Like `On`, but if a frame misses a vertical blank it is swapped immediately instead of
+    /// waiting for the next one. This avoids halving the frame rate when a frame takes slightly
+    /// too long, at the cost of occasional tearing. This corresponds to a swap interval of -1.
+    Adaptive,
+}
+
+impl VsyncMode {
+    pub fn swap_interval(self) -> i32 {
+        match self {
+            VsyncMode::Off      => 0,
+            VsyncMode::On       => 1,
+            VsyncMode::Adaptive => -1,
+        }
+    }
+
+    pub fn from_swap_interval(interval: i32) -> VsyncMode {
+        if interval == 0 {
+            VsyncMode::Off
+        } else if interval > 0 {
+            VsyncMode::On
+        } else {
+            VsyncMode::Adaptive
         }
     }
 }
@@ -33,15 +103,345 @@ pub enum CursorType {
     Normal,
     Clickable,
     Invisible,
+    /// The image last passed to `WindowCommon::set_cursor_image`. Looks like `Normal` until an
+    /// image has been set.
+    Custom,
 }
 
-const CURSOR_TYPE_COUNT: usize = 3;
+const CURSOR_TYPE_COUNT: usize = 4;
 const ALL_CURSOR_TYPES: [CursorType; CURSOR_TYPE_COUNT] = [
     CursorType::Normal,
     CursorType::Clickable,
     CursorType::Invisible,
+    CursorType::Custom,
 ];
 
+// Custom serialization
+#[cfg(feature = "serialize")]
+mod serialize {
+    use super::*;
+
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+    use serde::de::Error;
+
+    // Serialized by name, e.g. "Clickable"
+    impl Serialize for CursorType {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&format!("{:?}", self))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for CursorType {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let name = String::deserialize(d)?;
+
+            for &ty in ALL_CURSOR_TYPES.iter() {
+                if format!("{:?}", ty) == name {
+                    return Ok(ty);
+                }
+            }
+
+            Err(D::Error::custom(format!("\"{}\" is not a valid cursor type", name)))
+        }
+    }
+
+    // Serialized as a `(position, size, maximized, monitor)` tuple
+    impl Serialize for WindowGeometry {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            (self.position, self.size, self.maximized, &self.monitor).serialize(s)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for WindowGeometry {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let (position, size, maximized, monitor) = <(Vec2<f32>, Vec2<f32>, bool, Option<String>)>::deserialize(d)?;
+            Ok(WindowGeometry { position, size, maximized, monitor })
+        }
+    }
+}
+
+/// Options for creating a window. Use [`WindowCommon::new`] to create a window with the
+/// default options.
+///
+/// # Example
+/// ```rust,no_run
+/// # extern crate gondola;
+/// # extern crate cable_math;
+/// use gondola::{WindowBuilder, WindowCommon};
+/// use cable_math::Vec2;
+/// # fn main() {
+///
+/// // A splash screen
+/// let mut window = WindowBuilder::new("Loading")
+///     .size(Vec2::new(400.0, 300.0))
+///     .decorations(false)
+///     .always_on_top(true)
+///     .skip_taskbar(true)
+///     .create()
+///     .unwrap();
+/// window.show();
+/// # }
+/// ```
+///
+/// [`WindowCommon::new`]: trait.WindowCommon.html#method.new
+#[derive(Debug, Clone)]
+pub struct WindowBuilder {
+    pub title: String,
+    /// The size of the area inside the window, in pixels. Defaults to 1024x576.
+    pub size: Vec2<f32>,
+    /// The position of the top left corner of the window on the screen. If `None`, which is the
+    /// default, the position is chosen by the platform.
+    pub position: Option<Vec2<f32>>,
+    /// Whether the user can resize the window. Defaults to `true`.
+    pub resizable: bool,
+    pub gl: GlRequest,
+    /// Whether the default framebuffer converts colors from linear to srgb when writing. Defaults
+    /// to `false`. Creation fails with `WindowError::NoMatchingFormat` if no supported format
+    /// matches, which is always the case on windows for now.
+    pub srgb: bool,
+    /// The number of samples per pixel for multisampling in the default framebuffer. Values of
+    /// zero or one disable multisampling, which is the default. Creation fails with
+    /// `WindowError::NoMatchingFormat` if no supported format has this many samples, which is
+    /// always the case on windows for now.
+    pub samples: u32,
+    /// Whether the window has a title bar and border. Defaults to `true`.
+    pub decorations: bool,
+    /// Whether the window stays above other windows, even when it is not focused. Defaults to
+    /// `false`.
+    pub always_on_top: bool,
+    /// Whether the window is hidden from the taskbar. On windows this also gives the window a
+    /// smaller title bar, as used by tool windows. Defaults to `false`.
+    pub skip_taskbar: bool,
+    /// The monitor the window is opened on. If set, `position` is relative to the top left of
+    /// the monitor, and if `position` is `None` the window is centered on the monitor. Defaults to
+    /// `None`, in which case the platform chooses a monitor.
+    pub monitor: Option<Monitor>,
+    /// Whether the window starts out transparent, see `WindowCommon::set_transparent`. On linux
+    /// this also picks a visual with an alpha channel, which is needed to make the window
+    /// transparent later on. Defaults to `false`.
+    pub transparent: bool,
+    /// On windows, makes the process per monitor dpi aware. Windows then sends
+    /// `Event::ScaleFactorChanged` when the window moves between monitors with different scales,
+    /// instead of scaling the window as a bitmap. This is a process wide setting, and can not be
+    /// undone. Ignored on linux. Defaults to `false`.
+    pub per_monitor_dpi: bool,
+}
+
+impl WindowBuilder {
+    pub fn new(title: &str) -> WindowBuilder {
+        WindowBuilder {
+            title: title.to_owned(),
+            size: Vec2::new(1024.0, 576.0),
+            position: None,
+            resizable: true,
+            gl: GlRequest::default(),
+            srgb: false,
+            samples: 0,
+            decorations: true,
+            always_on_top: false,
+            skip_taskbar: false,
+            monitor: None,
+            transparent: false,
+            per_monitor_dpi: false,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> WindowBuilder {
+        self.title = title.to_owned();
+        self
+    }
+
+    pub fn size(mut self, size: Vec2<f32>) -> WindowBuilder {
+        self.size = size;
+        self
+    }
+
+    pub fn position(mut self, position: Vec2<f32>) -> WindowBuilder {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> WindowBuilder {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn gl(mut self, gl: GlRequest) -> WindowBuilder {
+        self.gl = gl;
+        self
+    }
+
+    pub fn srgb(mut self, srgb: bool) -> WindowBuilder {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn samples(mut self, samples: u32) -> WindowBuilder {
+        self.samples = samples;
+        self
+    }
+
+    pub fn decorations(mut self, decorations: bool) -> WindowBuilder {
+        self.decorations = decorations;
+        self
+    }
+
+    pub fn always_on_top(mut self, always_on_top: bool) -> WindowBuilder {
+        self.always_on_top = always_on_top;
+        self
+    }
+
+    pub fn skip_taskbar(mut self, skip_taskbar: bool) -> WindowBuilder {
+        self.skip_taskbar = skip_taskbar;
+        self
+    }
+
+    pub fn transparent(mut self, transparent: bool) -> WindowBuilder {
+        self.transparent = transparent;
+        self
+    }
+
+    pub fn per_monitor_dpi(mut self, per_monitor_dpi: bool) -> WindowBuilder {
+        self.per_monitor_dpi = per_monitor_dpi;
+        self
+    }
+
+    /// Opens the window on the given monitor, see [`monitors`].
+    ///
+    /// [`monitors`]: fn.monitors.html
+    pub fn monitor(mut self, monitor: &Monitor) -> WindowBuilder {
+        self.monitor = Some(monitor.clone());
+        self
+    }
+
+    // The position of the window in display space, taking `monitor` into account
+    fn placement(&self) -> Option<Vec2<f32>> {
+        match self.monitor {
+            Some(ref monitor) => Some(match self.position {
+                Some(position) => monitor.region.min + position,
+                None => monitor.region.center() - self.size/2.0,
+            }),
+            None => self.position,
+        }
+    }
+
+    /// Creates a window with these options. The window is not visible until
+    /// [`WindowCommon::show`] is called.
+    ///
+    /// If creation fails, e.g. because multisampling is not supported, the builder can be changed
+    /// and `create` can be called again.
+    ///
+    /// [`WindowCommon::show`]: trait.WindowCommon.html#tymethod.show
+    pub fn create(&self) -> Result<Window, WindowError> {
+        Window::from_builder(self)
+    }
+}
+
+impl Default for WindowBuilder {
+    fn default() -> WindowBuilder {
+        WindowBuilder::new("")
+    }
+}
+
+/// A display connected to the computer. See [`monitors`].
+///
+/// [`monitors`]: fn.monitors.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    /// Identifies the monitor while it stays connected.
+    pub id: u64,
+    /// The name the platform uses for the monitor, e.g. `DP-1` on linux or `\\.\DISPLAY1` on
+    /// windows.
+    pub name: String,
+    /// The area covered by the monitor, in display space. This is the same space as
+    /// `WindowCommon::screen_region` uses.
+    pub region: Region,
+    /// How much larger than normal user interfaces should be drawn on this monitor, where `1.0`
+    /// corresponds to 96 dpi. On linux this is read from the `Xft.dpi` setting, and on windows it
+    /// is the system wide dpi setting, so all monitors have the same scale factor. Use
+    /// `WindowCommon::scale_factor` for the scale of the monitor a window is on.
+    pub scale_factor: f32,
+    /// The refresh rate of the monitor in hertz, if it is known.
+    pub refresh_rate: Option<f32>,
+    /// Whether this is the primary monitor, on which new windows usually open.
+    pub primary: bool,
+}
+
+/// The position, size and state of a window, as returned by [`WindowCommon::geometry`]. This
+/// can be stored when an application closes, and passed to [`WindowCommon::restore_geometry`]
+/// on the next start. With the `serialize` feature, this implements `Serialize` and
+/// `Deserialize`.
+///
+/// [`WindowCommon::geometry`]: trait.WindowCommon.html#method.geometry
+/// [`WindowCommon::restore_geometry`]: trait.WindowCommon.html#method.restore_geometry
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowGeometry {
+    /// The top-left of the drawable area, in display space.
+    pub position: Vec2<f32>,
+    /// The size of the drawable area.
+    pub size: Vec2<f32>,
+    pub maximized: bool,
+    /// The name of the monitor the window was on, see `Monitor::name`. Names stay the same
+    /// between runs, unlike `Monitor::id`.
+    pub monitor: Option<String>,
+}
+
+/// A error which can occur while creating a window. See [`WindowCommon::new`] and
+/// [`WindowBuilder::create`].
+///
+/// [`WindowCommon::new`]: trait.WindowCommon.html#method.new
+/// [`WindowBuilder::create`]: struct.WindowBuilder.html#method.create
+#[derive(Debug, Clone)]
+pub enum WindowError {
+    /// A system library which is needed to create windows could not be loaded, e.g. xlib or
+    /// `opengl32.dll`.
+    LibraryNotFound(String),
+    /// Could not connect to the X server. Only happens on linux.
+    NoDisplay,
+    /// No pixel format or framebuffer configuration matches the requested options. Retrying
+    /// without multisampling or sRGB might help.
+    NoMatchingFormat,
+    /// The system refused to create the window.
+    CreationFailed(String),
+    /// A extension which is needed for the requested OpenGL context is not supported.
+    MissingExtension(String),
+    /// The OpenGL context could not be created for the given request, e.g. because the
+    /// requested version is not supported by the driver. Retrying with an older version might
+    /// help.
+    ContextCreationFailed(GlRequest, String),
+}
+
+impl error::Error for WindowError {
+    fn description(&self) -> &str {
+        match *self {
+            WindowError::LibraryNotFound(_)          => "Window error: Could not load library",
+            WindowError::NoDisplay                   => "Window error: Could not connect to the X server",
+            WindowError::NoMatchingFormat            => "Window error: No matching pixel format",
+            WindowError::CreationFailed(_)           => "Window error: Could not create window",
+            WindowError::MissingExtension(_)         => "Window error: Missing extension",
+            WindowError::ContextCreationFailed(..)   => "Window error: Could not create OpenGL context",
+        }
+    }
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WindowError::LibraryNotFound(ref lib)    => write!(f, "Window error: Could not load {}", lib),
+            WindowError::NoDisplay                   => write!(f, "Window error: Could not connect to the X server"),
+            WindowError::NoMatchingFormat            => write!(f, "Window error: No pixel format matches the requested options"),
+            WindowError::CreationFailed(ref reason)  => write!(f, "Window error: Could not create window: {}", reason),
+            WindowError::MissingExtension(ref name)  => write!(f, "Window error: {} is not supported", name),
+            WindowError::ContextCreationFailed(ref request, ref reason) => write!(
+                f, "Window error: Could not create OpenGL {}.{} {} context: {}",
+                request.version.0, request.version.1,
+                if request.gles { "es" } else if request.core { "core" } else { "compat" },
+                reason,
+            ),
+        }
+    }
+}
+
 /// Because a different `struct Window` is used per platform, all functions are defined on this
 /// trait.
 ///
@@ -49,7 +449,7 @@ const ALL_CURSOR_TYPES: [CursorType; CURSOR_TYPE_COUNT] = [
 /// ```rust,no_run
 /// use gondola::{Window, WindowCommon};
 ///
-/// let mut window = Window::new("My title");
+/// let mut window = Window::new("My title").unwrap();
 ///
 /// while !window.close_requested {
 ///     // Update and render
@@ -58,35 +458,326 @@ const ALL_CURSOR_TYPES: [CursorType; CURSOR_TYPE_COUNT] = [
 /// }
 /// ```
 pub trait WindowCommon: Drop {
-    fn new(title: &str) -> Self;
+    /// Creates a window with the given title and default options. See [`WindowBuilder`] for
+    /// more options.
+    ///
+    /// [`WindowBuilder`]: struct.WindowBuilder.html
+    fn new(title: &str) -> Result<Self, WindowError> where Self: Sized {
+        Self::from_builder(&WindowBuilder::new(title))
+    }
+    /// Starts building a window with custom options. Call [`WindowBuilder::create`] to create
+    /// the window.
+    ///
+    /// [`WindowBuilder::create`]: struct.WindowBuilder.html#method.create
+    fn build() -> WindowBuilder where Self: Sized {
+        WindowBuilder::default()
+    }
+    fn from_builder(builder: &WindowBuilder) -> Result<Self, WindowError> where Self: Sized;
     fn show(&mut self);
 
     fn poll_events(&mut self, input: &mut Input);
+    /// Like `poll_events`, but blocks until at least one event arrives or until `timeout` has
+    /// passed. This is useful for tools which only need to redraw when something changes. A
+    /// [`WakeHandle`] can be used to return early from another thread.
+    ///
+    /// Note that gamepads are not considered while waiting.
+    ///
+    /// [`WakeHandle`]: struct.WakeHandle.html
+    fn wait_events(&mut self, input: &mut Input, timeout: Option<Time>);
+    /// Creates a handle which can be sent to other threads, and used to wake this window from
+    /// `wait_events`.
+    fn wake_handle(&self) -> WakeHandle;
+    /// Creates a OpenGL context which shares textures, buffers and other objects with the
+    /// context of this window. The shared context can be sent to another thread and made current
+    /// there, so resources can be loaded without stalling the main thread. See [`Uploader`] for a
+    /// simpler way of doing this.
+    ///
+    /// [`Uploader`]: upload/struct.Uploader.html
+    fn create_shared_context(&self) -> Result<SharedContext, WindowError>;
     fn swap_buffers(&mut self);
+    /// Presents only the given regions of the back buffer, in pixels with `(0, 0)` at the top
+    /// left, for tools which only redraw what changed. See `DrawGroup::set_damage_tracking`.
+    /// Nothing is presented if `damage` is empty. Returns whether the back buffer still has the
+    /// contents of the presented frame afterwards. If not, the next frame has to be drawn
+    /// completely.
+    ///
+    /// Partial presents need `GLX_MESA_copy_sub_buffer`, see [`partial_swap_supported`].
+    /// Otherwise this swaps the whole back buffer, like `swap_buffers`, and returns `false`.
+    /// Note that partial presents are not synchronized with vsync.
+    ///
+    /// [`partial_swap_supported`]: #method.partial_swap_supported
+    fn swap_buffers_with_damage(&mut self, _damage: &[Region]) -> bool {
+        self.swap_buffers();
+        false
+    }
+    /// Whether `swap_buffers_with_damage` can present parts of the back buffer. This is only
+    /// supported on linux, through `GLX_MESA_copy_sub_buffer`.
+    fn partial_swap_supported(&self) -> bool {
+        false
+    }
+    /// Whether the OpenGL context has been lost, e.g. because the graphics driver was updated or
+    /// crashed. Nothing can be drawn in a lost context, so [`recreate_context`] should be called
+    /// once this is set. This is checked in `swap_buffers`.
+    ///
+    /// Context loss can only be detected if `GlRequest::robust` is set and the driver supports
+    /// `GLX_ARB_create_context_robustness` or `WGL_ARB_create_context_robustness`. Otherwise this
+    /// always returns `false`.
+    ///
+    /// [`recreate_context`]: #tymethod.recreate_context
+    fn context_lost(&self) -> bool;
+    /// Replaces the OpenGL context of this window with a new context, e.g. after it has been
+    /// lost. All textures, shaders, buffers and framebuffers created in the old context are
+    /// invalid afterwards, and should be replaced in callbacks registered with
+    /// [`graphics::add_rebuild_callback`], which are called before this returns. Dropping objects
+    /// from the old context is safe.
+    ///
+    /// Shared contexts created with `create_shared_context` are not replaced. The vsync mode
+    /// might have to be set again.
+    ///
+    /// [`graphics::add_rebuild_callback`]: graphics/fn.add_rebuild_callback.html
+    fn recreate_context(&mut self) -> Result<(), WindowError>;
+    /// Waits until the current frame has lasted as long as the target frame time of `pacer`,
+    /// and then swaps buffers. The time spent swapping is recorded in the pacer. Returns the
+    /// duration of the frame. See [`FramePacer`] for more info.
+    ///
+    /// [`FramePacer`]: struct.FramePacer.html
+    fn swap_buffers_paced(&mut self, pacer: &mut FramePacer) -> Time {
+        let frame_time = pacer.wait();
+
+        let before = Instant::now();
+        self.swap_buffers();
+        pacer.record_swap_time(before.elapsed().into());
+
+        frame_time
+    }
 
+    /// Whether the window should close, either because the user asked for it, e.g. by clicking
+    /// the close button, or because [`request_close`] was called. The window is never closed
+    /// automatically, it stays open until it is dropped. Once set, this stays set until
+    /// [`cancel_close`] is called.
+    ///
+    /// [`request_close`]: #tymethod.request_close
+    /// [`cancel_close`]: #tymethod.cancel_close
     fn close_requested(&self) -> bool;
+    /// Denies a close request, so `close_requested` returns false again. This can be used to e.g.
+    /// show a "Save changes?" prompt before closing, and then call [`request_close`] once the user
+    /// has made a decision.
+    ///
+    /// ```rust,ignore
+    /// if window.close_requested() && !confirmed_exit {
+    ///     window.cancel_close();
+    ///     show_save_prompt = true;
+    /// }
+    ///
+    /// // Later, when the user clicks "Discard" in the prompt
+    /// window.request_close();
+    /// ```
+    ///
+    /// [`request_close`]: #tymethod.request_close
+    fn cancel_close(&mut self);
+    /// Makes `close_requested` return true, as if the user had clicked the close button. Unlike a
+    /// close request from the user, this does not add a `Event::CloseRequested`. Drop the window
+    /// to actually close it.
+    fn request_close(&mut self);
     fn resized(&self) -> bool;
     fn moved(&self) -> bool;
     /// The region covered by the window, in display space. Use `Region::size` to find the size of
     /// the window.
     fn screen_region(&self) -> Region;
     fn focused(&self) -> bool;
+    /// How much larger than normal user interfaces should be drawn in this window, where `1.0`
+    /// corresponds to 96 dpi. A `Event::ScaleFactorChanged` is sent when this changes, e.g.
+    /// because the window was moved to a monitor with a different scale.
+    ///
+    /// On windows this requires `WindowBuilder::per_monitor_dpi`, in which case the window is
+    /// resized to keep its apparent size when the scale changes. Otherwise windows scales the
+    /// window itself, and this stays at the system wide scale. On linux all monitors share the `Xft.dpi` setting, which is checked again when the monitor
+    /// configuration changes.
+    fn scale_factor(&self) -> f32;
+
+    /// Resizes the window so that its drawable area has the given size. `resized` will return
+    /// true once the change has been processed in `poll_events`.
+    fn set_size(&mut self, size: Vec2<f32>);
+    /// Moves the window so that the top-left of its drawable area is at the given position, in
+    /// display space. `moved` will return true once the change has been processed in `poll_events`.
+    fn set_position(&mut self, pos: Vec2<f32>);
+    fn maximize(&mut self);
+    fn minimize(&mut self);
+    /// Undoes the effect of `maximize` and `minimize`
+    fn restore(&mut self);
+    fn maximized(&self) -> bool;
+    /// The region the window covers when it is neither maximized nor minimized, in display
+    /// space. This is the region `restore` returns the window to.
+    fn normal_region(&self) -> Region;
+
+    /// The monitor which contains the largest part of this window. Returns `None` if the window
+    /// is not on any monitor.
+    fn current_monitor(&self) -> Option<Monitor> {
+        let region = self.screen_region();
+        let mut best = None;
+        let mut best_area = 0.0;
+
+        for monitor in monitors() {
+            let overlap = monitor.region.overlap(region);
+            let area = overlap.width().max(0.0) * overlap.height().max(0.0);
+            if area > best_area {
+                best_area = area;
+                best = Some(monitor);
+            }
+        }
+
+        best
+    }
+    /// The refresh rate of the monitor this window is on, in hertz, or `None` if it is not known.
+    /// This can be passed straight to `FramePacer::set_target_fps` to pace frames to the monitor:
+    ///
+    /// ```rust,ignore
+    /// pacer.set_target_fps(window.refresh_rate().or(Some(60.0)));
+    /// ```
+    ///
+    /// This queries the system every time it is called, so it should only be called again when
+    /// the window is moved, not every frame.
+    fn refresh_rate(&self) -> Option<f32> {
+        self.current_monitor().and_then(|monitor| monitor.refresh_rate)
+    }
+    /// Moves the window to the center of the given monitor. Combine this with `maximize` to fill
+    /// a specific monitor.
+    fn move_to_monitor(&mut self, monitor: &Monitor) {
+        let size = self.screen_region().size();
+        self.set_position(monitor.region.center() - size/2.0);
+    }
+    /// The current position, size and state of this window. Pass this to [`restore_geometry`],
+    /// e.g. on the next start of the application, to restore the window layout. If the window
+    /// is maximized, the position and size are those it will have once it is restored, see
+    /// `normal_region`.
+    ///
+    /// [`restore_geometry`]: #method.restore_geometry
+    fn geometry(&self) -> WindowGeometry {
+        let region = self.normal_region();
+        WindowGeometry {
+            position: region.min,
+            size: region.size(),
+            maximized: self.maximized(),
+            monitor: self.current_monitor().map(|monitor| monitor.name),
+        }
+    }
+    /// Moves and resizes this window according to geometry previously returned by [`geometry`],
+    /// and maximizes it if it was maximized. If the window would not be visible at the stored
+    /// position, e.g. because the monitor layout changed, it is centered on the stored monitor
+    /// instead, or on the primary monitor if that monitor is no longer connected.
+    ///
+    /// [`geometry`]: #method.geometry
+    fn restore_geometry(&mut self, geometry: WindowGeometry) {
+        // The minimum area which has to be on a monitor for the window to count as visible, so
+        // it can still be grabbed and moved
+        const MIN_VISIBLE: f32 = 32.0;
+
+        let monitors = monitors();
+        let region = Region { min: geometry.position, max: geometry.position + geometry.size };
+        let visible = monitors.iter().any(|monitor| {
+            let overlap = monitor.region.overlap(region);
+            overlap.width() >= MIN_VISIBLE && overlap.height() >= MIN_VISIBLE
+        });
+
+        if self.maximized() {
+            self.restore();
+        }
+        self.set_size(geometry.size);
+
+        if visible {
+            self.set_position(geometry.position);
+        } else {
+            let fallback = monitors.iter()
+                .find(|monitor| Some(&monitor.name) == geometry.monitor.as_ref())
+                .or_else(|| monitors.iter().find(|monitor| monitor.primary))
+                .or_else(|| monitors.first());
+            if let Some(monitor) = fallback {
+                self.set_position(monitor.region.center() - geometry.size/2.0);
+            }
+        }
+
+        if geometry.maximized {
+            self.maximize();
+        }
+    }
+
+    /// Finds the key which types the given character with the users current keyboard layout.
+    /// This is useful for shortcuts which should follow the symbols printed on the keyboard,
+    /// e.g. ctrl+z for undo. Pass the character typed without any modifiers (e.g. `'z'`, not
+    /// `'Z'`). Returns `None` if no key types the character, or if that key is not represented
+    /// in [`Key`].
+    ///
+    /// [`Key`]: enum.Key.html
+    fn key_for_char(&self, c: char) -> Option<Key>;
 
     fn change_title(&mut self, title: &str);
     /// Enables/disables vsync, if supported by the graphics driver. In debug mode a warning is
     /// printed when calling this function if changing vsync is not supported. By default, vsync is
-    /// disabled.
-    fn set_vsync(&mut self, vsync: bool);
+    /// disabled. This is a shorthand for `set_vsync_mode`.
+    fn set_vsync(&mut self, vsync: bool) {
+        self.set_vsync_mode(if vsync { VsyncMode::On } else { VsyncMode::Off });
+    }
+    /// Changes the swap interval. If adaptive vsync is requested but not supported, normal vsync
+    /// is used instead. Returns the mode which actually is in effect after the call, as reported
+    /// by the driver, so a frame limiter can be enabled if vsync could not be enabled. Note that
+    /// drivers can be configured to override the applications choice.
+    fn set_vsync_mode(&mut self, mode: VsyncMode) -> VsyncMode;
+    /// Whether the swap interval can be changed at all.
+    fn vsync_supported(&self) -> bool;
+    /// Whether `VsyncMode::Adaptive` is supported, through `GLX_EXT_swap_control_tear` or
+    /// `WGL_EXT_swap_control_tear`.
+    fn adaptive_vsync_supported(&self) -> bool;
+    /// The swap interval currently in use, as reported by the driver. 0 means vsync is disabled,
+    /// 1 means vsync is enabled and -1 means adaptive vsync is enabled. If swap control is not
+    /// supported this returns 1, as that is the initial swap interval.
+    fn current_swap_interval(&self) -> i32;
+
+    /// Makes the background of the window transparent, so that the desktop and other windows
+    /// show through wherever the alpha of the default framebuffer is below one. When enabled,
+    /// colors should be written with premultiplied alpha, and the framebuffer should be
+    /// cleared to a color with zero alpha. Returns whether transparency is supported, which
+    /// requires a compositing window manager.
+    ///
+    /// When transparency is disabled, which is the default, the alpha of the default framebuffer
+    /// is ignored. On linux, windows can only be made transparent if they were created with
+    /// `WindowBuilder::transparent`.
+    fn set_transparent(&mut self, transparent: bool) -> bool;
+    fn transparent(&self) -> bool;
 
     /// Sets the visual apperance of the cursor when it is inside this window
     fn set_cursor(&mut self, cursor: CursorType);
+    /// Sets the image used for `CursorType::Custom`, and switches to that cursor. `pixels` should
+    /// contain `size.x*size.y` RGBA pixels, starting at the top-left. `hotspot` is the pixel
+    /// which is placed at the actual mouse position.
+    ///
+    /// On linux cursors can only have two colors and no partial transparency, so the image is
+    /// approximated: Pixels with less than 50% alpha are transparent, and the remaining pixels
+    /// are split into a bright and a dark color.
+    fn set_cursor_image(&mut self, pixels: &[u8], size: Vec2<u32>, hotspot: Vec2<u32>);
+    /// Moves the cursor to the given position, in window space.
+    fn set_cursor_position(&mut self, pos: Vec2<f32>);
     /// Clips the cursor so it can not leave the given region. The region should be in window
-    /// space. That is, the region is relative to the top-left of this windows screen region.
+    /// space. That is, the region is relative to the top-left of this windows screen region, and
+    /// it moves along with the window. The clip only applies while the window is focused.
     fn clip_cursor(&mut self, region: Option<Region>);
     /// Constrains the cursor to the center of the screen. This takes precedence over `clip_cursor`
     fn grab_cursor(&mut self, grabbed: bool);
 }
 
+// Called by both platforms once a new context has replaced the old context of a window, and has
+// been made current
+fn restore_context_state(gl_request: GlRequest, viewport: Region, srgb: bool) {
+    graphics::note_context_replaced();
+    if gl_request.debug {
+        graphics::enable_debug_output();
+    }
+    framebuffer::set_window_viewport(viewport);
+    if srgb {
+        unsafe { gl::Enable(gl::FRAMEBUFFER_SRGB) };
+    }
+    graphics::run_rebuild_callbacks();
+}
+
 #[cfg(target_os = "linux")]
 pub use self::linux::*;
 
@@ -99,7 +790,8 @@ mod linux {
     use std::ptr;
     use std::mem;
     use std::str;
-    use std::ffi::CString;
+    use std::slice;
+    use std::ffi::{CString, CStr};
 
     use gl;
 
@@ -109,11 +801,406 @@ mod linux {
         pub(super) use super::x11_dl::xlib::*;
         pub(super) use super::x11_dl::glx::*;
         pub(super) use super::x11_dl::glx::arb::*;
+        pub(super) use super::x11_dl::xrandr::*;
+        pub(super) use super::x11_dl::xinput2::*;
+
+        use std::os::raw::{c_int, c_short, c_ulong, c_double};
 
         pub const GLX_RGBA_TYPE: i32 = 0x8014; // From /usr/include/GL/glx.h
+        pub const GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB: i32 = 0x20b2; // From GLX_ARB_framebuffer_sRGB
+        pub const GLX_CONTEXT_ES2_PROFILE_BIT_EXT: i32 = 0x0004; // From GLX_EXT_create_context_es2_profile
+        // From GLX_ARB_create_context_robustness
+        pub const GLX_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB: i32 = 0x8256;
+        pub const GLX_LOSE_CONTEXT_ON_RESET_ARB: i32 = 0x8252;
 
         #[allow(non_camel_case_types)]
         pub type glXSwapIntervalEXT = extern "system" fn(*mut Display, GLXDrawable, i32);
+        #[allow(non_camel_case_types)]
+        pub type glXSwapIntervalMESA = extern "system" fn(u32) -> i32;
+        #[allow(non_camel_case_types)]
+        pub type glXGetSwapIntervalMESA = extern "system" fn() -> i32;
+        #[allow(non_camel_case_types)]
+        pub type glXCopySubBufferMESA = extern "system" fn(*mut Display, GLXDrawable, c_int, c_int, c_int, c_int);
+
+        pub use super::x11_dl::glx::ext::GLX_SWAP_INTERVAL_EXT;
+        pub const GLX_LATE_SWAPS_TEAR_EXT: i32 = 0x20f3; // From GLX_EXT_swap_control_tear
+
+        // From /usr/include/poll.h, used to wait for events with a timeout
+        #[allow(non_camel_case_types)]
+        #[repr(C)]
+        pub struct pollfd {
+            pub fd: c_int,
+            pub events: c_short,
+            pub revents: c_short,
+        }
+        pub const POLLIN: c_short = 0x001;
+
+        extern "C" {
+            pub fn poll(fds: *mut pollfd, nfds: c_ulong, timeout: c_int) -> c_int;
+        }
+
+        // Touchpad gestures from XInput 2.4, which are missing from x11_dl. From
+        // /usr/include/X11/extensions/XInput2.h
+        #[allow(non_upper_case_globals)]
+        pub const XI_GesturePinchBegin: c_int = 27;
+        #[allow(non_upper_case_globals)]
+        pub const XI_GesturePinchUpdate: c_int = 28;
+        #[allow(non_upper_case_globals)]
+        pub const XI_GesturePinchEnd: c_int = 29;
+
+        #[repr(C)]
+        pub struct XIGesturePinchEvent {
+            pub type_: c_int,
+            pub serial: c_ulong,
+            pub send_event: Bool,
+            pub display: *mut Display,
+            pub extension: c_int,
+            pub evtype: c_int,
+            pub time: Time,
+            pub deviceid: c_int,
+            pub sourceid: c_int,
+            pub detail: c_int,
+            pub root: Window,
+            pub event: Window,
+            pub child: Window,
+            pub root_x: c_double,
+            pub root_y: c_double,
+            pub event_x: c_double,
+            pub event_y: c_double,
+            pub delta_x: c_double,
+            pub delta_y: c_double,
+            pub delta_unaccel_x: c_double,
+            pub delta_unaccel_y: c_double,
+            pub scale: c_double,
+            pub delta_angle: c_double,
+            pub flags: c_int,
+            pub mods: XIModifierState,
+            pub group: XIGroupState,
+        }
+    }
+
+    /// Shows a modal dialog with the given error message, and blocks until the user closes it.
+    /// This is intended for fatal errors, e.g. when no OpenGL context can be created. It does not
+    /// require a window or an OpenGL context.
+    ///
+    /// On linux this tries `zenity`, `kdialog` and `xmessage`, in that order. If none of them
+    /// are available the message is only printed.
+    pub fn show_error_dialog(title: &str, message: &str) {
+        use std::process::Command;
+
+        // The tools exit with an error if they can not show a dialog, e.g. without a X server
+        let run = |command: &mut Command| {
+            command.status().map(|status| status.success()).unwrap_or(false)
+        };
+
+        let shown =
+            run(Command::new("zenity").arg("--error").arg("--title").arg(title).arg("--text").arg(message)) ||
+            run(Command::new("kdialog").arg("--title").arg(title).arg("--error").arg(message)) ||
+            run(Command::new("xmessage").arg("-center").arg(format!("{}\n\n{}", title, message)));
+
+        if !shown {
+            eprintln!("{}: {}", title, message);
+        }
+    }
+
+    /// Lists the monitors connected to the computer, with the primary monitor first. Returns an
+    /// empty list if the X server can not be reached.
+    ///
+    /// Monitors are found through xrandr. If xrandr is not available, the whole X screen is
+    /// returned as a single monitor.
+    pub fn monitors() -> Vec<Monitor> {
+        let xlib = match ffi::Xlib::open() {
+            Ok(x) => x,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut monitors = Vec::new();
+
+        unsafe {
+            let display = (xlib.XOpenDisplay)(ptr::null());
+            if display.is_null() {
+                return Vec::new();
+            }
+            let root = (xlib.XDefaultRootWindow)(display);
+            let scale_factor = xft_scale_factor(&xlib, display);
+
+            if let Ok(xrandr) = ffi::Xrandr::open() {
+                let resources = (xrandr.XRRGetScreenResourcesCurrent)(display, root);
+
+                let mut count = 0;
+                let infos = (xrandr.XRRGetMonitors)(display, root, ffi::True, &mut count);
+                if !infos.is_null() {
+                    for i in 0..count {
+                        let info = &*infos.offset(i as isize);
+
+                        let name_ptr = (xlib.XGetAtomName)(display, info.name);
+                        let name = if name_ptr.is_null() {
+                            String::new()
+                        } else {
+                            let name = CStr::from_ptr(name_ptr).to_string_lossy().into_owned();
+                            (xlib.XFree)(name_ptr as *mut _);
+                            name
+                        };
+
+                        let refresh_rate = if info.noutput > 0 && !resources.is_null() {
+                            output_refresh_rate(&xrandr, display, resources, *info.outputs)
+                        } else {
+                            None
+                        };
+
+                        let min = Vec2::new(info.x, info.y).as_f32();
+                        let size = Vec2::new(info.width, info.height).as_f32();
+
+                        monitors.push(Monitor {
+                            id: info.name as u64,
+                            name,
+                            region: Region { min, max: min + size },
+                            scale_factor,
+                            refresh_rate,
+                            primary: info.primary != 0,
+                        });
+                    }
+
+                    (xrandr.XRRFreeMonitors)(infos);
+                }
+
+                if !resources.is_null() {
+                    (xrandr.XRRFreeScreenResources)(resources);
+                }
+            }
+
+            if monitors.is_empty() {
+                let screen = (xlib.XDefaultScreen)(display);
+                let size = Vec2::new(
+                    (xlib.XDisplayWidth)(display, screen),
+                    (xlib.XDisplayHeight)(display, screen),
+                ).as_f32();
+
+                monitors.push(Monitor {
+                    id: 0,
+                    name: String::new(),
+                    region: Region { min: Vec2::ZERO, max: size },
+                    scale_factor,
+                    refresh_rate: None,
+                    primary: true,
+                });
+            }
+
+            (xlib.XCloseDisplay)(display);
+        }
+
+        // `sort_by_key` is stable, so the order reported by xrandr is kept otherwise
+        monitors.sort_by_key(|monitor| !monitor.primary);
+        monitors
+    }
+
+    // Finds the refresh rate of the mode the given output is currently using
+    unsafe fn output_refresh_rate(
+        xrandr: &ffi::Xrandr,
+        display: *mut ffi::Display,
+        resources: *mut ffi::XRRScreenResources,
+        output: ffi::RROutput,
+    ) -> Option<f32>
+    {
+        let output_info = (xrandr.XRRGetOutputInfo)(display, resources, output);
+        if output_info.is_null() {
+            return None;
+        }
+        let crtc = (*output_info).crtc;
+        (xrandr.XRRFreeOutputInfo)(output_info);
+
+        if crtc == 0 {
+            return None;
+        }
+        let crtc_info = (xrandr.XRRGetCrtcInfo)(display, resources, crtc);
+        if crtc_info.is_null() {
+            return None;
+        }
+        let mode = (*crtc_info).mode;
+        (xrandr.XRRFreeCrtcInfo)(crtc_info);
+
+        let modes = ::std::slice::from_raw_parts((*resources).modes, (*resources).nmode as usize);
+        let mode = modes.iter().find(|m| m.id == mode)?;
+
+        let mut lines = mode.vTotal as f64;
+        if mode.modeFlags & (ffi::RR_DoubleScan as ffi::XRRModeFlags) != 0 {
+            lines *= 2.0;
+        }
+        if mode.modeFlags & (ffi::RR_Interlace as ffi::XRRModeFlags) != 0 {
+            lines /= 2.0;
+        }
+
+        let dots = mode.hTotal as f64 * lines;
+        if dots == 0.0 {
+            None
+        } else {
+            Some((mode.dotClock as f64 / dots) as f32)
+        }
+    }
+
+    // Reads `Xft.dpi` from the X resource database, which desktop environments set to the
+    // configured ui scale
+    unsafe fn xft_scale_factor(xlib: &ffi::Xlib, display: *mut ffi::Display) -> f32 {
+        let resources = (xlib.XResourceManagerString)(display);
+        if resources.is_null() {
+            return 1.0;
+        }
+
+        let resources = CStr::from_ptr(resources).to_string_lossy();
+        for line in resources.lines() {
+            if line.starts_with("Xft.dpi:") {
+                if let Ok(dpi) = line["Xft.dpi:".len()..].trim().parse::<f32>() {
+                    return dpi / 96.0;
+                }
+            }
+        }
+
+        1.0
+    }
+
+    // The different extensions which can be used to change the swap interval
+    #[derive(Copy, Clone)]
+    enum SwapControl {
+        Ext(ffi::glXSwapIntervalEXT),
+        Mesa(ffi::glXSwapIntervalMESA, ffi::glXGetSwapIntervalMESA),
+        Unsupported,
+    }
+
+    // Creates a OpenGL context for the given config. If `share` is not null, the new context
+    // shares objects with it.
+    unsafe fn create_context(
+        glx: &ffi::Glx,
+        display: *mut ffi::Display,
+        fb_config: ffi::GLXFBConfig,
+        share: ffi::GLXContext,
+        gl_request: GlRequest,
+        robust: bool,
+    ) -> Result<ffi::GLXContext, WindowError>
+    {
+        #[allow(non_camel_case_types)]
+        type glXCreateContextAttribsARB = extern "system" fn(
+            *mut ffi::Display,
+            ffi::GLXFBConfig,
+            ffi::GLXContext,
+            i32,
+            *const i32
+        ) -> ffi::GLXContext;
+
+        let create_fn = (glx.glXGetProcAddress)(b"glXCreateContextAttribsARB\0".as_ptr());
+
+        let context = if let Some(create_fn) = create_fn {
+            let profile_mask = if gl_request.gles {
+                ffi::GLX_CONTEXT_ES2_PROFILE_BIT_EXT
+            } else if gl_request.core {
+                ffi::GLX_CONTEXT_CORE_PROFILE_BIT_ARB
+            } else {
+                ffi::GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB
+            };
+
+            let mut flags = 0;
+            if gl_request.debug {
+                flags |= ffi::GLX_CONTEXT_DEBUG_BIT_ARB;
+            }
+            if gl_request.forward_compatible {
+                flags |= ffi::GLX_CONTEXT_FORWARD_COMPATIBLE_BIT_ARB;
+            }
+
+            // Shared contexts need the same reset strategy as the context they share with
+            let (reset_strategy_name, reset_strategy) = if robust {
+                (ffi::GLX_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB, ffi::GLX_LOSE_CONTEXT_ON_RESET_ARB)
+            } else {
+                (0, 0) // Ends the list early
+            };
+
+            let context_attributes = [
+                ffi::GLX_CONTEXT_MAJOR_VERSION_ARB, gl_request.version.0 as i32,
+                ffi::GLX_CONTEXT_MINOR_VERSION_ARB, gl_request.version.1 as i32,
+                ffi::GLX_CONTEXT_FLAGS_ARB, flags,
+                ffi::GLX_CONTEXT_PROFILE_MASK_ARB, profile_mask,
+                reset_strategy_name, reset_strategy,
+                0,
+            ];
+
+            let create_fn = mem::transmute::<_, glXCreateContextAttribsARB>(create_fn);
+
+            create_fn(
+                display, fb_config, 
+                share, 1,
+                context_attributes.as_ptr(),
+            )
+        } else if gl_request.gles {
+            return Err(WindowError::MissingExtension("GLX_ARB_create_context".to_owned()));
+        } else {
+            println!("Could not use glXCreateContextAttribsARB!");
+            (glx.glXCreateNewContext)(
+                display, fb_config,
+                ffi::GLX_RGBA_TYPE,
+                share, 1
+            )
+        };
+
+        if context.is_null() {
+            return Err(WindowError::ContextCreationFailed(gl_request, "glXCreateContext failed".to_owned()));
+        }
+
+        Ok(context)
+    }
+
+    // Releases everything created so far if `from_builder` fails partway through. Resources are
+    // added as they are created, and `disarm` is called once the window is complete.
+    struct CreationGuard<'a> {
+        xlib: &'a ffi::Xlib,
+        glx: &'a ffi::Glx,
+        display: *mut ffi::Display,
+        colormap: ffi::Colormap,
+        window: u64,
+        context: ffi::GLXContext,
+        im: ffi::XIM,
+        ic: ffi::XIC,
+    }
+
+    impl<'a> CreationGuard<'a> {
+        fn new(xlib: &'a ffi::Xlib, glx: &'a ffi::Glx, display: *mut ffi::Display) -> CreationGuard<'a> {
+            CreationGuard {
+                xlib, glx, display,
+                colormap: 0,
+                window: 0,
+                context: ptr::null_mut(),
+                im: ptr::null_mut(),
+                ic: ptr::null_mut(),
+            }
+        }
+
+        fn disarm(self) {
+            mem::forget(self);
+        }
+    }
+
+    impl<'a> Drop for CreationGuard<'a> {
+        fn drop(&mut self) {
+            let xlib = self.xlib;
+            let glx = self.glx;
+
+            unsafe {
+                if !self.ic.is_null() {
+                    (xlib.XDestroyIC)(self.ic);
+                }
+                if !self.im.is_null() {
+                    (xlib.XCloseIM)(self.im);
+                }
+                if !self.context.is_null() {
+                    (glx.glXMakeCurrent)(self.display, 0, ptr::null_mut());
+                    (glx.glXDestroyContext)(self.display, self.context);
+                }
+                if self.window != 0 {
+                    (xlib.XDestroyWindow)(self.display, self.window);
+                }
+                if self.colormap != 0 {
+                    (xlib.XFreeColormap)(self.display, self.colormap);
+                }
+                (xlib.XCloseDisplay)(self.display);
+            }
+        }
     }
 
     pub struct Window {
@@ -122,13 +1209,26 @@ mod linux {
 
         display: *mut ffi::Display,
         window: u64,
+        context: ffi::GLXContext,
+        fb_config: ffi::GLXFBConfig,
+        gl_request: GlRequest,
+        robust: bool,
+        context_lost: bool,
+        srgb: bool,
 
         im: ffi::XIM,
         ic: ffi::XIC,
 
         wm_delete_window: ffi::Atom,
+        wake_atom: ffi::Atom,
+        net_wm_state: ffi::Atom,
+        net_wm_state_maximized: [ffi::Atom; 2], // Vertical and horizontal
         cursors: [u64; CURSOR_TYPE_COUNT],
-        swap_function: ffi::glXSwapIntervalEXT,
+        swap_control: SwapControl,
+        adaptive_vsync_supported: bool,
+        copy_sub_buffer: Option<ffi::glXCopySubBufferMESA>,
+        argb_visual: bool,
+        transparent: bool,
 
         close_requested: bool,
         resized: bool,
@@ -137,26 +1237,228 @@ mod linux {
         cursor_clip_region: Option<Region>,
         cursor: CursorType,
         focused: bool,
+        xinput: Option<XInput>,
+        // Used to listen for monitor configuration changes. Contains the event base of xrandr.
+        xrandr: Option<(ffi::Xrandr, i32)>,
 
         screen_region: Region,
+        // The last `screen_region` while the window was not maximized
+        normal_region: Region,
+        scale_factor: f32,
+    }
+
+    // Smooth scrolling and touchpad gestures through XInput2. Without it we fall back to the core
+    // protocol, which only reports whole scroll wheel clicks.
+    struct XInput {
+        xinput2: ffi::XInput2,
+        opcode: i32,
+        scroll_valuators: Vec<ScrollValuator>,
+        pinch_scale: f64,
+    }
+
+    struct ScrollValuator {
+        device: i32,
+        number: i32,
+        vertical: bool,
+        increment: f64, // Distance of one scroll wheel click
+    }
+
+    impl XInput {
+        unsafe fn init(xlib: &ffi::Xlib, display: *mut ffi::Display, window: u64) -> Option<XInput> {
+            let xinput2 = ffi::XInput2::open().ok()?;
+
+            let (mut opcode, mut first_event, mut first_error) = (0, 0, 0);
+            let name = b"XInputExtension\0";
+            let present = (xlib.XQueryExtension)(
+                display, name.as_ptr() as *const _,
+                &mut opcode, &mut first_event, &mut first_error,
+            );
+            if present == 0 {
+                return None;
+            }
+
+            // Smooth scrolling was added in 2.1, and touchpad gestures in 2.4. The server replies
+            // with the highest version it supports.
+            let (mut major, mut minor) = (2, 4);
+            if (xinput2.XIQueryVersion)(display, &mut major, &mut minor) != 0 {
+                return None;
+            }
+            if major < 2 || (major == 2 && minor < 1) {
+                return None;
+            }
+            let gestures = major > 2 || minor >= 4;
+
+            let mut xinput = XInput {
+                xinput2,
+                opcode,
+                scroll_valuators: Vec::new(),
+                pinch_scale: 1.0,
+            };
+            xinput.query_scroll_valuators(display);
+
+            // Raw events are only delivered to the root window. We listen for hierarchy changes
+            // to find the scroll valuators of devices which are plugged in later.
+            let root = (xlib.XDefaultRootWindow)(display);
+            let mut raw_mask = [0u8; 4];
+            ffi::XISetMask(&mut raw_mask, ffi::XI_RawMotion);
+            let mut hierarchy_mask = [0u8; 4];
+            ffi::XISetMask(&mut hierarchy_mask, ffi::XI_HierarchyChanged);
+            let mut masks = [
+                ffi::XIEventMask {
+                    deviceid: ffi::XIAllMasterDevices,
+                    mask_len: raw_mask.len() as i32,
+                    mask: raw_mask.as_mut_ptr(),
+                },
+                ffi::XIEventMask {
+                    deviceid: ffi::XIAllDevices,
+                    mask_len: hierarchy_mask.len() as i32,
+                    mask: hierarchy_mask.as_mut_ptr(),
+                },
+            ];
+            (xinput.xinput2.XISelectEvents)(display, root, masks.as_mut_ptr(), masks.len() as i32);
+
+            if gestures {
+                let mut gesture_mask = [0u8; 4];
+                ffi::XISetMask(&mut gesture_mask, ffi::XI_GesturePinchBegin);
+                ffi::XISetMask(&mut gesture_mask, ffi::XI_GesturePinchUpdate);
+                ffi::XISetMask(&mut gesture_mask, ffi::XI_GesturePinchEnd);
+                let mut mask = ffi::XIEventMask {
+                    deviceid: ffi::XIAllMasterDevices,
+                    mask_len: gesture_mask.len() as i32,
+                    mask: gesture_mask.as_mut_ptr(),
+                };
+                (xinput.xinput2.XISelectEvents)(display, window, &mut mask, 1);
+            }
+
+            Some(xinput)
+        }
+
+        unsafe fn query_scroll_valuators(&mut self, display: *mut ffi::Display) {
+            self.scroll_valuators.clear();
+
+            let mut count = 0;
+            let devices = (self.xinput2.XIQueryDevice)(display, ffi::XIAllDevices, &mut count);
+            if devices.is_null() {
+                return;
+            }
+
+            for device in slice::from_raw_parts(devices, count as usize) {
+                if device._use != ffi::XISlavePointer {
+                    continue;
+                }
+
+                for &class in slice::from_raw_parts(device.classes, device.num_classes as usize) {
+                    if (*class)._type != ffi::XIScrollClass {
+                        continue;
+                    }
+
+                    let class = &*(class as *const ffi::XIScrollClassInfo);
+                    self.scroll_valuators.push(ScrollValuator {
+                        device: device.deviceid,
+                        number: class.number,
+                        vertical: class.scroll_type == ffi::XIScrollTypeVertical,
+                        increment: class.increment,
+                    });
+                }
+            }
+
+            (self.xinput2.XIFreeDeviceInfo)(devices);
+        }
+
+        // Whether scrolling is reported through XInput2, in which case the core scroll wheel
+        // buttons should be ignored
+        fn smooth_scroll(&self) -> bool {
+            !self.scroll_valuators.is_empty()
+        }
+
+        unsafe fn handle_event(
+            &mut self,
+            display: *mut ffi::Display,
+            cookie: &ffi::XGenericEventCookie,
+            input: &mut Input,
+        ) {
+            match cookie.evtype {
+                ffi::XI_RawMotion => {
+                    // Raw events are sent regardless of which window the pointer is over
+                    if !input.mouse_inside_window {
+                        return;
+                    }
+
+                    let event = &*(cookie.data as *const ffi::XIRawEvent);
+                    let mask = slice::from_raw_parts(event.valuators.mask, event.valuators.mask_len as usize);
+
+                    // Values are only stored for valuators which are set in the mask
+                    let mut scroll = Vec2::ZERO;
+                    let mut value_index = 0;
+                    for number in 0..(mask.len()*8) as i32 {
+                        if !ffi::XIMaskIsSet(mask, number) {
+                            continue;
+                        }
+                        let value = *event.valuators.values.offset(value_index);
+                        value_index += 1;
+
+                        let valuator = self.scroll_valuators.iter()
+                            .find(|v| v.device == event.sourceid && v.number == number);
+                        if let Some(valuator) = valuator {
+                            if valuator.increment == 0.0 {
+                                continue;
+                            }
+
+                            let clicks = (value / valuator.increment) as f32;
+                            if valuator.vertical {
+                                scroll.y -= clicks;
+                            } else {
+                                scroll.x += clicks;
+                            }
+                        }
+                    }
+
+                    if scroll != Vec2::ZERO {
+                        input.received_events_this_frame = true;
+                        input.add_scroll(scroll);
+                    }
+                },
+
+                ffi::XI_HierarchyChanged => {
+                    self.query_scroll_valuators(display);
+                },
+
+                ffi::XI_GesturePinchBegin | ffi::XI_GesturePinchUpdate | ffi::XI_GesturePinchEnd => {
+                    let event = &*(cookie.data as *const ffi::XIGesturePinchEvent);
+
+                    // `scale` is relative to the start of the gesture
+                    if cookie.evtype == ffi::XI_GesturePinchBegin {
+                        self.pinch_scale = 1.0;
+                    }
+
+                    if event.scale > 0.0 && event.scale != self.pinch_scale {
+                        input.received_events_this_frame = true;
+                        input.add_zoom((event.scale / self.pinch_scale) as f32);
+                        self.pinch_scale = event.scale;
+                    }
+                },
+
+                _ => {},
+            }
+        }
     }
 
     impl WindowCommon for Window {
-        fn new(title: &str) -> Window {
-            let gl_request = GlRequest::default();
+        fn from_builder(builder: &WindowBuilder) -> Result<Window, WindowError> {
+            let gl_request = builder.gl;
 
             // Load xlib and glx
             let xlib = match ffi::Xlib::open() {
                 Ok(x) => x,
                 Err(err) => {
-                    panic!("Could not load xlib: {:?}", err);
+                    return Err(WindowError::LibraryNotFound(format!("xlib: {:?}", err)));
                 },
             };
 
             let glx = match ffi::Glx::open() {
                 Ok(x) => x,
                 Err(err) => {
-                    panic!("Could not load glx: {:?}", err);
+                    return Err(WindowError::LibraryNotFound(format!("glx: {:?}", err)));
                 },
             };
 
@@ -168,14 +1470,15 @@ mod linux {
                 let display = (xlib.XOpenDisplay)(ptr::null());
 
                 if display.is_null() {
-                    panic!("Could not connect to the X server");
+                    return Err(WindowError::NoDisplay);
                 }
 
                 display
             };
+            let mut guard = CreationGuard::new(&xlib, &glx, display);
 
             // Set up OpenGL
-            let mut attributes = [
+            let mut attributes = vec![
                 ffi::GLX_X_RENDERABLE,  1,
                 ffi::GLX_DRAWABLE_TYPE, ffi::GLX_WINDOW_BIT,
                 ffi::GLX_RENDER_TYPE,   ffi::GLX_RGBA_BIT,
@@ -187,10 +1490,19 @@ mod linux {
                 ffi::GLX_DEPTH_SIZE,    24,
                 ffi::GLX_STENCIL_SIZE,  8,
                 ffi::GLX_DOUBLEBUFFER,  1,
-
-                0,
             ];
 
+            if builder.srgb {
+                attributes.extend_from_slice(&[ffi::GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB, 1]);
+            }
+            if builder.samples > 1 {
+                attributes.extend_from_slice(&[
+                    ffi::GLX_SAMPLE_BUFFERS, 1,
+                    ffi::GLX_SAMPLES,        builder.samples as i32,
+                ]);
+            }
+            attributes.push(0);
+
             let default_screen = unsafe { (xlib.XDefaultScreen)(display) };
 
             let mut count = 0;
@@ -200,42 +1512,78 @@ mod linux {
                 attributes.as_mut_ptr(),
                 &mut count,
             ) };
-            if fb_configs.is_null() {
-                panic!("No FB configs");
+            if fb_configs.is_null() || count == 0 {
+                return Err(WindowError::NoMatchingFormat);
             }
 
-            let fb_config = unsafe { *fb_configs }; // Just use the first one, whatever
-            unsafe { (xlib.XFree)(fb_configs as *mut _) };
+            // For transparent windows, prefer a config with a 32 bit visual, which compositors
+            // will treat as having an alpha channel. Otherwise we just use the first one,
+            // whatever.
+            let (fb_config, visual) = unsafe {
+                let configs = ::std::slice::from_raw_parts(fb_configs, count as usize);
+                let mut chosen = None;
+
+                for &config in configs.iter() {
+                    let visual = (glx.glXGetVisualFromFBConfig)(display, config);
+                    if visual.is_null() {
+                        continue;
+                    }
 
-            let visual = unsafe { (glx.glXGetVisualFromFBConfig)(display, fb_config) };
-            if visual.is_null() {
-                panic!("No appropriate visual found");
-            }
+                    if builder.transparent && (*visual).depth == 32 {
+                        if let Some((_, previous)) = chosen {
+                            (xlib.XFree)(previous as *mut _);
+                        }
+                        chosen = Some((config, visual));
+                        break;
+                    } else if chosen.is_none() {
+                        chosen = Some((config, visual));
+                    } else {
+                        (xlib.XFree)(visual as *mut _);
+                    }
+                }
+
+                (xlib.XFree)(fb_configs as *mut _);
+
+                match chosen {
+                    Some(chosen) => chosen,
+                    None => return Err(WindowError::NoMatchingFormat),
+                }
+            };
+            let argb_visual = unsafe { (*visual).depth == 32 };
 
             // Create window
             let root = unsafe { (xlib.XDefaultRootWindow)(display) };
 
             let colormap = unsafe { (xlib.XCreateColormap)(display, root, (*visual).visual, 0) };
+            guard.colormap = colormap;
 
             let mut win_attributes = ffi::XSetWindowAttributes {
                 event_mask: 
                     ffi::ExposureMask |
                     ffi::StructureNotifyMask |
                     ffi::PointerMotionMask |
+                    ffi::EnterWindowMask | ffi::LeaveWindowMask |
                     ffi::KeyPressMask | ffi::KeyReleaseMask |
                     ffi::ButtonPressMask | ffi::ButtonReleaseMask |
                     ffi::FocusChangeMask,
 
                 colormap: colormap,
+                // Required when the visual differs from the visual of the root window, which
+                // is the case with 32 bit visuals
+                border_pixel: 0,
 
                 .. unsafe { mem::zeroed() }
             };
 
-            let center = Vec2::new(500.0, 400.0);
-            let size = Vec2::new(1024.0, 576.0);
+            let size = builder.size;
+            let placement = builder.placement();
+            let position = placement.unwrap_or_else(|| {
+                let center = Vec2::new(500.0, 400.0);
+                center/2.0 - size/2.0
+            });
             let screen_region = Region {
-                min: center/2.0 - size/2.0,
-                max: center/2.0 + size/2.0,
+                min: position,
+                max: position + size,
             };
 
             let window = unsafe { (xlib.XCreateWindow)(
@@ -248,15 +1596,72 @@ mod linux {
                 ffi::InputOutput as _,
                 (*visual).visual,
 
-                ffi::CWColormap | ffi::CWEventMask,
+                ffi::CWColormap | ffi::CWEventMask | ffi::CWBorderPixel,
                 &mut win_attributes,
             ) };
+            guard.window = window;
 
             unsafe { (xlib.XFree)(visual as *mut _); }
 
-            let title = CString::new(title).unwrap();
+            let title = CString::new(builder.title.as_str()).unwrap();
             unsafe { (xlib.XStoreName)(display, window, title.into_raw()); }
 
+            // Set window style. The window manager reads these when the window is mapped.
+            unsafe {
+                // Without `PPosition` most window managers ignore the position we created the
+                // window at
+                let mut size_hints: ffi::XSizeHints = mem::zeroed();
+                if placement.is_some() {
+                    size_hints.flags |= ffi::PPosition;
+                    size_hints.x = screen_region.min.x as i32;
+                    size_hints.y = screen_region.min.y as i32;
+                }
+                if !builder.resizable {
+                    size_hints.flags |= ffi::PMinSize | ffi::PMaxSize;
+                    size_hints.min_width = screen_region.width() as i32;
+                    size_hints.max_width = screen_region.width() as i32;
+                    size_hints.min_height = screen_region.height() as i32;
+                    size_hints.max_height = screen_region.height() as i32;
+                }
+                if size_hints.flags != 0 {
+                    (xlib.XSetWMNormalHints)(display, window, &mut size_hints);
+                }
+
+                let intern = |name: &[u8]| (xlib.XInternAtom)(display, name.as_ptr() as *const _, 0);
+
+                if !builder.decorations {
+                    // Flags, functions, decorations, input mode and status. See MwmUtil.h
+                    const MWM_HINTS_DECORATIONS: i64 = 1 << 1;
+                    let hints: [i64; 5] = [MWM_HINTS_DECORATIONS, 0, 0, 0, 0];
+
+                    let motif_hints = intern(b"_MOTIF_WM_HINTS\0");
+                    (xlib.XChangeProperty)(
+                        display, window,
+                        motif_hints, motif_hints, 32,
+                        ffi::PropModeReplace,
+                        hints.as_ptr() as *const u8, hints.len() as i32,
+                    );
+                }
+
+                let mut states = Vec::new();
+                if builder.always_on_top {
+                    states.push(intern(b"_NET_WM_STATE_ABOVE\0"));
+                }
+                if builder.skip_taskbar {
+                    states.push(intern(b"_NET_WM_STATE_SKIP_TASKBAR\0"));
+                    states.push(intern(b"_NET_WM_STATE_SKIP_PAGER\0"));
+                }
+
+                if !states.is_empty() {
+                    (xlib.XChangeProperty)(
+                        display, window,
+                        intern(b"_NET_WM_STATE\0"), ffi::XA_ATOM, 32,
+                        ffi::PropModeReplace,
+                        states.as_ptr() as *const u8, states.len() as i32,
+                    );
+                }
+            }
+
             // Load cursors
             let cursors = unsafe {
                 let mut cursors: [u64; CURSOR_TYPE_COUNT] = mem::uninitialized();
@@ -283,72 +1688,32 @@ mod linux {
                             CursorType::Normal    => 2,
                             CursorType::Clickable => 58, // or 60 for different hand
                             CursorType::Invisible => 0,
+                            CursorType::Custom    => 2, // Replaced in `set_cursor_image`
                         };
 
                         cursors[i] = (xlib.XCreateFontCursor)(display, cursor);
                     }
                 }
 
-                cursors
-            };
-
-            // Finish setting up OpenGL
-            // (_context is not used anywhere, hence the underscore)
-            let _context = unsafe {
-                #[allow(non_camel_case_types)]
-                type glXCreateContextAttribsARB = extern "system" fn(
-                    *mut ffi::Display,
-                    ffi::GLXFBConfig,
-                    ffi::GLXContext,
-                    i32,
-                    *const i32
-                ) -> ffi::GLXContext;
-
-                let create_fn = (glx.glXGetProcAddress)(b"glXCreateContextAttribsARB\0".as_ptr());
-
-                let context = if let Some(create_fn) = create_fn {
-                    let profile_mask = if gl_request.core {
-                        ffi::GLX_CONTEXT_CORE_PROFILE_BIT_ARB
-                    } else {
-                        ffi::GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB
-                    };
-
-                    let mut flags = 0;
-                    if gl_request.debug {
-                        flags |= ffi::GLX_CONTEXT_DEBUG_BIT_ARB;
-                    }
-                    if gl_request.forward_compatible {
-                        flags |= ffi::GLX_CONTEXT_FORWARD_COMPATIBLE_BIT_ARB;
-                    }
-
-                    let context_attributes = [
-                        ffi::GLX_CONTEXT_MAJOR_VERSION_ARB, gl_request.version.0 as i32,
-                        ffi::GLX_CONTEXT_MINOR_VERSION_ARB, gl_request.version.1 as i32,
-                        ffi::GLX_CONTEXT_FLAGS_ARB, flags,
-                        ffi::GLX_CONTEXT_PROFILE_MASK_ARB, profile_mask,
-                        0,
-                    ];
-
-                    let create_fn = mem::transmute::<_, glXCreateContextAttribsARB>(create_fn);
-
-                    create_fn(
-                        display, fb_config, 
-                        ptr::null_mut(), 1,
-                        context_attributes.as_ptr(),
-                    )
-                } else {
-                    println!("Could not use glXCreateContextAttribsARB!");
-                    (glx.glXCreateNewContext)(
-                        display, fb_config,
-                        ffi::GLX_RGBA_TYPE,
-                        ptr::null_mut(), 1
-                    )
-                };
+                cursors
+            };
 
-                if context.is_null() {
-                    panic!("Could not create GLX context for the given request: {:?}", gl_request);
+            let extensions = unsafe {
+                let raw = (glx.glXQueryExtensionsString)(display, default_screen);
+                if raw.is_null() {
+                    Vec::new()
+                } else {
+                    let string = CStr::from_ptr(raw).to_string_lossy();
+                    string.split_whitespace().map(str::to_owned).collect::<Vec<_>>()
                 }
+            };
+            let has_extension = |name: &str| extensions.iter().any(|e| e == name);
 
+            // Finish setting up OpenGL
+            let robust = gl_request.robust && has_extension("GLX_ARB_create_context_robustness");
+            let context = unsafe {
+                let context = create_context(&glx, display, fb_config, ptr::null_mut(), gl_request, robust)?;
+                guard.context = context;
                 (glx.glXMakeCurrent)(display, window, context);
                 context
             };
@@ -363,41 +1728,73 @@ mod linux {
                     (glx.glXGetProcAddress)(gl_name_buf.as_ptr()).unwrap() as *const _
                 }
             });
+
+            // `gl` does not know the name this function has in `GL_ARB_robustness`
+            if robust && !gl::GetGraphicsResetStatus::is_loaded() {
+                gl::GetGraphicsResetStatus::load_with(|_| unsafe {
+                    match (glx.glXGetProcAddress)(b"glGetGraphicsResetStatusARB\0".as_ptr()) {
+                        Some(function) => function as *const _,
+                        None => ptr::null(),
+                    }
+                });
+            }
             
             unsafe {
                 let raw = gl::GetString(gl::VERSION);
                 if raw.is_null() {
-                    panic!("glGetString(GL_VERSION) returned null!");
+                    return Err(WindowError::ContextCreationFailed(gl_request, "glGetString(GL_VERSION) returned null".to_owned()));
                 }
     //            let version = CStr::from_ptr(raw as *const _).to_string_lossy();
     //            println!("{}", version);
             }
 
-            // Vsync stuff
-            // TODO: This is not completly correct, we should be checking for extensions
-            // before retrieving the function. See https://www.khronos.org/opengl/wiki/Swap_Interval
-            // for more info.
-            let swap_function = unsafe { 
-                let function = (glx.glXGetProcAddress)(b"glXSwapIntervalEXT\0".as_ptr());
-                if let Some(function) = function {
-                    mem::transmute::<_, ffi::glXSwapIntervalEXT>(function)
-                } else {
-                    panic!(
-                        "Could not retrieve glXSwapIntervalEXT."
+            // Vsync stuff. See https://www.khronos.org/opengl/wiki/Swap_Interval
+            let get_proc_address = |name: &[u8]| unsafe { (glx.glXGetProcAddress)(name.as_ptr()) };
+
+            let swap_control = unsafe {
+                let ext = get_proc_address(b"glXSwapIntervalEXT\0");
+                let mesa = (get_proc_address(b"glXSwapIntervalMESA\0"), get_proc_address(b"glXGetSwapIntervalMESA\0"));
+
+                if let (true, Some(ext)) = (has_extension("GLX_EXT_swap_control"), ext) {
+                    SwapControl::Ext(mem::transmute::<_, ffi::glXSwapIntervalEXT>(ext))
+                } else if let (true, (Some(set), Some(get))) = (has_extension("GLX_MESA_swap_control"), mesa) {
+                    SwapControl::Mesa(
+                        mem::transmute::<_, ffi::glXSwapIntervalMESA>(set),
+                        mem::transmute::<_, ffi::glXGetSwapIntervalMESA>(get),
                     )
+                } else {
+                    SwapControl::Unsupported
                 }
             };
 
+            let adaptive_vsync_supported = match swap_control {
+                SwapControl::Ext(_) => has_extension("GLX_EXT_swap_control_tear"),
+                _ => false,
+            };
+
+            // Used for partial presents in `swap_buffers_with_damage`
+            let copy_sub_buffer = match get_proc_address(b"glXCopySubBufferMESA\0") {
+                Some(function) if has_extension("GLX_MESA_copy_sub_buffer") => unsafe {
+                    Some(mem::transmute::<_, ffi::glXCopySubBufferMESA>(function))
+                },
+                _ => None,
+            };
+
             // Disable vsync initially
-            swap_function(display, window, 0);
+            match swap_control {
+                SwapControl::Ext(swap_function) => swap_function(display, window, 0),
+                SwapControl::Mesa(swap_function, _) => { swap_function(0); },
+                SwapControl::Unsupported => {},
+            }
 
             // Create IM and IC (Input method and context)
             let im = unsafe {
                 let im = (xlib.XOpenIM)(display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
 
                 if im.is_null() {
-                    panic!("xlib::XOpenIM failed");
+                    return Err(WindowError::CreationFailed("XOpenIM failed".to_owned()));
                 }
+                guard.im = im;
                 im
             };
 
@@ -412,12 +1809,21 @@ mod linux {
                 );
 
                 if ic.is_null() {
-                    panic!("xlib::XCreateIC failed");
+                    return Err(WindowError::CreationFailed("XCreateIC failed".to_owned()));
                 }
+                guard.ic = ic;
                 ic
             };
 
-            graphics::viewport(screen_region.unpositioned());
+            graphics::load_capabilities();
+            if gl_request.debug {
+                graphics::enable_debug_output();
+            }
+            framebuffer::set_window_viewport(screen_region.unpositioned());
+
+            if builder.srgb {
+                unsafe { gl::Enable(gl::FRAMEBUFFER_SRGB) };
+            }
 
             // Listen for close events
             let wm_delete_window = unsafe {
@@ -430,16 +1836,67 @@ mod linux {
                 atom
             };
 
-            Window {
+            // Sent by `WakeHandle`
+            let wake_atom = unsafe {
+                (xlib.XInternAtom)(display, b"GONDOLA_WAKE\0".as_ptr() as *const _, 0)
+            };
+
+            // Used to maximize the window, see `set_maximized`
+            let (net_wm_state, net_wm_state_maximized) = unsafe {
+                let intern = |name: &[u8]| (xlib.XInternAtom)(display, name.as_ptr() as *const _, 0);
+
+                let state = intern(b"_NET_WM_STATE\0");
+                let maximized = [
+                    intern(b"_NET_WM_STATE_MAXIMIZED_VERT\0"),
+                    intern(b"_NET_WM_STATE_MAXIMIZED_HORZ\0"),
+                ];
+                (state, maximized)
+            };
+
+            // Optional, we fall back to core scroll events if XInput2 is not available
+            let xinput = unsafe { XInput::init(&xlib, display, window) };
+
+            // Optional, without it the scale factor does not update after the window is created
+            let xrandr = unsafe {
+                ffi::Xrandr::open().ok().and_then(|xrandr| {
+                    let (mut event_base, mut error_base) = (0, 0);
+                    if (xrandr.XRRQueryExtension)(display, &mut event_base, &mut error_base) == 0 {
+                        return None;
+                    }
+                    (xrandr.XRRSelectInput)(display, window, ffi::RRScreenChangeNotifyMask);
+                    Some((xrandr, event_base))
+                })
+            };
+
+            let scale_factor = unsafe { xft_scale_factor(&xlib, display) };
+
+            guard.disarm();
+
+            let mut window = Window {
                 xlib, glx,
                 display,
                 window,
+                context,
+                fb_config,
+                gl_request,
+                robust,
+                context_lost: false,
+                srgb: builder.srgb,
                 im,
                 ic,
                 wm_delete_window,
+                wake_atom,
+                net_wm_state,
+                net_wm_state_maximized,
                 cursors,
-                swap_function,
+                swap_control,
+                adaptive_vsync_supported,
+                copy_sub_buffer,
+                argb_visual,
                 screen_region,
+                normal_region: screen_region,
+
+                transparent: false,
 
                 close_requested: false,
                 resized: false,
@@ -448,7 +1905,16 @@ mod linux {
                 cursor: CursorType::Normal,
                 cursor_clip_region: None,
                 focused: false,
+                xinput,
+                xrandr,
+                scale_factor,
+            };
+
+            if builder.transparent {
+                window.set_transparent(true);
             }
+
+            Ok(window)
         }
 
         fn show(&mut self) {
@@ -460,7 +1926,6 @@ mod linux {
 
             self.moved = false;
             self.resized = false;
-            self.close_requested = false;
 
             // Handle events
             unsafe { while (self.xlib.XPending)(self.display) > 0 {
@@ -484,6 +1949,7 @@ mod linux {
 
                         self.focused = true;
                         input.window_has_keyboard_focus = self.focused;
+                        input.events.push(Event::FocusGained);
                     },
 
                     ffi::FocusOut => {
@@ -492,6 +1958,21 @@ mod linux {
 
                         self.focused = false;
                         input.window_has_keyboard_focus = self.focused;
+                        input.events.push(Event::FocusLost);
+                    },
+
+                    ffi::EnterNotify | ffi::LeaveNotify => {
+                        let event: ffi::XCrossingEvent = event.into();
+
+                        // Crossing events are also generated when the pointer is grabbed, without
+                        // the pointer actually moving
+                        if event.mode == ffi::NotifyGrab {
+                            continue;
+                        }
+
+                        input.received_events_this_frame = true;
+                        input.mouse_inside_window = event.type_ == ffi::EnterNotify;
+                        input.events.push(if input.mouse_inside_window { Event::MouseEnter } else { Event::MouseLeave });
                     },
 
                     ffi::KeyPress | ffi::KeyRelease => {
@@ -500,17 +1981,7 @@ mod linux {
 
                         // Normal key input
                         let scancode = event.keycode;
-
-                        let ref mut state = input.keys[scancode as usize];
-                        *state = if ty == ffi::KeyPress {
-                            if state.down() {
-                                KeyState::PressedRepeat
-                            } else {
-                                KeyState::Pressed
-                            }
-                        } else {
-                            KeyState::Released
-                        };
+                        input.update_key(scancode as u8, ty == ffi::KeyPress);
 
                         // Typing
                         if ty == ffi::KeyPress {
@@ -527,6 +1998,9 @@ mod linux {
                             if status != ffi::XBufferOverflow {
                                 let text = str::from_utf8(&buffer[..count as usize]).unwrap_or("");
                                 input.type_buffer.push_str(text);
+                                if !text.is_empty() {
+                                    input.events.push(Event::Text(text.to_owned()));
+                                }
                             } else {
                                 // Try again with a dynamic buffer
                                 let mut buffer = vec![0u8; count as usize];
@@ -539,6 +2013,9 @@ mod linux {
 
                                 let text = str::from_utf8(&buffer[..count as usize]).unwrap_or("");
                                 input.type_buffer.push_str(text);
+                                if !text.is_empty() {
+                                    input.events.push(Event::Text(text.to_owned()));
+                                }
                             }
                         }
                     },
@@ -555,20 +2032,49 @@ mod linux {
                             KeyState::Released
                         };
 
-                        match event.button {
-                            // X11 uses different button indices
-                            1 => input.mouse_keys[0] = state,
-                            2 => input.mouse_keys[2] = state,
-                            3 => input.mouse_keys[1] = state,
-                            
-                            // Scrolling
-                            4 | 5 if state == KeyState::Pressed => {
-                                let scroll = if event.button == 4 { 1.0 } else { -1.0 };
-                                input.mouse_scroll += scroll;
-                            },
-
-                            _ => {},
+                        // X11 uses different button indices
+                        let index = match event.button {
+                            1 => Some(0),
+                            2 => Some(2),
+                            3 => Some(1),
+                            _ => None,
+                        };
+
+                        if let Some(index) = index {
+                            let down = state == KeyState::Pressed;
+                            input.update_mouse_key(index, down, Time::from_ms(event.time as u64));
+                        }
+
+                        // Scrolling. 4 and 5 are the vertical wheel, 6 and 7 are horizontal.
+                        let scroll = match event.button {
+                            4 => Some(Vec2::new(0.0, 1.0)),
+                            5 => Some(Vec2::new(0.0, -1.0)),
+                            6 => Some(Vec2::new(-1.0, 0.0)),
+                            7 => Some(Vec2::new(1.0, 0.0)),
+                            _ => None,
+                        };
+                        let smooth_scroll = self.xinput.as_ref().map_or(false, XInput::smooth_scroll);
+                        if let (Some(scroll), KeyState::Pressed, false) = (scroll, state, smooth_scroll) {
+                            input.add_scroll(scroll);
+                        }
+                    },
+
+                    // XInput2 events
+                    ffi::GenericEvent => {
+                        let mut cookie: ffi::XGenericEventCookie = event.into();
+                        let xinput = match self.xinput {
+                            Some(ref mut xinput) => xinput,
+                            None => continue,
                         };
+
+                        if cookie.extension != xinput.opcode {
+                            continue;
+                        }
+                        if (self.xlib.XGetEventData)(self.display, &mut cookie) == 0 {
+                            continue;
+                        }
+                        xinput.handle_event(self.display, &cookie, input);
+                        (self.xlib.XFreeEventData)(self.display, &mut cookie);
                     },
 
                     // Mouse movement
@@ -576,6 +2082,7 @@ mod linux {
                         input.received_events_this_frame = true;
 
                         let event: ffi::XMotionEvent = event.into();
+                        let old_pos = input.mouse_pos;
 
                         let new_pos = Vec2::new(event.x, event.y).as_f32();
                         if new_pos != input.mouse_pos {
@@ -603,106 +2110,503 @@ mod linux {
                                 }
                             }
                         }
+
+                        if input.mouse_pos != old_pos {
+                            input.events.push(Event::MouseMove(input.mouse_pos));
+                        }
                     },
 
                     ffi::MappingNotify => {
                         (self.xlib.XRefreshKeyboardMapping)(event.as_mut());
                     },
 
-                    ffi::ConfigureNotify => {
-                        let event: ffi::XConfigureEvent = event.into();
+                    ffi::ConfigureNotify => {
+                        let event: ffi::XConfigureEvent = event.into();
+
+                        let pos = Vec2::new(event.x, event.y).as_f32();
+                        let size = Vec2::new(event.width, event.height).as_f32();
+
+                        let new_region = Region {
+                            min: pos,
+                            max: pos + size,
+                        };
+
+                        if new_region.min != self.screen_region.min {
+                            self.moved = true;
+                            input.events.push(Event::Moved(new_region.min));
+
+                            // The pointer is now at a different position relative to the window,
+                            // but we get no motion event for that. The cursor is clipped based
+                            // on `mouse_pos` below, so it has to be up to date.
+                            let (mut root, mut child) = (0, 0);
+                            let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+                            let mut mask = 0;
+                            let on_screen = (self.xlib.XQueryPointer)(
+                                self.display, self.window,
+                                &mut root, &mut child,
+                                &mut root_x, &mut root_y, &mut x, &mut y,
+                                &mut mask,
+                            );
+                            if on_screen != 0 {
+                                input.mouse_pos = Vec2::new(x, y).as_f32();
+                            }
+                        }
+
+                        if new_region.size() != self.screen_region.size() {
+                            self.resized = true;
+                            input.events.push(Event::Resized(new_region.size()));
+                        }
+
+                        self.screen_region = new_region;
+                        framebuffer::set_window_viewport(self.screen_region.unpositioned());
+
+                        // Window managers usually set `_NET_WM_STATE` before resizing the window, so
+                        // this skips the maximized size
+                        if !self.maximized() {
+                            self.normal_region = new_region;
+                        }
+                    },
+                    ffi::ReparentNotify => {},
+                    ffi::MapNotify => {},
+                    ffi::UnmapNotify => {}, // Sent when the window is minimized
+                    ffi::GravityNotify => {},
+
+                    ffi::ClientMessage => {
+                        let event: ffi::XClientMessageEvent = event.into();
+
+                        if event.message_type == self.wake_atom {
+                            // Only sent to return from `wait_events`
+                        } else if event.data.get_long(0) == self.wm_delete_window as i64 {
+                            self.close_requested = true;
+                            input.events.push(Event::CloseRequested);
+                        }
+                    },
+
+                    other if Some(other) == self.xrandr.as_ref().map(|&(_, base)| base + ffi::RRScreenChangeNotify) => {
+                        if let Some((ref xrandr, _)) = self.xrandr {
+                            (xrandr.XRRUpdateConfiguration)(&mut event);
+                        }
+
+                        // `monitors` opens a new connection, which sees the current `Xft.dpi`
+                        let scale_factor = self.current_monitor()
+                            .map(|monitor| monitor.scale_factor)
+                            .unwrap_or(self.scale_factor);
+                        if scale_factor != self.scale_factor {
+                            self.scale_factor = scale_factor;
+                            input.events.push(Event::ScaleFactorChanged(scale_factor));
+                        }
+                    },
+
+                    other => {
+                        panic!("Unkown X event type: {}", other);
+                    },
+                }
+            } }
+
+            // Constrain cursor if it is grabbed or clipped
+            if self.focused {
+                if self.cursor_grabbed {
+                    let center = self.screen_region.unpositioned().center().as_i32();
+                    input.mouse_pos = center.as_f32();
+
+                    unsafe {
+                        (self.xlib.XWarpPointer)(
+                            self.display, 0, self.window,
+                            0, 0, 0, 0,
+                            center.x, center.y,
+                        );
+                        (self.xlib.XFlush)(self.display);
+                    }
+                } else if let Some(region) = self.cursor_clip_region {
+                    let pos = input.mouse_pos;
+                    let new_pos = region.clip(pos);
+
+                    if pos != new_pos {
+                        input.mouse_pos = new_pos;
+
+                        unsafe {
+                            (self.xlib.XWarpPointer)(
+                                self.display, 0, self.window,
+                                0, 0, 0, 0,
+                                new_pos.x as i32, new_pos.y as i32,
+                            );
+                            (self.xlib.XFlush)(self.display);
+                        }
+                    }
+                }
+            }
+        }
+
+        fn wait_events(&mut self, input: &mut Input, timeout: Option<Time>) {
+            unsafe {
+                // `XPending` flushes our output and reads any events which have already arrived.
+                // If there are none we wait on the connection to the X server directly, as
+                // `XNextEvent` does not support timeouts.
+                if (self.xlib.XPending)(self.display) == 0 {
+                    let timeout = match timeout {
+                        Some(timeout) => Ord::min(timeout.to_ms(), i32::max_value() as u64) as i32,
+                        None => -1, // Infinite
+                    };
+
+                    let mut pollfd = ffi::pollfd {
+                        fd: (self.xlib.XConnectionNumber)(self.display),
+                        events: ffi::POLLIN,
+                        revents: 0,
+                    };
+                    ffi::poll(&mut pollfd, 1, timeout);
+                }
+            }
+
+            self.poll_events(input);
+        }
+
+        fn wake_handle(&self) -> WakeHandle {
+            let xlib = match ffi::Xlib::open() {
+                Ok(x) => x,
+                Err(err) => panic!("Could not load xlib: {:?}", err),
+            };
+
+            // We use a separate connection, so the handle stays valid even if the window is
+            // dropped before it.
+            let display = unsafe { (xlib.XOpenDisplay)(ptr::null()) };
+            if display.is_null() {
+                panic!("Could not connect to the X server");
+            }
+
+            WakeHandle {
+                xlib,
+                display,
+                window: self.window,
+                wake_atom: self.wake_atom,
+            }
+        }
+
+        fn create_shared_context(&self) -> Result<SharedContext, WindowError> {
+            let glx = match ffi::Glx::open() {
+                Ok(x) => x,
+                Err(err) => {
+                    return Err(WindowError::LibraryNotFound(format!("glx: {:?}", err)));
+                },
+            };
+
+            let xlib = match ffi::Xlib::open() {
+                Ok(x) => x,
+                Err(err) => {
+                    return Err(WindowError::LibraryNotFound(format!("xlib: {:?}", err)));
+                },
+            };
+
+            // Like `WakeHandle`, we use a separate connection so the context stays valid even if
+            // the window is dropped before it.
+            let display = unsafe { (xlib.XOpenDisplay)(ptr::null()) };
+            if display.is_null() {
+                return Err(WindowError::NoDisplay);
+            }
+
+            let context = unsafe {
+                // Configs are specific to a connection, so we look up the window's config by id
+                let mut id = 0;
+                (glx.glXGetFBConfigAttrib)(self.display, self.fb_config, ffi::GLX_FBCONFIG_ID, &mut id);
+
+                let attributes = [ffi::GLX_FBCONFIG_ID, id, 0];
+                let mut count = 0;
+                let fb_configs = (glx.glXChooseFBConfig)(
+                    display,
+                    (xlib.XDefaultScreen)(display),
+                    attributes.as_ptr(),
+                    &mut count,
+                );
+                if fb_configs.is_null() || count == 0 {
+                    (xlib.XCloseDisplay)(display);
+                    return Err(WindowError::NoMatchingFormat);
+                }
+                let fb_config = *fb_configs;
+                (xlib.XFree)(fb_configs as *mut _);
+
+                match create_context(&glx, display, fb_config, self.context, self.gl_request, self.robust) {
+                    Ok(context) => context,
+                    Err(err) => {
+                        (xlib.XCloseDisplay)(display);
+                        return Err(err);
+                    },
+                }
+            };
+
+            Ok(SharedContext {
+                xlib,
+                glx,
+                display,
+                context,
+                gl_request: self.gl_request,
+            })
+        }
+
+        fn swap_buffers(&mut self) {
+            if self.argb_visual && !self.transparent {
+                make_framebuffer_opaque();
+            }
+
+            let ref glx = self.glx;
+
+            unsafe {
+                (glx.glXSwapBuffers)(self.display, self.window);
+            }
+
+            if self.robust && !self.context_lost && graphics::context_reset_detected() {
+                self.context_lost = true;
+            }
+        }
+
+        fn swap_buffers_with_damage(&mut self, damage: &[Region]) -> bool {
+            let copy_sub_buffer = match self.copy_sub_buffer {
+                Some(function) => function,
+                None => {
+                    self.swap_buffers();
+                    return false;
+                },
+            };
+
+            if self.argb_visual && !self.transparent {
+                make_framebuffer_opaque();
+            }
+
+            // Copies from the back buffer to the front buffer, with y pointing up
+            let height = self.screen_region.height();
+            for region in damage.iter() {
+                let min = Vec2::new(region.min.x.floor(), (height - region.max.y).floor());
+                let max = Vec2::new(region.max.x.ceil(), (height - region.min.y).ceil());
+                let size = max - min;
+                if size.x > 0.0 && size.y > 0.0 {
+                    copy_sub_buffer(self.display, self.window, min.x as i32, min.y as i32, size.x as i32, size.y as i32);
+                }
+            }
+
+            if self.robust && !self.context_lost && graphics::context_reset_detected() {
+                self.context_lost = true;
+            }
+
+            true
+        }
+
+        fn partial_swap_supported(&self) -> bool {
+            self.copy_sub_buffer.is_some()
+        }
+
+        fn context_lost(&self) -> bool {
+            self.context_lost
+        }
+
+        fn recreate_context(&mut self) -> Result<(), WindowError> {
+            unsafe {
+                let context = create_context(
+                    &self.glx, self.display, self.fb_config,
+                    ptr::null_mut(), self.gl_request, self.robust,
+                )?;
+                (self.glx.glXMakeCurrent)(self.display, self.window, context);
+                (self.glx.glXDestroyContext)(self.display, self.context);
+                self.context = context;
+            }
+            self.context_lost = false;
+
+            restore_context_state(self.gl_request, self.screen_region.unpositioned(), self.srgb);
+            Ok(())
+        }
+
+        fn close_requested(&self) -> bool   { self.close_requested }
+        fn cancel_close(&mut self)          { self.close_requested = false; }
+        fn request_close(&mut self)         { self.close_requested = true; }
+        fn resized(&self) -> bool           { self.resized }
+        fn moved(&self) -> bool             { self.moved }
+        fn focused(&self) -> bool           { self.focused }
+        fn screen_region(&self) -> Region   { self.screen_region }
+        fn normal_region(&self) -> Region   { self.normal_region }
+        fn scale_factor(&self) -> f32       { self.scale_factor }
+
+        fn set_size(&mut self, size: Vec2<f32>) {
+            let size = size.as_u32();
+            unsafe {
+                (self.xlib.XResizeWindow)(self.display, self.window, size.x, size.y);
+                (self.xlib.XFlush)(self.display);
+            }
+        }
+
+        fn set_position(&mut self, pos: Vec2<f32>) {
+            let pos = pos.as_i32();
+            unsafe {
+                (self.xlib.XMoveWindow)(self.display, self.window, pos.x, pos.y);
+                (self.xlib.XFlush)(self.display);
+            }
+        }
+
+        fn maximize(&mut self) {
+            self.set_maximized(true);
+        }
+
+        fn minimize(&mut self) {
+            unsafe {
+                let screen = (self.xlib.XDefaultScreen)(self.display);
+                (self.xlib.XIconifyWindow)(self.display, self.window, screen);
+                (self.xlib.XFlush)(self.display);
+            }
+        }
+
+        fn restore(&mut self) {
+            // Mapping the window again undoes `XIconifyWindow`
+            unsafe { (self.xlib.XMapWindow)(self.display, self.window) };
+            self.set_maximized(false);
+        }
+
+        fn maximized(&self) -> bool {
+            let mut found = [false; 2];
+
+            unsafe {
+                let mut actual_type = 0;
+                let mut actual_format = 0;
+                let mut item_count = 0;
+                let mut bytes_after = 0;
+                let mut data = ptr::null_mut();
+
+                let result = (self.xlib.XGetWindowProperty)(
+                    self.display, self.window, self.net_wm_state,
+                    0, 1024, ffi::False,
+                    ffi::XA_ATOM,
+                    &mut actual_type, &mut actual_format,
+                    &mut item_count, &mut bytes_after,
+                    &mut data,
+                );
+
+                if result != ffi::Success as i32 || data.is_null() {
+                    return false;
+                }
+
+                // Format 32 properties are returned as an array of longs, regardless of the size
+                // of long on the current platform.
+                let atoms = data as *const ffi::Atom;
+                for i in 0..(item_count as isize) {
+                    let atom = *atoms.offset(i);
+                    for (j, &maximized) in self.net_wm_state_maximized.iter().enumerate() {
+                        if atom == maximized {
+                            found[j] = true;
+                        }
+                    }
+                }
+
+                (self.xlib.XFree)(data as *mut _);
+            }
 
-                        let pos = Vec2::new(event.x, event.y).as_f32();
-                        let size = Vec2::new(event.width, event.height).as_f32();
+            found[0] && found[1]
+        }
 
-                        let new_region = Region {
-                            min: pos,
-                            max: pos + size,
-                        };
+        fn key_for_char(&self, c: char) -> Option<Key> {
+            // Latin-1 keysyms are equal to their codepoints, all other unicode characters are
+            // offset. See appendix A of the X protocol specification.
+            let c = c as u64;
+            let keysym = if (c >= 0x20 && c <= 0x7e) || (c >= 0xa0 && c <= 0xff) {
+                c
+            } else {
+                0x01000000 | c
+            };
 
-                        if new_region.min != self.screen_region.min {
-                            self.moved = true;
-                        }
+            let scancode = unsafe { (self.xlib.XKeysymToKeycode)(self.display, keysym) };
+            if scancode == 0 {
+                None
+            } else {
+                scancode_to_key(scancode)
+            }
+        }
 
-                        if new_region.size() != self.screen_region.size() {
-                            self.resized = true;
-                        }
+        fn change_title(&mut self, title: &str) {
+            let title = CString::new(title).unwrap();
+            unsafe { (self.xlib.XStoreName)(self.display, self.window, title.into_raw()) };
+        }
 
-                        self.screen_region = new_region;
-                        graphics::viewport(self.screen_region.unpositioned());
-                    },
-                    ffi::ReparentNotify => {},
-                    ffi::MapNotify => {},
+        fn set_vsync_mode(&mut self, mode: VsyncMode) -> VsyncMode {
+            let mode = if mode == VsyncMode::Adaptive && !self.adaptive_vsync_supported {
+                VsyncMode::On
+            } else {
+                mode
+            };
 
-                    ffi::ClientMessage => {
-                        let event: ffi::XClientMessageEvent = event.into();
+            match self.swap_control {
+                SwapControl::Ext(swap_function) => {
+                    swap_function(self.display, self.window, mode.swap_interval());
+                },
+                SwapControl::Mesa(swap_function, _) => {
+                    // The mesa extension does not support adaptive vsync, so `mode` is never negative
+                    swap_function(mode.swap_interval() as u32);
+                },
+                SwapControl::Unsupported => {
+                    #[cfg(debug_assertions)]
+                    println!("`set_vsync_mode` called, but neither GLX_EXT_swap_control nor GLX_MESA_swap_control is supported");
+                },
+            }
 
-                        if event.data.get_long(0) == self.wm_delete_window as i64 {
-                            self.close_requested = true;
-                        }
-                    },
+            VsyncMode::from_swap_interval(self.current_swap_interval())
+        }
 
-                    other => {
-                        panic!("Unkown X event type: {}", other);
-                    },
-                }
-            } }
+        fn vsync_supported(&self) -> bool {
+            match self.swap_control {
+                SwapControl::Unsupported => false,
+                _ => true,
+            }
+        }
 
-            // Constrain cursor if it is grabbed or clipped
-            if self.focused {
-                if self.cursor_grabbed {
-                    let center = self.screen_region.unpositioned().center().as_i32();
-                    input.mouse_pos = center.as_f32();
+        fn adaptive_vsync_supported(&self) -> bool {
+            self.adaptive_vsync_supported
+        }
 
-                    unsafe {
-                        (self.xlib.XWarpPointer)(
-                            self.display, 0, self.window,
-                            0, 0, 0, 0,
-                            center.x, center.y,
-                        );
-                        (self.xlib.XFlush)(self.display);
-                    }
-                } else if let Some(region) = self.cursor_clip_region {
-                    let pos = input.mouse_pos;
-                    let new_pos = region.clip(pos);
+        fn current_swap_interval(&self) -> i32 {
+            match self.swap_control {
+                SwapControl::Ext(_) => unsafe {
+                    let mut interval = 0;
+                    (self.glx.glXQueryDrawable)(self.display, self.window, ffi::GLX_SWAP_INTERVAL_EXT, &mut interval);
 
-                    if pos != new_pos {
-                        input.mouse_pos = new_pos;
+                    let mut late_swaps_tear = 0;
+                    if self.adaptive_vsync_supported {
+                        (self.glx.glXQueryDrawable)(self.display, self.window, ffi::GLX_LATE_SWAPS_TEAR_EXT, &mut late_swaps_tear);
+                    }
 
-                        unsafe {
-                            (self.xlib.XWarpPointer)(
-                                self.display, 0, self.window,
-                                0, 0, 0, 0,
-                                new_pos.x as i32, new_pos.y as i32,
-                            );
-                            (self.xlib.XFlush)(self.display);
-                        }
+                    if late_swaps_tear != 0 {
+                        -(interval as i32)
+                    } else {
+                        interval as i32
                     }
-                }
+                },
+                SwapControl::Mesa(_, get_swap_interval) => get_swap_interval(),
+                // The initial swap interval is 1 according to the spec
+                SwapControl::Unsupported => 1,
             }
         }
 
-        fn swap_buffers(&mut self) {
-            let ref glx = self.glx;
+        fn set_transparent(&mut self, transparent: bool) -> bool {
+            if !transparent {
+                self.transparent = false;
+                return true;
+            }
 
-            unsafe {
-                (glx.glXSwapBuffers)(self.display, self.window);
+            if !self.argb_visual {
+                println!("`set_transparent` called, but no visual with an alpha channel is available");
+                return false;
             }
-        }
 
-        fn close_requested(&self) -> bool   { self.close_requested }
-        fn resized(&self) -> bool           { self.resized }
-        fn moved(&self) -> bool             { self.resized }
-        fn focused(&self) -> bool           { self.focused }
-        fn screen_region(&self) -> Region   { self.screen_region }
+            // A compositing manager owns the `_NET_WM_CM_Sn` selection, where n is the screen
+            let compositing = unsafe {
+                let screen = (self.xlib.XDefaultScreen)(self.display);
+                let name = CString::new(format!("_NET_WM_CM_S{}", screen)).unwrap();
+                let atom = (self.xlib.XInternAtom)(self.display, name.as_ptr(), 0);
+                (self.xlib.XGetSelectionOwner)(self.display, atom) != 0
+            };
+            if !compositing {
+                println!("`set_transparent` called, but no compositing window manager is running");
+                return false;
+            }
 
-        fn change_title(&mut self, title: &str) {
-            let title = CString::new(title).unwrap();
-            unsafe { (self.xlib.XStoreName)(self.display, self.window, title.into_raw()) };
+            self.transparent = true;
+            true
         }
 
-        fn set_vsync(&mut self, vsync: bool) {
-            (self.swap_function)(self.display, self.window, if vsync { 1 } else { 0 });
+        fn transparent(&self) -> bool {
+            self.transparent
         }
 
         fn set_cursor(&mut self, cursor: CursorType) {
@@ -713,6 +2617,99 @@ mod linux {
             self.internal_set_cursor(cursor);
         }
 
+        fn set_cursor_image(&mut self, pixels: &[u8], size: Vec2<u32>, hotspot: Vec2<u32>) {
+            assert_eq!(
+                pixels.len(), (size.x*size.y*4) as usize,
+                "`pixels` should contain `size.x*size.y` RGBA pixels"
+            );
+
+            // Build a one-bit image and mask. Bitmaps have the first pixel in the lowest bit, and
+            // each row is padded to a whole byte.
+            let stride = ((size.x + 7) / 8) as usize;
+            let mut source = vec![0u8; stride*size.y as usize];
+            let mut mask   = vec![0u8; stride*size.y as usize];
+
+            // Sums of r, g, b and the number of pixels
+            let mut bright = [0u32; 4];
+            let mut dark   = [0u32; 4];
+
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let i = ((y*size.x + x)*4) as usize;
+                    let r = pixels[i]   as u32;
+                    let g = pixels[i+1] as u32;
+                    let b = pixels[i+2] as u32;
+                    let a = pixels[i+3] as u32;
+
+                    if a < 128 {
+                        continue;
+                    }
+
+                    let byte = y as usize*stride + (x/8) as usize;
+                    let bit = 1 << (x % 8);
+                    mask[byte] |= bit;
+
+                    let luminance = (r*3 + g*6 + b) / 10;
+                    let sums = if luminance >= 128 {
+                        source[byte] |= bit;
+                        &mut bright
+                    } else {
+                        &mut dark
+                    };
+                    sums[0] += r; sums[1] += g; sums[2] += b; sums[3] += 1;
+                }
+            }
+
+            fn average(sums: [u32; 4]) -> ffi::XColor {
+                let count = Ord::max(sums[3], 1);
+                // XColor uses 16 bit channels
+                let red   = (sums[0] / count * 257) as u16;
+                let green = (sums[1] / count * 257) as u16;
+                let blue  = (sums[2] / count * 257) as u16;
+                ffi::XColor { pixel: 0, red, green, blue, flags: 0, pad: 0 }
+            }
+            let mut foreground = average(bright);
+            let mut background = average(dark);
+
+            unsafe {
+                let source = (self.xlib.XCreateBitmapFromData)(
+                    self.display, self.window, source.as_ptr() as *const _, size.x, size.y,
+                );
+                let mask = (self.xlib.XCreateBitmapFromData)(
+                    self.display, self.window, mask.as_ptr() as *const _, size.x, size.y,
+                );
+
+                let cursor = (self.xlib.XCreatePixmapCursor)(
+                    self.display,
+                    source, mask,
+                    &mut foreground, &mut background,
+                    hotspot.x, hotspot.y,
+                );
+
+                (self.xlib.XFreePixmap)(self.display, source);
+                (self.xlib.XFreePixmap)(self.display, mask);
+
+                let ref mut custom = self.cursors[CursorType::Custom as usize];
+                (self.xlib.XFreeCursor)(self.display, *custom);
+                *custom = cursor;
+            }
+
+            self.cursor = CursorType::Custom;
+            self.internal_set_cursor(CursorType::Custom);
+        }
+
+        fn set_cursor_position(&mut self, pos: Vec2<f32>) {
+            let pos = pos.as_i32();
+            unsafe {
+                (self.xlib.XWarpPointer)(
+                    self.display, 0, self.window,
+                    0, 0, 0, 0,
+                    pos.x, pos.y,
+                );
+                (self.xlib.XFlush)(self.display);
+            }
+        }
+
         fn clip_cursor(&mut self, region: Option<Region>) {
             self.cursor_clip_region = region;
         }
@@ -755,6 +2752,35 @@ mod linux {
                 self.cursors[cursor as usize],
             ) };
         }
+
+        // The window manager owns the maximized state, so we have to ask it to change it for us.
+        // See the EWMH spec, under `_NET_WM_STATE`.
+        fn set_maximized(&mut self, maximized: bool) {
+            unsafe {
+                let mut event = ffi::XClientMessageEvent {
+                    type_: ffi::ClientMessage,
+                    window: self.window,
+                    message_type: self.net_wm_state,
+                    format: 32,
+                    .. mem::zeroed()
+                };
+
+                let action = if maximized { 1 } else { 0 }; // _NET_WM_STATE_ADD/REMOVE
+                event.data.set_long(0, action);
+                event.data.set_long(1, self.net_wm_state_maximized[0] as i64);
+                event.data.set_long(2, self.net_wm_state_maximized[1] as i64);
+                event.data.set_long(3, 1); // Source indication: normal application
+
+                let root = (self.xlib.XDefaultRootWindow)(self.display);
+                let mut event = ffi::XEvent { client_message: event };
+                (self.xlib.XSendEvent)(
+                    self.display, root, ffi::False,
+                    ffi::SubstructureRedirectMask | ffi::SubstructureNotifyMask,
+                    &mut event,
+                );
+                (self.xlib.XFlush)(self.display);
+            }
+        }
     }
 
     impl Drop for Window {
@@ -768,6 +2794,113 @@ mod linux {
                 (xlib.XDestroyWindow)(self.display, self.window);
                 (xlib.XCloseDisplay)(self.display);
             }
+            graphics::note_context_destroyed();
+        }
+    }
+
+    /// Wakes a window from `WindowCommon::wait_events`. Created with `WindowCommon::wake_handle`.
+    pub struct WakeHandle {
+        xlib: ffi::Xlib,
+        display: *mut ffi::Display,
+        window: u64,
+        wake_atom: ffi::Atom,
+    }
+
+    // We call `XInitThreads` before opening any displays, so xlib is thread safe.
+    unsafe impl Send for WakeHandle {}
+
+    impl WakeHandle {
+        pub fn wake(&self) {
+            unsafe {
+                let event = ffi::XClientMessageEvent {
+                    type_: ffi::ClientMessage,
+                    window: self.window,
+                    message_type: self.wake_atom,
+                    format: 32,
+                    .. mem::zeroed()
+                };
+                let mut event = ffi::XEvent { client_message: event };
+
+                (self.xlib.XSendEvent)(self.display, self.window, ffi::False, 0, &mut event);
+                (self.xlib.XFlush)(self.display);
+            }
+        }
+    }
+
+    impl Drop for WakeHandle {
+        fn drop(&mut self) {
+            unsafe { (self.xlib.XCloseDisplay)(self.display) };
+        }
+    }
+
+    /// A OpenGL context which shares objects with the context of a window. Created with
+    /// `WindowCommon::create_shared_context`.
+    pub struct SharedContext {
+        xlib: ffi::Xlib,
+        glx: ffi::Glx,
+        display: *mut ffi::Display,
+        context: ffi::GLXContext,
+        gl_request: GlRequest,
+    }
+
+    // We call `XInitThreads` before opening any displays, so xlib is thread safe.
+    unsafe impl Send for SharedContext {}
+
+    impl SharedContext {
+        /// Makes this context current on the calling thread. A context can only be current on a
+        /// single thread at a time.
+        pub fn make_current(&self) -> Result<(), WindowError> {
+            // Contexts created with glXCreateContextAttribsARB for OpenGL 3.0 and later can be
+            // made current without a drawable
+            let result = unsafe { (self.glx.glXMakeContextCurrent)(self.display, 0, 0, self.context) };
+            if result != 0 {
+                Ok(())
+            } else {
+                Err(WindowError::ContextCreationFailed(self.gl_request, "glXMakeContextCurrent failed".to_owned()))
+            }
+        }
+
+        /// Makes no context current on the calling thread.
+        pub fn release(&self) {
+            unsafe { (self.glx.glXMakeContextCurrent)(self.display, 0, 0, ptr::null_mut()) };
+        }
+    }
+
+    impl Drop for SharedContext {
+        fn drop(&mut self) {
+            unsafe {
+                (self.glx.glXDestroyContext)(self.display, self.context);
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+
+    // With a 32 bit visual, compositors blend the window with whatever is behind it. We only use
+    // such visuals for windows created as transparent, but if transparency is disabled later on we
+    // set the alpha of the default framebuffer to one before swapping, so translucent geometry does
+    // not let the desktop show through.
+    fn make_framebuffer_opaque() {
+        unsafe {
+            let mut framebuffer = 0;
+            let mut color_mask = [0u8; 4];
+            let mut clear_color = [0.0f32; 4];
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut framebuffer);
+            gl::GetBooleanv(gl::COLOR_WRITEMASK, color_mask.as_mut_ptr());
+            gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+            let scissor = gl::IsEnabled(gl::SCISSOR_TEST) == gl::TRUE;
+
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::TRUE);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer as u32);
+            if scissor {
+                gl::Enable(gl::SCISSOR_TEST);
+            }
+            gl::ColorMask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+            gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
         }
     }
 
@@ -800,8 +2933,10 @@ mod windows {
     use std::mem;
     use std::char;
     use std::sync::mpsc;
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
     use std::ffi::CStr;
+    #[cfg(feature = "gamepad")]
+    use std::time::{Instant, Duration};
 
     use gl;
 
@@ -832,18 +2967,96 @@ mod windows {
 
         pub(super) const WGL_CONTEXT_CORE_PROFILE_BIT_ARB: i32 = 0x00000001;
         pub(super) const WGL_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: i32 = 0x00000002;
+        pub(super) const WGL_CONTEXT_ES2_PROFILE_BIT_EXT: i32 = 0x00000004;
+
+        // From WGL_ARB_create_context_robustness
+        pub(super) const WGL_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB: i32 = 0x8256;
+        pub(super) const WGL_LOSE_CONTEXT_ON_RESET_ARB: i32 = 0x8252;
 
         pub(super) type wglCreateContextAttribsARBType = extern "system" fn(HDC, HGLRC, *const i32) -> HGLRC;
         pub(super) type wglGetExtensionsStringARBType = extern "system" fn(HDC) -> *const i8;
         pub(super) type wglSwapIntervalEXTType = extern "system" fn(i32) -> i32;
+        pub(super) type wglGetSwapIntervalEXTType = extern "system" fn() -> i32;
+
+        // From WGL_ARB_pixel_format and WGL_ARB_pbuffer, used for shared contexts
+        pub(super) const WGL_DRAW_TO_PBUFFER_ARB: i32 = 0x202d;
+        pub(super) const WGL_SUPPORT_OPENGL_ARB: i32 = 0x2010;
+        pub(super) const WGL_PIXEL_TYPE_ARB: i32 = 0x2013;
+        pub(super) const WGL_TYPE_RGBA_ARB: i32 = 0x202b;
+        pub(super) const WGL_COLOR_BITS_ARB: i32 = 0x2014;
+        pub(super) const WGL_ALPHA_BITS_ARB: i32 = 0x201b;
+
+        pub(super) type HPBUFFERARB = HANDLE;
+
+        pub(super) type wglChoosePixelFormatARBType = extern "system" fn(HDC, *const i32, *const f32, UINT, *mut i32, *mut UINT) -> BOOL;
+        pub(super) type wglCreatePbufferARBType = extern "system" fn(HDC, i32, i32, i32, *const i32) -> HPBUFFERARB;
+        pub(super) type wglGetPbufferDCARBType = extern "system" fn(HPBUFFERARB) -> HDC;
+        pub(super) type wglReleasePbufferDCARBType = extern "system" fn(HPBUFFERARB, HDC) -> i32;
+        pub(super) type wglDestroyPbufferARBType = extern "system" fn(HPBUFFERARB) -> BOOL;
+
+        // From dwmapi.h, used for transparent windows
+        pub(super) const DWM_BB_ENABLE: DWORD = 0x00000001;
+        pub(super) const DWM_BB_BLURREGION: DWORD = 0x00000002;
+
+        #[link(name = "dwmapi")]
+        extern "system" {
+            pub(super) fn DwmEnableBlurBehindWindow(window: HWND, blur_behind: *const DWM_BLURBEHIND) -> HRESULT;
+            pub(super) fn DwmIsCompositionEnabled(enabled: *mut BOOL) -> HRESULT;
+        }
+
+        // From winuser.h, used for touch gestures. winapi declares `WM_GESTURE`, but not the
+        // rest of the gesture api.
+        pub(super) type HGESTUREINFO = HANDLE;
+
+        pub(super) const GID_ZOOM: DWORD = 3;
+        pub(super) const GF_BEGIN: DWORD = 0x00000001;
+
+        #[repr(C)]
+        #[allow(non_snake_case)]
+        pub(super) struct GESTUREINFO {
+            pub cbSize: UINT,
+            pub dwFlags: DWORD,
+            pub dwID: DWORD,
+            pub hwndTarget: HWND,
+            pub ptsLocation: [i16; 2], // POINTS
+            pub dwInstanceID: DWORD,
+            pub dwSequenceID: DWORD,
+            pub ullArguments: u64,
+            pub cbExtraArgs: UINT,
+        }
+
+        // From winuser.h, used for per monitor dpi awareness. The functions are loaded at runtime
+        // with `user32_proc`, as they are missing before windows 10.
+        pub(super) const WM_DPICHANGED: UINT = 0x02e0;
+        pub(super) const DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2: isize = -4;
+
+        pub(super) type SetProcessDpiAwarenessContextType = extern "system" fn(HANDLE) -> BOOL;
+        pub(super) type GetDpiForWindowType = extern "system" fn(HWND) -> UINT;
+
+        #[link(name = "user32")]
+        extern "system" {
+            pub(super) fn GetGestureInfo(info_handle: HGESTUREINFO, info: *mut GESTUREINFO) -> BOOL;
+            pub(super) fn CloseGestureInfoHandle(info_handle: HGESTUREINFO) -> BOOL;
+        }
     }
 
     pub struct Window {
         raw_event_receiver: mpsc::Receiver<RawEvent>,
         device_context: ffi::HDC,
         gl_context: ffi::HGLRC,
+        // Used to create shared contexts with the same attributes as `gl_context`. `None` for
+        // legacy contexts.
+        shared_context_attributes: Option<(ffi::wglCreateContextAttribsARBType, [i32; 11])>,
+        // Used to give shared contexts their own device context. `None` if the driver does not
+        // support pbuffers.
+        pbuffer_functions: Option<PbufferFunctions>,
+        gl_request: GlRequest,
+        robust: bool,
+        context_lost: bool,
         window: ffi::HWND,
-        swap_function: Option<ffi::wglSwapIntervalEXTType>,
+        swap_function: Option<(ffi::wglSwapIntervalEXTType, ffi::wglGetSwapIntervalEXTType)>,
+        adaptive_vsync_supported: bool,
+        transparent: bool,
         cursors: [ffi::HCURSOR; CURSOR_TYPE_COUNT],
 
         screen_region: Region,
@@ -856,17 +3069,28 @@ mod windows {
         cursor_captured: bool, // Cursor is dragging something out of the window, don't loose focus on release
         cursor_grabbed: bool, // Cursor cant leave window
         cursor_clip_region: Option<Region>, // Relative to `screen_region.min`!
+        scale_factor: f32,
 
         #[cfg(feature = "gamepad")]
         gamepad_states: [InternalGamepadState; 4],
+        // Checking for disconnected gamepads is slow, so we only do it when windows tells us that
+        // devices changed, or every `GAMEPAD_SCAN_INTERVAL` in case we missed that
+        #[cfg(feature = "gamepad")]
+        gamepad_scan_requested: bool,
+        #[cfg(feature = "gamepad")]
+        last_gamepad_scan: Option<Instant>,
     }
 
+    #[cfg(feature = "gamepad")]
+    const GAMEPAD_SCAN_INTERVAL: Duration = Duration::from_secs(3);
+
     #[cfg(feature = "gamepad")]
     #[derive(Copy, Clone)]
     struct InternalGamepadState {
         connected: bool,
         last_packet_number: u32,
         xinput_state: ffi::XINPUT_STATE,
+        rumble_end: Option<Instant>,
     }
 
     #[cfg(feature = "gamepad")]
@@ -876,6 +3100,7 @@ mod windows {
                 connected: false,
                 last_packet_number: 0,
                 xinput_state: unsafe { mem::zeroed() },
+                rumble_end: None,
             }
         }
     }
@@ -892,27 +3117,147 @@ mod windows {
 
     fn last_win_error() -> u32 { unsafe { ffi::GetLastError() } }
 
+    // Finds a function in user32.dll which might not exist on older versions of windows
+    unsafe fn user32_proc(name: &[u8]) -> Option<ffi::FARPROC> {
+        let user32 = ffi::GetModuleHandleA(b"user32.dll\0".as_ptr() as *const i8);
+        if user32.is_null() {
+            return None;
+        }
+        let address = kernel32::GetProcAddress(user32, name.as_ptr() as *const i8);
+        if address.is_null() { None } else { Some(address) }
+    }
+
+    // The dpi of the monitor the window is on with per monitor dpi awareness, or the system dpi
+    // otherwise
+    unsafe fn window_scale_factor(window: ffi::HWND) -> f32 {
+        if let Some(get_dpi_for_window) = user32_proc(b"GetDpiForWindow\0") {
+            let get_dpi_for_window: ffi::GetDpiForWindowType = mem::transmute(get_dpi_for_window);
+            let dpi = get_dpi_for_window(window);
+            if dpi != 0 {
+                return dpi as f32 / 96.0;
+            }
+        }
+
+        let dc = ffi::GetDC(window);
+        if dc.is_null() {
+            return 1.0;
+        }
+        let dpi = ffi::GetDeviceCaps(dc, ffi::LOGPIXELSX);
+        ffi::ReleaseDC(window, dc);
+        dpi as f32 / 96.0
+    }
+
+    /// Lists the monitors connected to the computer, with the primary monitor first.
+    pub fn monitors() -> Vec<Monitor> {
+        unsafe extern "system"
+        fn callback(monitor: ffi::HMONITOR, _: ffi::HDC, _: ffi::LPRECT, data: ffi::LPARAM) -> ffi::BOOL {
+            let monitors = &mut *(data as *mut Vec<Monitor>);
+
+            let mut info: ffi::MONITORINFOEXW = mem::zeroed();
+            info.cbSize = mem::size_of::<ffi::MONITORINFOEXW>() as ffi::DWORD;
+            if ffi::GetMonitorInfoW(monitor, &mut info as *mut _ as ffi::LPMONITORINFO) == 0 {
+                return ffi::TRUE;
+            }
+
+            let name_len = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+            let name = String::from_utf16_lossy(&info.szDevice[..name_len]);
+
+            let mut mode: ffi::DEVMODEW = mem::zeroed();
+            mode.dmSize = mem::size_of::<ffi::DEVMODEW>() as ffi::WORD;
+            let has_mode = ffi::EnumDisplaySettingsW(info.szDevice.as_ptr(), ffi::ENUM_CURRENT_SETTINGS, &mut mode) != 0;
+            // 0 and 1 mean that the hardware default is used
+            let refresh_rate = if has_mode && mode.dmDisplayFrequency > 1 {
+                Some(mode.dmDisplayFrequency as f32)
+            } else {
+                None
+            };
+
+            let dc = ffi::CreateDCW(info.szDevice.as_ptr(), info.szDevice.as_ptr(), ptr::null(), ptr::null());
+            let scale_factor = if dc.is_null() {
+                1.0
+            } else {
+                let dpi = ffi::GetDeviceCaps(dc, ffi::LOGPIXELSX);
+                ffi::DeleteDC(dc);
+                dpi as f32 / 96.0
+            };
+
+            let rect = info.rcMonitor;
+            monitors.push(Monitor {
+                id: monitor as u64,
+                name,
+                region: Region {
+                    min: Vec2::new(rect.left, rect.top).as_f32(),
+                    max: Vec2::new(rect.right, rect.bottom).as_f32(),
+                },
+                scale_factor,
+                refresh_rate,
+                primary: info.dwFlags & ffi::MONITORINFOF_PRIMARY != 0,
+            });
+
+            ffi::TRUE
+        }
+
+        let mut monitors: Vec<Monitor> = Vec::new();
+        unsafe {
+            ffi::EnumDisplayMonitors(
+                ptr::null_mut(), ptr::null(),
+                Some(callback),
+                &mut monitors as *mut _ as ffi::LPARAM,
+            );
+        }
+
+        monitors.sort_by_key(|monitor| !monitor.primary);
+        monitors
+    }
+
+    /// Shows a modal dialog with the given error message, and blocks until the user closes it.
+    /// This is intended for fatal errors, e.g. when no OpenGL context can be created. It does not
+    /// require a window or an OpenGL context.
+    pub fn show_error_dialog(title: &str, message: &str) {
+        let title = encode_wide(title);
+        let message = encode_wide(message);
+
+        unsafe {
+            ffi::MessageBoxW(
+                ptr::null_mut(),
+                message.as_ptr(), title.as_ptr(),
+                ffi::MB_OK | ffi::MB_ICONERROR,
+            );
+        }
+    }
+
     #[derive(Debug, Copy, Clone)]
     enum RawEvent {
         MoveOrSize,
         CloseRequest,
         Key(bool, usize),
         Char(u16),
-        Scroll(f32),
+        Scroll(Vec2<f32>),
+        Zoom(f32),
         MousePos(Vec2<f32>),
+        MouseLeave,
         MouseDelta(Vec2<f32>),
-        MouseButton(bool, usize),
+        MouseButton(bool, usize, i32), // Down, index, message time
+        DevicesChanged,
+        ScaleFactorChanged(f32),
     }
 
     thread_local! {
         static MSG_SENDER: RefCell<Option<mpsc::Sender<RawEvent>>> = RefCell::new(None);
+        // Whether we have requested a `WM_MOUSELEAVE` message. This has to be requested again
+        // each time the mouse enters the window.
+        static TRACKING_MOUSE: Cell<bool> = Cell::new(false);
+        // The distance between the fingers in the last zoom gesture message
+        static ZOOM_DISTANCE: Cell<u64> = Cell::new(0);
     }
 
     // This is WNDPROC
     unsafe extern "system" 
     fn event_callback(window: ffi::HWND, msg: u32, w: ffi::WPARAM, l: ffi::LPARAM) -> ffi::LRESULT {
         let maybe_event = match msg {
-            ffi::WM_SIZE | ffi::WM_MOVE => {
+            // Dragging or resizing the window resets the cursor clip once done, so we also treat
+            // `WM_EXITSIZEMOVE` as a move to apply the clip again
+            ffi::WM_SIZE | ffi::WM_MOVE | ffi::WM_EXITSIZEMOVE => {
                 Some(RawEvent::MoveOrSize)
             },
 
@@ -935,16 +3280,63 @@ mod windows {
 
             ffi::WM_MOUSEWHEEL => {
                 let delta = ffi::GET_WHEEL_DELTA_WPARAM(w) as f32 / ffi::WHEEL_DELTA as f32;
-                Some(RawEvent::Scroll(delta))
+                Some(RawEvent::Scroll(Vec2::new(0.0, delta)))
+            },
+
+            // Positive values are to the right, same as for us
+            ffi::WM_MOUSEHWHEEL => {
+                let delta = ffi::GET_WHEEL_DELTA_WPARAM(w) as f32 / ffi::WHEEL_DELTA as f32;
+                Some(RawEvent::Scroll(Vec2::new(delta, 0.0)))
+            },
+
+            // Pinching on touch screens. Precision touchpads instead send `WM_MOUSEWHEEL` with
+            // control held down.
+            ffi::WM_GESTURE => {
+                let handle = l as ffi::HGESTUREINFO;
+                let mut info = mem::zeroed::<ffi::GESTUREINFO>();
+                info.cbSize = mem::size_of::<ffi::GESTUREINFO>() as u32;
+
+                if ffi::GetGestureInfo(handle, &mut info) == 0 || info.dwID != ffi::GID_ZOOM {
+                    // Also closes the handle
+                    return ffi::DefWindowProcW(window, msg, w, l);
+                }
+                ffi::CloseGestureInfoHandle(handle);
+
+                let distance = info.ullArguments & 0xffffffff;
+                let previous = ZOOM_DISTANCE.with(|d| d.replace(distance));
+
+                if info.dwFlags & ffi::GF_BEGIN != 0 || previous == 0 || distance == 0 {
+                    None
+                } else {
+                    Some(RawEvent::Zoom(distance as f32 / previous as f32))
+                }
             },
 
             ffi::WM_MOUSEMOVE => {
                 let x = ffi::GET_X_LPARAM(l);
                 let y = ffi::GET_Y_LPARAM(l);
                 let pos = Vec2::new(x, y).as_f32();
+
+                if !TRACKING_MOUSE.with(Cell::get) {
+                    let mut track = ffi::TRACKMOUSEEVENT {
+                        cbSize: mem::size_of::<ffi::TRACKMOUSEEVENT>() as u32,
+                        dwFlags: ffi::TME_LEAVE,
+                        hwndTrack: window,
+                        dwHoverTime: 0,
+                    };
+                    if ffi::TrackMouseEvent(&mut track) != ffi::FALSE {
+                        TRACKING_MOUSE.with(|t| t.set(true));
+                    }
+                }
+
                 Some(RawEvent::MousePos(pos))
             },
 
+            ffi::WM_MOUSELEAVE => {
+                TRACKING_MOUSE.with(|t| t.set(false));
+                Some(RawEvent::MouseLeave)
+            },
+
             ffi::WM_INPUT => {
                 let mut bytes = [0u8; 48];
                 let mut size = bytes.len() as u32;
@@ -968,12 +3360,30 @@ mod windows {
                 }
             },
 
-            ffi::WM_LBUTTONDOWN => Some(RawEvent::MouseButton(true, 0)),
-            ffi::WM_LBUTTONUP   => Some(RawEvent::MouseButton(false, 0)),
-            ffi::WM_MBUTTONDOWN => Some(RawEvent::MouseButton(true, 2)),
-            ffi::WM_MBUTTONUP   => Some(RawEvent::MouseButton(false, 2)),
-            ffi::WM_RBUTTONDOWN => Some(RawEvent::MouseButton(true, 1)),
-            ffi::WM_RBUTTONUP   => Some(RawEvent::MouseButton(false, 1)),
+            ffi::WM_LBUTTONDOWN => Some(RawEvent::MouseButton(true, 0, ffi::GetMessageTime())),
+            ffi::WM_LBUTTONUP   => Some(RawEvent::MouseButton(false, 0, ffi::GetMessageTime())),
+            ffi::WM_MBUTTONDOWN => Some(RawEvent::MouseButton(true, 2, ffi::GetMessageTime())),
+            ffi::WM_MBUTTONUP   => Some(RawEvent::MouseButton(false, 2, ffi::GetMessageTime())),
+            ffi::WM_RBUTTONDOWN => Some(RawEvent::MouseButton(true, 1, ffi::GetMessageTime())),
+            ffi::WM_RBUTTONUP   => Some(RawEvent::MouseButton(false, 1, ffi::GetMessageTime())),
+
+            // Sent to all top level windows when a device is plugged in or unplugged
+            ffi::WM_DEVICECHANGE => Some(RawEvent::DevicesChanged),
+
+            // Sent when the window moves to a monitor with a different dpi. `l` points to the
+            // window rect windows suggests for the new dpi, which keeps the apparent size of the
+            // window the same. Resizing sends `WM_SIZE`, so `screen_region` is updated normally.
+            ffi::WM_DPICHANGED => {
+                let dpi = (w & 0xffff) as u32;
+                let rect = &*(l as *const ffi::RECT);
+                ffi::SetWindowPos(
+                    window, ptr::null_mut(),
+                    rect.left, rect.top,
+                    rect.right - rect.left, rect.bottom - rect.top,
+                    ffi::SWP_NOZORDER | ffi::SWP_NOACTIVATE,
+                );
+                Some(RawEvent::ScaleFactorChanged(dpi as f32 / 96.0))
+            },
 
             _ => return ffi::DefWindowProcW(window, msg, w, l), // Maybe we don't need this
         };
@@ -992,13 +3402,13 @@ mod windows {
     }
 
     impl WindowCommon for Window {
-        fn new(title: &str) -> Window {
-            let gl_request = GlRequest::default();
+        fn from_builder(builder: &WindowBuilder) -> Result<Window, WindowError> {
+            let gl_request = builder.gl;
 
             let instance = unsafe { ffi::GetModuleHandleW(ptr::null()) };
 
             let class_name = encode_wide("My windows class is great");
-            let window_name = encode_wide(title);
+            let window_name = encode_wide(&builder.title);
 
             let window_class = ffi::WNDCLASSW {
                 style:          ffi::CS_OWNDC,
@@ -1011,9 +3421,10 @@ mod windows {
                 .. unsafe { mem::zeroed() }
             };
 
+            // The class is still registered if a previous attempt to create a window failed
             let window_class_atom = unsafe { ffi::RegisterClassW(&window_class) };
-            if window_class_atom == 0 {
-                panic!("Failed to register window class");
+            if window_class_atom == 0 && last_win_error() != ffi::ERROR_CLASS_ALREADY_EXISTS {
+                return Err(WindowError::CreationFailed(format!("RegisterClassW failed: {}", last_win_error())));
             }
 
             let (raw_event_sender, raw_event_receiver) = mpsc::channel();
@@ -1027,6 +3438,12 @@ mod windows {
                 *sender = Some(raw_event_sender);
             });
 
+            let mut guard = CreationGuard {
+                window: ptr::null_mut(),
+                device_context: ptr::null_mut(),
+                gl_context: ptr::null_mut(),
+            };
+
             // Load cursors
             let cursors = unsafe {
                 let mut cursors = [ptr::null_mut(); CURSOR_TYPE_COUNT];
@@ -1035,24 +3452,73 @@ mod windows {
                         CursorType::Normal    => ffi::IDC_ARROW,
                         CursorType::Clickable => ffi::IDC_HAND,
                         CursorType::Invisible => continue,
+                        CursorType::Custom    => ffi::IDC_ARROW, // Replaced in `set_cursor_image`
                     };
                     cursors[i] = ffi::LoadCursorW(ptr::null_mut(), cursor);
                 }
                 cursors
             };
 
+            let mut ex_style = 0;
+            if builder.always_on_top {
+                ex_style |= ffi::WS_EX_TOPMOST;
+            }
+            if builder.skip_taskbar {
+                ex_style |= ffi::WS_EX_TOOLWINDOW;
+            }
+
+            let mut style = if builder.decorations { ffi::WS_OVERLAPPEDWINDOW } else { ffi::WS_POPUP };
+            if !builder.resizable {
+                style &= !(ffi::WS_THICKFRAME | ffi::WS_MAXIMIZEBOX);
+            }
+
+            if builder.srgb || builder.samples > 1 {
+                return Err(WindowError::NoMatchingFormat);
+            }
+
+            // `builder.size` and `builder.position` refer to the client area, so we add the size
+            // of the border. `outer.left` and `outer.top` are the offsets from the client area to
+            // the outside of the border.
+            let outer = unsafe {
+                let mut rect = ffi::RECT {
+                    left: 0, top: 0,
+                    right: builder.size.x as i32, bottom: builder.size.y as i32,
+                };
+                ffi::AdjustWindowRectEx(&mut rect, style, ffi::FALSE, ex_style);
+                rect
+            };
+            let size = (outer.right - outer.left, outer.bottom - outer.top);
+
+            // `CW_USEDEFAULT` is only valid for overlapped windows, so popups need an explicit position
+            let position = match builder.placement() {
+                Some(position) => (position.x as i32 + outer.left, position.y as i32 + outer.top),
+                None if builder.decorations => (ffi::CW_USEDEFAULT, ffi::CW_USEDEFAULT),
+                None => (100, 100),
+            };
+
+            // Without per monitor dpi awareness windows scales our window as a bitmap instead of
+            // sending `WM_DPICHANGED`. This is a process wide setting, and fails if it has already
+            // been set, e.g. through a manifest, which is fine.
+            if builder.per_monitor_dpi {
+                unsafe {
+                    if let Some(set_awareness) = user32_proc(b"SetProcessDpiAwarenessContext\0") {
+                        let set_awareness: ffi::SetProcessDpiAwarenessContextType = mem::transmute(set_awareness);
+                        set_awareness(ffi::DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2 as ffi::HANDLE);
+                    }
+                }
+            }
+
             // Actually create window 
             let window = unsafe { ffi::CreateWindowExW(
-                // Extended style
-                0, 
+                ex_style,
 
                 class_name.as_ptr(),
                 window_name.as_ptr(),
 
-                ffi::WS_OVERLAPPEDWINDOW,
+                style,
 
-                ffi::CW_USEDEFAULT, ffi::CW_USEDEFAULT,
-                ffi::CW_USEDEFAULT, ffi::CW_USEDEFAULT,
+                position.0, position.1,
+                size.0, size.1,
 
                 ptr::null_mut(), // Parent
                 ptr::null_mut(), // Menu
@@ -1060,22 +3526,31 @@ mod windows {
                 ptr::null_mut(), // lParam
             ) };
             if window.is_null() {
-                panic!("Failed to create window");
+                return Err(WindowError::CreationFailed(format!("CreateWindowExW failed: {}", last_win_error())));
             } 
+            guard.window = window;
 
+            // Like in `poll_events`, the screen region is the client area
             let region = unsafe {
                 let mut rect = new_rect();
-                if ffi::GetWindowRect(window, &mut rect) == 0 {
-                    panic!("GetWindowRect failed: {}", last_win_error());
+                if ffi::GetClientRect(window, &mut rect) == 0 {
+                    return Err(WindowError::CreationFailed(format!("GetClientRect failed: {}", last_win_error())));
                 }
 
+                let mut min = ffi::POINT { x: rect.left,  y: rect.top };
+                let mut max = ffi::POINT { x: rect.right, y: rect.bottom };
+                ffi::ClientToScreen(window, &mut min);
+                ffi::ClientToScreen(window, &mut max);
+
                 Region {
-                    min: Vec2::new(rect.left, rect.top).as_f32(),
-                    max: Vec2::new(rect.right, rect.bottom).as_f32(),
+                    min: Vec2::new(min.x, min.y).as_f32(),
+                    max: Vec2::new(max.x, max.y).as_f32(),
                 }
             };
 
             let device_context = unsafe { ffi::GetDC(window) };
+            guard.device_context = device_context;
+            let scale_factor = unsafe { window_scale_factor(window) };
 
             // Set up raw input
             let raw_mouse_device = ffi::RAWINPUTDEVICE {
@@ -1093,7 +3568,7 @@ mod windows {
             let mut pixel_format_descriptor = ffi::PIXELFORMATDESCRIPTOR {
                 nSize: mem::size_of::<ffi::PIXELFORMATDESCRIPTOR>() as u16,
                 nVersion: 1,
-                dwFlags: ffi::PFD_DRAW_TO_WINDOW | ffi::PFD_SUPPORT_OPENGL | ffi::PFD_DOUBLEBUFFER,
+                dwFlags: ffi::PFD_DRAW_TO_WINDOW | ffi::PFD_SUPPORT_OPENGL | ffi::PFD_DOUBLEBUFFER | ffi::PFD_SUPPORT_COMPOSITION,
                 iPixelType: ffi::PFD_TYPE_RGBA,
                 cColorBits: 24,
                 cAlphaBits: 8,
@@ -1107,7 +3582,7 @@ mod windows {
                 let result = ffi::SetPixelFormat(device_context, i, &mut pixel_format_descriptor);
 
                 if result == ffi::FALSE {
-                    panic!("Failed to set pixel format");
+                    return Err(WindowError::NoMatchingFormat);
                 }
             };
 
@@ -1115,7 +3590,7 @@ mod windows {
             let library_name = b"opengl32.dll\0";
             let gl32_lib = unsafe { ffi::LoadLibraryA(library_name.as_ptr() as *const i8) };
             if gl32_lib.is_null() {
-                panic!("Could not load opengl32.dll: {}", last_win_error());
+                return Err(WindowError::LibraryNotFound(format!("opengl32.dll: {}", last_win_error())));
             }
 
             // Set up opengl context
@@ -1124,6 +3599,7 @@ mod windows {
                 ffi::wglMakeCurrent(device_context, c);
                 c
             };
+            guard.gl_context = legacy_gl_context;
 
             let mut gl_name_buf = Vec::with_capacity(500);
             let mut get_proc_address = |name: &str| { 
@@ -1152,7 +3628,7 @@ mod windows {
             let wglGetExtensionsStringARB = unsafe {
                 let p = get_proc_address("wglGetExtensionsStringARB");
                 if p.is_null() {
-                    panic!("WGL_ARB_extensions_string is not supported. Can not create a gl context");
+                    return Err(WindowError::MissingExtension("WGL_ARB_extensions_string".to_owned()));
                 }
                 mem::transmute::<_, ffi::wglGetExtensionsStringARBType>(p)
             };
@@ -1173,8 +3649,10 @@ mod windows {
                 false
             };
 
-            let gl_context = if gl_request.version.0 < 3 {
-                legacy_gl_context
+            let robust = gl_request.robust && has_extension("WGL_ARB_create_context_robustness");
+
+            let (gl_context, shared_context_attributes) = if gl_request.version.0 < 3 && !gl_request.gles {
+                (legacy_gl_context, None)
 
                     // Set up modern OpenGL
             } else {
@@ -1184,7 +3662,7 @@ mod windows {
                 ];
                 for name in required_extensions.iter() {
                     if !has_extension(name) {
-                        panic!("{} is not supported. Can not create a gl 3+ context", name);
+                        return Err(WindowError::MissingExtension(name.to_string()));
                     }
                 }
 
@@ -1192,10 +3670,7 @@ mod windows {
                 let wglCreateContextAttribsARB = unsafe {
                     let p = get_proc_address("wglCreateContextAttribsARB");
                     if p.is_null() {
-                        panic!(
-                            "wglCreateContextAttribsARB is not present, although the required \
-                            extensions are supported. Your drivers/the spec suck"
-                            );
+                        return Err(WindowError::MissingExtension("WGL_ARB_create_context".to_owned()));
                     }
                     mem::transmute::<_, ffi::wglCreateContextAttribsARBType>(p)
                 };
@@ -1208,17 +3683,31 @@ mod windows {
                     flags |= ffi::WGL_CONTEXT_FORWARD_COMPATIBLE_BIT_ARB;
                 }
 
-                let profile_mask = if gl_request.core {
+                if gl_request.gles && !has_extension("WGL_EXT_create_context_es2_profile") {
+                    return Err(WindowError::MissingExtension("WGL_EXT_create_context_es2_profile".to_owned()));
+                }
+
+                let profile_mask = if gl_request.gles {
+                    ffi::WGL_CONTEXT_ES2_PROFILE_BIT_EXT
+                } else if gl_request.core {
                     ffi::WGL_CONTEXT_CORE_PROFILE_BIT_ARB
                 } else {
                     ffi::WGL_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB
                 };
 
+                // Shared contexts need the same reset strategy as the context they share with
+                let (reset_strategy_name, reset_strategy) = if robust {
+                    (ffi::WGL_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB, ffi::WGL_LOSE_CONTEXT_ON_RESET_ARB)
+                } else {
+                    (0, 0) // Ends the list early
+                };
+
                 let context_attributes = [
                     ffi::WGL_CONTEXT_MAJOR_VERSION_ARB, gl_request.version.0 as i32,
                     ffi::WGL_CONTEXT_MINOR_VERSION_ARB, gl_request.version.1 as i32,
                     ffi::WGL_CONTEXT_FLAGS_ARB, flags,
                     ffi::WGL_CONTEXT_PROFILE_MASK_ARB, profile_mask,
+                    reset_strategy_name, reset_strategy,
                     0,
                 ];
 
@@ -1230,22 +3719,12 @@ mod windows {
 
                 if gl_context.is_null() {
                     let last_error = last_win_error();
-                    match last_error {
-                        ffi::ERROR_INVALID_VERSION_ARB => panic!(
-                            "Could not create GL context. Invalid version: ({}.{} {})",
-                            gl_request.version.0, gl_request.version.1,
-                            if gl_request.core { "core" } else { "compat" },
-                            ),
-                        ffi::ERROR_INVALID_PROFILE_ARB => panic!(
-                            "Could not create GL context. Invalid profile: ({}.{} {})",
-                            gl_request.version.0, gl_request.version.1,
-                            if gl_request.core { "core" } else { "compat" },
-                            ),
-                        _ => panic!(
-                            "Could not create GL context. Unkown error: {}",
-                            last_error,
-                            ),
+                    let reason = match last_error {
+                        ffi::ERROR_INVALID_VERSION_ARB => "Invalid version".to_owned(),
+                        ffi::ERROR_INVALID_PROFILE_ARB => "Invalid profile".to_owned(),
+                        _ => format!("Unkown error: {}", last_error),
                     };
+                    return Err(WindowError::ContextCreationFailed(gl_request, reason));
                 }
 
                 // Replace the legacy context with the new and improved context
@@ -1253,44 +3732,91 @@ mod windows {
                     ffi::wglDeleteContext(legacy_gl_context);
                     ffi::wglMakeCurrent(device_context, gl_context);
                 }
+                guard.gl_context = gl_context;
 
-                gl_context
+                (gl_context, Some((wglCreateContextAttribsARB, context_attributes)))
             };
 
             let swap_function = if has_extension("WGL_EXT_swap_control") {
                 Some(unsafe {
-                    let p = get_proc_address("wglSwapIntervalEXT");
-                    if p.is_null() {
-                        panic!(
-                            "wglSwapIntervalEXTis not present, although the required \
-                            extensions are supported. Your drivers/the specification suck"
-                        );
+                    let set = get_proc_address("wglSwapIntervalEXT");
+                    let get = get_proc_address("wglGetSwapIntervalEXT");
+                    if set.is_null() || get.is_null() {
+                        return Err(WindowError::MissingExtension("WGL_EXT_swap_control".to_owned()));
                     }
-                    mem::transmute::<_, ffi::wglSwapIntervalEXTType>(p)
+                    (
+                        mem::transmute::<_, ffi::wglSwapIntervalEXTType>(set),
+                        mem::transmute::<_, ffi::wglGetSwapIntervalEXTType>(get),
+                    )
                 })
             } else {
                 None
             };
+            let adaptive_vsync_supported = swap_function.is_some() && has_extension("WGL_EXT_swap_control_tear");
+
+            let pbuffer_functions = if has_extension("WGL_ARB_pixel_format") && has_extension("WGL_ARB_pbuffer") {
+                let choose_pixel_format = get_proc_address("wglChoosePixelFormatARB");
+                let create = get_proc_address("wglCreatePbufferARB");
+                let get_dc = get_proc_address("wglGetPbufferDCARB");
+                let release_dc = get_proc_address("wglReleasePbufferDCARB");
+                let destroy = get_proc_address("wglDestroyPbufferARB");
+
+                let all_loaded = [choose_pixel_format, create, get_dc, release_dc, destroy].iter().all(|p| !p.is_null());
+                if all_loaded {
+                    Some(unsafe { PbufferFunctions {
+                        choose_pixel_format: mem::transmute::<_, ffi::wglChoosePixelFormatARBType>(choose_pixel_format),
+                        create: mem::transmute::<_, ffi::wglCreatePbufferARBType>(create),
+                        get_dc: mem::transmute::<_, ffi::wglGetPbufferDCARBType>(get_dc),
+                        release_dc: mem::transmute::<_, ffi::wglReleasePbufferDCARBType>(release_dc),
+                        destroy: mem::transmute::<_, ffi::wglDestroyPbufferARBType>(destroy),
+                    } })
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+            gl::load_with(&mut get_proc_address);
 
-            gl::load_with(get_proc_address);
+            // Only legacy contexts can't have a reset strategy
+            let robust = robust && shared_context_attributes.is_some();
+
+            // `gl` does not know the name this function has in `GL_ARB_robustness`
+            if robust && !gl::GetGraphicsResetStatus::is_loaded() {
+                gl::GetGraphicsResetStatus::load_with(|_| get_proc_address("glGetGraphicsResetStatusARB"));
+            }
 
             unsafe {
                 let raw = gl::GetString(gl::VERSION);
                 if raw.is_null() {
-                    panic!("glGetString(GL_VERSION) returned null!");
+                    return Err(WindowError::ContextCreationFailed(gl_request, "glGetString(GL_VERSION) returned null".to_owned()));
                 }
                 //            let version = CStr::from_ptr(raw as *const _).to_string_lossy();
                 //            println!("{}", version);
             }
 
-            graphics::viewport(region.unpositioned());
+            graphics::load_capabilities();
+            if gl_request.debug {
+                graphics::enable_debug_output();
+            }
+            framebuffer::set_window_viewport(region.unpositioned());
+
+            guard.disarm();
 
-            Window {
+            let mut window = Window {
                 raw_event_receiver,
                 device_context,
                 gl_context,
+                shared_context_attributes,
+                pbuffer_functions,
+                gl_request,
+                robust,
+                context_lost: false,
                 window,
                 swap_function,
+                adaptive_vsync_supported,
+                transparent: false,
                 cursors,
 
                 screen_region: region,
@@ -1306,7 +3832,19 @@ mod windows {
 
                 #[cfg(feature = "gamepad")]
                 gamepad_states: [InternalGamepadState::default(); 4],
+                scale_factor,
+
+                #[cfg(feature = "gamepad")]
+                gamepad_scan_requested: true,
+                #[cfg(feature = "gamepad")]
+                last_gamepad_scan: None,
+            };
+
+            if builder.transparent {
+                window.set_transparent(true);
             }
+
+            Ok(window)
         } 
 
         fn show(&mut self) {
@@ -1318,6 +3856,7 @@ mod windows {
             let focus_changed = self.focused != focused;
             self.focused = focused;
             input.window_has_keyboard_focus = self.focused;
+            input.double_click_time = Time::from_ms(unsafe { ffi::GetDoubleClickTime() } as u64);
 
             // Receive events from windows, dispatch them to `event_callback` and let them get sent
             // back through `raw_event_receiver`.
@@ -1341,9 +3880,12 @@ mod windows {
 
             input.refresh();
 
+            if focus_changed {
+                input.events.push(if self.focused { Event::FocusGained } else { Event::FocusLost });
+            }
+
             self.moved = false;
             self.resized = false;
-            self.close_requested = false;
 
             for raw_event in self.raw_event_receiver.try_iter() {
                 use self::RawEvent::*;
@@ -1366,35 +3908,29 @@ mod windows {
 
                         if new_region.min != self.screen_region.min {
                             self.moved = true;
+                            input.events.push(Event::Moved(new_region.min));
                         }
 
                         if new_region.size() != self.screen_region.size() {
                             self.resized = true;
+                            input.events.push(Event::Resized(new_region.size()));
                         }
 
                         self.screen_region = new_region;
-                        graphics::viewport(self.screen_region.unpositioned());
+                        framebuffer::set_window_viewport(self.screen_region.unpositioned());
 
                         self.update_cursor_clip();
                     },
 
                     CloseRequest => {
                         self.close_requested = true;
+                        input.events.push(Event::CloseRequested);
                     },
 
                     Key(pressed, code) => {
                         input.received_events_this_frame = true;
 
-                        let ref mut state = input.keys[code];
-                        *state = if pressed {
-                            if state.down() {
-                                KeyState::PressedRepeat
-                            } else {
-                                KeyState::Pressed
-                            }
-                        } else {
-                            KeyState::Released
-                        };
+                        input.update_key(code as u8, pressed);
                     },
 
                     Char(wchar) => {
@@ -1402,7 +3938,10 @@ mod windows {
 
                         for result in char::decode_utf16([wchar].iter().cloned()) {
                             match result {
-                                Ok(c) => input.type_buffer.push(c),
+                                Ok(c) => {
+                                    input.type_buffer.push(c);
+                                    input.events.push(Event::Text(c.to_string()));
+                                },
                                 Err(_) => println!("WM_CHAR with invalid code: {}", wchar),
                             }
                         }
@@ -1410,7 +3949,12 @@ mod windows {
 
                     Scroll(delta) => {
                         input.received_events_this_frame = true;
-                        input.mouse_scroll += delta;
+                        input.add_scroll(delta);
+                    },
+
+                    Zoom(factor) => {
+                        input.received_events_this_frame = true;
+                        input.add_zoom(factor);
                     },
 
                     MousePos(new_pos) => {
@@ -1419,9 +3963,22 @@ mod windows {
 
                             input.mouse_delta += new_pos - input.mouse_pos;
                             input.mouse_pos = new_pos;
+                            input.events.push(Event::MouseMove(new_pos));
+                        }
+
+                        if !input.mouse_inside_window {
+                            input.received_events_this_frame = true;
+                            input.mouse_inside_window = true;
+                            input.events.push(Event::MouseEnter);
                         }
                     },
 
+                    MouseLeave => {
+                        input.received_events_this_frame = true;
+                        input.mouse_inside_window = false;
+                        input.events.push(Event::MouseLeave);
+                    },
+
                     MouseDelta(delta) => {
                         if delta != Vec2::ZERO {
                             input.received_events_this_frame = true;
@@ -1429,11 +3986,12 @@ mod windows {
                         }
                     },
 
-                    MouseButton(down, code) => {
+                    MouseButton(down, code, time) => {
                         input.received_events_this_frame = true;
 
-                        let state = if down { KeyState::Pressed } else { KeyState::Released };
-                        input.mouse_keys[code] = state;
+                        // The message time wraps around after ~50 days, which `register_click`
+                        // treats as a non-consecutive click
+                        input.update_mouse_key(code, down, Time::from_ms(time as u32 as u64));
 
                         let mut any_down = false;
                         for state in input.mouse_keys.iter() {
@@ -1456,6 +4014,18 @@ mod windows {
                             }
                         }
                     },
+
+                    DevicesChanged => {
+                        #[cfg(feature = "gamepad")]
+                        { self.gamepad_scan_requested = true; }
+                    },
+
+                    ScaleFactorChanged(scale_factor) => {
+                        if scale_factor != self.scale_factor {
+                            self.scale_factor = scale_factor;
+                            input.events.push(Event::ScaleFactorChanged(scale_factor));
+                        }
+                    },
                 }
             }
 
@@ -1480,13 +4050,29 @@ mod windows {
             }
             
             // XInput gamepad mess
+            #[cfg(feature = "gamepad")]
+            let scan_gamepads = {
+                let now = Instant::now();
+                let scan = self.gamepad_scan_requested || match self.last_gamepad_scan {
+                    Some(last_scan) => now.duration_since(last_scan) >= GAMEPAD_SCAN_INTERVAL,
+                    None => true,
+                };
+                if scan {
+                    self.gamepad_scan_requested = false;
+                    self.last_gamepad_scan = Some(now);
+                }
+                scan
+            };
+
             #[cfg(feature = "gamepad")]
             for (index, state) in self.gamepad_states.iter_mut().enumerate() {
-                let result = unsafe { ffi::XInputGetState(index as u32, &mut state.xinput_state) };
+                // `XInputGetState` is slow for disconnected gamepads, so we don't call it every
+                // frame for them
+                if !state.connected && !scan_gamepads {
+                    continue;
+                }
 
-                // TODO don't retry connecting all the time, as that lags. I think
-                // casey talked about this at some point, in one of the pubg streams.
-                // It would be a pain in the ass to find though.
+                let result = unsafe { ffi::XInputGetState(index as u32, &mut state.xinput_state) };
 
                 if result == ffi::ERROR_SUCCESS {
                     state.connected = true;
@@ -1496,7 +4082,10 @@ mod windows {
                     println!("Unexpected return from `XInputGetState`: {}", result);
                 }
 
+                input.set_gamepad_connected(index, state.connected);
+
                 if !state.connected {
+                    state.rumble_end = None;
                     continue;
                 }
 
@@ -1508,106 +4097,430 @@ mod windows {
                 let ref mut s = state.xinput_state.Gamepad;
                 let ref mut gamepad = input.gamepads[index];
 
-                gamepad.connected = state.connected;
-
-                // We can probably factor out a lot of this stuff to `input.rs`
-                let deadzone = 0.3;
+                // Dead zones and the digital stick buttons are handled in `input.rs`
+                gamepad.update_axes(
+                    Vec2::new((s.sThumbLX as f32 + 0.5) / 32767.5, (s.sThumbLY as f32 + 0.5) / 32767.5),
+                    Vec2::new((s.sThumbRX as f32 + 0.5) / 32767.5, (s.sThumbRY as f32 + 0.5) / 32767.5),
+                    s.bLeftTrigger as f32 / 255.0,
+                    s.bRightTrigger as f32 / 255.0,
+                );
 
-                gamepad.left_trigger  = s.bLeftTrigger  as f32 / 255.0;
-                gamepad.right_trigger = s.bRightTrigger as f32 / 255.0;
+                use GamepadButton::*;
+                gamepad.update_button(DpadUp,      s.wButtons & 0x0001 != 0);
+                gamepad.update_button(DpadDown,    s.wButtons & 0x0002 != 0);
+                gamepad.update_button(DpadLeft,    s.wButtons & 0x0004 != 0);
+                gamepad.update_button(DpadRight,   s.wButtons & 0x0008 != 0);
+                gamepad.update_button(Start,       s.wButtons & 0x0010 != 0);
+                gamepad.update_button(Back,        s.wButtons & 0x0020 != 0);
+                gamepad.update_button(LeftStick,   s.wButtons & 0x0040 != 0);
+                gamepad.update_button(RightStick,  s.wButtons & 0x0080 != 0);
+                gamepad.update_button(LeftBumper,  s.wButtons & 0x0100 != 0);
+                gamepad.update_button(RightBumper, s.wButtons & 0x0200 != 0);
+                gamepad.update_button(A,           s.wButtons & 0x1000 != 0);
+                gamepad.update_button(B,           s.wButtons & 0x2000 != 0);
+                gamepad.update_button(X,           s.wButtons & 0x4000 != 0);
+                gamepad.update_button(Y,           s.wButtons & 0x8000 != 0);
+
+                // Rumble
+                let mut speeds = None;
+                if let Some((low_freq, high_freq, duration)) = gamepad.rumble_request.take() {
+                    speeds = Some((low_freq, high_freq));
+                    state.rumble_end = Some(Instant::now() + Duration::from(duration));
+                } else if let Some(end) = state.rumble_end {
+                    if Instant::now() >= end {
+                        speeds = Some((0.0, 0.0));
+                        state.rumble_end = None;
+                    }
+                }
 
-                if gamepad.left_trigger < deadzone  { gamepad.left_trigger = 0.0; }
-                if gamepad.right_trigger < deadzone { gamepad.right_trigger = 0.0; }
+                if let Some((low_freq, high_freq)) = speeds {
+                    fn to_speed(v: f32) -> u16 {
+                        let v = if v < 0.0 { 0.0 } else if v > 1.0 { 1.0 } else { v };
+                        (v * 65535.0) as u16
+                    }
 
-                gamepad.left = Vec2::new(
-                    (s.sThumbLX as f32 + 0.5) / 32767.5,
-                    (s.sThumbLY as f32 + 0.5) / 32767.5,
-                );
-                if gamepad.left.len_sqr() < deadzone*deadzone {
-                    gamepad.left = Vec2::ZERO;
+                    let mut vibration = ffi::XINPUT_VIBRATION {
+                        wLeftMotorSpeed:  to_speed(low_freq),
+                        wRightMotorSpeed: to_speed(high_freq),
+                    };
+                    unsafe { ffi::XInputSetState(index as u32, &mut vibration) };
                 }
+            }
+        }
 
-                gamepad.right = Vec2::new(
-                    (s.sThumbRX as f32 + 0.5) / 32767.5,
-                    (s.sThumbRY as f32 + 0.5) / 32767.5,
-                );
-                if gamepad.right.len_sqr() < deadzone*deadzone {
-                    gamepad.right = Vec2::ZERO;
-                }
+        fn wait_events(&mut self, input: &mut Input, timeout: Option<Time>) {
+            let timeout = match timeout {
+                Some(timeout) => Ord::min(timeout.to_ms(), (ffi::INFINITE - 1) as u64) as u32,
+                None => ffi::INFINITE,
+            };
 
-                fn update_state(down: bool, gamepad: &mut Gamepad, button: GamepadButton) {
-                    let ref mut state = gamepad.buttons[button as usize];
+            // `poll_events` removes all messages from the queue, so this only returns once new
+            // messages arrive.
+            unsafe { ffi::MsgWaitForMultipleObjects(
+                0, ptr::null(), ffi::FALSE,
+                timeout, ffi::QS_ALLINPUT,
+            ) };
 
-                    if down && !state.down() {
-                        *state = KeyState::Pressed;
-                    }
+            self.poll_events(input);
+        }
 
-                    if !down && state.down() {
-                        *state = KeyState::Released;
-                    }
+        fn wake_handle(&self) -> WakeHandle {
+            WakeHandle { window: self.window as usize }
+        }
+
+        fn create_shared_context(&self) -> Result<SharedContext, WindowError> {
+            let functions = match self.pbuffer_functions {
+                Some(f) => f,
+                None => return Err(WindowError::MissingExtension("WGL_ARB_pbuffer".to_owned())),
+            };
+
+            // The shared context gets its own device context from a tiny pbuffer, so it stays
+            // valid even if the window is dropped before it. Unlike a hidden window, a pbuffer can
+            // be destroyed from any thread.
+            let pixel_format_attributes = [
+                ffi::WGL_DRAW_TO_PBUFFER_ARB, 1,
+                ffi::WGL_SUPPORT_OPENGL_ARB, 1,
+                ffi::WGL_PIXEL_TYPE_ARB, ffi::WGL_TYPE_RGBA_ARB,
+                ffi::WGL_COLOR_BITS_ARB, 24,
+                ffi::WGL_ALPHA_BITS_ARB, 8,
+                0,
+            ];
+            let mut pixel_format = 0;
+            let mut count = 0;
+            let found = (functions.choose_pixel_format)(
+                self.device_context,
+                pixel_format_attributes.as_ptr(), ptr::null(),
+                1, &mut pixel_format, &mut count,
+            );
+            if found == 0 || count == 0 {
+                return Err(WindowError::NoMatchingFormat);
+            }
+
+            let pbuffer = (functions.create)(self.device_context, pixel_format, 1, 1, [0].as_ptr());
+            if pbuffer.is_null() {
+                let reason = format!("Could not create pbuffer: {}", last_win_error());
+                return Err(WindowError::CreationFailed(reason));
+            }
+            let device_context = (functions.get_dc)(pbuffer);
+            if device_context.is_null() {
+                let reason = format!("Could not get pbuffer device context: {}", last_win_error());
+                (functions.destroy)(pbuffer);
+                return Err(WindowError::CreationFailed(reason));
+            }
+
+            let gl_context = unsafe {
+                match self.shared_context_attributes {
+                    Some((create_fn, ref attributes)) => {
+                        create_fn(device_context, self.gl_context, attributes.as_ptr())
+                    },
+                    None => {
+                        let context = ffi::wglCreateContext(device_context);
+                        if !context.is_null() && ffi::wglShareLists(self.gl_context, context) == 0 {
+                            ffi::wglDeleteContext(context);
+                            ptr::null_mut()
+                        } else {
+                            context
+                        }
+                    },
                 }
+            };
 
-                use GamepadButton::*;
-                update_state(s.wButtons & 0x0001 != 0, gamepad, DpadUp);
-                update_state(s.wButtons & 0x0002 != 0, gamepad, DpadUp);
-                update_state(s.wButtons & 0x0004 != 0, gamepad, DpadUp);
-                update_state(s.wButtons & 0x0008 != 0, gamepad, DpadUp);
-                update_state(s.wButtons & 0x0010 != 0, gamepad, Start);
-                update_state(s.wButtons & 0x0020 != 0, gamepad, Back);
-                update_state(s.wButtons & 0x0040 != 0, gamepad, LeftStick);
-                update_state(s.wButtons & 0x0080 != 0, gamepad, RightStick);
-                update_state(s.wButtons & 0x0100 != 0, gamepad, LeftBumper);
-                update_state(s.wButtons & 0x0200 != 0, gamepad, RightBumper);
-                update_state(s.wButtons & 0x1000 != 0, gamepad, A);
-                update_state(s.wButtons & 0x2000 != 0, gamepad, B);
-                update_state(s.wButtons & 0x4000 != 0, gamepad, X);
-                update_state(s.wButtons & 0x8000 != 0, gamepad, Y);
-
-                let v = 0.8;
-                update_state(gamepad.left.y  > v,  gamepad, LeftUp);
-                update_state(gamepad.left.y  < -v, gamepad, LeftDown);
-                update_state(gamepad.left.x  > v,  gamepad, LeftRight);
-                update_state(gamepad.left.x  < -v, gamepad, LeftLeft);
-                update_state(gamepad.right.y > v,  gamepad, RightUp);
-                update_state(gamepad.right.y < -v, gamepad, RightDown);
-                update_state(gamepad.right.x > v,  gamepad, RightRight);
-                update_state(gamepad.right.x < -v, gamepad, RightLeft);
-                update_state(gamepad.left_trigger  > v, gamepad, LeftTrigger);
-                update_state(gamepad.right_trigger > v, gamepad, RightTrigger); 
+            if gl_context.is_null() {
+                let reason = format!("Could not create shared context: {}", last_win_error());
+                (functions.release_dc)(pbuffer, device_context);
+                (functions.destroy)(pbuffer);
+                return Err(WindowError::ContextCreationFailed(self.gl_request, reason));
             }
+
+            Ok(SharedContext {
+                functions,
+                pbuffer,
+                device_context,
+                gl_context,
+                gl_request: self.gl_request,
+            })
         }
 
         fn swap_buffers(&mut self) {
             unsafe { 
                 ffi::SwapBuffers(self.device_context); 
             }
+
+            if self.robust && !self.context_lost && graphics::context_reset_detected() {
+                self.context_lost = true;
+            }
+        }
+
+        fn context_lost(&self) -> bool {
+            self.context_lost
+        }
+
+        fn recreate_context(&mut self) -> Result<(), WindowError> {
+            let gl_context = unsafe {
+                match self.shared_context_attributes {
+                    Some((create_fn, ref attributes)) => {
+                        create_fn(self.device_context, ptr::null_mut(), attributes.as_ptr())
+                    },
+                    None => ffi::wglCreateContext(self.device_context),
+                }
+            };
+
+            if gl_context.is_null() {
+                let reason = format!("Could not recreate context: {}", last_win_error());
+                return Err(WindowError::ContextCreationFailed(self.gl_request, reason));
+            }
+
+            unsafe {
+                ffi::wglMakeCurrent(self.device_context, gl_context);
+                ffi::wglDeleteContext(self.gl_context);
+            }
+            self.gl_context = gl_context;
+            self.context_lost = false;
+
+            // srgb default framebuffers are not supported on windows yet
+            restore_context_state(self.gl_request, self.screen_region.unpositioned(), false);
+            Ok(())
         }
 
         fn close_requested(&self) -> bool { self.close_requested }
+        fn cancel_close(&mut self)        { self.close_requested = false; }
+        fn request_close(&mut self)       { self.close_requested = true; }
         fn resized(&self) -> bool         { self.resized }
         fn moved(&self) -> bool           { self.moved }
         fn focused(&self) -> bool         { self.focused }
+        fn scale_factor(&self) -> f32     { self.scale_factor }
 
         fn screen_region(&self) -> Region { self.screen_region }
 
+        fn set_size(&mut self, size: Vec2<f32>) {
+            // `size` is the size of the client area, but `SetWindowPos` wants the size including
+            // borders and the title bar.
+            let outer = self.client_to_window_rect(Region { min: Vec2::ZERO, max: size });
+            let outer_size = outer.size().as_i32();
+
+            unsafe { ffi::SetWindowPos(
+                self.window, ptr::null_mut(),
+                0, 0, outer_size.x, outer_size.y,
+                ffi::SWP_NOMOVE | ffi::SWP_NOZORDER | ffi::SWP_NOACTIVATE,
+            ) };
+        }
+
+        fn set_position(&mut self, pos: Vec2<f32>) {
+            let outer = self.client_to_window_rect(Region { min: pos, max: pos });
+            let outer_pos = outer.min.as_i32();
+
+            unsafe { ffi::SetWindowPos(
+                self.window, ptr::null_mut(),
+                outer_pos.x, outer_pos.y, 0, 0,
+                ffi::SWP_NOSIZE | ffi::SWP_NOZORDER | ffi::SWP_NOACTIVATE,
+            ) };
+        }
+
+        fn maximize(&mut self) {
+            unsafe { ffi::ShowWindow(self.window, ffi::SW_MAXIMIZE) };
+        }
+
+        fn minimize(&mut self) {
+            unsafe { ffi::ShowWindow(self.window, ffi::SW_MINIMIZE) };
+        }
+
+        fn restore(&mut self) {
+            unsafe { ffi::ShowWindow(self.window, ffi::SW_RESTORE) };
+        }
+
+        fn maximized(&self) -> bool {
+            unsafe { ffi::IsZoomed(self.window) != 0 }
+        }
+
+        fn normal_region(&self) -> Region {
+            unsafe {
+                let mut placement: ffi::WINDOWPLACEMENT = mem::zeroed();
+                placement.length = mem::size_of::<ffi::WINDOWPLACEMENT>() as ffi::UINT;
+                if ffi::GetWindowPlacement(self.window, &mut placement) == 0 {
+                    return self.screen_region;
+                }
+
+                let rect = placement.rcNormalPosition;
+                let mut outer = Region {
+                    min: Vec2::new(rect.left, rect.top).as_f32(),
+                    max: Vec2::new(rect.right, rect.bottom).as_f32(),
+                };
+
+                // The normal position is in workspace coordinates, which are offset by taskbars
+                // on the top or left of the monitor. Tool windows use screen coordinates instead.
+                let ex_style = ffi::GetWindowLongW(self.window, ffi::GWL_EXSTYLE) as u32;
+                if ex_style & ffi::WS_EX_TOOLWINDOW == 0 {
+                    let monitor = ffi::MonitorFromWindow(self.window, ffi::MONITOR_DEFAULTTONEAREST);
+                    let mut info: ffi::MONITORINFO = mem::zeroed();
+                    info.cbSize = mem::size_of::<ffi::MONITORINFO>() as ffi::DWORD;
+                    if ffi::GetMonitorInfoW(monitor, &mut info) != 0 {
+                        let offset = Vec2::new(
+                            info.rcWork.left - info.rcMonitor.left,
+                            info.rcWork.top - info.rcMonitor.top,
+                        );
+                        outer = outer.offset(offset.as_f32());
+                    }
+                }
+
+                // Remove the borders and title bar
+                let frame = self.client_to_window_rect(Region { min: Vec2::ZERO, max: Vec2::ZERO });
+                Region { min: outer.min - frame.min, max: outer.max - frame.max }
+            }
+        }
+
+        fn key_for_char(&self, c: char) -> Option<Key> {
+            let mut utf16 = [0u16; 2];
+            let utf16 = c.encode_utf16(&mut utf16);
+            if utf16.len() != 1 {
+                return None; // Keyboards don't type surrogate pairs with a single key
+            }
+
+            // The low byte is the virtual key code, the high byte the required modifiers
+            let result = unsafe { ffi::VkKeyScanW(utf16[0]) };
+            if result == -1 {
+                return None;
+            }
+            let virtual_key = (result & 0xff) as u32;
+
+            let scancode = unsafe { ffi::MapVirtualKeyW(virtual_key, ffi::MAPVK_VK_TO_VSC) };
+            if scancode == 0 || scancode > 0xff {
+                None
+            } else {
+                scancode_to_key(scancode as u8)
+            }
+        }
+
         fn change_title(&mut self, title: &str) {
             let title = encode_wide(title);
             unsafe { ffi::SetWindowTextW(self.window, title.as_ptr()) };
         }
 
-        fn set_vsync(&mut self, vsync: bool) {
-            if let Some(swap_function) = self.swap_function {
-                swap_function(if vsync { 1 } else { 0 });
+        fn set_vsync_mode(&mut self, mode: VsyncMode) -> VsyncMode {
+            let mode = if mode == VsyncMode::Adaptive && !self.adaptive_vsync_supported {
+                VsyncMode::On
+            } else {
+                mode
+            };
+
+            if let Some((swap_function, _)) = self.swap_function {
+                swap_function(mode.swap_interval());
             } else {
                 #[cfg(debug_assertions)]
-                println!("`set_vsync` called, but WGL_EXT_swap_control is not supported");
+                println!("`set_vsync_mode` called, but WGL_EXT_swap_control is not supported");
+            }
+
+            VsyncMode::from_swap_interval(self.current_swap_interval())
+        }
+
+        fn vsync_supported(&self) -> bool {
+            self.swap_function.is_some()
+        }
+
+        fn adaptive_vsync_supported(&self) -> bool {
+            self.adaptive_vsync_supported
+        }
+
+        fn current_swap_interval(&self) -> i32 {
+            if let Some((_, get_swap_interval)) = self.swap_function {
+                get_swap_interval()
+            } else {
+                1 // The initial swap interval
+            }
+        }
+
+        fn set_transparent(&mut self, transparent: bool) -> bool {
+            unsafe {
+                let mut compositing = ffi::FALSE;
+                ffi::DwmIsCompositionEnabled(&mut compositing);
+                if compositing == ffi::FALSE {
+                    if transparent {
+                        println!("`set_transparent` called, but desktop composition is disabled");
+                    }
+                    return !transparent;
+                }
+
+                // Blurring behind an empty region makes DWM use the alpha channel of the window
+                // without actually blurring anything.
+                let region = ffi::CreateRectRgn(0, 0, -1, -1);
+                let blur_behind = ffi::DWM_BLURBEHIND {
+                    dwFlags: ffi::DWM_BB_ENABLE | ffi::DWM_BB_BLURREGION,
+                    fEnable: if transparent { ffi::TRUE } else { ffi::FALSE },
+                    hRgnBlur: region,
+                    fTransitionOnMaximized: ffi::FALSE,
+                };
+                let result = ffi::DwmEnableBlurBehindWindow(self.window, &blur_behind);
+                ffi::DeleteObject(region as *mut _);
+
+                if result < 0 {
+                    println!("DwmEnableBlurBehindWindow failed: {}", result);
+                    return false;
+                }
             }
+
+            self.transparent = transparent;
+            true
+        }
+
+        fn transparent(&self) -> bool {
+            self.transparent
         }
 
         fn set_cursor(&mut self, cursor: CursorType) {
             self.cursor = cursor;
         }
 
+        fn set_cursor_image(&mut self, pixels: &[u8], size: Vec2<u32>, hotspot: Vec2<u32>) {
+            assert_eq!(
+                pixels.len(), (size.x*size.y*4) as usize,
+                "`pixels` should contain `size.x*size.y` RGBA pixels"
+            );
+
+            // Windows wants BGRA
+            let mut color_bits = pixels.to_vec();
+            for pixel in color_bits.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+
+            // The mask is ignored when the color bitmap has an alpha channel, but it still needs
+            // to be present. Rows are padded to 16 bits.
+            let mask_stride = ((size.x + 15) / 16 * 2) as usize;
+            let mask_bits = vec![0u8; mask_stride*size.y as usize];
+
+            let cursor = unsafe {
+                let color = ffi::CreateBitmap(size.x as i32, size.y as i32, 1, 32, color_bits.as_ptr() as *const _);
+                let mask  = ffi::CreateBitmap(size.x as i32, size.y as i32, 1, 1, mask_bits.as_ptr() as *const _);
+
+                let mut icon_info = ffi::ICONINFO {
+                    fIcon: ffi::FALSE, // This is a cursor
+                    xHotspot: hotspot.x,
+                    yHotspot: hotspot.y,
+                    hbmMask: mask,
+                    hbmColor: color,
+                };
+                let cursor = ffi::CreateIconIndirect(&mut icon_info);
+
+                // `CreateIconIndirect` copies the bitmaps
+                ffi::DeleteObject(color as *mut _);
+                ffi::DeleteObject(mask as *mut _);
+
+                cursor
+            };
+
+            if cursor.is_null() {
+                println!("CreateIconIndirect failed: {}", last_win_error());
+                return;
+            }
+
+            self.destroy_custom_cursor();
+            self.cursors[CursorType::Custom as usize] = cursor;
+            self.cursor = CursorType::Custom;
+        }
+
+        fn set_cursor_position(&mut self, pos: Vec2<f32>) {
+            let pos = (self.screen_region.min + pos).as_i32();
+            unsafe { ffi::SetCursorPos(pos.x, pos.y) };
+        }
+
         fn grab_cursor(&mut self, grabbed: bool) {
             if self.cursor_grabbed == grabbed {
                 return;
@@ -1625,10 +4538,13 @@ mod windows {
 
     impl Drop for Window {
         fn drop(&mut self) {
+            self.destroy_custom_cursor();
+
             unsafe { 
                 ffi::wglDeleteContext(self.gl_context);
                 ffi::DestroyWindow(self.window);
             }
+            graphics::note_context_destroyed();
         }
     }
 
@@ -1638,12 +4554,22 @@ mod windows {
             self.window
         }
 
+        // Until `set_cursor_image` is called, the custom cursor is the shared arrow cursor, which
+        // we must not destroy.
+        fn destroy_custom_cursor(&mut self) {
+            let custom = self.cursors[CursorType::Custom as usize];
+            if custom != self.cursors[CursorType::Normal as usize] {
+                unsafe { ffi::DestroyCursor(custom) };
+                self.cursors[CursorType::Custom as usize] = self.cursors[CursorType::Normal as usize];
+            }
+        }
+
         fn update_cursor_clip(&self) {
             let mut clip = None;
 
             if self.focused {
                 if self.cursor_grabbed {
-                    internal_clip_cursor(Some(self.screen_region));
+                    clip = Some(self.screen_region);
                 } else if let Some(region) = self.cursor_clip_region {
                     clip = Some(region.offset(self.screen_region.min));
                 }
@@ -1661,6 +4587,121 @@ mod windows {
 
             self.screen_region.contains(mouse_pos)
         }
+
+        // Converts a region in client space to the region that the entire window, including borders
+        // and the title bar, would cover.
+        fn client_to_window_rect(&self, region: Region) -> Region {
+            unsafe {
+                let style    = ffi::GetWindowLongW(self.window, ffi::GWL_STYLE) as u32;
+                let ex_style = ffi::GetWindowLongW(self.window, ffi::GWL_EXSTYLE) as u32;
+
+                let mut rect = ffi::RECT {
+                    left:   region.min.x as i32,
+                    right:  region.max.x as i32,
+                    top:    region.min.y as i32,
+                    bottom: region.max.y as i32,
+                };
+                ffi::AdjustWindowRectEx(&mut rect, style, ffi::FALSE, ex_style);
+
+                Region {
+                    min: Vec2::new(rect.left, rect.top).as_f32(),
+                    max: Vec2::new(rect.right, rect.bottom).as_f32(),
+                }
+            }
+        }
+    }
+
+    /// Wakes a window from `WindowCommon::wait_events`. Created with `WindowCommon::wake_handle`.
+    pub struct WakeHandle {
+        window: usize, // HWND is not `Send`, but `PostMessageW` can be called from any thread
+    }
+
+    impl WakeHandle {
+        pub fn wake(&self) {
+            unsafe { ffi::PostMessageW(self.window as ffi::HWND, ffi::WM_NULL, 0, 0) };
+        }
+    }
+
+    /// A OpenGL context which shares objects with the context of a window. Created with
+    /// `WindowCommon::create_shared_context`.
+    pub struct SharedContext {
+        functions: PbufferFunctions,
+        pbuffer: ffi::HPBUFFERARB,
+        device_context: ffi::HDC,
+        gl_context: ffi::HGLRC,
+        gl_request: GlRequest,
+    }
+
+    // A context which is not current on any thread can be made current on any other thread
+    unsafe impl Send for SharedContext {}
+
+    impl SharedContext {
+        /// Makes this context current on the calling thread. A context can only be current on a
+        /// single thread at a time.
+        pub fn make_current(&self) -> Result<(), WindowError> {
+            let result = unsafe { ffi::wglMakeCurrent(self.device_context, self.gl_context) };
+            if result != 0 {
+                Ok(())
+            } else {
+                let reason = format!("wglMakeCurrent failed: {}", last_win_error());
+                Err(WindowError::ContextCreationFailed(self.gl_request, reason))
+            }
+        }
+
+        /// Makes no context current on the calling thread.
+        pub fn release(&self) {
+            unsafe { ffi::wglMakeCurrent(ptr::null_mut(), ptr::null_mut()) };
+        }
+    }
+
+    impl Drop for SharedContext {
+        fn drop(&mut self) {
+            unsafe { ffi::wglDeleteContext(self.gl_context) };
+            (self.functions.release_dc)(self.pbuffer, self.device_context);
+            (self.functions.destroy)(self.pbuffer);
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    struct PbufferFunctions {
+        choose_pixel_format: ffi::wglChoosePixelFormatARBType,
+        create: ffi::wglCreatePbufferARBType,
+        get_dc: ffi::wglGetPbufferDCARBType,
+        release_dc: ffi::wglReleasePbufferDCARBType,
+        destroy: ffi::wglDestroyPbufferARBType,
+    }
+
+    // Releases everything created so far if `from_builder` fails partway through, and allows
+    // creating a new window on this thread. The cursors are shared system cursors, so they are
+    // not destroyed.
+    struct CreationGuard {
+        window: ffi::HWND,
+        device_context: ffi::HDC,
+        gl_context: ffi::HGLRC,
+    }
+
+    impl CreationGuard {
+        fn disarm(self) {
+            mem::forget(self);
+        }
+    }
+
+    impl Drop for CreationGuard {
+        fn drop(&mut self) {
+            unsafe {
+                if !self.gl_context.is_null() {
+                    ffi::wglMakeCurrent(ptr::null_mut(), ptr::null_mut());
+                    ffi::wglDeleteContext(self.gl_context);
+                }
+                if !self.device_context.is_null() {
+                    ffi::ReleaseDC(self.window, self.device_context);
+                }
+                if !self.window.is_null() {
+                    ffi::DestroyWindow(self.window);
+                }
+            }
+            MSG_SENDER.with(|sender| *sender.borrow_mut() = None);
+        }
     }
 
     fn new_rect() -> ffi::RECT {